@@ -0,0 +1,152 @@
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Method renames between the client's dialect and the server's. Outgoing
+/// requests and notifications are rewritten client→server; server-originated
+/// requests and notifications are rewritten back through the inverse map.
+/// Responses carry no `method` and are never touched.
+#[derive(Clone, Debug, Default)]
+pub struct MethodAliases {
+    to_server: HashMap<String, String>,
+    to_client: HashMap<String, String>,
+}
+
+impl MethodAliases {
+    pub fn new(pairs: &[(String, String)]) -> Self {
+        let mut aliases = Self::default();
+        for (client, server) in pairs {
+            aliases.to_server.insert(client.clone(), server.clone());
+            aliases.to_client.insert(server.clone(), client.clone());
+        }
+        aliases
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.to_server.is_empty()
+    }
+
+    /// Rewrites a client message for the server. Returns `None` when nothing
+    /// changed so callers can keep the original bytes.
+    pub fn to_server(&self, msg: &str) -> Option<String> {
+        rewrite(msg, &self.to_server)
+    }
+
+    /// Rewrites a server message for the client. Returns `None` when nothing
+    /// changed.
+    pub fn to_client(&self, msg: &str) -> Option<String> {
+        rewrite(msg, &self.to_client)
+    }
+}
+
+/// Parses `client=server` pairs separated by commas, e.g.
+/// `tools/run=tools/call,notifications/progress2=notifications/progress`.
+pub fn parse_pairs(spec: &str) -> Result<Vec<(String, String)>, String> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((from, to)) if !from.trim().is_empty() && !to.trim().is_empty() => {
+                Ok((from.trim().to_string(), to.trim().to_string()))
+            }
+            _ => Err(format!("invalid method alias {:?} (expected from=to)", pair)),
+        })
+        .collect()
+}
+
+fn rewrite(msg: &str, map: &HashMap<String, String>) -> Option<String> {
+    if map.is_empty() {
+        return None;
+    }
+    let mut value: Value = serde_json::from_str(msg).ok()?;
+    let changed = match &mut value {
+        Value::Array(items) => {
+            let mut changed = false;
+            for item in items {
+                changed |= rename_method(item, map);
+            }
+            changed
+        }
+        item => rename_method(item, map),
+    };
+    changed.then(|| value.to_string())
+}
+
+fn rename_method(item: &mut Value, map: &HashMap<String, String>) -> bool {
+    let Some(method) = item.get_mut("method") else {
+        return false;
+    };
+    match method.as_str().and_then(|m| map.get(m)) {
+        Some(renamed) => {
+            *method = Value::String(renamed.clone());
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aliases() -> MethodAliases {
+        MethodAliases::new(
+            &parse_pairs("tools/run=tools/call, notifications/progress2=notifications/progress")
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn outgoing_method_is_renamed_and_fields_preserved() {
+        let msg = r#"{"jsonrpc":"2.0","id":3,"method":"tools/run","params":{"name":"x","arguments":{"b":1,"a":2}}}"#;
+        let out = aliases().to_server(msg).unwrap();
+        assert_eq!(
+            out,
+            r#"{"jsonrpc":"2.0","id":3,"method":"tools/call","params":{"name":"x","arguments":{"b":1,"a":2}}}"#
+        );
+    }
+
+    #[test]
+    fn server_notification_is_renamed_back() {
+        let msg = r#"{"jsonrpc":"2.0","method":"notifications/progress","params":{"progress":1}}"#;
+        let out = aliases().to_client(msg).unwrap();
+        assert_eq!(
+            out,
+            r#"{"jsonrpc":"2.0","method":"notifications/progress2","params":{"progress":1}}"#
+        );
+    }
+
+    #[test]
+    fn unmapped_methods_and_responses_are_untouched() {
+        let a = aliases();
+        assert_eq!(a.to_server(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#), None);
+        assert_eq!(a.to_client(r#"{"jsonrpc":"2.0","id":1,"result":{"method":"tools/call"}}"#), None);
+        assert_eq!(a.to_server("not json"), None);
+    }
+
+    #[test]
+    fn duplicate_keys_collapse_to_the_last_value() {
+        let msg = r#"{"jsonrpc":"2.0","id":1,"method":"ping","method":"tools/run","params":{"a":1,"a":2}}"#;
+        let out = aliases().to_server(msg).unwrap();
+        assert_eq!(
+            out,
+            r#"{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{"a":2}}"#
+        );
+    }
+
+    #[test]
+    fn batch_elements_are_renamed() {
+        let msg = r#"[{"jsonrpc":"2.0","id":1,"method":"tools/run"},{"jsonrpc":"2.0","id":2,"method":"ping"}]"#;
+        let out = aliases().to_server(msg).unwrap();
+        assert_eq!(
+            out,
+            r#"[{"jsonrpc":"2.0","id":1,"method":"tools/call"},{"jsonrpc":"2.0","id":2,"method":"ping"}]"#
+        );
+    }
+
+    #[test]
+    fn malformed_pairs_are_rejected() {
+        assert!(parse_pairs("tools/run").is_err());
+        assert!(parse_pairs("=tools/call").is_err());
+        assert_eq!(parse_pairs("").unwrap(), vec![]);
+    }
+}
@@ -0,0 +1,179 @@
+//! Spreading requests over `URI` and `MCP_FALLBACK_URIS` for stateless
+//! servers (`MCP_LB_STRATEGY=round-robin`). An endpoint that gives a
+//! retryable failure sits out `MCP_LB_COOLDOWN_MS` before it is picked
+//! again.
+
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// How long an endpoint is skipped after a retryable failure, when
+/// `MCP_LB_COOLDOWN_MS` is not set.
+pub const DEFAULT_COOLDOWN_MS: u64 = 30_000;
+
+/// How requests are spread over the configured endpoints
+/// (`MCP_LB_STRATEGY`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LbStrategy {
+    /// Everything goes to one endpoint, moving on only when it keeps
+    /// failing (`MCP_FAILOVER_AFTER`).
+    #[default]
+    Sticky,
+    /// Each attempt goes to the next healthy endpoint in turn. Session ids
+    /// are not kept, since each belongs to one endpoint.
+    RoundRobin,
+}
+
+impl FromStr for LbStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "sticky" => Ok(Self::Sticky),
+            "round-robin" | "round_robin" => Ok(Self::RoundRobin),
+            other => Err(format!("unknown load-balancing strategy {:?}", other)),
+        }
+    }
+}
+
+impl LbStrategy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Sticky => "sticky",
+            Self::RoundRobin => "round-robin",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Endpoint {
+    uri: String,
+    /// Skipped until then after a retryable failure.
+    cooling_until: Option<Instant>,
+}
+
+/// The endpoints in turn, each with its health.
+#[derive(Debug)]
+pub struct RoundRobin {
+    endpoints: Vec<Endpoint>,
+    next: usize,
+    cooldown: Duration,
+}
+
+impl RoundRobin {
+    pub fn new(uri: &str, others: &[String], cooldown: Duration) -> Self {
+        let endpoints = std::iter::once(uri)
+            .chain(others.iter().map(String::as_str))
+            .map(|uri| Endpoint {
+                uri: uri.to_string(),
+                cooling_until: None,
+            })
+            .collect();
+        Self {
+            endpoints,
+            next: 0,
+            cooldown,
+        }
+    }
+
+    /// The endpoint for the next attempt: the next one in turn that is not
+    /// cooling down. When all are, the one whose cooldown ends first, so
+    /// requests keep going out.
+    pub fn pick(&mut self, now: Instant) -> String {
+        let n = self.endpoints.len();
+        let healthy = (0..n)
+            .map(|i| (self.next + i) % n)
+            .find(|&i| self.endpoints[i].cooling_until.is_none_or(|until| until <= now));
+        let chosen = healthy.unwrap_or_else(|| {
+            (0..n)
+                .min_by_key(|&i| self.endpoints[i].cooling_until)
+                .unwrap_or(0)
+        });
+        self.next = (chosen + 1) % n;
+        self.endpoints[chosen].uri.clone()
+    }
+
+    /// Takes `uri` out of the rotation for the cooldown after a retryable
+    /// failure. True when it was healthy until now.
+    pub fn on_failure(&mut self, uri: &str, now: Instant) -> bool {
+        let cooldown = self.cooldown;
+        let Some(endpoint) = self.endpoints.iter_mut().find(|e| e.uri == uri) else {
+            return false;
+        };
+        let was_healthy = endpoint.cooling_until.is_none_or(|until| until <= now);
+        endpoint.cooling_until = Some(now + cooldown);
+        was_healthy
+    }
+
+    /// `uri` answered, so it is back in the rotation. True when it was
+    /// cooling down.
+    pub fn on_success(&mut self, uri: &str) -> bool {
+        self.endpoints
+            .iter_mut()
+            .find(|e| e.uri == uri)
+            .and_then(|e| e.cooling_until.take())
+            .is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: &str = "https://a.example/mcp";
+    const B: &str = "https://b.example/mcp";
+    const C: &str = "https://c.example/mcp";
+
+    fn three() -> RoundRobin {
+        RoundRobin::new(A, &[B.to_string(), C.to_string()], Duration::from_secs(30))
+    }
+
+    #[test]
+    fn endpoints_take_turns() {
+        let mut lb = three();
+        let now = Instant::now();
+        let picks: Vec<String> = (0..4).map(|_| lb.pick(now)).collect();
+        assert_eq!(picks, [A, B, C, A]);
+    }
+
+    #[test]
+    fn failing_endpoint_sits_out_the_cooldown() {
+        let mut lb = three();
+        let now = Instant::now();
+        assert!(lb.on_failure(B, now));
+        assert!(!lb.on_failure(B, now));
+        let picks: Vec<String> = (0..3).map(|_| lb.pick(now)).collect();
+        assert_eq!(picks, [A, C, A]);
+        let later = now + Duration::from_secs(30);
+        let picks: Vec<String> = (0..3).map(|_| lb.pick(later)).collect();
+        assert_eq!(picks, [B, C, A]);
+    }
+
+    #[test]
+    fn success_ends_the_cooldown_early() {
+        let mut lb = three();
+        let now = Instant::now();
+        lb.on_failure(A, now);
+        assert_eq!(lb.pick(now), B);
+        assert!(lb.on_success(A));
+        assert!(!lb.on_success(A));
+        assert_eq!(lb.pick(now), C);
+        assert_eq!(lb.pick(now), A);
+    }
+
+    #[test]
+    fn all_cooling_down_picks_the_one_back_soonest() {
+        let mut lb = three();
+        let now = Instant::now();
+        lb.on_failure(C, now);
+        lb.on_failure(A, now + Duration::from_secs(1));
+        lb.on_failure(B, now + Duration::from_secs(2));
+        assert_eq!(lb.pick(now + Duration::from_secs(3)), C);
+    }
+
+    #[test]
+    fn strategy_names_parse() {
+        assert_eq!("Round-Robin".parse(), Ok(LbStrategy::RoundRobin));
+        assert_eq!("sticky".parse(), Ok(LbStrategy::Sticky));
+        assert!("random".parse::<LbStrategy>().is_err());
+    }
+}
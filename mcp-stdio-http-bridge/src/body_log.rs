@@ -0,0 +1,116 @@
+//! Message bodies as logged with `MCP_LOG_BODIES`: secrets redacted, then
+//! cut to a byte limit. Headers are never logged, so neither is the
+//! `Authorization` one.
+
+use serde_json::Value;
+
+/// Bytes of a body logged when `MCP_LOG_BODY_LIMIT` is not set.
+pub const DEFAULT_LIMIT: usize = 2048;
+/// Object keys whose values are replaced, compared case-insensitively.
+pub const SECRET_KEYS: &[&str] = &["token", "apiKey", "password", "secret"];
+pub const REDACTED: &str = "[redacted]";
+/// Nesting below this is replaced wholesale rather than searched, so a
+/// deeply nested secret cannot slip through.
+const MAX_DEPTH: usize = 32;
+const TOO_DEEP: &str = "[too deep]";
+
+/// `value` with every secret field, at any depth down to `MAX_DEPTH`,
+/// replaced by `REDACTED`.
+pub fn redact(value: &Value) -> Value {
+    redact_at(value, 0)
+}
+
+fn redact_at(value: &Value, depth: usize) -> Value {
+    match value {
+        Value::Object(_) | Value::Array(_) if depth >= MAX_DEPTH => Value::from(TOO_DEEP),
+        Value::Object(map) => map
+            .iter()
+            .map(|(k, v)| {
+                let v = if is_secret(k) {
+                    Value::from(REDACTED)
+                } else {
+                    redact_at(v, depth + 1)
+                };
+                (k.clone(), v)
+            })
+            .collect(),
+        Value::Array(items) => items.iter().map(|v| redact_at(v, depth + 1)).collect(),
+        other => other.clone(),
+    }
+}
+
+fn is_secret(key: &str) -> bool {
+    SECRET_KEYS.iter().any(|s| s.eq_ignore_ascii_case(key))
+}
+
+/// What to log for `body`: the redacted JSON, at most `limit` bytes of it.
+/// A body that is not JSON cannot be redacted, so only its size is logged.
+pub fn for_log(body: &str, limit: usize) -> String {
+    let Ok(value) = serde_json::from_str::<Value>(body) else {
+        return format!("[{} bytes, not JSON]", body.len());
+    };
+    let text = redact(&value).to_string();
+    if text.len() <= limit {
+        return text;
+    }
+    let mut end = limit;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}... [{} bytes]", &text[..end], text.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn secrets_are_redacted_at_any_depth() {
+        let body = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "token": "t0p",
+            "params": {
+                "arguments": {"APIKEY": "k3y", "user": "ann", "nested": [{"Password": "pw"}]},
+                "secret": {"whole": "object"},
+            },
+        });
+        let redacted = redact(&body);
+        let text = redacted.to_string();
+        for leaked in ["t0p", "k3y", "pw", "object"] {
+            assert!(!text.contains(leaked), "{} leaked in {}", leaked, text);
+        }
+        assert_eq!(redacted["token"], REDACTED);
+        assert_eq!(redacted["params"]["arguments"]["APIKEY"], REDACTED);
+        assert_eq!(redacted["params"]["arguments"]["nested"][0]["Password"], REDACTED);
+        assert_eq!(redacted["params"]["secret"], REDACTED);
+        assert_eq!(redacted["params"]["arguments"]["user"], "ann");
+        assert_eq!(redacted["id"], 1);
+    }
+
+    #[test]
+    fn nesting_past_the_limit_is_dropped() {
+        let mut body = json!({"note": "buried"});
+        for _ in 0..MAX_DEPTH + 5 {
+            body = json!({ "a": body });
+        }
+        let text = redact(&body).to_string();
+        assert!(!text.contains("buried"));
+        assert!(text.contains(TOO_DEEP));
+    }
+
+    #[test]
+    fn long_bodies_are_cut_on_a_char_boundary() {
+        let body = json!({"text": "é".repeat(100)}).to_string();
+        let logged = for_log(&body, 10);
+        assert!(logged.starts_with(r#"{"text":""#));
+        assert!(logged.ends_with(&format!("... [{} bytes]", body.len())));
+        assert_eq!(for_log(r#"{"a":1}"#, 10), r#"{"a":1}"#);
+    }
+
+    #[test]
+    fn non_json_is_logged_by_size_only() {
+        assert_eq!(for_log("token=abc", 100), "[9 bytes, not JSON]");
+    }
+}
@@ -0,0 +1,171 @@
+//! A circuit breaker shared by every client message (`MCP_CB_FAILURES`).
+//! After that many retryable failures in a row the upstream is taken to be
+//! down, and messages are answered with an error at once instead of each
+//! working through its own retries. Once the breaker has been open for
+//! `MCP_CB_RESET_MS`, one message is let through as a probe: its success
+//! closes the breaker, its failure opens it for another period.
+
+use std::time::{Duration, Instant};
+
+/// How long the breaker stays open before a probe, when `MCP_CB_RESET_MS`
+/// is not set.
+pub const DEFAULT_RESET_MS: u64 = 30_000;
+
+/// The failure category of messages the open breaker answered.
+pub const CIRCUIT_OPEN: &str = "circuit-open";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Messages go upstream.
+    Closed,
+    /// Messages are answered with an error until the reset time.
+    Open,
+    /// One probe is on its way; other messages are still answered with an
+    /// error.
+    HalfOpen,
+}
+
+impl CircuitState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Closed => "closed",
+            Self::Open => "open",
+            Self::HalfOpen => "half-open",
+        }
+    }
+}
+
+/// Whether a message may go upstream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Admit {
+    Send,
+    /// Send it as the probe of a half-open breaker.
+    Probe,
+    Reject,
+}
+
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    reset: Duration,
+    failures: u32,
+    state: CircuitState,
+    /// When an open breaker lets a probe through, or a half-open one gives
+    /// up on a probe that never settled and lets another through.
+    until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, reset: Duration) -> Self {
+        Self {
+            threshold,
+            reset,
+            failures: 0,
+            state: CircuitState::Closed,
+            until: None,
+        }
+    }
+
+    pub fn state(&self) -> CircuitState {
+        self.state
+    }
+
+    /// Decides on one attempt to send a message.
+    pub fn admit(&mut self, now: Instant) -> Admit {
+        match self.state {
+            CircuitState::Closed => Admit::Send,
+            _ if self.until.is_some_and(|until| now < until) => Admit::Reject,
+            _ => {
+                self.state = CircuitState::HalfOpen;
+                self.until = Some(now + self.reset);
+                Admit::Probe
+            }
+        }
+    }
+
+    /// Whether messages are answered without an attempt, as one whose
+    /// attempt just failed would be.
+    pub fn rejects(&self, now: Instant) -> bool {
+        self.state != CircuitState::Closed && self.until.is_some_and(|until| now < until)
+    }
+
+    /// A message got through. Returns true when that closed the breaker.
+    pub fn on_success(&mut self) -> bool {
+        self.failures = 0;
+        self.until = None;
+        std::mem::replace(&mut self.state, CircuitState::Closed) != CircuitState::Closed
+    }
+
+    /// An attempt failed in a way worth retrying. Returns true when that
+    /// opened the breaker.
+    pub fn on_failure(&mut self, now: Instant) -> bool {
+        let open = match self.state {
+            CircuitState::Closed => {
+                self.failures += 1;
+                self.failures >= self.threshold
+            }
+            // The probe failed.
+            CircuitState::HalfOpen => true,
+            // Sent before the breaker opened; it stays open as it is.
+            CircuitState::Open => false,
+        };
+        if open {
+            self.state = CircuitState::Open;
+            self.until = Some(now + self.reset);
+        }
+        open
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RESET: Duration = Duration::from_secs(10);
+
+    #[test]
+    fn opens_after_the_threshold_in_a_row() {
+        let now = Instant::now();
+        let mut breaker = CircuitBreaker::new(3, RESET);
+        assert!(!breaker.on_failure(now));
+        assert!(!breaker.on_failure(now));
+        assert!(!breaker.on_success());
+        assert!(!breaker.on_failure(now));
+        assert!(!breaker.on_failure(now));
+        assert_eq!(breaker.admit(now), Admit::Send);
+        assert!(breaker.on_failure(now));
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(breaker.rejects(now));
+        assert_eq!(breaker.admit(now + RESET / 2), Admit::Reject);
+        // In-flight failures do not push the probe back.
+        assert!(!breaker.on_failure(now + RESET / 2));
+        assert_eq!(breaker.admit(now + RESET), Admit::Probe);
+    }
+
+    #[test]
+    fn one_probe_at_a_time_closes_or_reopens() {
+        let now = Instant::now();
+        let mut breaker = CircuitBreaker::new(1, RESET);
+        assert!(breaker.on_failure(now));
+        let probe = now + RESET;
+        assert_eq!(breaker.admit(probe), Admit::Probe);
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert_eq!(breaker.admit(probe), Admit::Reject);
+        assert!(breaker.on_failure(probe));
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert_eq!(breaker.admit(probe + RESET / 2), Admit::Reject);
+        assert_eq!(breaker.admit(probe + RESET), Admit::Probe);
+        assert!(breaker.on_success());
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert_eq!(breaker.admit(probe + RESET), Admit::Send);
+    }
+
+    #[test]
+    fn a_probe_that_never_settles_is_replaced() {
+        let now = Instant::now();
+        let mut breaker = CircuitBreaker::new(1, RESET);
+        breaker.on_failure(now);
+        assert_eq!(breaker.admit(now + RESET), Admit::Probe);
+        assert_eq!(breaker.admit(now + RESET * 2), Admit::Probe);
+    }
+}
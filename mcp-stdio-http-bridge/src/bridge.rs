@@ -1,62 +1,1460 @@
-use crate::config::Config;
-use crate::remote_transport::{self, build_client, send_message};
-use tokio::sync::mpsc;
+use crate::aliases::MethodAliases;
+use crate::auth::AuthProvider;
+use crate::balance::{LbStrategy, RoundRobin};
+use crate::breaker::{self, Admit, CircuitBreaker, CircuitState};
+use crate::cache::ResponseCache;
+use crate::capabilities::CapabilityFilter;
+use crate::config::{redact_uri, Config};
+use crate::control::{self, Pause, PauseExpiry};
+use crate::echo::{self, Mode};
+use crate::failover::Failover;
+use crate::health::{self, FailureStreak, KeepAlive, RetrySchedule, StreakLog};
+use crate::get_stream::{self, GetStream};
+use crate::hedge;
+use crate::idempotency;
+use crate::ids::{self, IdMapper};
+use crate::jsonrpc;
+use crate::method_policy::MethodPolicy;
+use crate::notices::RateLimitWarning;
+use crate::ordered::{self, ResponseOrder};
+use crate::otel::{self, Tracer};
+use crate::pending::Waiter;
+use crate::quirks::{self, InitializedAck};
+use crate::remote_transport::{self, build_client, send_message, Response, TransportError};
+use crate::request_body::RequestBody;
+use crate::responder::{Closed, Responder};
+use crate::server_log::LogNotifications;
+use crate::session::{Reinit, Session};
+use crate::stats::{Sample, Stats};
+use crate::stdio::EvictOldest;
+#[cfg(feature = "websocket")]
+use crate::websocket;
+use serde::de::IgnoredAny;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
+use tokio::task::{JoinError, JoinHandle, JoinSet};
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, warn};
 
 const INITIAL_BACKOFF_MS: u64 = 500;
+pub(crate) const TOP_METHODS_LOGGED: usize = 10;
+/// Requests in flight upstream at once (`MCP_MAX_CONCURRENCY`).
+pub const DEFAULT_MAX_CONCURRENCY: usize = 8;
+/// The most `MCP_MAX_CONCURRENCY` may be; larger values are capped to it.
+pub const MAX_CONCURRENCY: usize = 10_000;
+/// How long queued and in-flight requests may keep running once shutdown
+/// has begun (`MCP_SHUTDOWN_GRACE_MS`).
+pub const DEFAULT_SHUTDOWN_GRACE_MS: u64 = 5000;
+/// Retries of one message before it is answered with its last error
+/// (`MCP_MAX_RETRIES`).
+pub const DEFAULT_MAX_RETRIES: u32 = 10;
 
+/// Everything the in-flight requests share with the dispatch loop. The
+/// mutexes are never held across an await.
+struct Shared {
+    config: Arc<Config>,
+    client: remote_transport::Client,
+    auth: Arc<AuthProvider>,
+    session: Arc<Session>,
+    responder: Responder,
+    capabilities: CapabilityFilter,
+    // Answers kept for `MCP_CACHE`; off in raw passthrough, since a hit is
+    // written by the bridge rather than the upstream.
+    cache: Option<Arc<ResponseCache>>,
+    // Write each element of a batch response on a line of its own
+    // (`MCP_SPLIT_BATCH_RESPONSES`); off in raw passthrough.
+    split_batches: bool,
+    stats: Arc<Stats>,
+    // Cancelled once the shutdown grace has run out; whatever is still
+    // queued or in flight is then answered with an error.
+    grace_over: CancellationToken,
+    streak: Mutex<FailureStreak>,
+    rate_limit: Mutex<Option<RateLimitWarning>>,
+    initialized_ack: Mutex<Option<InitializedAck>>,
+    reinit: Reinit,
+    failover: Mutex<Failover>,
+    // Set for `MCP_LB_STRATEGY=round-robin` with more than one endpoint,
+    // which then replaces failover.
+    balancer: Option<Mutex<RoundRobin>>,
+    // Set for `MCP_CB_FAILURES`.
+    breaker: Option<Mutex<CircuitBreaker>>,
+    // Started once the client has finished initializing, dropped (and so
+    // closed) when it starts over or the bridge returns.
+    get_stream: Mutex<Option<get_stream::Running>>,
+    // Set for `MCP_TRACE_CONTEXT` or `MCP_OTEL_EXPORTER`.
+    tracer: Option<Tracer>,
+    // `MCP_MAX_CONCURRENCY`: one per request being dispatched and one per
+    // hedge of it.
+    slots: Arc<Semaphore>,
+}
+
+type InFlight = JoinSet<Result<(), Closed>>;
+
+/// Starts the bridge on its own task, over the transport `config.uri`
+/// calls for, or answering locally with `MCP_MODE=echo`.
+#[allow(clippy::too_many_arguments)]
+pub fn spawn(
+    config: Config,
+    rx: mpsc::Receiver<RequestBody>,
+    evict_oldest: Arc<EvictOldest>,
+    errors: mpsc::Receiver<String>,
+    tx_out: mpsc::Sender<String>,
+    shutdown: CancellationToken,
+    stats: Arc<Stats>,
+    auth: Arc<AuthProvider>,
+) -> JoinHandle<()> {
+    if config.mode == Mode::Echo {
+        return tokio::spawn(echo::run_bridge(rx, evict_oldest, errors, tx_out, stats));
+    }
+    #[cfg(feature = "websocket")]
+    if websocket::is_websocket(&config.uri) {
+        return tokio::spawn(websocket::run_bridge(
+            config,
+            rx,
+            evict_oldest,
+            errors,
+            tx_out,
+            shutdown,
+            stats,
+            auth,
+        ));
+    }
+    tokio::spawn(run_bridge(
+        config,
+        rx,
+        evict_oldest,
+        errors,
+        tx_out,
+        shutdown,
+        stats,
+        auth,
+    ))
+}
+
+/// `errors` carries the reader's answers to lines it refused; they are
+/// written through the bridge's `Responder` like its other answers.
+#[allow(clippy::too_many_arguments)]
 pub async fn run_bridge(
     config: Config,
-    mut rx: mpsc::Receiver<String>,
+    mut rx: mpsc::Receiver<RequestBody>,
+    evict_oldest: Arc<EvictOldest>,
+    errors: mpsc::Receiver<String>,
     tx_out: mpsc::Sender<String>,
-    mut shutdown: tokio::sync::oneshot::Receiver<()>,
+    shutdown: CancellationToken,
+    stats: Arc<Stats>,
+    auth: Arc<AuthProvider>,
 ) {
+    let config = Arc::new(config);
     let client = build_client(&config);
     let name = config
         .mcp_name
         .as_deref()
         .unwrap_or("mcp-stdio-http-bridge");
-    let mut backoff_ms = INITIAL_BACKOFF_MS;
-    while let Some(msg) = rx.recv().await {
-        if shutdown.try_recv().is_ok() {
-            debug!("bridge received shutdown, dropping pending");
+    // Blocking is not a rewrite, so it holds in raw passthrough too.
+    let policy = MethodPolicy::new(&config.allow_methods, &config.deny_methods);
+    // Raw passthrough is decided once here: every content rewriter stays
+    // empty, so message bytes reach the upstream and stdout unchanged.
+    let (aliases, ids, capabilities) = if config.raw_passthrough {
+        (MethodAliases::default(), None, CapabilityFilter::default())
+    } else {
+        (
+            MethodAliases::new(&config.method_aliases),
+            config
+                .rewrite_ids
+                .then(|| IdMapper::new(config.state_table_limit, ids::DEFAULT_TTL)),
+            CapabilityFilter::new(
+                &[config.strip_capabilities.clone(), policy.denied_capabilities()].concat(),
+            ),
+        )
+    };
+    if let Some(ids) = &ids {
+        stats.track_table(ids.gauge());
+    }
+    let cache = (!config.cache.is_empty() && !config.raw_passthrough)
+        .then(|| Arc::new(ResponseCache::new(&config.cache, config.state_table_limit)));
+    if let Some(cache) = &cache {
+        stats.track_table(cache.gauge());
+    }
+    // Passthrough does not look at what it writes, so it cannot tell answers
+    // apart to order them.
+    let order = (config.ordered_responses && !config.raw_passthrough)
+        .then(|| Arc::new(ResponseOrder::new(tx_out.clone(), ordered::BUFFER_LIMIT_BYTES)));
+    // A local notification, so passthrough leaves it out like the others.
+    let rate_limit = config
+        .rate_limit_warn_below
+        .filter(|_| !config.raw_passthrough)
+        .map(RateLimitWarning::new);
+    let initialized_ack = (config.quirk_ack_initialized && !config.raw_passthrough)
+        .then(InitializedAck::default);
+    let session = Arc::new(Session::new(&config.uri));
+    stats.track_session(&session);
+    let shared = Arc::new(Shared {
+        config: config.clone(),
+        client,
+        auth,
+        session,
+        responder: Responder::new(tx_out, stats.clone(), aliases, ids)
+            .with_log_notifications(LogNotifications::for_config(&config))
+            .with_cache(cache.clone())
+            .with_order(order),
+        capabilities,
+        cache,
+        split_batches: config.split_batch_responses && !config.raw_passthrough,
+        stats: stats.clone(),
+        grace_over: CancellationToken::new(),
+        streak: Mutex::new(FailureStreak::new(config.log_summary_interval)),
+        rate_limit: Mutex::new(rate_limit),
+        initialized_ack: Mutex::new(initialized_ack),
+        reinit: Reinit::default(),
+        failover: Mutex::new(Failover::new(
+            &config.uri,
+            &config.fallback_uris,
+            config.failover_after,
+        )),
+        balancer: (config.lb_strategy == LbStrategy::RoundRobin && !config.fallback_uris.is_empty())
+            .then(|| {
+                Mutex::new(RoundRobin::new(
+                    &config.uri,
+                    &config.fallback_uris,
+                    config.lb_cooldown,
+                ))
+            }),
+        breaker: (config.cb_failures > 0)
+            .then(|| Mutex::new(CircuitBreaker::new(config.cb_failures, config.cb_reset))),
+        get_stream: Mutex::new(None),
+        tracer: Tracer::new(&config),
+        slots: Arc::new(Semaphore::new(config.max_concurrency)),
+    });
+    if shared.breaker.is_some() {
+        stats.set_circuit(CircuitState::Closed);
+    }
+    let responder = &shared.responder;
+    tokio::spawn(responder.clone().forward_errors(errors));
+    let grace_over = &shared.grace_over;
+    let _end_grace_on_return = grace_over.clone().drop_guard();
+    tokio::spawn(end_grace(
+        shutdown.clone(),
+        grace_over.clone(),
+        config.shutdown_grace,
+    ));
+    let mut keepalive = KeepAlive::new(config.keepalive_interval);
+    let mut probes: u64 = 0;
+    // At most one probe runs, beside the loop so that no client message
+    // waits for it. Dropping the set when the bridge returns aborts it.
+    let mut probe: JoinSet<()> = JoinSet::new();
+    // Control methods are local responders, so passthrough turns them off too.
+    let control = config.control_methods && !config.raw_passthrough;
+    let validate_json = config.validate_stdin_json && !config.raw_passthrough;
+    let mut pause = Pause::new(config.max_pause);
+    let mut held: VecDeque<RequestBody> = VecDeque::new();
+    let mut in_flight = InFlight::new();
+    loop {
+        let free = shared.slots.available_permits() > 0;
+        let released = if pause.is_paused() || !free {
+            None
+        } else {
+            held.pop_front()
+        };
+        let msg = match released {
+            Some(msg) => msg,
+            None => tokio::select! {
+                biased;
+                _ = grace_over.cancelled() => break,
+                Some(done) = in_flight.join_next() => {
+                    keepalive.touch();
+                    if finished(done).is_err() {
+                        return;
+                    }
+                    continue;
+                }
+                msg = rx.recv(), if free => match msg {
+                    Some(msg) => msg,
+                    None => break,
+                },
+                evict = evict_oldest.requested() => {
+                    if evict && drop_oldest(responder, &mut rx).await.is_err() {
+                        return;
+                    }
+                    continue;
+                }
+                expiry = pause.expired() => {
+                    if expiry == PauseExpiry::MaxPause {
+                        warn!("pause reached MCP_MAX_PAUSE_SECS, resuming automatically");
+                    }
+                    resume(&mut pause, &held, &stats);
+                    continue;
+                }
+                Some(_) = probe.join_next() => continue,
+                _ = keepalive.idle(),
+                    if !shutdown.is_cancelled()
+                        && !pause.is_paused()
+                        && in_flight.is_empty()
+                        && probe.is_empty() =>
+                {
+                    // Asked on each tick rather than in the guard, which
+                    // nothing would evaluate again once the reset is due.
+                    // Past it, the probe is the circuit breaker's.
+                    if admit(&shared) == Admit::Reject {
+                        debug!("circuit breaker open, skipping the keep-alive probe");
+                        keepalive.touch();
+                        continue;
+                    }
+                    probes += 1;
+                    probe.spawn(keepalive_probe(shared.clone(), shutdown.clone(), probes));
+                    keepalive.touch();
+                    continue;
+                }
+            },
+        };
+        if grace_over.is_cancelled() {
+            held.push_front(msg);
             break;
         }
-        loop {
-            match send_message(&config, &client, &msg).await {
-                Ok(responses) => {
-                    backoff_ms = INITIAL_BACKOFF_MS;
-                    for line in responses {
-                        if tx_out.send(line).await.is_err() {
-                            return;
-                        }
+        // A hedge may have taken the last slot since it was checked. It
+        // gives the slot back before its request's task ends, which wakes
+        // this loop.
+        let Ok(slot) = shared.slots.clone().try_acquire_owned() else {
+            held.push_front(msg);
+            continue;
+        };
+        stats.note_activity();
+        // Refused before any rewriter parses it. Passthrough never parses
+        // message bodies, so it forwards deep messages untouched.
+        if !config.raw_passthrough && jsonrpc::too_deep(&msg.text(), config.max_json_depth) {
+            warn!(limit = config.max_json_depth, "refusing client message nested too deeply");
+            let err = jsonrpc::error_response(
+                None,
+                jsonrpc::PARSE_ERROR,
+                &depth_message("message", config.max_json_depth),
+            );
+            if responder.local(err).await.is_err() {
+                return;
+            }
+            continue;
+        }
+        // A truncated or garbled line would only earn an HTML error page
+        // from the upstream. Valid JSON that is not JSON-RPC still goes out.
+        if validate_json {
+            if let Err(e) = serde_json::from_str::<IgnoredAny>(&msg.text()) {
+                warn!(%e, "refusing client line that is not valid JSON");
+                let message = format!("invalid JSON: {}", e);
+                let err = jsonrpc::error_response(None, jsonrpc::PARSE_ERROR, &message);
+                if responder.local(err).await.is_err() {
+                    return;
+                }
+                continue;
+            }
+        }
+        if control {
+            if let Some(reply) = local_control(&msg, &mut pause, &mut held, &stats) {
+                if let Some(reply) = reply {
+                    if responder.local(reply).await.is_err() {
+                        return;
                     }
-                    break;
                 }
-                Err(e) if remote_transport::is_retryable(&e) => {
-                    warn!(%e, "remote request failed, retrying with backoff");
-                    let delay = std::time::Duration::from_millis(backoff_ms);
-                    tokio::select! {
-                        _ = tokio::time::sleep(delay) => {}
-                        _ = &mut shutdown => {
-                            let err_msg = serde_json::json!({"jsonrpc":"2.0","error":{"code":-32603,"message":"bridge shutdown during retry"}}).to_string();
-                            if let Err(_) = tx_out.send(err_msg).await {}
-                            return;
+                continue;
+            }
+        }
+        match policy.refuse(&msg.text(), responder).await {
+            Ok(true) => continue,
+            Ok(false) => {}
+            Err(Closed) => return,
+        }
+        // A line that is not UTF-8 (`MCP_STDIN_VALIDATION=bytes`) goes
+        // upstream as it was read: nothing rewrites what it cannot parse.
+        // A rewritten line keeps its place in the listener's budget.
+        let admission = msg.admission();
+        let cancelled = jsonrpc::cancelled_request(&msg.text());
+        let msg = match msg.into_string() {
+            Ok(msg) => match responder.to_server(msg).await {
+                Ok(msg) => RequestBody::from(msg).admitted(admission),
+                Err(Closed) => return,
+            },
+            Err(raw) => raw,
+        };
+        // The notification is still forwarded, so the upstream can stop
+        // its own work too. It is rewritten first: the aborted call drops
+        // the id mapping it needs.
+        if let Some(target) = cancelled {
+            if responder.cancel(&target) {
+                debug!(request_id = %target, "client cancelled request, aborting its upstream call");
+            } else {
+                debug!(request_id = %target, "client cancelled a request that is not in flight");
+            }
+        }
+        // The handshake runs on its own: nothing may overtake the session it
+        // sets up, and it may not start while the old session is in use.
+        let exclusive = config.max_concurrency == 1
+            || matches!(
+                jsonrpc::method(&msg.text()).as_deref(),
+                Some("initialize" | quirks::INITIALIZED_METHOD)
+            );
+        // A probe sent under the old session could otherwise record it again
+        // once the handshake has replaced it.
+        if exclusive {
+            probe.shutdown().await;
+        }
+        if exclusive
+            && drain_evicting(&mut in_flight, responder, &mut rx, &evict_oldest)
+                .await
+                .is_err()
+        {
+            return;
+        }
+        // Tracked before the task runs, so a cancellation read next finds it.
+        let waiter = responder.track(&msg.text());
+        let order = responder.order().filter(|_| waiter.is_some()).cloned();
+        let task = dispatch(shared.clone(), msg, waiter, slot);
+        match order {
+            Some(order) => {
+                let seq = order.next();
+                in_flight.spawn(ordered::answering(order, seq, task))
+            }
+            None => in_flight.spawn(task),
+        };
+        if exclusive {
+            let drained = drain_evicting(&mut in_flight, responder, &mut rx, &evict_oldest).await;
+            keepalive.touch();
+            if drained.is_err() {
+                return;
+            }
+        }
+    }
+    // Stdin is closed or the grace is over: nothing queued will be sent now.
+    if abandon_queued(responder, held, &mut rx).await.is_err() {
+        return;
+    }
+    // Requests already sent run to completion, or until the shutdown grace
+    // answers them.
+    if drain(&mut in_flight).await.is_err() {
+        return;
+    }
+    // A request whose task panicked leaves the answers behind it held.
+    if let Some(order) = responder.order() {
+        if order.release().await.is_err() {
+            return;
+        }
+    }
+    // The GET stream is the last holder of the stdout queue besides
+    // `shared`, which goes when this returns and so closes the queue.
+    let stream = shared.get_stream.lock().unwrap().take();
+    if let Some(stream) = stream {
+        stream.stop().await;
+    }
+    if let Some(tracer) = &shared.tracer {
+        tracer.shutdown().await;
+    }
+    info!(%name, top_methods = %stats.top_summary(TOP_METHODS_LOGGED), "bridge finished");
+}
+
+/// Answers every message still held or queued with a shutdown error.
+pub(crate) async fn abandon_queued(
+    responder: &Responder,
+    held: VecDeque<RequestBody>,
+    rx: &mut mpsc::Receiver<RequestBody>,
+) -> Result<(), Closed> {
+    rx.close();
+    let mut abandoned = 0;
+    for msg in held {
+        abandoned += 1;
+        responder.unsent(&msg.text(), "bridge shutting down").await?;
+    }
+    while let Some(msg) = rx.recv().await {
+        abandoned += 1;
+        responder.unsent(&msg.text(), "bridge shutting down").await?;
+    }
+    if abandoned > 0 {
+        warn!(abandoned, "answering queued messages that will not be sent");
+    }
+    Ok(())
+}
+
+/// Cancels `grace_over` once `grace` has passed since `shutdown`.
+pub(crate) async fn end_grace(
+    shutdown: CancellationToken,
+    grace_over: CancellationToken,
+    grace: Duration,
+) {
+    tokio::select! {
+        _ = grace_over.cancelled() => {}
+        _ = async {
+            shutdown.cancelled().await;
+            tokio::time::sleep(grace).await;
+        } => grace_over.cancel(),
+    }
+}
+
+/// Waits for every in-flight request. Fails once one found stdout closed.
+async fn drain(in_flight: &mut InFlight) -> Result<(), Closed> {
+    while let Some(done) = in_flight.join_next().await {
+        finished(done)?;
+    }
+    Ok(())
+}
+
+/// `drain` that keeps answering the reader's `EvictOldest` requests, so
+/// `MCP_QUEUE_POLICY=drop-oldest` works while a request runs on its own.
+async fn drain_evicting(
+    in_flight: &mut InFlight,
+    responder: &Responder,
+    rx: &mut mpsc::Receiver<RequestBody>,
+    evict_oldest: &EvictOldest,
+) -> Result<(), Closed> {
+    loop {
+        tokio::select! {
+            biased;
+            done = in_flight.join_next() => match done {
+                Some(done) => finished(done)?,
+                None => return Ok(()),
+            },
+            evict = evict_oldest.requested() => {
+                if evict {
+                    drop_oldest(responder, rx).await?;
+                }
+            }
+        }
+    }
+}
+
+/// Answers the oldest queued message with a busy error in place of sending
+/// it, making room for the one the stdin reader is waiting to queue.
+pub(crate) async fn drop_oldest(
+    responder: &Responder,
+    rx: &mut mpsc::Receiver<RequestBody>,
+) -> Result<(), Closed> {
+    let Ok(old) = rx.try_recv() else {
+        return Ok(());
+    };
+    debug!("dropping oldest queued message, stdin queue full");
+    let busy = "server busy: dropped from full bridge queue";
+    match jsonrpc::error_for(&old.text(), jsonrpc::SERVER_BUSY, busy) {
+        Some(err) => responder.local(err).await,
+        None => Ok(()),
+    }
+}
+
+fn finished(done: Result<Result<(), Closed>, JoinError>) -> Result<(), Closed> {
+    match done {
+        Ok(result) => result,
+        Err(e) => {
+            error!(%e, "request task failed");
+            Ok(())
+        }
+    }
+}
+
+/// Sends one client message upstream, retrying as needed, and relays
+/// whatever comes back. Runs on its own task, holding `_slot` until done.
+async fn dispatch(
+    shared: Arc<Shared>,
+    msg: RequestBody,
+    waiter: Option<Arc<Waiter>>,
+    _slot: OwnedSemaphorePermit,
+) -> Result<(), Closed> {
+    let _in_flight = shared.stats.start_in_flight();
+    let span = shared.tracer.as_ref().map(|tracer| tracer.start(&msg.text()));
+    let deadline = shared.config.request_deadline;
+    let send = idempotency::within(
+        &shared.config,
+        otel::within(span.clone(), send_and_relay(&shared, &msg, waiter.as_deref())),
+    );
+    // Dropping the send aborts its HTTP request and stops its retries.
+    let result = match &waiter {
+        Some(waiter) => tokio::select! {
+            result = send => result,
+            // The client expects no answer to a request it cancelled, so
+            // its ids are not kept to restore one.
+            _ = waiter.cancelled() => {
+                shared.responder.forget(&msg.text());
+                Ok(())
+            }
+            _ = waiter.unanswered_for(deadline), if !deadline.is_zero() => {
+                warn!(
+                    deadline_ms = deadline.as_millis() as u64,
+                    "no response within MCP_REQUEST_DEADLINE_MS, giving up on message"
+                );
+                shared.stats.note_failure_category("deadline");
+                let message = format!(
+                    "bridge gave up: no response within {} ms (MCP_REQUEST_DEADLINE_MS)",
+                    deadline.as_millis()
+                );
+                let error = jsonrpc::error_object(jsonrpc::INTERNAL_ERROR, &message);
+                give_up(&shared, &msg.text(), &error).await
+            }
+        },
+        None => send.await,
+    };
+    if let Some((tracer, span)) = shared.tracer.as_ref().zip(span) {
+        tracer.finish(&span);
+    }
+    if let Some(waiter) = &waiter {
+        shared.responder.untrack(waiter);
+    }
+    shared.responder.settle(&msg.text());
+    result
+}
+
+async fn send_and_relay(
+    shared: &Shared,
+    body: &RequestBody,
+    waiter: Option<&Waiter>,
+) -> Result<(), Closed> {
+    let Shared {
+        config,
+        client,
+        auth,
+        session,
+        responder,
+        capabilities,
+        stats,
+        ..
+    } = shared;
+    let text = body.text();
+    let msg: &str = &text;
+    let method = jsonrpc::method(msg);
+    let initialize = method.as_deref() == Some("initialize");
+    // Lines that are not UTF-8 can read the same without being the same.
+    let cacheable = body.as_str().is_some();
+    if let Some(cache) = &shared.cache {
+        if initialize {
+            cache.clear();
+        } else if let Some(hit) = cacheable
+            .then(|| cache.lookup(msg, Instant::now()))
+            .flatten()
+        {
+            debug!(?method, "answering from MCP_CACHE");
+            return responder.relay(hit).await;
+        }
+    }
+    // Answers to requests sent before an invalidation are not cached.
+    let generation = shared
+        .cache
+        .as_ref()
+        .filter(|_| cacheable)
+        .map(|c| c.generation());
+    if initialize {
+        shared.reinit.remember(msg);
+        *shared.get_stream.lock().unwrap() = None;
+        // A new session: the upstream assigns its id in the response.
+        if let Some(old) = session.clear() {
+            debug!(session = %old, "client re-initializing, dropping upstream session");
+        }
+    }
+    let hedged = method
+        .as_ref()
+        .is_some_and(|m| config.hedge_methods.contains(m));
+    let retry_allowed = config.retry_methods.allows(msg);
+    let started = Instant::now();
+    let record = |bytes_in: u64, error: bool| {
+        stats.record(Sample {
+            method: method.as_deref(),
+            latency: started.elapsed(),
+            bytes_out: body.len() as u64,
+            bytes_in,
+            error,
+        })
+    };
+    let mut integrity_failures = 0;
+    let mut failed_attempts: u32 = 0;
+    let grace = shared.grace_over.cancelled();
+    tokio::pin!(grace);
+    let mut retries = RetrySchedule::new(
+        Duration::from_millis(INITIAL_BACKOFF_MS),
+        config.max_backoff,
+        config.backoff_jitter,
+        config.timeout,
+        config.total_budget,
+    );
+    loop {
+        let admitted = admit(shared);
+        if admitted == Admit::Reject {
+            debug!(?method, "circuit breaker open, not sending");
+            stats.note_failure_category(breaker::CIRCUIT_OPEN);
+            record(0, true);
+            let error = jsonrpc::error_object(
+                jsonrpc::INTERNAL_ERROR,
+                "upstream unavailable (circuit breaker open)",
+            );
+            return give_up(shared, msg, &error).await;
+        }
+        let timeout = retries.attempt_timeout();
+        // Round-robin gives each attempt an endpoint of its own and no
+        // session, since a session id is only valid on one endpoint.
+        let routed = shared
+            .balancer
+            .as_ref()
+            .map(|lb| shared.session.routed(&lb.lock().unwrap().pick(Instant::now())));
+        let session = routed.as_ref().unwrap_or(shared.session.as_ref());
+        let sent_session = session.id();
+        let sent_endpoint = session.endpoint();
+        let res = tokio::select! {
+            res = async {
+                // The probe of a half-open breaker is one request.
+                if hedged && admitted != Admit::Probe {
+                    let attempt = || async {
+                        send_message(config, client, auth, session, body, timeout, stats)
+                            .await?
+                            .collect()
+                            .await
+                    };
+                    hedge::send_hedged(attempt, config.hedge_delay, stats, &shared.slots)
+                        .await
+                        .map(Response::from_lines)
+                } else {
+                    send_message(config, client, auth, session, body, timeout, stats).await
+                }
+            } => res,
+            _ = &mut grace => {
+                warn!("in-flight request outlived shutdown grace, abandoning");
+                return responder.terminated(msg, "bridge terminated").await;
+            }
+        };
+        // Messages are relayed as they arrive, so a long-running call's
+        // progress notifications reach the client before its result.
+        let res = match res {
+            Ok(mut response) => {
+                let mut bytes_in = 0;
+                let mut received = false;
+                let mut answered = false;
+                let mut depth_reported = false;
+                let mut resumed = 0;
+                let end = loop {
+                    let next = tokio::select! {
+                        next = response.next() => next,
+                        _ = &mut grace => {
+                            warn!("in-flight request outlived shutdown grace, abandoning");
+                            return responder.terminated(msg, "bridge terminated").await;
+                        }
+                    };
+                    let line = match next {
+                        Ok(Some(line)) => line,
+                        Ok(None) => break Ok(bytes_in),
+                        Err(e) => {
+                            // A stream that dropped part way resumes after
+                            // its last event id, if the upstream sent one.
+                            let resumable = !answered
+                                && resumed < config.sse_resume_max
+                                && remote_transport::is_dropped_stream(&e);
+                            let Some(from) = response.resume_from().filter(|_| resumable) else {
+                                break Err(e);
+                            };
+                            resumed += 1;
+                            warn!(
+                                %e,
+                                last_event_id = from.last_event_id(),
+                                attempt = resumed,
+                                "upstream stream interrupted, resuming"
+                            );
+                            let resume = remote_transport::resume_stream(
+                                config, client, auth, session, from, stats,
+                            );
+                            match resume.await {
+                                Ok(next) => response = next,
+                                Err(resume_error) => {
+                                    warn!(%resume_error, "could not resume the upstream stream");
+                                    break Err(e);
+                                }
+                            }
+                            continue;
+                        }
+                    };
+                    bytes_in += line.len() as u64;
+                    received = true;
+                    if let Some(waiter) = waiter {
+                        waiter.heard();
+                    }
+                    if !answered && jsonrpc::method(&line).is_none() {
+                        let id = jsonrpc::request_id(&line);
+                        answered = id.is_some() && id == jsonrpc::request_id(msg);
+                        if let Some((cache, generation)) = shared.cache.as_ref().zip(generation) {
+                            if answered {
+                                cache.store(msg, &line, generation, Instant::now());
+                            }
                         }
                     }
-                    backoff_ms = (backoff_ms * 2).min(config.max_backoff.as_millis() as u64);
+                    if !config.raw_passthrough && jsonrpc::too_deep(&line, config.max_json_depth)
+                    {
+                        warn!(
+                            limit = config.max_json_depth,
+                            "dropping upstream message nested too deeply"
+                        );
+                        if !depth_reported {
+                            depth_reported = true;
+                            let message =
+                                depth_message("upstream response", config.max_json_depth);
+                            responder.error(msg, jsonrpc::PARSE_ERROR, &message).await?;
+                        }
+                        continue;
+                    }
+                    if let Some(rejection) = capabilities.rejection(&line) {
+                        debug!("rejecting server request for a stripped capability");
+                        let timeout = config.timeout;
+                        let rejection = RequestBody::from(rejection);
+                        let sent = send_message(
+                            config, client, auth, session, &rejection, timeout, stats,
+                        );
+                        if let Err(e) = on_own_behalf(sent).await {
+                            warn!(%e, "could not send capability rejection upstream");
+                        }
+                        continue;
+                    }
+                    let line = if initialize {
+                        capabilities.strip(&line).unwrap_or(line)
+                    } else {
+                        line
+                    };
+                    if initialize {
+                        if let Some(ack) = shared.initialized_ack.lock().unwrap().as_mut() {
+                            ack.on_initialize_response(&line);
+                        }
+                        if let Some(version) = jsonrpc::protocol_version(&line) {
+                            shared.session.negotiated(&version);
+                        }
+                    }
+                    // An SSE event or NDJSON line is one message by
+                    // definition; only a plain JSON body is a batch to split.
+                    if shared.split_batches && !response.is_stream() {
+                        match jsonrpc::split_batch(&line) {
+                            Ok(Some(items)) => {
+                                for item in items {
+                                    responder.relay(item).await?;
+                                }
+                                continue;
+                            }
+                            Ok(None) => {}
+                            Err(e) => {
+                                warn!(%e, "batch response is not valid JSON, forwarding it whole")
+                            }
+                        }
+                    }
+                    responder.relay(line).await?;
+                };
+                match end {
+                    Err(e) if answered => {
+                        warn!(%e, "upstream stream failed after the response, ignoring");
+                        Ok(bytes_in)
+                    }
+                    Err(e) if received => {
+                        // Part of the answer reached the client already, so
+                        // a retry would repeat it.
+                        error!(%e, "upstream stream interrupted");
+                        note_gave_up(stats, &e);
+                        record(bytes_in, true);
+                        let error = failure(&e, Some("stream interrupted"));
+                        return give_up(shared, msg, &error).await;
+                    }
+                    end => end,
+                }
+            }
+            Err(e) => Err(e),
+        };
+        match res {
+            Ok(bytes_in) => {
+                note_success(shared);
+                note_breaker_success(shared);
+                note_endpoint_success(shared, &sent_endpoint);
+                shared.reinit.succeeded();
+                record(bytes_in, false);
+                let initialized = method.as_deref() == Some(quirks::INITIALIZED_METHOD);
+                if initialized && config.get_stream {
+                    let mut get_stream = shared.get_stream.lock().unwrap();
+                    if get_stream.is_none() {
+                        let stream = GetStream {
+                            config: config.clone(),
+                            client: client.clone(),
+                            auth: auth.clone(),
+                            session: shared.session.clone(),
+                            capabilities: capabilities.clone(),
+                            push: responder.push(),
+                            stats: stats.clone(),
+                        };
+                        *get_stream = Some(stream.spawn());
+                    }
+                }
+                let ack = match shared.initialized_ack.lock().unwrap().as_mut() {
+                    Some(ack) if initialized => ack.on_initialized(),
+                    _ => None,
+                };
+                if let Some(ack) = ack {
+                    responder.local(ack).await?;
                 }
-                Err(e) => {
-                    error!(%e, "remote request failed (non-retryable)");
-                    let err_body = serde_json::json!({
-                        "jsonrpc": "2.0",
-                        "error": { "code": -32603, "message": format!("bridge transport error: {}", e) }
-                    });
-                    let _ = tx_out.send(err_body.to_string()).await;
-                    break;
+                let (remaining, reset) = stats.rate_limit();
+                let notice = shared
+                    .rate_limit
+                    .lock()
+                    .unwrap()
+                    .as_mut()
+                    .and_then(|w| w.check(remaining, reset));
+                if let Some(notice) = notice {
+                    warn!(remaining, "upstream rate limit nearly exhausted");
+                    responder.local(notice).await?;
                 }
+                return Ok(());
             }
+            Err(e) if should_retry(&e, &mut integrity_failures, config) => {
+                // The attempt may have run the request already; only one
+                // that never went out can be sent again.
+                if !retry_allowed && !remote_transport::never_sent(&e) {
+                    warn!(%e, ?method, "remote request failed, not retrying a method outside MCP_RETRY_METHODS");
+                    note_gave_up(stats, &e);
+                    record(0, true);
+                    let context = "not retried: the method is not in MCP_RETRY_METHODS";
+                    return give_up(shared, msg, &failure(&e, Some(context))).await;
+                }
+                match note_failure(shared, &e) {
+                    StreakLog::Warn => warn!(%e, "remote request failed, retrying with backoff"),
+                    StreakLog::Suppressed => {
+                        debug!(%e, "remote request failed, retrying with backoff")
+                    }
+                    summary => log_summary(&e, summary),
+                }
+                if note_breaker_failure(shared, &e) {
+                    note_gave_up(stats, &e);
+                    record(0, true);
+                    return give_up(shared, msg, &failure(&e, Some("circuit breaker open"))).await;
+                }
+                failed_attempts += 1;
+                if config.max_retries != 0 && failed_attempts > config.max_retries {
+                    warn!(%e, attempts = failed_attempts, "MCP_MAX_RETRIES exhausted, giving up on message");
+                    note_gave_up(stats, &e);
+                    record(0, true);
+                    let context = format!("gave up after {} attempts", failed_attempts);
+                    return give_up(shared, msg, &failure(&e, Some(&context))).await;
+                }
+                if let Some(next) = note_endpoint_failure(shared, &sent_endpoint) {
+                    fail_over(shared, &sent_endpoint, &next, initialize).await;
+                    stats.note_retry();
+                    continue;
+                }
+                // A server-directed wait replaces the backoff for this
+                // attempt, so it does not grow the next one.
+                let delay = match &e {
+                    TransportError::RateLimited {
+                        retry_after: Some(wait),
+                        ..
+                    } => retries.server_delay(*wait),
+                    _ => retries.next_delay(),
+                };
+                let Some(delay) = delay else {
+                    warn!(%e, "MCP_TOTAL_BUDGET_MS exhausted, giving up on message");
+                    note_gave_up(stats, &e);
+                    record(0, true);
+                    let budget = format!(
+                        "MCP_TOTAL_BUDGET_MS of {} ms exhausted",
+                        config.total_budget.as_millis()
+                    );
+                    return give_up(shared, msg, &failure(&e, Some(&budget))).await;
+                };
+                stats.note_retry();
+                tokio::select! {
+                    _ = tokio::time::sleep(delay) => {}
+                    _ = shared.grace_over.cancelled() => {
+                        return responder.terminated(msg, "bridge shutdown during retry").await;
+                    }
+                }
+            }
+            Err(TransportError::SessionExpired) if !initialize => {
+                match reinitialize(shared, sent_session).await {
+                    Ok(true) => continue,
+                    Ok(false) => {}
+                    Err(e) => {
+                        warn!(%e, "could not re-initialize expired upstream session");
+                        note_gave_up(stats, &e);
+                        record(0, true);
+                        let error = failure(&e, Some("re-initializing expired session"));
+                        return give_up(shared, msg, &error).await;
+                    }
+                }
+                let e = TransportError::SessionExpired;
+                error!(%e, "remote request failed (non-retryable)");
+                note_gave_up(stats, &e);
+                record(0, true);
+                return give_up(shared, msg, &failure(&e, None)).await;
+            }
+            Err(e) => {
+                error!(%e, "remote request failed (non-retryable)");
+                note_gave_up(stats, &e);
+                record(0, true);
+                // Answered under its id so the client can stop waiting, and
+                // after an expired session tell which request to repeat.
+                return give_up(shared, msg, &failure(&e, None)).await;
+            }
+        }
+    }
+}
+
+/// Replaces the session the upstream expired by replaying the client's
+/// `initialize` and `notifications/initialized`, their responses discarded.
+/// `Ok(false)` when it may not: the client never initialized, or
+/// `MCP_SESSION_REINIT_MAX` re-initializations ran without a request
+/// succeeding. Requests that find the session expired together wait for one
+/// of them to replace it.
+async fn reinitialize(shared: &Shared, expired: Option<String>) -> Result<bool, TransportError> {
+    let Shared {
+        config,
+        client,
+        auth,
+        session,
+        stats,
+        ..
+    } = shared;
+    let _running = shared.reinit.running.lock().await;
+    if session.id().is_some() {
+        return Ok(true);
+    }
+    let Some((initialize, attempt)) = shared.reinit.next(config.session_reinit_max) else {
+        return Ok(false);
+    };
+    let Ok(mut initialize) = serde_json::from_str::<Value>(&initialize) else {
+        return Ok(false);
+    };
+    initialize["id"] = json!(format!("bridge-reinit-{}", attempt));
+    let initialized = json!({"jsonrpc": "2.0", "method": quirks::INITIALIZED_METHOD});
+    for body in [initialize, initialized] {
+        let body = RequestBody::from(body.to_string());
+        let lines = on_own_behalf(async {
+            send_message(config, client, auth, session, &body, config.timeout, stats)
+                .await?
+                .collect()
+                .await
+        });
+        for line in lines.await? {
+            if let Some(version) = jsonrpc::protocol_version(&line) {
+                session.negotiated(&version);
+            }
+        }
+    }
+    info!(
+        old_session = expired.as_deref().unwrap_or("-"),
+        new_session = session.id().as_deref().unwrap_or("-"),
+        attempt,
+        "re-initialized expired upstream session"
+    );
+    Ok(true)
+}
+
+/// Runs a send the bridge makes on its own while handling a client message
+/// outside that message's scope, so it carries neither its idempotency key
+/// nor its trace context, and is not counted as one of its attempts.
+async fn on_own_behalf<F: Future>(task: F) -> F::Output {
+    idempotency::without(otel::within(None, task)).await
+}
+
+/// Returns the endpoint to fail over to, if `endpoint` has now failed often
+/// enough; round-robin instead takes it out of the rotation for a while.
+fn note_endpoint_failure(shared: &Shared, endpoint: &str) -> Option<String> {
+    let Some(balancer) = &shared.balancer else {
+        return shared.failover.lock().unwrap().on_failure(endpoint);
+    };
+    if balancer.lock().unwrap().on_failure(endpoint, Instant::now()) {
+        warn!(
+            uri = %redact_uri(endpoint),
+            cooldown_ms = shared.config.lb_cooldown.as_millis() as u64,
+            "upstream endpoint failing, skipping it for the cooldown"
+        );
+    }
+    None
+}
+
+fn note_endpoint_success(shared: &Shared, endpoint: &str) {
+    match &shared.balancer {
+        Some(balancer) => {
+            if balancer.lock().unwrap().on_success(endpoint) {
+                info!(uri = %redact_uri(endpoint), "upstream endpoint answering again");
+            }
+        }
+        None => shared.failover.lock().unwrap().on_success(endpoint),
+    }
+}
+
+/// Sends every later request to `next`, as `from` keeps failing. The
+/// session does not carry over, so one is set up there by replaying the
+/// client's `initialize`, unless that is the request being retried.
+async fn fail_over(shared: &Shared, from: &str, next: &str, initialize: bool) {
+    warn!(
+        from = %redact_uri(from),
+        to = %redact_uri(next),
+        "upstream keeps failing, failing over"
+    );
+    let old = shared.session.move_to(next);
+    if initialize {
+        return;
+    }
+    if let Err(e) = reinitialize(shared, old).await {
+        warn!(%e, "could not set up a session on the new upstream");
+    }
+}
+
+/// The `error` answering a request given up on after `e`, with `context`
+/// appended to its message. Upstream status failures get the code
+/// `remote_transport::status_error` picks; anything else is -32603.
+fn failure(e: &TransportError, context: Option<&str>) -> Value {
+    let mut error = match e {
+        TransportError::Status(status, body) => remote_transport::status_error(*status, body),
+        TransportError::RateLimited { status, .. } => remote_transport::status_error(*status, ""),
+        TransportError::Unauthorized { body, challenge } => {
+            remote_transport::unauthorized_error(body, challenge.as_deref())
+        }
+        e => {
+            let message = format!("bridge transport error: {}", e);
+            jsonrpc::error_object(jsonrpc::INTERNAL_ERROR, &message)
+        }
+    };
+    if let Some(context) = context {
+        let message = error["message"].as_str().unwrap_or_default();
+        error["message"] = Value::String(format!("{} ({})", message, context));
+    }
+    error
+}
+
+/// Reports a request the bridge gave up on with an error under its id. A
+/// notification must never get a response, so its failure is only logged.
+async fn give_up(shared: &Shared, msg: &str, error: &Value) -> Result<(), Closed> {
+    if !jsonrpc::expects_response(msg) {
+        let method = jsonrpc::method(msg);
+        let message = error["message"].as_str().unwrap_or_default();
+        warn!(?method, "{}; notifications get no answer", message);
+        return Ok(());
+    }
+    shared.responder.error_object(msg, error).await
+}
+
+/// Handles `bridge/pause`, `bridge/resume` and `bridge/stats`, and holds
+/// client traffic while paused: pings are answered locally, a cancellation
+/// drops the held request it refers to or, when none is held, goes on to
+/// cancel one in flight, and everything else is queued in arrival order.
+/// Returns `None` for messages to dispatch now, otherwise the optional
+/// reply for the client.
+fn local_control(
+    msg: &RequestBody,
+    pause: &mut Pause,
+    held: &mut VecDeque<RequestBody>,
+    stats: &Stats,
+) -> Option<Option<String>> {
+    let value: Option<Value> = serde_json::from_str(&msg.text()).ok();
+    let method = value
+        .as_ref()
+        .and_then(|v| v.get("method"))
+        .and_then(Value::as_str);
+    let id = value
+        .as_ref()
+        .and_then(|v| v.get("id"))
+        .filter(|id| !id.is_null());
+    let reply = |result: Value| {
+        id.map(|id| json!({"jsonrpc": "2.0", "id": id, "result": result}).to_string())
+    };
+    match method {
+        Some(control::PAUSE_METHOD) => {
+            let resume_after = value.as_ref().and_then(control::resume_after);
+            pause.pause(resume_after);
+            stats.set_paused(true);
+            info!(
+                resume_after_ms = ?resume_after.map(|d| d.as_millis()),
+                "bridge paused"
+            );
+            return Some(reply(json!({ "paused": true })));
+        }
+        Some(control::RESUME_METHOD) => {
+            let released = held.len();
+            resume(pause, held, stats);
+            return Some(reply(json!({ "paused": false, "released": released })));
+        }
+        // Answered while paused too, so an operator can watch a pause.
+        Some(control::STATS_METHOD) => return Some(reply(stats.snapshot())),
+        _ => {}
+    }
+    if !pause.is_paused() {
+        return None;
+    }
+    match method {
+        Some("ping") => Some(reply(json!({}))),
+        // A cancellation that names no request is held like any other
+        // message, rather than dropping every held message without an id.
+        Some("notifications/cancelled")
+            if value.as_ref().and_then(|v| v["params"].get("requestId")).is_some() =>
+        {
+            let target = value.as_ref().and_then(|v| v["params"].get("requestId"));
+            let before = held.len();
+            held.retain(|m| jsonrpc::request_id(&m.text()).as_ref() != target);
+            // A request already in flight is aborted and the cancellation
+            // forwarded, paused or not.
+            (held.len() < before).then_some(None)
+        }
+        _ => {
+            // Held messages give their place in the listener's budget back,
+            // so a paused client does not stall the others.
+            held.push_back(msg.clone().admitted(None));
+            Some(None)
+        }
+    }
+}
+
+fn resume(pause: &mut Pause, held: &VecDeque<RequestBody>, stats: &Stats) {
+    if let Some(paused_for) = pause.resume() {
+        stats.set_paused(false);
+        info!(
+            "bridge resumed after {} ms, releasing {} held messages",
+            paused_for.as_millis(),
+            held.len()
+        );
+    }
+}
+
+fn note_success(shared: &Shared) {
+    let recovered = shared.streak.lock().unwrap().on_success(Instant::now());
+    if let Some((failures, elapsed)) = recovered {
+        shared.stats.end_failure_streak(failures, elapsed);
+        info!(
+            "upstream recovered after {} failures over {} seconds",
+            failures,
+            elapsed.as_secs()
+        );
+    }
+}
+
+/// Asks the circuit breaker whether an attempt may go upstream; always
+/// without one.
+fn admit(shared: &Shared) -> Admit {
+    let Some(breaker) = &shared.breaker else {
+        return Admit::Send;
+    };
+    let admit = breaker.lock().unwrap().admit(breaker_now());
+    if admit == Admit::Probe {
+        info!("circuit breaker half-open, sending a probe upstream");
+        shared.stats.set_circuit(CircuitState::HalfOpen);
+    }
+    admit
+}
+
+/// The circuit breaker's clock: tokio's, so paused-time tests can move it.
+fn breaker_now() -> Instant {
+    tokio::time::Instant::now().into_std()
+}
+
+fn note_breaker_success(shared: &Shared) {
+    let Some(breaker) = &shared.breaker else {
+        return;
+    };
+    if breaker.lock().unwrap().on_success() {
+        info!("upstream answering again, circuit breaker closed");
+        shared.stats.set_circuit(CircuitState::Closed);
+    }
+}
+
+/// Counts a retryable failure against the circuit breaker. True when the
+/// breaker is open, so the failed message is answered rather than retried.
+fn note_breaker_failure(shared: &Shared, e: &TransportError) -> bool {
+    let Some(breaker) = &shared.breaker else {
+        return false;
+    };
+    let now = breaker_now();
+    let mut breaker = breaker.lock().unwrap();
+    let probing = breaker.state() == CircuitState::HalfOpen;
+    if breaker.on_failure(now) {
+        let reset_ms = shared.config.cb_reset.as_millis() as u64;
+        if probing {
+            warn!(%e, reset_ms, "circuit breaker probe failed, open again");
+        } else {
+            warn!(
+                %e,
+                failures = shared.config.cb_failures,
+                reset_ms,
+                "upstream keeps failing, circuit breaker open"
+            );
         }
+        shared.stats.set_circuit(CircuitState::Open);
+    }
+    breaker.rejects(now)
+}
+
+/// Records the transport error a request was given up on, also counted by
+/// category for `/metrics`.
+fn note_gave_up(stats: &Stats, e: &TransportError) {
+    stats.note_transport_error(e.to_string());
+    stats.note_failure_category(remote_transport::classify(e));
+}
+
+fn note_failure(shared: &Shared, e: &remote_transport::TransportError) -> StreakLog {
+    shared.stats.note_transport_error(e.to_string());
+    let mut streak = shared.streak.lock().unwrap();
+    let log = streak.on_failure(remote_transport::classify(e), Instant::now());
+    shared.stats.set_failure_streak(streak.failures());
+    log
+}
+
+fn log_summary(e: &remote_transport::TransportError, log: StreakLog) {
+    if let StreakLog::Summary { failures, elapsed } = log {
+        warn!(
+            %e,
+            "upstream still failing: {} failures over {} seconds",
+            failures,
+            elapsed.as_secs()
+        );
+    }
+}
+
+/// Sends one `ping` to keep the pooled connection warm. The response is
+/// discarded and a failure counts as a single streak sample: no retry, and
+/// nothing reaches the client. Dropped once shutdown begins.
+async fn keepalive_probe(shared: Arc<Shared>, shutdown: CancellationToken, seq: u64) {
+    let Shared {
+        config,
+        client,
+        auth,
+        session,
+        stats,
+        ..
+    } = &*shared;
+    let body = RequestBody::from(
+        serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": format!("bridge-keepalive-{}", seq),
+            "method": health::KEEPALIVE_METHOD,
+        })
+        .to_string(),
+    );
+    let probe = async {
+        let response = send_message(config, client, auth, session, &body, config.timeout, stats);
+        response.await?.collect().await
+    };
+    let probed = tokio::select! {
+        _ = shutdown.cancelled() => {
+            debug!("shutting down, dropping the keep-alive probe");
+            return;
+        }
+        probed = probe => probed,
+    };
+    match probed {
+        Ok(_) => {
+            debug!("keep-alive probe ok");
+            note_success(&shared);
+            note_breaker_success(&shared);
+        }
+        Err(e) => {
+            match note_failure(&shared, &e) {
+                StreakLog::Warn => warn!(%e, "keep-alive probe failed"),
+                StreakLog::Suppressed => debug!(%e, "keep-alive probe failed"),
+                summary => log_summary(&e, summary),
+            }
+            note_breaker_failure(&shared, &e);
+        }
+    }
+}
+
+/// Integrity mismatches are retried at most `MCP_INTEGRITY_MAX_RETRIES`
+/// times per message; a proxy that keeps corrupting a body will do so again.
+fn should_retry(e: &TransportError, integrity_failures: &mut u32, config: &Config) -> bool {
+    if let TransportError::Integrity(_) = e {
+        *integrity_failures += 1;
+        return *integrity_failures <= config.integrity_max_retries;
+    }
+    if let TransportError::Status(status, _) = e {
+        return config.retry_statuses.contains(status);
+    }
+    remote_transport::is_retryable(e)
+}
+
+fn depth_message(what: &str, limit: usize) -> String {
+    format!("{} nesting depth exceeds limit of {}", what, limit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn held(lines: &[&str]) -> VecDeque<RequestBody> {
+        lines.iter().map(|l| RequestBody::from(*l)).collect()
+    }
+
+    #[test]
+    fn a_cancellation_without_a_request_id_keeps_held_messages() {
+        let mut pause = Pause::new(Duration::from_secs(60));
+        pause.pause(None);
+        let stats = Stats::new(8);
+        let notification = r#"{"jsonrpc":"2.0","method":"notifications/roots/list_changed"}"#;
+        let mut queue = held(&[notification]);
+        let cancel = RequestBody::from(r#"{"jsonrpc":"2.0","method":"notifications/cancelled","params":{}}"#);
+        assert_eq!(local_control(&cancel, &mut pause, &mut queue, &stats), Some(None));
+        assert_eq!(queue, held(&[notification, &cancel.text()]));
+    }
+
+    #[test]
+    fn a_paused_cancellation_of_a_request_not_held_is_dispatched() {
+        let mut pause = Pause::new(Duration::from_secs(60));
+        pause.pause(None);
+        let stats = Stats::new(8);
+        let mut queue = held(&[r#"{"jsonrpc":"2.0","id":2,"method":"x"}"#]);
+        let cancel = |id: u32| {
+            RequestBody::from(format!(
+                r#"{{"jsonrpc":"2.0","method":"notifications/cancelled","params":{{"requestId":{}}}}}"#,
+                id
+            ))
+        };
+        assert_eq!(local_control(&cancel(5), &mut pause, &mut queue, &stats), None);
+        assert_eq!(queue.len(), 1);
+        assert_eq!(local_control(&cancel(2), &mut pause, &mut queue, &stats), Some(None));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn only_the_configured_statuses_are_retried() {
+        let config = Config::from_lookup(|name| {
+            (name == "MCP_RETRY_STATUSES").then(|| "500".to_string())
+        });
+        let status = |code| TransportError::Status(code, String::new());
+        let mut integrity_failures = 0;
+        assert!(should_retry(&status(500), &mut integrity_failures, &config));
+        assert!(!should_retry(&status(503), &mut integrity_failures, &config));
+        let config = Config::from_lookup(|name| {
+            (name == "MCP_RETRY_STATUSES").then(|| "none".to_string())
+        });
+        assert!(!should_retry(&status(502), &mut integrity_failures, &config));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keepalive_waits_out_the_open_circuit() {
+        // Port 0 cannot be connected to, so every probe fails.
+        let config = Config::from_lookup(|name| match name {
+            "MCP_BRIDGE_URI" => Some("http://127.0.0.1:0/mcp".to_string()),
+            "MCP_KEEPALIVE_INTERVAL_SECS" => Some("1".to_string()),
+            "MCP_ATTEMPT_TIMEOUT_MS" => Some("100".to_string()),
+            "MCP_CB_FAILURES" => Some("2".to_string()),
+            "MCP_CB_RESET_MS" => Some("60000".to_string()),
+            _ => None,
+        });
+        let auth = Arc::new(AuthProvider::from_config(&config));
+        let stats = Arc::new(Stats::new(8));
+        let (_tx, rx) = mpsc::channel(8);
+        let (tx_out, _rx_out) = mpsc::channel(8);
+        let shutdown = CancellationToken::new();
+        let evict_oldest = Arc::new(EvictOldest::default());
+        let (_tx_errors, errors) = mpsc::channel(8);
+        let bridge = spawn(
+            config,
+            rx,
+            evict_oldest,
+            errors,
+            tx_out,
+            shutdown.clone(),
+            stats.clone(),
+            auth,
+        );
+
+        // Two failed probes open the circuit, after which none is sent.
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        assert_eq!(stats.circuit(), Some(("open", 1)));
+        assert_eq!(stats.failure_streak(), 2);
+        // Once the reset has passed one probe goes out, fails, and opens it
+        // again.
+        tokio::time::sleep(Duration::from_secs(40)).await;
+        assert_eq!(stats.failure_streak(), 3);
+        assert_eq!(stats.circuit(), Some(("open", 2)));
+
+        shutdown.cancel();
+        bridge.await.unwrap();
     }
-    info!(%name, "bridge finished");
 }
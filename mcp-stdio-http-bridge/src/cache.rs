@@ -0,0 +1,341 @@
+//! Answers to read-only requests kept for a while (`MCP_CACHE`), for clients
+//! that ask for `tools/list` or `prompts/list` on every turn. An entry is
+//! keyed by method and params, and a hit is answered under the new request's
+//! id without going upstream. The upstream's `list_changed` notifications,
+//! on POST responses and the GET stream alike, drop the entries they make
+//! stale, as does a new `initialize`.
+
+use crate::hedge;
+use crate::state::{Table, TableGauge};
+use serde_json::{json, Value};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// Makes every cached `resources/read` answer stale.
+const RESOURCE_UPDATED: &str = "notifications/resources/updated";
+
+/// Cached methods and how long their answers are kept.
+pub type Ttls = Vec<(String, Duration)>;
+
+/// Method and a hash of its params.
+type Key = (String, u64);
+
+struct Entry {
+    expires: Instant,
+    result: Value,
+}
+
+struct Entries {
+    table: Table<Key, Entry>,
+    /// Bumped by every invalidation, so an answer to a request sent before
+    /// one is not stored after it.
+    generation: u64,
+}
+
+/// The `MCP_CACHE` entries, shared by the requests in flight and the
+/// writers that see the upstream's notifications.
+pub struct ResponseCache {
+    ttls: Ttls,
+    entries: Mutex<Entries>,
+}
+
+impl ResponseCache {
+    /// Holds at most `limit` answers (`MCP_STATE_TABLE_LIMIT`).
+    pub fn new(ttls: &[(String, Duration)], limit: usize) -> Self {
+        Self {
+            ttls: ttls.to_vec(),
+            entries: Mutex::new(Entries {
+                table: Table::new("response_cache", limit),
+                generation: 0,
+            }),
+        }
+    }
+
+    pub fn gauge(&self) -> Arc<TableGauge> {
+        self.entries.lock().unwrap().table.gauge()
+    }
+
+    /// The cached answer to `msg`, a request as sent upstream, under its id.
+    pub fn lookup(&self, msg: &str, now: Instant) -> Option<String> {
+        let msg: Value = serde_json::from_str(msg).ok()?;
+        let id = msg.get("id")?;
+        let key = self.key(&msg)?;
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.table.get(&key)?;
+        if entry.expires <= now {
+            entries.table.remove(&key);
+            return None;
+        }
+        let answer = json!({ "jsonrpc": "2.0", "id": id, "result": entry.result });
+        Some(answer.to_string())
+    }
+
+    /// Where invalidation stood when a request was sent, for `store`.
+    pub fn generation(&self) -> u64 {
+        self.entries.lock().unwrap().generation
+    }
+
+    /// Keeps `line`, the upstream's answer to `msg`, if the method is cached,
+    /// the answer is a result and nothing was invalidated since `generation`.
+    pub fn store(&self, msg: &str, line: &str, generation: u64, now: Instant) {
+        let Ok(msg) = serde_json::from_str::<Value>(msg) else {
+            return;
+        };
+        let Some((key, ttl)) = self.key(&msg).zip(self.ttl(&msg)) else {
+            return;
+        };
+        let Ok(Value::Object(mut answer)) = serde_json::from_str::<Value>(line) else {
+            return;
+        };
+        let Some(result) = answer.remove("result") else {
+            return;
+        };
+        let mut entries = self.entries.lock().unwrap();
+        if entries.generation != generation {
+            debug!(method = %key.0, "not caching an answer made stale in flight");
+            return;
+        }
+        while entries.table.pop_expired(|e| e.expires <= now).is_some() {}
+        let entry = Entry {
+            expires: now + ttl,
+            result,
+        };
+        entries.table.insert(key.clone(), entry);
+        entries.table.settle(&key);
+    }
+
+    /// Drops the entries a `list_changed` or `resources/updated` notification
+    /// in `line` makes stale.
+    pub fn observe(&self, line: &str) {
+        // Most lines are not such notifications; only those that could be are
+        // parsed.
+        if !line.contains("list_changed") && !line.contains(RESOURCE_UPDATED) {
+            return;
+        }
+        let Ok(value) = serde_json::from_str::<Value>(line) else {
+            return;
+        };
+        let items = match &value {
+            Value::Array(items) => items.as_slice(),
+            item => std::slice::from_ref(item),
+        };
+        for item in items {
+            if item.get("id").is_some() {
+                continue;
+            }
+            if let Some(prefix) = item.get("method").and_then(Value::as_str).and_then(stale_prefix) {
+                self.invalidate(prefix);
+            }
+        }
+    }
+
+    /// Drops every entry, for a new session.
+    pub fn clear(&self) {
+        self.invalidate("");
+    }
+
+    /// Drops the entries of methods starting with `prefix`.
+    fn invalidate(&self, prefix: &str) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.generation += 1;
+        let stale: Vec<Key> = entries
+            .table
+            .iter()
+            .filter(|((method, _), _)| method.starts_with(prefix))
+            .map(|(key, _)| key.clone())
+            .collect();
+        if !stale.is_empty() {
+            debug!(prefix, dropped = stale.len(), "invalidating cached answers");
+        }
+        for key in stale {
+            entries.table.remove(&key);
+        }
+    }
+
+    fn ttl(&self, msg: &Value) -> Option<Duration> {
+        let method = msg.get("method")?.as_str()?;
+        self.ttls
+            .iter()
+            .find(|(m, _)| m == method)
+            .map(|(_, ttl)| *ttl)
+    }
+
+    fn key(&self, msg: &Value) -> Option<Key> {
+        self.ttl(msg)?;
+        let method = msg.get("method")?.as_str()?;
+        let mut hasher = DefaultHasher::new();
+        hash_value(msg.get("params").unwrap_or(&Value::Null), &mut hasher);
+        Some((method.to_string(), hasher.finish()))
+    }
+}
+
+/// The methods whose answers the notification `method` makes stale:
+/// `notifications/tools/list_changed` covers every `tools/` method, and
+/// `notifications/resources/updated` every `resources/read`.
+fn stale_prefix(method: &str) -> Option<&str> {
+    if method == RESOURCE_UPDATED {
+        return Some("resources/read");
+    }
+    let family = method
+        .strip_prefix("notifications/")?
+        .strip_suffix("list_changed")?;
+    family.ends_with('/').then_some(family)
+}
+
+/// Hashes `value` with object keys in sorted order, so params differing only
+/// in key order share an entry.
+fn hash_value(value: &Value, hasher: &mut DefaultHasher) {
+    match value {
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            hasher.write_u8(b'{');
+            for key in keys {
+                key.hash(hasher);
+                hash_value(&map[key], hasher);
+            }
+        }
+        Value::Array(items) => {
+            hasher.write_u8(b'[');
+            hasher.write_usize(items.len());
+            for item in items {
+                hash_value(item, hasher);
+            }
+        }
+        other => other.to_string().hash(hasher),
+    }
+}
+
+/// Parses `MCP_CACHE`: comma-separated `method=ttl` pairs, the ttl a number
+/// with `ms`, `s`, `m` or `h` (seconds when bare). Methods that are not
+/// read-only, such as `tools/call`, are returned apart and never cached.
+pub fn parse_ttls(spec: &str) -> Result<(Ttls, Vec<String>), String> {
+    let mut ttls = Vec::new();
+    let mut rejected = Vec::new();
+    for pair in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let parsed = pair
+            .split_once('=')
+            .and_then(|(method, ttl)| Some((method.trim(), parse_ttl(ttl.trim())?)));
+        match parsed {
+            Some((method, _)) if !hedge::is_read_only(method) => rejected.push(method.to_string()),
+            Some((method, ttl)) if !method.is_empty() && !ttl.is_zero() => {
+                ttls.push((method.to_string(), ttl))
+            }
+            _ => {
+                return Err(format!(
+                    "invalid cache entry {:?} (expected method=ttl, e.g. tools/list=60s)",
+                    pair
+                ))
+            }
+        }
+    }
+    Ok((ttls, rejected))
+}
+
+fn parse_ttl(ttl: &str) -> Option<Duration> {
+    let split = ttl.find(|c: char| !c.is_ascii_digit()).unwrap_or(ttl.len());
+    let (n, unit) = ttl.split_at(split);
+    let n: u64 = n.parse().ok()?;
+    match unit {
+        "ms" => Some(Duration::from_millis(n)),
+        "" | "s" => Some(Duration::from_secs(n)),
+        "m" => Some(Duration::from_secs(n.checked_mul(60)?)),
+        "h" => Some(Duration::from_secs(n.checked_mul(3600)?)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIST: &str = r#"{"jsonrpc":"2.0","id":1,"method":"tools/list","params":{"a":1,"b":2}}"#;
+    const ANSWER: &str = r#"{"jsonrpc":"2.0","id":1,"result":{"tools":[]}}"#;
+
+    fn cache() -> ResponseCache {
+        let ttls = [
+            ("tools/list".to_string(), Duration::from_secs(60)),
+            ("resources/read".to_string(), Duration::from_secs(60)),
+        ];
+        ResponseCache::new(&ttls, 8)
+    }
+
+    #[test]
+    fn ttls_parse_and_side_effecting_methods_are_refused() {
+        let (ttls, rejected) =
+            parse_ttls("tools/list=60s, prompts/list=5m,ping=500ms,x/get=2,tools/call=1s").unwrap();
+        assert_eq!(
+            ttls,
+            [
+                ("tools/list".to_string(), Duration::from_secs(60)),
+                ("prompts/list".to_string(), Duration::from_secs(300)),
+                ("ping".to_string(), Duration::from_millis(500)),
+                ("x/get".to_string(), Duration::from_secs(2)),
+            ]
+        );
+        assert_eq!(rejected, ["tools/call"]);
+        assert!(parse_ttls("tools/list").is_err());
+        assert!(parse_ttls("tools/list=1d").is_err());
+        assert!(parse_ttls("tools/list=0s").is_err());
+    }
+
+    #[test]
+    fn hits_carry_the_new_id_until_they_expire() {
+        let c = cache();
+        let now = Instant::now();
+        assert_eq!(c.lookup(LIST, now), None);
+        c.store(LIST, ANSWER, c.generation(), now);
+        let again = r#"{"jsonrpc":"2.0","id":"x","method":"tools/list","params":{"b":2,"a":1}}"#;
+        assert_eq!(
+            c.lookup(again, now).as_deref(),
+            Some(r#"{"jsonrpc":"2.0","id":"x","result":{"tools":[]}}"#)
+        );
+        let other = r#"{"jsonrpc":"2.0","id":2,"method":"tools/list","params":{"a":2}}"#;
+        assert_eq!(c.lookup(other, now), None);
+        assert_eq!(c.lookup(LIST, now + Duration::from_secs(60)), None);
+        assert_eq!(c.lookup(LIST, now), None);
+    }
+
+    #[test]
+    fn only_results_of_cached_methods_are_stored() {
+        let c = cache();
+        let now = Instant::now();
+        let call = r#"{"jsonrpc":"2.0","id":1,"method":"tools/call"}"#;
+        c.store(call, ANSWER, 0, now);
+        assert_eq!(c.lookup(call, now), None);
+        c.store(LIST, r#"{"jsonrpc":"2.0","id":1,"error":{"code":1,"message":"m"}}"#, 0, now);
+        assert_eq!(c.lookup(LIST, now), None);
+    }
+
+    #[test]
+    fn notifications_drop_what_they_make_stale() {
+        let c = cache();
+        let now = Instant::now();
+        let read = r#"{"jsonrpc":"2.0","id":3,"method":"resources/read","params":{"uri":"a"}}"#;
+        c.store(LIST, ANSWER, 0, now);
+        c.store(read, ANSWER, 0, now);
+        c.observe(r#"{"jsonrpc":"2.0","method":"notifications/prompts/list_changed"}"#);
+        assert!(c.lookup(LIST, now).is_some());
+        c.observe(r#"[{"jsonrpc":"2.0","method":"notifications/tools/list_changed"}]"#);
+        assert_eq!(c.lookup(LIST, now), None);
+        assert!(c.lookup(read, now).is_some());
+        c.observe(r#"{"jsonrpc":"2.0","method":"notifications/resources/updated","params":{"uri":"a"}}"#);
+        assert_eq!(c.lookup(read, now), None);
+    }
+
+    #[test]
+    fn answers_from_before_an_invalidation_are_not_stored() {
+        let c = cache();
+        let now = Instant::now();
+        let sent = c.generation();
+        c.observe(r#"{"jsonrpc":"2.0","method":"notifications/tools/list_changed"}"#);
+        c.store(LIST, ANSWER, sent, now);
+        assert_eq!(c.lookup(LIST, now), None);
+        c.store(LIST, ANSWER, c.generation(), now);
+        c.clear();
+        assert_eq!(c.lookup(LIST, now), None);
+    }
+}
@@ -0,0 +1,177 @@
+use crate::jsonrpc;
+use serde_json::Value;
+
+/// Server capabilities hidden from the client. Paths are dotted
+/// (`sampling`, `resources.subscribe`) and are removed from
+/// `result.capabilities` of the initialize response. Server-originated
+/// requests in the family of a stripped top-level capability (`sampling/…`
+/// for `sampling`) are answered with an error instead of reaching the client.
+#[derive(Clone, Debug, Default)]
+pub struct CapabilityFilter {
+    paths: Vec<Vec<String>>,
+}
+
+impl CapabilityFilter {
+    pub fn new(paths: &[String]) -> Self {
+        Self {
+            paths: paths
+                .iter()
+                .map(|p| p.split('.').map(str::to_string).collect())
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    /// Removes stripped capabilities from an initialize response. Returns
+    /// `None` when nothing changed so callers can keep the original bytes.
+    pub fn strip(&self, msg: &str) -> Option<String> {
+        if self.paths.is_empty() {
+            return None;
+        }
+        let mut value: Value = serde_json::from_str(msg).ok()?;
+        let changed = match &mut value {
+            Value::Array(items) => {
+                let mut changed = false;
+                for item in items {
+                    changed |= self.strip_item(item);
+                }
+                changed
+            }
+            item => self.strip_item(item),
+        };
+        changed.then(|| value.to_string())
+    }
+
+    /// For a server-originated request belonging to a stripped capability,
+    /// the error response to send back upstream in place of forwarding it.
+    pub fn rejection(&self, msg: &str) -> Option<String> {
+        if self.paths.is_empty() {
+            return None;
+        }
+        let value: Value = serde_json::from_str(msg).ok()?;
+        let method = value.get("method")?.as_str()?;
+        let id = value.get("id").filter(|id| !id.is_null())?;
+        let (family, _) = method.split_once('/')?;
+        self.paths
+            .iter()
+            .any(|p| p.len() == 1 && p[0] == family)
+            .then(|| {
+                jsonrpc::error_response(
+                    Some(id),
+                    jsonrpc::METHOD_NOT_FOUND,
+                    &format!(
+                        "capability {:?} is not available through this bridge",
+                        family
+                    ),
+                )
+            })
+    }
+
+    fn strip_item(&self, item: &mut Value) -> bool {
+        let Some(capabilities) = item
+            .get_mut("result")
+            .and_then(|r| r.get_mut("capabilities"))
+        else {
+            return false;
+        };
+        let mut changed = false;
+        for path in &self.paths {
+            changed |= remove_path(capabilities, path);
+        }
+        changed
+    }
+}
+
+fn remove_path(value: &mut Value, path: &[String]) -> bool {
+    let Some((last, parents)) = path.split_last() else {
+        return false;
+    };
+    let mut current = value;
+    for key in parents {
+        match current.get_mut(key) {
+            Some(next) => current = next,
+            None => return false,
+        }
+    }
+    current
+        .as_object_mut()
+        .is_some_and(|obj| obj.remove(last).is_some())
+}
+
+/// Parses the comma-separated `MCP_STRIP_SERVER_CAPABILITIES` value.
+pub fn parse_paths(spec: &str) -> Vec<String> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INIT: &str = r#"{"jsonrpc":"2.0","id":0,"result":{"protocolVersion":"2025-03-26","capabilities":{"sampling":{},"logging":{},"resources":{"subscribe":true,"listChanged":true},"tools":{}},"serverInfo":{"name":"s"}}}"#;
+
+    fn filter(spec: &str) -> CapabilityFilter {
+        CapabilityFilter::new(&parse_paths(spec))
+    }
+
+    fn capabilities(msg: &str) -> Value {
+        serde_json::from_str::<Value>(msg).unwrap()["result"]["capabilities"].clone()
+    }
+
+    #[test]
+    fn strips_top_level_keys() {
+        let out = filter("sampling, logging").strip(INIT).unwrap();
+        let caps = capabilities(&out);
+        assert!(caps.get("sampling").is_none());
+        assert!(caps.get("logging").is_none());
+        assert_eq!(caps["tools"], serde_json::json!({}));
+    }
+
+    #[test]
+    fn strips_nested_flags_only() {
+        let out = filter("resources.subscribe").strip(INIT).unwrap();
+        assert_eq!(
+            capabilities(&out)["resources"],
+            serde_json::json!({"listChanged": true})
+        );
+    }
+
+    #[test]
+    fn absent_capabilities_leave_message_untouched() {
+        assert_eq!(filter("elicitation,prompts.listChanged").strip(INIT), None);
+        assert_eq!(
+            filter("sampling").strip(r#"{"jsonrpc":"2.0","id":1,"result":{}}"#),
+            None
+        );
+    }
+
+    #[test]
+    fn rejects_server_requests_of_stripped_capability() {
+        let f = filter("sampling,resources.subscribe");
+        let err = f
+            .rejection(r#"{"jsonrpc":"2.0","id":"s1","method":"sampling/createMessage"}"#)
+            .unwrap();
+        let err: Value = serde_json::from_str(&err).unwrap();
+        assert_eq!(err["id"], "s1");
+        assert_eq!(err["error"]["code"], jsonrpc::METHOD_NOT_FOUND);
+        // Notifications, other families and dotted flags are not rejected.
+        assert_eq!(
+            f.rejection(r#"{"jsonrpc":"2.0","method":"sampling/progress"}"#),
+            None
+        );
+        assert_eq!(
+            f.rejection(r#"{"jsonrpc":"2.0","id":2,"method":"roots/list"}"#),
+            None
+        );
+        assert_eq!(
+            f.rejection(r#"{"jsonrpc":"2.0","id":3,"method":"resources/subscribe"}"#),
+            None
+        );
+    }
+}
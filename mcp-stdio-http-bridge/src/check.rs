@@ -0,0 +1,385 @@
+//! `--check`: instead of bridging stdio, runs the `initialize` handshake
+//! against the configured upstream with the configured credentials, lists
+//! its tools, and prints what it found. The requests go through
+//! `send_message`, as the bridge's do, so a passing check means the bridge
+//! would get as far. A failure exits with a status naming its category.
+
+use crate::auth::AuthProvider;
+use crate::config::Config;
+use crate::echo;
+use crate::quirks;
+use crate::remote_transport::{send_message, Client, TransportError};
+use crate::request_body::RequestBody;
+use crate::session::Session;
+use crate::stats::Stats;
+use serde_json::{json, Value};
+use std::error::Error;
+use std::fmt;
+
+/// First argument that runs the check instead of the bridge.
+pub const CHECK_ARG: &str = "--check";
+/// `tools/list` pages followed before the list is reported as cut short.
+const MAX_TOOL_PAGES: usize = 100;
+
+/// What kept the check from completing, each with its own exit status.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Failure {
+    /// The upstream's host name did not resolve.
+    Dns,
+    /// No connection, or it broke or timed out before an answer.
+    Connect,
+    /// The TLS handshake failed, e.g. on an untrusted certificate.
+    Tls,
+    /// The credentials were refused or could not be acquired.
+    Auth,
+    /// The upstream answered with an error status.
+    Http,
+    /// The upstream answered, but not as an MCP server would.
+    Protocol,
+}
+
+impl Failure {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Dns => "dns",
+            Self::Connect => "connect",
+            Self::Tls => "tls",
+            Self::Auth => "auth",
+            Self::Http => "http",
+            Self::Protocol => "protocol",
+        }
+    }
+
+    /// Exit status of a check that failed this way.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Self::Dns => 10,
+            Self::Connect => 11,
+            Self::Tls => 12,
+            Self::Auth => 13,
+            Self::Http => 14,
+            Self::Protocol => 15,
+        }
+    }
+
+    fn of(e: &TransportError) -> Self {
+        match e {
+            TransportError::Unauthorized { .. }
+            | TransportError::Credentials(_)
+            | TransportError::Status(403, _) => Self::Auth,
+            TransportError::RateLimited { .. } | TransportError::Status(..) => Self::Http,
+            TransportError::Network(err) => Self::of_network(err),
+            TransportError::Timeout | TransportError::Io(_) | TransportError::Http2Refused(_) => {
+                Self::Connect
+            }
+            _ => Self::Protocol,
+        }
+    }
+
+    /// Tells a failed lookup or handshake from another connection failure
+    /// by the error's causes, which are all the HTTP client exposes of them.
+    fn of_network(err: &(dyn Error + 'static)) -> Self {
+        let mut causes = String::new();
+        let mut next = Some(err);
+        while let Some(e) = next {
+            causes.push_str(&e.to_string().to_ascii_lowercase());
+            causes.push('\n');
+            next = e.source();
+        }
+        if causes.contains("dns error") || causes.contains("failed to lookup address") {
+            Self::Dns
+        } else if ["certificate", "tls", "ssl", "handshake"]
+            .iter()
+            .any(|word| causes.contains(word))
+        {
+            Self::Tls
+        } else {
+            Self::Connect
+        }
+    }
+}
+
+/// Why the check failed, for the log.
+#[derive(Debug)]
+pub struct CheckError {
+    pub failure: Failure,
+    /// The step that failed, e.g. `initialize`.
+    pub step: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for CheckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} failed ({}): {}", self.step, self.failure.as_str(), self.message)
+    }
+}
+
+impl CheckError {
+    fn transport(step: &'static str, e: &TransportError) -> Self {
+        Self {
+            failure: Failure::of(e),
+            step,
+            message: e.to_string(),
+        }
+    }
+
+    fn protocol(step: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            failure: Failure::Protocol,
+            step,
+            message: message.into(),
+        }
+    }
+}
+
+/// What the upstream said about itself.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    pub server_name: String,
+    pub server_version: Option<String>,
+    pub protocol_version: String,
+    pub capabilities: Value,
+    /// Each tool's name and the first line of its description; `None` when
+    /// the server does not offer tools.
+    pub tools: Option<Vec<(String, String)>>,
+    /// More pages of tools were left than `MAX_TOOL_PAGES` allows.
+    pub tools_truncated: bool,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "server: {}", self.server_name)?;
+        if let Some(version) = &self.server_version {
+            write!(f, " {}", version)?;
+        }
+        writeln!(f)?;
+        writeln!(f, "protocol version: {}", self.protocol_version)?;
+        writeln!(f, "capabilities: {}", self.capabilities)?;
+        let Some(tools) = &self.tools else {
+            return writeln!(f, "tools: not offered");
+        };
+        let more = if self.tools_truncated { ", more not listed" } else { "" };
+        writeln!(f, "tools ({}{}):", tools.len(), more)?;
+        for (name, description) in tools {
+            if description.is_empty() {
+                writeln!(f, "  {}", name)?;
+            } else {
+                writeln!(f, "  {} - {}", name, description)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Initializes a session with the upstream, as a client would, then lists
+/// its tools. Each request is tried once: a check reports the first
+/// failure rather than retrying past it.
+pub async fn run(
+    config: &Config,
+    client: &Client,
+    auth: &AuthProvider,
+    stats: &Stats,
+) -> Result<Report, CheckError> {
+    let session = Session::new(&config.uri);
+    let exchange = Exchange {
+        config,
+        client,
+        auth,
+        stats,
+        session: &session,
+    };
+    let initialize = json!({
+        "jsonrpc": "2.0",
+        "id": 0,
+        "method": "initialize",
+        "params": {
+            "protocolVersion": config.protocol_version.as_deref().unwrap_or(echo::PROTOCOL_VERSION),
+            "capabilities": {},
+            "clientInfo": {
+                "name": "mcp-stdio-http-bridge",
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+        },
+    });
+    let result = exchange.request("initialize", &initialize).await?;
+    let protocol_version = result
+        .get("protocolVersion")
+        .and_then(Value::as_str)
+        .ok_or_else(|| CheckError::protocol("initialize", "the result has no protocolVersion"))?
+        .to_string();
+    session.negotiated(&protocol_version);
+    let server_info = result.get("serverInfo");
+    let text = |field| {
+        server_info
+            .and_then(|info| info.get(field))
+            .and_then(Value::as_str)
+            .map(str::to_string)
+    };
+    let server_name = text("name")
+        .ok_or_else(|| CheckError::protocol("initialize", "the result has no serverInfo.name"))?;
+    let capabilities = result.get("capabilities").cloned().unwrap_or(json!({}));
+    let initialized = json!({"jsonrpc": "2.0", "method": quirks::INITIALIZED_METHOD});
+    exchange.notify(quirks::INITIALIZED_METHOD, &initialized).await?;
+    let mut report = Report {
+        server_name,
+        server_version: text("version"),
+        protocol_version,
+        tools: None,
+        tools_truncated: false,
+        capabilities,
+    };
+    if report.capabilities.get("tools").is_some() {
+        let (tools, truncated) = list_tools(&exchange).await?;
+        report.tools = Some(tools);
+        report.tools_truncated = truncated;
+    }
+    Ok(report)
+}
+
+/// Every tool, following `nextCursor` for at most `MAX_TOOL_PAGES` pages.
+async fn list_tools(exchange: &Exchange<'_>) -> Result<(Vec<(String, String)>, bool), CheckError> {
+    let mut tools = Vec::new();
+    let mut cursor: Option<Value> = None;
+    for page in 1..=MAX_TOOL_PAGES {
+        let mut request = json!({"jsonrpc": "2.0", "id": page, "method": "tools/list"});
+        if let Some(cursor) = cursor.take() {
+            request["params"] = json!({ "cursor": cursor });
+        }
+        let result = exchange.request("tools/list", &request).await?;
+        let listed = result
+            .get("tools")
+            .and_then(Value::as_array)
+            .ok_or_else(|| CheckError::protocol("tools/list", "the result has no tools array"))?;
+        for tool in listed {
+            let name = tool.get("name").and_then(Value::as_str).unwrap_or("(unnamed)");
+            let description = tool
+                .get("description")
+                .and_then(Value::as_str)
+                .and_then(|d| d.lines().next())
+                .unwrap_or_default();
+            tools.push((name.to_string(), description.trim().to_string()));
+        }
+        match result.get("nextCursor") {
+            Some(next) if !next.is_null() => cursor = Some(next.clone()),
+            _ => return Ok((tools, false)),
+        }
+    }
+    Ok((tools, true))
+}
+
+/// One upstream session's worth of what `send_message` needs.
+struct Exchange<'a> {
+    config: &'a Config,
+    client: &'a Client,
+    auth: &'a AuthProvider,
+    stats: &'a Stats,
+    session: &'a Session,
+}
+
+impl Exchange<'_> {
+    /// Sends `msg` and returns the messages that came back.
+    async fn send(&self, step: &'static str, msg: &Value) -> Result<Vec<String>, CheckError> {
+        let Exchange {
+            config,
+            client,
+            auth,
+            stats,
+            session,
+        } = self;
+        let body = RequestBody::from(msg.to_string());
+        let response = send_message(config, client, auth, session, &body, config.timeout, stats)
+            .await
+            .map_err(|e| CheckError::transport(step, &e))?;
+        response
+            .collect()
+            .await
+            .map_err(|e| CheckError::transport(step, &e))
+    }
+
+    /// Sends the request `msg` and returns its result.
+    async fn request(&self, step: &'static str, msg: &Value) -> Result<Value, CheckError> {
+        let lines = self.send(step, msg).await?;
+        let answer = lines
+            .iter()
+            .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+            .find(|answer| answer.get("method").is_none() && answer.get("id") == msg.get("id"))
+            .ok_or_else(|| CheckError::protocol(step, "no JSON-RPC response to the request"))?;
+        if let Some(error) = answer.get("error") {
+            return Err(CheckError::protocol(step, format!("the upstream answered with an error: {}", error)));
+        }
+        answer
+            .get("result")
+            .cloned()
+            .ok_or_else(|| CheckError::protocol(step, "the response has no result"))
+    }
+
+    async fn notify(&self, step: &'static str, msg: &Value) -> Result<(), CheckError> {
+        self.send(step, msg).await.map(drop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failures_are_classified_by_what_went_wrong() {
+        let unauthorized = TransportError::Unauthorized {
+            body: String::new(),
+            challenge: None,
+        };
+        assert_eq!(Failure::of(&unauthorized), Failure::Auth);
+        assert_eq!(Failure::of(&TransportError::Status(403, String::new())), Failure::Auth);
+        assert_eq!(Failure::of(&TransportError::Status(500, String::new())), Failure::Http);
+        assert_eq!(Failure::of(&TransportError::Timeout), Failure::Connect);
+        assert_eq!(Failure::of(&TransportError::InvalidUtf8(0)), Failure::Protocol);
+        let codes: Vec<i32> = [
+            Failure::Dns,
+            Failure::Connect,
+            Failure::Tls,
+            Failure::Auth,
+            Failure::Http,
+            Failure::Protocol,
+        ]
+        .iter()
+        .map(|f| f.exit_code())
+        .collect();
+        assert_eq!(codes, [10, 11, 12, 13, 14, 15]);
+    }
+
+    #[test]
+    fn network_causes_tell_dns_and_tls_apart() {
+        #[derive(Debug)]
+        struct Chain(&'static str, Option<Box<Chain>>);
+        impl fmt::Display for Chain {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(self.0)
+            }
+        }
+        impl Error for Chain {
+            fn source(&self) -> Option<&(dyn Error + 'static)> {
+                self.1.as_deref().map(|e| e as &(dyn Error + 'static))
+            }
+        }
+        let wrapped = |cause| Chain("error trying to connect", Some(Box::new(Chain(cause, None))));
+        assert_eq!(Failure::of_network(&wrapped("dns error: failed to lookup address information")), Failure::Dns);
+        assert_eq!(Failure::of_network(&wrapped("certificate verify failed")), Failure::Tls);
+        assert_eq!(Failure::of_network(&wrapped("Connection refused (os error 111)")), Failure::Connect);
+    }
+
+    #[test]
+    fn the_report_lists_tools_by_first_description_line() {
+        let report = Report {
+            server_name: "demo".into(),
+            server_version: Some("1.2".into()),
+            protocol_version: "2025-03-26".into(),
+            capabilities: json!({"tools": {}}),
+            tools: Some(vec![("search".into(), "Finds things".into()), ("ping".into(), String::new())]),
+            tools_truncated: false,
+        };
+        assert_eq!(
+            report.to_string(),
+            "server: demo 1.2\nprotocol version: 2025-03-26\ncapabilities: {\"tools\":{}}\ntools (2):\n  search - Finds things\n  ping\n"
+        );
+    }
+}
@@ -0,0 +1,236 @@
+//! Connection counting behind the `conn-metrics` feature, so the connection
+//! reuse ratio can be reported. hyper tags each response reqwest returns
+//! with the addresses of the connection it came over: addresses not seen
+//! before mean a new connection, and TLS details on it a handshake.
+//! native-tls does not expose session resumption, so resumed sessions are
+//! not counted.
+
+use crate::remote_transport::DEFAULT_POOL_IDLE_TIMEOUT_MS;
+use hyper::client::connect::HttpInfo;
+use reqwest::tls::TlsInfo;
+use reqwest::Response;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Connections remembered at most. Past it the least recently used one is
+/// forgotten, so a burst of short-lived connections cannot grow the map.
+pub const MAX_TRACKED: usize = 1024;
+
+/// How long an unused connection is remembered when the pool keeps idle
+/// connections until the server closes them. The server may have closed it
+/// long before, and a new connection that got its port must count as
+/// opened; one reused after idling this long is counted as opened again.
+pub const UNTIMED_IDLE_HORIZON: Duration = Duration::from_millis(DEFAULT_POOL_IDLE_TIMEOUT_MS);
+
+/// A connection a response came over that no earlier one did.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Opened {
+    pub tls: bool,
+}
+
+/// The connections responses have come over, by local and remote address,
+/// with when each was last used.
+#[derive(Debug, Default)]
+pub struct Connections {
+    last_used: Mutex<HashMap<(SocketAddr, SocketAddr), Instant>>,
+}
+
+impl Connections {
+    /// The connection `res` came over, when it is a new one. A connection
+    /// idle for longer than `idle_timeout` has left the pool, so its
+    /// addresses coming back belong to a new one that got the same port.
+    /// Zero keeps idle connections until the server closes them, which
+    /// cannot be seen here, so `UNTIMED_IDLE_HORIZON` stands in for it.
+    pub fn opened(&self, res: &Response, idle_timeout: Duration) -> Option<Opened> {
+        let info = res.extensions().get::<HttpInfo>()?;
+        let addrs = (info.local_addr(), info.remote_addr());
+        if !self.is_new(addrs, idle_timeout, Instant::now()) {
+            return None;
+        }
+        Some(Opened {
+            tls: res.extensions().get::<TlsInfo>().is_some(),
+        })
+    }
+
+    fn is_new(&self, addrs: (SocketAddr, SocketAddr), idle_timeout: Duration, now: Instant) -> bool {
+        let horizon = if idle_timeout.is_zero() {
+            UNTIMED_IDLE_HORIZON
+        } else {
+            idle_timeout
+        };
+        let mut last_used = self.last_used.lock().unwrap();
+        last_used.retain(|_, used| now.duration_since(*used) <= horizon);
+        if last_used.insert(addrs, now).is_some() {
+            return false;
+        }
+        if last_used.len() > MAX_TRACKED {
+            let oldest = last_used
+                .iter()
+                .min_by_key(|(_, used)| **used)
+                .map(|(addrs, _)| *addrs);
+            if let Some(oldest) = oldest {
+                last_used.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::AuthProvider;
+    use crate::config::Config;
+    use crate::remote_transport::{build_client, send_message};
+    use crate::request_body::RequestBody;
+    use crate::session::Session;
+    use crate::stats::{ConnCounters, Stats};
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const CERT: &[u8] = include_bytes!("../tests/fixtures/localhost.crt");
+    const KEY: &[u8] = include_bytes!("../tests/fixtures/localhost.key");
+
+    /// Minimal keep-alive HTTPS server answering every request with the same
+    /// JSON-RPC result.
+    async fn tls_server() -> std::net::SocketAddr {
+        let identity = native_tls::Identity::from_pkcs8(CERT, KEY).unwrap();
+        let acceptor: tokio_native_tls::TlsAcceptor =
+            native_tls::TlsAcceptor::new(identity).unwrap().into();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((tcp, _)) = listener.accept().await {
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    let Ok(mut stream) = acceptor.accept(tcp).await else {
+                        return;
+                    };
+                    let mut buf = Vec::new();
+                    let mut chunk = [0u8; 4096];
+                    loop {
+                        let Some(head_end) = buf.windows(4).position(|w| w == b"\r\n\r\n") else {
+                            match stream.read(&mut chunk).await {
+                                Ok(0) | Err(_) => return,
+                                Ok(n) => buf.extend_from_slice(&chunk[..n]),
+                            }
+                            continue;
+                        };
+                        let head = String::from_utf8_lossy(&buf[..head_end]).to_lowercase();
+                        let len: usize = head
+                            .lines()
+                            .find_map(|l| l.strip_prefix("content-length:"))
+                            .and_then(|v| v.trim().parse().ok())
+                            .unwrap_or(0);
+                        while buf.len() < head_end + 4 + len {
+                            match stream.read(&mut chunk).await {
+                                Ok(0) | Err(_) => return,
+                                Ok(n) => buf.extend_from_slice(&chunk[..n]),
+                            }
+                        }
+                        buf.drain(..head_end + 4 + len);
+                        let body = r#"{"jsonrpc":"2.0","id":1,"result":{}}"#;
+                        let response = format!(
+                            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                            body.len(),
+                            body
+                        );
+                        if stream.write_all(response.as_bytes()).await.is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+        addr
+    }
+
+    /// Sends `n` pings over one client built from `vars`, returning its
+    /// counters.
+    async fn ping(addr: std::net::SocketAddr, vars: &[(&str, &str)], n: usize) -> Arc<ConnCounters> {
+        let uri = format!("https://localhost:{}/mcp", addr.port());
+        let ca = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/localhost.crt");
+        let config = Config::from_lookup(|name| match name {
+            "MCP_BRIDGE_URI" => Some(uri.clone()),
+            "MCP_CA_CERT" => Some(ca.to_string()),
+            _ => vars.iter().find(|(k, _)| *k == name).map(|(_, v)| v.to_string()),
+        });
+        let stats = Stats::new(8);
+        let client = build_client(&config);
+        for _ in 0..n {
+            let lines = send_message(
+                &config,
+                &client,
+                &AuthProvider::from_config(&config),
+                &Session::new(&config.uri),
+                &RequestBody::from(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#),
+                config.timeout,
+                &stats,
+            )
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+            assert_eq!(lines.len(), 1);
+        }
+        stats.conn_counters()
+    }
+
+    fn addrs(port: u16) -> (SocketAddr, SocketAddr) {
+        (
+            SocketAddr::from(([127, 0, 0, 1], port)),
+            SocketAddr::from(([127, 0, 0, 1], 443)),
+        )
+    }
+
+    #[test]
+    fn a_port_back_after_the_horizon_is_a_new_connection() {
+        let connections = Connections::default();
+        let start = Instant::now();
+        let untimed = Duration::ZERO;
+        assert!(connections.is_new(addrs(5000), untimed, start));
+        let soon = start + Duration::from_secs(30);
+        assert!(!connections.is_new(addrs(5000), untimed, soon));
+        let later = soon + UNTIMED_IDLE_HORIZON + Duration::from_secs(1);
+        assert!(connections.is_new(addrs(5000), untimed, later));
+        assert_eq!(connections.last_used.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn tracked_connections_are_capped() {
+        let connections = Connections::default();
+        let start = Instant::now();
+        let timeout = Duration::from_secs(3600);
+        for port in 0..=MAX_TRACKED as u16 {
+            let at = start + Duration::from_millis(port.into());
+            assert!(connections.is_new(addrs(port), timeout, at));
+        }
+        assert_eq!(connections.last_used.lock().unwrap().len(), MAX_TRACKED);
+        // The least recently used one was forgotten, the newest kept.
+        let now = start + Duration::from_secs(10);
+        assert!(!connections.is_new(addrs(MAX_TRACKED as u16), timeout, now));
+        assert!(connections.is_new(addrs(0), timeout, now));
+    }
+
+    #[tokio::test]
+    async fn sequential_requests_reuse_one_tls_connection() {
+        let counters = ping(tls_server().await, &[], 5).await;
+        assert_eq!(counters.opened.load(Ordering::Relaxed), 1);
+        assert_eq!(counters.tls_handshakes.load(Ordering::Relaxed), 1);
+        assert_eq!(counters.requests.load(Ordering::Relaxed), 5);
+        assert_eq!(counters.reuse_ratio(), Some(5.0));
+    }
+
+    #[tokio::test]
+    async fn an_empty_pool_opens_a_connection_per_request() {
+        let counters = ping(tls_server().await, &[("MCP_POOL_MAX_IDLE", "0")], 3).await;
+        assert_eq!(counters.opened.load(Ordering::Relaxed), 3);
+        assert_eq!(counters.tls_handshakes.load(Ordering::Relaxed), 3);
+        assert_eq!(counters.reuse_ratio(), Some(1.0));
+    }
+}
@@ -0,0 +1,715 @@
+use crate::auth::AuthMode;
+use crate::balance::LbStrategy;
+use crate::config::{Config, Source};
+use crate::remote_transport::RedirectPolicy;
+use crate::server_log::LogNotifications;
+use crate::stdio::Utf8Mode;
+use crate::websocket::is_websocket;
+
+/// Exit status when the configuration contradicts itself.
+pub const CONFIG_ERROR_CODE: i32 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    /// Both settings cannot be honored; startup fails.
+    Contradiction,
+    /// One setting makes the other pointless; startup warns.
+    Redundant,
+}
+
+/// One incompatible or redundant combination of two settings.
+#[derive(Debug)]
+pub struct Rule {
+    pub severity: Severity,
+    /// Environment variables of the settings involved.
+    pub settings: [&'static str; 2],
+    pub applies: fn(&Config) -> bool,
+    pub problem: &'static str,
+    pub fix: &'static str,
+}
+
+impl Rule {
+    pub fn message(&self) -> String {
+        format!(
+            "{} and {}: {}; {}",
+            self.settings[0], self.settings[1], self.problem, self.fix
+        )
+    }
+}
+
+/// Whether `field` was set in the environment or on the command line
+/// rather than defaulted.
+fn is_set(config: &Config, field: &str) -> bool {
+    config
+        .provenance
+        .iter()
+        .any(|(f, source)| *f == field && *source != Source::Default)
+}
+
+/// Whether bearer tokens are sent at all (`MCP_AUTH_MODE`).
+fn bearer(config: &Config) -> bool {
+    matches!(config.auth_mode, AuthMode::Bearer(_))
+}
+
+pub const RULES: &[Rule] = &[
+    Rule {
+        severity: Severity::Contradiction,
+        settings: ["MCP_RAW_PASSTHROUGH", "MCP_STDIN_VALIDATION"],
+        applies: |c| c.raw_passthrough && c.stdin_validation == Utf8Mode::Lossy,
+        problem: "lossy decoding replaces bytes that passthrough forwards unchanged",
+        fix: "use MCP_STDIN_VALIDATION=strict or turn passthrough off",
+    },
+    Rule {
+        severity: Severity::Contradiction,
+        settings: ["MCP_RAW_PASSTHROUGH", "MCP_STDOUT_VALIDATION"],
+        applies: |c| c.raw_passthrough && c.stdout_validation == Utf8Mode::Lossy,
+        problem: "lossy decoding replaces bytes that passthrough forwards unchanged",
+        fix: "use MCP_STDOUT_VALIDATION=strict or turn passthrough off",
+    },
+    Rule {
+        severity: Severity::Contradiction,
+        settings: ["MCP_STDIN_VALIDATION", "MCP_REWRITE_IDS"],
+        applies: |c| {
+            c.stdin_validation == Utf8Mode::Bytes && c.rewrite_ids && !c.raw_passthrough
+        },
+        problem: "a line that is not UTF-8 goes upstream unparsed, under an id that may collide with a rewritten one",
+        fix: "use MCP_STDIN_VALIDATION=strict or unset MCP_REWRITE_IDS",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_RAW_PASSTHROUGH", "MCP_REWRITE_IDS"],
+        applies: |c| c.raw_passthrough && c.rewrite_ids,
+        problem: "passthrough disables id rewriting",
+        fix: "unset MCP_REWRITE_IDS",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_RAW_PASSTHROUGH", "MCP_METHOD_ALIASES"],
+        applies: |c| c.raw_passthrough && !c.method_aliases.is_empty(),
+        problem: "passthrough disables method aliases",
+        fix: "unset MCP_METHOD_ALIASES",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_RAW_PASSTHROUGH", "MCP_STRIP_SERVER_CAPABILITIES"],
+        applies: |c| c.raw_passthrough && !c.strip_capabilities.is_empty(),
+        problem: "passthrough disables capability stripping",
+        fix: "unset MCP_STRIP_SERVER_CAPABILITIES",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_RAW_PASSTHROUGH", "MCP_CONTROL_METHODS"],
+        applies: |c| c.raw_passthrough && c.control_methods,
+        problem: "passthrough disables the local control methods",
+        fix: "unset MCP_CONTROL_METHODS",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_RAW_PASSTHROUGH", "MCP_RATE_LIMIT_WARN_BELOW"],
+        applies: |c| c.raw_passthrough && c.rate_limit_warn_below.is_some(),
+        problem: "passthrough never sends the client rate-limit warnings",
+        fix: "unset MCP_RATE_LIMIT_WARN_BELOW",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_RAW_PASSTHROUGH", "MCP_MAX_JSON_DEPTH"],
+        applies: |c| c.raw_passthrough && is_set(c, "max_json_depth"),
+        problem: "passthrough does not check nesting depth",
+        fix: "unset MCP_MAX_JSON_DEPTH",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_RAW_PASSTHROUGH", "MCP_SPLIT_BATCH_RESPONSES"],
+        applies: |c| c.raw_passthrough && c.split_batch_responses,
+        problem: "passthrough forwards batch responses whole",
+        fix: "unset MCP_SPLIT_BATCH_RESPONSES",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_RAW_PASSTHROUGH", "MCP_ORDERED_RESPONSES"],
+        applies: |c| c.raw_passthrough && c.ordered_responses,
+        problem: "passthrough writes answers as they arrive",
+        fix: "unset MCP_ORDERED_RESPONSES",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_MAX_CONCURRENCY", "MCP_ORDERED_RESPONSES"],
+        applies: |c| c.max_concurrency == 1 && c.ordered_responses,
+        problem: "requests sent one at a time are already answered in order",
+        fix: "unset MCP_ORDERED_RESPONSES",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_RAW_PASSTHROUGH", "MCP_META_STRIP"],
+        applies: |c| c.raw_passthrough && c.meta_strip,
+        problem: "passthrough sends message bodies unchanged, _meta keys included",
+        fix: "unset MCP_META_STRIP",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_RAW_PASSTHROUGH", "MCP_LOG_NOTIFICATIONS"],
+        applies: |c| c.raw_passthrough && c.log_notifications != LogNotifications::Forward,
+        problem: "passthrough forwards the upstream's log notifications to stdout",
+        fix: "unset MCP_LOG_NOTIFICATIONS",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_RAW_PASSTHROUGH", "MCP_CACHE"],
+        applies: |c| c.raw_passthrough && !c.cache.is_empty(),
+        problem: "passthrough sends every request upstream, so nothing is cached",
+        fix: "unset MCP_CACHE",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_RAW_PASSTHROUGH", "MCP_VALIDATE_STDIN_JSON"],
+        applies: |c| {
+            c.raw_passthrough && is_set(c, "validate_stdin_json") && c.validate_stdin_json
+        },
+        problem: "passthrough forwards client lines without parsing them",
+        fix: "unset MCP_VALIDATE_STDIN_JSON",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_INTEGRITY_MAX_RETRIES", "MCP_INTEGRITY_HEADER"],
+        applies: |c| is_set(c, "integrity_max_retries") && !c.integrity_header,
+        problem: "integrity retries only happen when the integrity header is on",
+        fix: "set MCP_INTEGRITY_HEADER=1 or unset MCP_INTEGRITY_MAX_RETRIES",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_META_STRIP", "MCP_META_HEADERS"],
+        applies: |c| c.meta_strip && c.meta_headers.is_empty(),
+        problem: "no _meta key is sent as a header, so none is stripped",
+        fix: "map keys in MCP_META_HEADERS or unset MCP_META_STRIP",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_HEDGE_DELAY_MS", "MCP_HEDGE_METHODS"],
+        applies: |c| is_set(c, "hedge_delay") && c.hedge_methods.is_empty(),
+        problem: "no method is hedged",
+        fix: "list methods in MCP_HEDGE_METHODS or unset MCP_HEDGE_DELAY_MS",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_HEDGE_DELAY_MS", "MCP_TOTAL_BUDGET_MS"],
+        applies: |c| {
+            !c.hedge_methods.is_empty()
+                && !c.total_budget.is_zero()
+                && c.total_budget <= c.hedge_delay
+        },
+        problem: "the budget runs out before a hedged request is sent",
+        fix: "lower MCP_HEDGE_DELAY_MS below the budget",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_MAX_PAUSE_SECS", "MCP_CONTROL_METHODS"],
+        applies: |c| is_set(c, "max_pause") && !c.control_methods,
+        problem: "bridging can only be paused through the control methods",
+        fix: "set MCP_CONTROL_METHODS=1 or unset MCP_MAX_PAUSE_SECS",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_SESSION_QUERY_PARAM", "MCP_SESSION_STYLE"],
+        applies: |c| is_set(c, "session_query_param") && !c.session_style.uses_query(),
+        problem: "the query parameter is only used in query or both style",
+        fix: "set MCP_SESSION_STYLE=query or unset MCP_SESSION_QUERY_PARAM",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_KEEPALIVE_INTERVAL_MS", "MCP_KEEPALIVE_INTERVAL_SECS"],
+        applies: |c| is_set(c, "keepalive_interval_ms") && is_set(c, "keepalive_interval"),
+        problem: "the interval in milliseconds overrides the one in seconds",
+        fix: "unset MCP_KEEPALIVE_INTERVAL_SECS",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_KEEPALIVE_INTERVAL_SECS", "MCP_IDLE_EXIT_SECS"],
+        applies: |c| {
+            !c.keepalive_interval.is_zero()
+                && !c.idle_exit.is_zero()
+                && c.idle_exit <= c.keepalive_interval
+        },
+        problem: "the bridge exits on idleness before a keep-alive probe is due",
+        fix: "lower the keep-alive interval below MCP_IDLE_EXIT_SECS",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_RAW_PASSTHROUGH", "MCP_QUIRK_ACK_INITIALIZED"],
+        applies: |c| c.raw_passthrough && c.quirk_ack_initialized,
+        problem: "passthrough never writes local lines to the client",
+        fix: "unset MCP_QUIRK_ACK_INITIALIZED",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_GET_STREAM_405_LIMIT", "MCP_ENABLE_GET_STREAM"],
+        applies: |c| is_set(c, "get_stream_405_limit") && !c.get_stream,
+        problem: "the limit only applies to the GET stream",
+        fix: "set MCP_ENABLE_GET_STREAM=1 or unset MCP_GET_STREAM_405_LIMIT",
+    },
+    Rule {
+        severity: Severity::Contradiction,
+        settings: ["MCP_OAUTH_TOKEN_URL", "MCP_OAUTH_CLIENT_ID"],
+        applies: |c| c.oauth_token_url.is_some() != c.oauth_client_id.is_some(),
+        problem: "the client credentials grant needs both",
+        fix: "set both or neither",
+    },
+    Rule {
+        severity: Severity::Contradiction,
+        settings: ["MCP_OAUTH_TOKEN_URL", "MCP_OAUTH_CLIENT_SECRET"],
+        applies: |c| c.oauth_token_url.is_some() != c.oauth_client_secret.is_some(),
+        problem: "the client credentials grant needs both",
+        fix: "set both or neither",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_BRIDGE_BEARER_TOKEN", "MCP_OAUTH_TOKEN_URL"],
+        applies: |c| c.bearer_token.is_some() && c.oauth_token_url.is_some(),
+        problem: "the token from the OAuth2 endpoint replaces the static one",
+        fix: "unset MCP_BRIDGE_BEARER_TOKEN",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_OAUTH_SCOPE", "MCP_OAUTH_TOKEN_URL"],
+        applies: |c| c.oauth_scope.is_some() && c.oauth_token_url.is_none(),
+        problem: "the scope is only sent to the OAuth2 token endpoint",
+        fix: "set MCP_OAUTH_TOKEN_URL or unset MCP_OAUTH_SCOPE",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_BRIDGE_BEARER_TOKEN", "MCP_BEARER_TOKEN_FILE"],
+        applies: |c| c.bearer_token.is_some() && c.bearer_token_file.is_some(),
+        problem: "the token from the file replaces the static one",
+        fix: "unset MCP_BRIDGE_BEARER_TOKEN",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_BEARER_TOKEN_FILE", "MCP_OAUTH_TOKEN_URL"],
+        applies: |c| c.bearer_token_file.is_some() && c.oauth_token_url.is_some(),
+        problem: "the token from the OAuth2 endpoint replaces the file's",
+        fix: "unset MCP_BEARER_TOKEN_FILE",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_BEARER_TOKEN_RELOAD_EVERY", "MCP_BEARER_TOKEN_FILE"],
+        applies: |c| is_set(c, "bearer_token_reload_every") && c.bearer_token_file.is_none(),
+        problem: "only the token file is reloaded",
+        fix: "set MCP_BEARER_TOKEN_FILE or unset MCP_BEARER_TOKEN_RELOAD_EVERY",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_BRIDGE_BEARER_TOKEN", "MCP_AUTH_MODE"],
+        applies: |c| c.bearer_token.is_some() && !bearer(c),
+        problem: "the bearer token is only sent in bearer mode",
+        fix: "unset MCP_BRIDGE_BEARER_TOKEN or set MCP_AUTH_MODE=bearer",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_BEARER_TOKEN_FILE", "MCP_AUTH_MODE"],
+        applies: |c| c.bearer_token_file.is_some() && !bearer(c),
+        problem: "the token file is only read in bearer mode",
+        fix: "unset MCP_BEARER_TOKEN_FILE or set MCP_AUTH_MODE=bearer",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_OAUTH_TOKEN_URL", "MCP_AUTH_MODE"],
+        applies: |c| c.oauth_token_url.is_some() && !bearer(c),
+        problem: "OAuth2 tokens are only requested in bearer mode",
+        fix: "unset MCP_OAUTH_TOKEN_URL or set MCP_AUTH_MODE=bearer",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_BASIC_USER", "MCP_AUTH_MODE"],
+        applies: |c| {
+            (c.basic_user.is_some() || c.basic_password.is_some())
+                && !matches!(c.auth_mode, AuthMode::Basic { .. })
+        },
+        problem: "the basic auth user and password are only sent in basic mode",
+        fix: "set MCP_AUTH_MODE=basic or unset MCP_BASIC_USER and MCP_BASIC_PASSWORD",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_API_KEY", "MCP_AUTH_MODE"],
+        applies: |c| {
+            (c.api_key.is_some() || is_set(c, "api_key_header"))
+                && !matches!(c.auth_mode, AuthMode::Header { .. })
+        },
+        problem: "the API key is only sent in header mode",
+        fix: "set MCP_AUTH_MODE=header or unset MCP_API_KEY and MCP_API_KEY_HEADER",
+    },
+    Rule {
+        severity: Severity::Contradiction,
+        settings: ["MCP_PROXY", "MCP_NO_PROXY"],
+        applies: |c| c.proxy.is_some() && c.no_proxy,
+        problem: "one sends upstream traffic through a proxy, the other forbids it",
+        fix: "unset one of them",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_REDIRECT_LIMIT", "MCP_REDIRECT"],
+        applies: |c| is_set(c, "redirect_limit") && c.redirect == RedirectPolicy::None,
+        problem: "no redirect is followed, so there is nothing to limit",
+        fix: "unset MCP_REDIRECT_LIMIT or let MCP_REDIRECT follow redirects",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_REDIRECT_AUTH_HOSTS", "MCP_REDIRECT"],
+        applies: |c| !c.redirect_auth_hosts.is_empty() && c.redirect != RedirectPolicy::Follow,
+        problem: "credentials only follow redirects to other hosts, which are not followed",
+        fix: "set MCP_REDIRECT=follow or unset MCP_REDIRECT_AUTH_HOSTS",
+    },
+    Rule {
+        severity: Severity::Contradiction,
+        settings: ["MCP_CLIENT_KEY", "MCP_CLIENT_CERT"],
+        applies: |c| c.client_key.is_some() && c.client_cert.is_none(),
+        problem: "a private key is of no use without its certificate",
+        fix: "set MCP_CLIENT_CERT or unset MCP_CLIENT_KEY",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_CLIENT_CERT_PASSWORD", "MCP_CLIENT_CERT"],
+        applies: |c| c.client_cert_password.is_some() && c.client_cert.is_none(),
+        problem: "the password only opens a PKCS#12 client certificate",
+        fix: "set MCP_CLIENT_CERT or unset MCP_CLIENT_CERT_PASSWORD",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_CA_CERT", "MCP_TLS_INSECURE"],
+        applies: |c| c.ca_cert.is_some() && c.tls_insecure,
+        problem: "insecure mode accepts every certificate, so the extra roots are unused",
+        fix: "unset MCP_TLS_INSECURE",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_LOG_BODY_LIMIT", "MCP_LOG_BODIES"],
+        applies: |c| is_set(c, "log_body_limit") && !c.log_bodies,
+        problem: "the limit only applies to logged bodies",
+        fix: "set MCP_LOG_BODIES=1 or unset MCP_LOG_BODY_LIMIT",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_FAILOVER_AFTER", "MCP_FALLBACK_URIS"],
+        applies: |c| is_set(c, "failover_after") && c.fallback_uris.is_empty(),
+        problem: "there is no other endpoint to fail over to",
+        fix: "set MCP_FALLBACK_URIS or unset MCP_FAILOVER_AFTER",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_LB_STRATEGY", "MCP_FALLBACK_URIS"],
+        applies: |c| c.lb_strategy == LbStrategy::RoundRobin && c.fallback_uris.is_empty(),
+        problem: "with a single endpoint there is nothing to rotate over",
+        fix: "set MCP_FALLBACK_URIS or unset MCP_LB_STRATEGY",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_FAILOVER_AFTER", "MCP_LB_STRATEGY"],
+        applies: |c| is_set(c, "failover_after") && c.lb_strategy == LbStrategy::RoundRobin,
+        problem: "round-robin skips failing endpoints instead of failing over",
+        fix: "unset MCP_FAILOVER_AFTER or use MCP_LB_STRATEGY=sticky",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_LB_COOLDOWN_MS", "MCP_LB_STRATEGY"],
+        applies: |c| is_set(c, "lb_cooldown") && c.lb_strategy != LbStrategy::RoundRobin,
+        problem: "only round-robin takes failing endpoints out of the rotation",
+        fix: "set MCP_LB_STRATEGY=round-robin or unset MCP_LB_COOLDOWN_MS",
+    },
+    // Without the websocket feature a WebSocket URI fails at load time.
+    #[cfg(feature = "websocket")]
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_FALLBACK_URIS", "MCP_BRIDGE_URI"],
+        applies: |c| is_websocket(&c.uri) && !c.fallback_uris.is_empty(),
+        problem: "a WebSocket upstream reconnects to the same endpoint instead of failing over",
+        fix: "unset MCP_FALLBACK_URIS or use an http(s) URI",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_WS_PING_SECS", "MCP_BRIDGE_URI"],
+        applies: |c| is_set(c, "ws_ping_interval") && !is_websocket(&c.uri),
+        problem: "pings are only sent on a WebSocket upstream",
+        fix: "unset MCP_WS_PING_SECS or use a ws:// or wss:// URI",
+    },
+    Rule {
+        severity: Severity::Contradiction,
+        settings: ["MCP_LISTEN_ADDR", "MCP_METRICS_ADDR"],
+        applies: |c| c.listen_addr.is_some() && c.listen_addr == c.metrics_addr,
+        problem: "the client and status listeners cannot share an address",
+        fix: "give them different ports",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_CONNECTION_WEIGHTS", "MCP_LISTEN_ADDR"],
+        applies: |c| !c.connection_weights.is_empty() && c.listen_addr.is_none(),
+        problem: "weights only apply to client connections on MCP_LISTEN_ADDR",
+        fix: "set MCP_LISTEN_ADDR or unset MCP_CONNECTION_WEIGHTS",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_POOL_IDLE_TIMEOUT_MS", "MCP_POOL_MAX_IDLE"],
+        applies: |c| is_set(c, "pool_idle_timeout") && c.pool_max_idle == Some(0),
+        problem: "no idle connection is kept, so none can time out",
+        fix: "raise MCP_POOL_MAX_IDLE or unset MCP_POOL_IDLE_TIMEOUT_MS",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_TRACE_CONTEXT", "MCP_OTEL_EXPORTER"],
+        applies: |c| c.trace_context && c.otel_exporter.is_some(),
+        problem: "exporting spans already sends trace context upstream",
+        fix: "unset MCP_TRACE_CONTEXT",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_CB_RESET_MS", "MCP_CB_FAILURES"],
+        applies: |c| is_set(c, "cb_reset") && c.cb_failures == 0,
+        problem: "the circuit breaker is off, so it is never reset",
+        fix: "set MCP_CB_FAILURES or unset MCP_CB_RESET_MS",
+    },
+    Rule {
+        severity: Severity::Redundant,
+        settings: ["MCP_CB_FAILURES", "MCP_FAILOVER_AFTER"],
+        applies: |c| {
+            c.cb_failures > 0
+                && c.cb_failures <= c.failover_after
+                && !c.fallback_uris.is_empty()
+                && c.lb_strategy == LbStrategy::Sticky
+        },
+        problem: "the circuit breaker opens before the bridge fails over, so MCP_FALLBACK_URIS is never used",
+        fix: "raise MCP_CB_FAILURES above MCP_FAILOVER_AFTER",
+    },
+];
+
+/// Every rule the configuration breaks, in table order.
+pub fn check(config: &Config) -> Vec<&'static Rule> {
+    RULES.iter().filter(|rule| (rule.applies)(config)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::OPTIONS;
+
+    /// Smallest environment that breaks each rule, in `RULES` order.
+    /// Any readable, non-empty file passes for a token file.
+    const TOKEN_FILE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+
+    const CA_CERT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/localhost.crt");
+
+    const TRIGGERS: &[&[(&str, &str)]] = &[
+        &[
+            ("MCP_RAW_PASSTHROUGH", "1"),
+            ("MCP_STDIN_VALIDATION", "lossy"),
+        ],
+        &[
+            ("MCP_RAW_PASSTHROUGH", "1"),
+            ("MCP_STDOUT_VALIDATION", "lossy"),
+        ],
+        &[("MCP_STDIN_VALIDATION", "bytes"), ("MCP_REWRITE_IDS", "1")],
+        &[("MCP_RAW_PASSTHROUGH", "1"), ("MCP_REWRITE_IDS", "1")],
+        &[("MCP_RAW_PASSTHROUGH", "1"), ("MCP_METHOD_ALIASES", "a=b")],
+        &[
+            ("MCP_RAW_PASSTHROUGH", "1"),
+            ("MCP_STRIP_SERVER_CAPABILITIES", "sampling"),
+        ],
+        &[("MCP_RAW_PASSTHROUGH", "1"), ("MCP_CONTROL_METHODS", "1")],
+        &[
+            ("MCP_RAW_PASSTHROUGH", "1"),
+            ("MCP_RATE_LIMIT_WARN_BELOW", "5"),
+        ],
+        &[("MCP_RAW_PASSTHROUGH", "1"), ("MCP_MAX_JSON_DEPTH", "64")],
+        &[
+            ("MCP_RAW_PASSTHROUGH", "1"),
+            ("MCP_SPLIT_BATCH_RESPONSES", "1"),
+        ],
+        &[
+            ("MCP_RAW_PASSTHROUGH", "1"),
+            ("MCP_ORDERED_RESPONSES", "1"),
+        ],
+        &[
+            ("MCP_MAX_CONCURRENCY", "1"),
+            ("MCP_ORDERED_RESPONSES", "1"),
+        ],
+        &[
+            ("MCP_RAW_PASSTHROUGH", "1"),
+            ("MCP_META_HEADERS", "tenantId=X-Tenant-Id"),
+            ("MCP_META_STRIP", "1"),
+        ],
+        &[
+            ("MCP_RAW_PASSTHROUGH", "1"),
+            ("MCP_LOG_NOTIFICATIONS", "drop"),
+        ],
+        &[
+            ("MCP_RAW_PASSTHROUGH", "1"),
+            ("MCP_CACHE", "tools/list=60s"),
+        ],
+        &[
+            ("MCP_RAW_PASSTHROUGH", "1"),
+            ("MCP_VALIDATE_STDIN_JSON", "1"),
+        ],
+        &[("MCP_INTEGRITY_MAX_RETRIES", "3")],
+        &[("MCP_META_STRIP", "1")],
+        &[("MCP_HEDGE_DELAY_MS", "100")],
+        &[
+            ("MCP_HEDGE_METHODS", "tools/list"),
+            ("MCP_HEDGE_DELAY_MS", "500"),
+            ("MCP_TOTAL_BUDGET_MS", "500"),
+        ],
+        &[("MCP_MAX_PAUSE_SECS", "60")],
+        &[("MCP_SESSION_QUERY_PARAM", "sid")],
+        &[
+            ("MCP_KEEPALIVE_INTERVAL_MS", "30000"),
+            ("MCP_KEEPALIVE_INTERVAL_SECS", "60"),
+        ],
+        &[
+            ("MCP_KEEPALIVE_INTERVAL_SECS", "300"),
+            ("MCP_IDLE_EXIT_SECS", "300"),
+        ],
+        &[
+            ("MCP_RAW_PASSTHROUGH", "1"),
+            ("MCP_QUIRK_ACK_INITIALIZED", "1"),
+        ],
+        &[("MCP_GET_STREAM_405_LIMIT", "5")],
+        &[
+            ("MCP_OAUTH_TOKEN_URL", "https://idp.example/token"),
+            ("MCP_OAUTH_CLIENT_SECRET", "s"),
+        ],
+        &[
+            ("MCP_OAUTH_TOKEN_URL", "https://idp.example/token"),
+            ("MCP_OAUTH_CLIENT_ID", "c"),
+        ],
+        &[
+            ("MCP_BRIDGE_BEARER_TOKEN", "t"),
+            ("MCP_OAUTH_TOKEN_URL", "https://idp.example/token"),
+            ("MCP_OAUTH_CLIENT_ID", "c"),
+            ("MCP_OAUTH_CLIENT_SECRET", "s"),
+        ],
+        &[("MCP_OAUTH_SCOPE", "mcp:read")],
+        &[("MCP_BRIDGE_BEARER_TOKEN", "t"), ("MCP_BEARER_TOKEN_FILE", TOKEN_FILE)],
+        &[
+            ("MCP_BEARER_TOKEN_FILE", TOKEN_FILE),
+            ("MCP_OAUTH_TOKEN_URL", "https://idp.example/token"),
+            ("MCP_OAUTH_CLIENT_ID", "c"),
+            ("MCP_OAUTH_CLIENT_SECRET", "s"),
+        ],
+        &[("MCP_BEARER_TOKEN_RELOAD_EVERY", "10")],
+        &[("MCP_BRIDGE_BEARER_TOKEN", "t"), ("MCP_AUTH_MODE", "none")],
+        &[("MCP_BEARER_TOKEN_FILE", TOKEN_FILE), ("MCP_AUTH_MODE", "none")],
+        &[
+            ("MCP_OAUTH_TOKEN_URL", "https://idp.example/token"),
+            ("MCP_OAUTH_CLIENT_ID", "c"),
+            ("MCP_OAUTH_CLIENT_SECRET", "s"),
+            ("MCP_AUTH_MODE", "none"),
+        ],
+        &[("MCP_BASIC_USER", "me")],
+        &[("MCP_API_KEY_HEADER", "X-Key")],
+        &[
+            ("MCP_PROXY", "http://proxy.internal:3128"),
+            ("MCP_NO_PROXY", "1"),
+        ],
+        &[("MCP_REDIRECT_LIMIT", "3"), ("MCP_REDIRECT", "none")],
+        &[
+            ("MCP_REDIRECT_AUTH_HOSTS", "eu.mcp.example"),
+            ("MCP_REDIRECT", "follow-same-host"),
+        ],
+        &[("MCP_CLIENT_KEY", "client.key")],
+        &[("MCP_CLIENT_CERT_PASSWORD", "pw")],
+        &[("MCP_CA_CERT", CA_CERT), ("MCP_TLS_INSECURE", "1")],
+        &[("MCP_LOG_BODY_LIMIT", "512")],
+        &[("MCP_FAILOVER_AFTER", "5")],
+        &[("MCP_LB_STRATEGY", "round-robin")],
+        &[
+            ("MCP_FALLBACK_URIS", "https://b.example/mcp"),
+            ("MCP_LB_STRATEGY", "round-robin"),
+            ("MCP_FAILOVER_AFTER", "5"),
+        ],
+        &[("MCP_LB_COOLDOWN_MS", "1000")],
+        #[cfg(feature = "websocket")]
+        &[
+            ("MCP_BRIDGE_URI", "wss://mcp.example/ws"),
+            ("MCP_FALLBACK_URIS", "https://b.example/mcp"),
+        ],
+        &[("MCP_WS_PING_SECS", "10")],
+        &[
+            ("MCP_LISTEN_ADDR", "127.0.0.1:9100"),
+            ("MCP_METRICS_ADDR", "127.0.0.1:9100"),
+        ],
+        &[("MCP_CONNECTION_WEIGHTS", "10.0.0.5=3")],
+        &[
+            ("MCP_POOL_IDLE_TIMEOUT_MS", "5000"),
+            ("MCP_POOL_MAX_IDLE", "0"),
+        ],
+        &[
+            ("MCP_TRACE_CONTEXT", "1"),
+            ("MCP_OTEL_EXPORTER", "http://otel.internal:4318"),
+        ],
+        &[("MCP_CB_RESET_MS", "5000")],
+        &[
+            ("MCP_CB_FAILURES", "3"),
+            ("MCP_FALLBACK_URIS", "https://b.example/mcp"),
+        ],
+    ];
+
+    fn config(vars: &[(&str, &str)]) -> Config {
+        Config::from_lookup(|name| {
+            vars.iter()
+                .find(|(k, _)| *k == name)
+                .map(|(_, v)| v.to_string())
+        })
+    }
+
+    #[test]
+    fn defaults_are_consistent() {
+        assert!(check(&config(&[])).is_empty());
+    }
+
+    #[test]
+    fn each_rule_fires_on_its_own_trigger_only() {
+        assert_eq!(RULES.len(), TRIGGERS.len());
+        for (rule, vars) in RULES.iter().zip(TRIGGERS) {
+            let broken: Vec<_> = check(&config(vars)).iter().map(|r| r.settings).collect();
+            assert_eq!(broken, vec![rule.settings], "trigger {:?}", vars);
+        }
+    }
+
+    #[test]
+    fn fixed_configurations_pass() {
+        let fixed: &[&[(&str, &str)]] = &[
+            &[
+                ("MCP_RAW_PASSTHROUGH", "1"),
+                ("MCP_STDIN_VALIDATION", "strict"),
+            ],
+            &[
+                ("MCP_INTEGRITY_MAX_RETRIES", "3"),
+                ("MCP_INTEGRITY_HEADER", "1"),
+            ],
+            &[
+                ("MCP_HEDGE_DELAY_MS", "100"),
+                ("MCP_HEDGE_METHODS", "tools/list"),
+            ],
+            &[("MCP_MAX_PAUSE_SECS", "60"), ("MCP_CONTROL_METHODS", "1")],
+            &[
+                ("MCP_SESSION_QUERY_PARAM", "sid"),
+                ("MCP_SESSION_STYLE", "both"),
+            ],
+            &[
+                ("MCP_KEEPALIVE_INTERVAL_SECS", "60"),
+                ("MCP_IDLE_EXIT_SECS", "300"),
+            ],
+        ];
+        for vars in fixed {
+            assert!(check(&config(vars)).is_empty(), "{:?}", vars);
+        }
+    }
+
+    #[test]
+    fn messages_name_both_settings_and_the_fix() {
+        let envs: Vec<&str> = OPTIONS.iter().flat_map(|o| o.env.iter().copied()).collect();
+        for rule in RULES {
+            let message = rule.message();
+            for setting in rule.settings {
+                assert!(envs.contains(&setting), "{} is not an option", setting);
+                assert!(message.contains(setting), "{}", message);
+            }
+            assert!(message.ends_with(rule.fix));
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use serde_json::Value;
+use std::time::Duration;
+use tokio::time::Instant;
+
+pub const PAUSE_METHOD: &str = "bridge/pause";
+pub const RESUME_METHOD: &str = "bridge/resume";
+pub const STATS_METHOD: &str = "bridge/stats";
+pub const DEFAULT_MAX_PAUSE_SECS: u64 = 300;
+
+/// Why a pause ended on its own.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PauseExpiry {
+    /// The `resume_after_ms` requested with the pause elapsed.
+    Requested,
+    /// The pause hit `MCP_MAX_PAUSE_SECS`.
+    MaxPause,
+}
+
+#[derive(Debug)]
+struct Paused {
+    since: Instant,
+    resume_at: Instant,
+    expiry: PauseExpiry,
+}
+
+/// Pause state for `bridge/pause` / `bridge/resume`. Uses tokio time so the
+/// deadline follows a paused clock in tests.
+#[derive(Debug)]
+pub struct Pause {
+    max: Duration,
+    paused: Option<Paused>,
+}
+
+impl Pause {
+    pub fn new(max: Duration) -> Self {
+        Self { max, paused: None }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.is_some()
+    }
+
+    /// Starts (or re-arms) a pause. `resume_after` beyond the maximum is
+    /// capped to it.
+    pub fn pause(&mut self, resume_after: Option<Duration>) {
+        let now = Instant::now();
+        let (delay, expiry) = match resume_after {
+            Some(d) if d <= self.max => (d, PauseExpiry::Requested),
+            _ => (self.max, PauseExpiry::MaxPause),
+        };
+        let since = self.paused.as_ref().map_or(now, |p| p.since);
+        self.paused = Some(Paused {
+            since,
+            resume_at: now + delay,
+            expiry,
+        });
+    }
+
+    /// Ends the pause, returning how long it lasted.
+    pub fn resume(&mut self) -> Option<Duration> {
+        self.paused.take().map(|p| p.since.elapsed())
+    }
+
+    /// Resolves when the current pause should end on its own; never when not
+    /// paused.
+    pub async fn expired(&self) -> PauseExpiry {
+        match &self.paused {
+            Some(p) => {
+                tokio::time::sleep_until(p.resume_at).await;
+                p.expiry
+            }
+            None => std::future::pending().await,
+        }
+    }
+}
+
+/// `resume_after_ms` from a `bridge/pause` request, if present.
+pub fn resume_after(msg: &Value) -> Option<Duration> {
+    msg.get("params")?
+        .get("resume_after_ms")?
+        .as_u64()
+        .map(Duration::from_millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test(start_paused = true)]
+    async fn requested_resume_fires_after_delay() {
+        let mut pause = Pause::new(Duration::from_secs(300));
+        pause.pause(Some(Duration::from_millis(1500)));
+        let start = Instant::now();
+        assert_eq!(pause.expired().await, PauseExpiry::Requested);
+        assert_eq!(start.elapsed(), Duration::from_millis(1500));
+        assert_eq!(pause.resume(), Some(Duration::from_millis(1500)));
+        assert!(!pause.is_paused());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn pause_is_capped_at_max() {
+        let mut pause = Pause::new(Duration::from_secs(60));
+        pause.pause(Some(Duration::from_secs(3600)));
+        let start = Instant::now();
+        assert_eq!(pause.expired().await, PauseExpiry::MaxPause);
+        assert_eq!(start.elapsed(), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn resume_after_is_read_from_params() {
+        let msg = json!({"method": "bridge/pause", "params": {"resume_after_ms": 250}});
+        assert_eq!(resume_after(&msg), Some(Duration::from_millis(250)));
+        assert_eq!(resume_after(&json!({"method": "bridge/pause"})), None);
+    }
+}
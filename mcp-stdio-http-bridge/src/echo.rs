@@ -0,0 +1,195 @@
+//! `MCP_MODE=echo`: requests are answered by the bridge itself instead of
+//! an upstream, to check a client's stdio wiring without a server.
+//! `initialize` gets a minimal capabilities result, `tools/list` an empty
+//! list and every other request its own params back. Messages still go
+//! through the same queues and stdout writer as in proxy mode, so ordering,
+//! queue policies and shutdown behave as they would against a server.
+
+use crate::aliases::MethodAliases;
+use crate::bridge;
+use crate::jsonrpc;
+use crate::request_body::RequestBody;
+use crate::responder::Responder;
+use crate::stats::{Sample, Stats};
+use crate::stdio::EvictOldest;
+use serde_json::{json, Map, Value};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::mpsc;
+use tracing::{debug, info};
+
+/// `protocolVersion` answered when the client's `initialize` names none,
+/// and the one `--check` asks for.
+pub const PROTOCOL_VERSION: &str = "2025-03-26";
+
+/// Where client requests are answered (`MCP_MODE`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// Sent to the upstream in `MCP_BRIDGE_URI`.
+    #[default]
+    Proxy,
+    /// Answered locally; nothing is sent upstream.
+    Echo,
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    /// Only the exact names are accepted, so a typo cannot turn echo on.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "proxy" => Ok(Self::Proxy),
+            "echo" => Ok(Self::Echo),
+            other => Err(format!("unknown mode {:?}; use proxy or echo", other)),
+        }
+    }
+}
+
+impl Mode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Proxy => "proxy",
+            Self::Echo => "echo",
+        }
+    }
+}
+
+/// Answers each queued client message in turn until stdin has closed or
+/// stdout has gone away.
+pub async fn run_bridge(
+    mut rx: mpsc::Receiver<RequestBody>,
+    evict_oldest: Arc<EvictOldest>,
+    errors: mpsc::Receiver<String>,
+    tx_out: mpsc::Sender<String>,
+    stats: Arc<Stats>,
+) {
+    let responder = Responder::new(tx_out, stats.clone(), MethodAliases::default(), None);
+    tokio::spawn(responder.clone().forward_errors(errors));
+    loop {
+        let msg = tokio::select! {
+            msg = rx.recv() => msg,
+            evict = evict_oldest.requested() => {
+                if evict && bridge::drop_oldest(&responder, &mut rx).await.is_err() {
+                    break;
+                }
+                continue;
+            }
+        };
+        let Some(msg) = msg else {
+            break;
+        };
+        let started = Instant::now();
+        let text = msg.text();
+        let reply = answer(&text);
+        stats.record(Sample {
+            method: jsonrpc::method(&text).as_deref(),
+            latency: started.elapsed(),
+            bytes_out: msg.len() as u64,
+            bytes_in: reply.as_ref().map_or(0, |r| r.len() as u64),
+            error: false,
+        });
+        let Some(reply) = reply else {
+            debug!("echo mode: no answer for a notification or response");
+            continue;
+        };
+        if responder.local(reply).await.is_err() {
+            break;
+        }
+    }
+    info!(top_methods = %stats.top_summary(bridge::TOP_METHODS_LOGGED), "bridge finished");
+}
+
+/// The local answer to a client message: one per request, a batch for a
+/// batch, and none for notifications and responses.
+pub fn answer(msg: &str) -> Option<String> {
+    let value: Value = match serde_json::from_str(msg) {
+        Ok(value) => value,
+        Err(_) => {
+            return Some(jsonrpc::error_response(
+                None,
+                jsonrpc::PARSE_ERROR,
+                "echo mode: message is not valid JSON",
+            ))
+        }
+    };
+    match value {
+        Value::Array(items) => {
+            let answers: Vec<Value> = items.iter().filter_map(answer_one).collect();
+            (!answers.is_empty()).then(|| Value::Array(answers).to_string())
+        }
+        value => answer_one(&value).map(|a| a.to_string()),
+    }
+}
+
+fn answer_one(msg: &Value) -> Option<Value> {
+    let request = msg.as_object()?;
+    let method = request.get("method")?.as_str()?;
+    let id = request.get("id")?;
+    let params = request.get("params");
+    let result = match method {
+        "initialize" => {
+            let version = params
+                .and_then(|p| p.get("protocolVersion"))
+                .and_then(Value::as_str)
+                .unwrap_or(PROTOCOL_VERSION);
+            json!({
+                "protocolVersion": version,
+                "capabilities": {"tools": {}},
+                "serverInfo": {
+                    "name": "mcp-stdio-http-bridge (echo)",
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+            })
+        }
+        "tools/list" => json!({"tools": []}),
+        _ => params.cloned().unwrap_or_else(|| Value::Object(Map::new())),
+    };
+    Some(json!({"jsonrpc": "2.0", "id": id, "result": result}))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(msg: &str) -> Value {
+        serde_json::from_str(&answer(msg).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn initialize_and_tools_list_get_canned_results() {
+        let init = parsed(r#"{"jsonrpc":"2.0","id":0,"method":"initialize","params":{"protocolVersion":"2024-11-05"}}"#);
+        assert_eq!(init["id"], 0);
+        assert_eq!(init["result"]["protocolVersion"], "2024-11-05");
+        assert_eq!(init["result"]["capabilities"], json!({"tools": {}}));
+        let list = parsed(r#"{"jsonrpc":"2.0","id":"l","method":"tools/list"}"#);
+        assert_eq!(list, json!({"jsonrpc": "2.0", "id": "l", "result": {"tools": []}}));
+    }
+
+    #[test]
+    fn other_requests_get_their_params_back() {
+        let call = parsed(r#"{"jsonrpc":"2.0","id":7,"method":"tools/call","params":{"name":"x"}}"#);
+        assert_eq!(call, json!({"jsonrpc": "2.0", "id": 7, "result": {"name": "x"}}));
+        let ping = parsed(r#"{"jsonrpc":"2.0","id":8,"method":"ping"}"#);
+        assert_eq!(ping["result"], json!({}));
+    }
+
+    #[test]
+    fn notifications_get_no_answer_and_batches_get_a_batch() {
+        assert_eq!(answer(r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#), None);
+        assert_eq!(answer(r#"{"jsonrpc":"2.0","id":1,"result":{}}"#), None);
+        let batch = parsed(
+            r#"[{"jsonrpc":"2.0","id":1,"method":"a","params":[1]},{"jsonrpc":"2.0","method":"n"}]"#,
+        );
+        assert_eq!(batch, json!([{"jsonrpc": "2.0", "id": 1, "result": [1]}]));
+        assert_eq!(parsed("{oops")["error"]["code"], jsonrpc::PARSE_ERROR);
+    }
+
+    #[test]
+    fn only_exact_mode_names_are_accepted() {
+        assert_eq!("echo".parse(), Ok(Mode::Echo));
+        assert_eq!("proxy".parse(), Ok(Mode::Proxy));
+        assert!("Echo".parse::<Mode>().is_err());
+        assert!("1".parse::<Mode>().is_err());
+    }
+}
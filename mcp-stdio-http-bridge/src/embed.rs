@@ -0,0 +1,492 @@
+//! Running the bridge inside another program rather than as a process of
+//! its own. A `Bridge` takes client messages from an input and hands the
+//! answers to an output: byte streams such as stdio carry them as lines
+//! (`Bridge::run_lines`), and a host program can pass them directly as a
+//! `Stream` and a `Sink` (`Bridge::run`).
+//!
+//! `MCP_MODE=echo` answers locally, so this example runs without a server:
+//!
+//! ```
+//! use futures_util::stream;
+//! use mcp_stdio_http_bridge::config::Config;
+//! use mcp_stdio_http_bridge::embed::Ended;
+//! use mcp_stdio_http_bridge::Bridge;
+//! use tokio_util::sync::PollSender;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let config = Config::from_lookup(|name| match name {
+//!     "MCP_BRIDGE_URI" => Some("http://127.0.0.1:3000/mcp".to_string()),
+//!     "MCP_MODE" => Some("echo".to_string()),
+//!     _ => None,
+//! });
+//! let bridge = Bridge::builder().config(config).build()?;
+//! let (answers_tx, mut answers) = tokio::sync::mpsc::channel(16);
+//! let ping = r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#.to_string();
+//! let running = tokio::spawn(bridge.run(stream::iter([ping]), PollSender::new(answers_tx)));
+//! let answer: serde_json::Value = serde_json::from_str(&answers.recv().await.unwrap())?;
+//! assert_eq!(answer["id"], 1);
+//! assert_eq!(answer["result"], serde_json::json!({}));
+//! assert_eq!(running.await?.ended, Ended::InputClosed);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::auth::AuthProvider;
+use crate::bridge;
+use crate::config::Config;
+use crate::fair::{self, Budget, ClientGauge};
+use crate::jsonrpc;
+use crate::reassemble::Reassembler;
+use crate::record::Recorder;
+use crate::request_body::RequestBody;
+use crate::stats::Stats;
+use crate::stdio::{self, Enqueue, EvictOldest};
+use futures_util::{Sink, SinkExt, Stream, StreamExt};
+use std::fmt;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::mpsc::{self, WeakSender};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+/// How long answers still queued once the bridge has stopped may take to
+/// reach the output.
+const OUTPUT_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// One bridge to the upstream in `Config::uri`, with its own queues and
+/// session.
+pub struct Bridge {
+    config: Config,
+    stats: Arc<Stats>,
+    auth: Arc<AuthProvider>,
+    shutdown: CancellationToken,
+    share: Option<(Arc<Budget>, SocketAddr)>,
+    input: (mpsc::Sender<RequestBody>, mpsc::Receiver<RequestBody>),
+    output: (mpsc::Sender<String>, mpsc::Receiver<String>),
+}
+
+/// Settings for a `Bridge`; only the config is required.
+#[derive(Default)]
+pub struct BridgeBuilder {
+    config: Option<Config>,
+    stats: Option<Arc<Stats>>,
+    auth: Option<Arc<AuthProvider>>,
+    shutdown: Option<CancellationToken>,
+    share: Option<(Arc<Budget>, SocketAddr)>,
+}
+
+/// Why a `Bridge` could not be built.
+#[derive(Debug, PartialEq, Eq)]
+pub enum BuildError {
+    MissingConfig,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::MissingConfig => write!(f, "Bridge::builder() was given no config"),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+impl BridgeBuilder {
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Counters to record into, e.g. ones a status page also reads; fresh
+    /// ones, with the `MCP_RECORD_FILE` recorder, by default.
+    pub fn stats(mut self, stats: Arc<Stats>) -> Self {
+        self.stats = Some(stats);
+        self
+    }
+
+    /// Credentials shared with other bridges; by default they are acquired
+    /// as the config says.
+    pub fn auth(mut self, auth: Arc<AuthProvider>) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// A token whose cancellation shuts the bridge down, for a host that
+    /// stops several things at once. The bridge never cancels it itself.
+    pub fn shutdown(mut self, token: CancellationToken) -> Self {
+        self.shutdown = Some(token);
+        self
+    }
+
+    /// A `MCP_MAX_CONCURRENCY` budget shared with other bridges, as under
+    /// `MCP_LISTEN_ADDR`. Messages then wait in a queue of this bridge's
+    /// own until the budget admits them, at the weight it gives `peer`.
+    pub fn share(mut self, budget: Arc<Budget>, peer: SocketAddr) -> Self {
+        self.share = Some((budget, peer));
+        self
+    }
+
+    pub fn build(self) -> Result<Bridge, BuildError> {
+        let config = self.config.ok_or(BuildError::MissingConfig)?;
+        let stats = self.stats.unwrap_or_else(|| {
+            let recorder = config.record_file.as_deref().and_then(Recorder::open);
+            Arc::new(Stats::new(config.stats_method_limit).with_recorder(recorder))
+        });
+        let auth = self
+            .auth
+            .unwrap_or_else(|| Arc::new(AuthProvider::from_config(&config)));
+        Ok(Bridge {
+            stats,
+            auth,
+            shutdown: self.shutdown.unwrap_or_default(),
+            share: self.share,
+            input: mpsc::channel(config.max_queue),
+            output: mpsc::channel(config.max_queue),
+            config,
+        })
+    }
+}
+
+/// Stops a running `Bridge`: input is no longer read, and what is queued or
+/// in flight gets `MCP_SHUTDOWN_GRACE_MS` to be answered.
+#[derive(Clone, Debug)]
+pub struct ShutdownHandle(CancellationToken);
+
+impl ShutdownHandle {
+    pub fn shutdown(&self) {
+        self.0.cancel();
+    }
+}
+
+/// Why a `Bridge` stopped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ended {
+    /// Its `ShutdownHandle` (or the token it was built with) was used.
+    Shutdown,
+    /// The input ended, and everything read from it has been answered:
+    /// by the upstream within `MCP_SHUTDOWN_GRACE_MS`, otherwise with a
+    /// shutdown error.
+    InputClosed,
+    /// The input could not be read: an I/O error, or invalid UTF-8 under
+    /// `MCP_STDIN_VALIDATION=strict`.
+    InputFailed,
+    /// The output stopped taking answers.
+    OutputClosed,
+    /// The bridge stopped on its own.
+    Stopped,
+    /// The input reader, the output writer or the bridge panicked.
+    Panicked,
+}
+
+/// What `Bridge::run` and `Bridge::run_lines` return.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Finished {
+    pub ended: Ended,
+    /// Every answer reached the output before the bridge returned.
+    pub drained: bool,
+}
+
+/// What the input or output closing means.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Departure {
+    /// The client has no more requests: those already read are still sent
+    /// and answered, within the shutdown grace.
+    Drain,
+    /// The client has gone away: what it still had queued is dropped and
+    /// its upstream calls cancelled.
+    Abandon,
+}
+
+impl Bridge {
+    pub fn builder() -> BridgeBuilder {
+        BridgeBuilder::default()
+    }
+
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle(self.shutdown.clone())
+    }
+
+    pub fn stats(&self) -> &Arc<Stats> {
+        &self.stats
+    }
+
+    /// The queue of client messages waiting for the bridge, for reporting
+    /// its depth.
+    pub fn input_queue(&self) -> WeakSender<RequestBody> {
+        self.input.0.downgrade()
+    }
+
+    /// The queue of answers waiting for the output, for reporting its depth.
+    pub fn output_queue(&self) -> WeakSender<String> {
+        self.output.0.downgrade()
+    }
+
+    /// Runs over a byte stream of newline-delimited JSON-RPC, as the binary
+    /// does over stdin and stdout: `MCP_MAX_LINE_BYTES`,
+    /// `MCP_STDIN_VALIDATION` and `MCP_REASSEMBLE_SPLIT_JSON` apply to
+    /// every line read. The end of `input` starts a shutdown, as in `run`.
+    pub async fn run_lines<R, W>(self, input: R, output: W) -> Finished
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        self.lines(Departure::Drain, input, output).await
+    }
+
+    /// `run_lines` for a client connection, which ends when either
+    /// direction closes: the client is then gone, so nothing more is sent
+    /// for it and its upstream calls are cancelled.
+    pub async fn run_connection<R, W>(self, input: R, output: W) -> Finished
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        self.lines(Departure::Abandon, input, output).await
+    }
+
+    async fn lines<R, W>(self, departure: Departure, input: R, output: W) -> Finished
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        let mode = self.config.stdin_validation;
+        let max_line = self.config.max_line_bytes;
+        let reassembler = self
+            .config
+            .reassemble_split_json
+            .then(Reassembler::default);
+        let recorder = self.stats.recorder().cloned();
+        self.run_with(
+            departure,
+            move |queue, shutdown, errors| {
+                stdio::read_lines(input, queue, shutdown, mode, max_line, reassembler, errors)
+            },
+            move |rx| stdio::write_lines(rx, output, recorder),
+        )
+        .await
+    }
+
+    /// Runs over messages passed directly: each item of `input` is one
+    /// JSON-RPC message, and each answer is sent to `output`. The bridge
+    /// stops once `input` ends and everything read from it is answered.
+    ///
+    /// The end of `input` starts a shutdown: what is queued or in flight
+    /// gets `MCP_SHUTDOWN_GRACE_MS` (5 s by default) and is then answered
+    /// with a shutdown error. A host whose calls may take longer keeps
+    /// `input` open until their answers have arrived, or raises the grace.
+    pub async fn run<I, O>(self, input: I, output: O) -> Finished
+    where
+        I: Stream<Item = String> + Send + 'static,
+        O: Sink<String> + Send + 'static,
+    {
+        let recorder = self.stats.recorder().cloned();
+        self.run_with(
+            Departure::Drain,
+            move |queue, shutdown, _| read_messages(input, queue, shutdown),
+            move |rx| write_messages(rx, output, recorder),
+        )
+        .await
+    }
+
+    /// Wires an input reader and an output writer to the bridge, and waits
+    /// until it stops and the output has drained (for at most
+    /// `OUTPUT_DRAIN_TIMEOUT`).
+    async fn run_with<R, RF, W, WF>(self, departure: Departure, reader: R, writer: W) -> Finished
+    where
+        R: FnOnce(Enqueue, CancellationToken, mpsc::Sender<String>) -> RF,
+        RF: Future<Output = std::io::Result<()>> + Send + 'static,
+        W: FnOnce(mpsc::Receiver<String>) -> WF,
+        WF: Future<Output = ()> + Send + 'static,
+    {
+        let Bridge {
+            config,
+            stats,
+            auth,
+            shutdown: host,
+            share,
+            input: (tx_in, rx_in),
+            output: (tx_out, rx_out),
+        } = self;
+        // The input ending shuts this bridge down, but not the host.
+        let shutdown = host.child_token();
+        let evict_oldest = Arc::new(EvictOldest::default());
+        let (tx_errors, rx_errors) = mpsc::channel(config.max_queue);
+        // With a shared budget the reader fills a lane of this bridge's own,
+        // and the admitter feeds the bridge from it. The admitter then makes
+        // room in the lane, which is what fills up.
+        let (tx_queue, bridge_evicts, admitter) = match share {
+            Some((budget, peer)) => {
+                let (tx_lane, rx_lane) = mpsc::channel(config.max_queue);
+                let gauge = Arc::new(ClientGauge::new(peer, budget.weight(peer.ip()), &tx_lane));
+                stats.track_client(&gauge);
+                let admitter = tokio::spawn(fair::admit(
+                    budget,
+                    gauge,
+                    rx_lane,
+                    evict_oldest.clone(),
+                    tx_errors.clone(),
+                    tx_in,
+                ));
+                (tx_lane, Arc::new(EvictOldest::default()), Some(admitter))
+            }
+            None => (tx_in, evict_oldest.clone(), None),
+        };
+        let queue = Enqueue::new(
+            tx_queue,
+            config.queue_policy,
+            evict_oldest,
+            tx_errors.clone(),
+        )
+        .recording(stats.recorder().cloned());
+        let mut reader = tokio::spawn(reader(queue, shutdown.clone(), tx_errors.clone()));
+        let mut writer = tokio::spawn(writer(rx_out));
+        let mut bridge = bridge::spawn(
+            config,
+            rx_in,
+            bridge_evicts,
+            rx_errors,
+            tx_out,
+            shutdown.clone(),
+            stats,
+            auth,
+        );
+        let abandon = departure == Departure::Abandon;
+        let mut reader_finished = false;
+        let mut writer_finished = false;
+        let mut bridge_finished = false;
+        let mut panicked = false;
+        let ended = tokio::select! {
+            // The reader also stops for shutdown, which comes first.
+            biased;
+            _ = shutdown.cancelled() => Ended::Shutdown,
+            read = &mut reader => {
+                reader_finished = true;
+                match read {
+                    Ok(Ok(())) => Ended::InputClosed,
+                    Ok(Err(_)) => Ended::InputFailed,
+                    Err(e) => {
+                        panicked |= e.is_panic();
+                        Ended::Stopped
+                    }
+                }
+            }
+            written = &mut writer, if abandon => {
+                writer_finished = true;
+                panicked |= written.is_err_and(|e| e.is_panic());
+                Ended::OutputClosed
+            }
+            bridged = &mut bridge => {
+                bridge_finished = true;
+                panicked |= bridged.is_err_and(|e| e.is_panic());
+                // The bridge only stops on its own when the output has gone
+                // away, or because the input ended and this branch won the
+                // race against the reader's.
+                if writer.is_finished() {
+                    Ended::OutputClosed
+                } else if reader.is_finished() {
+                    Ended::InputClosed
+                } else {
+                    Ended::Stopped
+                }
+            }
+        };
+        let client_gone = abandon && !bridge_finished && ended != Ended::Shutdown;
+        if client_gone {
+            info!("client disconnected, cancelling its in-flight requests");
+            reader.abort();
+            if let Some(admitter) = &admitter {
+                admitter.abort();
+            }
+            bridge.abort();
+        }
+        shutdown.cancel();
+        if !reader_finished {
+            panicked |= reader.await.is_err_and(|e| e.is_panic());
+        }
+        // What the bridge never took is answered, unless nobody is listening.
+        let unsent = match admitter {
+            Some(admitter) => admitter.await.unwrap_or_default(),
+            None => Vec::new(),
+        };
+        // The bridge owns the only senders to the output besides the reader's
+        // errors: it stops its GET stream before returning, and what writes
+        // the reader's errors stops once the reader (done above) has and
+        // `tx_errors` is dropped. Every answer and synthesized error is then
+        // queued and the channel closes, so the writer drains to the end
+        // instead of being cut off mid-queue.
+        if !bridge_finished {
+            panicked |= bridge.await.is_err_and(|e| e.is_panic());
+        }
+        if !client_gone && !unsent.is_empty() {
+            warn!(abandoned = unsent.len(), "answering queued messages that will not be sent");
+            for msg in unsent {
+                let err = jsonrpc::error_for(&msg.text(), jsonrpc::INTERNAL_ERROR, "bridge shutting down");
+                if let Some(err) = err {
+                    if tx_errors.send(err).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+        drop(tx_errors);
+        let drained = writer_finished || {
+            let written = tokio::time::timeout(OUTPUT_DRAIN_TIMEOUT, &mut writer).await;
+            if written.is_err() {
+                warn!("output did not drain within {:?}, giving up", OUTPUT_DRAIN_TIMEOUT);
+                writer.abort();
+            }
+            panicked |= written.as_ref().is_ok_and(|w| w.as_ref().is_err_and(|e| e.is_panic()));
+            written.is_ok()
+        };
+        let ended = if panicked { Ended::Panicked } else { ended };
+        Finished { ended, drained }
+    }
+}
+
+/// Queues each message from `input` until it ends or shutdown begins. A
+/// `Stream` of messages cannot fail, so neither can this.
+async fn read_messages<I: Stream<Item = String>>(
+    input: I,
+    mut queue: Enqueue,
+    shutdown: CancellationToken,
+) -> std::io::Result<()> {
+    tokio::pin!(input);
+    loop {
+        let next = tokio::select! {
+            _ = shutdown.cancelled() => break,
+            next = input.next() => next,
+        };
+        let Some(msg) = next else {
+            debug!("bridge input ended");
+            break;
+        };
+        if queue.send(RequestBody::from(msg)).await.is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Sends every answer from `rx` to `output` until the last sender is
+/// dropped, then closes it. Stops early if `output` fails.
+async fn write_messages<O: Sink<String>>(
+    mut rx: mpsc::Receiver<String>,
+    output: O,
+    recorder: Option<Arc<Recorder>>,
+) {
+    tokio::pin!(output);
+    while let Some(msg) = rx.recv().await {
+        if let Some(recorder) = &recorder {
+            recorder.stdout(&msg);
+        }
+        if output.send(msg).await.is_err() {
+            debug!("bridge output closed");
+            return;
+        }
+    }
+    let _ = output.close().await;
+}
@@ -0,0 +1,100 @@
+//! Moving to the next upstream endpoint when the one in use keeps failing
+//! (`MCP_FALLBACK_URIS`). The bridge stays with whichever endpoint last
+//! worked; there is no automatic return to `MCP_BRIDGE_URI`.
+
+/// Retryable failures in a row against one endpoint before moving on, when
+/// `MCP_FAILOVER_AFTER` is not set.
+pub const DEFAULT_FAILOVER_AFTER: u32 = 3;
+
+/// Splits `MCP_FALLBACK_URIS` into URIs, returning those that are not
+/// http(s) URLs separately.
+pub fn parse_uris(spec: &str) -> (Vec<String>, Vec<String>) {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|uri| !uri.is_empty())
+        .map(str::to_string)
+        .partition(|uri| uri.starts_with("http://") || uri.starts_with("https://"))
+}
+
+/// The endpoints in order, `MCP_BRIDGE_URI` first, and a count of failures in a row
+/// against the one in use.
+#[derive(Debug)]
+pub struct Failover {
+    uris: Vec<String>,
+    current: usize,
+    failures: u32,
+    after: u32,
+}
+
+impl Failover {
+    pub fn new(uri: &str, fallbacks: &[String], after: u32) -> Self {
+        let mut uris = vec![uri.to_string()];
+        uris.extend(fallbacks.iter().cloned());
+        Self {
+            uris,
+            current: 0,
+            failures: 0,
+            after,
+        }
+    }
+
+    /// Counts a retryable failure of a request sent to `sent`, returning the
+    /// endpoint to use from now on once the one in use has failed `after`
+    /// times in a row. Failures against an endpoint already left behind do
+    /// not count, so requests that were in flight during a failover cannot
+    /// trigger another.
+    pub fn on_failure(&mut self, sent: &str) -> Option<String> {
+        if self.uris.len() < 2 || self.uris[self.current] != sent {
+            return None;
+        }
+        self.failures += 1;
+        if self.failures < self.after {
+            return None;
+        }
+        self.failures = 0;
+        self.current = (self.current + 1) % self.uris.len();
+        Some(self.uris[self.current].clone())
+    }
+
+    /// A request sent to `sent` succeeded.
+    pub fn on_success(&mut self, sent: &str) {
+        if self.uris[self.current] == sent {
+            self.failures = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: &str = "https://a.example/mcp";
+    const B: &str = "https://b.example/mcp";
+
+    #[test]
+    fn moves_on_after_failures_in_a_row_and_wraps_around() {
+        let mut failover = Failover::new(A, &[B.to_string()], 2);
+        assert_eq!(failover.on_failure(A), None);
+        failover.on_success(A);
+        assert_eq!(failover.on_failure(A), None);
+        assert_eq!(failover.on_failure(A).as_deref(), Some(B));
+        // A late failure from a request still sent to A changes nothing.
+        assert_eq!(failover.on_failure(A), None);
+        assert_eq!(failover.on_failure(A), None);
+        assert_eq!(failover.on_failure(B), None);
+        assert_eq!(failover.on_failure(B).as_deref(), Some(A));
+    }
+
+    #[test]
+    fn a_single_endpoint_never_fails_over() {
+        let mut failover = Failover::new(A, &[], 1);
+        assert_eq!(failover.on_failure(A), None);
+    }
+
+    #[test]
+    fn fallback_list_keeps_only_http_urls() {
+        let (uris, rejected) = parse_uris(" https://b.example/mcp, ,ftp://c,http://d:8080/");
+        assert_eq!(uris, vec!["https://b.example/mcp", "http://d:8080/"]);
+        assert_eq!(rejected, vec!["ftp://c"]);
+    }
+}
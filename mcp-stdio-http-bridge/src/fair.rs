@@ -0,0 +1,315 @@
+//! Fair admission for `MCP_LISTEN_ADDR`. The connections share one budget
+//! of `MCP_MAX_CONCURRENCY` upstream requests, handed out in weighted
+//! round-robin so that a client flooding the bridge cannot starve a quiet
+//! one.
+//!
+//! Each connection's lines queue in a lane of its own, `MCP_MAX_QUEUE`
+//! deep under `MCP_QUEUE_POLICY`. Its admitter puts up to its weight of
+//! them in line for the budget. The line is served in order, and a
+//! connection served joins the back of it again, so while every client is
+//! busy each gets its weight in requests per round. An admitted message
+//! keeps its place in the budget until its upstream request ends.
+
+use crate::jsonrpc;
+use crate::request_body::RequestBody;
+use crate::stdio::EvictOldest;
+use futures_util::stream::{FuturesOrdered, StreamExt};
+use std::collections::VecDeque;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc::{self, WeakSender};
+use tokio::sync::Semaphore;
+use tracing::debug;
+
+/// The listener-wide budget, and the weight each peer gets in it.
+#[derive(Debug)]
+pub struct Budget {
+    slots: Arc<Semaphore>,
+    weights: Vec<(IpAddr, u32)>,
+}
+
+impl Budget {
+    pub fn new(slots: usize, weights: Vec<(IpAddr, u32)>) -> Self {
+        Self {
+            slots: Arc::new(Semaphore::new(slots)),
+            weights,
+        }
+    }
+
+    /// The weight `MCP_CONNECTION_WEIGHTS` gives `peer`, otherwise 1.
+    pub fn weight(&self, peer: IpAddr) -> u32 {
+        self.weights
+            .iter()
+            .find(|(ip, _)| *ip == peer)
+            .map_or(1, |(_, weight)| *weight)
+    }
+}
+
+/// One client connection as stats shows it.
+#[derive(Debug)]
+pub struct ClientGauge {
+    pub peer: SocketAddr,
+    pub weight: u32,
+    /// Held weakly, so stats never keeps the lane open.
+    lane: WeakSender<RequestBody>,
+    in_line: AtomicUsize,
+    admitted: AtomicU64,
+}
+
+impl ClientGauge {
+    pub fn new(peer: SocketAddr, weight: u32, lane: &mpsc::Sender<RequestBody>) -> Self {
+        Self {
+            peer,
+            weight,
+            lane: lane.downgrade(),
+            in_line: AtomicUsize::new(0),
+            admitted: AtomicU64::new(0),
+        }
+    }
+
+    /// Messages not admitted yet: in the lane or in line for the budget.
+    pub fn queued(&self) -> usize {
+        let lane = self
+            .lane
+            .upgrade()
+            .map_or(0, |tx| tx.max_capacity() - tx.capacity());
+        lane + self.in_line.load(Ordering::Relaxed)
+    }
+
+    /// Messages handed to the connection's bridge so far.
+    pub fn admitted(&self) -> u64 {
+        self.admitted.load(Ordering::Relaxed)
+    }
+}
+
+/// Feeds one connection's bridge from its lane, each message once the
+/// budget admits it. Also serves `MCP_QUEUE_POLICY=drop-oldest` for the
+/// lane. Returns the messages never handed over, in order, once the lane
+/// is closed and empty or the bridge has stopped taking messages.
+pub async fn admit(
+    budget: Arc<Budget>,
+    gauge: Arc<ClientGauge>,
+    mut lane: mpsc::Receiver<RequestBody>,
+    evict_oldest: Arc<EvictOldest>,
+    errors: mpsc::Sender<String>,
+    bridge: mpsc::Sender<RequestBody>,
+) -> Vec<RequestBody> {
+    let mut in_line = VecDeque::new();
+    // One place in line per message in `in_line`, in the same order.
+    let mut places = FuturesOrdered::new();
+    let mut open = true;
+    while open || !in_line.is_empty() {
+        gauge.in_line.store(in_line.len(), Ordering::Relaxed);
+        tokio::select! {
+            biased;
+            _ = bridge.closed() => break,
+            Some(slot) = places.next() => {
+                // The budget is never closed.
+                let Ok(slot) = slot else { break };
+                let msg: RequestBody = in_line.pop_front().expect("a message per place");
+                gauge.admitted.fetch_add(1, Ordering::Relaxed);
+                if let Err(mpsc::error::SendError(msg)) =
+                    bridge.send(msg.admitted(Some(Arc::new(slot)))).await
+                {
+                    in_line.push_front(msg.admitted(None));
+                    break;
+                }
+            }
+            msg = lane.recv(), if open && in_line.len() < gauge.weight as usize => match msg {
+                Some(msg) => {
+                    places.push_back(budget.slots.clone().acquire_owned());
+                    in_line.push_back(msg);
+                }
+                None => open = false,
+            },
+            evict = evict_oldest.requested() => {
+                if evict && drop_oldest(&mut lane, &errors).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+    gauge.in_line.store(0, Ordering::Relaxed);
+    lane.close();
+    while let Ok(msg) = lane.try_recv() {
+        in_line.push_back(msg);
+    }
+    in_line.into()
+}
+
+/// Answers the oldest message in the lane with a busy error in place of
+/// sending it, making room for the one the reader is waiting to queue.
+/// Messages already in line for the budget keep their place.
+async fn drop_oldest(
+    lane: &mut mpsc::Receiver<RequestBody>,
+    errors: &mpsc::Sender<String>,
+) -> Result<(), ()> {
+    let Ok(old) = lane.try_recv() else {
+        return Ok(());
+    };
+    debug!("dropping oldest queued message, connection queue full");
+    let busy = "server busy: dropped from full bridge queue";
+    match jsonrpc::error_for(&old.text(), jsonrpc::SERVER_BUSY, busy) {
+        Some(err) => errors.send(err).await.map_err(|_| ()),
+        None => Ok(()),
+    }
+}
+
+/// Parses `MCP_CONNECTION_WEIGHTS`: comma-separated `ip=weight` pairs.
+pub fn parse_weights(spec: &str) -> Result<Vec<(IpAddr, u32)>, String> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|pair| {
+            let parsed = pair.split_once('=').and_then(|(ip, weight)| {
+                Some((ip.trim().parse().ok()?, weight.trim().parse::<u32>().ok()?))
+            });
+            match parsed {
+                Some((ip, weight)) if weight > 0 => Ok((ip, weight)),
+                _ => Err(format!(
+                    "invalid connection weight {:?} (expected ip=weight, weight at least 1)",
+                    pair
+                )),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn peer(n: u8) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, n], 4000))
+    }
+
+    struct Conn {
+        lane: mpsc::Sender<RequestBody>,
+        bridge: mpsc::Receiver<RequestBody>,
+        gauge: Arc<ClientGauge>,
+        task: tokio::task::JoinHandle<Vec<RequestBody>>,
+    }
+
+    fn connect(budget: &Arc<Budget>, n: u8, weight: u32) -> Conn {
+        let (lane, rx_lane) = mpsc::channel(100);
+        let (tx_bridge, bridge) = mpsc::channel(8);
+        let (errors, _) = mpsc::channel(8);
+        let gauge = Arc::new(ClientGauge::new(peer(n), weight, &lane));
+        let task = tokio::spawn(admit(
+            budget.clone(),
+            gauge.clone(),
+            rx_lane,
+            Arc::new(EvictOldest::default()),
+            errors,
+            tx_bridge,
+        ));
+        Conn {
+            lane,
+            bridge,
+            gauge,
+            task,
+        }
+    }
+
+    async fn send(conn: &Conn, name: &str, n: usize) {
+        for i in 0..n {
+            let msg = format!(r#"{{"id":"{}{}"}}"#, name, i);
+            conn.lane.send(RequestBody::from(msg)).await.unwrap();
+        }
+    }
+
+    /// Takes the next admitted message from whichever connection it went
+    /// to, and ends its request at once.
+    async fn next_admitted(conns: &mut [&mut Conn]) -> usize {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                for (i, conn) in conns.iter_mut().enumerate() {
+                    if conn.bridge.try_recv().is_ok() {
+                        return i;
+                    }
+                }
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("a message admitted")
+    }
+
+    #[tokio::test]
+    async fn a_trickling_connection_is_not_starved_by_a_flood() {
+        let budget = Arc::new(Budget::new(1, Vec::new()));
+        let mut flood = connect(&budget, 1, 1);
+        let mut trickle = connect(&budget, 2, 1);
+        send(&flood, "f", 90).await;
+        for _ in 0..5 {
+            next_admitted(&mut [&mut flood, &mut trickle]).await;
+        }
+        let mut worst_wait = 0;
+        for i in 0..5 {
+            send(&trickle, &format!("t{}-", i), 1).await;
+            let mut waited = 0;
+            loop {
+                waited += 1;
+                if next_admitted(&mut [&mut flood, &mut trickle]).await == 1 {
+                    break;
+                }
+            }
+            worst_wait = worst_wait.max(waited);
+        }
+        assert!(worst_wait <= 3, "trickle waited {} admissions", worst_wait);
+        assert_eq!(trickle.gauge.admitted(), 5);
+        assert!(flood.gauge.queued() > 50);
+    }
+
+    #[tokio::test]
+    async fn weights_set_the_share_per_round() {
+        let budget = Arc::new(Budget::new(1, Vec::new()));
+        let mut heavy = connect(&budget, 1, 3);
+        let mut light = connect(&budget, 2, 1);
+        send(&heavy, "h", 50).await;
+        send(&light, "l", 50).await;
+        // Past the start, while both lines fill up.
+        for _ in 0..8 {
+            next_admitted(&mut [&mut heavy, &mut light]).await;
+        }
+        let mut light_turns = 0;
+        for _ in 0..24 {
+            if next_admitted(&mut [&mut heavy, &mut light]).await == 1 {
+                light_turns += 1;
+            }
+        }
+        assert_eq!(light_turns, 6);
+    }
+
+    #[tokio::test]
+    async fn a_closed_bridge_hands_back_only_its_connections_messages() {
+        let budget = Arc::new(Budget::new(0, Vec::new()));
+        let gone = connect(&budget, 1, 2);
+        let mut stays = connect(&budget, 2, 1);
+        send(&gone, "g", 4).await;
+        send(&stays, "s", 2).await;
+        tokio::task::yield_now().await;
+        assert_eq!(gone.gauge.queued(), 4);
+        drop(gone.bridge);
+        let unsent = gone.task.await.unwrap();
+        let ids: Vec<String> = unsent.iter().map(|m| m.text().into_owned()).collect();
+        assert_eq!(ids, [r#"{"id":"g0"}"#, r#"{"id":"g1"}"#, r#"{"id":"g2"}"#, r#"{"id":"g3"}"#]);
+        budget.slots.add_permits(1);
+        let first = tokio::time::timeout(Duration::from_secs(5), stays.bridge.recv()).await;
+        assert_eq!(first.unwrap().unwrap().as_str(), Some(r#"{"id":"s0"}"#));
+    }
+
+    #[test]
+    fn weights_are_parsed() {
+        let weights = parse_weights("10.0.0.5=3, ::1=2").unwrap();
+        assert_eq!(weights, vec![("10.0.0.5".parse().unwrap(), 3), ("::1".parse().unwrap(), 2)]);
+        let budget = Budget::new(1, weights);
+        assert_eq!(budget.weight("10.0.0.5".parse().unwrap()), 3);
+        assert_eq!(budget.weight("10.0.0.6".parse().unwrap()), 1);
+        assert!(parse_weights("10.0.0.5=0").is_err());
+        assert!(parse_weights("ide=3").is_err());
+        assert!(parse_weights("10.0.0.5").is_err());
+    }
+}
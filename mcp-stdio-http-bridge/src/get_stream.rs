@@ -0,0 +1,164 @@
+use crate::auth::AuthProvider;
+use crate::capabilities::CapabilityFilter;
+use crate::config::Config;
+use crate::health::{GetStreamAction, GetStreamGate, StreamReconnect};
+use crate::jsonrpc;
+use crate::remote_transport::{
+    self, send_message, Client, ResumeFrom, Response, TransportError,
+};
+use crate::request_body::RequestBody;
+use crate::responder::{Closed, Push};
+use crate::session::Session;
+use crate::stats::Stats;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+/// First reconnect delay; it doubles up to `Config::max_backoff`.
+const INITIAL_RECONNECT: Duration = Duration::from_millis(500);
+
+/// The standalone GET stream (`MCP_ENABLE_GET_STREAM`): the upstream's
+/// channel for requests and notifications that answer no POST. It shares
+/// the POST path's client, credentials and session, and its messages reach
+/// the client through the same stdout queue.
+pub struct GetStream {
+    pub config: Arc<Config>,
+    pub client: Client,
+    pub auth: Arc<AuthProvider>,
+    pub session: Arc<Session>,
+    pub capabilities: CapabilityFilter,
+    pub push: Push,
+    pub stats: Arc<Stats>,
+}
+
+/// The running stream; dropping it closes the stream.
+pub struct Running(JoinHandle<()>);
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+impl Running {
+    /// Closes the stream and waits for its task to end, so it no longer
+    /// holds the stdout queue open once this returns.
+    pub async fn stop(mut self) {
+        self.0.abort();
+        let _ = (&mut self.0).await;
+    }
+}
+
+impl GetStream {
+    pub fn spawn(self) -> Running {
+        self.stats.set_get_stream_disabled(false);
+        Running(tokio::spawn(self.run()))
+    }
+
+    /// Keeps the stream open: reconnects with backoff whenever it drops or
+    /// is refused, until the server has answered 405 (or 404)
+    /// `MCP_GET_STREAM_405_LIMIT` times in a row or stdout is gone. Once the
+    /// upstream has sent an event id, each reconnection resumes after it.
+    async fn run(self) {
+        let mut gate = GetStreamGate::new(self.config.get_stream_405_limit);
+        let mut reconnect = StreamReconnect::new(INITIAL_RECONNECT, self.config.max_backoff);
+        let mut resume: Option<ResumeFrom> = None;
+        loop {
+            if let Some(from) = &resume {
+                debug!(last_event_id = from.last_event_id(), "resuming the GET stream");
+            }
+            let opened = remote_transport::open_stream(
+                &self.config,
+                &self.client,
+                &self.auth,
+                &self.session,
+                resume.clone(),
+                &self.stats,
+            )
+            .await;
+            match opened {
+                Ok((status, mut events)) => match gate.on_status(status) {
+                    GetStreamAction::Connected => {
+                        reconnect.on_connected();
+                        info!("GET stream open");
+                        let relayed = self.relay(&mut events, &mut reconnect).await;
+                        resume = events.resume_from();
+                        let Ok(ended) = relayed else {
+                            return;
+                        };
+                        match ended {
+                            Ok(()) => debug!("GET stream ended, reconnecting"),
+                            Err(e) => warn!(%e, "GET stream failed, reconnecting"),
+                        }
+                    }
+                    GetStreamAction::Reconnect => debug!(status, "GET stream refused, retrying"),
+                    GetStreamAction::Disable => {
+                        info!(
+                            status,
+                            limit = self.config.get_stream_405_limit,
+                            "upstream does not offer the GET stream, giving up until the client re-initializes"
+                        );
+                        self.stats.set_get_stream_disabled(true);
+                        return;
+                    }
+                    GetStreamAction::AuthFailure => {
+                        warn!(status, "GET stream credentials refused, re-acquiring");
+                        self.auth.invalidate();
+                    }
+                },
+                Err(e) => debug!(%e, "could not open the GET stream"),
+            }
+            let (delay, honored) = reconnect.next_delay();
+            if let Some(retry) = honored {
+                self.stats.note_sse_retry(retry);
+            }
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Forwards events until the stream ends. The outer error means stdout
+    /// has closed, the inner one that the stream failed.
+    async fn relay(
+        &self,
+        events: &mut Response,
+        reconnect: &mut StreamReconnect,
+    ) -> Result<Result<(), TransportError>, Closed> {
+        loop {
+            let line = match events.next().await {
+                Ok(Some(line)) => line,
+                Ok(None) => return Ok(Ok(())),
+                Err(e) => return Ok(Err(e)),
+            };
+            if let Some(retry) = events.retry() {
+                reconnect.on_retry_hint(retry);
+            }
+            let config = &self.config;
+            if !config.raw_passthrough && jsonrpc::too_deep(&line, config.max_json_depth) {
+                warn!(
+                    limit = config.max_json_depth,
+                    "dropping GET stream message nested too deeply"
+                );
+                continue;
+            }
+            if let Some(rejection) = self.capabilities.rejection(&line) {
+                debug!("rejecting server request for a stripped capability");
+                let rejection = RequestBody::from(rejection);
+                let sent = send_message(
+                    config,
+                    &self.client,
+                    &self.auth,
+                    &self.session,
+                    &rejection,
+                    config.timeout,
+                    &self.stats,
+                );
+                if let Err(e) = sent.await {
+                    warn!(%e, "could not send capability rejection upstream");
+                }
+                continue;
+            }
+            self.push.send(line).await?;
+        }
+    }
+}
@@ -0,0 +1,748 @@
+use crate::stats::Stats;
+use serde_json::Value;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_LOG_SUMMARY_SECS: u64 = 60;
+pub const DEFAULT_GET_STREAM_405_LIMIT: u32 = 3;
+pub const KEEPALIVE_METHOD: &str = "ping";
+
+/// How a failure in an ongoing streak should be logged.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StreakLog {
+    /// First failure of a streak (or a new classification): log at warn.
+    Warn,
+    /// Repeat of the current streak: log at debug only.
+    Suppressed,
+    /// Periodic warn-level summary of the ongoing streak.
+    Summary { failures: u64, elapsed: Duration },
+}
+
+/// Consecutive upstream failures, used to keep repetitive transport warnings
+/// out of the logs during outages.
+#[derive(Debug)]
+pub struct FailureStreak {
+    summary_interval: Duration,
+    class: Option<&'static str>,
+    failures: u64,
+    started: Option<Instant>,
+    last_warn: Option<Instant>,
+}
+
+impl FailureStreak {
+    pub fn new(summary_interval: Duration) -> Self {
+        Self {
+            summary_interval,
+            class: None,
+            failures: 0,
+            started: None,
+            last_warn: None,
+        }
+    }
+
+    pub fn on_failure(&mut self, class: &'static str, now: Instant) -> StreakLog {
+        self.failures += 1;
+        let started = *self.started.get_or_insert(now);
+        if self.class != Some(class) {
+            self.class = Some(class);
+            self.last_warn = Some(now);
+            return StreakLog::Warn;
+        }
+        let last = self.last_warn.unwrap_or(started);
+        if now.duration_since(last) >= self.summary_interval {
+            self.last_warn = Some(now);
+            return StreakLog::Summary {
+                failures: self.failures,
+                elapsed: now.duration_since(started),
+            };
+        }
+        StreakLog::Suppressed
+    }
+
+    /// Ends the streak, returning its failure count and duration if there was
+    /// one.
+    pub fn on_success(&mut self, now: Instant) -> Option<(u64, Duration)> {
+        let started = self.started.take()?;
+        let failures = std::mem::take(&mut self.failures);
+        self.class = None;
+        self.last_warn = None;
+        Some((failures, now.duration_since(started)))
+    }
+
+    pub fn failures(&self) -> u64 {
+        self.failures
+    }
+}
+
+/// Tracks how long the upstream connection has been idle so the bridge can
+/// send a keep-alive probe before a NAT or proxy drops it. Uses tokio time so
+/// the schedule follows a paused clock in tests.
+#[derive(Debug)]
+pub struct KeepAlive {
+    interval: Option<Duration>,
+    last_sent: tokio::time::Instant,
+}
+
+impl KeepAlive {
+    /// A zero interval disables the probe.
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval: (!interval.is_zero()).then_some(interval),
+            last_sent: tokio::time::Instant::now(),
+        }
+    }
+
+    /// Records that a request just went upstream.
+    pub fn touch(&mut self) {
+        self.last_sent = tokio::time::Instant::now();
+    }
+
+    /// Resolves once nothing has been sent for the interval; never resolves
+    /// when disabled.
+    pub async fn idle(&self) {
+        match self.interval {
+            Some(interval) => tokio::time::sleep_until(self.last_sent + interval).await,
+            None => std::future::pending().await,
+        }
+    }
+}
+
+/// Reconnect schedule for a long-lived SSE stream: exponential backoff,
+/// floored by the server's most recent `retry:` hint and capped at `max`.
+#[derive(Debug)]
+pub struct StreamReconnect {
+    initial: Duration,
+    max: Duration,
+    backoff: Duration,
+    server_retry: Option<Duration>,
+}
+
+impl StreamReconnect {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max,
+            backoff: initial,
+            server_retry: None,
+        }
+    }
+
+    /// Records a `retry:` value seen on the current stream; the latest wins.
+    pub fn on_retry_hint(&mut self, retry: Duration) {
+        self.server_retry = Some(retry);
+    }
+
+    /// A new stream connected: start backoff over and forget the previous
+    /// stream's hint.
+    pub fn on_connected(&mut self) {
+        self.backoff = self.initial;
+        self.server_retry = None;
+    }
+
+    /// Delay before the next reconnect attempt, plus the server hint if it
+    /// raised the delay (for stats).
+    pub fn next_delay(&mut self) -> (Duration, Option<Duration>) {
+        let backoff = self.backoff;
+        self.backoff = (self.backoff * 2).min(self.max);
+        let honored = self.server_retry.filter(|r| *r > backoff);
+        let delay = honored.unwrap_or(backoff).min(self.max);
+        (delay, honored.map(|r| r.min(self.max)))
+    }
+}
+
+/// Least time worth giving a retry; with less of the budget left after the
+/// backoff, the message fails instead.
+pub const MIN_RETRY_ATTEMPT: Duration = Duration::from_millis(100);
+
+/// How much of each backoff is randomized (`MCP_BACKOFF_JITTER`), so
+/// bridges retrying against the same recovering upstream spread out
+/// instead of retrying in lockstep.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Jitter {
+    /// The backoff as is: 500 ms, 1 s, 2 s, ...
+    None,
+    /// Anywhere from zero to the backoff.
+    Full,
+    /// Anywhere from half the backoff to all of it.
+    #[default]
+    Equal,
+}
+
+impl FromStr for Jitter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Self::None),
+            "full" => Ok(Self::Full),
+            "equal" => Ok(Self::Equal),
+            other => Err(format!("unknown backoff jitter {:?}", other)),
+        }
+    }
+}
+
+impl Jitter {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Full => "full",
+            Self::Equal => "equal",
+        }
+    }
+}
+
+/// The sleep for a backoff of `base` under `jitter`, given `random` drawn
+/// uniformly from `[0, 1)`.
+pub fn jittered(base: Duration, jitter: Jitter, random: f64) -> Duration {
+    match jitter {
+        Jitter::None => base,
+        Jitter::Full => base.mul_f64(random),
+        Jitter::Equal => base / 2 + (base / 2).mul_f64(random),
+    }
+}
+
+/// The backoff after `base`: twice as long, up to `max`.
+pub fn next_backoff(base: Duration, max: Duration) -> Duration {
+    (base * 2).min(max)
+}
+
+/// Requests retried by default (`MCP_RETRY_METHODS`): the ones that only
+/// read, so a retry after an attempt that did reach the server cannot do
+/// anything twice.
+pub const DEFAULT_RETRY_METHODS: &[&str] = &[
+    "initialize",
+    "ping",
+    "tools/list",
+    "resources/list",
+    "resources/templates/list",
+    "resources/read",
+    "prompts/list",
+    "prompts/get",
+];
+
+/// Which client requests are retried after a retryable failure
+/// (`MCP_RETRY_METHODS`). Responses and notifications carry no method to
+/// run, so they are always retried.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RetryMethods {
+    /// `*`: every request, as before the list existed.
+    All,
+    Only(Vec<String>),
+}
+
+impl Default for RetryMethods {
+    fn default() -> Self {
+        Self::Only(DEFAULT_RETRY_METHODS.iter().map(|m| m.to_string()).collect())
+    }
+}
+
+impl RetryMethods {
+    /// A comma-separated list of methods; `*` anywhere in it allows all.
+    pub fn parse(spec: &str) -> Self {
+        let methods: Vec<String> = spec
+            .split(',')
+            .map(str::trim)
+            .filter(|m| !m.is_empty())
+            .map(str::to_string)
+            .collect();
+        if methods.iter().any(|m| m == "*") {
+            return Self::All;
+        }
+        Self::Only(methods)
+    }
+
+    /// Whether `msg` may be retried: every request in it, a batch's
+    /// included, has a method on the list.
+    pub fn allows(&self, msg: &str) -> bool {
+        let Self::Only(methods) = self else {
+            return true;
+        };
+        let Ok(value) = serde_json::from_str::<Value>(msg) else {
+            return false;
+        };
+        let items = match &value {
+            Value::Array(items) => items.as_slice(),
+            single => std::slice::from_ref(single),
+        };
+        items.iter().all(|item| {
+            let Some(method) = item.get("method").and_then(Value::as_str) else {
+                return true;
+            };
+            item.get("id").is_none() || methods.iter().any(|m| m == method)
+        })
+    }
+
+    pub fn summary(&self) -> String {
+        match self {
+            Self::All => "*".to_string(),
+            Self::Only(methods) => methods.join(","),
+        }
+    }
+}
+
+/// Retry pacing for one message: exponential backoff capped at `max` and
+/// jittered, within an optional wall-clock budget (`MCP_TOTAL_BUDGET_MS`)
+/// covering every attempt and sleep. The budget wins over the per-attempt
+/// timeout, so the last attempt only gets what is left of it.
+#[derive(Debug)]
+pub struct RetrySchedule {
+    backoff: Duration,
+    max: Duration,
+    jitter: Jitter,
+    attempt_timeout: Duration,
+    deadline: Option<tokio::time::Instant>,
+}
+
+impl RetrySchedule {
+    /// A zero `budget` leaves the message unbounded.
+    pub fn new(
+        initial: Duration,
+        max: Duration,
+        jitter: Jitter,
+        attempt_timeout: Duration,
+        budget: Duration,
+    ) -> Self {
+        Self {
+            backoff: initial,
+            max,
+            jitter,
+            attempt_timeout,
+            deadline: (!budget.is_zero()).then(|| tokio::time::Instant::now() + budget),
+        }
+    }
+
+    /// Budget left, or `None` when unbounded.
+    pub fn remaining(&self) -> Option<Duration> {
+        let now = tokio::time::Instant::now();
+        self.deadline.map(|d| d.saturating_duration_since(now))
+    }
+
+    /// Timeout for the next attempt.
+    pub fn attempt_timeout(&self) -> Duration {
+        match self.remaining() {
+            Some(remaining) => self.attempt_timeout.min(remaining),
+            None => self.attempt_timeout,
+        }
+    }
+
+    /// Delay before the next attempt, or `None` when sleeping it would leave
+    /// less than `MIN_RETRY_ATTEMPT` of the budget for that attempt.
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        let delay = jittered(self.backoff, self.jitter, fastrand::f64());
+        if let Some(remaining) = self.remaining() {
+            if remaining < delay + MIN_RETRY_ATTEMPT {
+                return None;
+            }
+        }
+        self.backoff = next_backoff(self.backoff, self.max);
+        Some(delay)
+    }
+
+    /// Delay before retrying an attempt the server asked to be repeated
+    /// after `wait` (`Retry-After`), capped at `max`. It replaces the backoff
+    /// for that attempt without advancing it; `None` as for `next_delay`.
+    pub fn server_delay(&mut self, wait: Duration) -> Option<Duration> {
+        let delay = wait.min(self.max);
+        if let Some(remaining) = self.remaining() {
+            if remaining < delay + MIN_RETRY_ATTEMPT {
+                return None;
+            }
+        }
+        Some(delay)
+    }
+}
+
+/// Resolves once the bridge has seen no client traffic for `limit`
+/// (`MCP_IDLE_EXIT_SECS`). Activity in the meantime pushes the deadline out.
+pub async fn idle_expired(stats: &Stats, limit: Duration) {
+    loop {
+        let idle = stats.idle_for();
+        if idle >= limit {
+            return;
+        }
+        tokio::time::sleep(limit - idle).await;
+    }
+}
+
+/// What the GET stream should do after the server answered an attempt.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GetStreamAction {
+    /// The stream is open.
+    Connected,
+    /// Try again on the normal reconnect schedule.
+    Reconnect,
+    /// The server does not offer the stream; stop trying for this session.
+    Disable,
+    /// Credentials were refused; hand over to auth-failure handling.
+    AuthFailure,
+}
+
+/// Decides when to give up on the optional GET stream. Servers may answer
+/// it with 405 (or 404); after `limit` such answers in a row it is disabled
+/// until the session is re-initialized. A limit of 0 never disables it.
+#[derive(Debug)]
+pub struct GetStreamGate {
+    limit: u32,
+    rejections: u32,
+    disabled: bool,
+}
+
+impl GetStreamGate {
+    pub fn new(limit: u32) -> Self {
+        Self {
+            limit,
+            rejections: 0,
+            disabled: false,
+        }
+    }
+
+    pub fn is_disabled(&self) -> bool {
+        self.disabled
+    }
+
+    pub fn on_status(&mut self, status: u16) -> GetStreamAction {
+        match status {
+            200..=299 => {
+                self.rejections = 0;
+                GetStreamAction::Connected
+            }
+            404 | 405 => {
+                self.rejections += 1;
+                if self.limit > 0 && self.rejections >= self.limit {
+                    self.disabled = true;
+                    GetStreamAction::Disable
+                } else {
+                    GetStreamAction::Reconnect
+                }
+            }
+            401 | 403 => {
+                self.rejections = 0;
+                GetStreamAction::AuthFailure
+            }
+            _ => {
+                self.rejections = 0;
+                GetStreamAction::Reconnect
+            }
+        }
+    }
+
+    /// A new session may land on a server that does offer the stream.
+    pub fn on_reinitialize(&mut self) {
+        self.rejections = 0;
+        self.disabled = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_streak_logs_first_failure_and_periodic_summaries() {
+        let mut streak = FailureStreak::new(Duration::from_secs(60));
+        let start = Instant::now();
+        let mut warn_lines = 0;
+        // One failure every 2 s for 30 minutes.
+        for i in 0..900u64 {
+            let now = start + Duration::from_secs(i * 2);
+            match streak.on_failure("connect", now) {
+                StreakLog::Warn | StreakLog::Summary { .. } => warn_lines += 1,
+                StreakLog::Suppressed => {}
+            }
+        }
+        // The first warning plus one summary per elapsed minute.
+        assert_eq!(warn_lines, 30);
+        let (failures, elapsed) = streak
+            .on_success(start + Duration::from_secs(1800))
+            .unwrap();
+        assert_eq!(failures, 900);
+        assert_eq!(elapsed, Duration::from_secs(1800));
+        assert_eq!(streak.on_success(start + Duration::from_secs(1801)), None);
+    }
+
+    #[test]
+    fn summary_reports_count_and_duration() {
+        let mut streak = FailureStreak::new(Duration::from_secs(10));
+        let start = Instant::now();
+        assert_eq!(streak.on_failure("timeout", start), StreakLog::Warn);
+        assert_eq!(
+            streak.on_failure("timeout", start + Duration::from_secs(5)),
+            StreakLog::Suppressed
+        );
+        assert_eq!(
+            streak.on_failure("timeout", start + Duration::from_secs(10)),
+            StreakLog::Summary {
+                failures: 3,
+                elapsed: Duration::from_secs(10)
+            }
+        );
+    }
+
+    #[test]
+    fn new_classification_warns_again() {
+        let mut streak = FailureStreak::new(Duration::from_secs(60));
+        let now = Instant::now();
+        assert_eq!(streak.on_failure("connect", now), StreakLog::Warn);
+        assert_eq!(streak.on_failure("connect", now), StreakLog::Suppressed);
+        assert_eq!(streak.on_failure("timeout", now), StreakLog::Warn);
+        assert_eq!(streak.failures(), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keepalive_fires_after_interval_of_idleness() {
+        let keepalive = KeepAlive::new(Duration::from_secs(300));
+        let start = tokio::time::Instant::now();
+        keepalive.idle().await;
+        assert_eq!(start.elapsed(), Duration::from_secs(300));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn recent_traffic_postpones_keepalive() {
+        let mut keepalive = KeepAlive::new(Duration::from_secs(300));
+        let start = tokio::time::Instant::now();
+        tokio::time::sleep(Duration::from_secs(200)).await;
+        keepalive.touch();
+        assert!(
+            tokio::time::timeout(Duration::from_secs(299), keepalive.idle())
+                .await
+                .is_err()
+        );
+        keepalive.idle().await;
+        assert_eq!(start.elapsed(), Duration::from_secs(500));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn zero_interval_disables_keepalive() {
+        let keepalive = KeepAlive::new(Duration::ZERO);
+        assert!(
+            tokio::time::timeout(Duration::from_secs(86_400), keepalive.idle())
+                .await
+                .is_err()
+        );
+    }
+
+    fn schedule(budget_ms: u64) -> RetrySchedule {
+        RetrySchedule::new(
+            Duration::from_millis(500),
+            Duration::from_secs(30),
+            Jitter::None,
+            Duration::from_secs(60),
+            Duration::from_millis(budget_ms),
+        )
+    }
+
+    #[test]
+    fn only_listed_requests_are_retried() {
+        let methods = RetryMethods::default();
+        assert!(methods.allows(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#));
+        assert!(!methods.allows(r#"{"jsonrpc":"2.0","id":1,"method":"tools/call"}"#));
+        assert!(methods.allows(r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#));
+        assert!(methods.allows(r#"{"jsonrpc":"2.0","id":"s1","result":{}}"#));
+        assert!(!methods.allows(
+            r#"[{"jsonrpc":"2.0","id":1,"method":"ping"},{"jsonrpc":"2.0","id":2,"method":"tools/call"}]"#
+        ));
+        assert!(RetryMethods::parse("tools/list, *").allows(r#"{"id":1,"method":"tools/call"}"#));
+        assert_eq!(
+            RetryMethods::parse(" ping ,,tools/call"),
+            RetryMethods::Only(vec!["ping".into(), "tools/call".into()])
+        );
+        assert!(!RetryMethods::parse("").allows(r#"{"id":1,"method":"ping"}"#));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let max = Duration::from_secs(30);
+        let progression: Vec<u64> =
+            std::iter::successors(Some(Duration::from_millis(500)), |b| Some(next_backoff(*b, max)))
+                .take(8)
+                .map(|b| b.as_millis() as u64)
+                .collect();
+        assert_eq!(progression, [500, 1000, 2000, 4000, 8000, 16000, 30000, 30000]);
+    }
+
+    #[test]
+    fn jitter_stays_within_its_range() {
+        let base = Duration::from_secs(2);
+        assert_eq!(jittered(base, Jitter::None, 0.7), base);
+        assert_eq!(jittered(base, Jitter::Full, 0.0), Duration::ZERO);
+        assert_eq!(jittered(base, Jitter::Full, 0.25), Duration::from_millis(500));
+        assert_eq!(jittered(base, Jitter::Equal, 0.0), Duration::from_secs(1));
+        assert_eq!(jittered(base, Jitter::Equal, 0.5), Duration::from_millis(1500));
+        assert!(jittered(base, Jitter::Equal, 0.999_999) < base);
+        assert_eq!("FULL".parse(), Ok(Jitter::Full));
+        assert!("decorrelated".parse::<Jitter>().is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn equal_jitter_keeps_at_least_half_of_each_backoff() {
+        let mut retries = RetrySchedule::new(
+            Duration::from_millis(500),
+            Duration::from_secs(30),
+            Jitter::Equal,
+            Duration::from_secs(60),
+            Duration::ZERO,
+        );
+        let mut base = Duration::from_millis(500);
+        for _ in 0..10 {
+            let delay = retries.next_delay().unwrap();
+            assert!(delay >= base / 2 && delay <= base, "{:?} for {:?}", delay, base);
+            base = next_backoff(base, Duration::from_secs(30));
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn zero_budget_keeps_the_unbounded_schedule() {
+        let mut retries = schedule(0);
+        let delays: Vec<u64> = (0..8)
+            .map(|_| retries.next_delay().unwrap().as_millis() as u64)
+            .collect();
+        assert_eq!(delays, [500, 1000, 2000, 4000, 8000, 16000, 30000, 30000]);
+        tokio::time::sleep(Duration::from_secs(3600)).await;
+        assert_eq!(retries.attempt_timeout(), Duration::from_secs(60));
+        assert_eq!(retries.remaining(), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn budget_exhausted_mid_backoff_stops_retrying() {
+        let mut retries = schedule(2_000);
+        // Attempt 1 fails at once; 500 ms of backoff leaves 1.5 s.
+        let delay = retries.next_delay().unwrap();
+        tokio::time::sleep(delay).await;
+        // Attempt 2 fails after 600 ms; a 1 s backoff would leave under
+        // MIN_RETRY_ATTEMPT, so no attempt 3.
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        assert_eq!(retries.next_delay(), None);
+        assert_eq!(retries.remaining(), Some(Duration::from_millis(900)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn remaining_budget_shrinks_the_last_attempt() {
+        let mut retries = schedule(5_000);
+        assert_eq!(retries.attempt_timeout(), Duration::from_secs(5));
+        // Attempt 1 runs for 3 s before failing, then backs off 500 ms.
+        tokio::time::sleep(Duration::from_secs(3)).await;
+        let delay = retries.next_delay().unwrap();
+        tokio::time::sleep(delay).await;
+        assert_eq!(retries.attempt_timeout(), Duration::from_millis(1_500));
+        // The attempt times out with the budget, leaving nothing to retry in.
+        tokio::time::sleep(retries.attempt_timeout()).await;
+        assert_eq!(retries.attempt_timeout(), Duration::ZERO);
+        assert_eq!(retries.next_delay(), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn server_delays_leave_the_backoff_alone() {
+        let mut retries = schedule(0);
+        assert_eq!(retries.next_delay(), Some(Duration::from_millis(500)));
+        assert_eq!(
+            retries.server_delay(Duration::from_secs(2)),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(
+            retries.server_delay(Duration::from_secs(600)),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(retries.next_delay(), Some(Duration::from_millis(1000)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn server_delay_must_fit_the_budget() {
+        let mut retries = schedule(2_000);
+        assert_eq!(
+            retries.server_delay(Duration::from_secs(1)),
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(retries.server_delay(Duration::from_secs(2)), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn server_retry_hint_floors_reconnect_delay() {
+        let mut reconnect =
+            StreamReconnect::new(Duration::from_millis(500), Duration::from_secs(60));
+        reconnect.on_retry_hint(Duration::from_millis(10_000));
+        let (delay, honored) = reconnect.next_delay();
+        assert_eq!(honored, Some(Duration::from_secs(10)));
+        let start = tokio::time::Instant::now();
+        tokio::time::sleep(delay).await;
+        assert!(start.elapsed() >= Duration::from_secs(10));
+    }
+
+    #[test]
+    fn retry_hint_is_capped_and_reset_on_connect() {
+        let mut reconnect =
+            StreamReconnect::new(Duration::from_millis(500), Duration::from_secs(5));
+        reconnect.on_retry_hint(Duration::from_secs(30));
+        assert_eq!(reconnect.next_delay().0, Duration::from_secs(5));
+        reconnect.on_connected();
+        assert_eq!(reconnect.next_delay(), (Duration::from_millis(500), None));
+        assert_eq!(reconnect.next_delay().0, Duration::from_secs(1));
+    }
+
+    fn statuses(gate: &mut GetStreamGate, seq: &[u16]) -> Vec<GetStreamAction> {
+        seq.iter().map(|s| gate.on_status(*s)).collect()
+    }
+
+    #[test]
+    fn consecutive_405s_disable_the_get_stream() {
+        use GetStreamAction::*;
+        let mut gate = GetStreamGate::new(3);
+        assert_eq!(
+            statuses(&mut gate, &[405, 404, 405]),
+            vec![Reconnect, Reconnect, Disable]
+        );
+        assert!(gate.is_disabled());
+        gate.on_reinitialize();
+        assert!(!gate.is_disabled());
+        assert_eq!(gate.on_status(405), Reconnect);
+    }
+
+    #[test]
+    fn other_statuses_break_the_405_run() {
+        use GetStreamAction::*;
+        let mut gate = GetStreamGate::new(2);
+        assert_eq!(
+            statuses(&mut gate, &[405, 503, 405, 200, 405, 401, 405]),
+            vec![
+                Reconnect,
+                Reconnect,
+                Reconnect,
+                Connected,
+                Reconnect,
+                AuthFailure,
+                Reconnect
+            ]
+        );
+        assert!(!gate.is_disabled());
+        assert_eq!(gate.on_status(403), AuthFailure);
+    }
+
+    #[test]
+    fn zero_limit_never_disables() {
+        let mut gate = GetStreamGate::new(0);
+        for _ in 0..100 {
+            assert_eq!(gate.on_status(405), GetStreamAction::Reconnect);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn idle_exit_fires_after_limit() {
+        let stats = Stats::new(8);
+        let start = tokio::time::Instant::now();
+        idle_expired(&stats, Duration::from_secs(100)).await;
+        assert_eq!(start.elapsed(), Duration::from_secs(100));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn activity_resets_the_idle_timer() {
+        let stats = std::sync::Arc::new(Stats::new(8));
+        let start = tokio::time::Instant::now();
+        let pinger = stats.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            // One ping from the client.
+            pinger.note_activity();
+        });
+        idle_expired(&stats, Duration::from_secs(100)).await;
+        assert_eq!(start.elapsed(), Duration::from_secs(160));
+    }
+}
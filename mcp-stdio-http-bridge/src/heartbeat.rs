@@ -0,0 +1,166 @@
+//! Periodic one-line summary of the shared stats (`MCP_HEARTBEAT_SECS`), and
+//! the stats file (`MCP_STATS_FILE`) rewritten with the full snapshot on each
+//! beat and once more at exit.
+
+use crate::bridge::TOP_METHODS_LOGGED;
+use crate::stats::Stats;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::{Instant, MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+/// Logs a heartbeat every `every`, and rewrites `file` after each one,
+/// until `shutdown` is cancelled.
+pub async fn run(
+    stats: Arc<Stats>,
+    every: Duration,
+    file: Option<PathBuf>,
+    shutdown: CancellationToken,
+) {
+    let mut ticks = tokio::time::interval_at(Instant::now() + every, every);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => return,
+            _ = ticks.tick() => {}
+        }
+        log(&stats);
+        if let Some(path) = &file {
+            write_file(&stats, path);
+        }
+    }
+}
+
+/// Logs the heartbeat line. The rate-limit fields are `null` until the
+/// upstream has sent `X-RateLimit-Remaining` or `X-RateLimit-Reset`.
+pub fn log(stats: &Stats) {
+    let snapshot = stats.snapshot();
+    let connections = connection_summary(&snapshot["connections"]);
+    info!(
+        uptime_secs = %snapshot["uptime_secs"],
+        requests = %snapshot["requests"],
+        errors = %snapshot["errors"],
+        in_flight = stats.in_flight(),
+        paused = stats.paused(),
+        p95_ms = %snapshot["p95_ms"],
+        rate_limit_remaining = %snapshot["rate_limit_remaining"],
+        rate_limit_reset = %snapshot["rate_limit_reset"],
+        %connections,
+        top_methods = %stats.top_summary(TOP_METHODS_LOGGED),
+        "heartbeat"
+    );
+}
+
+/// `opened=… tls_handshakes=… reuse_ratio=…` from the snapshot's
+/// connection counters, or `off` without the `conn-metrics` feature, where
+/// they stay zero.
+fn connection_summary(conn: &Value) -> String {
+    if conn["instrumented"] != true {
+        return "off".to_string();
+    }
+    format!(
+        "opened={} tls_handshakes={} reuse_ratio={}",
+        conn["opened"], conn["tls_handshakes"], conn["reuse_ratio"]
+    )
+}
+
+/// Replaces `path` with the current snapshot. The JSON goes to a sibling
+/// temporary file first, so a reader never sees it half written. A failure
+/// is logged and otherwise ignored.
+pub fn write_file(stats: &Stats, path: &Path) {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".tmp");
+    let partial = PathBuf::from(partial);
+    let written = std::fs::write(&partial, format!("{:#}\n", stats.snapshot()))
+        .and_then(|()| std::fs::rename(&partial, path));
+    if let Err(e) = written {
+        warn!(%e, path = %path.display(), "could not write stats file");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::Sample;
+    use serde_json::json;
+
+    fn stats_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("bridge-stats-{}-{}.json", name, std::process::id()))
+    }
+
+    fn read(path: &Path) -> Value {
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn stats_file_holds_the_full_method_map() {
+        let stats = Stats::new(8);
+        stats.record(Sample {
+            method: Some("tools/call"),
+            latency: Duration::from_millis(12),
+            bytes_out: 10,
+            bytes_in: 20,
+            error: true,
+        });
+        let path = stats_path("map");
+        write_file(&stats, &path);
+        let written = read(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written["requests"], 1);
+        assert_eq!(written["methods"]["tools/call"]["count"], 1);
+        assert_eq!(written["methods"]["tools/call"]["errors"], 1);
+    }
+
+    #[test]
+    fn connections_are_summarized_only_when_counted() {
+        let counted = json!({
+            "instrumented": true,
+            "opened": 2,
+            "requests": 9,
+            "reuse_ratio": 4.5,
+            "tls_handshakes": 2,
+        });
+        assert_eq!(
+            connection_summary(&counted),
+            "opened=2 tls_handshakes=2 reuse_ratio=4.5"
+        );
+        assert_eq!(connection_summary(&json!({"instrumented": false})), "off");
+        let stats = Stats::new(8);
+        let expected = if cfg!(feature = "conn-metrics") {
+            "opened=0 tls_handshakes=0 reuse_ratio=null"
+        } else {
+            "off"
+        };
+        assert_eq!(connection_summary(&stats.snapshot()["connections"]), expected);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn each_beat_rewrites_the_stats_file() {
+        let stats = Arc::new(Stats::new(8));
+        let path = stats_path("beat");
+        let shutdown = CancellationToken::new();
+        let beat = tokio::spawn(run(
+            stats.clone(),
+            Duration::from_secs(60),
+            Some(path.clone()),
+            shutdown.clone(),
+        ));
+        tokio::time::sleep(Duration::from_secs(61)).await;
+        assert_eq!(read(&path)["requests"], 0);
+        stats.record(Sample {
+            method: Some("ping"),
+            latency: Duration::from_millis(1),
+            bytes_out: 1,
+            bytes_in: 1,
+            error: false,
+        });
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        assert_eq!(read(&path)["requests"], 1);
+        shutdown.cancel();
+        beat.await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
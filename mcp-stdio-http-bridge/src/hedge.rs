@@ -0,0 +1,110 @@
+use crate::remote_transport::TransportError;
+use crate::stats::Stats;
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tracing::debug;
+
+pub const DEFAULT_HEDGE_DELAY_MS: u64 = 300;
+
+/// Whether a method is a pure read and therefore safe to send twice.
+/// Only `ping` and methods whose last segment is `list`, `read` or `get`
+/// qualify; anything else may have side effects and is never hedged.
+pub fn is_read_only(method: &str) -> bool {
+    method == "ping"
+        || matches!(
+            method.rsplit('/').next(),
+            Some("list") | Some("read") | Some("get")
+        )
+}
+
+/// Splits a comma-separated `MCP_HEDGE_METHODS` value into hedgeable
+/// methods and rejected ones (not read-only).
+pub fn parse_methods(spec: &str) -> (Vec<String>, Vec<String>) {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|m| !m.is_empty())
+        .map(str::to_string)
+        .partition(|m| is_read_only(m))
+}
+
+/// Makes `attempt`, and if nothing has come back after `delay`
+/// (`MCP_HEDGE_DELAY_MS`), makes an identical second one and returns
+/// whichever succeeds first. The loser is dropped, which cancels it and
+/// discards any response it would produce. If one attempt fails the other
+/// is awaited instead. Each attempt reads its whole body, so a streamed
+/// answer is only relayed once complete. The second attempt takes one of
+/// `slots`, the `MCP_MAX_CONCURRENCY` budget, and is not made when none is
+/// free.
+pub async fn send_hedged<F, A>(
+    attempt: F,
+    delay: Duration,
+    stats: &Stats,
+    slots: &Semaphore,
+) -> Result<Vec<String>, TransportError>
+where
+    F: Fn() -> A,
+    A: Future<Output = Result<Vec<String>, TransportError>>,
+{
+    let primary = attempt();
+    tokio::pin!(primary);
+    tokio::select! {
+        res = &mut primary => return res,
+        _ = tokio::time::sleep(delay) => {}
+    }
+    let Ok(_slot) = slots.try_acquire() else {
+        debug!("no response yet, but no MCP_MAX_CONCURRENCY slot free to hedge");
+        return primary.await;
+    };
+    let _in_flight = stats.start_in_flight();
+    debug!(?delay, "no response yet, launching hedged request");
+    stats.hedge_launched();
+    let hedge = attempt();
+    tokio::pin!(hedge);
+    tokio::select! {
+        res = &mut primary => match res {
+            Ok(lines) => Ok(lines),
+            Err(e) => {
+                debug!(%e, "primary request failed, waiting for hedge");
+                let res = hedge.await;
+                if res.is_ok() {
+                    stats.hedge_won();
+                }
+                res
+            }
+        },
+        res = &mut hedge => match res {
+            Ok(lines) => {
+                stats.hedge_won();
+                Ok(lines)
+            }
+            Err(e) => {
+                debug!(%e, "hedged request failed, waiting for primary");
+                primary.await
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_read_methods_are_hedgeable() {
+        assert!(is_read_only("tools/list"));
+        assert!(is_read_only("resources/read"));
+        assert!(is_read_only("resources/templates/list"));
+        assert!(is_read_only("ping"));
+        assert!(!is_read_only("tools/call"));
+        assert!(!is_read_only("initialize"));
+        assert!(!is_read_only("notifications/initialized"));
+    }
+
+    #[test]
+    fn parse_rejects_side_effecting_methods() {
+        let (ok, rejected) = parse_methods(" tools/list, tools/call ,,resources/read");
+        assert_eq!(ok, vec!["tools/list", "resources/read"]);
+        assert_eq!(rejected, vec!["tools/call"]);
+    }
+}
@@ -0,0 +1,114 @@
+//! An idempotency key for each client message (`MCP_IDEMPOTENCY_HEADER`),
+//! so that an upstream can tell a retry of a request it already handled
+//! from a new one. The key is a random UUID drawn once per message; every
+//! attempt to send the message carries it, whether retried, hedged or
+//! failed over to another endpoint. Messages the bridge sends on its own,
+//! such as keep-alive pings, carry none.
+
+use crate::config::Config;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use std::future::Future;
+
+pub const DEFAULT_HEADER: &str = "Idempotency-Key";
+
+tokio::task_local! {
+    /// The key of the message the current task sends, if it has one.
+    static KEY: Option<HeaderValue>;
+}
+
+/// Reads `MCP_IDEMPOTENCY_HEADER`: a header name, `true` for
+/// `Idempotency-Key`, or `false` for none.
+pub fn parse_header(spec: &str) -> Result<Option<String>, String> {
+    match spec.trim().to_ascii_lowercase().as_str() {
+        "" | "false" | "off" | "0" => Ok(None),
+        "true" | "on" | "1" => Ok(Some(DEFAULT_HEADER.to_string())),
+        _ => {
+            let name = spec.trim();
+            HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| format!("invalid header name {:?}", name))?;
+            Ok(Some(name.to_string()))
+        }
+    }
+}
+
+/// A new random (version 4) UUID.
+pub fn new_key() -> String {
+    let bits = fastrand::u128(..);
+    // Version 4, variant 1.
+    let bits = (bits & !(0xf << 76) | (0x4 << 76)) & !(0x3 << 62) | (0x2 << 62);
+    let hex = format!("{:032x}", bits);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// Runs `task` as the sender of a message, under a key of its own when
+/// keys are configured.
+pub async fn within<F: Future>(config: &Config, task: F) -> F::Output {
+    if config.idempotency_header.is_none() {
+        return task.await;
+    }
+    let key = HeaderValue::from_str(&new_key()).expect("a UUID is a legal header value");
+    KEY.scope(Some(key), task).await
+}
+
+/// Runs `task` without a key, for a message the bridge sends on its own
+/// while handling a client's.
+pub async fn without<F: Future>(task: F) -> F::Output {
+    KEY.scope(None, task).await
+}
+
+/// The key header for the current message's next attempt.
+pub fn headers(config: &Config) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let Some(name) = &config.idempotency_header else {
+        return headers;
+    };
+    let name = HeaderName::from_bytes(name.as_bytes()).expect("checked at startup");
+    if let Some(key) = KEY.try_with(Option::clone).ok().flatten() {
+        headers.insert(name, key);
+    }
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_names_parse() {
+        assert_eq!(parse_header("off"), Ok(None));
+        assert_eq!(parse_header("TRUE"), Ok(Some(DEFAULT_HEADER.to_string())));
+        assert_eq!(parse_header(" X-Request-Key "), Ok(Some("X-Request-Key".to_string())));
+        assert!(parse_header("Request Key").is_err());
+    }
+
+    #[test]
+    fn keys_are_version_4_uuids() {
+        let key = new_key();
+        assert_eq!(key.len(), 36);
+        assert_eq!(key.matches('-').count(), 4);
+        assert_eq!(&key[14..15], "4");
+        assert!(matches!(&key[19..20], "8" | "9" | "a" | "b"), "{}", key);
+        assert_ne!(new_key(), key);
+    }
+
+    #[tokio::test]
+    async fn one_message_keeps_its_key() {
+        let mut config = Config::from_lookup(|_| None);
+        assert!(within(&config, async { headers(&config) }).await.is_empty());
+        config.idempotency_header = Some("X-Request-Key".to_string());
+        let (first, again) = within(&config, async { (headers(&config), headers(&config)) }).await;
+        assert_eq!(first["x-request-key"], again["x-request-key"]);
+        let other = within(&config, async { headers(&config) }).await;
+        assert_ne!(other["x-request-key"], first["x-request-key"]);
+        let own = within(&config, without(async { headers(&config) })).await;
+        assert!(own.is_empty());
+        assert!(headers(&config).is_empty());
+    }
+}
@@ -0,0 +1,421 @@
+use crate::jsonrpc;
+use crate::state::{Table, TableGauge};
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_TTL: Duration = Duration::from_secs(600);
+
+#[derive(Debug)]
+struct Entry {
+    client_id: Value,
+    created: Instant,
+}
+
+/// Replaces client request ids with bridge-assigned ones on the way upstream
+/// and restores them on the way back, so clients that reuse ids within a
+/// session never collide at the server.
+///
+/// Only client→server requests are remapped. Server-originated requests and
+/// the client's responses to them keep their ids in both directions.
+/// Mappings are dropped once the response is delivered, or at once when the
+/// client cancels the request. A request still unanswered after `ttl`, or
+/// pushed out of the table beyond `limit`, is forgotten and its client id
+/// queued for `take_evicted`.
+#[derive(Debug)]
+pub struct IdMapper {
+    next: u64,
+    entries: Table<u64, Entry>,
+    ttl: Duration,
+    evicted: Vec<Value>,
+}
+
+impl IdMapper {
+    pub fn new(limit: usize, ttl: Duration) -> Self {
+        Self {
+            next: 1,
+            entries: Table::new("ids", limit),
+            ttl,
+            evicted: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn gauge(&self) -> Arc<TableGauge> {
+        self.entries.gauge()
+    }
+
+    /// Client ids of requests forgotten before their response arrived, in
+    /// eviction order. Each is owed an error.
+    pub fn take_evicted(&mut self) -> Vec<Value> {
+        std::mem::take(&mut self.evicted)
+    }
+
+    /// Rewrites a client message for the upstream. Returns `None` when the
+    /// message needed no change.
+    pub fn to_server(&mut self, msg: &str, now: Instant) -> Option<String> {
+        let mut value: Value = serde_json::from_str(msg).ok()?;
+        self.expire(now);
+        let changed = match &mut value {
+            Value::Array(items) => {
+                let mut changed = false;
+                for item in items {
+                    changed |= self.map_outgoing(item, now);
+                }
+                changed
+            }
+            item => self.map_outgoing(item, now),
+        };
+        changed.then(|| value.to_string())
+    }
+
+    /// Lets the requests in `msg`, as rewritten by `to_server`, expire or be
+    /// evicted ahead of those still in flight: their dispatch has finished,
+    /// so any answer still owed will not come.
+    pub fn settle(&mut self, msg: &str) {
+        let Ok(value) = serde_json::from_str::<Value>(msg) else {
+            return;
+        };
+        let items = match &value {
+            Value::Array(items) => items.as_slice(),
+            item => std::slice::from_ref(item),
+        };
+        for item in items {
+            if item.get("method").is_some() {
+                if let Some(bridge_id) = item.get("id").and_then(Value::as_u64) {
+                    self.entries.settle(&bridge_id);
+                }
+            }
+        }
+    }
+
+    /// Drops the mappings of the requests in `msg`, as rewritten by
+    /// `to_server`, once the client has cancelled them. The client expects
+    /// no answer, so none is owed when they would have expired.
+    pub fn forget(&mut self, msg: &str) {
+        for id in jsonrpc::request_ids(msg) {
+            if let Some(bridge_id) = id.as_u64() {
+                self.entries.remove(&bridge_id);
+            }
+        }
+    }
+
+    /// Whether `msg` only answers requests that were sent under bridge ids
+    /// and have since been forgotten, such as a late reply to a cancelled
+    /// request. Their client ids are gone, so such a line cannot be
+    /// relayed.
+    pub fn answers_forgotten(&self, msg: &str) -> bool {
+        let ids = jsonrpc::answered_ids(msg);
+        !ids.is_empty()
+            && ids.iter().all(|id| {
+                id.as_u64().is_some_and(|id| {
+                    (1..self.next).contains(&id) && self.entries.get(&id).is_none()
+                })
+            })
+    }
+
+    /// Restores client ids on upstream responses (including errors). Returns
+    /// `None` when the message needed no change.
+    pub fn to_client(&mut self, msg: &str) -> Option<String> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        let mut value: Value = serde_json::from_str(msg).ok()?;
+        let changed = match &mut value {
+            Value::Array(items) => {
+                let mut changed = false;
+                for item in items {
+                    changed |= self.map_incoming(item);
+                }
+                changed
+            }
+            item => self.map_incoming(item),
+        };
+        changed.then(|| value.to_string())
+    }
+
+    fn map_outgoing(&mut self, item: &mut Value, now: Instant) -> bool {
+        let Some(obj) = item.as_object_mut() else {
+            return false;
+        };
+        let method = obj.get("method").and_then(Value::as_str);
+        if method == Some("notifications/cancelled") {
+            let Some(request_id) = obj.get_mut("params").and_then(|p| p.get_mut("requestId"))
+            else {
+                return false;
+            };
+            return match self.find_live(request_id) {
+                Some(bridge_id) => {
+                    *request_id = Value::from(bridge_id);
+                    true
+                }
+                None => false,
+            };
+        }
+        // Responses to server-originated requests carry no method and must
+        // keep the server's id.
+        if method.is_none() {
+            return false;
+        }
+        let Some(id) = obj.get_mut("id").filter(|id| !id.is_null()) else {
+            return false;
+        };
+        let bridge_id = self.next;
+        self.next += 1;
+        let client_id = std::mem::replace(id, Value::from(bridge_id));
+        self.insert(bridge_id, client_id, now);
+        true
+    }
+
+    fn map_incoming(&mut self, item: &mut Value) -> bool {
+        let Some(obj) = item.as_object_mut() else {
+            return false;
+        };
+        // Server-originated requests have a method; their ids belong to the
+        // server and pass through untranslated.
+        if obj.contains_key("method") {
+            return false;
+        }
+        let Some(bridge_id) = obj.get("id").and_then(Value::as_u64) else {
+            return false;
+        };
+        match self.entries.remove(&bridge_id) {
+            Some(entry) => {
+                obj.insert("id".to_string(), entry.client_id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The id a client request still in flight was sent upstream under.
+    pub fn server_id(&self, client_id: &Value) -> Option<u64> {
+        self.find_live(client_id)
+    }
+
+    /// Newest live mapping for a client id; with colliding client ids the
+    /// most recent request is the one a cancellation refers to.
+    fn find_live(&self, client_id: &Value) -> Option<u64> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|(_, e)| &e.client_id == client_id)
+            .map(|(id, _)| *id)
+    }
+
+    fn insert(&mut self, bridge_id: u64, client_id: Value, now: Instant) {
+        let entry = Entry {
+            client_id,
+            created: now,
+        };
+        if let Some((_, evicted)) = self.entries.insert(bridge_id, entry) {
+            self.evicted.push(evicted.client_id);
+        }
+    }
+
+    fn expire(&mut self, now: Instant) {
+        let ttl = self.ttl;
+        while let Some((_, expired)) = self
+            .entries
+            .pop_expired(|e| now.duration_since(e.created) >= ttl)
+        {
+            self.evicted.push(expired.client_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parse(s: &str) -> Value {
+        serde_json::from_str(s).unwrap()
+    }
+
+    fn mapper() -> IdMapper {
+        IdMapper::new(crate::state::DEFAULT_LIMIT, DEFAULT_TTL)
+    }
+
+    #[test]
+    fn colliding_client_ids_get_distinct_upstream_ids() {
+        let mut m = mapper();
+        let now = Instant::now();
+        let a = parse(
+            &m.to_server(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#, now)
+                .unwrap(),
+        );
+        let b = parse(
+            &m.to_server(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#, now)
+                .unwrap(),
+        );
+        assert_ne!(a["id"], b["id"]);
+        let resp_b = json!({"jsonrpc":"2.0","id":b["id"],"result":{}}).to_string();
+        assert_eq!(parse(&m.to_client(&resp_b).unwrap())["id"], 1);
+        let resp_a =
+            json!({"jsonrpc":"2.0","id":a["id"],"error":{"code":-1,"message":"x"}}).to_string();
+        assert_eq!(parse(&m.to_client(&resp_a).unwrap())["id"], 1);
+        assert!(m.is_empty());
+    }
+
+    #[test]
+    fn string_ids_round_trip() {
+        let mut m = mapper();
+        let out = parse(
+            &m.to_server(
+                r#"{"jsonrpc":"2.0","id":"abc","method":"ping"}"#,
+                Instant::now(),
+            )
+            .unwrap(),
+        );
+        let resp = json!({"jsonrpc":"2.0","id":out["id"],"result":{}}).to_string();
+        assert_eq!(parse(&m.to_client(&resp).unwrap())["id"], "abc");
+    }
+
+    #[test]
+    fn duplicate_ids_map_the_last_occurrence() {
+        let mut m = mapper();
+        let out = m
+            .to_server(r#"{"jsonrpc":"2.0","id":1,"id":2,"method":"ping"}"#, Instant::now())
+            .unwrap();
+        assert_eq!(out.matches("\"id\"").count(), 1);
+        let resp = format!(
+            r#"{{"jsonrpc":"2.0","id":"x","id":{},"result":{{}}}}"#,
+            parse(&out)["id"]
+        );
+        assert_eq!(parse(&m.to_client(&resp).unwrap())["id"], 2);
+    }
+
+    #[test]
+    fn cancellation_is_translated_to_latest_live_request() {
+        let mut m = mapper();
+        let now = Instant::now();
+        m.to_server(r#"{"jsonrpc":"2.0","id":5,"method":"tools/call"}"#, now);
+        let second = parse(
+            &m.to_server(r#"{"jsonrpc":"2.0","id":5,"method":"tools/call"}"#, now)
+                .unwrap(),
+        );
+        let cancel = m
+            .to_server(r#"{"jsonrpc":"2.0","method":"notifications/cancelled","params":{"requestId":5,"reason":"stop"}}"#, now)
+            .unwrap();
+        let cancel = parse(&cancel);
+        assert_eq!(cancel["params"]["requestId"], second["id"]);
+        assert_eq!(cancel["params"]["reason"], "stop");
+        assert!(cancel.get("id").is_none());
+    }
+
+    #[test]
+    fn cancellation_for_unknown_id_is_untouched() {
+        let mut m = mapper();
+        let msg =
+            r#"{"jsonrpc":"2.0","method":"notifications/cancelled","params":{"requestId":9}}"#;
+        assert_eq!(m.to_server(msg, Instant::now()), None);
+    }
+
+    #[test]
+    fn server_originated_requests_pass_through_both_ways() {
+        let mut m = mapper();
+        let now = Instant::now();
+        m.to_server(r#"{"jsonrpc":"2.0","id":1,"method":"tools/call"}"#, now);
+        // Server asks the client something, reusing id 1 in its own space.
+        let server_req = r#"{"jsonrpc":"2.0","id":1,"method":"sampling/createMessage"}"#;
+        assert_eq!(m.to_client(server_req), None);
+        // The client's answer keeps the server's id.
+        let client_resp = r#"{"jsonrpc":"2.0","id":1,"result":{}}"#;
+        assert_eq!(m.to_server(client_resp, now), None);
+        assert_eq!(m.len(), 1);
+    }
+
+    #[test]
+    fn notifications_are_untouched() {
+        let mut m = mapper();
+        let msg = r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#;
+        assert_eq!(m.to_server(msg, Instant::now()), None);
+    }
+
+    #[test]
+    fn mappings_expire_and_are_bounded() {
+        let mut m = IdMapper::new(3, Duration::from_secs(10));
+        let start = Instant::now();
+        for i in 0..5 {
+            let sent = m.to_server(
+                &format!(r#"{{"jsonrpc":"2.0","id":{},"method":"ping"}}"#, i),
+                start,
+            );
+            m.settle(&sent.unwrap());
+        }
+        assert_eq!(m.len(), 3);
+        m.to_server(
+            r#"{"jsonrpc":"2.0","id":"late","method":"ping"}"#,
+            start + Duration::from_secs(11),
+        );
+        assert_eq!(m.len(), 1);
+        let evicted: Vec<Value> = (0..5).map(Value::from).collect();
+        assert_eq!(m.take_evicted(), evicted);
+        assert!(m.take_evicted().is_empty());
+    }
+
+    #[test]
+    fn the_request_being_dispatched_is_never_expired() {
+        let mut m = IdMapper::new(8, Duration::from_secs(10));
+        let start = Instant::now();
+        let batch = r#"[{"jsonrpc":"2.0","id":"a","method":"x"},{"jsonrpc":"2.0","id":"b","method":"x"}]"#;
+        let sent = m.to_server(batch, start).unwrap();
+        let late = start + Duration::from_secs(30);
+        m.expire(late);
+        assert_eq!(m.len(), 2);
+        // Once its dispatch has finished, the batch expires.
+        m.settle(&sent);
+        m.to_server(r#"{"jsonrpc":"2.0","method":"notifications/x"}"#, late);
+        assert_eq!(m.take_evicted(), vec![json!("a"), json!("b")]);
+    }
+
+    #[test]
+    fn only_finished_requests_expire() {
+        let mut m = IdMapper::new(8, Duration::from_secs(10));
+        let start = Instant::now();
+        let slow = m
+            .to_server(r#"{"jsonrpc":"2.0","id":"slow","method":"x"}"#, start)
+            .unwrap();
+        let fast = m
+            .to_server(r#"{"jsonrpc":"2.0","id":"fast","method":"x"}"#, start)
+            .unwrap();
+        m.settle(&fast);
+        m.expire(start + Duration::from_secs(30));
+        // The oldest entry is still in flight, so nothing behind it expires.
+        assert!(m.take_evicted().is_empty());
+        m.settle(&slow);
+        m.expire(start + Duration::from_secs(30));
+        assert_eq!(m.take_evicted(), vec![json!("slow"), json!("fast")]);
+    }
+
+    #[test]
+    fn cancelled_requests_are_forgotten_without_an_eviction() {
+        let mut m = IdMapper::new(2, Duration::from_secs(10));
+        let start = Instant::now();
+        let cancelled = m
+            .to_server(r#"{"jsonrpc":"2.0","id":7,"method":"tools/call"}"#, start)
+            .unwrap();
+        m.forget(&cancelled);
+        assert!(m.is_empty());
+        // Past the TTL and the limit, nothing is owed for id 7.
+        let late = start + Duration::from_secs(30);
+        for id in 0..3 {
+            let sent = m.to_server(&format!(r#"{{"jsonrpc":"2.0","id":{},"method":"ping"}}"#, id), late);
+            m.settle(&sent.unwrap());
+        }
+        assert_eq!(m.take_evicted(), vec![json!(0)]);
+        // A late upstream reply to it has no client id to go back under.
+        let reply = json!({"jsonrpc":"2.0","id":parse(&cancelled)["id"],"result":{}}).to_string();
+        assert!(m.answers_forgotten(&reply));
+        assert!(!m.answers_forgotten(r#"{"jsonrpc":"2.0","id":3,"result":{}}"#));
+        assert!(!m.answers_forgotten(r#"{"jsonrpc":"2.0","id":99,"result":{}}"#));
+    }
+}
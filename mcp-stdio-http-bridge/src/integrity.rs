@@ -0,0 +1,122 @@
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+
+pub const HEADER: &str = "X-Content-SHA256";
+pub const DEFAULT_MAX_RETRIES: u32 = 1;
+
+/// Lowercase hex SHA-256 of `bytes`, the `X-Content-SHA256` value.
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    hex(&Sha256::digest(bytes))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut s, b| {
+        let _ = write!(s, "{:02x}", b);
+        s
+    })
+}
+
+/// Checks a response body against its `Content-Length` and an echoed
+/// `X-Content-SHA256` while it is read, so the digest is accumulated chunk
+/// by chunk rather than over a second copy of the body.
+#[derive(Debug)]
+pub struct BodyCheck {
+    hasher: Sha256,
+    received: u64,
+    content_length: Option<u64>,
+    digest: Option<String>,
+}
+
+impl BodyCheck {
+    pub fn new(content_length: Option<u64>, digest: Option<&str>) -> Self {
+        Self {
+            hasher: Sha256::new(),
+            received: 0,
+            content_length,
+            digest: digest.map(|d| d.trim().to_ascii_lowercase()),
+        }
+    }
+
+    pub fn update(&mut self, chunk: &[u8]) {
+        self.hasher.update(chunk);
+        self.received += chunk.len() as u64;
+    }
+
+    /// Whether fewer bytes arrived than `Content-Length` announced. Used when
+    /// the body ends in a read error, which a truncating proxy produces.
+    pub fn truncated(&self) -> Option<String> {
+        self.content_length
+            .filter(|expected| self.received < *expected)
+            .map(|expected| {
+                format!(
+                    "body truncated: received {} of {} bytes",
+                    self.received, expected
+                )
+            })
+    }
+
+    /// The mismatch found once the whole body was read, if any.
+    pub fn finish(self) -> Result<(), String> {
+        if let Some(expected) = self.content_length.filter(|l| *l != self.received) {
+            return Err(format!(
+                "received {} bytes but Content-Length was {}",
+                self.received, expected
+            ));
+        }
+        let actual = hex(&self.hasher.finalize());
+        match self.digest {
+            Some(expected) if expected != actual => Err(format!(
+                "{} mismatch: header {}, body {}",
+                HEADER, expected, actual
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BODY: &[u8] = br#"{"jsonrpc":"2.0","id":1,"result":{}}"#;
+
+    #[test]
+    fn digest_of_known_input() {
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn chunked_body_matches_echoed_digest() {
+        let digest = sha256_hex(BODY).to_uppercase();
+        let mut check = BodyCheck::new(Some(BODY.len() as u64), Some(&digest));
+        for chunk in BODY.chunks(7) {
+            check.update(chunk);
+        }
+        assert_eq!(check.truncated(), None);
+        assert_eq!(check.finish(), Ok(()));
+    }
+
+    #[test]
+    fn wrong_digest_is_reported() {
+        let mut check = BodyCheck::new(None, Some(&sha256_hex(b"other")));
+        check.update(BODY);
+        assert!(check
+            .finish()
+            .unwrap_err()
+            .contains("X-Content-SHA256 mismatch"));
+    }
+
+    #[test]
+    fn short_body_is_reported() {
+        let mut check = BodyCheck::new(Some(100), None);
+        check.update(BODY);
+        assert_eq!(
+            check.truncated().as_deref(),
+            Some("body truncated: received 36 of 100 bytes")
+        );
+        assert!(check.finish().is_err());
+    }
+}
@@ -0,0 +1,377 @@
+use serde_json::{json, Value};
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INTERNAL_ERROR: i64 = -32603;
+/// Server error codes for upstream HTTP failures, so a client can tell
+/// credentials from routing from payload problems.
+pub const UPSTREAM_AUTH_FAILED: i64 = -32001;
+pub const UPSTREAM_NOT_FOUND: i64 = -32002;
+pub const UPSTREAM_PAYLOAD_TOO_LARGE: i64 = -32003;
+pub const UPSTREAM_RATE_LIMITED: i64 = -32004;
+/// The bridge's own queue is full and `MCP_QUEUE_POLICY` turned the request
+/// away.
+pub const SERVER_BUSY: i64 = -32005;
+pub const LOG_MESSAGE_METHOD: &str = "notifications/message";
+/// serde_json's own recursion limit; it refuses input nested this deep.
+pub const MAX_JSON_DEPTH: usize = 128;
+
+/// Returns the `id` of a JSON-RPC request, or `None` for notifications,
+/// responses without an id and lines that are not JSON objects.
+pub fn request_id(msg: &str) -> Option<Value> {
+    let value: Value = serde_json::from_str(msg).ok()?;
+    value.get("id").filter(|id| !id.is_null()).cloned()
+}
+
+/// The `id` of a request that cannot be parsed, such as the start of one
+/// too large to read: the first string or number under an `id` key of the
+/// top-level object. A value running to the end of `prefix` may have been
+/// cut short, so it does not count.
+pub fn salvage_id(prefix: &str) -> Option<Value> {
+    let (mut depth, mut in_string, mut escaped) = (0usize, false, false);
+    let mut key_start = None;
+    for (i, b) in prefix.bytes().enumerate() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if b == b'\\' {
+                escaped = true;
+            } else if b == b'"' {
+                in_string = false;
+                let value = match key_start.take() {
+                    Some(start) if &prefix[start..i] == "id" => {
+                        prefix[i + 1..].trim_start().strip_prefix(':')
+                    }
+                    _ => None,
+                };
+                if let Some(value) = value {
+                    let mut values = serde_json::Deserializer::from_str(value).into_iter::<Value>();
+                    let id = values.next().and_then(Result::ok);
+                    let complete = values.byte_offset() < value.len();
+                    return id.filter(|id| complete && (id.is_string() || id.is_number()));
+                }
+            }
+            continue;
+        }
+        match b {
+            b'"' => {
+                in_string = true;
+                if depth == 1 {
+                    key_start = Some(i + 1);
+                }
+            }
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The `requestId` a `notifications/cancelled` refers to; `None` for any
+/// other message.
+pub fn cancelled_request(msg: &str) -> Option<Value> {
+    let value: Value = serde_json::from_str(msg).ok()?;
+    if value.get("method")?.as_str()? != "notifications/cancelled" {
+        return None;
+    }
+    value["params"].get("requestId").filter(|id| !id.is_null()).cloned()
+}
+
+/// The `protocolVersion` of an `initialize` result.
+pub fn protocol_version(msg: &str) -> Option<String> {
+    let value: Value = serde_json::from_str(msg).ok()?;
+    value["result"].get("protocolVersion")?.as_str().map(str::to_string)
+}
+
+/// Returns the `method` of a JSON-RPC request or notification.
+pub fn method(msg: &str) -> Option<String> {
+    let value: Value = serde_json::from_str(msg).ok()?;
+    value.get("method")?.as_str().map(str::to_string)
+}
+
+pub fn error_response(id: Option<&Value>, code: i64, message: &str) -> String {
+    error_value(id, &error_object(code, message)).to_string()
+}
+
+/// The `error` member of a response.
+pub fn error_object(code: i64, message: &str) -> Value {
+    json!({ "code": code, "message": message })
+}
+
+fn error_value(id: Option<&Value>, error: &Value) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id.cloned().unwrap_or(Value::Null),
+        "error": error
+    })
+}
+
+/// Whether `msg` holds a request, alone or in a batch, that the client is
+/// waiting on an answer for. Notifications and responses never get one.
+pub fn expects_response(msg: &str) -> bool {
+    error_for(msg, INTERNAL_ERROR, "").is_some()
+}
+
+/// Error answer to every request in `msg` under its id: one object, or an
+/// array for a batch. `None` when nothing in it expects an answer, i.e. it
+/// holds only notifications and responses, or is not JSON.
+pub fn error_for(msg: &str, code: i64, message: &str) -> Option<String> {
+    error_object_for(msg, &error_object(code, message))
+}
+
+/// `error_for` with a complete `error` member, e.g. one carrying `data`.
+pub fn error_object_for(msg: &str, error: &Value) -> Option<String> {
+    let value: Value = serde_json::from_str(msg).ok()?;
+    let request_id = |item: &Value| {
+        item.get("method")?;
+        item.get("id").filter(|id| !id.is_null()).cloned()
+    };
+    match &value {
+        Value::Array(items) => {
+            let errors: Vec<Value> = items
+                .iter()
+                .filter_map(request_id)
+                .map(|id| error_value(Some(&id), error))
+                .collect();
+            (!errors.is_empty()).then(|| Value::Array(errors).to_string())
+        }
+        item => request_id(item).map(|id| error_value(Some(&id), error).to_string()),
+    }
+}
+
+/// The elements of a batch, in order, each as a message of its own. `None`
+/// for anything that is not an array, and for an empty one; an error when
+/// `msg` starts like an array but is not valid JSON.
+pub fn split_batch(msg: &str) -> Result<Option<Vec<String>>, serde_json::Error> {
+    if !msg.trim_start().starts_with('[') {
+        return Ok(None);
+    }
+    let items: Vec<Value> = serde_json::from_str(msg)?;
+    Ok((!items.is_empty()).then(|| items.iter().map(Value::to_string).collect()))
+}
+
+/// Ids of a response, or of every response in a batch.
+pub fn response_ids(msg: &str) -> Vec<Value> {
+    ids_where(msg, |_| true)
+}
+
+/// Ids of the requests in `msg`, alone or in a batch.
+pub fn request_ids(msg: &str) -> Vec<Value> {
+    ids_where(msg, |item| item.get("method").is_some())
+}
+
+/// Ids of the requests `msg` answers: its items without a `method`, so a
+/// server request that happens to reuse a client id does not count.
+pub fn answered_ids(msg: &str) -> Vec<Value> {
+    ids_where(msg, |item| item.get("method").is_none())
+}
+
+fn ids_where(msg: &str, keep: impl Fn(&Value) -> bool) -> Vec<Value> {
+    let Ok(value) = serde_json::from_str::<Value>(msg) else {
+        return Vec::new();
+    };
+    let items = match &value {
+        Value::Array(items) => items.as_slice(),
+        item => std::slice::from_ref(item),
+    };
+    items
+        .iter()
+        .filter(|item| keep(item))
+        .filter_map(|item| item.get("id").filter(|id| !id.is_null()).cloned())
+        .collect()
+}
+
+/// Whether `msg` is a JSON-RPC response (`result` or `error`, no `method`),
+/// or a non-empty batch of them.
+pub fn is_response(msg: &str) -> bool {
+    let Ok(value) = serde_json::from_str::<Value>(msg) else {
+        return false;
+    };
+    let response = |item: &Value| {
+        item.get("jsonrpc").is_some()
+            && item.get("method").is_none()
+            && (item.get("result").is_some() || item.get("error").is_some())
+    };
+    match &value {
+        Value::Array(items) => !items.is_empty() && items.iter().all(response),
+        item => response(item),
+    }
+}
+
+/// Deepest array/object nesting in `s`. Scans bytes without recursion, so
+/// arbitrarily deep input is safe to measure; brackets inside strings are
+/// ignored.
+pub fn nesting_depth(s: &str) -> usize {
+    let (mut depth, mut max) = (0usize, 0usize);
+    let (mut in_string, mut escaped) = (false, false);
+    for b in s.bytes() {
+        if in_string {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match b {
+            b'"' => in_string = true,
+            b'[' | b'{' => {
+                depth += 1;
+                max = max.max(depth);
+            }
+            b']' | b'}' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    max
+}
+
+/// Whether `s` is nested too deeply to parse under `limit`. Like serde_json's
+/// recursion limit, a message is refused once its depth reaches the limit.
+pub fn too_deep(s: &str, limit: usize) -> bool {
+    nesting_depth(s) >= limit
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn id_is_salvaged_from_a_cut_request() {
+        let cut = r#"{"jsonrpc":"2.0","id":"a\"b","method":"tools/call","params":{"text":"xxxx"#;
+        assert_eq!(salvage_id(cut), Some(json!("a\"b")));
+        let cut = r#"{"params":{"id":9,"x":[1,{"id":8}]},"id": 42 ,"params2":"#;
+        assert_eq!(salvage_id(cut), Some(json!(42)));
+        // Cut inside the id itself: 12 might have been 123.
+        assert_eq!(salvage_id(r#"{"jsonrpc":"2.0","id":12"#), None);
+        assert_eq!(salvage_id(r#"{"method":"id","params":{"id":1,"#), None);
+        assert_eq!(salvage_id(r#"{"id":null,"method":"x""#), None);
+        assert_eq!(salvage_id(r#"[{"id":1,"method":"x"},"#), None);
+    }
+
+    #[test]
+    fn batches_split_in_order() {
+        let batch = r#" [{"jsonrpc":"2.0","id":2,"result":{"b":1,"a":2}},{"jsonrpc":"2.0","id":1,"result":[]}]"#;
+        assert_eq!(
+            split_batch(batch).unwrap(),
+            Some(vec![
+                r#"{"jsonrpc":"2.0","id":2,"result":{"b":1,"a":2}}"#.to_string(),
+                r#"{"jsonrpc":"2.0","id":1,"result":[]}"#.to_string(),
+            ])
+        );
+        assert_eq!(split_batch(r#"{"jsonrpc":"2.0","id":1,"result":[1]}"#).unwrap(), None);
+        assert_eq!(split_batch("[]").unwrap(), None);
+        assert!(split_batch(r#"[{"id":1},"#).is_err());
+    }
+
+    #[test]
+    fn protocol_version_comes_from_a_result() {
+        let init = r#"{"jsonrpc":"2.0","id":0,"result":{"protocolVersion":"2025-03-26"}}"#;
+        assert_eq!(protocol_version(init).as_deref(), Some("2025-03-26"));
+        assert_eq!(protocol_version(r#"{"jsonrpc":"2.0","id":0,"error":{"code":1}}"#), None);
+        assert_eq!(protocol_version(r#"{"jsonrpc":"2.0","id":0,"result":{"protocolVersion":1}}"#), None);
+    }
+
+    #[test]
+    fn only_results_and_errors_are_responses() {
+        assert!(is_response(r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32600,"message":"x"}}"#));
+        assert!(is_response(r#"[{"jsonrpc":"2.0","id":1,"result":{}}]"#));
+        assert!(!is_response(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#));
+        assert!(!is_response(r#"{"error":"invalid_token"}"#));
+        assert!(!is_response("[]"));
+        assert!(!is_response("<html>Bad Gateway</html>"));
+    }
+
+    #[test]
+    fn depth_ignores_brackets_in_strings() {
+        assert_eq!(nesting_depth(r#"{"a":"[[[{{{\"]]]","b":[1,[2]]}"#), 3);
+        assert_eq!(nesting_depth("42"), 0);
+    }
+
+    #[test]
+    fn depth_limit_matches_serde() {
+        let nested = |d: usize| "[".repeat(d) + &"]".repeat(d);
+        assert!(!too_deep(&nested(127), MAX_JSON_DEPTH));
+        assert!(serde_json::from_str::<Value>(&nested(127)).is_ok());
+        assert!(too_deep(&nested(128), MAX_JSON_DEPTH));
+        assert!(serde_json::from_str::<Value>(&nested(128)).is_err());
+    }
+
+    /// Fuzz corpus under `tests/fixtures/json`, with whether each entry is
+    /// refused under the default limit.
+    const CORPUS: &[(&str, bool)] = &[
+        (include_str!("../tests/fixtures/json/deep_arrays_request.json"), true),
+        (include_str!("../tests/fixtures/json/deep_key_chain_request.json"), true),
+        (include_str!("../tests/fixtures/json/deep_arrays_response.json"), true),
+        (include_str!("../tests/fixtures/json/limit_minus_one_request.json"), false),
+        (include_str!("../tests/fixtures/json/brackets_in_strings_request.json"), false),
+        (include_str!("../tests/fixtures/json/duplicate_keys_request.json"), false),
+        (include_str!("../tests/fixtures/json/duplicate_keys_response.json"), false),
+    ];
+
+    #[test]
+    fn corpus_depth_verdicts_agree_with_serde() {
+        for (entry, refused) in CORPUS {
+            assert_eq!(too_deep(entry, MAX_JSON_DEPTH), *refused, "{}", &entry[..60]);
+            assert_eq!(serde_json::from_str::<Value>(entry).is_err(), *refused);
+        }
+    }
+
+    #[test]
+    fn duplicate_keys_resolve_last_wins() {
+        let request = include_str!("../tests/fixtures/json/duplicate_keys_request.json");
+        assert_eq!(request_id(request), Some(json!(7)));
+        assert_eq!(method(request).as_deref(), Some("tools/list"));
+        let response = include_str!("../tests/fixtures/json/duplicate_keys_response.json");
+        assert_eq!(request_id(response), Some(json!(9)));
+    }
+
+    #[test]
+    fn errors_carry_numeric_and_string_ids() {
+        assert_eq!(
+            error_for(r#"{"jsonrpc":"2.0","id":7,"method":"x"}"#, INTERNAL_ERROR, "boom")
+                .as_deref(),
+            Some(r#"{"jsonrpc":"2.0","id":7,"error":{"code":-32603,"message":"boom"}}"#)
+        );
+        assert_eq!(
+            error_for(r#"{"jsonrpc":"2.0","id":"a","method":"x"}"#, INTERNAL_ERROR, "boom")
+                .as_deref(),
+            Some(r#"{"jsonrpc":"2.0","id":"a","error":{"code":-32603,"message":"boom"}}"#)
+        );
+    }
+
+    #[test]
+    fn batch_errors_answer_each_request() {
+        let batch = r#"[{"jsonrpc":"2.0","id":1,"method":"x"},{"jsonrpc":"2.0","method":"n"},{"jsonrpc":"2.0","id":"b","method":"y"}]"#;
+        let errors = error_for(batch, INTERNAL_ERROR, "boom").unwrap();
+        assert_eq!(
+            serde_json::from_str::<Value>(&errors).unwrap(),
+            json!([
+                {"jsonrpc": "2.0", "id": 1, "error": {"code": -32603, "message": "boom"}},
+                {"jsonrpc": "2.0", "id": "b", "error": {"code": -32603, "message": "boom"}},
+            ])
+        );
+        assert_eq!(response_ids(&errors), vec![json!(1), json!("b")]);
+    }
+
+    #[test]
+    fn notifications_and_responses_get_no_error() {
+        let none = |msg: &str| error_for(msg, INTERNAL_ERROR, "boom");
+        assert_eq!(none(r#"{"jsonrpc":"2.0","method":"notifications/x"}"#), None);
+        assert_eq!(none(r#"{"jsonrpc":"2.0","id":null,"method":"x"}"#), None);
+        assert_eq!(none(r#"{"jsonrpc":"2.0","id":3,"result":{}}"#), None);
+        assert_eq!(none(r#"[{"jsonrpc":"2.0","method":"n"}]"#), None);
+        assert_eq!(none("not json"), None);
+        assert!(!expects_response(r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#));
+        assert!(expects_response(r#"[{"jsonrpc":"2.0","method":"n"},{"jsonrpc":"2.0","id":1,"method":"x"}]"#));
+    }
+
+    #[test]
+    fn very_deep_input_is_measured_without_recursion() {
+        let deep = "[".repeat(80_000) + &"]".repeat(80_000);
+        assert_eq!(nesting_depth(&deep), 80_000);
+    }
+}
@@ -1,4 +1,54 @@
+pub mod aliases;
+pub mod auth;
+pub mod balance;
+pub mod body_log;
+pub mod breaker;
 pub mod bridge;
+pub mod cache;
+pub mod capabilities;
+pub mod check;
 pub mod config;
+pub mod control;
+#[cfg(feature = "conn-metrics")]
+pub mod conn_metrics;
+pub mod consistency;
+pub mod echo;
+pub mod embed;
+pub mod failover;
+pub mod fair;
+pub mod get_stream;
+pub mod health;
+pub mod heartbeat;
+pub mod hedge;
+pub mod idempotency;
+pub mod ids;
+pub mod integrity;
+pub mod jsonrpc;
+pub mod listen;
+pub mod meta_headers;
+pub mod method_policy;
+pub mod metrics;
+pub mod notices;
+pub mod ordered;
+pub mod otel;
+pub mod pending;
+pub mod probe;
+pub mod quirks;
+pub mod reassemble;
+pub mod record;
+pub mod redirect;
 pub mod remote_transport;
+pub mod request_body;
+pub mod report;
+pub mod responder;
+pub mod server_log;
+pub mod service;
+pub mod session;
+pub mod state;
+pub mod stats;
 pub mod stdio;
+pub mod tls;
+pub mod watchdog;
+pub mod websocket;
+
+pub use embed::{Bridge, BridgeBuilder};
@@ -0,0 +1,95 @@
+//! Serving clients over TCP (`MCP_LISTEN_ADDR`) instead of stdio, so
+//! several tools can share one bridge process. Each connection speaks the
+//! same newline-delimited JSON-RPC as stdio and gets a bridge of its own,
+//! with its own queues and upstream session, so one client's responses
+//! never reach another. Each is an embedded `Bridge` over the socket.
+//! Stats and credentials are shared, and so is the `MCP_MAX_CONCURRENCY`
+//! budget, which `fair` hands out across clients.
+
+use crate::auth::AuthProvider;
+use crate::config::Config;
+use crate::embed::Bridge;
+use crate::fair::Budget;
+use crate::stats::Stats;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, info_span, Instrument};
+
+pub async fn bind(addr: SocketAddr) -> io::Result<TcpListener> {
+    let listener = TcpListener::bind(addr).await?;
+    info!(addr = %listener.local_addr()?, "client listener bound");
+    Ok(listener)
+}
+
+/// Accepts clients until shutdown, then waits for every connection to
+/// finish, which takes at most the shutdown grace.
+pub async fn serve(
+    listener: TcpListener,
+    config: Config,
+    shutdown: CancellationToken,
+    stats: Arc<Stats>,
+    auth: Arc<AuthProvider>,
+) {
+    let budget = Arc::new(Budget::new(
+        config.max_concurrency,
+        config.connection_weights.clone(),
+    ));
+    let mut connections = JoinSet::new();
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            Some(_) = connections.join_next() => {}
+            accepted = listener.accept() => match accepted {
+                Ok((stream, peer)) => {
+                    let client = connection(
+                        stream,
+                        peer,
+                        budget.clone(),
+                        config.clone(),
+                        shutdown.clone(),
+                        stats.clone(),
+                        auth.clone(),
+                    );
+                    // Everything logged for the connection names its peer.
+                    connections.spawn(client.instrument(info_span!("client", %peer)));
+                }
+                Err(e) => debug!(%e, "client accept failed"),
+            }
+        }
+    }
+    drop(listener);
+    while connections.join_next().await.is_some() {}
+    debug!("client listener stopped");
+}
+
+/// Runs one client: its lines go to a bridge of its own as the budget
+/// admits them, and its answers come back on the same connection. When
+/// the client goes away, its queued messages are dropped and the bridge
+/// with whatever it still had in flight, which cancels those upstream
+/// calls; on shutdown it drains like the stdio bridge.
+async fn connection(
+    stream: TcpStream,
+    peer: SocketAddr,
+    budget: Arc<Budget>,
+    config: Config,
+    shutdown: CancellationToken,
+    stats: Arc<Stats>,
+    auth: Arc<AuthProvider>,
+) {
+    info!(weight = budget.weight(peer.ip()), "client connected");
+    let (input, output) = stream.into_split();
+    let bridge = Bridge::builder()
+        .config(config)
+        .stats(stats)
+        .auth(auth)
+        .shutdown(shutdown)
+        .share(budget, peer)
+        .build()
+        .expect("the bridge is given a config");
+    bridge.run_connection(input, output).await;
+    debug!("client connection closed");
+}
@@ -1,10 +1,47 @@
-use mcp_stdio_http_bridge::bridge::run_bridge;
-use mcp_stdio_http_bridge::config::Config;
-use mcp_stdio_http_bridge::stdio;
-use tracing::info;
+use mcp_stdio_http_bridge::auth::AuthProvider;
+use mcp_stdio_http_bridge::check;
+use mcp_stdio_http_bridge::config::{self, redact_uri, Config, LoadError};
+use mcp_stdio_http_bridge::consistency::{self, Severity};
+use mcp_stdio_http_bridge::echo::Mode;
+use mcp_stdio_http_bridge::embed::{Bridge, Ended};
+use mcp_stdio_http_bridge::health;
+use mcp_stdio_http_bridge::heartbeat;
+use mcp_stdio_http_bridge::listen;
+use mcp_stdio_http_bridge::metrics;
+use mcp_stdio_http_bridge::probe::{self, StartupProbe};
+use mcp_stdio_http_bridge::record::{self, Recorded, Recorder};
+use mcp_stdio_http_bridge::remote_transport::{self, build_client};
+use mcp_stdio_http_bridge::report::{self, ExitReason};
+use mcp_stdio_http_bridge::service::{self, ServiceCommand};
+use mcp_stdio_http_bridge::stats::Stats;
+use mcp_stdio_http_bridge::watchdog;
+use mcp_stdio_http_bridge::websocket;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
 use tracing_subscriber::EnvFilter;
 
-async fn wait_for_shutdown_signal() {
+/// Exit status after `MCP_IDLE_EXIT_SECS` elapsed without client traffic, so
+/// supervisors can tell an idle exit from a failure.
+const IDLE_EXIT_CODE: i32 = 3;
+const IDLE_EXIT_REASON: &str = "idle timeout";
+/// Exit status when stdin could not be read or stdout could not be written.
+const STDIO_ERROR_CODE: i32 = 5;
+const STDIN_FAILED_REASON: &str = "stdin read failed";
+const STDOUT_CLOSED_REASON: &str = "stdout closed";
+/// Exit status after any task panicked, whether or not the bridge could
+/// carry on without it.
+const PANIC_EXIT_CODE: i32 = 6;
+const PANIC_EXIT_REASON: &str = "bridge panicked";
+const PARENT_EXIT_REASON: &str = "parent process exited";
+/// Bytes of recorded stdin written ahead of the bridge reading them.
+const REPLAY_BUFFER: usize = 64 * 1024;
+
+/// Waits for SIGINT or SIGTERM and returns the exit reason to report.
+#[cfg(not(windows))]
+async fn wait_for_shutdown_signal() -> &'static str {
     let sigint = tokio::signal::ctrl_c();
     tokio::pin!(sigint);
     #[cfg(unix)]
@@ -20,42 +57,480 @@ async fn wait_for_shutdown_signal() {
     tokio::select! {
         _ = &mut sigint => {
             info!("received SIGINT, shutting down");
+            "signal: SIGINT"
         }
         _ = sigterm => {
             info!("received SIGTERM, shutting down");
+            "signal: SIGTERM"
         }
     }
 }
 
-#[tokio::main]
-async fn main() {
-    let filter = EnvFilter::from_default_env()
-        .add_directive("mcp_stdio_http_bridge=info".parse().unwrap());
-    tracing_subscriber::fmt()
+/// Waits for a console event and returns the exit reason to report. Windows
+/// has no SIGTERM: closing the console window and system shutdown arrive as
+/// `CTRL_CLOSE` and `CTRL_SHUTDOWN` instead, and Windows ends the process a
+/// few seconds later whether or not the drain has finished.
+#[cfg(windows)]
+async fn wait_for_shutdown_signal() -> &'static str {
+    use tokio::signal::windows;
+    let mut ctrl_c = windows::ctrl_c().expect("register CTRL_C");
+    let mut ctrl_break = windows::ctrl_break().expect("register CTRL_BREAK");
+    let mut ctrl_close = windows::ctrl_close().expect("register CTRL_CLOSE");
+    let mut ctrl_shutdown = windows::ctrl_shutdown().expect("register CTRL_SHUTDOWN");
+    let reason = tokio::select! {
+        _ = ctrl_c.recv() => "console: CTRL_C",
+        _ = ctrl_break.recv() => "console: CTRL_BREAK",
+        _ = ctrl_close.recv() => "console: CTRL_CLOSE",
+        _ = ctrl_shutdown.recv() => "console: CTRL_SHUTDOWN",
+    };
+    info!(reason, "received console event, shutting down");
+    reason
+}
+
+/// Invalidates the cached credential on every SIGUSR2 so a secret-rotation
+/// agent can have the token re-acquired without a full reload.
+#[cfg(unix)]
+async fn reload_credentials_on_sigusr2(auth: Arc<AuthProvider>) {
+    use tokio::signal::unix::{signal, SignalKind};
+    let Ok(mut usr2) = signal(SignalKind::user_defined2()) else {
+        warn!("could not register SIGUSR2, credential reload is unavailable");
+        return;
+    };
+    while usr2.recv().await.is_some() {
+        info!("received SIGUSR2, re-acquiring credentials on the next request");
+        auth.invalidate();
+    }
+}
+
+/// How log lines on stderr look (`MCP_LOG_FORMAT`). Read straight from the
+/// environment like `RUST_LOG`, since logging starts before the config loads.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum LogFormat {
+    /// tracing's default human format.
+    #[default]
+    Full,
+    Compact,
+    Pretty,
+    /// One JSON object per line, event fields at the top level.
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "full" => Ok(Self::Full),
+            "compact" => Ok(Self::Compact),
+            "pretty" => Ok(Self::Pretty),
+            "json" => Ok(Self::Json),
+            other => Err(format!("unknown MCP_LOG_FORMAT {:?}, using full", other)),
+        }
+    }
+}
+
+fn init_tracing() {
+    let mut filter = EnvFilter::from_default_env();
+    // A level for the bridge given in RUST_LOG wins over the default one.
+    if !std::env::var("RUST_LOG").is_ok_and(|v| v.contains("mcp_stdio_http_bridge")) {
+        filter = filter.add_directive("mcp_stdio_http_bridge=info".parse().unwrap());
+    }
+    let format = std::env::var("MCP_LOG_FORMAT")
+        .ok()
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse::<LogFormat>());
+    // Escape codes only help a human watching a terminal; a log collector
+    // reading a pipe gets them as garbage.
+    let ansi = std::io::stderr().is_terminal();
+    let builder = tracing_subscriber::fmt()
         .with_env_filter(filter)
         .with_writer(std::io::stderr)
-        .init();
-    let config = Config::from_env();
+        .with_ansi(ansi);
+    match format.clone().and_then(Result::ok).unwrap_or_default() {
+        LogFormat::Full => builder.init(),
+        LogFormat::Compact => builder.compact().init(),
+        LogFormat::Pretty => builder.pretty().init(),
+        LogFormat::Json => builder
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .with_ansi(false)
+            .init(),
+    }
+    if let Some(Err(e)) = format {
+        warn!("{}", e);
+    }
+}
+
+/// Logs every inconsistent combination of settings and exits with
+/// `CONFIG_ERROR_CODE` when one is a contradiction; redundant ones only warn.
+fn check_consistency(config: &Config) {
+    let mut contradiction = false;
+    for rule in consistency::check(config) {
+        match rule.severity {
+            Severity::Contradiction => {
+                contradiction = true;
+                error!("invalid configuration: {}", rule.message());
+            }
+            Severity::Redundant => warn!("redundant configuration: {}", rule.message()),
+        }
+    }
+    if contradiction {
+        std::process::exit(consistency::CONFIG_ERROR_CODE);
+    }
+}
+
+/// Loads the config from `args`, the environment and the config file. Usage
+/// errors, `--help` and `--version` all go to stderr: stdout belongs to the
+/// JSON-RPC client.
+fn load_config(args: &[String]) -> Config {
+    match Config::load(args) {
+        Ok(config) => config,
+        Err(LoadError::Usage(e)) => {
+            eprint!("{}", e.render());
+            std::process::exit(e.exit_code());
+        }
+        Err(LoadError::File(message) | LoadError::Invalid(message)) => {
+            error!("{}", message);
+            std::process::exit(consistency::CONFIG_ERROR_CODE);
+        }
+    }
+}
+
+/// Set by the panic hook. Tokio catches a panicking task's unwind, so
+/// without it a panic would only show up as a line on stderr.
+static PANICKED: AtomicBool = AtomicBool::new(false);
+
+/// Records every panic in `PANICKED`, then reports it as Rust normally does.
+fn install_panic_hook() {
+    let report = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        PANICKED.store(true, Ordering::SeqCst);
+        report(info);
+    }));
+}
+
+/// The exit status for the reason the bridge stopped.
+fn exit_code(reason: Option<&str>) -> i32 {
+    if PANICKED.load(Ordering::SeqCst) {
+        return PANIC_EXIT_CODE;
+    }
+    match reason {
+        Some(IDLE_EXIT_REASON) => IDLE_EXIT_CODE,
+        Some(STDIN_FAILED_REASON | STDOUT_CLOSED_REASON) => STDIO_ERROR_CODE,
+        Some(PANIC_EXIT_REASON) => PANIC_EXIT_CODE,
+        _ => 0,
+    }
+}
+
+fn main() {
+    init_tracing();
+    install_panic_hook();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some(config::SCHEMA_ARG) {
+        let schema = serde_json::to_string_pretty(&config::schema()).expect("schema is JSON");
+        println!("{}", schema);
+        std::process::exit(0);
+    }
+    if let Some(command) = service::parse_command(&args) {
+        let result = match command {
+            ServiceCommand::Install => service::install(&args[1..]),
+            ServiceCommand::Uninstall => service::uninstall(),
+            ServiceCommand::Run => service::run_as_service(run_service_body),
+        };
+        if let Err(e) = result {
+            error!(%e, "service command failed");
+            std::process::exit(1);
+        }
+        std::process::exit(0);
+    }
+    if args.first().map(String::as_str) == Some(check::CHECK_ARG) {
+        std::process::exit(run_check(&args[1..]));
+    }
+    let (args, replay) = match record::parse_args(&args) {
+        Some(Ok((replay, rest))) => (rest, Some(replay)),
+        Some(Err(e)) => {
+            error!("{}", e);
+            std::process::exit(consistency::CONFIG_ERROR_CODE);
+        }
+        None => (args, None),
+    };
+    let config = load_config(&args);
+    check_consistency(&config);
+    let replay = replay.map(|replay| {
+        if config.listen_addr.is_some() {
+            error!("{} replays into the stdio bridge; unset MCP_LISTEN_ADDR", record::REPLAY_ARG);
+            std::process::exit(consistency::CONFIG_ERROR_CODE);
+        }
+        match record::load(&replay.path) {
+            Ok(lines) => {
+                info!(path = %replay.path.display(), lines = lines.len(), speed = replay.speed, "replaying recorded stdin");
+                (lines, replay.speed)
+            }
+            Err(e) => {
+                error!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    });
+    let runtime = tokio::runtime::Runtime::new().expect("tokio runtime");
+    let exit = runtime.block_on(async {
+        let shutdown = CancellationToken::new();
+        let exit = Arc::new(ExitReason::default());
+        let signal_token = shutdown.clone();
+        let signal_exit = exit.clone();
+        tokio::spawn(async move {
+            signal_exit.set(wait_for_shutdown_signal().await);
+            signal_token.cancel();
+        });
+        run(config, shutdown, exit.clone(), replay).await;
+        exit
+    });
+    // tokio's stdin keeps a blocking read parked on a runtime thread that
+    // dropping the runtime would wait on forever while the client keeps stdin
+    // open, so exit explicitly once stdout is done.
+    std::process::exit(exit_code(exit.get()));
+}
+
+/// Entry point used by the Windows service dispatcher: same bridge, with the
+/// service control handler cancelling `shutdown` instead of console signals.
+fn run_service_body(shutdown: CancellationToken) {
+    // The service is launched as `run-service` followed by the flags given
+    // to `install-service`.
+    let args: Vec<String> = std::env::args().skip(2).collect();
+    let config = load_config(&args);
+    check_consistency(&config);
+    let runtime = tokio::runtime::Runtime::new().expect("tokio runtime");
+    let exit = Arc::new(ExitReason::default());
+    runtime.block_on(run(config, shutdown, exit, None));
+    runtime.shutdown_background();
+}
+
+/// `--check`: runs the handshake against the upstream instead of bridging,
+/// prints what it found to stdout and returns the exit status.
+fn run_check(args: &[String]) -> i32 {
+    let config = load_config(args);
+    check_consistency(&config);
+    if config.mode == Mode::Echo || websocket::is_websocket(&config.uri) {
+        error!(
+            "{} needs a Streamable HTTP upstream, not MCP_MODE=echo or a WebSocket URI",
+            check::CHECK_ARG
+        );
+        return consistency::CONFIG_ERROR_CODE;
+    }
+    let runtime = tokio::runtime::Runtime::new().expect("tokio runtime");
+    let checked = runtime.block_on(async {
+        let stats = Stats::new(config.stats_method_limit);
+        let auth = AuthProvider::from_config(&config);
+        let client = build_client(&config);
+        check::run(&config, &client, &auth, &stats).await
+    });
+    match checked {
+        Ok(report) => {
+            print!("{}", report);
+            0
+        }
+        Err(e) => {
+            error!(uri = %redact_uri(&config.uri), "{}", e);
+            e.failure.exit_code()
+        }
+    }
+}
+
+/// Pings the upstream once and logs the outcome; with
+/// `MCP_STARTUP_PROBE=required` a failure exits with `PROBE_FAILED_CODE`.
+async fn startup_probe(config: &Config, stats: &Stats, auth: &AuthProvider) {
+    let client = build_client(config);
+    match probe::run(config, &client, auth, stats).await {
+        Ok(latency) => info!(latency_ms = latency.as_millis() as u64, "startup probe ok"),
+        Err(e) if config.startup_probe == StartupProbe::Required => {
+            error!(%e, uri = %redact_uri(&config.uri), "startup probe failed, exiting (MCP_STARTUP_PROBE=required)");
+            std::process::exit(probe::PROBE_FAILED_CODE);
+        }
+        Err(e) => warn!(%e, uri = %redact_uri(&config.uri), "startup probe failed, starting anyway"),
+    }
+}
+
+/// Runs the bridge until `shutdown` is cancelled, stdin closes or the bridge
+/// stops, then drains stdout and writes the shutdown report. Whoever cancels
+/// `shutdown` records the reason in `exit` first. With `replay`, its lines
+/// are read instead of stdin, at its speed.
+async fn run(
+    config: Config,
+    shutdown: CancellationToken,
+    exit: Arc<ExitReason>,
+    replay: Option<(Vec<Recorded>, f64)>,
+) {
     let name = config.mcp_name.as_deref().unwrap_or("mcp-stdio-http-bridge");
-    info!(%name, uri = %config.uri, "starting bridge");
-    let (tx_in, rx_in) = tokio::sync::mpsc::channel(config.max_queue);
-    let (tx_out, rx_out) = tokio::sync::mpsc::channel::<String>(config.max_queue);
-    let (shutdown_stdin_tx, shutdown_stdin_rx) = tokio::sync::oneshot::channel();
-    let (shutdown_bridge_tx, shutdown_bridge_rx) = tokio::sync::oneshot::channel();
-    let mut stdin_handle = tokio::spawn(stdio::stdin_reader(tx_in, shutdown_stdin_rx));
-    let stdout_handle = tokio::spawn(stdio::stdout_writer(rx_out));
-    let mut bridge_handle =
-        tokio::spawn(run_bridge(config, rx_in, tx_out, shutdown_bridge_rx));
-    let shutdown_fut = wait_for_shutdown_signal();
-    tokio::pin!(shutdown_fut);
-    tokio::select! {
-        _ = &mut shutdown_fut => {}
-        _ = &mut stdin_handle => {}
-        _ = &mut bridge_handle => {}
-    }
-    let _ = shutdown_stdin_tx.send(());
-    let _ = shutdown_bridge_tx.send(());
-    let _ = stdin_handle.await;
-    let _ = bridge_handle.await;
-    let _ = stdout_handle.await;
+    info!(%name, uri = %redact_uri(&config.uri), "starting bridge");
+    if config.raw_passthrough {
+        warn!("RAW PASSTHROUGH IS ON: message content is forwarded byte for byte; aliases and id rewriting are disabled");
+    }
+    if config.mode == Mode::Echo {
+        warn!("ECHO MODE IS ON (MCP_MODE=echo): nothing is sent to {}; every request is answered locally with canned or echoed results", redact_uri(&config.uri));
+    }
+    if config.tls_insecure {
+        warn!("TLS CERTIFICATE VERIFICATION IS OFF (MCP_TLS_INSECURE): any server can impersonate the upstream; never use this outside local development");
+    }
+    config.log_audit();
+    let upstream = config.mode == Mode::Proxy;
+    if upstream && !websocket::is_websocket(&config.uri) {
+        remote_transport::log_pool_settings(&config);
+    }
+    let recorder = config.record_file.as_deref().and_then(Recorder::open);
+    let stats = Arc::new(Stats::new(config.stats_method_limit).with_recorder(recorder));
+    let auth = Arc::new(AuthProvider::from_config(&config));
+    #[cfg(unix)]
+    tokio::spawn(reload_credentials_on_sigusr2(auth.clone()));
+    let metrics_listener = match config.metrics_addr {
+        Some(addr) => match metrics::bind(addr).await {
+            Ok(listener) => Some(listener),
+            Err(e) => {
+                error!(%e, %addr, "could not bind metrics listener");
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let client_listener = match config.listen_addr {
+        Some(addr) => match listen::bind(addr).await {
+            Ok(listener) => Some(listener),
+            Err(e) => {
+                error!(%e, %addr, "could not bind client listener");
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    // Client connections have bridges of their own.
+    let bridge = client_listener.is_none().then(|| {
+        Bridge::builder()
+            .config(config.clone())
+            .stats(stats.clone())
+            .auth(auth.clone())
+            .shutdown(shutdown.clone())
+            .build()
+            .expect("the bridge is given a config")
+    });
+    if let Some(listener) = metrics_listener {
+        let queues = match &bridge {
+            Some(bridge) => vec![
+                ("stdin", bridge.input_queue().into()),
+                ("stdout", bridge.output_queue().into()),
+            ],
+            None => Vec::new(),
+        };
+        let sources = metrics::Sources {
+            config_summary: config.redacted_summary(),
+            stats: stats.clone(),
+            queues,
+        };
+        tokio::spawn(metrics::serve(listener, Arc::new(sources), shutdown.clone()));
+    }
+    if !upstream {
+        if config.startup_probe != StartupProbe::Off {
+            warn!("there is no upstream to probe in echo mode, skipping the startup probe");
+        }
+    } else if websocket::is_websocket(&config.uri) {
+        if config.startup_probe != StartupProbe::Off {
+            warn!("the startup probe does not support WebSocket upstreams, skipping it");
+        }
+    } else if config.startup_probe != StartupProbe::Off {
+        startup_probe(&config, &stats, &auth).await;
+    }
+    let report_path = config.shutdown_report_path.clone();
+    let stats_file = config.stats_file.clone();
+    if !config.heartbeat.is_zero() {
+        tokio::spawn(heartbeat::run(
+            stats.clone(),
+            config.heartbeat,
+            stats_file.clone(),
+            shutdown.clone(),
+        ));
+    }
+    let idle_exit = config.idle_exit;
+    if let Some(pid) = config.parent_pid.pid() {
+        let (shutdown, exit) = (shutdown.clone(), exit.clone());
+        tokio::spawn(async move {
+            watchdog::parent_exited(pid).await;
+            info!(pid, "parent process exited, shutting down");
+            exit.set(PARENT_EXIT_REASON);
+            shutdown.cancel();
+        });
+    }
+    if let Some(listener) = client_listener {
+        let server = listen::serve(listener, config, shutdown.clone(), stats.clone(), auth);
+        tokio::pin!(server);
+        let mut server_finished = false;
+        tokio::select! {
+            _ = shutdown.cancelled() => exit.set("shutdown requested"),
+            _ = health::idle_expired(&stats, idle_exit), if !idle_exit.is_zero() => {
+                info!(?idle_exit, "no client traffic, shutting down");
+                exit.set(IDLE_EXIT_REASON);
+            }
+            _ = &mut server => {
+                server_finished = true;
+                exit.set("client listener stopped");
+            }
+        }
+        shutdown.cancel();
+        // Each connection drains within the shutdown grace.
+        if !server_finished {
+            server.await;
+        }
+        let reason = exit.get().unwrap_or("shutdown requested");
+        let drain_completed = stats.terminated_ids().is_empty();
+        report::write(
+            &report::build(reason, &stats, drain_completed),
+            report_path.as_deref(),
+        );
+        if let Some(path) = &stats_file {
+            heartbeat::write_file(&stats, path);
+        }
+        return;
+    }
+    let bridge = bridge.expect("stdio has a bridge when there is no client listener");
+    if !idle_exit.is_zero() {
+        let (stats, shutdown, exit) = (stats.clone(), shutdown.clone(), exit.clone());
+        tokio::spawn(async move {
+            health::idle_expired(&stats, idle_exit).await;
+            info!(?idle_exit, "no client traffic, shutting down");
+            exit.set(IDLE_EXIT_REASON);
+            shutdown.cancel();
+        });
+    }
+    let replaying = replay.is_some();
+    let finished = match replay {
+        Some((lines, speed)) => {
+            let (input, feed) = tokio::io::duplex(REPLAY_BUFFER);
+            tokio::spawn(record::feed(lines, speed, feed));
+            bridge.run_lines(input, tokio::io::stdout()).await
+        }
+        None => {
+            bridge
+                .run_lines(tokio::io::stdin(), tokio::io::stdout())
+                .await
+        }
+    };
+    exit.set(match finished.ended {
+        Ended::Shutdown => "shutdown requested",
+        Ended::InputClosed if replaying => "replay finished",
+        Ended::InputClosed => "stdin closed",
+        Ended::InputFailed => STDIN_FAILED_REASON,
+        Ended::OutputClosed => STDOUT_CLOSED_REASON,
+        Ended::Stopped => "bridge stopped",
+        Ended::Panicked => PANIC_EXIT_REASON,
+    });
+    shutdown.cancel();
+    let stdout_drained = finished.drained;
+    let drain_completed = stdout_drained && stats.terminated_ids().is_empty();
+    let reason = exit.get().unwrap_or("shutdown requested");
+    report::write(
+        &report::build(reason, &stats, drain_completed),
+        report_path.as_deref(),
+    );
+    if let Some(path) = &stats_file {
+        heartbeat::write_file(&stats, path);
+    }
 }
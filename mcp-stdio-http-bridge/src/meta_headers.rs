@@ -0,0 +1,160 @@
+//! Per-request HTTP headers taken from the JSON-RPC `_meta` object
+//! (`MCP_META_HEADERS`), for gateways that route on a value the client puts
+//! in each request's `params._meta`, such as a tenant id. The body is sent
+//! unchanged unless `MCP_META_STRIP` removes the keys that became headers.
+
+use crate::config::Config;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde_json::Value;
+use std::borrow::Cow;
+use tracing::warn;
+
+/// Parses `key=Header-Name` pairs separated by commas, e.g.
+/// `tenantId=X-Tenant-Id,traceId=X-Trace-Id`.
+pub fn parse_pairs(spec: &str) -> Result<Vec<(String, String)>, String> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((key, name)) if !key.trim().is_empty() => {
+                let name = name.trim();
+                HeaderName::from_bytes(name.as_bytes())
+                    .map_err(|_| format!("invalid header name {:?}", name))?;
+                Ok((key.trim().to_string(), name.to_string()))
+            }
+            _ => Err(format!("invalid mapping {:?} (expected key=Header-Name)", pair)),
+        })
+        .collect()
+}
+
+/// The headers `body` asks for, and the body to send: `body` itself unless
+/// consumed keys are stripped. In a batch, a key becomes a header only when
+/// every request carrying it agrees on the value. Values that are not
+/// strings, numbers or booleans, or not legal in a header, are left out.
+pub fn take<'a>(config: &Config, body: &'a str) -> (HeaderMap, Cow<'a, str>) {
+    let mut headers = HeaderMap::new();
+    if config.meta_headers.is_empty() || !body.contains("_meta") {
+        return (headers, Cow::Borrowed(body));
+    }
+    let Ok(mut msg) = serde_json::from_str::<Value>(body) else {
+        return (headers, Cow::Borrowed(body));
+    };
+    // Stripping rewrites the body, which passthrough never does.
+    let strip = config.meta_strip && !config.raw_passthrough;
+    let mut stripped = false;
+    for (key, name) in &config.meta_headers {
+        let name = HeaderName::from_bytes(name.as_bytes()).expect("checked at startup");
+        let mut found = Vec::new();
+        for item in items(&mut msg) {
+            let Some(meta) = item
+                .get_mut("params")
+                .and_then(|p| p.get_mut("_meta"))
+                .and_then(Value::as_object_mut)
+            else {
+                continue;
+            };
+            let value = if strip {
+                meta.remove(key)
+            } else {
+                meta.get(key).cloned()
+            };
+            stripped |= strip && value.is_some();
+            found.extend(value);
+        }
+        let Some(first) = found.first() else {
+            continue;
+        };
+        if found.iter().any(|v| v != first) {
+            warn!(key = %key, header = %name, "batch disagrees on a _meta value, header left out");
+            continue;
+        }
+        match header_value(first) {
+            Some(value) => {
+                headers.insert(name, value);
+            }
+            None => warn!(key = %key, header = %name, "_meta value is not a legal header value, header left out"),
+        }
+    }
+    let body = match stripped {
+        true => Cow::Owned(msg.to_string()),
+        false => Cow::Borrowed(body),
+    };
+    (headers, body)
+}
+
+fn items(msg: &mut Value) -> Vec<&mut Value> {
+    match msg {
+        Value::Array(items) => items.iter_mut().collect(),
+        item => vec![item],
+    }
+}
+
+fn header_value(value: &Value) -> Option<HeaderValue> {
+    let text = match value {
+        Value::String(s) => s.clone(),
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        _ => return None,
+    };
+    HeaderValue::from_str(&text).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(pairs: &str, strip: bool) -> Config {
+        let mut config = Config::from_lookup(|_| None);
+        config.meta_headers = parse_pairs(pairs).unwrap();
+        config.meta_strip = strip;
+        config
+    }
+
+    #[test]
+    fn pairs_parse() {
+        assert_eq!(
+            parse_pairs(" tenantId=X-Tenant-Id, traceId = X-Trace-Id ,").unwrap(),
+            [
+                ("tenantId".to_string(), "X-Tenant-Id".to_string()),
+                ("traceId".to_string(), "X-Trace-Id".to_string())
+            ]
+        );
+        assert!(parse_pairs("tenantId").is_err());
+        assert!(parse_pairs("=X-Tenant-Id").is_err());
+        assert!(parse_pairs("tenantId=X Tenant").is_err());
+    }
+
+    #[test]
+    fn meta_values_become_headers() {
+        let config = config("tenantId=X-Tenant-Id,traceId=X-Trace-Id", false);
+        let body = r#"{"jsonrpc":"2.0","id":1,"method":"tools/list","params":{"_meta":{"tenantId":"acme","n":1}}}"#;
+        let (headers, sent) = take(&config, body);
+        assert_eq!(headers["x-tenant-id"], "acme");
+        assert!(!headers.contains_key("x-trace-id"));
+        assert!(matches!(sent, Cow::Borrowed(b) if b == body));
+    }
+
+    #[test]
+    fn stripping_removes_only_consumed_keys() {
+        let config = config("tenantId=X-Tenant-Id", true);
+        let body = r#"{"jsonrpc":"2.0","id":1,"method":"m","params":{"_meta":{"tenantId":7,"n":1}}}"#;
+        let (headers, sent) = take(&config, body);
+        assert_eq!(headers["x-tenant-id"], "7");
+        assert_eq!(sent, r#"{"jsonrpc":"2.0","id":1,"method":"m","params":{"_meta":{"n":1}}}"#);
+    }
+
+    #[test]
+    fn illegal_or_conflicting_values_are_left_out() {
+        let config = config("tenantId=X-Tenant-Id", false);
+        let body = r#"{"jsonrpc":"2.0","id":1,"method":"m","params":{"_meta":{"tenantId":"a\nb"}}}"#;
+        assert!(take(&config, body).0.is_empty());
+        let body = r#"{"jsonrpc":"2.0","id":1,"method":"m","params":{"_meta":{"tenantId":{}}}}"#;
+        assert!(take(&config, body).0.is_empty());
+        let batch = r#"[{"jsonrpc":"2.0","id":1,"method":"m","params":{"_meta":{"tenantId":"a"}}},
+            {"jsonrpc":"2.0","id":2,"method":"m","params":{"_meta":{"tenantId":"b"}}}]"#;
+        assert!(take(&config, batch).0.is_empty());
+        let batch = r#"[{"jsonrpc":"2.0","id":1,"method":"m","params":{"_meta":{"tenantId":"a"}}},
+            {"jsonrpc":"2.0","id":2,"method":"m"}]"#;
+        assert_eq!(take(&config, batch).0["x-tenant-id"], "a");
+    }
+}
@@ -0,0 +1,209 @@
+//! Which client methods may reach the upstream (`MCP_ALLOW_METHODS`,
+//! `MCP_DENY_METHODS`), for a deployment that exposes only part of a
+//! server. Patterns are method names where `*` stands for any run of
+//! characters, so `resources/*` covers every resource method. A blocked
+//! request is answered by the bridge and never sent; a blocked notification
+//! is dropped. Capabilities whose every method is blocked are removed from
+//! the `initialize` result, so the client does not offer those features.
+
+use crate::jsonrpc;
+use crate::responder::{Closed, Responder};
+use serde_json::Value;
+use tracing::{debug, warn};
+
+/// Answer to a blocked request.
+pub const BLOCKED_MESSAGE: &str = "method not allowed by bridge policy";
+
+/// The session lifecycle, which an allowlist cannot leave out without
+/// breaking every session. Only `MCP_DENY_METHODS` can block them.
+const LIFECYCLE: &[&str] = &[
+    "initialize",
+    "notifications/initialized",
+    "notifications/cancelled",
+    "ping",
+];
+
+/// Each capability and the methods that make it up.
+const CAPABILITY_METHODS: &[(&str, &[&str])] = &[
+    ("tools", &["tools/list", "tools/call"]),
+    (
+        "resources",
+        &[
+            "resources/list",
+            "resources/templates/list",
+            "resources/read",
+            "resources/subscribe",
+            "resources/unsubscribe",
+        ],
+    ),
+    ("prompts", &["prompts/list", "prompts/get"]),
+    ("logging", &["logging/setLevel"]),
+    ("completions", &["completion/complete"]),
+    ("sampling", &["sampling/createMessage"]),
+    ("roots", &["roots/list"]),
+    ("elicitation", &["elicitation/create"]),
+];
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MethodPolicy {
+    /// When not empty, only methods matching one of these pass.
+    allow: Vec<String>,
+    /// Methods matching one of these never pass.
+    deny: Vec<String>,
+}
+
+impl MethodPolicy {
+    pub fn new(allow: &[String], deny: &[String]) -> Self {
+        Self {
+            allow: allow.to_vec(),
+            deny: deny.to_vec(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+
+    pub fn permits(&self, method: &str) -> bool {
+        if self.deny.iter().any(|p| matches(p, method)) {
+            return false;
+        }
+        self.allow.is_empty()
+            || LIFECYCLE.contains(&method)
+            || self.allow.iter().any(|p| matches(p, method))
+    }
+
+    /// The first method in `msg`, a message or a batch, that may not pass.
+    /// Responses to the server carry no method and always pass.
+    pub fn blocked(&self, msg: &str) -> Option<String> {
+        if self.is_empty() {
+            return None;
+        }
+        let value: Value = serde_json::from_str(msg).ok()?;
+        let items = match &value {
+            Value::Array(items) => items.as_slice(),
+            item => std::slice::from_ref(item),
+        };
+        items
+            .iter()
+            .filter_map(|item| item.get("method")?.as_str())
+            .find(|method| !self.permits(method))
+            .map(str::to_string)
+    }
+
+    /// Answers `msg` with an error, or drops it if it is a notification,
+    /// when it may not pass; true when it was refused.
+    pub async fn refuse(&self, msg: &str, responder: &Responder) -> Result<bool, Closed> {
+        let Some(method) = self.blocked(msg) else {
+            return Ok(false);
+        };
+        match rejection(msg) {
+            Some(err) => {
+                debug!(%method, "answering request blocked by bridge policy");
+                responder.local(err).await?;
+            }
+            None => warn!(%method, "dropping notification blocked by bridge policy"),
+        }
+        Ok(true)
+    }
+
+    /// The capabilities none of whose methods may pass, for
+    /// `CapabilityFilter` to strip.
+    pub fn denied_capabilities(&self) -> Vec<String> {
+        CAPABILITY_METHODS
+            .iter()
+            .filter(|(_, methods)| methods.iter().all(|m| !self.permits(m)))
+            .map(|(capability, _)| capability.to_string())
+            .collect()
+    }
+}
+
+/// The error answering a blocked `msg`, one per request in a batch; `None`
+/// for a notification.
+fn rejection(msg: &str) -> Option<String> {
+    jsonrpc::error_for(msg, jsonrpc::METHOD_NOT_FOUND, BLOCKED_MESSAGE)
+}
+
+/// Parses a comma-separated list of method patterns.
+pub fn parse_patterns(spec: &str) -> Vec<String> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Whether `method` matches `pattern`, where each `*` matches any run of
+/// characters, `/` included.
+fn matches(pattern: &str, method: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = method.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(allow: &str, deny: &str) -> MethodPolicy {
+        MethodPolicy::new(&parse_patterns(allow), &parse_patterns(deny))
+    }
+
+    #[test]
+    fn globs_match_any_run_of_characters() {
+        assert!(matches("resources/*", "resources/templates/list"));
+        assert!(matches("*/list", "tools/list"));
+        assert!(matches("tools/call", "tools/call"));
+        assert!(matches("*", "anything"));
+        assert!(matches("a*b*c", "a-b-b-c"));
+        assert!(!matches("tools/call", "tools/callx"));
+        assert!(!matches("resources/*", "tools/list"));
+        assert!(!matches("a*b*c", "a-c-b"));
+    }
+
+    #[test]
+    fn deny_wins_and_an_allowlist_keeps_the_lifecycle() {
+        let p = policy("tools/*", "tools/call");
+        assert!(p.permits("tools/list"));
+        assert!(!p.permits("tools/call"));
+        assert!(!p.permits("resources/read"));
+        assert!(p.permits("initialize"));
+        assert!(p.permits("ping"));
+        assert!(!policy("", "ping").permits("ping"));
+        assert!(MethodPolicy::default().permits("anything"));
+    }
+
+    #[test]
+    fn a_batch_is_blocked_by_any_of_its_methods() {
+        let p = policy("", "resources/*");
+        assert_eq!(p.blocked(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#), None);
+        assert_eq!(p.blocked(r#"{"jsonrpc":"2.0","id":1,"result":{}}"#), None);
+        let batch = r#"[{"jsonrpc":"2.0","id":1,"method":"tools/list"},{"jsonrpc":"2.0","id":2,"method":"resources/read"}]"#;
+        assert_eq!(p.blocked(batch).as_deref(), Some("resources/read"));
+    }
+
+    #[test]
+    fn fully_denied_capabilities_are_listed() {
+        let p = policy("tools/list,tools/call", "");
+        assert_eq!(
+            p.denied_capabilities(),
+            ["resources", "prompts", "logging", "completions", "sampling", "roots", "elicitation"]
+        );
+        // One resource method left keeps the capability.
+        assert_eq!(policy("", "resources/list,sampling/*").denied_capabilities(), ["sampling"]);
+        assert!(MethodPolicy::default().denied_capabilities().is_empty());
+    }
+}
@@ -0,0 +1,489 @@
+//! Local HTTP listener enabled by `MCP_METRICS_ADDR`. Serves a plain HTML
+//! `/status` page for humans and a Prometheus text exposition on
+//! `/metrics`; neither shows payloads or secrets.
+
+use crate::breaker::CircuitState;
+use crate::stats::Stats;
+use std::fmt::Write as _;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::WeakSender;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info};
+
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Everything the listener reads from. Queues are held weakly so the
+/// listener never keeps a bridge channel open during shutdown.
+pub struct Sources {
+    /// Redacted `(field, value)` pairs from `Config::redacted_summary`.
+    pub config_summary: Vec<(&'static str, String)>,
+    pub stats: Arc<Stats>,
+    pub queues: Vec<(&'static str, Queue)>,
+}
+
+/// A bridge queue, whatever it carries, held weakly.
+pub struct Queue(Box<dyn Fn() -> Option<usize> + Send + Sync>);
+
+impl Queue {
+    /// Messages waiting; `None` once the queue has closed.
+    pub fn depth(&self) -> Option<usize> {
+        (self.0)()
+    }
+}
+
+impl<T: Send + 'static> From<WeakSender<T>> for Queue {
+    fn from(queue: WeakSender<T>) -> Self {
+        Self(Box::new(move || {
+            let tx = queue.upgrade()?;
+            Some(tx.max_capacity() - tx.capacity())
+        }))
+    }
+}
+
+pub async fn bind(addr: SocketAddr) -> io::Result<TcpListener> {
+    let listener = TcpListener::bind(addr).await?;
+    info!(addr = %listener.local_addr()?, "metrics listener bound");
+    Ok(listener)
+}
+
+pub async fn serve(listener: TcpListener, sources: Arc<Sources>, shutdown: CancellationToken) {
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    tokio::spawn(handle(stream, sources.clone()));
+                }
+                Err(e) => debug!(%e, "metrics accept failed"),
+            }
+        }
+    }
+    debug!("metrics listener stopped");
+}
+
+async fn handle(mut stream: TcpStream, sources: Arc<Sources>) {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") && head.len() < MAX_REQUEST_HEAD {
+        match stream.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => head.extend_from_slice(&buf[..n]),
+        }
+    }
+    let head = String::from_utf8_lossy(&head);
+    let mut parts = head.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or_default();
+    let (status, content_type, body) = match (method, path) {
+        ("GET", "/status") => ("200 OK", "text/html; charset=utf-8", render_status(&sources)),
+        ("GET", "/metrics") => ("200 OK", PROMETHEUS_CONTENT_TYPE, render_prometheus(&sources)),
+        ("GET", _) => ("404 Not Found", "text/plain", "not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "text/plain", "method not allowed\n".to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+pub fn render_status(sources: &Sources) -> String {
+    let stats = &sources.stats;
+    let uptime = stats.uptime().as_secs();
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>mcp-stdio-http-bridge status</title></head><body>\n<h1>mcp-stdio-http-bridge</h1>\n",
+    );
+    let _ = writeln!(
+        html,
+        "<p>Uptime: {}h {}m {}s</p>",
+        uptime / 3600,
+        uptime / 60 % 60,
+        uptime % 60
+    );
+
+    html.push_str("<h2>Config</h2>\n<table>\n");
+    for (field, value) in &sources.config_summary {
+        let _ = writeln!(html, "<tr><td>{}</td><td>{}</td></tr>", field, escape(value));
+    }
+    html.push_str("</table>\n");
+
+    html.push_str("<h2>Queues</h2>\n<table>\n");
+    for (name, queue) in &sources.queues {
+        let depth = match queue.depth() {
+            Some(depth) => depth.to_string(),
+            None => "closed".to_string(),
+        };
+        let _ = writeln!(html, "<tr><td>{}</td><td>{}</td></tr>", name, depth);
+    }
+    html.push_str("</table>\n");
+
+    let tables = stats.tables();
+    if !tables.is_empty() {
+        html.push_str("<h2>State tables</h2>\n<table>\n");
+        for t in &tables {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{} / {} (peak {})</td></tr>",
+                t.name,
+                t.len(),
+                t.limit,
+                t.peak()
+            );
+        }
+        html.push_str("</table>\n");
+    }
+
+    let clients = stats.clients();
+    if !clients.is_empty() {
+        html.push_str("<h2>Clients</h2>\n<table>\n");
+        for c in &clients {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>weight {} &middot; queued {} &middot; admitted {}</td></tr>",
+                c.peer,
+                c.weight,
+                c.queued(),
+                c.admitted()
+            );
+        }
+        html.push_str("</table>\n");
+    }
+
+    let snapshot = stats.snapshot();
+    html.push_str("<h2>Upstream</h2>\n");
+    if stats.paused() {
+        html.push_str("<p><strong>Bridging is paused.</strong></p>\n");
+    }
+    let session = if stats.session_present() { "present" } else { "absent" };
+    let _ = writeln!(html, "<p>Session: {}</p>", session);
+    let _ = writeln!(
+        html,
+        "<p>Requests: {} &middot; Errors: {} &middot; Current failure streak: {}</p>",
+        snapshot["requests"],
+        snapshot["errors"],
+        stats.failure_streak()
+    );
+    let _ = writeln!(
+        html,
+        "<p>Hedges launched: {} &middot; won: {}</p>",
+        snapshot["hedges_launched"], snapshot["hedges_won"]
+    );
+    let (remaining, reset) = stats.rate_limit();
+    if let Some(remaining) = remaining {
+        let reset = reset.map_or(String::new(), |r| format!(" &middot; reset: {}", r));
+        let _ = writeln!(html, "<p>Rate limit remaining: {}{}</p>", remaining, reset);
+    }
+    if !snapshot["deprecation"].is_null() || !snapshot["sunset"].is_null() {
+        let field = |key: &str| escape(snapshot[key].as_str().unwrap_or("-"));
+        let _ = writeln!(
+            html,
+            "<p><strong>Upstream deprecated:</strong> {} &middot; sunset: {}</p>",
+            field("deprecation"),
+            field("sunset")
+        );
+    }
+    let conn = &snapshot["connections"];
+    if conn["instrumented"] == true {
+        let _ = writeln!(
+            html,
+            "<p>Connections opened: {} &middot; TLS handshakes: {} &middot; Reuse ratio: {}</p>",
+            conn["opened"], conn["tls_handshakes"], conn["reuse_ratio"]
+        );
+    }
+    if let Some((state, opened)) = stats.circuit() {
+        let _ = writeln!(
+            html,
+            "<p>Circuit breaker: {} &middot; opened: {}</p>",
+            state, opened
+        );
+    }
+    let streaks = stats.recent_streaks();
+    if !streaks.is_empty() {
+        html.push_str("<p>Recent failure streaks:</p>\n<ul>\n");
+        for s in streaks {
+            let _ = writeln!(
+                html,
+                "<li>{} failures over {}s</li>",
+                s.failures,
+                s.duration.as_secs()
+            );
+        }
+        html.push_str("</ul>\n");
+    }
+
+    html.push_str("<h2>Recent requests</h2>\n<pre>\n");
+    for entry in stats.access_log() {
+        let _ = writeln!(html, "{}", escape(&entry.to_string()));
+    }
+    html.push_str("</pre>\n</body></html>\n");
+    html
+}
+
+/// The Prometheus text exposition served on `/metrics`.
+pub fn render_prometheus(sources: &Sources) -> String {
+    let stats = &sources.stats;
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, u64)]| {
+        let _ = writeln!(out, "# HELP mcp_bridge_{} {}", name, help);
+        let _ = writeln!(out, "# TYPE mcp_bridge_{} {}", name, kind);
+        for (labels, value) in samples {
+            let _ = writeln!(out, "mcp_bridge_{}{} {}", name, labels, value);
+        }
+    };
+    let (sent, responses, retries) = stats.upstream_counts();
+    metric(
+        "upstream_requests_total",
+        "counter",
+        "HTTP requests sent upstream, retries included.",
+        &[(String::new(), sent)],
+    );
+    metric(
+        "upstream_responses_total",
+        "counter",
+        "HTTP responses received from the upstream, any status.",
+        &[(String::new(), responses)],
+    );
+    metric(
+        "retries_total",
+        "counter",
+        "Client requests sent again after a retryable failure.",
+        &[(String::new(), retries)],
+    );
+    let failures: Vec<(String, u64)> = stats
+        .failure_categories()
+        .into_iter()
+        .map(|(category, n)| (format!("{{category=\"{}\"}}", category), n))
+        .collect();
+    metric(
+        "failed_requests_total",
+        "counter",
+        "Client requests answered with a transport error, by error category.",
+        &failures,
+    );
+    if let Some((state, opened)) = stats.circuit() {
+        metric(
+            "circuit_open",
+            "gauge",
+            "1 while the circuit breaker answers messages without sending them, open or half-open.",
+            &[(String::new(), u64::from(state != CircuitState::Closed.as_str()))],
+        );
+        metric(
+            "circuit_opened_total",
+            "counter",
+            "Times the circuit breaker opened, again after a failed probe included.",
+            &[(String::new(), opened)],
+        );
+    }
+    metric(
+        "in_flight_requests",
+        "gauge",
+        "Client requests currently being sent upstream.",
+        &[(String::new(), stats.in_flight())],
+    );
+    // A closed queue is left out rather than reported as empty.
+    let queues: Vec<(String, u64)> = sources
+        .queues
+        .iter()
+        .filter_map(|(name, queue)| {
+            let depth = queue.depth()? as u64;
+            Some((format!("{{queue=\"{}\"}}", name), depth))
+        })
+        .collect();
+    metric(
+        "queue_length",
+        "gauge",
+        "Messages waiting in a bridge queue.",
+        &queues,
+    );
+    // Counted only with the conn-metrics feature, so left out without it.
+    if cfg!(feature = "conn-metrics") {
+        let conn = stats.conn_counters();
+        metric(
+            "upstream_connections_opened_total",
+            "counter",
+            "Upstream connections opened, from the first response over each.",
+            &[(String::new(), conn.opened.load(Ordering::Relaxed))],
+        );
+        metric(
+            "upstream_tls_handshakes_total",
+            "counter",
+            "Full TLS handshakes, one per upstream TLS connection opened.",
+            &[(String::new(), conn.tls_handshakes.load(Ordering::Relaxed))],
+        );
+    }
+    metric(
+        "uptime_seconds",
+        "gauge",
+        "Seconds since the bridge started.",
+        &[(String::new(), stats.uptime().as_secs())],
+    );
+    out
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::notices::UpstreamNotices;
+    use crate::session::Session;
+    use crate::state::Table;
+    use crate::stats::Sample;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn status_page_shows_key_fields_without_secrets() {
+        let config = Config::from_lookup(|name| match name {
+            "MCP_BRIDGE_URI" => Some("https://user:pw@example.test/mcp?key=abc".to_string()),
+            "MCP_BRIDGE_BEARER_TOKEN" => Some("super-secret-token".to_string()),
+            _ => None,
+        });
+        let stats = Arc::new(Stats::new(8));
+        stats.record(Sample {
+            method: Some("tools/list"),
+            latency: Duration::from_millis(12),
+            bytes_out: 40,
+            bytes_in: 90,
+            error: false,
+        });
+        stats.note_notices(&UpstreamNotices {
+            deprecation: Some("true".to_string()),
+            sunset: None,
+            rate_limit_remaining: Some(7),
+            rate_limit_reset: Some(30),
+        });
+        let mut ids = Table::new("ids", 10);
+        ids.insert(1, ());
+        stats.track_table(ids.gauge());
+        let session = Arc::new(Session::new("https://example.test/mcp"));
+        session.update(Some("session-1234"));
+        stats.track_session(&session);
+        let (tx, _rx) = tokio::sync::mpsc::channel::<String>(4);
+        tx.send("queued".to_string()).await.unwrap();
+        let sources = Arc::new(Sources {
+            config_summary: config.redacted_summary(),
+            stats,
+            queues: vec![("stdin", tx.downgrade().into())],
+        });
+        let listener = bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let shutdown = CancellationToken::new();
+        tokio::spawn(serve(listener, sources, shutdown.clone()));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /status HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        shutdown.cancel();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("Uptime:"));
+        assert!(response.contains("https://example.test/mcp"));
+        assert!(response.contains("<tr><td>stdin</td><td>1</td></tr>"));
+        assert!(response.contains("<tr><td>ids</td><td>1 / 10 (peak 1)</td></tr>"));
+        assert!(response.contains("tools/list ok 12ms"));
+        assert!(response.contains("Rate limit remaining: 7 &middot; reset: 30"));
+        assert!(response.contains("Upstream deprecated:</strong> true &middot; sunset: -"));
+        assert!(response.contains("<p>Session: present</p>"));
+        assert!(!response.contains("session-1234"));
+        assert!(!response.contains("super-secret-token"));
+        assert!(!response.contains("pw@"));
+        assert!(!response.contains("key=abc"));
+    }
+
+    #[tokio::test]
+    async fn prometheus_exposition_has_counters_and_gauges() {
+        let stats = Arc::new(Stats::new(8));
+        stats.note_upstream_sent();
+        stats.note_upstream_sent();
+        stats.note_upstream_response();
+        stats.note_retry();
+        stats.note_failure_category("timeout");
+        stats.note_failure_category("timeout");
+        stats.note_failure_category("status");
+        let _in_flight = stats.start_in_flight();
+        let (tx, _rx) = tokio::sync::mpsc::channel::<String>(4);
+        tx.send("queued".to_string()).await.unwrap();
+        let sources = Sources {
+            config_summary: Vec::new(),
+            stats: stats.clone(),
+            queues: vec![("stdin", tx.downgrade().into())],
+        };
+        let text = render_prometheus(&sources);
+        for line in [
+            "# TYPE mcp_bridge_upstream_requests_total counter",
+            "mcp_bridge_upstream_requests_total 2",
+            "mcp_bridge_upstream_responses_total 1",
+            "mcp_bridge_retries_total 1",
+            "mcp_bridge_failed_requests_total{category=\"status\"} 1",
+            "mcp_bridge_failed_requests_total{category=\"timeout\"} 2",
+            "# TYPE mcp_bridge_in_flight_requests gauge",
+            "mcp_bridge_in_flight_requests 1",
+            "mcp_bridge_queue_length{queue=\"stdin\"} 1",
+        ] {
+            assert!(text.lines().any(|l| l == line), "missing {:?} in\n{}", line, text);
+        }
+        drop(_in_flight);
+        assert!(render_prometheus(&sources).contains("mcp_bridge_in_flight_requests 0\n"));
+        // Circuit breaker series only once there is a breaker.
+        assert!(!text.contains("mcp_bridge_circuit"));
+        stats.set_circuit(CircuitState::Open);
+        stats.set_circuit(CircuitState::HalfOpen);
+        stats.set_circuit(CircuitState::Open);
+        let text = render_prometheus(&sources);
+        assert!(text.contains("mcp_bridge_circuit_open 1\n"), "{}", text);
+        assert!(text.contains("mcp_bridge_circuit_opened_total 2\n"), "{}", text);
+        stats.set_circuit(CircuitState::Closed);
+        assert!(render_prometheus(&sources).contains("mcp_bridge_circuit_open 0\n"));
+    }
+
+    #[test]
+    fn connection_counters_are_exported_when_instrumented() {
+        let stats = Arc::new(Stats::new(8));
+        let conn = stats.conn_counters();
+        conn.opened.fetch_add(2, Ordering::Relaxed);
+        conn.tls_handshakes.fetch_add(1, Ordering::Relaxed);
+        let sources = Sources {
+            config_summary: Vec::new(),
+            stats,
+            queues: Vec::new(),
+        };
+        let text = render_prometheus(&sources);
+        assert_eq!(
+            text.contains("mcp_bridge_upstream_connections_opened_total 2\n"),
+            cfg!(feature = "conn-metrics"),
+            "{}",
+            text
+        );
+        assert_eq!(
+            text.contains("mcp_bridge_upstream_tls_handshakes_total 1\n"),
+            cfg!(feature = "conn-metrics"),
+            "{}",
+            text
+        );
+    }
+
+    #[test]
+    fn escape_neutralizes_markup() {
+        assert_eq!(escape("<a href=\"x\">&</a>"), "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;");
+    }
+}
@@ -0,0 +1,164 @@
+use crate::jsonrpc;
+use reqwest::header::HeaderMap;
+use serde_json::json;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Deprecation and rate-limit signals an upstream sent with a response.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UpstreamNotices {
+    /// `Deprecation`: `true`, or the date the API was deprecated.
+    pub deprecation: Option<String>,
+    /// `Sunset` (RFC 8594): when the API stops working, as an HTTP date.
+    pub sunset: Option<String>,
+    pub rate_limit_remaining: Option<u64>,
+    /// `X-RateLimit-Reset` as sent (seconds or a unix timestamp).
+    pub rate_limit_reset: Option<u64>,
+}
+
+impl UpstreamNotices {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let get = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+        };
+        let number = |name: &str| get(name).and_then(|v| v.parse().ok());
+        Self {
+            deprecation: get("Deprecation").map(|v| match parse_date(v) {
+                Some(date) => httpdate::fmt_http_date(date),
+                None => v.to_string(),
+            }),
+            sunset: get("Sunset")
+                .and_then(parse_date)
+                .map(httpdate::fmt_http_date),
+            rate_limit_remaining: number("X-RateLimit-Remaining"),
+            rate_limit_reset: number("X-RateLimit-Reset"),
+        }
+    }
+
+    pub fn is_deprecated(&self) -> bool {
+        self.deprecation.is_some() || self.sunset.is_some()
+    }
+}
+
+/// Parses an HTTP date (RFC 8594 uses IMF-fixdate) or a unix timestamp,
+/// optionally written `@1700000000` as in the Deprecation header draft.
+pub fn parse_date(value: &str) -> Option<SystemTime> {
+    let value = value.trim();
+    let digits = value.strip_prefix('@').unwrap_or(value);
+    if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+        return digits
+            .parse()
+            .ok()
+            .map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+    }
+    httpdate::parse_http_date(value).ok()
+}
+
+/// Arms a single client-visible warning when the upstream's remaining rate
+/// limit drops below `threshold`; it re-arms once the limit recovers.
+#[derive(Debug)]
+pub struct RateLimitWarning {
+    threshold: u64,
+    warned: bool,
+}
+
+impl RateLimitWarning {
+    pub fn new(threshold: u64) -> Self {
+        Self {
+            threshold,
+            warned: false,
+        }
+    }
+
+    /// The `notifications/message` to send, the first time `remaining` is
+    /// seen below the threshold.
+    pub fn check(&mut self, remaining: Option<u64>, reset: Option<u64>) -> Option<String> {
+        let remaining = remaining?;
+        if remaining >= self.threshold {
+            self.warned = false;
+            return None;
+        }
+        if self.warned {
+            return None;
+        }
+        self.warned = true;
+        let reset = reset.map_or(String::new(), |r| format!(" (reset {})", r));
+        Some(
+            json!({
+                "jsonrpc": "2.0",
+                "method": jsonrpc::LOG_MESSAGE_METHOD,
+                "params": {
+                    "level": "warning",
+                    "logger": "mcp-stdio-http-bridge",
+                    "data": format!(
+                        "upstream rate limit nearly exhausted: {} requests remaining{}",
+                        remaining, reset
+                    ),
+                }
+            })
+            .to_string(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (k, v) in pairs {
+            map.insert(*k, HeaderValue::from_str(v).unwrap());
+        }
+        map
+    }
+
+    #[test]
+    fn dates_parse_in_both_formats() {
+        let http = parse_date("Sat, 01 Nov 2025 00:00:00 GMT").unwrap();
+        assert_eq!(parse_date("1761955200"), Some(http));
+        assert_eq!(parse_date("@1761955200"), Some(http));
+        assert_eq!(parse_date("next tuesday"), None);
+    }
+
+    #[test]
+    fn headers_are_normalized() {
+        let notices = UpstreamNotices::from_headers(&headers(&[
+            ("Deprecation", "@1761955200"),
+            ("Sunset", "1764547200"),
+            ("X-RateLimit-Remaining", "42"),
+            ("X-RateLimit-Reset", "30"),
+        ]));
+        assert_eq!(
+            notices.deprecation.as_deref(),
+            Some("Sat, 01 Nov 2025 00:00:00 GMT")
+        );
+        assert_eq!(
+            notices.sunset.as_deref(),
+            Some("Mon, 01 Dec 2025 00:00:00 GMT")
+        );
+        assert_eq!(notices.rate_limit_remaining, Some(42));
+        assert_eq!(notices.rate_limit_reset, Some(30));
+        let boolean = UpstreamNotices::from_headers(&headers(&[("Deprecation", "true")]));
+        assert_eq!(boolean.deprecation.as_deref(), Some("true"));
+        assert!(!UpstreamNotices::default().is_deprecated());
+    }
+
+    #[test]
+    fn rate_limit_warning_fires_once_per_dip() {
+        let mut warning = RateLimitWarning::new(10);
+        assert_eq!(warning.check(Some(50), None), None);
+        assert!(warning
+            .check(Some(9), Some(60))
+            .unwrap()
+            .contains("9 requests remaining"));
+        assert_eq!(warning.check(Some(5), None), None);
+        assert_eq!(warning.check(None, None), None);
+        assert_eq!(warning.check(Some(100), None), None);
+        assert!(warning.check(Some(1), None).is_some());
+    }
+}
@@ -0,0 +1,254 @@
+//! Answers released in the order their requests arrived
+//! (`MCP_ORDERED_RESPONSES`), for clients that match answers to requests by
+//! position rather than by id. Requests still run concurrently: each one
+//! that expects an answer takes a sequence number as it is read, its task
+//! runs under that number, and what it answers is held until every earlier
+//! request has been answered. Notifications and server requests are not
+//! held. Once `BUFFER_LIMIT_BYTES` of answers wait behind a stuck request,
+//! everything held is released as it stands, out of order.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+/// Bytes of answers held back before ordering gives way.
+pub const BUFFER_LIMIT_BYTES: usize = 32 * 1024 * 1024;
+
+tokio::task_local! {
+    /// Sequence number of the request the current task answers.
+    static SEQUENCE: u64;
+}
+
+/// Runs `task` as the one answering request `seq`, and releases what it
+/// answered once it finishes.
+pub async fn answering<F, T>(order: Arc<ResponseOrder>, seq: u64, task: F) -> T
+where
+    F: Future<Output = T>,
+{
+    let done = Done {
+        order: order.clone(),
+        seq,
+    };
+    let result = SEQUENCE.scope(seq, task).await;
+    drop(done);
+    // Stdout closing is reported by the task's own writes.
+    let _ = order.release().await;
+    result
+}
+
+/// Marks a request answered even when its task panicked.
+struct Done {
+    order: Arc<ResponseOrder>,
+    seq: u64,
+}
+
+impl Drop for Done {
+    fn drop(&mut self) {
+        self.order.finish(self.seq);
+    }
+}
+
+#[derive(Debug, Default)]
+struct Slot {
+    lines: Vec<String>,
+    finished: bool,
+}
+
+#[derive(Debug, Default)]
+struct Buffer {
+    /// The next sequence number to hand out.
+    issued: u64,
+    /// Requests not yet fully released, by sequence number. The first is
+    /// the one whose answers go out as they come.
+    slots: BTreeMap<u64, Slot>,
+    held_bytes: usize,
+    /// The request that was stuck when ordering last gave way, until it
+    /// has been answered.
+    overflowed: Option<u64>,
+}
+
+impl Buffer {
+    /// Takes the lines that may be written now, in order.
+    fn take_ready(&mut self, limit: usize) -> Vec<String> {
+        let mut ready = Vec::new();
+        if self.held_bytes > limit {
+            if self.overflowed.is_none() {
+                warn!(
+                    limit,
+                    waiting = self.slots.len(),
+                    "ordered response buffer full, releasing answers out of order"
+                );
+                self.overflowed = self.slots.keys().next().copied();
+            }
+            for slot in self.slots.values_mut() {
+                ready.append(&mut slot.lines);
+            }
+            self.slots.retain(|_, slot| !slot.finished);
+            self.held_bytes = 0;
+            return ready;
+        }
+        while let Some(mut head) = self.slots.first_entry() {
+            let slot = head.get_mut();
+            self.held_bytes -= slot.lines.iter().map(String::len).sum::<usize>();
+            ready.append(&mut slot.lines);
+            if !slot.finished {
+                break;
+            }
+            head.remove();
+        }
+        if let Some(stuck) = self.overflowed {
+            if self.slots.keys().next().is_none_or(|&head| head > stuck) {
+                info!("ordered responses caught up");
+                self.overflowed = None;
+            }
+        }
+        ready
+    }
+}
+
+/// The reorder buffer between the bridge's answers and stdout.
+#[derive(Debug)]
+pub struct ResponseOrder {
+    buffer: Mutex<Buffer>,
+    /// Held while releasing, so that lines taken in order are also written
+    /// in order.
+    releasing: tokio::sync::Mutex<mpsc::Sender<String>>,
+    limit: usize,
+}
+
+impl ResponseOrder {
+    pub fn new(tx_out: mpsc::Sender<String>, limit: usize) -> Self {
+        Self {
+            buffer: Mutex::default(),
+            releasing: tokio::sync::Mutex::new(tx_out),
+            limit,
+        }
+    }
+
+    /// A sequence number for the request just read.
+    pub fn next(&self) -> u64 {
+        let mut buffer = self.buffer.lock().unwrap();
+        let seq = buffer.issued;
+        buffer.issued += 1;
+        buffer.slots.insert(seq, Slot::default());
+        seq
+    }
+
+    fn finish(&self, seq: u64) {
+        if let Some(slot) = self.buffer.lock().unwrap().slots.get_mut(&seq) {
+            slot.finished = true;
+        }
+    }
+
+    /// Writes `answer` once every earlier request has been answered. An
+    /// answer written outside any request's task, e.g. the bridge's own
+    /// error for a line it refused, counts as a request read just now.
+    pub async fn write(&self, answer: String) -> Result<(), mpsc::error::SendError<String>> {
+        let unheld = {
+            let mut buffer = self.buffer.lock().unwrap();
+            let seq = match SEQUENCE.try_with(|seq| *seq) {
+                Ok(seq) => seq,
+                Err(_) => {
+                    let seq = buffer.issued;
+                    buffer.issued += 1;
+                    let slot = Slot {
+                        finished: true,
+                        ..Slot::default()
+                    };
+                    buffer.slots.insert(seq, slot);
+                    seq
+                }
+            };
+            let len = answer.len();
+            match buffer.slots.get_mut(&seq) {
+                Some(slot) => {
+                    slot.lines.push(answer);
+                    buffer.held_bytes += len;
+                    None
+                }
+                None => Some(answer),
+            }
+        };
+        // The request's slot is gone, so nothing waits for this answer.
+        if let Some(answer) = unheld {
+            return self.releasing.lock().await.send(answer).await;
+        }
+        self.release().await
+    }
+
+    /// Writes whatever may go out now.
+    pub async fn release(&self) -> Result<(), mpsc::error::SendError<String>> {
+        let tx_out = self.releasing.lock().await;
+        loop {
+            let ready = self.buffer.lock().unwrap().take_ready(self.limit);
+            if ready.is_empty() {
+                return Ok(());
+            }
+            for line in ready {
+                tx_out.send(line).await?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answer(n: u64) -> String {
+        format!(r#"{{"jsonrpc":"2.0","id":0,"result":{{"n":{}}}}}"#, n)
+    }
+
+    fn drain(rx: &mut mpsc::Receiver<String>) -> Vec<String> {
+        std::iter::from_fn(|| rx.try_recv().ok()).collect()
+    }
+
+    #[tokio::test]
+    async fn answers_follow_request_order() {
+        let (tx, mut rx) = mpsc::channel(16);
+        let order = Arc::new(ResponseOrder::new(tx, BUFFER_LIMIT_BYTES));
+        let (first, second, third) = (order.next(), order.next(), order.next());
+        let reply = |seq: u64| {
+            let order = order.clone();
+            answering(order.clone(), seq, async move { order.write(answer(seq)).await })
+        };
+        reply(third).await.unwrap();
+        reply(second).await.unwrap();
+        assert!(drain(&mut rx).is_empty());
+        reply(first).await.unwrap();
+        assert_eq!(drain(&mut rx), [answer(0), answer(1), answer(2)]);
+    }
+
+    #[tokio::test]
+    async fn the_oldest_request_is_answered_at_once() {
+        let (tx, mut rx) = mpsc::channel(16);
+        let order = Arc::new(ResponseOrder::new(tx, BUFFER_LIMIT_BYTES));
+        let first = order.next();
+        let _second = order.next();
+        SEQUENCE.scope(first, order.write(answer(0))).await.unwrap();
+        assert_eq!(drain(&mut rx), [answer(0)]);
+        // A local answer queues behind the unfinished second request.
+        order.write(answer(9)).await.unwrap();
+        assert!(drain(&mut rx).is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_full_buffer_gives_way() {
+        let (tx, mut rx) = mpsc::channel(16);
+        let order = Arc::new(ResponseOrder::new(tx, answer(1).len() * 2));
+        let stuck = order.next();
+        for n in 1..=2 {
+            let seq = order.next();
+            answering(order.clone(), seq, order.write(answer(n))).await.unwrap();
+        }
+        assert!(drain(&mut rx).is_empty());
+        let seq = order.next();
+        answering(order.clone(), seq, order.write(answer(3))).await.unwrap();
+        assert_eq!(drain(&mut rx), [answer(1), answer(2), answer(3)]);
+        // The stuck request's answer goes out whenever it comes.
+        answering(order.clone(), stuck, order.write(answer(0))).await.unwrap();
+        assert_eq!(drain(&mut rx), [answer(0)]);
+    }
+}
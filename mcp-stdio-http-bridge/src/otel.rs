@@ -0,0 +1,441 @@
+//! W3C trace context on upstream requests (`MCP_TRACE_CONTEXT`) and a span
+//! for each client message, exported over OTLP/HTTP (`MCP_OTEL_EXPORTER`).
+//! A message whose `params._meta` carries a `traceparent` joins that trace;
+//! any other starts one of its own. Every attempt to send the message,
+//! retries and hedges included, carries the same context. Without an
+//! exporter the bridge's span is never recorded, so a `traceparent` from
+//! `_meta` goes upstream unchanged and the server's span hangs off the
+//! client's directly.
+
+use crate::config::Config;
+use crate::jsonrpc;
+use crate::remote_transport::TransportError;
+use reqwest::header::{HeaderMap, HeaderValue};
+use serde_json::{json, Value};
+use std::future::Future;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+pub const TRACESTATE_HEADER: &str = "tracestate";
+/// Spans sent in one export request, at most.
+const EXPORT_BATCH: usize = 64;
+/// How long a finished span waits for others to share its export request.
+const EXPORT_INTERVAL: Duration = Duration::from_secs(2);
+/// Finished spans waiting for export; more are dropped.
+const EXPORT_QUEUE: usize = 1024;
+/// Bounds each export request, and the last one at shutdown.
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(5);
+const SAMPLED: u8 = 0x01;
+/// OTLP `SPAN_KIND_CLIENT`.
+const KIND_CLIENT: u8 = 3;
+const STATUS_OK: u8 = 1;
+const STATUS_ERROR: u8 = 2;
+
+tokio::task_local! {
+    /// The span of the message the current task sends, if it has one.
+    static CURRENT: Option<Arc<MessageSpan>>;
+}
+
+/// A parsed `traceparent` header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceParent {
+    pub trace_id: u128,
+    pub span_id: u64,
+    pub flags: u8,
+}
+
+impl TraceParent {
+    /// Parses `00-<trace id>-<span id>-<flags>`. Later versions are read
+    /// the same way, as the spec asks, ignoring what follows the flags.
+    pub fn parse(header: &str) -> Option<Self> {
+        let mut parts = header.trim().split('-');
+        let version = parts.next()?;
+        let (trace_id, span_id, flags) = (parts.next()?, parts.next()?, parts.next()?);
+        let rest = parts.next();
+        if !is_hex(version, 2) || version == "ff" || (version == "00" && rest.is_some()) {
+            return None;
+        }
+        if !is_hex(trace_id, 32) || !is_hex(span_id, 16) || !is_hex(flags, 2) {
+            return None;
+        }
+        let parent = Self {
+            trace_id: u128::from_str_radix(trace_id, 16).ok()?,
+            span_id: u64::from_str_radix(span_id, 16).ok()?,
+            flags: u8::from_str_radix(flags, 16).ok()?,
+        };
+        (parent.trace_id != 0 && parent.span_id != 0).then_some(parent)
+    }
+
+    pub fn header(&self) -> String {
+        format!("00-{:032x}-{:016x}-{:02x}", self.trace_id, self.span_id, self.flags)
+    }
+}
+
+fn is_hex(s: &str, len: usize) -> bool {
+    s.len() == len && s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// The `traceparent` and `tracestate` in a message's `params._meta`.
+fn from_meta(msg: &str) -> Option<(TraceParent, Option<String>)> {
+    if !msg.contains(TRACEPARENT_HEADER) {
+        return None;
+    }
+    let value: Value = serde_json::from_str(msg).ok()?;
+    let meta = value.get("params")?.get("_meta")?;
+    let parent = TraceParent::parse(meta.get(TRACEPARENT_HEADER)?.as_str()?)?;
+    let state = meta
+        .get(TRACESTATE_HEADER)
+        .and_then(Value::as_str)
+        .map(str::to_string);
+    Some((parent, state))
+}
+
+/// The bridge's span for one client message.
+#[derive(Debug)]
+pub struct MessageSpan {
+    name: String,
+    context: TraceParent,
+    parent: Option<TraceParent>,
+    state: Option<String>,
+    exported: bool,
+    started: SystemTime,
+    attempts: AtomicU32,
+    /// The last attempt's upstream status, and its error if it failed.
+    outcome: Mutex<(Option<u16>, Option<String>)>,
+}
+
+impl MessageSpan {
+    /// The `traceparent` sent upstream.
+    fn traceparent(&self) -> String {
+        match self.parent.filter(|_| !self.exported) {
+            Some(parent) => parent.header(),
+            None => self.context.header(),
+        }
+    }
+}
+
+/// Starts spans for client messages and hands finished ones to the
+/// exporter.
+#[derive(Debug)]
+pub struct Tracer {
+    service: String,
+    /// Dropped at shutdown, which ends the export task.
+    exporter: Mutex<Option<mpsc::Sender<Value>>>,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Tracer {
+    /// A tracer when `MCP_TRACE_CONTEXT` or `MCP_OTEL_EXPORTER` asks for
+    /// one. The export task starts here.
+    pub fn new(config: &Config) -> Option<Self> {
+        if !config.trace_context && config.otel_exporter.is_none() {
+            return None;
+        }
+        let service = config
+            .mcp_name
+            .clone()
+            .unwrap_or_else(|| env!("CARGO_PKG_NAME").to_string());
+        let (exporter, task) = match &config.otel_exporter {
+            Some(endpoint) => {
+                let (tx, rx) = mpsc::channel(EXPORT_QUEUE);
+                let task = tokio::spawn(export(traces_url(endpoint), service.clone(), rx));
+                (Some(tx), Some(task))
+            }
+            None => (None, None),
+        };
+        Some(Self {
+            service,
+            exporter: Mutex::new(exporter),
+            task: Mutex::new(task),
+        })
+    }
+
+    /// A span for `msg`, under the trace its `_meta` names if any.
+    pub fn start(&self, msg: &str) -> Arc<MessageSpan> {
+        let exported = self.exporter.lock().unwrap().is_some();
+        let (parent, state) = match from_meta(msg) {
+            Some((parent, state)) => (Some(parent), state),
+            None => (None, None),
+        };
+        let context = TraceParent {
+            trace_id: parent.map_or_else(|| fastrand::u128(1..), |p| p.trace_id),
+            span_id: fastrand::u64(1..),
+            flags: parent.map_or(if exported { SAMPLED } else { 0 }, |p| p.flags),
+        };
+        Arc::new(MessageSpan {
+            name: jsonrpc::method(msg).unwrap_or_else(|| "message".to_string()),
+            context,
+            parent,
+            state,
+            exported,
+            started: SystemTime::now(),
+            attempts: AtomicU32::new(0),
+            outcome: Mutex::new((None, None)),
+        })
+    }
+
+    /// Queues `span` for export, if spans are exported and it was sampled.
+    pub fn finish(&self, span: &MessageSpan) {
+        let exporter = self.exporter.lock().unwrap();
+        let Some(exporter) = exporter.as_ref() else {
+            return;
+        };
+        if span.context.flags & SAMPLED == 0 {
+            return;
+        }
+        if exporter.try_send(span_json(span, SystemTime::now())).is_err() {
+            debug!("span export queue full, dropping span");
+        }
+    }
+
+    /// Exports the spans still queued, waiting at most `EXPORT_TIMEOUT`.
+    pub async fn shutdown(&self) {
+        drop(self.exporter.lock().unwrap().take());
+        let task = self.task.lock().unwrap().take();
+        if let Some(task) = task {
+            if tokio::time::timeout(EXPORT_TIMEOUT, task).await.is_err() {
+                warn!(service = %self.service, "gave up exporting the last spans");
+            }
+        }
+    }
+}
+
+/// Runs `task` as the sender of the message `span` belongs to. With `None`
+/// it runs outside any message's span, as a message the bridge sends on its
+/// own while handling a client's does.
+pub async fn within<F: Future>(span: Option<Arc<MessageSpan>>, task: F) -> F::Output {
+    CURRENT.scope(span, task).await
+}
+
+/// Calls `f` with the current message's span, if there is one.
+fn with_current<R>(f: impl FnOnce(&MessageSpan) -> R) -> Option<R> {
+    CURRENT.try_with(|span| span.as_deref().map(f)).ok().flatten()
+}
+
+/// Trace context headers for the current message's next attempt.
+pub fn headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    with_current(|span| {
+        if let Ok(value) = HeaderValue::from_str(&span.traceparent()) {
+            headers.insert(TRACEPARENT_HEADER, value);
+        }
+        if let Some(value) = span.state.as_deref().and_then(|s| HeaderValue::from_str(s).ok()) {
+            headers.insert(TRACESTATE_HEADER, value);
+        }
+    });
+    headers
+}
+
+/// The current message's trace id, for its log lines.
+pub fn trace_id() -> Option<String> {
+    with_current(|span| format!("{:032x}", span.context.trace_id))
+}
+
+/// Records how an attempt to send the current message went.
+pub fn note_attempt(status: Option<u16>, error: Option<&TransportError>) {
+    with_current(|span| {
+        span.attempts.fetch_add(1, Ordering::Relaxed);
+        *span.outcome.lock().unwrap() = (status, error.map(ToString::to_string));
+    });
+}
+
+/// Where OTLP/HTTP takes traces: `/v1/traces` under `endpoint`, unless it
+/// already names that path.
+fn traces_url(endpoint: &str) -> String {
+    let endpoint = endpoint.trim_end_matches('/');
+    match endpoint.ends_with("/v1/traces") {
+        true => endpoint.to_string(),
+        false => format!("{}/v1/traces", endpoint),
+    }
+}
+
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
+
+fn attribute(key: &str, value: Value) -> Value {
+    let value = match value {
+        Value::Number(n) => json!({ "intValue": n.to_string() }),
+        other => json!({ "stringValue": other.as_str().unwrap_or_default() }),
+    };
+    json!({ "key": key, "value": value })
+}
+
+/// `span` in the OTLP JSON encoding.
+fn span_json(span: &MessageSpan, ended: SystemTime) -> Value {
+    let attempts = span.attempts.load(Ordering::Relaxed);
+    let (status, error) = span.outcome.lock().unwrap().clone();
+    let mut attributes = vec![
+        attribute("rpc.system", json!("jsonrpc")),
+        attribute("rpc.method", json!(span.name)),
+        attribute("mcp.bridge.retries", json!(attempts.saturating_sub(1))),
+    ];
+    if let Some(status) = status {
+        attributes.push(attribute("http.response.status_code", json!(status)));
+    }
+    let failed = error.is_some() || status.is_some_and(|s| s >= 400);
+    let mut out = json!({
+        "traceId": format!("{:032x}", span.context.trace_id),
+        "spanId": format!("{:016x}", span.context.span_id),
+        "name": span.name,
+        "kind": KIND_CLIENT,
+        "startTimeUnixNano": unix_nanos(span.started),
+        "endTimeUnixNano": unix_nanos(ended),
+        "attributes": attributes,
+        "status": match &error {
+            Some(error) => json!({ "code": STATUS_ERROR, "message": error }),
+            None if failed => json!({ "code": STATUS_ERROR }),
+            None => json!({ "code": STATUS_OK }),
+        },
+    });
+    if let Some(parent) = span.parent {
+        out["parentSpanId"] = json!(format!("{:016x}", parent.span_id));
+    }
+    if let Some(state) = &span.state {
+        out["traceState"] = json!(state);
+    }
+    out
+}
+
+/// Posts spans from `rx` to `url` in batches, until `rx` closes.
+async fn export(url: String, service: String, mut rx: mpsc::Receiver<Value>) {
+    let client = match reqwest::Client::builder().timeout(EXPORT_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => {
+            warn!(%e, "cannot build the span exporter's client, spans are not exported");
+            return;
+        }
+    };
+    let mut open = true;
+    while open {
+        let Some(first) = rx.recv().await else {
+            break;
+        };
+        let mut batch = vec![first];
+        let flush = tokio::time::sleep(EXPORT_INTERVAL);
+        tokio::pin!(flush);
+        while batch.len() < EXPORT_BATCH {
+            tokio::select! {
+                span = rx.recv() => match span {
+                    Some(span) => batch.push(span),
+                    None => {
+                        open = false;
+                        break;
+                    }
+                },
+                _ = &mut flush => break,
+            }
+        }
+        let spans = batch.len();
+        let body = json!({
+            "resourceSpans": [{
+                "resource": { "attributes": [attribute("service.name", json!(service))] },
+                "scopeSpans": [{
+                    "scope": { "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") },
+                    "spans": batch,
+                }],
+            }],
+        });
+        match client.post(&url).json(&body).send().await {
+            Ok(res) if res.status().is_success() => debug!(spans, "exported spans"),
+            Ok(res) => warn!(spans, status = res.status().as_u16(), "span export refused"),
+            Err(e) => warn!(spans, %e, "span export failed"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    fn tracer(vars: &[(&str, &str)]) -> Tracer {
+        let config = Config::from_lookup(|name| {
+            vars.iter()
+                .find(|(k, _)| *k == name)
+                .map(|(_, v)| v.to_string())
+        });
+        Tracer::new(&config).unwrap()
+    }
+
+    #[test]
+    fn traceparents_parse() {
+        let parent = TraceParent::parse(PARENT).unwrap();
+        assert_eq!(parent.span_id, 0x00f067aa0ba902b7);
+        assert_eq!(parent.header(), PARENT);
+        assert!(TraceParent::parse("01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-x").is_some());
+        for bad in [
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-x",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+        ] {
+            assert_eq!(TraceParent::parse(bad), None, "{}", bad);
+        }
+    }
+
+    #[tokio::test]
+    async fn a_traceparent_in_meta_is_passed_on() {
+        let tracer = tracer(&[("MCP_TRACE_CONTEXT", "1")]);
+        let msg = format!(
+            r#"{{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{{"_meta":{{"traceparent":"{}","tracestate":"k=v"}}}}}}"#,
+            PARENT
+        );
+        let headers = within(Some(tracer.start(&msg)), async { headers() }).await;
+        assert_eq!(headers[TRACEPARENT_HEADER], PARENT);
+        assert_eq!(headers[TRACESTATE_HEADER], "k=v");
+    }
+
+    #[tokio::test]
+    async fn an_exported_span_is_a_child_of_the_meta_one() {
+        let tracer = tracer(&[("MCP_OTEL_EXPORTER", "http://127.0.0.1:9/")]);
+        let msg = format!(
+            r#"{{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{{"_meta":{{"traceparent":"{}"}}}}}}"#,
+            PARENT
+        );
+        let span = tracer.start(&msg);
+        let sent = within(Some(span.clone()), async {
+            note_attempt(Some(503), None);
+            note_attempt(Some(200), None);
+            headers()
+        })
+        .await;
+        let sent = TraceParent::parse(sent[TRACEPARENT_HEADER].to_str().unwrap()).unwrap();
+        assert_eq!(sent.trace_id, 0x4bf92f3577b34da6a3ce929d0e0e4736);
+        assert_ne!(sent.span_id, 0x00f067aa0ba902b7);
+        let json = span_json(&span, SystemTime::now());
+        assert_eq!(json["parentSpanId"], "00f067aa0ba902b7");
+        assert_eq!(json["spanId"], format!("{:016x}", sent.span_id));
+        assert_eq!(json["name"], "tools/call");
+        assert_eq!(json["status"]["code"], STATUS_OK);
+        let attributes = json["attributes"].as_array().unwrap();
+        assert!(attributes.contains(&attribute("mcp.bridge.retries", json!(1))));
+        assert!(attributes.contains(&attribute("http.response.status_code", json!(200))));
+    }
+
+    #[tokio::test]
+    async fn a_message_without_context_starts_a_trace() {
+        let tracer = tracer(&[("MCP_TRACE_CONTEXT", "1")]);
+        let span = tracer.start(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#);
+        let sent = within(Some(span), async { headers() }).await;
+        let sent = TraceParent::parse(sent[TRACEPARENT_HEADER].to_str().unwrap()).unwrap();
+        assert_eq!(sent.flags, 0);
+        assert!(headers().is_empty());
+    }
+
+    #[test]
+    fn exports_go_to_the_traces_path() {
+        assert_eq!(traces_url("http://otel:4318/"), "http://otel:4318/v1/traces");
+        assert_eq!(traces_url("http://otel:4318/v1/traces"), "http://otel:4318/v1/traces");
+    }
+}
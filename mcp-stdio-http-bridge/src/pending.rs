@@ -0,0 +1,212 @@
+//! Client requests waiting for their response, by JSON-RPC id, so one left
+//! unanswered past `MCP_REQUEST_DEADLINE_MS` is given up on rather than
+//! retried for as long as the upstream keeps failing, and one the client
+//! cancels stops occupying a connection.
+
+use crate::jsonrpc;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+/// `MCP_REQUEST_DEADLINE_MS` when unset, in attempt timeouts.
+pub const DEFAULT_DEADLINE_ATTEMPTS: u32 = 5;
+
+/// One client message waiting for its response. A batch counts as answered
+/// once any of its requests is, since the upstream answers a batch at once.
+#[derive(Debug)]
+pub struct Waiter {
+    keys: Vec<String>,
+    answered: AtomicBool,
+    heard: Mutex<Instant>,
+    cancelled: CancellationToken,
+}
+
+impl Waiter {
+    pub fn is_answered(&self) -> bool {
+        self.answered.load(Ordering::Relaxed)
+    }
+
+    /// Records that the upstream sent something for this request, such as
+    /// a progress notification, which restarts the deadline.
+    pub fn heard(&self) {
+        *self.heard.lock().unwrap() = Instant::now();
+    }
+
+    /// Completes once the client has cancelled the request.
+    pub async fn cancelled(&self) {
+        self.cancelled.cancelled().await
+    }
+
+    /// Completes once `limit` has passed since the request was tracked or
+    /// last heard of; never, once it is answered.
+    pub async fn unanswered_for(&self, limit: Duration) {
+        loop {
+            let due = *self.heard.lock().unwrap() + limit;
+            tokio::time::sleep_until(due).await;
+            if self.is_answered() {
+                std::future::pending::<()>().await;
+            }
+            if self.heard.lock().unwrap().elapsed() >= limit {
+                return;
+            }
+        }
+    }
+}
+
+/// Client requests that have gone upstream and await a response, by their
+/// id as sent upstream. Entries are added and removed by the task sending
+/// the request, so the table never holds more than the requests in flight.
+/// Any relayed response marks its request answered, including one that
+/// arrives on another request's SSE stream.
+#[derive(Debug, Default)]
+pub struct PendingRequests {
+    entries: Mutex<HashMap<String, Arc<Waiter>>>,
+}
+
+impl PendingRequests {
+    /// Starts waiting for the requests in `msg`; `None` when it holds only
+    /// notifications and responses.
+    pub fn track(&self, msg: &str) -> Option<Arc<Waiter>> {
+        let keys: Vec<String> = jsonrpc::request_ids(msg).iter().map(Value::to_string).collect();
+        if keys.is_empty() {
+            return None;
+        }
+        let waiter = Arc::new(Waiter {
+            keys,
+            answered: AtomicBool::new(false),
+            heard: Mutex::new(Instant::now()),
+            cancelled: CancellationToken::new(),
+        });
+        let mut entries = self.entries.lock().unwrap();
+        for key in &waiter.keys {
+            entries.insert(key.clone(), waiter.clone());
+        }
+        Some(waiter)
+    }
+
+    /// Marks the requests `line`, an upstream message, responds to.
+    pub fn answered(&self, line: &str) {
+        let ids = jsonrpc::answered_ids(line);
+        if ids.is_empty() {
+            return;
+        }
+        let entries = self.entries.lock().unwrap();
+        for id in ids {
+            if let Some(waiter) = entries.get(&id.to_string()) {
+                waiter.answered.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Cancels the request sent upstream under `id`, and with it the rest
+    /// of its batch; false when it is not in flight.
+    pub fn cancel(&self, id: &Value) -> bool {
+        match self.entries.lock().unwrap().get(&id.to_string()) {
+            Some(waiter) => {
+                waiter.cancelled.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stops waiting for `waiter`'s requests. A later request that reused
+    /// one of the ids keeps its own entry.
+    pub fn untrack(&self, waiter: &Arc<Waiter>) {
+        let mut entries = self.entries.lock().unwrap();
+        for key in &waiter.keys {
+            if entries.get(key).is_some_and(|w| Arc::ptr_eq(w, waiter)) {
+                entries.remove(key);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn responses_mark_their_request_in_any_order() {
+        let pending = PendingRequests::default();
+        let a = pending.track(r#"{"jsonrpc":"2.0","id":1,"method":"a"}"#).unwrap();
+        let b = pending.track(r#"{"jsonrpc":"2.0","id":"1","method":"b"}"#).unwrap();
+        assert!(pending.track(r#"{"jsonrpc":"2.0","method":"n"}"#).is_none());
+        pending.answered(r#"{"jsonrpc":"2.0","id":"1","result":{}}"#);
+        assert!(b.is_answered());
+        assert!(!a.is_answered());
+        // A server request reusing the id answers nothing.
+        pending.answered(r#"{"jsonrpc":"2.0","id":1,"method":"sampling/createMessage"}"#);
+        assert!(!a.is_answered());
+        pending.answered(r#"[{"jsonrpc":"2.0","id":1,"error":{"code":1,"message":"x"}}]"#);
+        assert!(a.is_answered());
+        pending.untrack(&a);
+        pending.untrack(&b);
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn untrack_leaves_a_reused_id_alone() {
+        let pending = PendingRequests::default();
+        let msg = r#"{"jsonrpc":"2.0","id":7,"method":"a"}"#;
+        let first = pending.track(msg).unwrap();
+        let second = pending.track(msg).unwrap();
+        pending.untrack(&first);
+        assert_eq!(pending.len(), 1);
+        pending.answered(r#"{"jsonrpc":"2.0","id":7,"result":{}}"#);
+        assert!(second.is_answered());
+        pending.untrack(&second);
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn batch_is_answered_by_any_of_its_responses() {
+        let pending = PendingRequests::default();
+        let batch = r#"[{"jsonrpc":"2.0","id":1,"method":"a"},{"jsonrpc":"2.0","id":2,"method":"b"}]"#;
+        let waiter = pending.track(batch).unwrap();
+        assert_eq!(pending.len(), 2);
+        pending.answered(r#"{"jsonrpc":"2.0","id":2,"result":{}}"#);
+        assert!(waiter.is_answered());
+        pending.untrack(&waiter);
+        assert!(pending.is_empty());
+    }
+
+    #[tokio::test]
+    async fn cancel_reaches_only_the_request_in_flight() {
+        let pending = PendingRequests::default();
+        let waiter = pending.track(r#"{"jsonrpc":"2.0","id":3,"method":"a"}"#).unwrap();
+        assert!(!pending.cancel(&Value::from("3")));
+        assert!(pending.cancel(&Value::from(3)));
+        waiter.cancelled().await;
+        pending.untrack(&waiter);
+        assert!(!pending.cancel(&Value::from(3)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn deadline_restarts_when_heard_and_stops_when_answered() {
+        let pending = PendingRequests::default();
+        let waiter = pending.track(r#"{"jsonrpc":"2.0","id":1,"method":"a"}"#).unwrap();
+        let limit = Duration::from_secs(10);
+        let started = Instant::now();
+        tokio::time::sleep(Duration::from_secs(6)).await;
+        waiter.heard();
+        waiter.unanswered_for(limit).await;
+        assert!(started.elapsed() >= Duration::from_secs(16));
+
+        pending.answered(r#"{"jsonrpc":"2.0","id":1,"result":{}}"#);
+        let wait = tokio::time::timeout(limit * 3, waiter.unanswered_for(limit));
+        assert!(wait.await.is_err());
+    }
+}
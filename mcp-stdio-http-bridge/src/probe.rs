@@ -0,0 +1,97 @@
+use crate::auth::AuthProvider;
+use crate::config::Config;
+use crate::health;
+use crate::remote_transport::{send_message, Client, TransportError};
+use crate::request_body::RequestBody;
+use crate::session::Session;
+use crate::stats::Stats;
+use serde_json::json;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Exit status when `MCP_STARTUP_PROBE=required` could not reach the
+/// upstream, so supervisors can tell it apart from a config error.
+pub const PROBE_FAILED_CODE: i32 = 4;
+const PROBE_ID: &str = "bridge-startup-probe";
+
+/// Whether the upstream is tried once before stdin is read
+/// (`MCP_STARTUP_PROBE`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StartupProbe {
+    #[default]
+    Off,
+    /// Probe and log the outcome; start either way.
+    On,
+    /// Probe and exit with `PROBE_FAILED_CODE` when it fails.
+    Required,
+}
+
+impl FromStr for StartupProbe {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "0" | "false" | "off" => Ok(Self::Off),
+            "1" | "true" | "on" => Ok(Self::On),
+            "required" => Ok(Self::Required),
+            other => Err(format!("unknown startup probe mode {:?}", other)),
+        }
+    }
+}
+
+impl StartupProbe {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::On => "true",
+            Self::Required => "required",
+        }
+    }
+}
+
+/// Sends one `ping` outside any session and returns how long the upstream
+/// took to answer it. A `400` counts as reachable: servers that want
+/// `initialize` first refuse a sessionless ping that way, and the endpoint
+/// is evidently there.
+pub async fn run(
+    config: &Config,
+    client: &Client,
+    auth: &AuthProvider,
+    stats: &Stats,
+) -> Result<Duration, TransportError> {
+    let body: RequestBody = json!({
+        "jsonrpc": "2.0",
+        "id": PROBE_ID,
+        "method": health::KEEPALIVE_METHOD,
+    })
+    .to_string()
+    .into();
+    let session = Session::new(&config.uri);
+    let started = Instant::now();
+    let sent = async {
+        let response =
+            send_message(config, client, auth, &session, &body, config.timeout, stats).await?;
+        response.collect().await
+    };
+    match sent.await {
+        Ok(_) | Err(TransportError::Status(400, _)) => Ok(started.elapsed()),
+        // The caller logs the URI redacted; reqwest's message would repeat
+        // it in full.
+        Err(TransportError::Network(e)) => Err(TransportError::Network(e.without_url())),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modes_parse() {
+        assert_eq!("true".parse(), Ok(StartupProbe::On));
+        assert_eq!("1".parse(), Ok(StartupProbe::On));
+        assert_eq!("Required".parse(), Ok(StartupProbe::Required));
+        assert_eq!("false".parse(), Ok(StartupProbe::Off));
+        assert!("strict".parse::<StartupProbe>().is_err());
+    }
+}
@@ -0,0 +1,77 @@
+use crate::jsonrpc;
+use serde_json::{json, Value};
+
+/// Env prefix of options that work around misbehaving clients or servers
+/// rather than configure the bridge; the config schema marks them.
+pub const ENV_PREFIX: &str = "MCP_QUIRK_";
+pub const INITIALIZED_METHOD: &str = "notifications/initialized";
+
+/// `MCP_QUIRK_ACK_INITIALIZED`: some clients block after sending
+/// `notifications/initialized` until any line arrives on stdout, which never
+/// happens when the upstream answers it with 202. Once per session, after
+/// the initialize response reached the client, the forwarded notification
+/// is followed by a debug-level `notifications/message` to unblock them.
+#[derive(Debug, Default)]
+pub struct InitializedAck {
+    armed: bool,
+}
+
+impl InitializedAck {
+    /// Arms the acknowledgement when `line`, written to the client for an
+    /// `initialize` request, is a successful response: a new session starts.
+    pub fn on_initialize_response(&mut self, line: &str) {
+        let value: Option<Value> = serde_json::from_str(line).ok();
+        if value.is_some_and(|v| v.get("result").is_some()) {
+            self.armed = true;
+        }
+    }
+
+    /// Called after `notifications/initialized` was forwarded; returns the
+    /// line for the client the first time in a session.
+    pub fn on_initialized(&mut self) -> Option<String> {
+        if !std::mem::take(&mut self.armed) {
+            return None;
+        }
+        Some(
+            json!({
+                "jsonrpc": "2.0",
+                "method": jsonrpc::LOG_MESSAGE_METHOD,
+                "params": {
+                    "level": "debug",
+                    "logger": "bridge",
+                    "data": "initialized notification forwarded",
+                }
+            })
+            .to_string(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OK: &str = r#"{"jsonrpc":"2.0","id":1,"result":{"protocolVersion":"2025-03-26"}}"#;
+
+    #[test]
+    fn acknowledges_once_per_successful_initialize() {
+        let mut ack = InitializedAck::default();
+        assert_eq!(ack.on_initialized(), None);
+        ack.on_initialize_response(OK);
+        let line: Value = serde_json::from_str(&ack.on_initialized().unwrap()).unwrap();
+        assert_eq!(line["method"], "notifications/message");
+        assert_eq!(line["params"]["level"], "debug");
+        assert_eq!(line["params"]["logger"], "bridge");
+        assert_eq!(ack.on_initialized(), None);
+        ack.on_initialize_response(OK);
+        assert!(ack.on_initialized().is_some());
+    }
+
+    #[test]
+    fn failed_initialize_does_not_arm() {
+        let mut ack = InitializedAck::default();
+        ack.on_initialize_response(r#"{"jsonrpc":"2.0","id":1,"error":{"code":-1,"message":"x"}}"#);
+        ack.on_initialize_response("not json");
+        assert_eq!(ack.on_initialized(), None);
+    }
+}
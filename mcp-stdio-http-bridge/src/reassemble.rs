@@ -0,0 +1,161 @@
+use crate::jsonrpc;
+use serde::de::IgnoredAny;
+
+/// Most stdin lines one message may be split across.
+pub const MAX_FRAGMENT_LINES: usize = 8;
+/// Most bytes held while waiting for the rest of a message.
+pub const MAX_FRAGMENT_BYTES: usize = 1024 * 1024;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Assembled {
+    /// A complete message, standalone or joined from fragments.
+    Message(String),
+    /// Fragments that never formed valid JSON within the bounds.
+    Unparseable { lines: usize },
+}
+
+/// Joins JSON-RPC messages that broken clients split across stdin lines.
+/// A line that does not parse is held and later lines are appended to it
+/// until the result parses or a bound is hit. Lines that parse on their own
+/// are never held back.
+#[derive(Debug)]
+pub struct Reassembler {
+    pending: Vec<String>,
+    bytes: usize,
+    max_lines: usize,
+    max_bytes: usize,
+}
+
+impl Default for Reassembler {
+    fn default() -> Self {
+        Self::new(MAX_FRAGMENT_LINES, MAX_FRAGMENT_BYTES)
+    }
+}
+
+impl Reassembler {
+    pub fn new(max_lines: usize, max_bytes: usize) -> Self {
+        Self {
+            pending: Vec::new(),
+            bytes: 0,
+            max_lines,
+            max_bytes,
+        }
+    }
+
+    pub fn push(&mut self, line: String) -> Vec<Assembled> {
+        if self.pending.is_empty() {
+            if parses(&line) {
+                return vec![Assembled::Message(line)];
+            }
+        } else {
+            let joined = self.pending.concat() + &line;
+            if parses(&joined) {
+                self.pending.clear();
+                self.bytes = 0;
+                return vec![Assembled::Message(joined)];
+            }
+            if parses(&line) {
+                return vec![Assembled::Message(line)];
+            }
+        }
+        self.bytes += line.len();
+        self.pending.push(line);
+        if self.pending.len() >= self.max_lines || self.bytes > self.max_bytes {
+            return self.finish().into_iter().collect();
+        }
+        Vec::new()
+    }
+
+    /// Gives up on any held fragment, e.g. at EOF.
+    pub fn finish(&mut self) -> Option<Assembled> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        let lines = self.pending.len();
+        self.pending.clear();
+        self.bytes = 0;
+        Some(Assembled::Unparseable { lines })
+    }
+}
+
+/// Whether `s` is complete JSON. Messages too deep to parse count as
+/// complete so the depth limit, not reassembly, deals with them.
+fn parses(s: &str) -> bool {
+    jsonrpc::too_deep(s, jsonrpc::MAX_JSON_DEPTH) || serde_json::from_str::<IgnoredAny>(s).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MSG: &str = r#"{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{"s":"a b","n":[1,2.5,true,null]}}"#;
+    const PING: &str = r#"{"jsonrpc":"2.0","id":2,"method":"ping"}"#;
+
+    #[test]
+    fn valid_lines_pass_straight_through() {
+        let mut r = Reassembler::default();
+        assert_eq!(r.push(PING.into()), vec![Assembled::Message(PING.into())]);
+        assert_eq!(r.finish(), None);
+    }
+
+    #[test]
+    fn split_at_every_boundary_is_rejoined() {
+        for i in 1..MSG.len() {
+            let mut r = Reassembler::default();
+            assert_eq!(r.push(MSG[..i].into()), vec![], "split at {}", i);
+            assert_eq!(
+                r.push(MSG[i..].into()),
+                vec![Assembled::Message(MSG.into())],
+                "split at {}",
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn three_way_split_is_rejoined() {
+        let mut r = Reassembler::default();
+        assert!(r.push(MSG[..10].into()).is_empty());
+        assert!(r.push(MSG[10..40].into()).is_empty());
+        assert_eq!(
+            r.push(MSG[40..].into()),
+            vec![Assembled::Message(MSG.into())]
+        );
+    }
+
+    #[test]
+    fn interleaved_valid_message_is_not_delayed() {
+        let mut r = Reassembler::default();
+        assert!(r.push(MSG[..20].into()).is_empty());
+        assert_eq!(r.push(PING.into()), vec![Assembled::Message(PING.into())]);
+        assert_eq!(
+            r.push(MSG[20..].into()),
+            vec![Assembled::Message(MSG.into())]
+        );
+    }
+
+    #[test]
+    fn bounds_give_one_error_for_the_fragment() {
+        let mut r = Reassembler::new(3, 1024);
+        assert!(r.push("{".into()).is_empty());
+        assert!(r.push("nope".into()).is_empty());
+        assert_eq!(
+            r.push("still not".into()),
+            vec![Assembled::Unparseable { lines: 3 }]
+        );
+        assert_eq!(r.push(PING.into()), vec![Assembled::Message(PING.into())]);
+
+        let mut r = Reassembler::new(8, 16);
+        assert_eq!(
+            r.push("{\"x\":\"0123456789abcdef".into()),
+            vec![Assembled::Unparseable { lines: 1 }]
+        );
+    }
+
+    #[test]
+    fn eof_flushes_held_fragment() {
+        let mut r = Reassembler::default();
+        assert!(r.push(MSG[..5].into()).is_empty());
+        assert_eq!(r.finish(), Some(Assembled::Unparseable { lines: 1 }));
+    }
+}
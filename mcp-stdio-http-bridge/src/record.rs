@@ -0,0 +1,326 @@
+//! Traffic recording (`MCP_RECORD_FILE`) and its replay (`--replay`), for
+//! reproducing a protocol bug from a user's session. Every stdin line,
+//! upstream request, upstream response line or error, and stdout line is
+//! appended to the file as one JSON object with a `ts_ms` timestamp and a
+//! `kind`. Request headers are never recorded, so neither is the
+//! `Authorization` one, and endpoints have their credentials and query
+//! redacted; message content is recorded as it was sent, since a replay
+//! needs it byte for byte.
+//!
+//! `--replay session.jsonl` feeds the recorded stdin lines through the
+//! bridge at their original timing, or faster with `--replay-speed`.
+
+use crate::config::redact_uri;
+use serde_json::{json, Map, Value};
+use std::fmt::Display;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tracing::{debug, error, info};
+
+/// First argument that replays a recording instead of reading stdin.
+pub const REPLAY_ARG: &str = "--replay";
+/// Divides the recorded gaps between stdin lines; `0` sends them all at once.
+pub const REPLAY_SPEED_ARG: &str = "--replay-speed";
+
+/// Appends traffic to `MCP_RECORD_FILE`. A failed write never holds up a
+/// message: it is logged and recording stops.
+#[derive(Debug)]
+pub struct Recorder {
+    path: PathBuf,
+    file: Mutex<Option<File>>,
+}
+
+impl Recorder {
+    /// Opens `path` for appending; `None`, once the reason is logged, when
+    /// it cannot be.
+    pub fn open(path: &Path) -> Option<Arc<Self>> {
+        match OpenOptions::new().create(true).append(true).open(path) {
+            Ok(file) => {
+                info!(path = %path.display(), "recording traffic (MCP_RECORD_FILE)");
+                Some(Arc::new(Self {
+                    path: path.to_path_buf(),
+                    file: Mutex::new(Some(file)),
+                }))
+            }
+            Err(e) => {
+                error!(%e, path = %path.display(), "could not open MCP_RECORD_FILE, not recording");
+                None
+            }
+        }
+    }
+
+    /// A line read from the client.
+    pub fn stdin(&self, line: &str) {
+        self.write("stdin", json!({ "line": line }));
+    }
+
+    /// A request sent upstream; `body` is `None` for the GET stream.
+    pub fn request(&self, method: &str, endpoint: &str, body: Option<&str>) {
+        let mut fields = json!({ "method": method, "endpoint": redact_uri(endpoint) });
+        if let Some(body) = body {
+            fields["body"] = Value::from(body);
+        }
+        self.write("request", fields);
+    }
+
+    /// A message the upstream answered with.
+    pub fn response(&self, line: &str) {
+        self.write("response", json!({ "line": line }));
+    }
+
+    /// An upstream exchange that failed instead of answering.
+    pub fn error(&self, error: &dyn Display) {
+        self.write("error", json!({ "error": error.to_string() }));
+    }
+
+    /// A line written to the client.
+    pub fn stdout(&self, line: &str) {
+        self.write("stdout", json!({ "line": line }));
+    }
+
+    fn write(&self, kind: &str, fields: Value) {
+        let mut entry = Map::new();
+        entry.insert("ts_ms".into(), Value::from(unix_ms()));
+        entry.insert("kind".into(), Value::from(kind));
+        if let Value::Object(fields) = fields {
+            entry.extend(fields);
+        }
+        let line = format!("{}\n", Value::Object(entry));
+        let mut file = self.file.lock().unwrap();
+        let Some(f) = file.as_mut() else {
+            return;
+        };
+        if let Err(e) = f.write_all(line.as_bytes()) {
+            error!(%e, path = %self.path.display(), "could not write MCP_RECORD_FILE, recording stopped");
+            *file = None;
+        }
+    }
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+/// What `--replay` asked for.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replay {
+    pub path: PathBuf,
+    /// `1` keeps the recorded timing, `10` is ten times faster and `0`
+    /// does not wait at all.
+    pub speed: f64,
+}
+
+/// Recognises `--replay FILE [--replay-speed N]` at the start of `args`
+/// (program name excluded), and returns it with the arguments left for the
+/// config.
+pub fn parse_args(args: &[String]) -> Option<Result<(Replay, Vec<String>), String>> {
+    let mut rest = args.iter();
+    let path = flag_value(REPLAY_ARG, &mut rest)?;
+    Some(parse_rest(path, rest))
+}
+
+fn parse_rest(
+    path: Option<String>,
+    mut rest: std::slice::Iter<'_, String>,
+) -> Result<(Replay, Vec<String>), String> {
+    let path = path.ok_or_else(|| format!("{} needs a recording file", REPLAY_ARG))?;
+    let mut speed = 1.0;
+    let mut left = Vec::new();
+    while rest.len() > 0 {
+        let mut peek = rest.clone();
+        match flag_value(REPLAY_SPEED_ARG, &mut peek) {
+            Some(value) => {
+                let value = value.unwrap_or_default();
+                speed = value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|s| s.is_finite() && *s >= 0.0)
+                    .ok_or_else(|| {
+                        format!("{} must be a number of at least 0, not {:?}", REPLAY_SPEED_ARG, value)
+                    })?;
+                rest = peek;
+            }
+            None => left.extend(rest.next().cloned()),
+        }
+    }
+    let replay = Replay {
+        path: PathBuf::from(path),
+        speed,
+    };
+    Ok((replay, left))
+}
+
+/// The value of `flag` given as `flag value` or `flag=value`, when the next
+/// argument is that flag; `Some(None)` when it has no value.
+fn flag_value(flag: &str, args: &mut std::slice::Iter<'_, String>) -> Option<Option<String>> {
+    let arg = args.as_slice().first()?;
+    if arg == flag {
+        args.next();
+        return Some(args.next().cloned());
+    }
+    let value = arg.strip_prefix(flag)?.strip_prefix('=')?.to_string();
+    args.next();
+    Some(Some(value))
+}
+
+/// A recorded stdin line and when it was read, after the first one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Recorded {
+    pub at: Duration,
+    pub line: String,
+}
+
+/// The stdin lines of a recording, in order.
+pub fn load(path: &Path) -> Result<Vec<Recorded>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("could not read recording {}: {}", path.display(), e))?;
+    stdin_lines(&text).map_err(|e| format!("recording {}: {}", path.display(), e))
+}
+
+fn stdin_lines(text: &str) -> Result<Vec<Recorded>, String> {
+    let mut first = None;
+    let mut lines = Vec::new();
+    for (n, entry) in text.lines().enumerate() {
+        if entry.trim().is_empty() {
+            continue;
+        }
+        let entry: Value =
+            serde_json::from_str(entry).map_err(|e| format!("line {} is not JSON: {}", n + 1, e))?;
+        if entry["kind"] != "stdin" {
+            continue;
+        }
+        let (Some(ts), Some(line)) = (entry["ts_ms"].as_u64(), entry["line"].as_str()) else {
+            return Err(format!("line {} has no ts_ms or line", n + 1));
+        };
+        let first = *first.get_or_insert(ts);
+        lines.push(Recorded {
+            at: Duration::from_millis(ts.saturating_sub(first)),
+            line: line.to_string(),
+        });
+    }
+    Ok(lines)
+}
+
+/// Writes each recorded line to `out` at its recorded time divided by
+/// `speed`, then closes it as stdin closing would.
+pub async fn feed<W: AsyncWrite + Unpin>(lines: Vec<Recorded>, speed: f64, mut out: W) {
+    let start = tokio::time::Instant::now();
+    for recorded in lines {
+        if speed > 0.0 {
+            tokio::time::sleep_until(start + recorded.at.div_f64(speed)).await;
+        }
+        if out
+            .write_all(format!("{}\n", recorded.line).as_bytes())
+            .await
+            .is_err()
+        {
+            debug!("bridge stopped reading the replay");
+            return;
+        }
+    }
+    let _ = out.shutdown().await;
+    info!("replay finished");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("mcp-record-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn entries_carry_kind_and_redacted_endpoint() {
+        let path = temp_path("entries.jsonl");
+        let _ = std::fs::remove_file(&path);
+        let recorder = Recorder::open(&path).unwrap();
+        recorder.stdin(r#"{"id":1}"#);
+        recorder.request("POST", "https://user:pw@up.test/mcp?key=k", Some(r#"{"id":1}"#));
+        recorder.error(&"timed out");
+        let text = std::fs::read_to_string(&path).unwrap();
+        let entries: Vec<Value> = text.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(entries[0]["kind"], "stdin");
+        assert_eq!(entries[0]["line"], r#"{"id":1}"#);
+        assert!(entries[0]["ts_ms"].as_u64().unwrap() > 0);
+        assert_eq!(entries[1]["endpoint"], "https://up.test/mcp?[redacted]");
+        assert!(!text.contains("pw") && !text.contains("key=k"));
+        assert_eq!(entries[2], json!({"ts_ms": entries[2]["ts_ms"], "kind": "error", "error": "timed out"}));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_failed_write_stops_recording() {
+        let recorder = Recorder {
+            path: PathBuf::from("/dev/full"),
+            file: Mutex::new(Some(File::open("/dev/null").unwrap())),
+        };
+        recorder.stdout("x");
+        assert!(recorder.file.lock().unwrap().is_none());
+        recorder.stdout("y");
+    }
+
+    #[test]
+    fn replay_args_are_taken_off_the_front() {
+        let (replay, rest) = parse_args(&args(&["--replay", "s.jsonl", "--uri", "u"]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(replay, Replay { path: "s.jsonl".into(), speed: 1.0 });
+        assert_eq!(rest, args(&["--uri", "u"]));
+        let (replay, rest) = parse_args(&args(&["--replay=s", "--timeout-ms=5", "--replay-speed", "0"]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(replay.speed, 0.0);
+        assert_eq!(rest, args(&["--timeout-ms=5"]));
+        assert!(parse_args(&args(&["--uri", "u", "--replay", "s"])).is_none());
+        assert!(parse_args(&args(&["--replay"])).unwrap().is_err());
+        assert!(parse_args(&args(&["--replay", "s", "--replay-speed=-1"])).unwrap().is_err());
+    }
+
+    #[test]
+    fn only_stdin_lines_are_replayed() {
+        let text = [
+            r#"{"ts_ms":1000,"kind":"stdin","line":"a"}"#,
+            r#"{"ts_ms":1100,"kind":"request","method":"POST","endpoint":"e","body":"a"}"#,
+            r#"{"ts_ms":1250,"kind":"stdin","line":"b"}"#,
+            "",
+        ]
+        .join("\n");
+        let lines = stdin_lines(&text).unwrap();
+        assert_eq!(
+            lines,
+            [
+                Recorded { at: Duration::ZERO, line: "a".into() },
+                Recorded { at: Duration::from_millis(250), line: "b".into() },
+            ]
+        );
+        assert!(stdin_lines("{oops").unwrap_err().starts_with("line 1"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn feed_keeps_scaled_timing() {
+        let lines = vec![
+            Recorded { at: Duration::ZERO, line: "a".into() },
+            Recorded { at: Duration::from_secs(10), line: "b".into() },
+        ];
+        let (mut read, write) = tokio::io::duplex(64);
+        let start = tokio::time::Instant::now();
+        tokio::spawn(feed(lines, 2.0, write));
+        let mut out = String::new();
+        read.read_to_string(&mut out).await.unwrap();
+        assert_eq!(out, "a\nb\n");
+        assert_eq!(start.elapsed(), Duration::from_secs(5));
+    }
+}
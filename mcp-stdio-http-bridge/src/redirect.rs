@@ -0,0 +1,152 @@
+//! Which upstream redirects are followed (`MCP_REDIRECT`,
+//! `MCP_REDIRECT_LIMIT`). reqwest drops `Authorization` whenever a redirect
+//! changes host, which no policy can prevent, so a 307 or 308 to a host in
+//! `MCP_REDIRECT_AUTH_HOSTS` stops reqwest's chain and the bridge sends the
+//! request there itself, credentials included.
+
+use crate::config::Config;
+use crate::remote_transport::{RedirectPolicy, TransportError};
+use crate::stats::Stats;
+use reqwest::redirect::{Attempt, Policy};
+use reqwest::{Client, RequestBuilder, Response, StatusCode, Url};
+use std::fmt;
+use std::time::Duration;
+use tracing::debug;
+
+/// Why a redirect chain was given up.
+#[derive(Debug)]
+struct TooManyRedirects(usize);
+
+impl fmt::Display for TooManyRedirects {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "more than {} redirects", self.0)
+    }
+}
+
+impl std::error::Error for TooManyRedirects {}
+
+/// The policy for reqwest's own redirect handling.
+pub fn policy(config: &Config) -> Policy {
+    let mode = config.redirect;
+    let limit = config.redirect_limit;
+    let auth_hosts = config.redirect_auth_hosts.clone();
+    if mode == RedirectPolicy::None {
+        return Policy::none();
+    }
+    Policy::custom(move |attempt: Attempt| {
+        if attempt.previous().len() > limit {
+            return attempt.error(TooManyRedirects(limit));
+        }
+        let from = attempt.previous().last().expect("a redirect comes from somewhere");
+        if same_host(from, attempt.url()) {
+            return attempt.follow();
+        }
+        if mode == RedirectPolicy::FollowSameHost {
+            return attempt.stop();
+        }
+        if keeps_request(attempt.status()) && carries_auth(&auth_hosts, attempt.url()) {
+            // `send` takes it from here.
+            return attempt.stop();
+        }
+        attempt.follow()
+    })
+}
+
+/// Sends `req`, following the redirects `policy` left to the bridge with
+/// every header of the original request. Each hop has `timeout` to answer.
+/// Past `MCP_REDIRECT_LIMIT` of them, the last redirect is the response.
+pub async fn send(
+    config: &Config,
+    client: &Client,
+    req: RequestBuilder,
+    timeout: Duration,
+    stats: &Stats,
+) -> Result<Response, TransportError> {
+    let mut req = req.build().map_err(TransportError::Network)?;
+    let mut hops = 0;
+    loop {
+        let again = req.try_clone();
+        let res = match tokio::time::timeout(timeout, client.execute(req)).await {
+            Ok(res) => res.map_err(TransportError::Network)?,
+            Err(_) => return Err(TransportError::Timeout),
+        };
+        stats.note_connection(&res, config.pool_idle_timeout);
+        let (Some(mut next), Some(to)) = (again, carried_to(config, &res)) else {
+            return Ok(res);
+        };
+        if hops == config.redirect_limit {
+            return Ok(res);
+        }
+        debug!(
+            status = res.status().as_u16(),
+            host = to.host_str().unwrap_or("-"),
+            "following redirect with credentials"
+        );
+        *next.url_mut() = to;
+        req = next;
+        hops += 1;
+    }
+}
+
+/// Where `res` redirects to, when that is a redirect `policy` stopped at
+/// for `send` to follow.
+fn carried_to(config: &Config, res: &Response) -> Option<Url> {
+    if config.redirect != RedirectPolicy::Follow || !keeps_request(res.status()) {
+        return None;
+    }
+    let location = res.headers().get(reqwest::header::LOCATION)?.to_str().ok()?;
+    let to = res.url().join(location).ok()?;
+    (!same_host(res.url(), &to) && carries_auth(&config.redirect_auth_hosts, &to)).then_some(to)
+}
+
+/// Whether a redirect with `status` repeats the request as it was, body
+/// and method included.
+fn keeps_request(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TEMPORARY_REDIRECT | StatusCode::PERMANENT_REDIRECT
+    )
+}
+
+/// The test reqwest drops credentials on.
+fn same_host(a: &Url, b: &Url) -> bool {
+    a.host_str() == b.host_str() && a.port_or_known_default() == b.port_or_known_default()
+}
+
+/// Whether `url`'s host is listed in `hosts`, exactly or under a
+/// `*.domain` entry.
+fn carries_auth(hosts: &[String], url: &Url) -> bool {
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+    hosts.iter().any(|allowed| match allowed.strip_prefix("*.") {
+        Some(domain) => host
+            .strip_suffix(domain)
+            .is_some_and(|sub| sub.ends_with('.') && sub.len() > 1),
+        None => host.eq_ignore_ascii_case(allowed),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auth_hosts_match_exactly_or_by_domain() {
+        let hosts = ["eu.mcp.example".to_string(), "*.cluster.example".to_string()];
+        let url = |s: &str| Url::parse(s).unwrap();
+        assert!(carries_auth(&hosts, &url("https://EU.mcp.example/mcp")));
+        assert!(carries_auth(&hosts, &url("https://a.cluster.example:8443/mcp")));
+        assert!(!carries_auth(&hosts, &url("https://cluster.example/mcp")));
+        assert!(!carries_auth(&hosts, &url("https://evilcluster.example/mcp")));
+        assert!(!carries_auth(&hosts, &url("https://us.mcp.example/mcp")));
+    }
+
+    #[test]
+    fn ports_tell_hosts_apart() {
+        let url = |s: &str| Url::parse(s).unwrap();
+        assert!(same_host(&url("https://a.example/x"), &url("https://a.example:443/y")));
+        assert!(!same_host(&url("http://a.example/x"), &url("http://a.example:8080/x")));
+        assert!(!same_host(&url("http://a.example/x"), &url("http://b.example/x")));
+    }
+}
@@ -1,22 +1,215 @@
+use crate::auth::{AuthError, AuthProvider};
+use crate::body_log;
 use crate::config::Config;
-use reqwest::Client;
+use crate::idempotency;
+use crate::jsonrpc;
+use crate::meta_headers;
+use crate::redirect;
+use crate::integrity::{self, BodyCheck};
+use crate::notices::UpstreamNotices;
+use crate::otel;
+use crate::record::Recorder;
+use crate::request_body::RequestBody;
+use crate::session::{self, Session};
+use crate::stats::Stats;
+use crate::stdio::{self, Utf8Mode};
+use crate::tls;
+use futures_util::stream;
+use futures_util::stream::BoxStream;
+use futures_util::StreamExt;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde_json::{json, Value};
+use std::collections::VecDeque;
 use std::fmt;
 use std::io;
-use tracing::{debug, instrument};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tracing::{debug, info, instrument, warn};
+
+pub use reqwest::Client;
+
+/// How long an idle upstream connection is kept for reuse when
+/// `MCP_POOL_IDLE_TIMEOUT_MS` is not set; reqwest's own default.
+pub const DEFAULT_POOL_IDLE_TIMEOUT_MS: u64 = 90_000;
+
+/// HTTP version spoken to the upstream (`MCP_HTTP_VERSION`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HttpVersion {
+    /// HTTP/1.1, or HTTP/2 where TLS negotiates it.
+    #[default]
+    Auto,
+    Http1,
+    /// HTTP/2 from the first byte, without negotiation; the only way to
+    /// reach a cleartext HTTP/2 (h2c) server.
+    Http2PriorKnowledge,
+}
+
+impl FromStr for HttpVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "auto" => Ok(Self::Auto),
+            "http1" => Ok(Self::Http1),
+            "http2-prior-knowledge" => Ok(Self::Http2PriorKnowledge),
+            other => Err(format!("unknown HTTP version {:?}", other)),
+        }
+    }
+}
+
+impl HttpVersion {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Http1 => "http1",
+            Self::Http2PriorKnowledge => "http2-prior-knowledge",
+        }
+    }
+}
+
+/// Redirects followed in a row, as many as reqwest allows by default
+/// (`MCP_REDIRECT_LIMIT`).
+pub const DEFAULT_REDIRECT_LIMIT: usize = 10;
+
+/// Which redirects are followed (`MCP_REDIRECT`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RedirectPolicy {
+    /// Any, like reqwest does by default.
+    #[default]
+    Follow,
+    /// Only those to the host and port the request went to.
+    FollowSameHost,
+    /// None; the redirect is answered with an error.
+    None,
+}
+
+impl FromStr for RedirectPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "follow" => Ok(Self::Follow),
+            "follow-same-host" => Ok(Self::FollowSameHost),
+            "none" => Ok(Self::None),
+            other => Err(format!("unknown redirect policy {:?}", other)),
+        }
+    }
+}
+
+impl RedirectPolicy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Follow => "follow",
+            Self::FollowSameHost => "follow-same-host",
+            Self::None => "none",
+        }
+    }
+}
+
+/// Error statuses retried like network failures (`MCP_RETRY_STATUSES`).
+pub const DEFAULT_RETRY_STATUSES: &[u16] = &[502, 503, 504];
+/// Most of an error body read, and kept in `TransportError::Status`.
+const ERROR_BODY_LIMIT: usize = 64 * 1024;
+const ERROR_BODY_SNIPPET: usize = 200;
+/// Resumptions of one interrupted SSE response (`MCP_SSE_RESUME_MAX`).
+pub const DEFAULT_SSE_RESUME_MAX: u32 = 3;
+/// Header asking a resumed SSE stream for the events after this id.
+pub(crate) const LAST_EVENT_ID: &str = "Last-Event-ID";
 
 #[derive(Debug)]
 pub enum TransportError {
     Network(reqwest::Error),
-    InvalidUtf8,
+    /// The body was not UTF-8 under `MCP_STDOUT_VALIDATION=strict`; holds
+    /// the offset of the first invalid sequence in it.
+    InvalidUtf8(usize),
     Io(io::Error),
+    /// The body did not match its `Content-Length` or echoed digest.
+    Integrity(String),
+    /// The upstream answered 404 to a request carrying its session id.
+    SessionExpired,
+    /// A message in the response was longer than the limit it holds
+    /// (`MCP_MAX_MESSAGE_BYTES`).
+    TooLarge(usize),
+    /// A JSON text sequence record spans lines and cannot be put on one,
+    /// as raw passthrough forwards records unchanged; holds its offset in
+    /// the body.
+    SplitRecord(usize),
+    /// Nothing arrived within the attempt timeout: no response headers, or
+    /// no more of the body.
+    Timeout,
+    /// The upstream answered 429 or 503, with the wait its `Retry-After`
+    /// header asked for.
+    RateLimited {
+        status: u16,
+        retry_after: Option<Duration>,
+    },
+    /// The upstream answered with an error status and a body that is not a
+    /// JSON-RPC response; holds the start of that body.
+    Status(u16, String),
+    /// The upstream answered 401 even after the credentials were acquired
+    /// again; holds the start of the body and the `WWW-Authenticate`
+    /// challenge, if any.
+    Unauthorized {
+        body: String,
+        challenge: Option<String>,
+    },
+    /// No bearer token could be had, e.g. the OAuth2 token endpoint failed.
+    Credentials(AuthError),
+    /// The upstream never got as far as answering over HTTP/2 with
+    /// `MCP_HTTP_VERSION=http2-prior-knowledge`; holds the underlying error.
+    Http2Refused(String),
 }
 
 impl fmt::Display for TransportError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             TransportError::Network(e) => write!(f, "network: {}", e),
-            TransportError::InvalidUtf8 => write!(f, "invalid UTF-8 in response"),
+            TransportError::InvalidUtf8(offset) => {
+                write!(f, "invalid UTF-8 in response at byte {}", offset)
+            }
             TransportError::Io(e) => write!(f, "io: {}", e),
+            TransportError::Integrity(e) => write!(f, "possible middlebox corruption: {}", e),
+            TransportError::SessionExpired => {
+                write!(f, "upstream session expired (HTTP 404), re-initialize")
+            }
+            TransportError::TooLarge(limit) => write!(
+                f,
+                "upstream message larger than {} bytes (MCP_MAX_MESSAGE_BYTES)",
+                limit
+            ),
+            TransportError::SplitRecord(offset) => write!(
+                f,
+                "JSON sequence record at byte {} spans lines and cannot be relayed on one",
+                offset
+            ),
+            TransportError::Timeout => write!(f, "network: request timed out"),
+            TransportError::RateLimited {
+                status,
+                retry_after,
+            } => {
+                write!(f, "upstream is overloaded (HTTP {})", status)?;
+                match retry_after {
+                    Some(wait) => write!(f, ", retry after {} s", wait.as_secs()),
+                    None => Ok(()),
+                }
+            }
+            TransportError::Status(status, _) => {
+                write!(f, "upstream answered {}", status_line(*status))
+            }
+            TransportError::Unauthorized { challenge, .. } => {
+                write!(f, "upstream rejected the credentials ({})", status_line(401))?;
+                match challenge {
+                    Some(challenge) => write!(f, ", WWW-Authenticate: {}", challenge),
+                    None => Ok(()),
+                }
+            }
+            TransportError::Credentials(e) => write!(f, "could not acquire credentials: {}", e),
+            TransportError::Http2Refused(e) => write!(
+                f,
+                "upstream does not speak HTTP/2 without negotiation (MCP_HTTP_VERSION=http2-prior-knowledge); it may only support HTTP/1.1: {}",
+                e
+            ),
         }
     }
 }
@@ -29,6 +222,12 @@ impl From<reqwest::Error> for TransportError {
     }
 }
 
+impl From<AuthError> for TransportError {
+    fn from(e: AuthError) -> Self {
+        TransportError::Credentials(e)
+    }
+}
+
 impl From<io::Error> for TransportError {
     fn from(e: io::Error) -> Self {
         TransportError::Io(e)
@@ -40,83 +239,1357 @@ pub fn is_retryable(e: &TransportError) -> bool {
         TransportError::Network(err) => {
             err.is_connect() || err.is_timeout() || err.is_request()
         }
-        TransportError::InvalidUtf8 | TransportError::Io(_) => false,
+        TransportError::InvalidUtf8(_) | TransportError::Io(_) => false,
+        // The same session id would get the same answer.
+        TransportError::SessionExpired => false,
+        // The same request would most likely get the same answer.
+        TransportError::TooLarge(_) | TransportError::SplitRecord(_) => false,
+        // Retried, but the bridge caps it at MCP_INTEGRITY_MAX_RETRIES.
+        TransportError::Integrity(_) => true,
+        TransportError::Timeout => true,
+        // Waits for the server's Retry-After instead of the backoff.
+        TransportError::RateLimited { .. } => true,
+        // Which statuses are retried is configured (MCP_RETRY_STATUSES), so
+        // `bridge::should_retry` decides them before asking here.
+        TransportError::Status(..) => false,
+        // `send_message` already tried again with fresh credentials.
+        TransportError::Unauthorized { .. } => false,
+        // The token endpoint may be back by the next attempt.
+        TransportError::Credentials(_) => true,
+        // Only a change of MCP_HTTP_VERSION helps.
+        TransportError::Http2Refused(_) => false,
+    }
+}
+
+/// Whether `e` shows the request never reached the upstream, so sending it
+/// again cannot run anything twice: the connection or the credentials
+/// failed before it went out.
+pub fn never_sent(e: &TransportError) -> bool {
+    match e {
+        TransportError::Network(err) => err.is_connect(),
+        TransportError::Credentials(_) => true,
+        _ => false,
+    }
+}
+
+/// Coarse classification used to group repeated failures in the logs.
+pub fn classify(e: &TransportError) -> &'static str {
+    match e {
+        TransportError::Network(err) if err.is_connect() => "connect",
+        TransportError::Network(err) if err.is_timeout() => "timeout",
+        TransportError::Network(err) if err.is_request() => "request",
+        TransportError::Network(_) => "network",
+        TransportError::InvalidUtf8(_) => "invalid-utf8",
+        TransportError::Io(_) => "io",
+        TransportError::Integrity(_) => "decode",
+        TransportError::SessionExpired => "session",
+        TransportError::TooLarge(_) => "too-large",
+        TransportError::SplitRecord(_) => "decode",
+        TransportError::Timeout => "timeout",
+        TransportError::RateLimited { .. } => "rate-limited",
+        TransportError::Status(..) => "status",
+        TransportError::Unauthorized { .. } | TransportError::Credentials(_) => "auth",
+        TransportError::Http2Refused(_) => "protocol",
     }
 }
 
 pub fn build_client(config: &Config) -> Client {
-    let builder = Client::builder()
+    // No overall timeout: a streamed response may run for as long as it
+    // keeps sending, so `send_message` bounds each wait instead.
+    let builder = tls::configure(with_proxy(Client::builder(), config), config);
+    let idle_timeout = (!config.pool_idle_timeout.is_zero()).then_some(config.pool_idle_timeout);
+    let mut builder = builder
+        .user_agent(user_agent(config))
         .connect_timeout(config.timeout)
-        .timeout(config.timeout);
+        .gzip(config.compression)
+        .brotli(config.compression)
+        .pool_idle_timeout(idle_timeout)
+        // Tells the connections that did a TLS handshake (`conn-metrics`).
+        .tls_info(cfg!(feature = "conn-metrics"));
+    builder = match config.http_version {
+        HttpVersion::Auto => builder,
+        HttpVersion::Http1 => builder.http1_only(),
+        HttpVersion::Http2PriorKnowledge => builder.http2_prior_knowledge(),
+    };
+    if let Some(max) = config.pool_max_idle {
+        builder = builder.pool_max_idle_per_host(max);
+    }
+    if !config.tcp_keepalive.is_zero() {
+        builder = builder.tcp_keepalive(config.tcp_keepalive);
+    }
+    builder = builder.redirect(redirect::policy(config));
     builder.build().expect("reqwest client")
 }
 
-#[instrument(skip(config, client, body), fields(uri = %config.uri))]
+/// The default `User-Agent`, naming the bridge, its version and
+/// `MCP_NAME` when that can go in a header. `MCP_HEADERS` may replace it.
+pub fn user_agent(config: &Config) -> HeaderValue {
+    let base = concat!("mcp-stdio-http-bridge/", env!("CARGO_PKG_VERSION"));
+    config
+        .mcp_name
+        .as_deref()
+        .and_then(|name| HeaderValue::from_str(&format!("{} ({})", base, name)).ok())
+        .unwrap_or(HeaderValue::from_static(base))
+}
+
+/// With `MCP_HTTP_VERSION=http2-prior-knowledge`, a request that fails
+/// after connecting, before the upstream ever answered, most likely went
+/// to a server that only speaks HTTP/1.1. Underneath that is an opaque
+/// "channel closed" or frame error, so it is reported as such instead.
+fn refused_http2(config: &Config, stats: &Stats, e: TransportError) -> TransportError {
+    if config.http_version != HttpVersion::Http2PriorKnowledge || stats.upstream_counts().1 > 0 {
+        return e;
+    }
+    let refused = match &e {
+        TransportError::Network(err) => err.is_request() && !err.is_connect() && !err.is_timeout(),
+        _ => false,
+    };
+    if refused {
+        TransportError::Http2Refused(e.to_string())
+    } else {
+        e
+    }
+}
+
+/// Logs the upstream connection reuse settings in effect, zero meaning
+/// off, so a deployment can confirm what it runs with.
+pub fn log_pool_settings(config: &Config) {
+    let max_idle = config
+        .pool_max_idle
+        .map_or_else(|| "unlimited".to_string(), |n| n.to_string());
+    info!(
+        pool_max_idle = %max_idle,
+        pool_idle_timeout_ms = config.pool_idle_timeout.as_millis() as u64,
+        tcp_keepalive_secs = config.tcp_keepalive.as_secs(),
+        "upstream connection pool"
+    );
+}
+
+/// Response body chunks as they arrive.
+pub(crate) type BodyStream = BoxStream<'static, Result<Vec<u8>, TransportError>>;
+
+/// What one HTTP exchange returned. The body has not been read yet, except
+/// with `MCP_INTEGRITY_HEADER`, which needs all of it before anything can
+/// be trusted.
+pub struct Fetched {
+    pub status: u16,
+    pub content_type: String,
+    pub notices: UpstreamNotices,
+    /// The `Mcp-Session-Id` response header.
+    pub session_id: Option<String>,
+    /// How long the upstream asked to wait before retrying (`Retry-After`).
+    pub retry_after: Option<Duration>,
+    /// The challenge of a 401 (`WWW-Authenticate`).
+    pub www_authenticate: Option<String>,
+    pub body: BodyStream,
+}
+
+/// An SSE stream's position, to resume it after it failed: its last event
+/// id, the ids it already handed out and its limit on each wait for more.
+#[derive(Clone)]
+pub struct ResumeFrom {
+    decoder: SseDecoder,
+    idle: Option<Duration>,
+}
+
+impl ResumeFrom {
+    /// The id sent as `Last-Event-ID`.
+    pub fn last_event_id(&self) -> &str {
+        self.decoder.last_event_id().unwrap_or_default()
+    }
+}
+
+/// The messages of one upstream answer, read as its body arrives. Each SSE
+/// event is handed out as soon as the blank line ending it has been read,
+/// each NDJSON line as soon as its newline has; a JSON body once all of it
+/// has. Every wait for more of the body is
+/// limited to `idle`, so a stream that keeps sending may run for as long as
+/// it needs.
+pub struct Response {
+    body: Option<BodyStream>,
+    sse: Option<SseDecoder>,
+    seq: Option<SeqDecoder>,
+    json: Vec<u8>,
+    ready: VecDeque<String>,
+    idle: Option<Duration>,
+    mode: Utf8Mode,
+    /// Longest message, or JSON body, buffered before giving up.
+    max: usize,
+    /// Byte limit for logging each message (`MCP_LOG_BODIES`).
+    log: Option<usize>,
+    recorder: Option<Arc<Recorder>>,
+}
+
+impl Response {
+    fn new(fetched: Fetched, idle: Option<Duration>, mode: Utf8Mode, max: usize) -> Self {
+        let sse = fetched.content_type.contains("text/event-stream");
+        Self {
+            body: Some(fetched.body),
+            sse: sse.then(SseDecoder::default),
+            seq: SeqDecoder::for_content_type(&fetched.content_type),
+            json: Vec::new(),
+            ready: VecDeque::new(),
+            idle,
+            mode,
+            max,
+            log: None,
+            recorder: None,
+        }
+    }
+
+    /// An answer whose messages have all been read already.
+    pub fn from_lines(lines: Vec<String>) -> Self {
+        Self {
+            body: None,
+            sse: None,
+            seq: None,
+            json: Vec::new(),
+            ready: lines.into(),
+            idle: None,
+            mode: Utf8Mode::Strict,
+            max: usize::MAX,
+            log: None,
+            recorder: None,
+        }
+    }
+
+    /// Logs each message as it is handed out when `MCP_LOG_BODIES` is on.
+    fn logged(mut self, config: &Config) -> Self {
+        self.log = config.log_bodies.then_some(config.log_body_limit);
+        self
+    }
+
+    /// Refuses JSON text sequence records that span lines rather than
+    /// compacting them onto one, for raw passthrough.
+    fn passthrough(mut self, on: bool) -> Self {
+        if let Some(seq) = self.seq.as_mut() {
+            seq.compact = !on;
+        }
+        self
+    }
+
+    /// Records each message as it is handed out, and the error that ends
+    /// the body if one does, when `MCP_RECORD_FILE` is set.
+    fn recorded(mut self, stats: &Stats) -> Self {
+        self.recorder = stats.recorder().cloned();
+        self
+    }
+
+    /// Whether the body is an SSE stream rather than one JSON document.
+    pub fn is_sse(&self) -> bool {
+        self.sse.is_some()
+    }
+
+    /// Whether the body frames its messages itself, as SSE events or
+    /// NDJSON lines, rather than being one JSON document.
+    pub fn is_stream(&self) -> bool {
+        self.sse.is_some() || self.seq.is_some()
+    }
+
+    /// The latest SSE `retry:` hint seen so far.
+    pub fn retry(&self) -> Option<Duration> {
+        self.sse.as_ref().and_then(SseDecoder::retry)
+    }
+
+    /// The last SSE event id seen so far.
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.sse.as_ref().and_then(SseDecoder::last_event_id)
+    }
+
+    /// Where a stream resuming this one starts, once an event id has been
+    /// seen; a server that sends none cannot resume.
+    pub fn resume_from(&self) -> Option<ResumeFrom> {
+        let sse = self.sse.as_ref().filter(|sse| sse.last_event_id().is_some())?;
+        Some(ResumeFrom {
+            decoder: sse.resumed(),
+            idle: self.idle,
+        })
+    }
+
+    /// The next message, or `None` once the body has ended.
+    pub async fn next(&mut self) -> Result<Option<String>, TransportError> {
+        let next = self.read_next().await;
+        if let Some(recorder) = &self.recorder {
+            match &next {
+                Ok(Some(line)) => recorder.response(line),
+                Ok(None) => {}
+                Err(e) => recorder.error(e),
+            }
+        }
+        next
+    }
+
+    async fn read_next(&mut self) -> Result<Option<String>, TransportError> {
+        loop {
+            if let Some(line) = self.ready.pop_front() {
+                if let Some(limit) = self.log {
+                    debug!(body = %body_log::for_log(&line, limit), "upstream response line");
+                }
+                return Ok(Some(line));
+            }
+            let Some(body) = self.body.as_mut() else {
+                return Ok(None);
+            };
+            let next = match self.idle {
+                Some(idle) => tokio::time::timeout(idle, body.next())
+                    .await
+                    .map_err(|_| TransportError::Timeout)?,
+                None => body.next().await,
+            };
+            let Some(chunk) = next else {
+                self.body = None;
+                self.finish()?;
+                continue;
+            };
+            let chunk = chunk?;
+            match (self.sse.as_mut(), self.seq.as_mut()) {
+                (Some(sse), _) => {
+                    let events = sse.feed(&chunk, self.mode)?;
+                    if sse.pending() > self.max || events.iter().any(|e| e.len() > self.max) {
+                        return Err(TransportError::TooLarge(self.max));
+                    }
+                    self.ready.extend(events);
+                }
+                (None, Some(seq)) => {
+                    let records = seq.feed(&chunk, self.mode)?;
+                    if seq.pending() > self.max || records.iter().any(|r| r.len() > self.max) {
+                        return Err(TransportError::TooLarge(self.max));
+                    }
+                    self.ready.extend(records);
+                }
+                (None, None) if self.json.len() + chunk.len() > self.max => {
+                    return Err(TransportError::TooLarge(self.max));
+                }
+                (None, None) => self.json.extend_from_slice(&chunk),
+            }
+        }
+    }
+
+    /// Reads the whole body, for callers that have no use for early messages.
+    pub async fn collect(mut self) -> Result<Vec<String>, TransportError> {
+        let mut lines = Vec::new();
+        while let Some(line) = self.next().await? {
+            lines.push(line);
+        }
+        Ok(lines)
+    }
+
+    fn finish(&mut self) -> Result<(), TransportError> {
+        if let Some(sse) = self.sse.as_mut() {
+            self.ready.extend(sse.finish(self.mode)?);
+            return Ok(());
+        }
+        if let Some(seq) = self.seq.as_mut() {
+            self.ready.extend(seq.finish(self.mode)?);
+            return Ok(());
+        }
+        let json = std::mem::take(&mut self.json);
+        let body = stdio::decode_utf8(json, self.mode, "upstream response")
+            .map_err(TransportError::InvalidUtf8)?;
+        if !body.trim().is_empty() {
+            self.ready.push_back(body);
+        }
+        Ok(())
+    }
+}
+
+/// Sends one message upstream as a single HTTP attempt, carrying the current
+/// session id and recording any the response assigns. `timeout` limits the
+/// wait for the response headers and, through the returned `Response`, each
+/// wait for more of the body. A 401 is the exception: the credentials are
+/// acquired again and the message sent a second time, once. Attempts share
+/// `body`'s bytes, so none of them copies it.
+#[instrument(
+    skip(config, client, auth, session, body, stats),
+    fields(
+        uri = %session.endpoint(),
+        session = tracing::field::Empty,
+        trace_id = tracing::field::Empty
+    )
+)]
 pub async fn send_message(
     config: &Config,
     client: &Client,
-    body: &str,
-) -> Result<Vec<String>, TransportError> {
+    auth: &AuthProvider,
+    session: &Session,
+    body: &RequestBody,
+    timeout: Duration,
+    stats: &Stats,
+) -> Result<Response, TransportError> {
+    let session_id = session.id();
+    if let Some(id) = &session_id {
+        tracing::Span::current().record("session", id.as_str());
+    }
+    if let Some(trace_id) = otel::trace_id() {
+        tracing::Span::current().record("trace_id", trace_id.as_str());
+    }
+    if config.log_bodies {
+        debug!(body = %body_log::for_log(&body.text(), config.log_body_limit), "upstream request body");
+    }
+    let sent = post_recorded(config, client, auth, session, body, timeout, stats).await;
+    let Err(TransportError::Unauthorized { .. }) = sent else {
+        return sent;
+    };
+    // The credentials were invalidated on the 401, so this attempt acquires
+    // them again; a second 401 means they are revoked, and is given up on.
+    warn!("upstream answered 401, retrying once with refreshed credentials");
+    post_recorded(config, client, auth, session, body, timeout, stats).await
+}
+
+/// `post`, recorded to `MCP_RECORD_FILE` when that is on.
+async fn post_recorded(
+    config: &Config,
+    client: &Client,
+    auth: &AuthProvider,
+    session: &Session,
+    body: &RequestBody,
+    timeout: Duration,
+    stats: &Stats,
+) -> Result<Response, TransportError> {
+    let Some(recorder) = stats.recorder() else {
+        return post(config, client, auth, session, body, timeout, stats).await;
+    };
+    recorder.request("POST", &session.endpoint(), Some(&body.text()));
+    let sent = post(config, client, auth, session, body, timeout, stats).await;
+    match sent {
+        Ok(response) => Ok(response.recorded(stats)),
+        Err(e) => {
+            recorder.error(&e);
+            Err(e)
+        }
+    }
+}
+
+/// One attempt of `send_message`.
+async fn post(
+    config: &Config,
+    client: &Client,
+    auth: &AuthProvider,
+    session: &Session,
+    body: &RequestBody,
+    timeout: Duration,
+    stats: &Stats,
+) -> Result<Response, TransportError> {
+    let session_id = session.id();
+    stats.note_upstream_sent();
+    let endpoint = session.endpoint();
+    let protocol_version = session.protocol_version();
+    let target = Target::new(
+        config,
+        &endpoint,
+        session_id.as_deref(),
+        protocol_version.as_deref(),
+    );
+    let fetched = fetch(config, client, auth, &target, body, timeout, stats)
+        .await
+        .map_err(|e| refused_http2(config, stats, e));
+    otel::note_attempt(fetched.as_ref().ok().map(|f| f.status), fetched.as_ref().err());
+    let fetched = fetched?;
+    stats.note_upstream_response();
+    let notices = &fetched.notices;
+    if stats.note_notices(notices) {
+        warn!(
+            deprecation = notices.deprecation.as_deref().unwrap_or("-"),
+            sunset = notices.sunset.as_deref().unwrap_or("-"),
+            "upstream marked this endpoint as deprecated"
+        );
+    }
+    if let Some(sent) = session_id.as_deref().filter(|_| fetched.status == 404) {
+        if session.expire(sent) {
+            warn!(session = %sent, "upstream no longer knows the session");
+        }
+        return Err(TransportError::SessionExpired);
+    }
+    session.update(fetched.session_id.as_deref());
+    let retry_status = config.retry_statuses.contains(&fetched.status);
+    if fetched.status == 429 || (fetched.status == 503 && retry_status) {
+        return Err(TransportError::RateLimited {
+            status: fetched.status,
+            retry_after: fetched.retry_after,
+        });
+    }
+    if fetched.status == 401 {
+        // A rotated token file or expired token: the next request acquires
+        // it again.
+        auth.invalidate();
+        let body = error_body(fetched.body, timeout).await;
+        return Err(TransportError::Unauthorized {
+            body: body.trim().chars().take(ERROR_BODY_SNIPPET).collect(),
+            challenge: fetched.www_authenticate,
+        });
+    }
+    // A redirect `MCP_REDIRECT` did not follow, or one too many.
+    if (300..400).contains(&fetched.status) {
+        let snippet = "redirect not followed (see MCP_REDIRECT)".to_string();
+        return Err(TransportError::Status(fetched.status, snippet));
+    }
+    if fetched.status >= 400 {
+        let status = fetched.status;
+        let body = error_body(fetched.body, timeout).await;
+        // A JSON-RPC error sent with an error status is the server's answer.
+        if !retry_status && jsonrpc::is_response(&body) {
+            return Ok(Response::from_lines(vec![body]).logged(config));
+        }
+        let snippet: String = body.trim().chars().take(ERROR_BODY_SNIPPET).collect();
+        return Err(TransportError::Status(status, snippet));
+    }
+    if fetched.status == 202 {
+        return Ok(Response::from_lines(Vec::new()));
+    }
+    if fetched.content_type.contains("text/event-stream") {
+        debug!("reading SSE response");
+    } else if SeqDecoder::for_content_type(&fetched.content_type).is_some() {
+        debug!(content_type = %fetched.content_type, "reading NDJSON response");
+    }
+    let max = config.max_message_bytes;
+    Ok(Response::new(fetched, Some(timeout), config.stdout_validation, max)
+        .passthrough(config.raw_passthrough)
+        .logged(config))
+}
+
+/// Opens the standalone GET stream on which the upstream sends requests and
+/// notifications of its own, and returns its status and events. Only the
+/// wait for the response headers is limited: a connected stream may stay
+/// quiet for as long as the upstream has nothing to say. Given a stream to
+/// resume, it asks for the events after that stream's last id with
+/// `Last-Event-ID`, and drops any the upstream sends again.
+#[instrument(
+    skip(config, client, auth, session, resume, stats),
+    fields(uri = %session.endpoint(), session = tracing::field::Empty)
+)]
+pub async fn open_stream(
+    config: &Config,
+    client: &Client,
+    auth: &AuthProvider,
+    session: &Session,
+    resume: Option<ResumeFrom>,
+    stats: &Stats,
+) -> Result<(u16, Response), TransportError> {
+    let session_id = session.id();
+    if let Some(id) = &session_id {
+        tracing::Span::current().record("session", id.as_str());
+    }
+    let endpoint = session.endpoint();
+    let recorder = stats.recorder();
+    if let Some(recorder) = recorder {
+        recorder.request("GET", &endpoint, None);
+    }
+    let last_event_id = resume.as_ref().and_then(|r| r.decoder.last_event_id());
+    let protocol_version = session.protocol_version();
+    let target = Target::new(
+        config,
+        &endpoint,
+        session_id.as_deref(),
+        protocol_version.as_deref(),
+    );
+    let fetched = fetch_stream(config, client, auth, &target, last_event_id, stats)
+        .await
+        .inspect_err(|e| {
+            if let Some(recorder) = recorder {
+                recorder.error(e);
+            }
+        })?;
+    let status = fetched.status;
+    let mut response =
+        Response::new(fetched, None, config.stdout_validation, config.max_message_bytes)
+            .passthrough(config.raw_passthrough);
+    if let Some(resume) = resume {
+        response.idle = resume.idle;
+        if let Some(sse) = response.sse.as_mut() {
+            *sse = resume.decoder;
+        }
+    }
+    Ok((status, response.logged(config).recorded(stats)))
+}
+
+/// Picks up an SSE response that failed part way, by opening the GET stream
+/// with its last event id as the MCP spec describes.
+pub async fn resume_stream(
+    config: &Config,
+    client: &Client,
+    auth: &AuthProvider,
+    session: &Session,
+    from: ResumeFrom,
+    stats: &Stats,
+) -> Result<Response, TransportError> {
+    let (status, response) =
+        open_stream(config, client, auth, session, Some(from), stats).await?;
+    if status != 200 || !response.is_sse() {
+        return Err(TransportError::Status(status, String::new()));
+    }
+    Ok(response)
+}
+
+/// Whether `e` means the connection dropped while a body was being read, as
+/// opposed to the body itself being unacceptable.
+pub fn is_dropped_stream(e: &TransportError) -> bool {
+    matches!(
+        e,
+        TransportError::Network(_) | TransportError::Io(_) | TransportError::Timeout
+    )
+}
+
+/// `HTTP 403 Forbidden`, or just the number for a status with no reason.
+fn status_line(status: u16) -> String {
+    let reason = reqwest::StatusCode::from_u16(status)
+        .ok()
+        .and_then(|s| s.canonical_reason());
+    match reason {
+        Some(reason) => format!("HTTP {} {}", status, reason),
+        None => format!("HTTP {}", status),
+    }
+}
+
+/// The JSON-RPC `error` member for an upstream that answered `status`:
+/// credentials, routing, payload size and rate limits get codes of their
+/// own, anything else is an internal error. `data` carries the status and
+/// `body`, the start of the upstream's error page, when there is one.
+pub fn status_error(status: u16, body: &str) -> Value {
+    let (code, prefix) = match status {
+        401 | 403 => (jsonrpc::UPSTREAM_AUTH_FAILED, "upstream authentication failed: "),
+        404 => (jsonrpc::UPSTREAM_NOT_FOUND, "upstream endpoint not found: "),
+        413 => (jsonrpc::UPSTREAM_PAYLOAD_TOO_LARGE, "payload too large for upstream: "),
+        429 => (jsonrpc::UPSTREAM_RATE_LIMITED, "upstream rate limit exceeded: "),
+        _ => (jsonrpc::INTERNAL_ERROR, "bridge transport error: upstream answered "),
+    };
+    let message = format!("{}{}", prefix, status_line(status));
+    let mut error = jsonrpc::error_object(code, &message);
+    let mut data = json!({ "status": status });
+    if !body.is_empty() {
+        data["body"] = json!(body);
+    }
+    error["data"] = data;
+    error
+}
+
+/// The JSON-RPC `error` member for a request whose credentials the upstream
+/// rejected, with its `WWW-Authenticate` challenge in the message and in
+/// `data` so the client can tell an expired token from a revoked one.
+pub fn unauthorized_error(body: &str, challenge: Option<&str>) -> Value {
+    let mut error = status_error(401, body);
+    if let Some(challenge) = challenge {
+        let message = error["message"].as_str().unwrap_or_default();
+        error["message"] = json!(format!("{}, WWW-Authenticate: {}", message, challenge));
+        error["data"]["wwwAuthenticate"] = json!(challenge);
+    }
+    error
+}
+
+/// Reads up to `ERROR_BODY_LIMIT` bytes of an error response's body. It
+/// only explains the failure, so a body that breaks off is used as far as
+/// it got.
+async fn error_body(mut body: BodyStream, timeout: Duration) -> String {
+    let mut bytes = Vec::new();
+    while bytes.len() < ERROR_BODY_LIMIT {
+        match tokio::time::timeout(timeout, body.next()).await {
+            Ok(Some(Ok(chunk))) => bytes.extend_from_slice(&chunk),
+            _ => break,
+        }
+    }
+    bytes.truncate(ERROR_BODY_LIMIT);
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Parses `MCP_HEADERS`: `Name: value` pairs separated by newlines or
+/// commas. A comma-separated part without a header name of its own (as in
+/// `Accept: a, b`) continues the previous value. Names and values are
+/// checked here, so a bad one stops the bridge at startup rather than
+/// failing every request; errors never repeat a value, which may be secret.
+pub fn parse_headers(spec: &str) -> Result<Vec<(String, String)>, String> {
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for line in spec.lines().filter(|l| !l.trim().is_empty()) {
+        let mut first = true;
+        for part in line.split(',') {
+            let named = part
+                .split_once(':')
+                .filter(|(name, _)| HeaderName::from_bytes(name.trim().as_bytes()).is_ok());
+            match (named, headers.last_mut()) {
+                (Some((name, value)), _) => {
+                    headers.push((name.trim().to_string(), value.trim().to_string()))
+                }
+                (None, Some((_, value))) if !first => {
+                    value.push(',');
+                    value.push_str(part.trim_end());
+                }
+                (None, _) => {
+                    return Err(match part.split_once(':') {
+                        Some((name, _)) => format!("invalid header name {:?}", name.trim()),
+                        None => "expected `Name: value`, found a part with no name".to_string(),
+                    });
+                }
+            }
+            first = false;
+        }
+    }
+    for (name, value) in &headers {
+        if HeaderValue::from_str(value).is_err() {
+            return Err(format!("invalid value for header {:?}", name));
+        }
+    }
+    Ok(headers)
+}
+
+/// Parses `key=value` pairs separated by commas (`MCP_QUERY_PARAMS`), e.g.
+/// `agent=planner,env=staging`. Values may be empty; keys may not.
+pub fn parse_query_params(spec: &str) -> Result<Vec<(String, String)>, String> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => {
+                Ok((key.trim().to_string(), value.trim().to_string()))
+            }
+            _ => Err(format!("invalid query parameter {:?} (expected key=value)", pair)),
+        })
+        .collect()
+}
+
+/// `uri` with `params` in its query string, encoded. A configured key
+/// replaces the values `uri` has for it; the query's other parameters are
+/// kept verbatim, in order.
+pub fn with_query_params(uri: &str, params: &[(String, String)]) -> String {
+    if params.is_empty() {
+        return uri.to_string();
+    }
+    let Ok(mut url) = url::Url::parse(uri) else {
+        return uri.to_string();
+    };
+    let mut pairs: Vec<String> = url
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .filter(|pair| !params.iter().any(|(key, _)| session::names(pair, key)))
+        .map(str::to_string)
+        .collect();
+    pairs.push(
+        url::form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(params)
+            .finish(),
+    );
+    url.set_query(Some(&pairs.join("&")));
+    url.to_string()
+}
+
+/// `MCP_HEADERS` as a map, for adding to a request with replace semantics
+/// so they take the place of built-in headers of the same name.
+pub(crate) fn extra_headers(config: &Config) -> HeaderMap {
+    let mut map = HeaderMap::new();
+    for (name, value) in &config.headers {
+        let name = HeaderName::from_bytes(name.as_bytes()).expect("checked at startup");
+        let value = HeaderValue::from_str(value.expose()).expect("checked at startup");
+        map.append(name, value);
+    }
+    map
+}
+
+/// How upstream requests reach the network.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProxyMode {
+    /// Whatever `HTTP_PROXY`, `HTTPS_PROXY`, `ALL_PROXY` and `NO_PROXY` say.
+    System,
+    /// No proxy, whatever the environment says (`MCP_NO_PROXY`).
+    Direct,
+    /// Every request through this proxy (`MCP_PROXY`), ignoring the
+    /// environment.
+    Url(String),
+}
+
+pub fn proxy_mode(config: &Config) -> ProxyMode {
+    match &config.proxy {
+        _ if config.no_proxy => ProxyMode::Direct,
+        Some(url) => ProxyMode::Url(url.expose().to_string()),
+        None => ProxyMode::System,
+    }
+}
+
+/// Applies `MCP_PROXY`/`MCP_NO_PROXY` to a client for the upstream or the
+/// OAuth2 token endpoint.
+pub fn with_proxy(builder: reqwest::ClientBuilder, config: &Config) -> reqwest::ClientBuilder {
+    match proxy_mode(config) {
+        ProxyMode::System => builder,
+        ProxyMode::Direct => builder.no_proxy(),
+        ProxyMode::Url(url) => {
+            builder.proxy(reqwest::Proxy::all(url).expect("checked at startup"))
+        }
+    }
+}
+
+/// Checks `MCP_PROXY`: an http, https, socks5 or socks5h URL with a host.
+/// reqwest resolves a SOCKS proxy's host here rather than per request.
+/// Errors never repeat the URL, which may carry a password.
+pub fn parse_proxy(spec: &str) -> Result<String, String> {
+    let url = url::Url::parse(spec.trim()).map_err(|e| format!("not a URL ({})", e))?;
+    if !["http", "https", "socks5", "socks5h"].contains(&url.scheme()) {
+        return Err(format!(
+            "unsupported scheme {:?}; use http, https, socks5 or socks5h",
+            url.scheme()
+        ));
+    }
+    if url.host_str().is_none_or(str::is_empty) {
+        return Err("no proxy host".to_string());
+    }
+    reqwest::Proxy::all(url.as_str()).map_err(|e| format!("unusable proxy ({})", e))?;
+    Ok(url.to_string())
+}
+
+/// Parses `MCP_RETRY_STATUSES`: comma-separated 5xx statuses, or `none`.
+pub fn parse_statuses(spec: &str) -> Result<Vec<u16>, String> {
+    if spec.trim() == "none" {
+        return Ok(Vec::new());
+    }
+    spec.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| match item.parse::<u16>() {
+            Ok(status) if (500..600).contains(&status) => Ok(status),
+            _ => Err(format!("{:?} is not a 5xx status", item)),
+        })
+        .collect()
+}
+
+/// Parses a `Retry-After` value, in delta-seconds or as an HTTP date, into
+/// the wait from `now`. A date in the past means no wait.
+pub(crate) fn retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) {
+        return value.parse().ok().map(Duration::from_secs);
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(now).unwrap_or(Duration::ZERO))
+}
+
+/// Where a request goes and the session headers it carries.
+pub(crate) struct Target<'a> {
+    pub url: String,
+    /// `Mcp-Session-Id`, per `MCP_SESSION_STYLE`.
+    pub session_id: Option<&'a str>,
+    /// `MCP-Protocol-Version`: `MCP_PROTOCOL_VERSION`, or else the version
+    /// `initialize` negotiated.
+    pub protocol_version: Option<&'a str>,
+}
+
+impl<'a> Target<'a> {
+    pub fn new(
+        config: &'a Config,
+        endpoint: &str,
+        session_id: Option<&'a str>,
+        negotiated: Option<&'a str>,
+    ) -> Self {
+        let endpoint = with_query_params(endpoint, &config.query_params);
+        let url = session::request_url(
+            &endpoint,
+            config.session_style,
+            &config.session_query_param,
+            session_id,
+        );
+        Self {
+            url,
+            session_id: session_id.filter(|_| config.session_style.uses_header()),
+            protocol_version: config.protocol_version.as_deref().or(negotiated),
+        }
+    }
+}
+
+/// POSTs `body` and returns the status, `Content-Type`, deprecation,
+/// rate-limit and session headers, and the body as it arrives.
+async fn fetch(
+    config: &Config,
+    client: &Client,
+    auth: &AuthProvider,
+    target: &Target<'_>,
+    body: &RequestBody,
+    timeout: Duration,
+    stats: &Stats,
+) -> Result<Fetched, TransportError> {
+    let text = body.text();
+    let (meta, edited) = meta_headers::take(config, &text);
+    let body = body.or_edited(edited);
     let mut req = client
-        .post(&config.uri)
+        .post(&target.url)
         .header("Content-Type", "application/json")
         .header("Accept", "application/json, text/event-stream")
-        .body(body.to_string());
-    if let Some(ref token) = config.bearer_token {
-        req = req.header("Authorization", format!("Bearer {}", token));
-    }
-    let res = req.send().await.map_err(TransportError::Network)?;
-    let status = res.status();
-    let content_type = res
-        .headers()
-        .get("Content-Type")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("")
-        .to_string();
-    let bytes = res.bytes().await.map_err(TransportError::Network)?;
-    if status.as_u16() == 202 {
-        return Ok(Vec::new());
+        .body(body.clone());
+    if let Some((name, value)) = auth.header().await? {
+        req = req.header(name, value);
     }
-    let body_str = String::from_utf8(bytes.to_vec()).map_err(|_| TransportError::InvalidUtf8)?;
-    if body_str.trim().is_empty() {
-        return Ok(Vec::new());
+    if let Some(version) = target.protocol_version {
+        req = req.header(session::PROTOCOL_VERSION_HEADER, version);
+    }
+    req = req.headers(extra_headers(config));
+    req = req.headers(meta);
+    req = req.headers(otel::headers());
+    req = req.headers(idempotency::headers(config));
+    if let Some(id) = target.session_id {
+        req = req.header(session::HEADER, id);
+    }
+    if config.integrity_header {
+        req = req.header(integrity::HEADER, integrity::sha256_hex(&body));
+    }
+    let mut res = redirect::send(config, client, req, timeout, stats).await?;
+    let header = |name| {
+        res.headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    };
+    let mut fetched = Fetched {
+        status: res.status().as_u16(),
+        content_type: header("Content-Type").unwrap_or_default(),
+        notices: UpstreamNotices::from_headers(res.headers()),
+        session_id: header(session::HEADER),
+        retry_after: header("Retry-After").and_then(|v| retry_after(&v, SystemTime::now())),
+        www_authenticate: header("WWW-Authenticate"),
+        body: stream::empty().boxed(),
+    };
+    if !config.integrity_header {
+        fetched.body = res
+            .bytes_stream()
+            .map(|chunk| {
+                chunk
+                    .map(|c| c.to_vec())
+                    .map_err(TransportError::Network)
+            })
+            .boxed();
+        return Ok(fetched);
+    }
+    let digest = header(integrity::HEADER);
+    // reqwest has no length for a body it decompresses, so such a body is
+    // checked against the digest alone.
+    let mut check = BodyCheck::new(res.content_length(), digest.as_deref());
+    let mut bytes = Vec::new();
+    loop {
+        let chunk = tokio::time::timeout(timeout, res.chunk())
+            .await
+            .map_err(|_| TransportError::Timeout)?;
+        match chunk {
+            Ok(Some(chunk)) => {
+                if bytes.len() + chunk.len() > config.max_message_bytes {
+                    return Err(TransportError::TooLarge(config.max_message_bytes));
+                }
+                check.update(&chunk);
+                bytes.extend_from_slice(&chunk);
+            }
+            Ok(None) => break,
+            Err(e) => {
+                return Err(match check.truncated() {
+                    Some(truncated) => TransportError::Integrity(truncated),
+                    None => TransportError::Network(e),
+                })
+            }
+        }
     }
-    if content_type.contains("text/event-stream") {
-        let messages = parse_sse_to_json_lines(&body_str);
-        debug!(count = messages.len(), "parsed SSE response");
-        return Ok(messages);
+    check.finish().map_err(TransportError::Integrity)?;
+    fetched.body = stream::once(async { Ok(bytes) }).boxed();
+    Ok(fetched)
+}
+
+/// Sends the GET that opens the server's event stream, resuming after
+/// `last_event_id` when given.
+async fn fetch_stream(
+    config: &Config,
+    client: &Client,
+    auth: &AuthProvider,
+    target: &Target<'_>,
+    last_event_id: Option<&str>,
+    stats: &Stats,
+) -> Result<Fetched, TransportError> {
+    let mut req = client.get(&target.url).header("Accept", "text/event-stream");
+    if let Some((name, value)) = auth.header().await? {
+        req = req.header(name, value);
     }
-    Ok(vec![body_str])
+    if let Some(version) = target.protocol_version {
+        req = req.header(session::PROTOCOL_VERSION_HEADER, version);
+    }
+    req = req.headers(extra_headers(config));
+    if let Some(id) = target.session_id {
+        req = req.header(session::HEADER, id);
+    }
+    if let Some(id) = last_event_id {
+        req = req.header(LAST_EVENT_ID, id);
+    }
+    let res = redirect::send(config, client, req, config.timeout, stats).await?;
+    let header = |name| {
+        res.headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    };
+    Ok(Fetched {
+        status: res.status().as_u16(),
+        content_type: header("Content-Type").unwrap_or_default(),
+        notices: UpstreamNotices::from_headers(res.headers()),
+        session_id: header(session::HEADER),
+        retry_after: header("Retry-After").and_then(|v| retry_after(&v, SystemTime::now())),
+        www_authenticate: header("WWW-Authenticate"),
+        body: res
+            .bytes_stream()
+            .map(|chunk| {
+                chunk
+                    .map(|c| c.to_vec())
+                    .map_err(TransportError::Network)
+            })
+            .boxed(),
+    })
+}
+
+/// Messages, the latest `retry:` reconnection hint and the last event id
+/// from an SSE body.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SseBody {
+    pub messages: Vec<String>,
+    pub retry: Option<Duration>,
+    pub last_event_id: Option<String>,
 }
 
+#[cfg(test)]
 fn parse_sse_to_json_lines(s: &str) -> Vec<String> {
-    let mut out = Vec::new();
-    let mut data_buf = String::new();
-    for line in s.lines() {
-        if line.starts_with("data:") {
-            let rest = line[5..].trim();
-            if rest == "[DONE]" {
-                continue;
+    parse_sse(s).messages
+}
+
+pub fn parse_sse(s: &str) -> SseBody {
+    let mut decoder = SseDecoder::default();
+    let mut messages = decoder.feed(s.as_bytes(), Utf8Mode::Strict).unwrap_or_default();
+    messages.extend(decoder.finish(Utf8Mode::Strict).unwrap_or_default());
+    SseBody {
+        messages,
+        retry: decoder.retry,
+        last_event_id: decoder.last_event_id,
+    }
+}
+
+/// Incremental SSE parser: bytes go in as they arrive, and each event comes
+/// out once the blank line ending it has been read. A chunk may end
+/// anywhere, even inside a UTF-8 sequence or between the `\r` and `\n` of a
+/// line ending, since only whole lines are decoded. Lines end with `\n`,
+/// `\r\n` or a lone `\r`; `:` comment lines are ignored. An event's `data:`
+/// lines are joined with newlines, and an event with no data is not
+/// dispatched. An event carrying an id already dispatched is a replay after
+/// a resumption and is dropped.
+#[derive(Clone, Debug, Default)]
+pub struct SseDecoder {
+    line: Vec<u8>,
+    /// Offset in the body of `line`'s first byte, for error reports.
+    line_start: usize,
+    /// Bytes of the body fed so far.
+    fed: usize,
+    /// The previous chunk ended with `\r`, so a leading `\n` belongs to it.
+    after_cr: bool,
+    event: Event,
+    last_event_id: Option<String>,
+    retry: Option<Duration>,
+    /// Ids of the latest dispatched events, oldest first.
+    seen: VecDeque<String>,
+}
+
+/// Splits a body holding a sequence of JSON documents: one per line for
+/// NDJSON (`application/x-ndjson`, `application/jsonl`), or each after an
+/// RS byte for JSON text sequences (`application/json-seq`, RFC 7464), which
+/// may span lines. Blank records are skipped, and a record that spans lines
+/// is compacted onto one, since each message goes out as a line of stdout.
+#[derive(Debug)]
+struct SeqDecoder {
+    separator: u8,
+    /// Compact records that span lines; refuse them when false.
+    compact: bool,
+    record: Vec<u8>,
+    /// Offset in the body of `record`'s first byte, for error reports.
+    record_start: usize,
+    /// Bytes of the body fed so far.
+    fed: usize,
+}
+
+/// Media types of newline-delimited JSON.
+const NDJSON_TYPES: &[&str] = &[
+    "application/x-ndjson",
+    "application/ndjson",
+    "application/jsonl",
+    "application/x-jsonlines",
+];
+
+/// The record separator of `application/json-seq`.
+const RS: u8 = 0x1e;
+
+impl SeqDecoder {
+    /// A decoder for bodies of `content_type`, if it is a JSON sequence.
+    fn for_content_type(content_type: &str) -> Option<Self> {
+        let separator = if NDJSON_TYPES.iter().any(|t| content_type.contains(t)) {
+            b'\n'
+        } else if content_type.contains("application/json-seq") {
+            RS
+        } else {
+            return None;
+        };
+        Some(Self {
+            separator,
+            compact: true,
+            record: Vec::new(),
+            record_start: 0,
+            fed: 0,
+        })
+    }
+
+    /// Returns the documents that `chunk` completed.
+    fn feed(&mut self, chunk: &[u8], mode: Utf8Mode) -> Result<Vec<String>, TransportError> {
+        let mut records = Vec::new();
+        let mut rest = chunk;
+        let base = self.fed;
+        self.fed += chunk.len();
+        while let Some(at) = rest.iter().position(|&b| b == self.separator) {
+            self.record.extend_from_slice(&rest[..at]);
+            rest = &rest[at + 1..];
+            let record = std::mem::take(&mut self.record);
+            let start = std::mem::replace(&mut self.record_start, base + chunk.len() - rest.len());
+            records.extend(self.end_record(record, start, mode)?);
+        }
+        self.record.extend_from_slice(rest);
+        Ok(records)
+    }
+
+    /// The body has ended: a last document without a separator after it is
+    /// complete anyway.
+    fn finish(&mut self, mode: Utf8Mode) -> Result<Option<String>, TransportError> {
+        let record = std::mem::take(&mut self.record);
+        self.end_record(record, self.record_start, mode)
+    }
+
+    /// Bytes of the document not yet complete.
+    fn pending(&self) -> usize {
+        self.record.len()
+    }
+
+    /// Decodes one record, which began at offset `start` in the body, and
+    /// trims the whitespace around it (a line's `\r`, or the newline ending
+    /// a JSON text sequence record).
+    fn end_record(
+        &self,
+        record: Vec<u8>,
+        start: usize,
+        mode: Utf8Mode,
+    ) -> Result<Option<String>, TransportError> {
+        let mut record = stdio::decode_utf8(record, mode, "upstream response")
+            .map_err(|offset| TransportError::InvalidUtf8(start + offset))?;
+        record.truncate(record.trim_end().len());
+        let blank = record.len() - record.trim_start().len();
+        record.drain(..blank);
+        if record.contains(['\n', '\r']) {
+            record = self
+                .compact
+                .then(|| compact_json(&record))
+                .flatten()
+                .ok_or(TransportError::SplitRecord(start))?;
+        }
+        Ok(Some(record).filter(|r| !r.is_empty()))
+    }
+}
+
+/// `json` without the whitespace between its tokens, strings left as they
+/// are; `None` when a line break sits inside a string, which JSON does not
+/// allow.
+fn compact_json(json: &str) -> Option<String> {
+    let mut out = String::with_capacity(json.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in json.chars() {
+        if in_string {
+            if matches!(c, '\n' | '\r') {
+                return None;
+            }
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+        } else if matches!(c, ' ' | '\t' | '\n' | '\r') {
+            continue;
+        } else if c == '"' {
+            in_string = true;
+        }
+        out.push(c);
+    }
+    Some(out)
+}
+
+/// Event ids remembered to recognise events a resumed stream replays.
+const REPLAY_WINDOW: usize = 1024;
+
+/// The fields of the event being received.
+#[derive(Clone, Debug, Default)]
+struct Event {
+    name: Option<String>,
+    /// The event's own `id:` field, as opposed to one inherited from an
+    /// earlier event.
+    id: Option<String>,
+    data: String,
+    /// Whether a `data` field was seen, so the next one starts a new line.
+    has_data: bool,
+}
+
+impl SseDecoder {
+    /// Returns the events that `chunk` completed.
+    pub fn feed(&mut self, chunk: &[u8], mode: Utf8Mode) -> Result<Vec<String>, TransportError> {
+        let mut events = Vec::new();
+        let mut rest = chunk;
+        let base = self.fed;
+        self.fed += chunk.len();
+        if std::mem::take(&mut self.after_cr) {
+            if let Some(after) = rest.strip_prefix(b"\n") {
+                rest = after;
+                self.line_start += 1;
             }
-            if !data_buf.is_empty() {
-                data_buf.push('\n');
+        }
+        while let Some(at) = rest.iter().position(|&b| b == b'\n' || b == b'\r') {
+            self.line.extend_from_slice(&rest[..at]);
+            let cr = rest[at] == b'\r';
+            rest = &rest[at + 1..];
+            if cr {
+                match rest.first() {
+                    Some(b'\n') => rest = &rest[1..],
+                    Some(_) => {}
+                    None => self.after_cr = true,
+                }
             }
-            data_buf.push_str(rest);
-        } else if line.trim().is_empty() {
-            if !data_buf.is_empty() {
-                out.push(std::mem::take(&mut data_buf));
+            let line = std::mem::take(&mut self.line);
+            let start = std::mem::replace(&mut self.line_start, base + chunk.len() - rest.len());
+            events.extend(self.end_line(line, start, mode)?);
+        }
+        self.line.extend_from_slice(rest);
+        Ok(events)
+    }
+
+    /// The body has ended: an event it did not terminate is complete anyway.
+    pub fn finish(&mut self, mode: Utf8Mode) -> Result<Option<String>, TransportError> {
+        let line = std::mem::take(&mut self.line);
+        if !line.is_empty() {
+            if let Some(event) = self.end_line(line, self.line_start, mode)? {
+                return Ok(Some(event));
             }
         }
+        Ok(self.dispatch())
     }
-    if !data_buf.is_empty() {
-        out.push(data_buf);
+
+    /// The latest `retry:` reconnection hint.
+    pub fn retry(&self) -> Option<Duration> {
+        self.retry
+    }
+
+    /// The latest `id:` seen, which a reconnection sends back as
+    /// `Last-Event-ID`. An empty `id:` clears it.
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.last_event_id.as_deref()
+    }
+
+    /// Bytes of the event not yet complete, without a partial line's
+    /// `data: ` field name.
+    pub fn pending(&self) -> usize {
+        let line = self.line.strip_prefix(b"data:").unwrap_or(&self.line);
+        let line = line.strip_prefix(b" ").unwrap_or(line);
+        self.event.data.len() + line.len()
+    }
+
+    /// Handles one complete line, which began at offset `start` in the body.
+    fn end_line(
+        &mut self,
+        line: Vec<u8>,
+        start: usize,
+        mode: Utf8Mode,
+    ) -> Result<Option<String>, TransportError> {
+        if line.is_empty() {
+            return Ok(self.dispatch());
+        }
+        if line[0] == b':' {
+            return Ok(None);
+        }
+        let line = stdio::decode_utf8(line, mode, "upstream response")
+            .map_err(|offset| TransportError::InvalidUtf8(start + offset))?;
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line.as_str(), ""),
+        };
+        match field {
+            "data" => {
+                if self.event.has_data {
+                    self.event.data.push('\n');
+                }
+                self.event.data.push_str(value);
+                self.event.has_data = true;
+            }
+            "event" => self.event.name = Some(value.to_string()),
+            // Per the SSE spec an id containing NUL is ignored.
+            "id" if !value.contains('\0') => {
+                self.last_event_id = Some(value.to_string()).filter(|v| !v.is_empty());
+                self.event.id = self.last_event_id.clone();
+            }
+            // Per the SSE spec a non-integer value is ignored.
+            "retry" if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) => {
+                if let Ok(ms) = value.parse() {
+                    self.retry = Some(Duration::from_millis(ms));
+                }
+            }
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    /// Ends the current event, returning its data unless it was empty or
+    /// the `[DONE]` sentinel some servers close a stream with.
+    fn dispatch(&mut self) -> Option<String> {
+        let event = std::mem::take(&mut self.event);
+        if event.data.is_empty() || event.data.trim() == "[DONE]" {
+            return None;
+        }
+        if let Some(id) = event.id {
+            if self.seen.contains(&id) {
+                debug!(id = %id, "dropping SSE event replayed after resuming");
+                return None;
+            }
+            if self.seen.len() == REPLAY_WINDOW {
+                self.seen.pop_front();
+            }
+            self.seen.push_back(id);
+        }
+        if let Some(name) = event.name.filter(|n| n != "message") {
+            debug!(event = %name, "upstream SSE event with a custom type");
+        }
+        Some(event.data)
+    }
+
+    /// A decoder for the stream resuming this one: it starts from this
+    /// one's last event id and recognises the events already dispatched.
+    fn resumed(&self) -> Self {
+        Self {
+            last_event_id: self.last_event_id.clone(),
+            retry: self.retry,
+            seen: self.seen.clone(),
+            ..Self::default()
+        }
     }
-    out
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn http_versions_parse() {
+        assert_eq!("HTTP2-Prior-Knowledge".parse(), Ok(HttpVersion::Http2PriorKnowledge));
+        assert_eq!("http1".parse(), Ok(HttpVersion::Http1));
+        assert!("h2c".parse::<HttpVersion>().is_err());
+        let refused = TransportError::Http2Refused("channel closed".to_string());
+        assert!(!is_retryable(&refused));
+        assert!(refused.to_string().contains("MCP_HTTP_VERSION=http2-prior-knowledge"));
+    }
+
+    #[test]
+    fn redirect_policies_parse() {
+        assert_eq!("Follow-Same-Host".parse(), Ok(RedirectPolicy::FollowSameHost));
+        assert_eq!(" none".parse(), Ok(RedirectPolicy::None));
+        assert_eq!(RedirectPolicy::default().as_str(), "follow");
+        assert!("manual".parse::<RedirectPolicy>().is_err());
+    }
 
     #[test]
     fn parse_sse_multiple_events() {
@@ -126,4 +1599,298 @@ mod tests {
         assert!(out[0].contains("\"result\""));
         assert!(out[1].contains("\"method\""));
     }
+
+    #[test]
+    fn parse_sse_exposes_latest_retry_hint() {
+        let s = "retry: 5000\ndata: {\"a\":1}\n\nretry:10000\n\nretry: soon\n\n";
+        let body = parse_sse(s);
+        assert_eq!(body.messages, vec!["{\"a\":1}"]);
+        assert_eq!(body.retry, Some(Duration::from_millis(10_000)));
+        assert_eq!(parse_sse("data: {}\n\n").retry, None);
+    }
+
+    #[test]
+    fn retry_after_takes_seconds_or_a_date() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_445_412_480);
+        assert_eq!(retry_after(" 2 ", now), Some(Duration::from_secs(2)));
+        let date = "Wed, 21 Oct 2015 07:28:30 GMT";
+        assert_eq!(retry_after(date, now), Some(Duration::from_secs(30)));
+        let past = "Wed, 21 Oct 2015 07:27:00 GMT";
+        assert_eq!(retry_after(past, now), Some(Duration::ZERO));
+        assert_eq!(retry_after("-1", now), None);
+        assert_eq!(retry_after("soon", now), None);
+    }
+
+    #[test]
+    fn statuses_map_to_their_own_codes() {
+        let auth = status_error(401, "<html>denied</html>");
+        assert_eq!(
+            auth,
+            json!({
+                "code": jsonrpc::UPSTREAM_AUTH_FAILED,
+                "message": "upstream authentication failed: HTTP 401 Unauthorized",
+                "data": { "status": 401, "body": "<html>denied</html>" }
+            })
+        );
+        assert_eq!(status_error(403, "")["code"], jsonrpc::UPSTREAM_AUTH_FAILED);
+        assert_eq!(status_error(404, "")["code"], jsonrpc::UPSTREAM_NOT_FOUND);
+        assert_eq!(status_error(413, "")["code"], jsonrpc::UPSTREAM_PAYLOAD_TOO_LARGE);
+        assert_eq!(status_error(429, "")["code"], jsonrpc::UPSTREAM_RATE_LIMITED);
+        assert_eq!(
+            status_error(500, ""),
+            json!({
+                "code": jsonrpc::INTERNAL_ERROR,
+                "message": "bridge transport error: upstream answered HTTP 500 Internal Server Error",
+                "data": { "status": 500 }
+            })
+        );
+        assert_eq!(
+            status_error(599, "")["message"],
+            "bridge transport error: upstream answered HTTP 599"
+        );
+    }
+
+    #[test]
+    fn headers_split_on_commas_and_newlines() {
+        let headers = parse_headers(
+            "CF-Access-Client-Id: abc, CF-Access-Client-Secret: x:y\nAccept: a/b, c/d",
+        )
+        .unwrap();
+        let pairs = [
+            ("CF-Access-Client-Id", "abc"),
+            ("CF-Access-Client-Secret", "x:y"),
+            ("Accept", "a/b, c/d"),
+        ];
+        let expected: Vec<(String, String)> = pairs
+            .iter()
+            .map(|(n, v)| (n.to_string(), v.to_string()))
+            .collect();
+        assert_eq!(headers, expected);
+        assert_eq!(parse_headers(" X-Empty: ").unwrap(), vec![("X-Empty".into(), "".into())]);
+    }
+
+    #[test]
+    fn bad_headers_are_named_without_their_values() {
+        assert_eq!(
+            parse_headers("X Bad: 1").unwrap_err(),
+            r#"invalid header name "X Bad""#
+        );
+        let no_name = "expected `Name: value`, found a part with no name";
+        assert_eq!(parse_headers("secret-token").unwrap_err(), no_name);
+        assert_eq!(parse_headers("X-A: 1\n, 2").unwrap_err(), no_name);
+        let err = parse_headers("X-Secret: s3cr\u{7f}t").unwrap_err();
+        assert_eq!(err, r#"invalid value for header "X-Secret""#);
+    }
+
+    #[test]
+    fn query_params_parse_as_pairs() {
+        assert_eq!(
+            parse_query_params(" agent=planner, env= ,").unwrap(),
+            [("agent".to_string(), "planner".to_string()), ("env".to_string(), String::new())]
+        );
+        assert!(parse_query_params("agent").is_err());
+        assert!(parse_query_params("=planner").is_err());
+    }
+
+    #[test]
+    fn query_params_are_merged_into_the_uri() {
+        let params = parse_query_params("agent=planner,env=staging area").unwrap();
+        assert_eq!(
+            with_query_params("https://example.test/mcp", &params),
+            "https://example.test/mcp?agent=planner&env=staging+area"
+        );
+        assert_eq!(
+            with_query_params("https://example.test/mcp?agent=old&sig=a%2Fb&agent=x#f", &params),
+            "https://example.test/mcp?sig=a%2Fb&agent=planner&env=staging+area#f"
+        );
+        assert_eq!(
+            with_query_params("https://example.test/mcp?", &[]),
+            "https://example.test/mcp?"
+        );
+    }
+
+    #[test]
+    fn proxy_mode_follows_mcp_proxy_and_mcp_no_proxy() {
+        let mode = |vars: &[(&str, &str)]| {
+            let vars: Vec<(String, String)> =
+                vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+            proxy_mode(&Config::from_lookup(|name| {
+                vars.iter().find(|(k, _)| k == name).map(|(_, v)| v.clone())
+            }))
+        };
+        assert_eq!(mode(&[]), ProxyMode::System);
+        assert_eq!(mode(&[("MCP_NO_PROXY", "true")]), ProxyMode::Direct);
+        assert_eq!(
+            mode(&[("MCP_PROXY", "socks5h://127.0.0.1:1080")]),
+            ProxyMode::Url("socks5h://127.0.0.1:1080".into())
+        );
+        assert_eq!(
+            mode(&[("MCP_PROXY", "http://proxy.internal:3128"), ("MCP_NO_PROXY", "1")]),
+            ProxyMode::Direct
+        );
+    }
+
+    #[test]
+    fn proxy_errors_do_not_repeat_the_url() {
+        assert_eq!(
+            parse_proxy("http://user:pw@proxy:3128"),
+            Ok("http://user:pw@proxy:3128/".into())
+        );
+        assert_eq!(
+            parse_proxy("user:pw@proxy:3128").unwrap_err(),
+            r#"unsupported scheme "user"; use http, https, socks5 or socks5h"#
+        );
+        assert_eq!(
+            parse_proxy("proxy-pw").unwrap_err(),
+            "not a URL (relative URL without a base)"
+        );
+        assert_eq!(parse_proxy("socks5://").unwrap_err(), "no proxy host");
+    }
+
+    #[test]
+    fn retry_statuses_must_be_5xx() {
+        assert_eq!(parse_statuses(" 502, 504,,"), Ok(vec![502, 504]));
+        assert_eq!(parse_statuses("none"), Ok(vec![]));
+        assert!(parse_statuses("502,404").unwrap_err().contains("\"404\""));
+        assert!(parse_statuses("bad").is_err());
+    }
+
+    #[test]
+    fn sse_events_survive_any_chunk_boundary() {
+        let body = "event: message\r\nid: 7\r\ndata: {\"s\":\"é\"}\r\n\r\n: ping\rdata: a\rdata: b\r\rid: 8\ndata: tail".as_bytes();
+        for at in 0..=body.len() {
+            let mut decoder = SseDecoder::default();
+            let mut events = decoder.feed(&body[..at], Utf8Mode::Strict).unwrap();
+            events.extend(decoder.feed(&body[at..], Utf8Mode::Strict).unwrap());
+            assert_eq!(events, vec!["{\"s\":\"é\"}", "a\nb"], "split at {}", at);
+            assert_eq!(decoder.finish(Utf8Mode::Strict).unwrap().as_deref(), Some("tail"));
+            assert_eq!(decoder.last_event_id(), Some("8"), "split at {}", at);
+        }
+    }
+
+    #[test]
+    fn sse_comments_and_unknown_fields_are_ignored() {
+        let s = ": keep-alive\n\n:\nfoo: bar\ndata: {}\n: mid-event\n\n";
+        assert_eq!(parse_sse(s).messages, vec!["{}"]);
+        assert_eq!(parse_sse(": only a comment\n\n"), SseBody::default());
+    }
+
+    #[test]
+    fn sse_data_lines_join_within_an_event_only() {
+        let s = "data: {\"a\":\ndata: 1}\n\ndata: {\"b\":2}\n\n";
+        assert_eq!(parse_sse(s).messages, vec!["{\"a\":\n1}", "{\"b\":2}"]);
+        // Only one space after the colon is part of the syntax.
+        assert_eq!(parse_sse("data:  x \ndata:y\n\n").messages, vec![" x \ny"]);
+        // A `data` field with no colon is an empty line of data.
+        assert_eq!(parse_sse("data\ndata: z\n\n").messages, vec!["\nz"]);
+        assert_eq!(parse_sse("data:\n\nevent: x\n\n").messages, Vec::<String>::new());
+    }
+
+    #[test]
+    fn sse_tracks_event_names_and_the_last_event_id() {
+        let s = "event: message\nid: 1\ndata: a\n\nevent: custom\ndata: b\n\nid: 2\n\n";
+        let body = parse_sse(s);
+        assert_eq!(body.messages, vec!["a", "b"]);
+        assert_eq!(body.last_event_id.as_deref(), Some("2"));
+        assert_eq!(parse_sse("id: 3\nid\n\n").last_event_id, None);
+        assert_eq!(parse_sse("id: 4\nid: a\0b\n\n").last_event_id.as_deref(), Some("4"));
+    }
+
+    #[test]
+    fn resumed_decoder_drops_replayed_events() {
+        let mut first = SseDecoder::default();
+        let events = first.feed(b"id: 1\ndata: a\n\ndata: b\n\n", Utf8Mode::Strict);
+        assert_eq!(events.unwrap(), vec!["a", "b"]);
+        let mut resumed = first.resumed();
+        assert_eq!(resumed.last_event_id(), Some("1"));
+        let replay = b"id: 1\ndata: a\n\ndata: b\n\nid: 2\ndata: c\n\n";
+        // Events without an id of their own cannot be recognised.
+        assert_eq!(resumed.feed(replay, Utf8Mode::Strict).unwrap(), vec!["b", "c"]);
+        assert_eq!(resumed.last_event_id(), Some("2"));
+    }
+
+    #[test]
+    fn sse_done_sentinel_ends_no_message() {
+        assert_eq!(parse_sse("data: [DONE]\n\n").messages, Vec::<String>::new());
+        assert_eq!(parse_sse("data: x\n\ndata: [DONE]").messages, vec!["x"]);
+    }
+
+    fn response(content_type: &str, chunks: &[&str], max: usize) -> Response {
+        let chunks: Vec<Result<Vec<u8>, TransportError>> =
+            chunks.iter().map(|c| Ok(c.as_bytes().to_vec())).collect();
+        let fetched = Fetched {
+            status: 200,
+            content_type: content_type.to_string(),
+            notices: UpstreamNotices::default(),
+            session_id: None,
+            retry_after: None,
+            www_authenticate: None,
+            body: futures_util::stream::iter(chunks).boxed(),
+        };
+        Response::new(fetched, None, Utf8Mode::Strict, max)
+    }
+
+    #[tokio::test]
+    async fn json_body_is_capped_at_max_message_bytes() {
+        let body = r#"{"jsonrpc":"2.0","id":1,"result":{}}"#;
+        let (head, tail) = body.split_at(10);
+        let lines = response("application/json", &[head, tail], body.len()).collect().await;
+        assert_eq!(lines.unwrap(), vec![body]);
+        let over = response("application/json", &[head, tail], body.len() - 1).collect().await;
+        assert!(matches!(over, Err(TransportError::TooLarge(n)) if n == body.len() - 1));
+    }
+
+    #[tokio::test]
+    async fn sse_event_is_capped_at_max_message_bytes() {
+        let event = "data: 0123456789\n\n";
+        let lines = response("text/event-stream", &[event, event], 10).collect().await;
+        assert_eq!(lines.unwrap(), vec!["0123456789", "0123456789"]);
+        let split = response("text/event-stream", &["data: 0123456789", "\n\n"], 10);
+        assert_eq!(split.collect().await.unwrap(), vec!["0123456789"]);
+        let over = response("text/event-stream", &[event], 9).collect().await;
+        assert!(matches!(over, Err(TransportError::TooLarge(9))));
+        // An event still being received counts too.
+        let mut endless = response("text/event-stream", &["data: 01234", "56789"], 9);
+        assert!(matches!(endless.next().await, Err(TransportError::TooLarge(9))));
+    }
+
+    #[tokio::test]
+    async fn ndjson_lines_are_separate_messages() {
+        let chunks = ["{\"id\":1}\r\n\n{\"id\"", ":2}\n  \n{\"id\":3}"];
+        let mut ndjson = response("application/x-ndjson; charset=utf-8", &chunks, 10);
+        assert!(ndjson.is_stream());
+        assert_eq!(ndjson.next().await.unwrap().as_deref(), Some("{\"id\":1}"));
+        assert_eq!(ndjson.collect().await.unwrap(), vec!["{\"id\":2}", "{\"id\":3}"]);
+        let over = response("application/jsonl", &["{\"id\":1}\n", "{\"id\":10}"], 8);
+        assert!(matches!(over.collect().await, Err(TransportError::TooLarge(8))));
+        // A record spanning lines goes out on one.
+        let body = "\x1e{\n \"id\": 1,\n \"s\": \"a \\\" b\"\n}\n\x1e[]\n";
+        let seq = response("application/json-seq", &[body], 64);
+        assert_eq!(seq.collect().await.unwrap(), vec![r#"{"id":1,"s":"a \" b"}"#, "[]"]);
+        let raw = response("application/json-seq", &[body], 64).passthrough(true);
+        assert!(matches!(raw.collect().await, Err(TransportError::SplitRecord(1))));
+        let broken = response("application/json-seq", &["\x1e\"a\nb\"\n"], 64);
+        assert!(matches!(broken.collect().await, Err(TransportError::SplitRecord(1))));
+        assert!(!response("application/json", &[], 64).is_stream());
+    }
+
+    #[test]
+    fn ndjson_utf8_errors_count_from_the_body_start() {
+        let mut decoder = SeqDecoder::for_content_type("application/x-ndjson").unwrap();
+        assert_eq!(decoder.feed(b"{}\n{\"s\":", Utf8Mode::Strict).unwrap(), vec!["{}"]);
+        let invalid = decoder.feed(b"\"\xff\"}\n", Utf8Mode::Strict);
+        assert!(matches!(invalid, Err(TransportError::InvalidUtf8(9))));
+    }
+
+    #[test]
+    fn sse_event_is_released_by_its_blank_line() {
+        let mut decoder = SseDecoder::default();
+        assert!(decoder.feed(b"data: {}\n", Utf8Mode::Strict).unwrap().is_empty());
+        assert_eq!(decoder.feed(b"\n", Utf8Mode::Strict).unwrap(), vec!["{}"]);
+        assert_eq!(decoder.finish(Utf8Mode::Strict).unwrap(), None);
+        // Offsets count from the start of the body, across chunks.
+        assert!(decoder.feed(b": ping\r", Utf8Mode::Strict).unwrap().is_empty());
+        let invalid = decoder.feed(b"\ndata: \xff\n", Utf8Mode::Strict);
+        assert!(matches!(invalid, Err(TransportError::InvalidUtf8(24))));
+    }
 }
@@ -0,0 +1,81 @@
+//! JSON report describing why and how the bridge exited, written to stderr
+//! and optionally to `MCP_SHUTDOWN_REPORT_PATH`. Built only from shared
+//! stats so it is still produced when stdout is the thing that failed.
+
+use crate::stats::Stats;
+use serde_json::{json, Value};
+use std::path::Path;
+use std::sync::OnceLock;
+use tracing::warn;
+
+/// The first recorded reason the bridge began shutting down.
+#[derive(Debug, Default)]
+pub struct ExitReason(OnceLock<&'static str>);
+
+impl ExitReason {
+    /// Records `reason` unless one was already recorded.
+    pub fn set(&self, reason: &'static str) {
+        let _ = self.0.set(reason);
+    }
+
+    pub fn get(&self) -> Option<&'static str> {
+        self.0.get().copied()
+    }
+}
+
+pub fn build(reason: &str, stats: &Stats, drain_completed: bool) -> Value {
+    let snapshot = stats.snapshot();
+    json!({
+        "event": "bridge_shutdown",
+        "reason": reason,
+        "uptime_secs": snapshot["uptime_secs"],
+        "terminated_ids": stats.terminated_ids(),
+        // Null without MCP_CB_FAILURES.
+        "circuit": snapshot["circuit"],
+        "failure_streak": snapshot["failure_streak"],
+        "last_transport_error": stats.last_transport_error(),
+        "drain_completed": drain_completed,
+        "stats": snapshot,
+    })
+}
+
+/// Writes the report as one line to stderr and, when `path` is set, to that
+/// file. A file write failure is logged and otherwise ignored.
+pub fn write(report: &Value, path: Option<&Path>) {
+    eprintln!("{}", report);
+    if let Some(path) = path {
+        if let Err(e) = std::fs::write(path, format!("{:#}\n", report)) {
+            warn!(%e, path = %path.display(), "could not write shutdown report");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_carries_reason_ids_and_last_error() {
+        let stats = Stats::new(8);
+        stats.note_terminated(json!("slow"));
+        stats.note_transport_error("network: connection refused");
+        let report = build("signal: SIGTERM", &stats, false);
+        assert_eq!(report["reason"], "signal: SIGTERM");
+        assert_eq!(report["terminated_ids"], json!(["slow"]));
+        assert_eq!(
+            report["last_transport_error"],
+            "network: connection refused"
+        );
+        assert_eq!(report["drain_completed"], false);
+        assert_eq!(report["stats"]["requests"], 0);
+    }
+
+    #[test]
+    fn first_exit_reason_wins() {
+        let reason = ExitReason::default();
+        assert_eq!(reason.get(), None);
+        reason.set("stdin closed");
+        reason.set("bridge stopped");
+        assert_eq!(reason.get(), Some("stdin closed"));
+    }
+}
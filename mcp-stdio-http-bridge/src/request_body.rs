@@ -0,0 +1,181 @@
+//! A client message on its way upstream. The line read from the client is
+//! moved in without a copy, and every attempt to send it, retries, hedges
+//! and redirects included, hands the HTTP client another handle to the same
+//! bytes rather than a fresh `String`. Under `MCP_STDIN_VALIDATION=bytes`
+//! the line is taken as read, so the body need not be UTF-8.
+
+use bytes::Bytes;
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::Arc;
+use tokio::sync::OwnedSemaphorePermit;
+
+/// A place in the listener-wide budget (`MCP_LISTEN_ADDR`), shared by
+/// every handle to the message it admitted.
+pub type Admission = Arc<OwnedSemaphorePermit>;
+
+/// The body of an upstream request: JSON text, or under
+/// `MCP_STDIN_VALIDATION=bytes` whatever bytes the client sent.
+#[derive(Clone)]
+pub struct RequestBody {
+    bytes: Bytes,
+    /// Whether `bytes` is UTF-8, checked once when the body is made.
+    utf8: bool,
+    admission: Option<Admission>,
+}
+
+impl RequestBody {
+    /// The body holding `admission` until its last handle is dropped, so
+    /// the message counts against the listener's budget until its request
+    /// ends. `None` gives the place back.
+    pub fn admitted(mut self, admission: Option<Admission>) -> Self {
+        self.admission = admission;
+        self
+    }
+
+    pub fn admission(&self) -> Option<Admission> {
+        self.admission.clone()
+    }
+
+    /// A handle to the bytes, for one attempt's HTTP body.
+    pub fn bytes(&self) -> Bytes {
+        self.bytes.clone()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// The body as text, or `None` when it is not UTF-8.
+    pub fn as_str(&self) -> Option<&str> {
+        // SAFETY: `utf8` is only set for bytes that were checked or came
+        // from a `String` or `&str`.
+        self.utf8
+            .then(|| unsafe { std::str::from_utf8_unchecked(&self.bytes) })
+    }
+
+    /// The body as a `String` to rewrite, handed back when it is not UTF-8.
+    pub fn into_string(self) -> Result<String, Self> {
+        if !self.utf8 {
+            return Err(self);
+        }
+        // SAFETY: as for `as_str`. A body made from a `String` gets its
+        // buffer back without a copy.
+        Ok(unsafe { String::from_utf8_unchecked(Vec::from(self.bytes)) })
+    }
+
+    /// The body as text for reading its ids and method, with each invalid
+    /// sequence read as U+FFFD. What is sent upstream stays `bytes`.
+    pub fn text(&self) -> Cow<'_, str> {
+        match self.as_str() {
+            Some(text) => Cow::Borrowed(text),
+            None => String::from_utf8_lossy(&self.bytes),
+        }
+    }
+
+    /// What to send when the body may have been rewritten on the way, as
+    /// `MCP_META_STRIP` does: these bytes unless `edited` is a new copy.
+    /// A body that is not UTF-8 is never rewritten.
+    pub fn or_edited(&self, edited: Cow<'_, str>) -> Bytes {
+        match edited {
+            Cow::Owned(edited) if self.utf8 => Bytes::from(edited),
+            _ => self.bytes(),
+        }
+    }
+}
+
+impl From<String> for RequestBody {
+    fn from(body: String) -> Self {
+        Self {
+            bytes: Bytes::from(body),
+            utf8: true,
+            admission: None,
+        }
+    }
+}
+
+impl From<&str> for RequestBody {
+    fn from(body: &str) -> Self {
+        Self {
+            bytes: Bytes::copy_from_slice(body.as_bytes()),
+            utf8: true,
+            admission: None,
+        }
+    }
+}
+
+/// A line read as bytes, UTF-8 or not.
+impl From<Vec<u8>> for RequestBody {
+    fn from(body: Vec<u8>) -> Self {
+        Self {
+            utf8: std::str::from_utf8(&body).is_ok(),
+            bytes: Bytes::from(body),
+            admission: None,
+        }
+    }
+}
+
+/// Bodies are equal when their bytes are, admitted or not.
+impl PartialEq for RequestBody {
+    fn eq(&self, other: &Self) -> bool {
+        self.bytes == other.bytes && self.utf8 == other.utf8
+    }
+}
+
+impl Eq for RequestBody {}
+
+impl fmt::Debug for RequestBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.text(), f)
+    }
+}
+
+impl fmt::Display for RequestBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_attempt_shares_the_line_read() {
+        let line = r#"{"jsonrpc":"2.0","id":1,"method":"resources/write"}"#.to_string();
+        let read_at = line.as_ptr();
+        let body = RequestBody::from(line);
+        assert_eq!(body.as_bytes().as_ptr(), read_at);
+        let attempts = [body.bytes(), body.clone().bytes(), body.or_edited(body.text())];
+        for sent in attempts {
+            assert_eq!(sent.as_ptr(), read_at);
+            assert_eq!(sent, body.as_bytes());
+        }
+    }
+
+    #[test]
+    fn an_edited_body_is_sent_instead() {
+        let body = RequestBody::from(r#"{"params":{"_meta":{"k":1}}}"#);
+        let sent = body.or_edited(Cow::Owned(r#"{"params":{"_meta":{}}}"#.to_string()));
+        assert_eq!(sent, r#"{"params":{"_meta":{}}}"#.as_bytes());
+        assert_eq!(body.as_str(), Some(r#"{"params":{"_meta":{"k":1}}}"#));
+    }
+
+    #[test]
+    fn a_body_that_is_not_utf8_is_kept_as_read() {
+        let line = b"{\"id\":1,\"s\":\"\xff\"}".to_vec();
+        let body = RequestBody::from(line.clone());
+        assert_eq!(body.as_str(), None);
+        assert_eq!(body.text(), "{\"id\":1,\"s\":\"\u{fffd}\"}");
+        assert_eq!(body.bytes(), line);
+        assert_eq!(body.or_edited(body.text()), line);
+    }
+}
@@ -0,0 +1,414 @@
+use crate::aliases::MethodAliases;
+use crate::cache::ResponseCache;
+use crate::ids::IdMapper;
+use crate::jsonrpc;
+use crate::ordered::ResponseOrder;
+use crate::pending::{PendingRequests, Waiter};
+use crate::server_log::LogNotifications;
+use crate::state;
+use crate::stats::Stats;
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::mpsc;
+use tracing::debug;
+
+/// Stdout has gone away, so the bridge should stop.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Closed;
+
+/// The only way the bridge writes to the client. Relayed upstream lines,
+/// local answers and synthesized errors all go through it, so they share
+/// one queue in the order they are written, get the client's ids and method
+/// names back the same way, and all count as client activity. Shared by
+/// every in-flight request.
+#[derive(Clone)]
+pub struct Responder {
+    tx_out: mpsc::Sender<String>,
+    stats: Arc<Stats>,
+    aliases: MethodAliases,
+    ids: Option<Arc<Mutex<IdMapper>>>,
+    pending: Arc<PendingRequests>,
+    log_notifications: LogNotifications,
+    cache: Option<Arc<ResponseCache>>,
+    order: Option<Arc<ResponseOrder>>,
+}
+
+impl Responder {
+    pub fn new(
+        tx_out: mpsc::Sender<String>,
+        stats: Arc<Stats>,
+        aliases: MethodAliases,
+        ids: Option<IdMapper>,
+    ) -> Self {
+        Self {
+            tx_out,
+            stats,
+            aliases,
+            ids: ids.map(|ids| Arc::new(Mutex::new(ids))),
+            pending: Arc::default(),
+            log_notifications: LogNotifications::default(),
+            cache: None,
+            order: None,
+        }
+    }
+
+    /// Where upstream `notifications/message` events go instead of stdout
+    /// (`MCP_LOG_NOTIFICATIONS`).
+    pub fn with_log_notifications(mut self, mode: LogNotifications) -> Self {
+        self.log_notifications = mode;
+        self
+    }
+
+    /// The `MCP_CACHE` entries that upstream notifications invalidate.
+    pub fn with_cache(mut self, cache: Option<Arc<ResponseCache>>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Holds answers back until earlier requests have been answered
+    /// (`MCP_ORDERED_RESPONSES`).
+    pub fn with_order(mut self, order: Option<Arc<ResponseOrder>>) -> Self {
+        self.order = order;
+        self
+    }
+
+    pub fn order(&self) -> Option<&Arc<ResponseOrder>> {
+        self.order.as_ref()
+    }
+
+    /// Rewrites a client message for the upstream: method aliases first, then
+    /// bridge ids, which `relay` and `error` map back. Requests whose ids
+    /// had to be forgotten to make room are answered with an error first.
+    pub async fn to_server(&self, msg: String) -> Result<String, Closed> {
+        let msg = self.aliases.to_server(&msg).unwrap_or(msg);
+        let Some(ids) = self.ids.as_ref() else {
+            return Ok(msg);
+        };
+        let (msg, evicted) = {
+            let mut ids = ids.lock().unwrap();
+            let msg = ids.to_server(&msg, Instant::now()).unwrap_or(msg);
+            (msg, ids.take_evicted())
+        };
+        for id in evicted {
+            let err =
+                jsonrpc::error_response(Some(&id), jsonrpc::INTERNAL_ERROR, state::EVICTED_MESSAGE);
+            self.write(err).await?;
+        }
+        Ok(msg)
+    }
+
+    /// Writes an upstream line with the client's ids and method names.
+    pub async fn relay(&self, line: String) -> Result<(), Closed> {
+        if let Some(cache) = &self.cache {
+            cache.observe(&line);
+        }
+        if self.log_notifications.intercept(&line) {
+            return Ok(());
+        }
+        if self.answers_forgotten(&line) {
+            debug!("dropping an upstream answer to a request the client cancelled or the bridge forgot");
+            return Ok(());
+        }
+        self.pending.answered(&line);
+        let line = self.restore_ids(line);
+        let line = self.aliases.to_client(&line).unwrap_or(line);
+        self.write(line).await
+    }
+
+    /// Writes a line the bridge produced itself. It answers the client's
+    /// message directly, so its ids are the client's already.
+    pub async fn local(&self, line: String) -> Result<(), Closed> {
+        self.write(line).await
+    }
+
+    /// Answers `request`, as it was sent upstream, with an error under the
+    /// client's id; a batch gets one per request in it. Notifications get no
+    /// answer.
+    pub async fn error(&self, request: &str, code: i64, message: &str) -> Result<(), Closed> {
+        self.error_object(request, &jsonrpc::error_object(code, message))
+            .await
+    }
+
+    /// `error` with a complete `error` member, e.g. one carrying `data`.
+    pub async fn error_object(&self, request: &str, error: &Value) -> Result<(), Closed> {
+        let Some(err) = jsonrpc::error_object_for(request, error) else {
+            return Ok(());
+        };
+        let err = self.restore_ids(err);
+        self.write(err).await
+    }
+
+    /// Answers an abandoned `request` and records its client id for the
+    /// shutdown report. Called once shutdown has given up on the request, so
+    /// the stdout writer still flushes the error on the way out.
+    pub async fn terminated(&self, request: &str, message: &str) -> Result<(), Closed> {
+        let Some(err) = jsonrpc::error_for(request, jsonrpc::INTERNAL_ERROR, message) else {
+            return Ok(());
+        };
+        self.write_terminated(self.restore_ids(err)).await
+    }
+
+    /// `terminated` for the request with upstream id `id`, when only the id
+    /// was kept.
+    pub async fn terminated_id(&self, id: &Value, message: &str) -> Result<(), Closed> {
+        let err = jsonrpc::error_response(Some(id), jsonrpc::INTERNAL_ERROR, message);
+        self.write_terminated(self.restore_ids(err)).await
+    }
+
+    /// `terminated` for a client message shutdown left in the queue. It
+    /// never went upstream, so its ids are the client's already.
+    pub async fn unsent(&self, msg: &str, message: &str) -> Result<(), Closed> {
+        let Some(err) = jsonrpc::error_for(msg, jsonrpc::INTERNAL_ERROR, message) else {
+            return Ok(());
+        };
+        self.write_terminated(err).await
+    }
+
+    async fn write_terminated(&self, err: String) -> Result<(), Closed> {
+        for client_id in jsonrpc::response_ids(&err) {
+            self.stats.note_terminated(client_id);
+        }
+        self.write(err).await
+    }
+
+    /// Writes each error the input reader answers a refused line with, e.g.
+    /// one too long or one that finds the queue full, as the bridge's own
+    /// answer. Returns once the reader has stopped or stdout has gone away.
+    pub async fn forward_errors(self, mut errors: mpsc::Receiver<String>) {
+        while let Some(err) = errors.recv().await {
+            if self.local(err).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// A handle for writing server-initiated messages from outside the
+    /// dispatch loop.
+    pub fn push(&self) -> Push {
+        Push(self.clone())
+    }
+
+    /// Starts waiting for an answer to `request`, as it was sent upstream;
+    /// `None` when nothing in it expects one.
+    pub fn track(&self, request: &str) -> Option<Arc<Waiter>> {
+        self.pending.track(request)
+    }
+
+    pub fn untrack(&self, waiter: &Arc<Waiter>) {
+        self.pending.untrack(waiter);
+    }
+
+    /// Aborts the upstream call for the request the client knows as
+    /// `client_id`; false when none is in flight.
+    pub fn cancel(&self, client_id: &Value) -> bool {
+        let id = match &self.ids {
+            Some(ids) => match ids.lock().unwrap().server_id(client_id) {
+                Some(id) => Value::from(id),
+                None => return false,
+            },
+            None => client_id.clone(),
+        };
+        self.pending.cancel(&id)
+    }
+
+    /// Marks `request`, as it was sent upstream, as finished, so its id
+    /// mapping can expire if no answer restored it.
+    pub fn settle(&self, request: &str) {
+        if let Some(ids) = &self.ids {
+            ids.lock().unwrap().settle(request);
+        }
+    }
+
+    /// Drops the id mappings of `request`, as sent upstream, once the
+    /// client has cancelled it, so it is never answered as evicted.
+    pub fn forget(&self, request: &str) {
+        if let Some(ids) = &self.ids {
+            ids.lock().unwrap().forget(request);
+        }
+    }
+
+    fn answers_forgotten(&self, line: &str) -> bool {
+        self.ids
+            .as_ref()
+            .is_some_and(|ids| ids.lock().unwrap().answers_forgotten(line))
+    }
+
+    fn restore_ids(&self, line: String) -> String {
+        match &self.ids {
+            Some(ids) => ids.lock().unwrap().to_client(&line).unwrap_or(line),
+            None => line,
+        }
+    }
+
+    async fn write(&self, line: String) -> Result<(), Closed> {
+        match &self.order {
+            Some(order) if jsonrpc::is_response(&line) => {
+                order.write(line).await.map_err(|_| Closed)?
+            }
+            _ => self.tx_out.send(line).await.map_err(|_| Closed)?,
+        }
+        self.stats.note_activity();
+        Ok(())
+    }
+}
+
+/// Writes messages the upstream sends on its own, such as those on the GET
+/// stream, alongside the dispatch loop. Most are requests and notifications
+/// of the server's, but a resumed stream can replay the response to a
+/// client request, so each line is relayed like any other and gets the
+/// client's ids back. Holding one keeps stdout open, so it must not outlive
+/// the bridge.
+#[derive(Clone)]
+pub struct Push(Responder);
+
+impl Push {
+    pub async fn send(&self, line: String) -> Result<(), Closed> {
+        self.0.relay(line).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ids;
+
+    fn responder(aliases: &[(&str, &str)]) -> (Responder, mpsc::Receiver<String>, Arc<Stats>) {
+        let (tx, rx) = mpsc::channel(8);
+        let stats = Arc::new(Stats::new(8));
+        let pairs: Vec<(String, String)> = aliases
+            .iter()
+            .map(|(a, b)| (a.to_string(), b.to_string()))
+            .collect();
+        let ids = IdMapper::new(2, ids::DEFAULT_TTL);
+        let responder = Responder::new(tx, stats.clone(), MethodAliases::new(&pairs), Some(ids));
+        (responder, rx, stats)
+    }
+
+    #[tokio::test]
+    async fn upstream_lines_and_errors_get_client_ids_back() {
+        let (r, mut rx, _) = responder(&[("cli/list", "srv/list")]);
+        let first = r
+            .to_server(r#"{"jsonrpc":"2.0","id":"a","method":"cli/list"}"#.into())
+            .await
+            .unwrap();
+        assert_eq!(first, r#"{"jsonrpc":"2.0","id":1,"method":"srv/list"}"#);
+        r.relay(r#"{"jsonrpc":"2.0","id":1,"result":{}}"#.into())
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            r#"{"jsonrpc":"2.0","id":"a","result":{}}"#
+        );
+        let second = r
+            .to_server(r#"{"jsonrpc":"2.0","id":"b","method":"x"}"#.into())
+            .await
+            .unwrap();
+        r.error(&second, jsonrpc::INTERNAL_ERROR, "boom")
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            r#"{"jsonrpc":"2.0","id":"b","error":{"code":-32603,"message":"boom"}}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn local_answers_leave_pending_ids_alone() {
+        let (r, mut rx, _) = responder(&[]);
+        r.to_server(r#"{"jsonrpc":"2.0","id":"a","method":"x"}"#.into())
+            .await
+            .unwrap();
+        // The client's own id 1 collides with the pending bridge id 1.
+        r.local(r#"{"jsonrpc":"2.0","id":1,"result":{}}"#.into())
+            .await
+            .unwrap();
+        r.relay(r#"{"jsonrpc":"2.0","id":1,"result":"upstream"}"#.into())
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            r#"{"jsonrpc":"2.0","id":1,"result":{}}"#
+        );
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            r#"{"jsonrpc":"2.0","id":"a","result":"upstream"}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn terminated_requests_are_recorded_and_notifications_skipped() {
+        let (r, mut rx, stats) = responder(&[]);
+        let sent = r
+            .to_server(r#"{"jsonrpc":"2.0","id":"t","method":"x"}"#.into())
+            .await
+            .unwrap();
+        r.terminated(&sent, "bridge terminated").await.unwrap();
+        r.terminated(r#"{"jsonrpc":"2.0","method":"n"}"#, "bridge terminated")
+            .await
+            .unwrap();
+        drop(r);
+        assert_eq!(
+            jsonrpc::request_id(&rx.recv().await.unwrap()),
+            Some("t".into())
+        );
+        assert_eq!(rx.recv().await, None);
+        assert_eq!(stats.terminated_ids(), vec![serde_json::json!("t")]);
+    }
+
+    #[tokio::test]
+    async fn evicted_requests_are_answered_before_the_next_is_sent() {
+        let (r, mut rx, _) = responder(&[]);
+        for id in ["a", "b", "c"] {
+            let msg = format!(r#"{{"jsonrpc":"2.0","id":"{}","method":"x"}}"#, id);
+            r.to_server(msg).await.unwrap();
+        }
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            r#"{"jsonrpc":"2.0","id":"a","error":{"code":-32603,"message":"request state evicted"}}"#
+        );
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn cancelled_requests_are_never_answered() {
+        let (r, mut rx, _) = responder(&[]);
+        let cancelled = r
+            .to_server(r#"{"jsonrpc":"2.0","id":"a","method":"x"}"#.into())
+            .await
+            .unwrap();
+        r.forget(&cancelled);
+        // Filling the table past its limit evicts nothing owed to "a".
+        for id in ["b", "c"] {
+            let msg = format!(r#"{{"jsonrpc":"2.0","id":"{}","method":"x"}}"#, id);
+            r.to_server(msg).await.unwrap();
+        }
+        r.relay(r#"{"jsonrpc":"2.0","id":1,"result":"late"}"#.into())
+            .await
+            .unwrap();
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn pushed_lines_get_client_method_names() {
+        let (r, mut rx, _) = responder(&[("cli/updated", "srv/updated")]);
+        let push = r.push();
+        push.send(r#"{"jsonrpc":"2.0","id":1,"method":"srv/updated"}"#.into())
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            r#"{"jsonrpc":"2.0","id":1,"method":"cli/updated"}"#
+        );
+        drop(r);
+        drop(push);
+        assert_eq!(rx.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn closed_stdout_is_reported() {
+        let (r, rx, _) = responder(&[]);
+        drop(rx);
+        assert_eq!(r.local("{}".into()).await, Err(Closed));
+    }
+}
@@ -0,0 +1,131 @@
+//! What becomes of the upstream's `notifications/message` log events
+//! (`MCP_LOG_NOTIFICATIONS`). A chatty server can send enough of them during
+//! a tool call to crowd out the client's context, so they can be logged to
+//! stderr at their own level, or dropped, instead of written to stdout.
+//! Every other message passes through untouched.
+
+use crate::config::Config;
+use crate::jsonrpc::LOG_MESSAGE_METHOD;
+use serde_json::Value;
+use std::str::FromStr;
+use tracing::{debug, error, info, warn};
+
+/// Where upstream log notifications go (`MCP_LOG_NOTIFICATIONS`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogNotifications {
+    /// To stdout, like any other message.
+    #[default]
+    Forward,
+    /// To stderr through the bridge's own log, at the mapped level.
+    Stderr,
+    /// Nowhere.
+    Drop,
+}
+
+impl FromStr for LogNotifications {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "forward" => Ok(Self::Forward),
+            "stderr" => Ok(Self::Stderr),
+            "drop" => Ok(Self::Drop),
+            other => Err(format!(
+                "unknown log notification handling {:?}; use forward, stderr or drop",
+                other
+            )),
+        }
+    }
+}
+
+impl LogNotifications {
+    /// `MCP_LOG_NOTIFICATIONS`, except that raw passthrough forwards every
+    /// message.
+    pub fn for_config(config: &Config) -> Self {
+        if config.raw_passthrough {
+            Self::Forward
+        } else {
+            config.log_notifications
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Forward => "forward",
+            Self::Stderr => "stderr",
+            Self::Drop => "drop",
+        }
+    }
+
+    /// Takes `line` out of the stream to stdout if it is a log notification
+    /// this mode does not forward, logging it first in `stderr` mode. True
+    /// when it was taken.
+    pub fn intercept(self, line: &str) -> bool {
+        // Most lines are not log notifications; only those that could be are
+        // parsed.
+        if self == Self::Forward || !line.contains(LOG_MESSAGE_METHOD) {
+            return false;
+        }
+        let Ok(msg) = serde_json::from_str::<Value>(line) else {
+            return false;
+        };
+        let method = msg.get("method").and_then(Value::as_str);
+        if method != Some(LOG_MESSAGE_METHOD) || msg.get("id").is_some() {
+            return false;
+        }
+        if self == Self::Stderr {
+            log(&msg["params"]);
+        }
+        true
+    }
+}
+
+/// Logs a `notifications/message` under its MCP level: `debug` at debug,
+/// `info` and `notice` at info, `warning` at warn, and `error` and above at
+/// error. An unknown level is logged at info.
+fn log(params: &Value) {
+    let logger = params.get("logger").and_then(Value::as_str).unwrap_or("-");
+    let data = match params.get("data") {
+        Some(Value::String(text)) => text.clone(),
+        Some(data) => data.to_string(),
+        None => String::new(),
+    };
+    match params.get("level").and_then(Value::as_str).unwrap_or("info") {
+        "debug" => debug!(logger, "upstream: {}", data),
+        "warning" => warn!(logger, "upstream: {}", data),
+        "error" | "critical" | "alert" | "emergency" => {
+            error!(logger, "upstream: {}", data)
+        }
+        _ => info!(logger, "upstream: {}", data),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOG: &str = r#"{"jsonrpc":"2.0","method":"notifications/message","params":{"level":"info","logger":"db","data":"connected"}}"#;
+
+    #[test]
+    fn modes_parse() {
+        assert_eq!("forward".parse(), Ok(LogNotifications::Forward));
+        assert_eq!("STDERR".parse(), Ok(LogNotifications::Stderr));
+        assert_eq!("drop".parse(), Ok(LogNotifications::Drop));
+        assert!("quiet".parse::<LogNotifications>().is_err());
+    }
+
+    #[test]
+    fn only_log_notifications_are_taken() {
+        assert!(!LogNotifications::Forward.intercept(LOG));
+        assert!(LogNotifications::Stderr.intercept(LOG));
+        assert!(LogNotifications::Drop.intercept(LOG));
+        for other in [
+            r#"{"jsonrpc":"2.0","method":"notifications/progress","params":{"message":"notifications/message"}}"#,
+            r#"{"jsonrpc":"2.0","id":1,"result":{"text":"notifications/message"}}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"notifications/message"}"#,
+            "notifications/message {oops",
+        ] {
+            assert!(!LogNotifications::Drop.intercept(other), "{}", other);
+        }
+    }
+}
@@ -0,0 +1,224 @@
+//! Windows service support behind the `windows-service` feature. On other
+//! targets, or with the feature off, the subcommands are recognised but
+//! report that service support is unavailable.
+
+use tokio_util::sync::CancellationToken;
+
+pub const SERVICE_NAME: &str = "mcp-stdio-http-bridge";
+pub const INSTALL_ARG: &str = "install-service";
+pub const UNINSTALL_ARG: &str = "uninstall-service";
+/// Argument the service control manager launches the binary with.
+pub const RUN_SERVICE_ARG: &str = "run-service";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ServiceCommand {
+    Install,
+    Uninstall,
+    Run,
+}
+
+/// Recognises a service subcommand in the first argument (program name
+/// excluded).
+pub fn parse_command(args: &[String]) -> Option<ServiceCommand> {
+    match args.first().map(String::as_str) {
+        Some(INSTALL_ARG) => Some(ServiceCommand::Install),
+        Some(UNINSTALL_ARG) => Some(ServiceCommand::Uninstall),
+        Some(RUN_SERVICE_ARG) => Some(ServiceCommand::Run),
+        _ => None,
+    }
+}
+
+/// Arguments registered with the service: the run marker followed by
+/// whatever was passed after `install-service`.
+pub fn service_launch_args(extra: &[String]) -> Vec<String> {
+    std::iter::once(RUN_SERVICE_ARG.to_string())
+        .chain(extra.iter().cloned())
+        .collect()
+}
+
+#[cfg(all(windows, feature = "windows-service"))]
+pub use imp::{install, run_as_service, uninstall};
+
+#[cfg(not(all(windows, feature = "windows-service")))]
+const UNSUPPORTED: &str = "service support requires Windows and the windows-service feature";
+
+#[cfg(not(all(windows, feature = "windows-service")))]
+pub fn install(_extra: &[String]) -> Result<(), String> {
+    Err(UNSUPPORTED.to_string())
+}
+
+#[cfg(not(all(windows, feature = "windows-service")))]
+pub fn uninstall() -> Result<(), String> {
+    Err(UNSUPPORTED.to_string())
+}
+
+#[cfg(not(all(windows, feature = "windows-service")))]
+pub fn run_as_service(_body: fn(CancellationToken)) -> Result<(), String> {
+    Err(UNSUPPORTED.to_string())
+}
+
+#[cfg(all(windows, feature = "windows-service"))]
+mod imp {
+    use super::{service_launch_args, CancellationToken, SERVICE_NAME};
+    use std::ffi::OsString;
+    use std::sync::{Arc, OnceLock};
+    use std::time::Duration;
+    use tracing::{error, info};
+    use windows_service::service::{
+        ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode,
+        ServiceInfo, ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+    };
+    use windows_service::service_control_handler::{
+        self, ServiceControlHandlerResult, ServiceStatusHandle,
+    };
+    use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+    use windows_service::{define_windows_service, service_dispatcher};
+
+    /// Upper bound reported to the SCM while in-flight work drains; covers the
+    /// bridge's in-flight grace plus the stdout drain timeout.
+    const STOP_WAIT_HINT: Duration = Duration::from_secs(10);
+
+    static BODY: OnceLock<fn(CancellationToken)> = OnceLock::new();
+
+    define_windows_service!(ffi_service_main, service_main);
+
+    pub fn run_as_service(body: fn(CancellationToken)) -> Result<(), String> {
+        let _ = BODY.set(body);
+        service_dispatcher::start(SERVICE_NAME, ffi_service_main).map_err(|e| e.to_string())
+    }
+
+    fn service_main(_arguments: Vec<OsString>) {
+        if let Err(e) = run_service() {
+            error!(%e, "service failed");
+        }
+    }
+
+    fn status(state: ServiceState, wait_hint: Duration) -> ServiceStatus {
+        ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state: state,
+            controls_accepted: if state == ServiceState::Running {
+                ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN
+            } else {
+                ServiceControlAccept::empty()
+            },
+            exit_code: ServiceExitCode::Win32(0),
+            checkpoint: 0,
+            wait_hint,
+            process_id: None,
+        }
+    }
+
+    fn run_service() -> windows_service::Result<()> {
+        let shutdown = CancellationToken::new();
+        let handle: Arc<OnceLock<ServiceStatusHandle>> = Arc::new(OnceLock::new());
+        let token = shutdown.clone();
+        let handler_handle = handle.clone();
+        let status_handle =
+            service_control_handler::register(SERVICE_NAME, move |control| match control {
+                ServiceControl::Stop | ServiceControl::Shutdown => {
+                    info!("service stop requested, draining");
+                    if let Some(h) = handler_handle.get() {
+                        let _ =
+                            h.set_service_status(status(ServiceState::StopPending, STOP_WAIT_HINT));
+                    }
+                    token.cancel();
+                    ServiceControlHandlerResult::NoError
+                }
+                ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+                _ => ServiceControlHandlerResult::NotImplemented,
+            })?;
+        let _ = handle.set(status_handle);
+        status_handle.set_service_status(status(ServiceState::Running, Duration::ZERO))?;
+        if let Some(body) = BODY.get() {
+            body(shutdown);
+        }
+        status_handle.set_service_status(status(ServiceState::Stopped, Duration::ZERO))
+    }
+
+    pub fn install(extra: &[String]) -> Result<(), String> {
+        let manager = ServiceManager::local_computer(
+            None::<&str>,
+            ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+        )
+        .map_err(|e| e.to_string())?;
+        let executable_path = std::env::current_exe().map_err(|e| e.to_string())?;
+        let info = ServiceInfo {
+            name: OsString::from(SERVICE_NAME),
+            display_name: OsString::from("MCP stdio/HTTP bridge"),
+            service_type: ServiceType::OWN_PROCESS,
+            start_type: ServiceStartType::AutoStart,
+            error_control: ServiceErrorControl::Normal,
+            executable_path,
+            launch_arguments: service_launch_args(extra)
+                .into_iter()
+                .map(OsString::from)
+                .collect(),
+            dependencies: Vec::new(),
+            account_name: None,
+            account_password: None,
+        };
+        manager
+            .create_service(&info, ServiceAccess::QUERY_STATUS)
+            .map_err(|e| e.to_string())?;
+        info!(name = SERVICE_NAME, "service installed");
+        Ok(())
+    }
+
+    pub fn uninstall() -> Result<(), String> {
+        let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)
+            .map_err(|e| e.to_string())?;
+        let service = manager
+            .open_service(SERVICE_NAME, ServiceAccess::DELETE)
+            .map_err(|e| e.to_string())?;
+        service.delete().map_err(|e| e.to_string())?;
+        info!(name = SERVICE_NAME, "service uninstalled");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn recognises_service_subcommands_only_in_first_position() {
+        assert_eq!(
+            parse_command(&args(&["install-service"])),
+            Some(ServiceCommand::Install)
+        );
+        assert_eq!(
+            parse_command(&args(&["uninstall-service"])),
+            Some(ServiceCommand::Uninstall)
+        );
+        assert_eq!(
+            parse_command(&args(&["run-service", "x"])),
+            Some(ServiceCommand::Run)
+        );
+        assert_eq!(parse_command(&args(&["x", "install-service"])), None);
+        assert_eq!(parse_command(&[]), None);
+    }
+
+    #[test]
+    fn launch_args_start_with_run_marker_and_keep_extras() {
+        assert_eq!(service_launch_args(&[]), args(&["run-service"]));
+        assert_eq!(
+            service_launch_args(&args(&["--flag", "value"])),
+            args(&["run-service", "--flag", "value"])
+        );
+    }
+
+    #[cfg(not(all(windows, feature = "windows-service")))]
+    #[test]
+    fn subcommands_report_unsupported_elsewhere() {
+        assert!(install(&[])
+            .unwrap_err()
+            .contains("windows-service feature"));
+        assert!(uninstall().is_err());
+        assert!(run_as_service(|_| {}).is_err());
+    }
+}
@@ -0,0 +1,350 @@
+use std::str::FromStr;
+use std::sync::Mutex;
+use tracing::{info, warn};
+use url::form_urlencoded;
+
+/// Streamable HTTP session header.
+pub const HEADER: &str = "Mcp-Session-Id";
+/// Protocol version header sent once `initialize` has negotiated one.
+pub const PROTOCOL_VERSION_HEADER: &str = "MCP-Protocol-Version";
+pub const DEFAULT_QUERY_PARAM: &str = "sessionId";
+/// Re-initializations of an expired session allowed in a row without a
+/// request succeeding in between (`MCP_SESSION_REINIT_MAX`).
+pub const DEFAULT_REINIT_MAX: u32 = 3;
+
+/// Where the session id goes on requests (`MCP_SESSION_STYLE`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SessionStyle {
+    /// The `Mcp-Session-Id` header, as the spec describes.
+    #[default]
+    Header,
+    /// A query parameter on the request URL, for servers that predate the
+    /// header.
+    Query,
+    Both,
+}
+
+impl FromStr for SessionStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "header" => Ok(Self::Header),
+            "query" => Ok(Self::Query),
+            "both" => Ok(Self::Both),
+            other => Err(format!("unknown session style {:?}", other)),
+        }
+    }
+}
+
+impl SessionStyle {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Header => "header",
+            Self::Query => "query",
+            Self::Both => "both",
+        }
+    }
+
+    pub fn uses_header(self) -> bool {
+        self != Self::Query
+    }
+
+    pub fn uses_query(self) -> bool {
+        self != Self::Header
+    }
+}
+
+/// The upstream's Streamable HTTP session: the endpoint requests go to and
+/// the id it assigned in an `Mcp-Session-Id` response header, sent back on
+/// every later request, along with the protocol version its `initialize`
+/// result named.
+#[derive(Debug)]
+pub struct Session {
+    endpoint: Mutex<String>,
+    id: Mutex<Option<String>>,
+    protocol_version: Mutex<Option<String>>,
+}
+
+impl Session {
+    pub fn new(endpoint: &str) -> Self {
+        Self {
+            endpoint: Mutex::new(endpoint.to_string()),
+            id: Mutex::new(None),
+            protocol_version: Mutex::new(None),
+        }
+    }
+
+    /// A session for `endpoint` sending the same protocol version as this
+    /// one, for a request routed elsewhere.
+    pub fn routed(&self, endpoint: &str) -> Self {
+        let session = Self::new(endpoint);
+        *session.protocol_version.lock().unwrap() = self.protocol_version();
+        session
+    }
+
+    pub fn endpoint(&self) -> String {
+        self.endpoint.lock().unwrap().clone()
+    }
+
+    /// Sends later requests to `endpoint`, which knows nothing of the
+    /// current session, so it is forgotten and returned.
+    pub fn move_to(&self, endpoint: &str) -> Option<String> {
+        *self.endpoint.lock().unwrap() = endpoint.to_string();
+        self.clear()
+    }
+
+    pub fn id(&self) -> Option<String> {
+        self.id.lock().unwrap().clone()
+    }
+
+    pub fn protocol_version(&self) -> Option<String> {
+        self.protocol_version.lock().unwrap().clone()
+    }
+
+    /// Records the `protocolVersion` of an `initialize` result, sent as
+    /// `MCP-Protocol-Version` on every later request. A version that could
+    /// not go in a header is ignored.
+    pub fn negotiated(&self, version: &str) {
+        if version.is_empty() || !version.bytes().all(|b| b.is_ascii_graphic()) {
+            warn!(version, "ignoring unusable protocolVersion from initialize");
+            return;
+        }
+        let mut current = self.protocol_version.lock().unwrap();
+        if current.as_deref() != Some(version) {
+            info!(version, "upstream protocol version negotiated");
+            *current = Some(version.to_string());
+        }
+    }
+
+    /// Records the id a response carried. Responses without the header keep
+    /// the current session.
+    pub fn update(&self, id: Option<&str>) {
+        let Some(id) = id.filter(|id| !id.is_empty()) else {
+            return;
+        };
+        let mut current = self.id.lock().unwrap();
+        if current.as_deref() != Some(id) {
+            info!(session = %id, "upstream session established");
+            *current = Some(id.to_string());
+        }
+    }
+
+    /// Forgets the session, returning it. Requests go out without an id
+    /// until the upstream assigns a new one.
+    pub fn clear(&self) -> Option<String> {
+        self.id.lock().unwrap().take()
+    }
+
+    /// Forgets the session if it is still `id`, which the upstream no longer
+    /// knows. A session renewed meanwhile is kept.
+    pub fn expire(&self, id: &str) -> bool {
+        let mut current = self.id.lock().unwrap();
+        if current.as_deref() != Some(id) {
+            return false;
+        }
+        *current = None;
+        true
+    }
+}
+
+/// What replacing an expired session needs: the client's latest
+/// `initialize` as sent upstream, and a count of re-initializations since a
+/// request last succeeded, so an endpoint that expires every session cannot
+/// keep the bridge re-initializing.
+#[derive(Debug, Default)]
+pub struct Reinit {
+    initialize: Mutex<Option<String>>,
+    attempts: Mutex<u32>,
+    /// Held while one request re-initializes; the others wait and then use
+    /// the session it got.
+    pub running: tokio::sync::Mutex<()>,
+}
+
+impl Reinit {
+    pub fn remember(&self, initialize: &str) {
+        *self.initialize.lock().unwrap() = Some(initialize.to_string());
+    }
+
+    /// The `initialize` to replay and its attempt number, or `None` when
+    /// the client never sent one or `max` attempts ran without a success.
+    pub fn next(&self, max: u32) -> Option<(String, u32)> {
+        let initialize = self.initialize.lock().unwrap().clone()?;
+        let mut attempts = self.attempts.lock().unwrap();
+        if *attempts >= max {
+            return None;
+        }
+        *attempts += 1;
+        Some((initialize, *attempts))
+    }
+
+    /// A request succeeded, so the session works again.
+    pub fn succeeded(&self) {
+        *self.attempts.lock().unwrap() = 0;
+    }
+}
+
+/// The URL for a request carrying `session`. In query style `param` is set
+/// to the id, replacing any value already in `uri`, so rebuilding the URL
+/// for a retry never repeats it. Other query parameters are kept verbatim.
+/// The result holds the session id, so log it only through `redact_uri`.
+pub fn request_url(uri: &str, style: SessionStyle, param: &str, session: Option<&str>) -> String {
+    let Some(session) = session.filter(|_| style.uses_query()) else {
+        return uri.to_string();
+    };
+    let (base, fragment) = match uri.split_once('#') {
+        Some((base, fragment)) => (base, Some(fragment)),
+        None => (uri, None),
+    };
+    let (path, query) = base.split_once('?').unwrap_or((base, ""));
+    let mut pairs: Vec<String> = query
+        .split('&')
+        .filter(|pair| !pair.is_empty() && !names(pair, param))
+        .map(str::to_string)
+        .collect();
+    pairs.push(
+        form_urlencoded::Serializer::new(String::new())
+            .append_pair(param, session)
+            .finish(),
+    );
+    let mut url = format!("{}?{}", path, pairs.join("&"));
+    if let Some(fragment) = fragment {
+        url.push('#');
+        url.push_str(fragment);
+    }
+    url
+}
+
+/// Whether the raw `key=value` pair sets `param`.
+pub(crate) fn names(pair: &str, param: &str) -> bool {
+    let key = pair.split_once('=').map_or(pair, |(key, _)| key);
+    form_urlencoded::parse(key.as_bytes()).any(|(key, _)| key == param)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::redact_uri;
+
+    const URI: &str = "https://example.test/mcp";
+
+    #[test]
+    fn styles_parse() {
+        assert_eq!("Query".parse(), Ok(SessionStyle::Query));
+        assert_eq!("both".parse(), Ok(SessionStyle::Both));
+        assert!("cookie".parse::<SessionStyle>().is_err());
+        assert!(SessionStyle::Both.uses_header() && SessionStyle::Both.uses_query());
+        assert!(!SessionStyle::Query.uses_header());
+        assert!(!SessionStyle::Header.uses_query());
+    }
+
+    #[test]
+    fn url_per_style() {
+        let url = |style| request_url(URI, style, DEFAULT_QUERY_PARAM, Some("abc"));
+        assert_eq!(url(SessionStyle::Header), URI);
+        assert_eq!(
+            url(SessionStyle::Query),
+            "https://example.test/mcp?sessionId=abc"
+        );
+        assert_eq!(
+            url(SessionStyle::Both),
+            "https://example.test/mcp?sessionId=abc"
+        );
+        assert_eq!(
+            request_url(URI, SessionStyle::Query, DEFAULT_QUERY_PARAM, None),
+            URI
+        );
+    }
+
+    #[test]
+    fn id_is_encoded_and_other_parameters_kept() {
+        assert_eq!(
+            request_url(
+                "https://example.test/mcp?tenant=a%20b&x#frag",
+                SessionStyle::Query,
+                "sid",
+                Some("a&b=c d/é")
+            ),
+            "https://example.test/mcp?tenant=a%20b&x&sid=a%26b%3Dc+d%2F%C3%A9#frag"
+        );
+    }
+
+    #[test]
+    fn rebuilding_replaces_instead_of_duplicating() {
+        let first = request_url(URI, SessionStyle::Query, "sessionId", Some("one"));
+        let retry = request_url(&first, SessionStyle::Query, "sessionId", Some("one"));
+        assert_eq!(retry, first);
+        let renewed = request_url(
+            "https://example.test/mcp?a=1&sessionId=old&session%49d=older",
+            SessionStyle::Query,
+            "sessionId",
+            Some("new"),
+        );
+        assert_eq!(renewed, "https://example.test/mcp?a=1&sessionId=new");
+    }
+
+    #[test]
+    fn session_is_kept_until_replaced_or_cleared() {
+        let session = Session::new("https://example.test/mcp");
+        session.update(None);
+        assert_eq!(session.id(), None);
+        session.update(Some("one"));
+        session.update(None);
+        session.update(Some(""));
+        assert_eq!(session.id().as_deref(), Some("one"));
+        session.update(Some("two"));
+        assert_eq!(session.clear().as_deref(), Some("two"));
+        assert_eq!(session.id(), None);
+        session.update(Some("three"));
+        assert_eq!(session.move_to("https://b.test/mcp").as_deref(), Some("three"));
+        assert_eq!(session.endpoint(), "https://b.test/mcp");
+        assert_eq!(session.id(), None);
+    }
+
+    #[test]
+    fn expiring_keeps_a_renewed_session() {
+        let session = Session::new("https://example.test/mcp");
+        session.update(Some("old"));
+        assert!(session.expire("old"));
+        assert!(!session.expire("old"));
+        session.update(Some("new"));
+        assert!(!session.expire("old"));
+        assert_eq!(session.id().as_deref(), Some("new"));
+    }
+
+    #[test]
+    fn negotiated_version_is_kept_across_moves_and_routing() {
+        let session = Session::new("https://example.test/mcp");
+        assert_eq!(session.protocol_version(), None);
+        session.negotiated("2025-03-26");
+        session.negotiated("bad version");
+        session.negotiated("");
+        session.move_to("https://b.test/mcp");
+        assert_eq!(session.protocol_version().as_deref(), Some("2025-03-26"));
+        let routed = session.routed("https://c.test/mcp");
+        assert_eq!(routed.endpoint(), "https://c.test/mcp");
+        assert_eq!(routed.protocol_version().as_deref(), Some("2025-03-26"));
+    }
+
+    #[test]
+    fn reinit_is_capped_until_a_success() {
+        let reinit = Reinit::default();
+        assert_eq!(reinit.next(2), None);
+        reinit.remember("init");
+        assert_eq!(reinit.next(2), Some(("init".to_string(), 1)));
+        assert_eq!(reinit.next(2).map(|(_, n)| n), Some(2));
+        assert_eq!(reinit.next(2), None);
+        reinit.succeeded();
+        assert_eq!(reinit.next(2).map(|(_, n)| n), Some(1));
+        let disabled = Reinit::default();
+        disabled.remember("init");
+        assert_eq!(disabled.next(0), None);
+    }
+
+    #[test]
+    fn session_id_is_redacted_from_logged_urls() {
+        let url = request_url(URI, SessionStyle::Query, "sessionId", Some("secret-id"));
+        let logged = redact_uri(&url);
+        assert!(!logged.contains("secret-id"), "{}", logged);
+    }
+}
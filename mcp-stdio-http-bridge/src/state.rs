@@ -0,0 +1,227 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tracing::warn;
+
+/// Entries per table (`MCP_STATE_TABLE_LIMIT`).
+pub const DEFAULT_LIMIT: usize = 10_000;
+/// Share of the limit, in percent, at which a table warns once.
+const WARN_PERCENT: usize = 80;
+/// Error message for a request whose state was evicted before it completed.
+pub const EVICTED_MESSAGE: &str = "request state evicted";
+
+/// Size of one table, shared with stats for display.
+#[derive(Debug)]
+pub struct TableGauge {
+    pub name: &'static str,
+    pub limit: usize,
+    len: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+impl TableGauge {
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Largest size the table has reached.
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug)]
+struct Slot<V> {
+    value: V,
+    in_flight: bool,
+}
+
+/// Insertion-ordered map of per-request bookkeeping that holds at most
+/// `limit` entries, so a client that leaks requests cannot grow it without
+/// bound. New entries are in flight until settled; a full table evicts its
+/// oldest settled entry, and only when every entry is in flight the oldest
+/// of those. Evicted entries are handed back so the owner can answer the
+/// request they belonged to.
+#[derive(Debug)]
+pub struct Table<K, V> {
+    entries: HashMap<K, Slot<V>>,
+    order: VecDeque<K>,
+    gauge: Arc<TableGauge>,
+    warned: bool,
+}
+
+impl<K: Eq + Hash + Clone, V> Table<K, V> {
+    /// A `limit` of zero is treated as one.
+    pub fn new(name: &'static str, limit: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            gauge: Arc::new(TableGauge {
+                name,
+                limit: limit.max(1),
+                len: AtomicUsize::new(0),
+                peak: AtomicUsize::new(0),
+            }),
+            warned: false,
+        }
+    }
+
+    pub fn gauge(&self) -> Arc<TableGauge> {
+        self.gauge.clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.entries.get(key).map(|slot| &slot.value)
+    }
+
+    /// Inserts `key` as in flight and returns the entry evicted to make room,
+    /// if any.
+    pub fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
+        let evicted = if self.entries.len() >= self.gauge.limit && !self.entries.contains_key(&key)
+        {
+            let at = self
+                .order
+                .iter()
+                .position(|k| !self.entries[k].in_flight)
+                .unwrap_or(0);
+            self.order
+                .remove(at)
+                .and_then(|k| self.entries.remove(&k).map(|slot| (k, slot.value)))
+        } else {
+            None
+        };
+        let slot = Slot {
+            value,
+            in_flight: true,
+        };
+        if self.entries.insert(key.clone(), slot).is_some() {
+            self.order.retain(|k| *k != key);
+        }
+        self.order.push_back(key);
+        self.sync();
+        evicted
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let slot = self.entries.remove(key)?;
+        self.order.retain(|k| k != key);
+        self.sync();
+        Some(slot.value)
+    }
+
+    /// Marks `key` as no longer in flight: its request has been dispatched
+    /// and whatever answer was coming has arrived.
+    pub fn settle(&mut self, key: &K) {
+        if let Some(slot) = self.entries.get_mut(key) {
+            slot.in_flight = false;
+        }
+    }
+
+    /// Removes the oldest entry if it is settled and `expired`.
+    pub fn pop_expired(&mut self, expired: impl Fn(&V) -> bool) -> Option<(K, V)> {
+        let oldest = self.order.front()?;
+        let slot = &self.entries[oldest];
+        if slot.in_flight || !expired(&slot.value) {
+            return None;
+        }
+        let key = self.order.pop_front()?;
+        let slot = self.entries.remove(&key)?;
+        self.sync();
+        Some((key, slot.value))
+    }
+
+    /// Entries, oldest first.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&K, &V)> {
+        self.order.iter().map(|k| (k, &self.entries[k].value))
+    }
+
+    fn sync(&mut self) {
+        let len = self.entries.len();
+        self.gauge.len.store(len, Ordering::Relaxed);
+        self.gauge.peak.fetch_max(len, Ordering::Relaxed);
+        if !self.warned && len * 100 >= self.gauge.limit * WARN_PERCENT {
+            self.warned = true;
+            warn!(
+                table = self.gauge.name,
+                limit = self.gauge.limit,
+                "state table reached {}% of MCP_STATE_TABLE_LIMIT",
+                WARN_PERCENT
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys<V>(table: &Table<u32, V>) -> Vec<u32> {
+        table.iter().map(|(k, _)| *k).collect()
+    }
+
+    #[test]
+    fn evicts_oldest_settled_before_anything_in_flight() {
+        let mut t = Table::new("test", 3);
+        t.insert(1, "a");
+        t.insert(2, "b");
+        t.settle(&1);
+        t.settle(&2);
+        t.insert(3, "c");
+        assert_eq!(t.insert(4, "d"), Some((1, "a")));
+        assert_eq!(t.insert(5, "e"), Some((2, "b")));
+        // Only in-flight entries are left, so the oldest of them goes.
+        assert_eq!(t.insert(6, "f"), Some((3, "c")));
+        assert_eq!(keys(&t), vec![4, 5, 6]);
+    }
+
+    #[test]
+    fn reinserting_a_key_makes_it_newest_and_in_flight() {
+        let mut t = Table::new("test", 2);
+        t.insert(1, "old");
+        t.insert(2, "b");
+        t.settle(&1);
+        t.settle(&2);
+        assert_eq!(t.insert(1, "new"), None);
+        assert_eq!(keys(&t), vec![2, 1]);
+        assert_eq!(t.insert(3, "c"), Some((2, "b")));
+        assert_eq!(t.get(&1), Some(&"new"));
+    }
+
+    #[test]
+    fn expiry_skips_in_flight_entries() {
+        let mut t = Table::new("test", 4);
+        t.insert(1, 10);
+        assert_eq!(t.pop_expired(|_| true), None);
+        t.settle(&1);
+        t.insert(2, 20);
+        assert_eq!(t.pop_expired(|v| *v < 15), Some((1, 10)));
+        assert_eq!(t.pop_expired(|_| true), None);
+    }
+
+    #[test]
+    fn gauge_tracks_size_and_peak() {
+        let mut t = Table::new("test", 5);
+        let gauge = t.gauge();
+        for k in 0..4 {
+            t.insert(k, ());
+        }
+        t.remove(&0);
+        assert_eq!(gauge.len(), 3);
+        assert_eq!(gauge.peak(), 4);
+        assert!(t.warned);
+        assert_eq!(Table::<u32, ()>::new("zero", 0).gauge().limit, 1);
+    }
+}
@@ -0,0 +1,732 @@
+use crate::breaker::CircuitState;
+#[cfg(feature = "conn-metrics")]
+use crate::conn_metrics::Connections;
+use crate::notices::UpstreamNotices;
+use crate::record::Recorder;
+use crate::session::Session;
+use crate::fair::ClientGauge;
+use crate::state::TableGauge;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_METHOD_LIMIT: usize = 64;
+/// Bucket that absorbs every method seen after the per-method map is full.
+pub const OTHER_METHOD: &str = "other";
+/// Key used for messages without a `method` (responses to server requests).
+pub const NO_METHOD: &str = "(none)";
+/// Completed messages kept for the access log ring buffer.
+pub const ACCESS_LOG_CAPACITY: usize = 20;
+/// Ended failure streaks kept for display.
+const RECENT_STREAKS: usize = 5;
+
+/// Upper bounds (inclusive, in ms) of the latency histogram buckets; the last
+/// bucket is open-ended.
+const LATENCY_BOUNDS_MS: [u64; 15] = [
+    1, 2, 5, 10, 25, 50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000, 30_000, 60_000,
+];
+
+#[derive(Clone, Debug, Default)]
+pub struct LatencyHistogram {
+    buckets: [u64; LATENCY_BOUNDS_MS.len() + 1],
+    count: u64,
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, latency: Duration) {
+        let ms = latency.as_millis() as u64;
+        let idx = LATENCY_BOUNDS_MS
+            .iter()
+            .position(|&bound| ms <= bound)
+            .unwrap_or(LATENCY_BOUNDS_MS.len());
+        self.buckets[idx] += 1;
+        self.count += 1;
+    }
+
+    /// Returns the upper bound (ms) of the bucket holding the `p`-th
+    /// percentile, or `None` when nothing was recorded. Samples above the last
+    /// bound report that bound.
+    pub fn percentile(&self, p: f64) -> Option<u64> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = ((p / 100.0) * self.count as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (idx, n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                return Some(LATENCY_BOUNDS_MS[idx.min(LATENCY_BOUNDS_MS.len() - 1)]);
+            }
+        }
+        LATENCY_BOUNDS_MS.last().copied()
+    }
+
+    fn merge(&mut self, other: &LatencyHistogram) {
+        for (a, b) in self.buckets.iter_mut().zip(other.buckets.iter()) {
+            *a += b;
+        }
+        self.count += other.count;
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MethodStats {
+    pub count: u64,
+    pub errors: u64,
+    pub bytes_out: u64,
+    pub bytes_in: u64,
+    pub latency: LatencyHistogram,
+}
+
+impl MethodStats {
+    fn to_json(&self) -> Value {
+        json!({
+            "count": self.count,
+            "errors": self.errors,
+            "bytes_out": self.bytes_out,
+            "bytes_in": self.bytes_in,
+            "p50_ms": self.latency.percentile(50.0),
+            "p95_ms": self.latency.percentile(95.0),
+        })
+    }
+}
+
+/// One completed message, as seen by the bridge.
+#[derive(Clone, Debug)]
+pub struct Sample<'a> {
+    pub method: Option<&'a str>,
+    pub latency: Duration,
+    pub bytes_out: u64,
+    pub bytes_in: u64,
+    pub error: bool,
+}
+
+/// One line of the in-memory access log. Never holds payload contents.
+#[derive(Clone, Debug)]
+pub struct AccessEntry {
+    /// Time since the bridge started.
+    pub at: Duration,
+    pub method: String,
+    pub error: bool,
+    pub latency: Duration,
+    pub bytes_out: u64,
+    pub bytes_in: u64,
+}
+
+impl std::fmt::Display for AccessEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "+{:.1}s {} {} {}ms out={}B in={}B",
+            self.at.as_secs_f64(),
+            self.method,
+            if self.error { "error" } else { "ok" },
+            self.latency.as_millis(),
+            self.bytes_out,
+            self.bytes_in
+        )
+    }
+}
+
+/// A failure streak that has ended.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StreakRecord {
+    pub failures: u64,
+    pub duration: Duration,
+}
+
+/// Connection-level counters, filled in from the upstream responses with
+/// the `conn-metrics` feature. They stay zero without it.
+#[derive(Debug, Default)]
+pub struct ConnCounters {
+    pub opened: AtomicU64,
+    pub tls_handshakes: AtomicU64,
+    pub requests: AtomicU64,
+    #[cfg(feature = "conn-metrics")]
+    connections: Connections,
+}
+
+impl ConnCounters {
+    /// Counts the request `res` answered, and the connection it came over
+    /// when that is a new one.
+    #[cfg(feature = "conn-metrics")]
+    fn observe(&self, res: &reqwest::Response, idle_timeout: Duration) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        if let Some(opened) = self.connections.opened(res, idle_timeout) {
+            self.opened.fetch_add(1, Ordering::Relaxed);
+            if opened.tls {
+                self.tls_handshakes.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Requests served per connection opened; above 1 means pooled
+    /// connections are being reused.
+    pub fn reuse_ratio(&self) -> Option<f64> {
+        let opened = self.opened.load(Ordering::Relaxed);
+        (opened > 0).then(|| self.requests.load(Ordering::Relaxed) as f64 / opened as f64)
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "instrumented": cfg!(feature = "conn-metrics"),
+            "opened": self.opened.load(Ordering::Relaxed),
+            "requests": self.requests.load(Ordering::Relaxed),
+            "reuse_ratio": self.reuse_ratio(),
+            "tls_handshakes": self.tls_handshakes.load(Ordering::Relaxed),
+            // native-tls does not report whether a session was resumed.
+            "tls_resumed": Value::Null,
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    requests: u64,
+    errors: u64,
+    bytes_out: u64,
+    bytes_in: u64,
+    methods: HashMap<String, MethodStats>,
+    other: MethodStats,
+    access_log: VecDeque<AccessEntry>,
+    current_streak: u64,
+    hedges_launched: u64,
+    hedges_won: u64,
+    terminated_ids: Vec<Value>,
+    last_transport_error: Option<String>,
+    sse_retry: Option<Duration>,
+    paused: bool,
+    get_stream_disabled: bool,
+    recent_streaks: VecDeque<StreakRecord>,
+    deprecations_seen: HashSet<(Option<String>, Option<String>)>,
+    deprecation: Option<String>,
+    sunset: Option<String>,
+    rate_limit_remaining: Option<u64>,
+    rate_limit_reset: Option<u64>,
+    tables: Vec<Arc<TableGauge>>,
+    /// Held weakly, so a connection's bridge can end without untracking
+    /// its session.
+    sessions: Vec<Weak<Session>>,
+    /// Client connections under `MCP_LISTEN_ADDR`, held weakly like
+    /// sessions.
+    clients: Vec<Weak<ClientGauge>>,
+    upstream_sent: u64,
+    upstream_responses: u64,
+    retries: u64,
+    failures: BTreeMap<&'static str, u64>,
+    /// The circuit breaker's state, when there is one.
+    circuit: Option<&'static str>,
+    circuit_opened: u64,
+}
+
+/// Bridge-wide counters plus a bounded per-method breakdown and a short
+/// access log, and the traffic recorder when there is one. Shared between
+/// tasks behind an `Arc`.
+#[derive(Debug)]
+pub struct Stats {
+    started: Instant,
+    method_limit: usize,
+    conn: Arc<ConnCounters>,
+    /// Last client message or stdout write, on tokio's clock so paused-time
+    /// tests can drive idle detection.
+    last_activity: Mutex<tokio::time::Instant>,
+    inner: Mutex<Inner>,
+    in_flight: AtomicU64,
+    recorder: Option<Arc<Recorder>>,
+}
+
+/// Counts a client request as in flight until dropped, so an aborted
+/// request task is not counted forever.
+pub struct InFlight<'a>(&'a Stats);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Stats {
+    pub fn new(method_limit: usize) -> Self {
+        Self {
+            started: Instant::now(),
+            method_limit,
+            conn: Arc::default(),
+            last_activity: Mutex::new(tokio::time::Instant::now()),
+            inner: Mutex::new(Inner::default()),
+            in_flight: AtomicU64::new(0),
+            recorder: None,
+        }
+    }
+
+    /// Records traffic to `recorder` (`MCP_RECORD_FILE`).
+    pub fn with_recorder(mut self, recorder: Option<Arc<Recorder>>) -> Self {
+        self.recorder = recorder;
+        self
+    }
+
+    pub fn recorder(&self) -> Option<&Arc<Recorder>> {
+        self.recorder.as_ref()
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Records traffic with the client, which resets idle detection.
+    pub fn note_activity(&self) {
+        *self.last_activity.lock().unwrap() = tokio::time::Instant::now();
+    }
+
+    /// Time since the last client message or stdout write.
+    pub fn idle_for(&self) -> Duration {
+        self.last_activity.lock().unwrap().elapsed()
+    }
+
+    pub fn conn_counters(&self) -> Arc<ConnCounters> {
+        self.conn.clone()
+    }
+
+    /// Counts an upstream response toward connection reuse (the
+    /// `conn-metrics` feature); connections idle for `idle_timeout` have
+    /// been closed.
+    #[cfg(feature = "conn-metrics")]
+    pub fn note_connection(&self, res: &reqwest::Response, idle_timeout: Duration) {
+        self.conn.observe(res, idle_timeout);
+    }
+
+    #[cfg(not(feature = "conn-metrics"))]
+    pub fn note_connection(&self, _res: &reqwest::Response, _idle_timeout: Duration) {}
+
+    pub fn record(&self, sample: Sample<'_>) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.access_log.len() == ACCESS_LOG_CAPACITY {
+            inner.access_log.pop_front();
+        }
+        inner.access_log.push_back(AccessEntry {
+            at: self.started.elapsed(),
+            method: sample.method.unwrap_or(NO_METHOD).to_string(),
+            error: sample.error,
+            latency: sample.latency,
+            bytes_out: sample.bytes_out,
+            bytes_in: sample.bytes_in,
+        });
+        inner.requests += 1;
+        inner.bytes_out += sample.bytes_out;
+        inner.bytes_in += sample.bytes_in;
+        if sample.error {
+            inner.errors += 1;
+        }
+        let method = sample.method.unwrap_or(NO_METHOD);
+        let limit = self.method_limit;
+        let Inner { methods, other, .. } = &mut *inner;
+        let entry = if methods.contains_key(method) {
+            methods.get_mut(method).unwrap()
+        } else if methods.len() < limit {
+            methods.entry(method.to_string()).or_default()
+        } else {
+            other
+        };
+        entry.count += 1;
+        entry.bytes_out += sample.bytes_out;
+        entry.bytes_in += sample.bytes_in;
+        if sample.error {
+            entry.errors += 1;
+        }
+        entry.latency.record(sample.latency);
+    }
+
+    /// The most recent completed messages, oldest first.
+    pub fn access_log(&self) -> Vec<AccessEntry> {
+        self.inner.lock().unwrap().access_log.iter().cloned().collect()
+    }
+
+    pub fn set_failure_streak(&self, failures: u64) {
+        self.inner.lock().unwrap().current_streak = failures;
+    }
+
+    pub fn end_failure_streak(&self, failures: u64, duration: Duration) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.current_streak = 0;
+        if inner.recent_streaks.len() == RECENT_STREAKS {
+            inner.recent_streaks.pop_front();
+        }
+        inner.recent_streaks.push_back(StreakRecord { failures, duration });
+    }
+
+    /// Records the circuit breaker's state (`MCP_CB_FAILURES`).
+    pub fn set_circuit(&self, state: CircuitState) {
+        let mut inner = self.inner.lock().unwrap();
+        if state == CircuitState::Open && inner.circuit != Some(state.as_str()) {
+            inner.circuit_opened += 1;
+        }
+        inner.circuit = Some(state.as_str());
+    }
+
+    /// The circuit breaker's state, and how often it has opened, when there
+    /// is one.
+    pub fn circuit(&self) -> Option<(&'static str, u64)> {
+        let inner = self.inner.lock().unwrap();
+        inner.circuit.map(|state| (state, inner.circuit_opened))
+    }
+
+    pub fn hedge_launched(&self) {
+        self.inner.lock().unwrap().hedges_launched += 1;
+    }
+
+    /// Counts a hedged request whose second copy answered first.
+    pub fn hedge_won(&self) {
+        self.inner.lock().unwrap().hedges_won += 1;
+    }
+
+    /// Records the id of a request answered with a synthesized error because
+    /// the bridge shut down before it completed.
+    pub fn note_terminated(&self, id: Value) {
+        self.inner.lock().unwrap().terminated_ids.push(id);
+    }
+
+    pub fn terminated_ids(&self) -> Vec<Value> {
+        self.inner.lock().unwrap().terminated_ids.clone()
+    }
+
+    pub fn note_transport_error(&self, error: impl Into<String>) {
+        self.inner.lock().unwrap().last_transport_error = Some(error.into());
+    }
+
+    /// An HTTP request went upstream: a client message, a retry, a probe.
+    pub fn note_upstream_sent(&self) {
+        self.inner.lock().unwrap().upstream_sent += 1;
+    }
+
+    /// The upstream answered an HTTP request, with any status.
+    pub fn note_upstream_response(&self) {
+        self.inner.lock().unwrap().upstream_responses += 1;
+    }
+
+    pub fn note_retry(&self) {
+        self.inner.lock().unwrap().retries += 1;
+    }
+
+    /// Counts a request given up on after a transport error, by
+    /// `remote_transport::classify` category.
+    pub fn note_failure_category(&self, category: &'static str) {
+        *self.inner.lock().unwrap().failures.entry(category).or_default() += 1;
+    }
+
+    pub fn start_in_flight(&self) -> InFlight<'_> {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        InFlight(self)
+    }
+
+    pub fn in_flight(&self) -> u64 {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    /// Upstream requests sent, responses received and retries, in that order.
+    pub fn upstream_counts(&self) -> (u64, u64, u64) {
+        let inner = self.inner.lock().unwrap();
+        (inner.upstream_sent, inner.upstream_responses, inner.retries)
+    }
+
+    pub fn failure_categories(&self) -> Vec<(&'static str, u64)> {
+        let inner = self.inner.lock().unwrap();
+        inner.failures.iter().map(|(k, v)| (*k, *v)).collect()
+    }
+
+    pub fn last_transport_error(&self) -> Option<String> {
+        self.inner.lock().unwrap().last_transport_error.clone()
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.inner.lock().unwrap().paused = paused;
+    }
+
+    pub fn paused(&self) -> bool {
+        self.inner.lock().unwrap().paused
+    }
+
+    /// Records whether the GET stream was given up on for this session.
+    pub fn set_get_stream_disabled(&self, disabled: bool) {
+        self.inner.lock().unwrap().get_stream_disabled = disabled;
+    }
+
+    /// Records the deprecation and rate-limit headers of an upstream response.
+    /// Rate-limit values are kept until a later response replaces them.
+    /// Returns true the first time a `Deprecation`/`Sunset` combination is
+    /// seen, so the caller warns about it once.
+    pub fn note_notices(&self, notices: &UpstreamNotices) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        if notices.rate_limit_remaining.is_some() {
+            inner.rate_limit_remaining = notices.rate_limit_remaining;
+            inner.rate_limit_reset = notices.rate_limit_reset;
+        }
+        if !notices.is_deprecated() {
+            return false;
+        }
+        inner.deprecation = notices.deprecation.clone();
+        inner.sunset = notices.sunset.clone();
+        let combination = (notices.deprecation.clone(), notices.sunset.clone());
+        inner.deprecations_seen.insert(combination)
+    }
+
+    /// Last `X-RateLimit-Remaining` and `X-RateLimit-Reset` seen.
+    pub fn rate_limit(&self) -> (Option<u64>, Option<u64>) {
+        let inner = self.inner.lock().unwrap();
+        (inner.rate_limit_remaining, inner.rate_limit_reset)
+    }
+
+    /// Records a server `retry:` hint that lengthened a stream reconnect.
+    pub fn note_sse_retry(&self, retry: Duration) {
+        self.inner.lock().unwrap().sse_retry = Some(retry);
+    }
+
+    /// Consecutive failures of the ongoing streak (0 when healthy).
+    /// Reports the size of a bounded state table from now on.
+    pub fn track_table(&self, gauge: Arc<TableGauge>) {
+        self.inner.lock().unwrap().tables.push(gauge);
+    }
+
+    pub fn tables(&self) -> Vec<Arc<TableGauge>> {
+        self.inner.lock().unwrap().tables.clone()
+    }
+
+    pub fn track_session(&self, session: &Arc<Session>) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.sessions.retain(|s| s.strong_count() > 0);
+        inner.sessions.push(Arc::downgrade(session));
+    }
+
+    pub fn track_client(&self, client: &Arc<ClientGauge>) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.clients.retain(|c| c.strong_count() > 0);
+        inner.clients.push(Arc::downgrade(client));
+    }
+
+    /// Client connections still open, in the order they connected.
+    pub fn clients(&self) -> Vec<Arc<ClientGauge>> {
+        let inner = self.inner.lock().unwrap();
+        inner.clients.iter().filter_map(Weak::upgrade).collect()
+    }
+
+    /// Whether a tracked session has an upstream id, without the id.
+    pub fn session_present(&self) -> bool {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .sessions
+            .iter()
+            .filter_map(Weak::upgrade)
+            .any(|s| s.id().is_some())
+    }
+
+    pub fn failure_streak(&self) -> u64 {
+        self.inner.lock().unwrap().current_streak
+    }
+
+    /// Recently ended failure streaks, oldest first.
+    pub fn recent_streaks(&self) -> Vec<StreakRecord> {
+        self.inner.lock().unwrap().recent_streaks.iter().cloned().collect()
+    }
+
+    /// Per-method stats sorted by count (descending, then name), with the
+    /// `other` bucket last when it has absorbed anything.
+    pub fn methods(&self) -> Vec<(String, MethodStats)> {
+        let inner = self.inner.lock().unwrap();
+        let mut out: Vec<_> = inner
+            .methods
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        out.sort_by(|a, b| b.1.count.cmp(&a.1.count).then_with(|| a.0.cmp(&b.0)));
+        if inner.other.count > 0 {
+            out.push((OTHER_METHOD.to_string(), inner.other.clone()));
+        }
+        out
+    }
+
+    /// Compact `method=count/errors p95` summary of the `n` busiest methods,
+    /// suitable for a single log line.
+    pub fn top_summary(&self, n: usize) -> String {
+        let mut methods = self.methods();
+        methods.sort_by(|a, b| b.1.count.cmp(&a.1.count).then_with(|| a.0.cmp(&b.0)));
+        methods
+            .iter()
+            .take(n)
+            .map(|(name, m)| {
+                format!(
+                    "{}={}/{} p95={}ms",
+                    name,
+                    m.count,
+                    m.errors,
+                    m.latency.percentile(95.0).unwrap_or(0)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    pub fn snapshot(&self) -> Value {
+        let methods: serde_json::Map<String, Value> = self
+            .methods()
+            .into_iter()
+            .map(|(name, m)| (name, m.to_json()))
+            .collect();
+        let clients: Vec<Value> = self
+            .clients()
+            .iter()
+            .map(|c| {
+                json!({
+                    "peer": c.peer.to_string(),
+                    "weight": c.weight,
+                    "queued": c.queued(),
+                    "admitted": c.admitted(),
+                })
+            })
+            .collect();
+        let inner = self.inner.lock().unwrap();
+        let mut all = LatencyHistogram::default();
+        for m in inner.methods.values() {
+            all.merge(&m.latency);
+        }
+        all.merge(&inner.other.latency);
+        let access_log: Vec<String> = inner.access_log.iter().map(|e| e.to_string()).collect();
+        let tables: serde_json::Map<String, Value> = inner
+            .tables
+            .iter()
+            .map(|t| {
+                let gauge = json!({"size": t.len(), "peak": t.peak(), "limit": t.limit});
+                (t.name.to_string(), gauge)
+            })
+            .collect();
+        json!({
+            "uptime_secs": self.started.elapsed().as_secs(),
+            "idle_secs": self.idle_for().as_secs(),
+            "requests": inner.requests,
+            "errors": inner.errors,
+            "bytes_out": inner.bytes_out,
+            "bytes_in": inner.bytes_in,
+            "p50_ms": all.percentile(50.0),
+            "p95_ms": all.percentile(95.0),
+            "failure_streak": inner.current_streak,
+            "circuit": inner.circuit,
+            "circuit_opened": inner.circuit_opened,
+            "paused": inner.paused,
+            "hedges_launched": inner.hedges_launched,
+            "hedges_won": inner.hedges_won,
+            "sse_retry_ms": inner.sse_retry.map(|r| r.as_millis() as u64),
+            "get_stream_disabled": inner.get_stream_disabled,
+            "deprecation": inner.deprecation,
+            "sunset": inner.sunset,
+            "rate_limit_remaining": inner.rate_limit_remaining,
+            "rate_limit_reset": inner.rate_limit_reset,
+            "connections": self.conn.to_json(),
+            "state_tables": tables,
+            "clients": clients,
+            "methods": methods,
+            "access_log": access_log,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(method: &str, ms: u64) -> Sample<'_> {
+        Sample {
+            method: Some(method),
+            latency: Duration::from_millis(ms),
+            bytes_out: 10,
+            bytes_in: 20,
+            error: false,
+        }
+    }
+
+    #[test]
+    fn percentiles_match_known_samples() {
+        let mut h = LatencyHistogram::default();
+        for ms in 1..=100 {
+            h.record(Duration::from_millis(ms));
+        }
+        assert_eq!(h.percentile(50.0), Some(50));
+        assert_eq!(h.percentile(95.0), Some(100));
+        assert_eq!(h.percentile(1.0), Some(1));
+        assert_eq!(LatencyHistogram::default().percentile(50.0), None);
+    }
+
+    #[test]
+    fn percentile_of_slow_outliers_reports_last_bound() {
+        let mut h = LatencyHistogram::default();
+        h.record(Duration::from_secs(120));
+        assert_eq!(h.percentile(95.0), Some(60_000));
+    }
+
+    #[test]
+    fn methods_beyond_limit_go_to_other_bucket() {
+        let stats = Stats::new(2);
+        stats.record(sample("tools/list", 5));
+        stats.record(sample("tools/call", 5));
+        stats.record(sample("prompts/list", 5));
+        stats.record(sample("resources/list", 5));
+        stats.record(sample("tools/list", 5));
+        let methods = stats.methods();
+        let names: Vec<_> = methods.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, vec!["tools/list", "tools/call", OTHER_METHOD]);
+        assert_eq!(methods[0].1.count, 2);
+        assert_eq!(methods[2].1.count, 2);
+        let snap = stats.snapshot();
+        assert_eq!(snap["requests"], 5);
+        assert_eq!(snap["methods"]["other"]["bytes_in"], 40);
+    }
+
+    #[test]
+    fn access_log_keeps_most_recent_entries() {
+        let stats = Stats::new(8);
+        for i in 0..(ACCESS_LOG_CAPACITY + 5) {
+            stats.record(sample("ping", i as u64));
+        }
+        let log = stats.access_log();
+        assert_eq!(log.len(), ACCESS_LOG_CAPACITY);
+        assert_eq!(log[0].latency, Duration::from_millis(5));
+        assert!(log[0].to_string().contains(" ping ok 5ms out=10B in=20B"));
+    }
+
+    #[test]
+    fn top_summary_orders_by_count() {
+        let stats = Stats::new(8);
+        stats.record(sample("ping", 1));
+        for _ in 0..3 {
+            stats.record(sample("tools/call", 30));
+        }
+        stats.record(Sample {
+            error: true,
+            ..sample("tools/call", 30)
+        });
+        assert_eq!(stats.top_summary(1), "tools/call=4/1 p95=50ms");
+    }
+
+    #[test]
+    fn deprecation_is_reported_once_per_combination() {
+        let stats = Stats::new(8);
+        let deprecated = UpstreamNotices {
+            deprecation: Some("true".into()),
+            sunset: Some("Mon, 01 Dec 2025 00:00:00 GMT".into()),
+            rate_limit_remaining: Some(7),
+            rate_limit_reset: Some(30),
+        };
+        assert!(stats.note_notices(&deprecated));
+        assert!(!stats.note_notices(&deprecated));
+        assert!(!stats.note_notices(&UpstreamNotices::default()));
+        assert_eq!(stats.rate_limit(), (Some(7), Some(30)));
+        let later = UpstreamNotices {
+            sunset: Some("Thu, 01 Jan 2026 00:00:00 GMT".into()),
+            ..deprecated
+        };
+        assert!(stats.note_notices(&later));
+        let snap = stats.snapshot();
+        assert_eq!(snap["deprecation"], "true");
+        assert_eq!(snap["sunset"], "Thu, 01 Jan 2026 00:00:00 GMT");
+        assert_eq!(snap["rate_limit_remaining"], 7);
+        assert_eq!(snap["rate_limit_reset"], 30);
+    }
+}
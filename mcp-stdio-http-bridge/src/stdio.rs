@@ -1,61 +1,531 @@
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::sync::mpsc;
-use tracing::{debug, error, instrument};
+use crate::jsonrpc;
+use crate::reassemble::{Assembled, Reassembler};
+use crate::record::Recorder;
+use crate::request_body::RequestBody;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
 
-#[instrument(skip(rx))]
-pub async fn stdout_writer(mut rx: mpsc::Receiver<String>) {
-    let mut stdout = tokio::io::stdout();
+/// Handling of bytes that are not valid UTF-8 (`MCP_STDIN_VALIDATION` for
+/// client lines, `MCP_STDOUT_VALIDATION` for upstream bodies bound for
+/// stdout).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Utf8Mode {
+    /// Refuse invalid input: stdin stops being read, an upstream body
+    /// becomes a transport error.
+    #[default]
+    Strict,
+    /// Replace invalid sequences with U+FFFD and log where the first one was.
+    Lossy,
+    /// Stdin only: send each line upstream as it was read, leaving its
+    /// encoding to the upstream.
+    Bytes,
+}
+
+impl FromStr for Utf8Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "strict" => Ok(Self::Strict),
+            "lossy" => Ok(Self::Lossy),
+            "bytes" => Ok(Self::Bytes),
+            other => Err(format!("unknown UTF-8 mode {:?}", other)),
+        }
+    }
+}
+
+impl Utf8Mode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Strict => "strict",
+            Self::Lossy => "lossy",
+            Self::Bytes => "bytes",
+        }
+    }
+}
+
+/// What the stdin reader does with a client message when the queue to the
+/// bridge is full (`MCP_QUEUE_POLICY`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum QueuePolicy {
+    /// Wait for room; stdin is not read meanwhile.
+    #[default]
+    Block,
+    /// Answer the new message with a `SERVER_BUSY` error.
+    Reject,
+    /// Answer the oldest queued message with a `SERVER_BUSY` error and queue
+    /// the new one in its place.
+    DropOldest,
+}
+
+impl FromStr for QueuePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "block" => Ok(Self::Block),
+            "reject" => Ok(Self::Reject),
+            "drop-oldest" => Ok(Self::DropOldest),
+            other => Err(format!("unknown queue policy {:?}", other)),
+        }
+    }
+}
+
+impl QueuePolicy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Block => "block",
+            Self::Reject => "reject",
+            Self::DropOldest => "drop-oldest",
+        }
+    }
+}
+
+/// The reader's request, under `QueuePolicy::DropOldest`, that the bridge
+/// drop the oldest queued message: only the bridge takes from the queue.
+#[derive(Debug, Default)]
+pub struct EvictOldest {
+    requested: AtomicBool,
+    notify: Notify,
+}
+
+impl EvictOldest {
+    fn request(&self) {
+        self.requested.store(true, Ordering::SeqCst);
+        self.notify.notify_one();
+    }
+
+    /// The bridge made room on its own before getting to the request.
+    fn withdraw(&self) {
+        self.requested.store(false, Ordering::SeqCst);
+    }
+
+    /// Waits for a request; false when it was withdrawn meanwhile.
+    pub async fn requested(&self) -> bool {
+        self.notify.notified().await;
+        self.requested.swap(false, Ordering::SeqCst)
+    }
+}
+
+/// Puts client messages on the bridge's queue under a `QueuePolicy`, and
+/// logs once each time the queue fills up.
+pub struct Enqueue {
+    tx: mpsc::Sender<RequestBody>,
+    policy: QueuePolicy,
+    evict_oldest: Arc<EvictOldest>,
+    errors: mpsc::Sender<String>,
+    full: bool,
+    recorder: Option<Arc<Recorder>>,
+}
+
+impl Enqueue {
+    pub fn new(
+        tx: mpsc::Sender<RequestBody>,
+        policy: QueuePolicy,
+        evict_oldest: Arc<EvictOldest>,
+        errors: mpsc::Sender<String>,
+    ) -> Self {
+        Self {
+            tx,
+            policy,
+            evict_oldest,
+            errors,
+            full: false,
+            recorder: None,
+        }
+    }
+
+    /// Records each message before it is queued (`MCP_RECORD_FILE`).
+    pub fn recording(mut self, recorder: Option<Arc<Recorder>>) -> Self {
+        self.recorder = recorder;
+        self
+    }
+
+    /// Fails once the bridge has stopped taking messages.
+    pub(crate) async fn send(&mut self, msg: RequestBody) -> Result<(), ()> {
+        if let Some(recorder) = &self.recorder {
+            recorder.stdin(&msg.text());
+        }
+        let msg = match self.tx.try_send(msg) {
+            Ok(()) => {
+                if self.full {
+                    info!("stdin queue has room again");
+                    self.full = false;
+                }
+                return Ok(());
+            }
+            Err(TrySendError::Closed(_)) => return Err(()),
+            Err(TrySendError::Full(msg)) => msg,
+        };
+        if !self.full {
+            warn!(
+                policy = self.policy.as_str(),
+                high_water = self.tx.max_capacity(),
+                "stdin queue full"
+            );
+            self.full = true;
+        }
+        match self.policy {
+            QueuePolicy::Block => {}
+            QueuePolicy::Reject => {
+                debug!("rejecting client message, stdin queue full");
+                if let Some(err) =
+                    jsonrpc::error_for(&msg.text(), jsonrpc::SERVER_BUSY, "server busy: bridge queue full")
+                {
+                    self.errors.send(err).await.map_err(|_| ())?;
+                }
+                return Ok(());
+            }
+            QueuePolicy::DropOldest => self.evict_oldest.request(),
+        }
+        let sent = self.tx.send(msg).await.map_err(|_| ());
+        self.evict_oldest.withdraw();
+        sent
+    }
+}
+
+/// Decodes `bytes` under `mode`. When strict, invalid bytes are an error
+/// holding the offset of the first invalid sequence.
+pub fn decode_utf8(bytes: Vec<u8>, mode: Utf8Mode, source: &str) -> Result<String, usize> {
+    match String::from_utf8(bytes) {
+        Ok(s) => Ok(s),
+        Err(e) if mode == Utf8Mode::Lossy => {
+            let offset = e.utf8_error().valid_up_to();
+            // Each invalid sequence becomes one U+FFFD.
+            let replaced = e
+                .as_bytes()
+                .utf8_chunks()
+                .filter(|chunk| !chunk.invalid().is_empty())
+                .count();
+            warn!(source, offset, replaced, "replacing invalid UTF-8");
+            Ok(String::from_utf8_lossy(e.as_bytes()).into_owned())
+        }
+        Err(e) => Err(e.utf8_error().valid_up_to()),
+    }
+}
+
+/// A stdin line as a message body: decoded under `mode`, or under
+/// `Utf8Mode::Bytes` taken as it was read.
+fn decode_line(line: Vec<u8>, mode: Utf8Mode) -> Result<RequestBody, usize> {
+    match mode {
+        Utf8Mode::Bytes => Ok(RequestBody::from(line)),
+        _ => decode_utf8(line, mode, "stdin").map(RequestBody::from),
+    }
+}
+
+/// Writes every line from `rx` to `out` until the last sender is dropped,
+/// flushing after each one, so a line queued before the channel closed is
+/// always written before this returns. Stops early if `out` fails. Each line
+/// goes to `recorder` too, just before it is written.
+pub async fn write_lines<W: AsyncWrite + Unpin>(
+    mut rx: mpsc::Receiver<String>,
+    mut out: W,
+    recorder: Option<Arc<Recorder>>,
+) {
     while let Some(line) = rx.recv().await {
-        if let Err(e) = tokio::io::AsyncWriteExt::write_all(
-            &mut stdout,
-            format!("{}\n", line).as_bytes(),
-        )
-        .await
-        {
+        if let Some(recorder) = &recorder {
+            recorder.stdout(&line);
+        }
+        if let Err(e) = out.write_all(format!("{}\n", line).as_bytes()).await {
             error!(%e, "stdout write failed");
-            break;
+            return;
         }
-        if let Err(e) = stdout.flush().await {
+        if let Err(e) = out.flush().await {
             error!(%e, "stdout flush failed");
-            break;
+            return;
         }
         debug!(len = line.len(), "wrote response line");
     }
+    debug!("stdout queue closed, writer done");
 }
 
-pub async fn stdin_reader(
-    tx: mpsc::Sender<String>,
-    mut shutdown: tokio::sync::oneshot::Receiver<()>,
-) {
-    let mut reader = BufReader::new(tokio::io::stdin());
-    let mut line = String::new();
+/// Largest message accepted in either direction when
+/// `MCP_MAX_MESSAGE_BYTES` is not set.
+pub const DEFAULT_MAX_MESSAGE_BYTES: usize = 16 * 1024 * 1024;
+
+/// What `read_line` found.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Line {
+    /// A line, without its newline, is in the buffer.
+    Read,
+    /// The line was longer than the limit. The rest of it has been read and
+    /// thrown away; the buffer holds its first `limit` bytes.
+    TooLong,
+    Eof,
+}
+
+/// Reads one line into `buf` without ever holding more than `limit` bytes
+/// of it, the newline not counted. A last line without a newline is read
+/// like any other.
+pub async fn read_line<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+    limit: usize,
+) -> std::io::Result<Line> {
+    let mut too_long = false;
+    loop {
+        let available = reader.fill_buf().await?;
+        if available.is_empty() {
+            return Ok(match () {
+                _ if too_long => Line::TooLong,
+                _ if buf.is_empty() => Line::Eof,
+                _ => Line::Read,
+            });
+        }
+        let newline = available.iter().position(|&b| b == b'\n');
+        let part = &available[..newline.unwrap_or(available.len())];
+        if !too_long {
+            let room = limit - buf.len();
+            too_long = part.len() > room;
+            buf.extend_from_slice(&part[..part.len().min(room)]);
+        }
+        let used = part.len() + usize::from(newline.is_some());
+        reader.consume(used);
+        if newline.is_some() {
+            return Ok(if too_long { Line::TooLong } else { Line::Read });
+        }
+    }
+}
+
+/// Reads client lines from `input` into `queue`. With a `Reassembler`,
+/// lines split by a broken client are joined first; fragments that never
+/// form a message are answered with a parse error on `errors`. A line over
+/// `max_line` bytes is dropped and answered with an error, under its id
+/// when that can be found. Fails when `input` cannot be read, or holds invalid UTF-8 in
+/// strict mode; the end of `input` is not a failure.
+pub async fn read_lines<R: AsyncRead + Unpin>(
+    input: R,
+    mut queue: Enqueue,
+    shutdown: CancellationToken,
+    mode: Utf8Mode,
+    max_line: usize,
+    mut reassembler: Option<Reassembler>,
+    errors: mpsc::Sender<String>,
+) -> std::io::Result<()> {
+    let mut reader = BufReader::new(input);
+    let mut line = Vec::new();
     loop {
         tokio::select! {
-            _ = &mut shutdown => {
+            _ = shutdown.cancelled() => {
                 debug!("stdin reader received shutdown");
                 break;
             }
-            res = reader.read_line(&mut line) => {
+            res = read_line(&mut reader, &mut line, max_line) => {
                 match res {
-                    Ok(0) => break,
-                    Ok(_) => {
-                        let trimmed = line.trim_end_matches('\n').trim_end_matches('\r');
-                        if trimmed.is_empty() {
-                            line.clear();
+                    Ok(Line::Eof) => {
+                        if let Some(Assembled::Unparseable { lines }) =
+                            reassembler.as_mut().and_then(Reassembler::finish)
+                        {
+                            fragment_error(&errors, lines).await;
+                        }
+                        break;
+                    }
+                    Ok(Line::TooLong) => {
+                        let prefix = std::mem::take(&mut line);
+                        too_long_error(&errors, &prefix, max_line).await;
+                    }
+                    Ok(Line::Read) => {
+                        while matches!(line.last(), Some(b'\r')) {
+                            line.pop();
+                        }
+                        if line.is_empty() {
                             continue;
                         }
-                        if tx.send(trimmed.to_string()).await.is_err() {
-                            break;
+                        let line = std::mem::take(&mut line);
+                        let msg = match decode_line(line, mode) {
+                            Ok(msg) => msg,
+                            Err(offset) => {
+                                error!(
+                                    offset,
+                                    "stdin read error: stream did not contain valid UTF-8"
+                                );
+                                return Err(std::io::Error::new(
+                                    std::io::ErrorKind::InvalidData,
+                                    "stream did not contain valid UTF-8",
+                                ));
+                            }
+                        };
+                        let queued = match reassembler.as_mut() {
+                            Some(r) => match msg.into_string() {
+                                Ok(msg) => enqueue(&mut queue, &errors, r.push(msg)).await,
+                                // Only text is joined: a line that is not
+                                // UTF-8 is queued as it was read.
+                                Err(raw) => queue.send(raw).await,
+                            },
+                            None => queue.send(msg).await,
+                        };
+                        if queued.is_err() {
+                            return Ok(());
                         }
-                        line.clear();
                     }
                     Err(e) => {
                         error!(%e, "stdin read error");
-                        break;
+                        return Err(e);
                     }
                 }
             }
         }
     }
-    drop(tx);
+    drop(queue);
+    Ok(())
+}
+
+/// Queues what the reassembler gave back. Fails once the bridge has stopped
+/// taking messages.
+async fn enqueue(
+    queue: &mut Enqueue,
+    errors: &mpsc::Sender<String>,
+    assembled: Vec<Assembled>,
+) -> Result<(), ()> {
+    for item in assembled {
+        match item {
+            Assembled::Message(msg) => queue.send(RequestBody::from(msg)).await?,
+            Assembled::Unparseable { lines } => fragment_error(errors, lines).await,
+        }
+    }
+    Ok(())
+}
+
+async fn too_long_error(errors: &mpsc::Sender<String>, prefix: &[u8], limit: usize) {
+    let id = jsonrpc::salvage_id(&String::from_utf8_lossy(prefix));
+    warn!(?id, limit, "dropping a stdin line longer than MCP_MAX_LINE_BYTES");
+    let err = jsonrpc::error_response(
+        id.as_ref(),
+        jsonrpc::INVALID_REQUEST,
+        &format!("request too large: over {} bytes (MCP_MAX_LINE_BYTES)", limit),
+    );
+    let _ = errors.send(err).await;
+}
+
+async fn fragment_error(errors: &mpsc::Sender<String>, lines: usize) {
+    warn!(lines, "giving up on reassembling a split JSON message");
+    let err = jsonrpc::error_response(
+        None,
+        jsonrpc::PARSE_ERROR,
+        &format!("could not reassemble a JSON message from {} lines", lines),
+    );
+    let _ = errors.send(err).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INVALID: &[u8] = b"{\"s\":\"a\xffb\xc3\"}";
+
+    #[test]
+    fn strict_refuses_invalid_utf8() {
+        assert_eq!(decode_utf8(INVALID.to_vec(), Utf8Mode::Strict, "test"), Err(7));
+        assert_eq!(
+            decode_utf8(b"{}".to_vec(), Utf8Mode::Strict, "test").as_deref(),
+            Ok("{}")
+        );
+    }
+
+    #[test]
+    fn lossy_replaces_invalid_sequences() {
+        let out = decode_utf8(INVALID.to_vec(), Utf8Mode::Lossy, "test").unwrap();
+        assert_eq!(out, "{\"s\":\"a\u{fffd}b\u{fffd}\"}");
+    }
+
+    #[tokio::test]
+    async fn writer_drains_queue_after_senders_drop() {
+        let (tx, rx) = mpsc::channel(8);
+        for i in 0..5 {
+            tx.send(format!("{{\"id\":{}}}", i)).await.unwrap();
+        }
+        drop(tx);
+        let mut out = Vec::new();
+        write_lines(rx, &mut out, None).await;
+        let mut expected = String::new();
+        for i in 0..5 {
+            expected.push_str(&format!("{{\"id\":{}}}\n", i));
+        }
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn reject_answers_overflow_with_busy_error() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let (errors, mut out) = mpsc::channel(4);
+        let evict = Arc::new(EvictOldest::default());
+        let mut queue = Enqueue::new(tx, QueuePolicy::Reject, evict, errors);
+        queue.send(r#"{"id":1,"method":"a"}"#.into()).await.unwrap();
+        queue.send(r#"{"id":2,"method":"b"}"#.into()).await.unwrap();
+        queue.send(r#"{"method":"n"}"#.into()).await.unwrap();
+        assert_eq!(rx.recv().await.unwrap().as_str(), Some(r#"{"id":1,"method":"a"}"#));
+        let err: serde_json::Value = serde_json::from_str(&out.recv().await.unwrap()).unwrap();
+        assert_eq!(err["id"], 2);
+        assert_eq!(err["error"]["code"], jsonrpc::SERVER_BUSY);
+        assert!(out.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn lines_over_the_limit_are_skipped_to_their_end() {
+        let input: &[u8] = b"12345\n123456\r\n1234567890\nok";
+        // A small buffer makes lines span several reads.
+        let mut reader = BufReader::with_capacity(3, input);
+        let mut line = Vec::new();
+        let mut read = Vec::new();
+        loop {
+            let result = read_line(&mut reader, &mut line, 6).await.unwrap();
+            if result == Line::Eof {
+                break;
+            }
+            read.push((result, String::from_utf8(std::mem::take(&mut line)).unwrap()));
+        }
+        assert_eq!(
+            read,
+            vec![
+                (Line::Read, "12345".to_string()),
+                (Line::TooLong, "123456".to_string()),
+                (Line::TooLong, "123456".to_string()),
+                (Line::Read, "ok".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn line_of_exactly_the_limit_is_read() {
+        let mut reader = BufReader::new(&b"123456\n"[..]);
+        let mut line = Vec::new();
+        assert_eq!(read_line(&mut reader, &mut line, 6).await.unwrap(), Line::Read);
+        assert_eq!(line, b"123456");
+        line.clear();
+        assert_eq!(read_line(&mut reader, &mut line, 6).await.unwrap(), Line::Eof);
+    }
+
+    #[test]
+    fn queue_policy_names() {
+        for policy in [
+            QueuePolicy::Block,
+            QueuePolicy::Reject,
+            QueuePolicy::DropOldest,
+        ] {
+            assert_eq!(policy.as_str().parse(), Ok(policy));
+        }
+        assert!("drop_newest".parse::<QueuePolicy>().is_err());
+    }
+
+    #[test]
+    fn utf8_mode_names() {
+        for mode in [Utf8Mode::Strict, Utf8Mode::Lossy, Utf8Mode::Bytes] {
+            assert_eq!(mode.as_str().parse(), Ok(mode));
+        }
+        assert_eq!("Lossy".parse(), Ok(Utf8Mode::Lossy));
+        assert!("latin1".parse::<Utf8Mode>().is_err());
+    }
+
+    #[test]
+    fn bytes_mode_keeps_the_line_as_read() {
+        let body = decode_line(INVALID.to_vec(), Utf8Mode::Bytes).unwrap();
+        assert_eq!(body.as_bytes(), INVALID);
+        assert_eq!(decode_line(INVALID.to_vec(), Utf8Mode::Strict), Err(7));
+    }
 }
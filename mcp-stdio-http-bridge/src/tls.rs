@@ -0,0 +1,292 @@
+use crate::config::{Config, Secret};
+use std::path::Path;
+
+/// A client certificate with its private key, as read from disk.
+#[derive(Clone)]
+enum ClientIdentity {
+    /// PEM certificate chain plus a PKCS#8 PEM key (`MCP_CLIENT_KEY`).
+    Pem { cert: Vec<u8>, key: Vec<u8> },
+    /// PKCS#12 bundle holding both, opened with `MCP_CLIENT_CERT_PASSWORD`.
+    Pkcs12 { der: Vec<u8>, password: String },
+}
+
+fn read(what: &str, path: &Path) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|e| format!("cannot read {} {}: {}", what, path.display(), e))
+}
+
+/// Reads the client certificate for mutual TLS. A file starting with a PEM
+/// header is taken as a certificate chain that needs `key`; anything else
+/// as PKCS#12.
+fn read_identity(
+    cert: &Path,
+    key: Option<&Path>,
+    password: Option<&str>,
+) -> Result<ClientIdentity, String> {
+    let bytes = read("client certificate", cert)?;
+    if bytes.trim_ascii_start().starts_with(b"-----BEGIN") {
+        let Some(key) = key else {
+            return Err(format!(
+                "client certificate {} is PEM; MCP_CLIENT_KEY must name its private key",
+                cert.display()
+            ));
+        };
+        return Ok(ClientIdentity::Pem {
+            cert: bytes,
+            key: read("client key", key)?,
+        });
+    }
+    if key.is_some() {
+        return Err(format!(
+            "client certificate {} is PKCS#12, which holds its own key; unset MCP_CLIENT_KEY",
+            cert.display()
+        ));
+    }
+    Ok(ClientIdentity::Pkcs12 {
+        der: bytes,
+        password: password.unwrap_or_default().to_string(),
+    })
+}
+
+/// Loads and parses the client certificate, so a bad one stops the bridge
+/// at startup. Errors name the files but never the password.
+pub fn identity(
+    cert: &Path,
+    key: Option<&Path>,
+    password: Option<&str>,
+) -> Result<reqwest::Identity, String> {
+    let parsed = match read_identity(cert, key, password)? {
+        ClientIdentity::Pem { cert, key } => reqwest::Identity::from_pkcs8_pem(&cert, &key),
+        ClientIdentity::Pkcs12 { der, password } => {
+            reqwest::Identity::from_pkcs12_der(&der, &password)
+        }
+    };
+    parsed.map_err(|e| match key {
+        Some(key) => format!(
+            "cannot use client certificate {} with key {}: {}",
+            cert.display(),
+            key.display(),
+            e
+        ),
+        None => format!("cannot use client certificate {}: {}", cert.display(), e),
+    })
+}
+
+/// The certificates in a PEM bundle, each with its own BEGIN/END lines.
+fn pem_certificates(bundle: &[u8]) -> Vec<&[u8]> {
+    const BEGIN: &[u8] = b"-----BEGIN CERTIFICATE-----";
+    const END: &[u8] = b"-----END CERTIFICATE-----";
+    let find =
+        |haystack: &[u8], needle: &[u8]| haystack.windows(needle.len()).position(|w| w == needle);
+    let mut blocks = Vec::new();
+    let mut rest = bundle;
+    while let Some(start) = find(rest, BEGIN) {
+        let Some(len) = find(&rest[start..], END) else {
+            // An unterminated block is left for the parser to reject.
+            blocks.push(&rest[start..]);
+            break;
+        };
+        let end = start + len + END.len();
+        blocks.push(&rest[start..end]);
+        rest = &rest[end..];
+    }
+    blocks
+}
+
+/// Reads a PEM bundle of extra root certificates (`MCP_CA_CERT`), parsing
+/// each with `parse`. Errors name the file and which certificate failed.
+fn read_roots<T, E: std::fmt::Display>(
+    path: &Path,
+    parse: impl Fn(&[u8]) -> Result<T, E>,
+) -> Result<Vec<T>, String> {
+    let bundle = read("CA bundle", path)?;
+    let blocks = pem_certificates(&bundle);
+    if blocks.is_empty() {
+        return Err(format!(
+            "no PEM certificates in CA bundle {}",
+            path.display()
+        ));
+    }
+    blocks
+        .iter()
+        .enumerate()
+        .map(|(i, block)| {
+            parse(block).map_err(|e| {
+                format!(
+                    "cannot parse certificate {} in CA bundle {}: {}",
+                    i + 1,
+                    path.display(),
+                    e
+                )
+            })
+        })
+        .collect()
+}
+
+/// Loads and parses the extra root certificates, so a malformed bundle
+/// stops the bridge at startup.
+pub fn ca_certificates(path: &Path) -> Result<Vec<reqwest::Certificate>, String> {
+    read_roots(path, reqwest::Certificate::from_pem)
+}
+
+/// Applies the TLS settings to a client for the upstream or the OAuth2
+/// token endpoint: the client certificate, extra roots on top of the
+/// system ones, and `MCP_TLS_INSECURE`.
+pub fn configure(builder: reqwest::ClientBuilder, config: &Config) -> reqwest::ClientBuilder {
+    let mut builder = builder.danger_accept_invalid_certs(config.tls_insecure);
+    if let Some(cert) = &config.client_cert {
+        builder = builder.identity(
+            identity(
+                cert,
+                config.client_key.as_deref(),
+                config.client_cert_password.as_ref().map(Secret::expose),
+            )
+            .expect("checked at startup"),
+        );
+    }
+    if let Some(path) = &config.ca_cert {
+        for cert in ca_certificates(path).expect("checked at startup") {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+    builder
+}
+
+/// The TLS settings for WebSocket connections.
+#[cfg(feature = "websocket")]
+pub fn native_connector(config: &Config) -> native_tls::TlsConnector {
+    let mut builder = native_tls::TlsConnector::builder();
+    builder.danger_accept_invalid_certs(config.tls_insecure);
+    if let Some(path) = &config.ca_cert {
+        for cert in read_roots(path, native_tls::Certificate::from_pem).expect("checked at startup")
+        {
+            builder.add_root_certificate(cert);
+        }
+    }
+    if let Some(cert) = &config.client_cert {
+        let identity = read_identity(
+            cert,
+            config.client_key.as_deref(),
+            config.client_cert_password.as_ref().map(Secret::expose),
+        )
+        .and_then(|identity| {
+            match identity {
+                ClientIdentity::Pem { cert, key } => native_tls::Identity::from_pkcs8(&cert, &key),
+                ClientIdentity::Pkcs12 { der, password } => {
+                    native_tls::Identity::from_pkcs12(&der, &password)
+                }
+            }
+            .map_err(|e| e.to_string())
+        })
+        .expect("checked at startup");
+        builder.identity(identity);
+    }
+    builder.build().expect("tls connector")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    #[test]
+    fn pem_pairs_and_pkcs12_bundles_load() {
+        let cert = fixture("client.crt");
+        let key = fixture("client.key");
+        assert!(identity(&cert, Some(&key), None).is_ok());
+        assert!(identity(&fixture("client.p12"), None, Some("bridge-test")).is_ok());
+    }
+
+    #[test]
+    fn configured_clients_present_the_certificate() {
+        let cert = fixture("client.p12");
+        let config = Config::from_lookup(|name| match name {
+            "MCP_CLIENT_CERT" => cert.to_str().map(String::from),
+            "MCP_CLIENT_CERT_PASSWORD" => Some("bridge-test".to_string()),
+            _ => None,
+        });
+        assert!(configure(reqwest::Client::builder(), &config)
+            .build()
+            .is_ok());
+        #[cfg(feature = "websocket")]
+        native_connector(&config);
+    }
+
+    #[test]
+    fn ca_bundles_hold_one_or_more_certificates() {
+        let roots = ca_certificates(&fixture("localhost.crt")).unwrap();
+        assert_eq!(roots.len(), 1);
+        let bundle = [
+            std::fs::read(fixture("localhost.crt")).unwrap(),
+            std::fs::read(fixture("client.crt")).unwrap(),
+        ]
+        .concat();
+        assert_eq!(pem_certificates(&bundle).len(), 2);
+        assert_eq!(pem_certificates(b"# comment only\n").len(), 0);
+    }
+
+    #[test]
+    fn malformed_ca_bundles_are_named() {
+        let key = fixture("client.key");
+        assert_eq!(
+            ca_certificates(&key).unwrap_err(),
+            format!("no PEM certificates in CA bundle {}", key.display())
+        );
+        let path = std::env::temp_dir().join(format!("bridge-bad-ca-{}.pem", std::process::id()));
+        let mut bundle = std::fs::read(fixture("localhost.crt")).unwrap();
+        bundle.extend_from_slice(b"-----BEGIN CERTIFICATE-----\nbm90IGEgY2VydA==\n");
+        bundle.extend_from_slice(b"-----END CERTIFICATE-----\n");
+        std::fs::write(&path, bundle).unwrap();
+        let err = ca_certificates(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(
+            err.starts_with(&format!(
+                "cannot parse certificate 2 in CA bundle {}:",
+                path.display()
+            )),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn errors_name_the_file() {
+        let cert = fixture("client.crt");
+        let missing = fixture("missing.pem");
+        let err = identity(&missing, None, None).unwrap_err();
+        assert!(err.starts_with(&format!(
+            "cannot read client certificate {}:",
+            missing.display()
+        )));
+        let err = identity(&cert, None, None).unwrap_err();
+        assert_eq!(
+            err,
+            format!(
+                "client certificate {} is PEM; MCP_CLIENT_KEY must name its private key",
+                cert.display()
+            )
+        );
+        // A certificate is no key.
+        let err = identity(&cert, Some(&cert), None).unwrap_err();
+        assert!(err.starts_with(&format!(
+            "cannot use client certificate {} with key {}:",
+            cert.display(),
+            cert.display()
+        )));
+        let p12 = fixture("client.p12");
+        let err = identity(&p12, None, Some("wrong-password")).unwrap_err();
+        assert!(err.starts_with(&format!("cannot use client certificate {}:", p12.display())));
+        assert!(!err.contains("wrong-password"));
+        let err = identity(&p12, Some(&fixture("client.key")), None).unwrap_err();
+        assert!(
+            err.contains("is PKCS#12, which holds its own key"),
+            "{}",
+            err
+        );
+    }
+}
@@ -0,0 +1,201 @@
+//! Shutting down when the client process is gone (`MCP_PARENT_PID`). A
+//! client that crashes without closing the bridge's stdin, e.g. because a
+//! grandchild inherited the pipe, would otherwise leave the bridge running
+//! with its session and token for good. The watched process is polled every
+//! `POLL_INTERVAL`; nothing is watched unless the option is set.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+pub const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Which process to watch (`MCP_PARENT_PID`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ParentWatch {
+    /// Nothing.
+    #[default]
+    Off,
+    /// The process that started the bridge (Unix only).
+    Auto,
+    /// This process id.
+    Pid(u32),
+}
+
+impl FromStr for ParentWatch {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "" | "off" => Ok(Self::Off),
+            "auto" => Ok(Self::Auto),
+            other => match other.parse() {
+                Ok(0) | Err(_) => Err(format!(
+                    "unknown parent process {:?}; use a process id, auto or off",
+                    other
+                )),
+                Ok(pid) => Ok(Self::Pid(pid)),
+            },
+        }
+    }
+}
+
+impl fmt::Display for ParentWatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Off => f.write_str("off"),
+            Self::Auto => f.write_str("auto"),
+            Self::Pid(pid) => write!(f, "{}", pid),
+        }
+    }
+}
+
+impl ParentWatch {
+    /// The process id to watch, if any. `Auto` takes the current parent;
+    /// there is none worth watching when that is already init.
+    pub fn pid(self) -> Option<u32> {
+        match self {
+            Self::Off => None,
+            Self::Pid(pid) => Some(pid),
+            Self::Auto => {
+                let parent = sys::parent_pid();
+                if parent.is_none() {
+                    warn!("MCP_PARENT_PID=auto: no parent process to watch, the watchdog is off");
+                }
+                parent
+            }
+        }
+    }
+}
+
+/// Resolves once process `pid` has exited, or once the bridge has been
+/// handed to init because its parent exited (Unix). Never resolves when
+/// the process cannot be watched at all.
+pub async fn parent_exited(pid: u32) {
+    let Some(mut watched) = sys::Watched::open(pid) else {
+        warn!(pid, "cannot watch the parent process, the watchdog is off");
+        return std::future::pending().await;
+    };
+    debug!(pid, interval = ?POLL_INTERVAL, "watching the parent process");
+    loop {
+        if !watched.alive() {
+            return;
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+#[cfg(unix)]
+mod sys {
+    /// Init, which adopts the orphans of an exited parent.
+    const INIT_PID: u32 = 1;
+
+    pub fn parent_pid() -> Option<u32> {
+        // SAFETY: getppid has no preconditions and cannot fail.
+        let parent = unsafe { libc::getppid() } as u32;
+        (parent > INIT_PID).then_some(parent)
+    }
+
+    pub struct Watched {
+        pid: libc::pid_t,
+        /// Whether `pid` was the bridge's parent when the watch began, in
+        /// which case being re-parented means it exited.
+        is_parent: bool,
+    }
+
+    impl Watched {
+        pub fn open(pid: u32) -> Option<Self> {
+            Some(Self {
+                pid: libc::pid_t::try_from(pid).ok()?,
+                is_parent: parent_pid() == Some(pid),
+            })
+        }
+
+        pub fn alive(&mut self) -> bool {
+            if self.is_parent && parent_pid() != Some(self.pid as u32) {
+                return false;
+            }
+            // SAFETY: signal 0 only checks that the process exists and may
+            // be signalled; nothing is sent.
+            let found = unsafe { libc::kill(self.pid, 0) } == 0;
+            // A process owned by another user exists but refuses signals.
+            found || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+        }
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use windows_sys::Win32::Foundation::{CloseHandle, HANDLE, WAIT_TIMEOUT};
+    use windows_sys::Win32::System::Threading::{
+        OpenProcess, WaitForSingleObject, PROCESS_SYNCHRONIZE,
+    };
+
+    /// Windows does not keep track of a process's parent once it has
+    /// started, so `MCP_PARENT_PID=auto` needs an explicit id there.
+    pub fn parent_pid() -> Option<u32> {
+        None
+    }
+
+    pub struct Watched(HANDLE);
+
+    // SAFETY: a process handle may be used and closed from any thread.
+    unsafe impl Send for Watched {}
+
+    impl Watched {
+        pub fn open(pid: u32) -> Option<Self> {
+            // SAFETY: plain call; a null handle reports failure.
+            let handle = unsafe { OpenProcess(PROCESS_SYNCHRONIZE, 0, pid) };
+            (handle != 0).then_some(Self(handle))
+        }
+
+        pub fn alive(&mut self) -> bool {
+            // SAFETY: the handle is open until drop. A zero timeout only
+            // asks whether the process has ended.
+            unsafe { WaitForSingleObject(self.0, 0) == WAIT_TIMEOUT }
+        }
+    }
+
+    impl Drop for Watched {
+        fn drop(&mut self) {
+            // SAFETY: the handle was opened by `open` and is closed once.
+            unsafe { CloseHandle(self.0) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_parse() {
+        assert_eq!("".parse(), Ok(ParentWatch::Off));
+        assert_eq!("off".parse(), Ok(ParentWatch::Off));
+        assert_eq!("AUTO".parse(), Ok(ParentWatch::Auto));
+        assert_eq!("4242".parse(), Ok(ParentWatch::Pid(4242)));
+        assert!("0".parse::<ParentWatch>().is_err());
+        assert!("-1".parse::<ParentWatch>().is_err());
+        assert!("parent".parse::<ParentWatch>().is_err());
+        assert_eq!(ParentWatch::Pid(4242).to_string(), "4242");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn an_exited_process_is_noticed() {
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        let exited = tokio::time::timeout(POLL_INTERVAL, parent_exited(pid)).await;
+        assert!(exited.is_ok());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn a_running_process_is_not() {
+        let pid = std::process::id();
+        let exited = tokio::time::timeout(Duration::from_millis(100), parent_exited(pid)).await;
+        assert!(exited.is_err());
+    }
+}
@@ -0,0 +1,377 @@
+//! The upstream as an MCP WebSocket endpoint, for a `ws://` or `wss://`
+//! `MCP_BRIDGE_URI` (`--features websocket`). Each client line goes out as a
+//! text frame and each text frame that comes back is written to stdout.
+//! There are no per-request round trips, so retries, hedging, failover and the
+//! message rewriters do not apply; a dropped connection is reopened with
+//! backoff instead, and the requests it left unanswered get an error.
+
+/// Whether `uri` names a WebSocket endpoint.
+pub fn is_websocket(uri: &str) -> bool {
+    uri.starts_with("ws://") || uri.starts_with("wss://")
+}
+
+/// Seconds between WebSocket pings when `MCP_WS_PING_SECS` is not set.
+pub const DEFAULT_PING_SECS: u64 = 20;
+/// Subprotocol requested in the handshake, as the MCP SDKs do.
+pub const SUBPROTOCOL: &str = "mcp";
+
+#[cfg(feature = "websocket")]
+pub use connection::run_bridge;
+
+#[cfg(feature = "websocket")]
+mod connection {
+    use super::SUBPROTOCOL;
+    use crate::aliases::MethodAliases;
+    use crate::auth::{AuthError, AuthProvider};
+    use crate::body_log;
+    use crate::bridge;
+    use crate::config::{redact_uri, Config};
+    use crate::health::StreamReconnect;
+    use crate::jsonrpc;
+    use crate::method_policy::MethodPolicy;
+    use crate::remote_transport;
+    use crate::request_body::RequestBody;
+    use crate::responder::{Closed, Responder};
+    use crate::server_log::LogNotifications;
+    use crate::stats::Stats;
+    use crate::stdio::EvictOldest;
+    use crate::tls;
+    use futures_util::{SinkExt, StreamExt};
+    use serde_json::Value;
+    use std::collections::{HashMap, VecDeque};
+    use std::fmt;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use tokio::net::TcpStream;
+    use tokio::sync::mpsc;
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+    use tokio_tungstenite::tungstenite::http::{HeaderName, HeaderValue};
+    use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+    use tokio_tungstenite::tungstenite::{self, Message};
+    use tokio_tungstenite::{Connector, MaybeTlsStream, WebSocketStream};
+    use tokio_util::sync::CancellationToken;
+    use tracing::{debug, info, warn};
+
+    const INITIAL_RECONNECT: Duration = Duration::from_millis(500);
+    const LOST_MESSAGE: &str = "upstream connection lost";
+
+    type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+    #[derive(Debug)]
+    enum WsError {
+        Credentials(AuthError),
+        Handshake(tungstenite::Error),
+        Timeout,
+        /// The open connection failed or was closed.
+        Lost(String),
+    }
+
+    impl fmt::Display for WsError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                WsError::Credentials(e) => write!(f, "could not acquire credentials: {}", e),
+                WsError::Handshake(e) => write!(f, "handshake failed: {}", e),
+                WsError::Timeout => write!(f, "handshake timed out"),
+                WsError::Lost(why) => write!(f, "connection lost: {}", why),
+            }
+        }
+    }
+
+    /// Client requests sent on the current connection and not answered yet,
+    /// by id.
+    #[derive(Default)]
+    struct Outstanding(HashMap<String, Value>);
+
+    impl Outstanding {
+        fn sent(&mut self, msg: &str) {
+            for id in jsonrpc::request_ids(msg) {
+                self.0.insert(id.to_string(), id);
+            }
+        }
+
+        fn answered(&mut self, msg: &str) {
+            for id in jsonrpc::answered_ids(msg) {
+                self.0.remove(&id.to_string());
+            }
+        }
+
+        fn is_empty(&self) -> bool {
+            self.0.is_empty()
+        }
+
+        /// Answers every request left with an error and forgets them.
+        async fn lost(&mut self, responder: &Responder) -> Result<(), Closed> {
+            for (_, id) in self.0.drain() {
+                let err = jsonrpc::error_response(Some(&id), jsonrpc::INTERNAL_ERROR, LOST_MESSAGE);
+                responder.local(err).await?;
+            }
+            Ok(())
+        }
+
+        /// Answers every request left once the shutdown grace is over.
+        async fn terminated(&mut self, responder: &Responder) -> Result<(), Closed> {
+            if !self.is_empty() {
+                warn!(abandoned = self.0.len(), "in-flight requests outlived shutdown grace, abandoning");
+            }
+            for (_, id) in self.0.drain() {
+                responder.terminated_id(&id, "bridge terminated").await?;
+            }
+            Ok(())
+        }
+    }
+
+    enum Ended {
+        /// Nothing is left to answer, or stdout went away.
+        Done,
+        Lost(WsError),
+    }
+
+    /// Runs the bridge over one WebSocket connection at a time, reopening it
+    /// with backoff whenever it drops, until stdin has closed and everything
+    /// sent is answered. Once shutdown begins, that gets
+    /// `MCP_SHUTDOWN_GRACE_MS` to happen.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn run_bridge(
+        config: Config,
+        mut rx: mpsc::Receiver<RequestBody>,
+        evict_oldest: Arc<EvictOldest>,
+        errors: mpsc::Receiver<String>,
+        tx_out: mpsc::Sender<String>,
+        shutdown: CancellationToken,
+        stats: Arc<Stats>,
+        auth: Arc<AuthProvider>,
+    ) {
+        let responder = Responder::new(tx_out, stats.clone(), MethodAliases::default(), None)
+            .with_log_notifications(LogNotifications::for_config(&config));
+        tokio::spawn(responder.clone().forward_errors(errors));
+        let policy = MethodPolicy::new(&config.allow_methods, &config.deny_methods);
+        let grace_over = CancellationToken::new();
+        let _end_grace_on_return = grace_over.clone().drop_guard();
+        tokio::spawn(bridge::end_grace(
+            shutdown.clone(),
+            grace_over.clone(),
+            config.shutdown_grace,
+        ));
+        let mut reconnect = StreamReconnect::new(INITIAL_RECONNECT, config.max_backoff);
+        let mut outstanding = Outstanding::default();
+        let mut stdin_open = true;
+        loop {
+            let connected = tokio::select! {
+                connected = connect(&config, &auth) => connected,
+                _ = grace_over.cancelled() => break,
+            };
+            let error = match connected {
+                Ok(socket) => {
+                    reconnect.on_connected();
+                    info!(uri = %redact_uri(&config.uri), "WebSocket connected");
+                    let link = Link {
+                        config: &config,
+                        responder: &responder,
+                        policy: &policy,
+                        stats: &stats,
+                        grace_over: &grace_over,
+                    };
+                    let ended = link
+                        .serve(socket, &mut rx, &mut outstanding, &mut stdin_open)
+                        .await;
+                    match ended {
+                        Ended::Done => break,
+                        Ended::Lost(e) => e,
+                    }
+                }
+                Err(e) => e,
+            };
+            if let Some(recorder) = stats.recorder() {
+                recorder.error(&error);
+            }
+            warn!(%error, "WebSocket unavailable, reconnecting with backoff");
+            if outstanding.lost(&responder).await.is_err() || !stdin_open {
+                break;
+            }
+            let (delay, _) = reconnect.next_delay();
+            let sleep = tokio::time::sleep(delay);
+            tokio::pin!(sleep);
+            // Only this loop takes from the queue, so it also makes room
+            // for `MCP_QUEUE_POLICY=drop-oldest` while disconnected.
+            let evicted = loop {
+                tokio::select! {
+                    _ = &mut sleep => break Ok(()),
+                    _ = grace_over.cancelled() => break Ok(()),
+                    evict = evict_oldest.requested() => {
+                        if evict && bridge::drop_oldest(&responder, &mut rx).await.is_err() {
+                            break Err(Closed);
+                        }
+                    }
+                }
+            };
+            if evicted.is_err() || grace_over.is_cancelled() {
+                break;
+            }
+        }
+        // Whatever is still queued will not be sent now.
+        let _ = bridge::abandon_queued(&responder, VecDeque::new(), &mut rx).await;
+        info!(top_methods = %stats.top_summary(bridge::TOP_METHODS_LOGGED), "bridge finished");
+    }
+
+    /// Opens the connection, sending the credentials and `MCP_HEADERS` in
+    /// the handshake and `MCP_QUERY_PARAMS` in the URL.
+    async fn connect(config: &Config, auth: &AuthProvider) -> Result<Socket, WsError> {
+        let mut request = remote_transport::with_query_params(&config.uri, &config.query_params)
+            .as_str()
+            .into_client_request()
+            .map_err(WsError::Handshake)?;
+        let headers = request.headers_mut();
+        headers.insert(
+            "Sec-WebSocket-Protocol",
+            HeaderValue::from_static(SUBPROTOCOL),
+        );
+        if let Some((name, value)) = auth.header().await.map_err(WsError::Credentials)? {
+            let name = HeaderName::from_bytes(name.as_bytes()).expect("checked at startup");
+            let value = HeaderValue::from_str(&value)
+                .map_err(|e| WsError::Handshake(tungstenite::http::Error::from(e).into()))?;
+            headers.insert(name, value);
+        }
+        for (name, value) in &config.headers {
+            let name = HeaderName::from_bytes(name.as_bytes()).expect("checked at startup");
+            let value = HeaderValue::from_str(value.expose()).expect("checked at startup");
+            headers.insert(name, value);
+        }
+        let connector = config
+            .uri
+            .starts_with("wss://")
+            .then(|| Connector::NativeTls(tls::native_connector(config)));
+        let limits = WebSocketConfig {
+            max_message_size: Some(config.max_message_bytes),
+            ..WebSocketConfig::default()
+        };
+        let handshake =
+            tokio_tungstenite::connect_async_tls_with_config(request, Some(limits), false, connector);
+        match tokio::time::timeout(config.timeout, handshake).await {
+            Ok(Ok((socket, _))) => Ok(socket),
+            Ok(Err(e)) => {
+                if let tungstenite::Error::Http(response) = &e {
+                    if response.status() == 401 {
+                        auth.invalidate();
+                    }
+                }
+                Err(WsError::Handshake(e))
+            }
+            Err(_) => Err(WsError::Timeout),
+        }
+    }
+
+    /// What one connection needs from the bridge.
+    struct Link<'a> {
+        config: &'a Config,
+        responder: &'a Responder,
+        policy: &'a MethodPolicy,
+        stats: &'a Stats,
+        grace_over: &'a CancellationToken,
+    }
+
+    impl Link<'_> {
+        /// Relays in both directions over `socket` until it drops, or stdin
+        /// has closed and nothing is left to answer. Pings go out every
+        /// `MCP_WS_PING_SECS`; a connection that has sent nothing, not even a
+        /// pong, for two intervals counts as dropped.
+        async fn serve(
+            &self,
+            socket: Socket,
+            rx: &mut mpsc::Receiver<RequestBody>,
+            outstanding: &mut Outstanding,
+            stdin_open: &mut bool,
+        ) -> Ended {
+            let config = self.config;
+            let (mut sink, mut frames) = socket.split();
+            let interval = config.ws_ping_interval;
+            let mut ping = tokio::time::interval(interval.max(Duration::from_millis(1)));
+            ping.reset();
+            let mut heard = Instant::now();
+            let lost = |e: tungstenite::Error| Ended::Lost(WsError::Lost(e.to_string()));
+            loop {
+                if !*stdin_open && outstanding.is_empty() {
+                    let _ = sink.close().await;
+                    return Ended::Done;
+                }
+                tokio::select! {
+                    biased;
+                    _ = self.grace_over.cancelled() => {
+                        let _ = outstanding.terminated(self.responder).await;
+                        let _ = sink.close().await;
+                        return Ended::Done;
+                    }
+                    frame = frames.next() => {
+                        heard = Instant::now();
+                        let text = match frame {
+                            Some(Ok(Message::Text(text))) => text,
+                            Some(Ok(Message::Binary(bytes))) => {
+                                warn!(bytes = bytes.len(), "ignoring binary WebSocket frame");
+                                continue;
+                            }
+                            Some(Ok(Message::Close(close))) => {
+                                let why = close.map_or_else(
+                                    || "closed by upstream".to_string(),
+                                    |c| format!("closed by upstream ({}: {})", c.code, c.reason),
+                                );
+                                return Ended::Lost(WsError::Lost(why));
+                            }
+                            // Pings are answered by the library.
+                            Some(Ok(_)) => continue,
+                            Some(Err(e)) => return lost(e),
+                            None => return Ended::Lost(WsError::Lost("stream ended".to_string())),
+                        };
+                        if config.log_bodies {
+                            debug!(body = %body_log::for_log(&text, config.log_body_limit), "upstream response line");
+                        }
+                        if let Some(recorder) = self.stats.recorder() {
+                            recorder.response(&text);
+                        }
+                        self.stats.note_upstream_response();
+                        outstanding.answered(&text);
+                        if self.responder.relay(text).await.is_err() {
+                            return Ended::Done;
+                        }
+                    }
+                    msg = rx.recv(), if *stdin_open => {
+                        let Some(msg) = msg else {
+                            *stdin_open = false;
+                            continue;
+                        };
+                        self.stats.note_activity();
+                        match self.policy.refuse(&msg.text(), self.responder).await {
+                            Ok(true) => continue,
+                            Ok(false) => {}
+                            Err(Closed) => return Ended::Done,
+                        }
+                        if config.log_bodies {
+                            debug!(body = %body_log::for_log(&msg.text(), config.log_body_limit), "upstream request body");
+                        }
+                        if let Some(recorder) = self.stats.recorder() {
+                            recorder.request("WebSocket", &config.uri, Some(&msg.text()));
+                        }
+                        outstanding.sent(&msg.text());
+                        self.stats.note_upstream_sent();
+                        // A text frame must be UTF-8, so a line that is not
+                        // (`MCP_STDIN_VALIDATION=bytes`) goes as it was read
+                        // in a binary one.
+                        let frame = match msg.into_string() {
+                            Ok(text) => Message::Text(text),
+                            Err(raw) => Message::Binary(raw.bytes().into()),
+                        };
+                        if let Err(e) = sink.send(frame).await {
+                            return lost(e);
+                        }
+                    }
+                    _ = ping.tick(), if !interval.is_zero() => {
+                        if heard.elapsed() > interval * 2 {
+                            return Ended::Lost(WsError::Lost("no pong from upstream".to_string()));
+                        }
+                        if let Err(e) = sink.send(Message::Ping(Vec::new())).await {
+                            return lost(e);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
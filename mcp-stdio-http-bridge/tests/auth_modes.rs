@@ -0,0 +1,60 @@
+mod common;
+
+use common::{BridgeProcess, MockServer};
+use std::time::Duration;
+
+/// The request a ping makes the bridge send with `vars`.
+fn first_request(vars: &[(&str, &str)]) -> common::RecordedRequest {
+    let server = MockServer::echo();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), vars);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#);
+    assert!(bridge.recv_line(Duration::from_secs(10)).is_some());
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+    server.requests().remove(0)
+}
+
+#[test]
+fn a_bearer_token_alone_keeps_bearer_auth() {
+    let request = first_request(&[("MCP_BRIDGE_BEARER_TOKEN", "t0k3n")]);
+    assert_eq!(request.header("Authorization"), Some("Bearer t0k3n"));
+}
+
+#[test]
+fn basic_auth_encodes_user_and_password() {
+    let request = first_request(&[
+        ("MCP_AUTH_MODE", "basic"),
+        ("MCP_BASIC_USER", "Aladdin"),
+        ("MCP_BASIC_PASSWORD", "open sesame"),
+    ]);
+    assert_eq!(
+        request.header("Authorization"),
+        Some("Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ==")
+    );
+}
+
+#[test]
+fn header_auth_sends_the_key_in_its_own_header() {
+    let request = first_request(&[
+        ("MCP_AUTH_MODE", "header"),
+        ("MCP_API_KEY", "k-123"),
+        ("MCP_BRIDGE_BEARER_TOKEN", "unused"),
+    ]);
+    assert_eq!(request.header("X-Api-Key"), Some("k-123"));
+    assert_eq!(request.header("Authorization"), None);
+    let request = first_request(&[
+        ("MCP_AUTH_MODE", "header"),
+        ("MCP_API_KEY_HEADER", "Api-Key"),
+        ("MCP_API_KEY", "k-123"),
+    ]);
+    assert_eq!(request.header("Api-Key"), Some("k-123"));
+}
+
+#[test]
+fn a_mode_without_its_settings_fails_startup() {
+    let server = MockServer::echo();
+    let bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_AUTH_MODE", "basic")]);
+    let (code, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(2));
+    assert!(stderr.contains("basic auth needs MCP_BASIC_USER"), "{}", stderr);
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use std::time::Duration;
+
+const BATCH: &str =
+    r#"[{"jsonrpc":"2.0","id":1,"method":"tools/list"},{"jsonrpc":"2.0","id":2,"method":"ping"}]"#;
+const ANSWER: &str =
+    r#"[{"jsonrpc":"2.0","id":1,"result":{"tools":[]}},{"jsonrpc":"2.0","id":2,"result":{}}]"#;
+
+fn recv(bridge: &BridgeProcess) -> String {
+    bridge
+        .recv_line(Duration::from_secs(10))
+        .expect("a line on stdout")
+}
+
+fn finish(mut bridge: BridgeProcess) -> String {
+    bridge.close_stdin();
+    let (code, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(0), "stderr: {}", stderr);
+    stderr
+}
+
+#[test]
+fn batch_response_is_split_in_order() {
+    let server = MockServer::start(|_| MockResponse::json(ANSWER));
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_SPLIT_BATCH_RESPONSES", "1")]);
+    bridge.send_line(BATCH);
+    assert_eq!(recv(&bridge), r#"{"jsonrpc":"2.0","id":1,"result":{"tools":[]}}"#);
+    assert_eq!(recv(&bridge), r#"{"jsonrpc":"2.0","id":2,"result":{}}"#);
+    finish(bridge);
+}
+
+#[test]
+fn batch_response_stays_whole_by_default() {
+    let server = MockServer::start(|_| MockResponse::json(ANSWER));
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[]);
+    bridge.send_line(BATCH);
+    assert_eq!(recv(&bridge), ANSWER);
+    finish(bridge);
+}
+
+#[test]
+fn sse_payloads_are_not_split() {
+    let server = MockServer::start(|_| {
+        MockResponse::status(200)
+            .with_header("Content-Type", "text/event-stream")
+            .with_chunk(Duration::ZERO, format!("data: {}\n\n", ANSWER))
+    });
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_SPLIT_BATCH_RESPONSES", "1")]);
+    bridge.send_line(BATCH);
+    assert_eq!(recv(&bridge), ANSWER);
+    finish(bridge);
+}
+
+#[test]
+fn malformed_batch_is_forwarded_whole_with_a_warning() {
+    let broken = r#"[{"jsonrpc":"2.0","id":1,"result":{}},"#;
+    let server = MockServer::start(move |_| MockResponse::json(broken));
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_SPLIT_BATCH_RESPONSES", "1")]);
+    bridge.send_line(BATCH);
+    assert_eq!(recv(&bridge), broken);
+    let stderr = finish(bridge);
+    assert!(stderr.contains("forwarding it whole"), "stderr: {}", stderr);
+}
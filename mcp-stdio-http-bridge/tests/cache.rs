@@ -0,0 +1,90 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+const CHANGED: &str = r#"{"jsonrpc":"2.0","method":"notifications/tools/list_changed"}"#;
+
+/// Numbers each `tools/list` answer; answers `tools/call` over SSE with a
+/// `list_changed` ahead of the result.
+fn server() -> MockServer {
+    let lists = Arc::new(AtomicU32::new(0));
+    MockServer::start(move |req| {
+        let msg: Value = serde_json::from_slice(&req.body).unwrap();
+        let id = &msg["id"];
+        if msg["method"] == "tools/call" {
+            let result = json!({"jsonrpc": "2.0", "id": id, "result": {"content": []}});
+            let mut response =
+                MockResponse::status(200).with_header("Content-Type", "text/event-stream");
+            response.body = format!("data: {}\n\ndata: {}\n\n", CHANGED, result).into_bytes();
+            return response;
+        }
+        let n = lists.fetch_add(1, Ordering::SeqCst) + 1;
+        MockResponse::json(json!({"jsonrpc": "2.0", "id": id, "result": {"n": n}}).to_string())
+    })
+}
+
+fn request(bridge: &mut BridgeProcess, id: &str, method: &str) -> Value {
+    let msg = json!({"jsonrpc": "2.0", "id": id, "method": method, "params": {"cursor": null}});
+    bridge.send_line(&msg.to_string());
+    loop {
+        let line = bridge.recv_line(Duration::from_secs(10)).expect("answer");
+        let answer: Value = serde_json::from_str(&line).unwrap();
+        if answer.get("id").is_some() {
+            return answer;
+        }
+    }
+}
+
+fn upstream_methods(server: &MockServer) -> Vec<String> {
+    server
+        .requests()
+        .iter()
+        .map(|r| serde_json::from_slice::<Value>(&r.body).unwrap()["method"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn a_repeated_list_is_answered_from_the_cache_under_its_own_id() {
+    let server = server();
+    let mut bridge =
+        BridgeProcess::spawn(&server.uri(), &[("MCP_CACHE", "tools/list=60s,tools/call=60s")]);
+    assert_eq!(request(&mut bridge, "a", "tools/list"), json!({"jsonrpc": "2.0", "id": "a", "result": {"n": 1}}));
+    assert_eq!(request(&mut bridge, "b", "tools/list"), json!({"jsonrpc": "2.0", "id": "b", "result": {"n": 1}}));
+    bridge.close_stdin();
+    let (code, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(0));
+    assert!(stderr.contains(r#"not caching "tools/call""#), "{}", stderr);
+    assert_eq!(upstream_methods(&server), ["tools/list"]);
+}
+
+#[test]
+fn list_changed_sends_the_next_list_upstream() {
+    let server = server();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_CACHE", "tools/list=60s")]);
+    assert_eq!(request(&mut bridge, "1", "tools/list")["result"]["n"], 1);
+    assert_eq!(request(&mut bridge, "2", "tools/call")["id"], "2");
+    assert_eq!(request(&mut bridge, "3", "tools/call")["id"], "3");
+    assert_eq!(request(&mut bridge, "4", "tools/list")["result"]["n"], 2);
+    assert_eq!(request(&mut bridge, "5", "tools/list")["result"]["n"], 2);
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+    assert_eq!(
+        upstream_methods(&server),
+        ["tools/list", "tools/call", "tools/call", "tools/list"]
+    );
+}
+
+#[test]
+fn entries_expire_after_their_ttl() {
+    let server = server();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_CACHE", "tools/list=300ms")]);
+    assert_eq!(request(&mut bridge, "1", "tools/list")["result"]["n"], 1);
+    std::thread::sleep(Duration::from_millis(500));
+    assert_eq!(request(&mut bridge, "2", "tools/list")["result"]["n"], 2);
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
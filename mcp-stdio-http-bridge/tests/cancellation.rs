@@ -0,0 +1,65 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use serde_json::Value;
+use std::time::Duration;
+
+const SLOW_CALL: &str = r#"{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{"name":"slow"}}"#;
+
+fn slow_server() -> MockServer {
+    MockServer::start(|req| {
+        let msg: Value = serde_json::from_slice(&req.body).unwrap();
+        match (msg["method"].as_str(), msg.get("id")) {
+            (Some("tools/call"), Some(id)) => MockResponse::json(
+                serde_json::json!({"jsonrpc": "2.0", "id": id, "result": {}}).to_string(),
+            )
+            .with_delay(Duration::from_secs(30)),
+            (_, Some(id)) => MockResponse::json(
+                serde_json::json!({"jsonrpc": "2.0", "id": id, "result": {}}).to_string(),
+            ),
+            (_, None) => MockResponse::status(202),
+        }
+    })
+}
+
+#[test]
+fn cancelled_request_is_aborted_and_the_notification_forwarded() {
+    let server = slow_server();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[]);
+    bridge.send_line(SLOW_CALL);
+    bridge.send_line(
+        r#"{"jsonrpc":"2.0","method":"notifications/cancelled","params":{"requestId":1,"reason":"stop"}}"#,
+    );
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":2,"method":"ping"}"#);
+    let line = bridge.recv_line(Duration::from_secs(10)).expect("ping answer");
+    assert_eq!(serde_json::from_str::<Value>(&line).unwrap()["id"], 2);
+    // The bridge exits without waiting out the slow call, and writes
+    // nothing for it: a cancelled request gets no answer.
+    bridge.close_stdin();
+    let (code, lines, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(0), "stderr: {}", stderr);
+    assert!(lines.is_empty(), "{:?}", lines);
+    assert!(stderr.contains("aborting its upstream call"), "stderr: {}", stderr);
+    let cancels = server
+        .requests()
+        .iter()
+        .filter(|r| r.body_str().contains("notifications/cancelled"))
+        .count();
+    assert_eq!(cancels, 1);
+}
+
+#[test]
+fn cancelling_an_unknown_id_only_forwards_the_notification() {
+    let server = slow_server();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[]);
+    bridge.send_line(
+        r#"{"jsonrpc":"2.0","method":"notifications/cancelled","params":{"requestId":"gone"}}"#,
+    );
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":2,"method":"ping"}"#);
+    assert!(bridge.recv_line(Duration::from_secs(10)).is_some());
+    bridge.close_stdin();
+    let (code, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(0), "stderr: {}", stderr);
+    assert!(stderr.contains("not in flight"), "stderr: {}", stderr);
+    assert_eq!(server.requests().len(), 2);
+}
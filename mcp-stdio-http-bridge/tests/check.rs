@@ -0,0 +1,97 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// An MCP server with two pages of tools.
+fn server() -> MockServer {
+    MockServer::start(|req| {
+        let msg: Value = serde_json::from_slice(&req.body).unwrap();
+        let id = &msg["id"];
+        let result = match msg["method"].as_str().unwrap() {
+            "initialize" => json!({
+                "protocolVersion": "2025-03-26",
+                "capabilities": {"tools": {"listChanged": true}},
+                "serverInfo": {"name": "demo", "version": "1.2"},
+            }),
+            "tools/list" if msg["params"]["cursor"] == "2" => json!({
+                "tools": [{"name": "fetch", "description": "Fetches a page\nwith details"}],
+            }),
+            "tools/list" => json!({
+                "tools": [{"name": "search", "description": "Finds things"}],
+                "nextCursor": "2",
+            }),
+            _ => return MockResponse::status(202),
+        };
+        MockResponse::json(json!({"jsonrpc": "2.0", "id": id, "result": result}).to_string())
+            .with_header("Mcp-Session-Id", "s-1")
+    })
+}
+
+fn check(uri: &str, envs: &[(&str, &str)]) -> (Option<i32>, Vec<String>, String) {
+    let bridge = BridgeProcess::spawn_with_args(uri, envs, &["--check"]);
+    bridge.wait_with_stderr(Duration::from_secs(20))
+}
+
+#[test]
+fn a_healthy_upstream_is_reported_on_stdout() {
+    let server = server();
+    let (code, lines, stderr) = check(&server.uri(), &[("MCP_BRIDGE_BEARER_TOKEN", "t0k")]);
+    assert_eq!(code, Some(0), "{}", stderr);
+    assert_eq!(
+        lines,
+        [
+            "server: demo 1.2",
+            "protocol version: 2025-03-26",
+            r#"capabilities: {"tools":{"listChanged":true}}"#,
+            "tools (2):",
+            "  search - Finds things",
+            "  fetch - Fetches a page",
+        ]
+    );
+    let requests = server.requests();
+    let methods: Vec<Value> = requests
+        .iter()
+        .map(|r| serde_json::from_slice::<Value>(&r.body).unwrap()["method"].clone())
+        .collect();
+    assert_eq!(methods, ["initialize", "notifications/initialized", "tools/list", "tools/list"]);
+    let last = requests.last().unwrap();
+    assert_eq!(last.header("authorization"), Some("Bearer t0k"));
+    assert_eq!(last.header("mcp-session-id"), Some("s-1"));
+    assert_eq!(last.header("mcp-protocol-version"), Some("2025-03-26"));
+}
+
+#[test]
+fn refused_credentials_exit_with_the_auth_status() {
+    let server = MockServer::start(|_| MockResponse::status(401));
+    let (code, lines, stderr) = check(&server.uri(), &[]);
+    assert_eq!(code, Some(13), "{}", stderr);
+    assert!(lines.is_empty());
+    assert!(stderr.contains("initialize failed (auth)"), "{}", stderr);
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[test]
+fn an_error_result_is_a_protocol_failure() {
+    let server = MockServer::start(|_| {
+        MockResponse::json(r#"{"jsonrpc":"2.0","id":0,"error":{"code":-32602,"message":"unsupported protocol version"}}"#)
+    });
+    let (code, _, stderr) = check(&server.uri(), &[]);
+    assert_eq!(code, Some(15), "{}", stderr);
+    assert!(stderr.contains("unsupported protocol version"), "{}", stderr);
+}
+
+#[test]
+fn unreachable_upstreams_exit_with_the_network_statuses() {
+    let (code, _, stderr) = check("http://127.0.0.1:9/mcp", &[]);
+    assert_eq!(code, Some(11), "{}", stderr);
+    let (code, _, stderr) = check("http://upstream.invalid/mcp", &[]);
+    assert_eq!(code, Some(10), "{}", stderr);
+}
+
+#[test]
+fn echo_mode_cannot_be_checked() {
+    let (code, _, stderr) = check("http://127.0.0.1:9/mcp", &[("MCP_MODE", "echo")]);
+    assert_eq!(code, Some(2), "{}", stderr);
+}
@@ -0,0 +1,122 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use serde_json::Value;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+fn list(id: u32) -> String {
+    format!(r#"{{"jsonrpc":"2.0","id":{},"method":"tools/list"}}"#, id)
+}
+
+fn recv(bridge: &BridgeProcess) -> Value {
+    let line = bridge.recv_line(Duration::from_secs(10)).expect("stdout line");
+    serde_json::from_str(&line).unwrap()
+}
+
+#[test]
+fn open_breaker_answers_without_sending() {
+    let server = MockServer::failing_first(usize::MAX);
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[("MCP_CB_FAILURES", "2"), ("MCP_CB_RESET_MS", "60000")],
+    );
+    bridge.send_line(&list(1));
+    let first = recv(&bridge);
+    assert_eq!(first["id"], 1);
+    let message = first["error"]["message"].as_str().unwrap();
+    assert!(message.ends_with("(circuit breaker open)"), "{}", message);
+    assert_eq!(server.requests().len(), 2);
+
+    let started = Instant::now();
+    for id in 2..=4 {
+        bridge.send_line(&list(id));
+        let answer = recv(&bridge);
+        assert_eq!(answer["id"], id);
+        assert_eq!(answer["error"]["code"], -32603);
+        assert_eq!(
+            answer["error"]["message"],
+            "upstream unavailable (circuit breaker open)"
+        );
+    }
+    assert!(started.elapsed() < Duration::from_secs(2), "{:?}", started.elapsed());
+    assert_eq!(server.requests().len(), 2);
+
+    bridge.close_stdin();
+    let (_, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert!(stderr.contains("circuit breaker open"), "{}", stderr);
+    let report = BridgeProcess::shutdown_report(&stderr).expect("shutdown report");
+    assert_eq!(report["circuit"], "open");
+    assert_eq!(report["stats"]["circuit_opened"], 1);
+}
+
+#[test]
+fn a_successful_probe_closes_the_breaker() {
+    let server = MockServer::failing_first(2);
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[("MCP_CB_FAILURES", "2"), ("MCP_CB_RESET_MS", "300")],
+    );
+    bridge.send_line(&list(1));
+    assert!(recv(&bridge)["error"].is_object());
+    std::thread::sleep(Duration::from_millis(400));
+    for id in 2..=3 {
+        bridge.send_line(&list(id));
+        let answer = recv(&bridge);
+        assert_eq!(answer["id"], id);
+        assert!(answer["result"].is_object(), "{}", answer);
+    }
+    assert_eq!(server.requests().len(), 4);
+
+    bridge.close_stdin();
+    let (_, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert!(stderr.contains("circuit breaker half-open"), "{}", stderr);
+    assert!(stderr.contains("circuit breaker closed"), "{}", stderr);
+    let report = BridgeProcess::shutdown_report(&stderr).expect("shutdown report");
+    assert_eq!(report["circuit"], "closed");
+}
+
+#[test]
+fn no_breaker_by_default() {
+    let server = MockServer::failing_first(0);
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[]);
+    bridge.send_line(&list(1));
+    assert!(recv(&bridge)["result"].is_object());
+    bridge.close_stdin();
+    let (_, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    let report = BridgeProcess::shutdown_report(&stderr).expect("shutdown report");
+    assert!(report["circuit"].is_null());
+}
+
+#[test]
+fn the_probe_is_not_hedged() {
+    let served = AtomicUsize::new(0);
+    let server = MockServer::start(move |req| {
+        if served.fetch_add(1, Ordering::SeqCst) == 0 {
+            return MockResponse::status(503);
+        }
+        let msg: Value = serde_json::from_slice(&req.body).unwrap();
+        MockResponse::json(format!(r#"{{"jsonrpc":"2.0","id":{},"result":{{}}}}"#, msg["id"]))
+            .with_delay(Duration::from_millis(300))
+    });
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[
+            ("MCP_CB_FAILURES", "1"),
+            ("MCP_CB_RESET_MS", "300"),
+            ("MCP_HEDGE_METHODS", "tools/list"),
+            ("MCP_HEDGE_DELAY_MS", "50"),
+        ],
+    );
+    bridge.send_line(&list(1));
+    assert!(recv(&bridge)["error"].is_object());
+    std::thread::sleep(Duration::from_millis(400));
+    bridge.send_line(&list(2));
+    assert!(recv(&bridge)["result"].is_object());
+    assert_eq!(server.requests().len(), 2);
+
+    bridge.close_stdin();
+    let (_, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    let report = BridgeProcess::shutdown_report(&stderr).expect("shutdown report");
+    assert_eq!(report["circuit"], "closed");
+}
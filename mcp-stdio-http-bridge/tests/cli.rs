@@ -0,0 +1,59 @@
+mod common;
+
+use common::{BridgeProcess, MockServer};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+fn run(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_mcp-stdio-http-bridge"))
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn help_goes_to_stderr_and_leaves_stdout_empty() {
+    let out = run(&["--help"]);
+    assert_eq!(out.status.code(), Some(0));
+    assert!(out.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("--uri <STRING>"), "{}", stderr);
+    assert!(stderr.contains("--attempt-timeout-ms"), "{}", stderr);
+}
+
+#[test]
+fn unknown_flag_fails_with_usage_on_stderr() {
+    let out = run(&["--no-such-flag"]);
+    assert_eq!(out.status.code(), Some(2));
+    assert!(out.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("'--no-such-flag'"), "{}", stderr);
+    assert!(stderr.contains("--help"), "{}", stderr);
+}
+
+#[test]
+fn flags_take_precedence_over_env() {
+    let server = MockServer::echo();
+    // The env points nowhere; only the flag reaches the server.
+    let mut bridge = BridgeProcess::spawn_with_args(
+        "http://127.0.0.1:9/mcp",
+        &[],
+        &[
+            "--uri",
+            &server.uri(),
+            "--timeout-ms",
+            "5000",
+            "--name",
+            "cli-test",
+        ],
+    );
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#);
+    assert!(bridge.recv_line(Duration::from_secs(10)).is_some());
+    bridge.close_stdin();
+    let (code, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(0));
+    assert_eq!(server.requests().len(), 1);
+    assert!(stderr.contains("cli-test"), "{}", stderr);
+    assert!(stderr.contains("flag --attempt-timeout-ms"), "{}", stderr);
+}
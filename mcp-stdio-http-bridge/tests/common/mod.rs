@@ -0,0 +1,400 @@
+//! Std-only helpers for black-box tests: a tiny HTTP/1.1 mock upstream and a
+//! handle around the spawned bridge binary.
+#![allow(dead_code)]
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+#[derive(Clone, Debug)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl RecordedRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn body_str(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MockResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    pub delay: Duration,
+    /// Written after `body`, each after its pause, for streamed responses.
+    pub chunks: Vec<(Duration, Vec<u8>)>,
+}
+
+impl MockResponse {
+    pub fn json(body: impl Into<String>) -> Self {
+        Self {
+            status: 200,
+            headers: vec![("Content-Type".into(), "application/json".into())],
+            body: body.into().into_bytes(),
+            delay: Duration::ZERO,
+            chunks: Vec::new(),
+        }
+    }
+
+    pub fn status(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+            delay: Duration::ZERO,
+            chunks: Vec::new(),
+        }
+    }
+
+    /// An error page, as a proxy in front of the upstream would send it.
+    pub fn html(status: u16, page: &str) -> Self {
+        let mut response = Self::status(status).with_header("Content-Type", "text/html");
+        response.body = page.as_bytes().to_vec();
+        response
+    }
+
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Sends `bytes` after `pause`, once everything before has been written.
+    pub fn with_chunk(mut self, pause: Duration, bytes: impl Into<Vec<u8>>) -> Self {
+        self.chunks.push((pause, bytes.into()));
+        self
+    }
+}
+
+type Handler = dyn Fn(&RecordedRequest) -> MockResponse + Send + Sync;
+
+pub struct MockServer {
+    pub addr: SocketAddr,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+}
+
+impl MockServer {
+    pub fn start<F>(handler: F) -> Self
+    where
+        F: Fn(&RecordedRequest) -> MockResponse + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind mock server");
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let handler: Arc<Handler> = Arc::new(handler);
+        let recorded = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else { break };
+                let handler = handler.clone();
+                let recorded = recorded.clone();
+                thread::spawn(move || serve_connection(stream, &*handler, &recorded));
+            }
+        });
+        Self { addr, requests }
+    }
+
+    /// Echoes each JSON-RPC request back as `{"id": <id>, "result": <params>}`.
+    pub fn echo() -> Self {
+        Self::start(|req| {
+            let msg: serde_json::Value = match serde_json::from_slice(&req.body) {
+                Ok(v) => v,
+                Err(_) => return MockResponse::status(400),
+            };
+            match msg.get("id") {
+                Some(id) => MockResponse::json(
+                    serde_json::json!({"jsonrpc": "2.0", "id": id, "result": msg.get("params")})
+                        .to_string(),
+                ),
+                None => MockResponse::status(202),
+            }
+        })
+    }
+
+    /// Fails the first `failures` requests with a 503, then answers each
+    /// with an empty result.
+    pub fn failing_first(failures: usize) -> Self {
+        Self::failing_first_with(failures, || MockResponse::status(503))
+    }
+
+    /// Answers the first `failures` requests with `failure()`, then each
+    /// with an empty result.
+    pub fn failing_first_with<F>(failures: usize, failure: F) -> Self
+    where
+        F: Fn() -> MockResponse + Send + Sync + 'static,
+    {
+        let served = AtomicUsize::new(0);
+        Self::start(move |req| {
+            if served.fetch_add(1, Ordering::SeqCst) < failures {
+                return failure();
+            }
+            let msg: serde_json::Value = serde_json::from_slice(&req.body).unwrap();
+            MockResponse::json(
+                serde_json::json!({"jsonrpc": "2.0", "id": msg["id"], "result": {}}).to_string(),
+            )
+        })
+    }
+
+    pub fn uri(&self) -> String {
+        format!("http://{}/mcp", self.addr)
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+fn serve_connection(stream: TcpStream, handler: &Handler, recorded: &Mutex<Vec<RecordedRequest>>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut stream = stream;
+    loop {
+        let mut request_line = String::new();
+        if reader.read_line(&mut request_line).unwrap_or(0) == 0 {
+            return;
+        }
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap_or_default().to_string();
+        let path = parts.next().unwrap_or_default().to_string();
+        let mut headers = Vec::new();
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                return;
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((k, v)) = line.split_once(':') {
+                headers.push((k.trim().to_string(), v.trim().to_string()));
+            }
+        }
+        let len = headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case("content-length"))
+            .and_then(|(_, v)| v.parse::<usize>().ok())
+            .unwrap_or(0);
+        let mut body = vec![0u8; len];
+        if reader.read_exact(&mut body).is_err() {
+            return;
+        }
+        let req = RecordedRequest {
+            method,
+            path,
+            headers,
+            body,
+        };
+        recorded.lock().unwrap().push(req.clone());
+        let resp = handler(&req);
+        if !resp.delay.is_zero() {
+            thread::sleep(resp.delay);
+        }
+        let mut out = format!("HTTP/1.1 {} Mock\r\n", resp.status);
+        for (k, v) in &resp.headers {
+            out.push_str(&format!("{}: {}\r\n", k, v));
+        }
+        if !resp
+            .headers
+            .iter()
+            .any(|(k, _)| k.eq_ignore_ascii_case("content-length"))
+        {
+            let len = resp.body.len() + resp.chunks.iter().map(|(_, c)| c.len()).sum::<usize>();
+            out.push_str(&format!("Content-Length: {}\r\n", len));
+        }
+        out.push_str("\r\n");
+        if stream.write_all(out.as_bytes()).is_err() || stream.write_all(&resp.body).is_err() {
+            return;
+        }
+        let _ = stream.flush();
+        for (pause, chunk) in &resp.chunks {
+            thread::sleep(*pause);
+            if stream.write_all(chunk).is_err() || stream.flush().is_err() {
+                return;
+            }
+        }
+        if resp
+            .headers
+            .iter()
+            .any(|(k, v)| k.eq_ignore_ascii_case("connection") && v.eq_ignore_ascii_case("close"))
+        {
+            return;
+        }
+    }
+}
+
+pub struct BridgeProcess {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    stdout_lines: mpsc::Receiver<String>,
+    stderr: Arc<Mutex<String>>,
+}
+
+impl BridgeProcess {
+    pub fn spawn(uri: &str, envs: &[(&str, &str)]) -> Self {
+        Self::spawn_with_args(uri, envs, &[])
+    }
+
+    pub fn spawn_with_args(uri: &str, envs: &[(&str, &str)], args: &[&str]) -> Self {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_mcp-stdio-http-bridge"));
+        cmd.args(args)
+            .env("MCP_BRIDGE_URI", uri)
+            .env("RUST_LOG", "mcp_stdio_http_bridge=debug")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        for (k, v) in envs {
+            cmd.env(k, v);
+        }
+        let mut child = cmd.spawn().expect("spawn bridge binary");
+        let stdin = child.stdin.take();
+        let stdout = child.stdout.take().unwrap();
+        let stderr_pipe = child.stderr.take().unwrap();
+        let (tx, stdout_lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        let stderr = Arc::new(Mutex::new(String::new()));
+        let captured = stderr.clone();
+        thread::spawn(move || {
+            for line in BufReader::new(stderr_pipe).lines() {
+                let Ok(line) = line else { break };
+                let mut buf = captured.lock().unwrap();
+                buf.push_str(&line);
+                buf.push('\n');
+            }
+        });
+        Self {
+            child,
+            stdin,
+            stdout_lines,
+            stderr,
+        }
+    }
+
+    pub fn send_line(&mut self, line: &str) {
+        let stdin = self.stdin.as_mut().expect("stdin already closed");
+        stdin.write_all(line.as_bytes()).unwrap();
+        stdin.write_all(b"\n").unwrap();
+        stdin.flush().unwrap();
+    }
+
+    /// Writes `bytes` plus a newline, for input that is not valid UTF-8.
+    pub fn send_bytes(&mut self, bytes: &[u8]) {
+        let stdin = self.stdin.as_mut().expect("stdin already closed");
+        stdin.write_all(bytes).unwrap();
+        stdin.write_all(b"\n").unwrap();
+        stdin.flush().unwrap();
+    }
+
+    pub fn close_stdin(&mut self) {
+        self.stdin.take();
+    }
+
+    pub fn recv_line(&self, timeout: Duration) -> Option<String> {
+        self.stdout_lines.recv_timeout(timeout).ok()
+    }
+
+    #[cfg(unix)]
+    pub fn signal(&self, signal: &str) {
+        let status = Command::new("kill")
+            .arg(format!("-{}", signal))
+            .arg(self.child.id().to_string())
+            .status()
+            .expect("run kill");
+        assert!(status.success());
+    }
+
+    /// Waits for the process to exit and returns its exit code plus every
+    /// stdout line not yet consumed through `recv_line`.
+    pub fn wait(self, timeout: Duration) -> (Option<i32>, Vec<String>) {
+        let (code, lines, _) = self.wait_with_stderr(timeout);
+        (code, lines)
+    }
+
+    /// Like `wait`, also returning everything written to stderr.
+    pub fn wait_with_stderr(mut self, timeout: Duration) -> (Option<i32>, Vec<String>, String) {
+        let deadline = std::time::Instant::now() + timeout;
+        let status = loop {
+            if let Some(status) = self.child.try_wait().unwrap() {
+                break status;
+            }
+            if std::time::Instant::now() >= deadline {
+                let _ = self.child.kill();
+                panic!("bridge did not exit in time; stderr:\n{}", self.stderr());
+            }
+            thread::sleep(Duration::from_millis(20));
+        };
+        let mut lines = Vec::new();
+        while let Ok(line) = self.stdout_lines.recv_timeout(Duration::from_millis(500)) {
+            lines.push(line);
+        }
+        let stderr = self.stderr();
+        (status.code(), lines, stderr)
+    }
+
+    /// The JSON shutdown report line from captured stderr, if any.
+    pub fn shutdown_report(stderr: &str) -> Option<serde_json::Value> {
+        stderr
+            .lines()
+            .filter_map(|l| serde_json::from_str::<serde_json::Value>(l).ok())
+            .find(|v| v["event"] == "bridge_shutdown")
+    }
+
+    pub fn stderr(&self) -> String {
+        self.stderr.lock().unwrap().clone()
+    }
+}
+
+/// Sends each line to a bridge and waits for its answer, then closes stdin
+/// and expects a clean exit.
+/// A URI nothing listens on: the port was free a moment ago, so every
+/// connection to it is refused.
+pub fn dead_uri() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    drop(listener);
+    format!("http://{}/mcp", addr)
+}
+
+pub fn run_lines<S: AsRef<str>>(uri: &str, envs: &[(&str, &str)], lines: &[S]) {
+    let mut bridge = BridgeProcess::spawn(uri, envs);
+    for line in lines {
+        bridge.send_line(line.as_ref());
+        assert!(bridge.recv_line(Duration::from_secs(10)).is_some());
+    }
+    bridge.close_stdin();
+    let (code, _) = bridge.wait(Duration::from_secs(15));
+    assert_eq!(code, Some(0));
+}
+
+impl Drop for BridgeProcess {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+    }
+}
@@ -0,0 +1,142 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use flate2::write::GzEncoder;
+use mcp_stdio_http_bridge::integrity::sha256_hex;
+use serde_json::{json, Value};
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+const REQUEST: &str = r#"{"jsonrpc":"2.0","id":1,"method":"tools/call"}"#;
+const BODY: &str = r#"{"jsonrpc":"2.0","id":1,"result":{"text":"compressed"}}"#;
+
+fn gzip(bytes: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(bytes).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn brotli(bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut encoder = brotli::CompressorWriter::new(&mut out, 4096, 5, 22);
+    encoder.write_all(bytes).unwrap();
+    drop(encoder);
+    out
+}
+
+fn compressed_json(encoding: &str, body: Vec<u8>) -> MockResponse {
+    MockResponse {
+        body,
+        ..MockResponse::json("")
+    }
+    .with_header("Content-Encoding", encoding)
+}
+
+fn recv(bridge: &BridgeProcess) -> Value {
+    serde_json::from_str(&bridge.recv_line(Duration::from_secs(10)).unwrap()).unwrap()
+}
+
+#[test]
+fn gzip_and_brotli_bodies_are_decoded() {
+    let server = MockServer::start(|req| {
+        let accepted = req.header("Accept-Encoding").unwrap_or_default();
+        assert!(accepted.contains("gzip") && accepted.contains("br"), "{}", accepted);
+        let msg: Value = serde_json::from_slice(&req.body).unwrap();
+        if msg["id"] == 1 {
+            compressed_json("gzip", gzip(BODY.as_bytes()))
+        } else {
+            compressed_json("br", brotli(BODY.replace(":1,", ":2,").as_bytes()))
+        }
+    });
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[]);
+    bridge.send_line(REQUEST);
+    assert_eq!(bridge.recv_line(Duration::from_secs(10)).unwrap(), BODY);
+    bridge.send_line(&REQUEST.replace(":1,", ":2,"));
+    assert_eq!(recv(&bridge)["result"]["text"], "compressed");
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
+
+#[test]
+fn compressed_event_streams_are_relayed_as_they_arrive() {
+    let progress = json!({
+        "jsonrpc": "2.0",
+        "method": "notifications/progress",
+        "params": {"progressToken": "t", "progress": 1}
+    });
+    let result = json!({"jsonrpc": "2.0", "id": 1, "result": {}});
+    // A sync flush after the first event makes it decodable on its own.
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder
+        .write_all(format!("data: {}\n\n", progress).as_bytes())
+        .unwrap();
+    encoder.flush().unwrap();
+    let head = std::mem::take(encoder.get_mut());
+    encoder
+        .write_all(format!("data: {}\n\n", result).as_bytes())
+        .unwrap();
+    let tail = encoder.finish().unwrap();
+    let server = MockServer::start(move |_| {
+        MockResponse::status(200)
+            .with_header("Content-Type", "text/event-stream")
+            .with_header("Content-Encoding", "gzip")
+            .with_chunk(Duration::ZERO, head.clone())
+            .with_chunk(Duration::from_secs(3), tail.clone())
+    });
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[]);
+    bridge.send_line(REQUEST);
+    let started = Instant::now();
+    assert_eq!(recv(&bridge)["method"], "notifications/progress");
+    assert!(
+        started.elapsed() < Duration::from_secs(2),
+        "{:?}",
+        started.elapsed()
+    );
+    assert_eq!(recv(&bridge)["result"], json!({}));
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
+
+#[test]
+fn decoded_bodies_are_still_checked_for_utf8() {
+    let server = MockServer::start(|_| {
+        compressed_json(
+            "gzip",
+            gzip(b"{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":{\"s\":\"latin1 \xe9\"}}"),
+        )
+    });
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[]);
+    bridge.send_line(REQUEST);
+    let line = recv(&bridge);
+    assert_eq!(line["error"]["code"], -32603);
+    assert!(line["error"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("invalid UTF-8"));
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
+
+#[test]
+fn integrity_digest_covers_the_decoded_body() {
+    let server = MockServer::start(|_| {
+        compressed_json("gzip", gzip(BODY.as_bytes()))
+            .with_header("X-Content-SHA256", &sha256_hex(BODY.as_bytes()))
+    });
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_INTEGRITY_HEADER", "1")]);
+    bridge.send_line(REQUEST);
+    assert_eq!(bridge.recv_line(Duration::from_secs(10)).unwrap(), BODY);
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
+
+#[test]
+fn compression_can_be_turned_off() {
+    let server = MockServer::start(|_| MockResponse::json(BODY));
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_COMPRESSION", "0")]);
+    bridge.send_line(REQUEST);
+    assert_eq!(bridge.recv_line(Duration::from_secs(10)).unwrap(), BODY);
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+    assert_eq!(server.requests()[0].header("Accept-Encoding"), None);
+}
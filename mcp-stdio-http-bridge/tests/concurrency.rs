@@ -0,0 +1,97 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Echoes requests, holding `tools/call` for `slow` and counting how many
+/// requests it is answering at once.
+fn server(slow: Duration, peak: Arc<AtomicUsize>) -> MockServer {
+    let active = AtomicUsize::new(0);
+    MockServer::start(move |req| {
+        let msg: Value = serde_json::from_slice(&req.body).unwrap();
+        let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+        peak.fetch_max(now, Ordering::SeqCst);
+        if msg["method"] == "tools/call" {
+            std::thread::sleep(slow);
+        } else {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        active.fetch_sub(1, Ordering::SeqCst);
+        MockResponse::json(json!({"jsonrpc": "2.0", "id": msg["id"], "result": {}}).to_string())
+    })
+}
+
+fn recv_id(bridge: &BridgeProcess) -> Value {
+    let line = bridge
+        .recv_line(Duration::from_secs(10))
+        .expect("stdout line");
+    serde_json::from_str::<Value>(&line).unwrap()["id"].clone()
+}
+
+#[test]
+fn slow_call_does_not_hold_up_later_requests() {
+    let peak = Arc::new(AtomicUsize::new(0));
+    let server = server(Duration::from_secs(2), peak.clone());
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[]);
+    let started = Instant::now();
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":"slow","method":"tools/call"}"#);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":"fast","method":"tools/list"}"#);
+    assert_eq!(recv_id(&bridge), "fast");
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert_eq!(recv_id(&bridge), "slow");
+    assert_eq!(peak.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn in_flight_requests_are_capped() {
+    let peak = Arc::new(AtomicUsize::new(0));
+    let server = server(Duration::from_millis(300), peak.clone());
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_MAX_CONCURRENCY", "2")]);
+    for id in 0..6 {
+        bridge.send_line(&format!(
+            r#"{{"jsonrpc":"2.0","id":{},"method":"tools/call"}}"#,
+            id
+        ));
+    }
+    let mut ids: Vec<u64> = (0..6).map(|_| recv_id(&bridge).as_u64().unwrap()).collect();
+    ids.sort();
+    assert_eq!(ids, vec![0, 1, 2, 3, 4, 5]);
+    assert_eq!(peak.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn concurrency_of_one_keeps_responses_in_order() {
+    let peak = Arc::new(AtomicUsize::new(0));
+    let server = server(Duration::from_millis(300), peak.clone());
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_MAX_CONCURRENCY", "1")]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":"slow","method":"tools/call"}"#);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":"fast","method":"tools/list"}"#);
+    assert_eq!(recv_id(&bridge), "slow");
+    assert_eq!(recv_id(&bridge), "fast");
+    assert_eq!(peak.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn shutdown_waits_for_every_in_flight_request() {
+    let peak = Arc::new(AtomicUsize::new(0));
+    let server = server(Duration::from_millis(500), peak);
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[]);
+    for id in 0..3 {
+        bridge.send_line(&format!(
+            r#"{{"jsonrpc":"2.0","id":{},"method":"tools/call"}}"#,
+            id
+        ));
+    }
+    // Close stdin once all three are in flight upstream.
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while server.requests().len() < 3 && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    bridge.close_stdin();
+    let (code, lines) = bridge.wait(Duration::from_secs(10));
+    assert_eq!(code, Some(0));
+    assert_eq!(lines.len(), 3, "{:?}", lines);
+}
@@ -0,0 +1,51 @@
+mod common;
+
+use common::{BridgeProcess, MockServer};
+use std::path::PathBuf;
+use std::time::Duration;
+
+fn write_config(name: &str, text: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("bridge-{}-{}.toml", name, std::process::id()));
+    std::fs::write(&path, text).unwrap();
+    path
+}
+
+#[test]
+fn invalid_file_fails_startup_naming_the_key() {
+    let server = MockServer::echo();
+    let path = write_config("invalid", "max_queue = \"lots\"\n");
+    let bridge =
+        BridgeProcess::spawn_with_args(&server.uri(), &[], &["--config", path.to_str().unwrap()]);
+    let (code, lines, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(code, Some(2));
+    assert!(lines.is_empty());
+    assert!(
+        stderr.contains("key `max_queue` must be a non-negative integer"),
+        "{}",
+        stderr
+    );
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn env_overrides_the_file() {
+    let server = MockServer::echo();
+    // The file's uri points nowhere; URI from the environment wins.
+    let path = write_config(
+        "override",
+        "uri = \"http://127.0.0.1:9/mcp\"\nmcp_name = \"from-file\"\n",
+    );
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[("MCP_CONFIG_FILE", path.to_str().unwrap())],
+    );
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#);
+    assert!(bridge.recv_line(Duration::from_secs(10)).is_some());
+    bridge.close_stdin();
+    let (code, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(code, Some(0));
+    assert_eq!(server.requests().len(), 1);
+    assert!(stderr.contains("from-file"), "{}", stderr);
+}
@@ -0,0 +1,44 @@
+use serde_json::Value;
+use std::process::Command;
+
+#[test]
+fn config_schema_prints_every_option() {
+    let out = Command::new(env!("CARGO_BIN_EXE_mcp-stdio-http-bridge"))
+        .arg("config-schema")
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    let schema: Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(schema["schema_version"], 1);
+    let options = schema["options"].as_array().unwrap();
+    assert_eq!(options.len(), mcp_stdio_http_bridge::config::OPTIONS.len());
+    for option in options {
+        for key in [
+            "name",
+            "env",
+            "cli_flag",
+            "type",
+            "default",
+            "constraints",
+            "since",
+        ] {
+            assert!(
+                option.get(key).is_some(),
+                "{} lacks {}",
+                option["name"],
+                key
+            );
+        }
+    }
+    let timeout = options.iter().find(|o| o["name"] == "timeout").unwrap();
+    assert_eq!(timeout["env"], "MCP_ATTEMPT_TIMEOUT_MS");
+    assert_eq!(timeout["env_aliases"], serde_json::json!(["MCP_TIMEOUT_MS"]));
+    assert_eq!(timeout["cli_flag"], "--attempt-timeout-ms");
+    assert_eq!(timeout["default"], 60_000);
+    assert_eq!(timeout["quirk"], false);
+    let ack = options
+        .iter()
+        .find(|o| o["env"] == "MCP_QUIRK_ACK_INITIALIZED")
+        .unwrap();
+    assert_eq!(ack["quirk"], true);
+}
@@ -0,0 +1,42 @@
+mod common;
+
+use common::{BridgeProcess, MockServer};
+use std::time::Duration;
+
+#[test]
+fn contradiction_fails_startup_with_exit_code_2() {
+    let server = MockServer::echo();
+    let bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[
+            ("MCP_RAW_PASSTHROUGH", "1"),
+            ("MCP_STDIN_VALIDATION", "lossy"),
+        ],
+    );
+    let (code, lines, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(2));
+    assert!(lines.is_empty());
+    let error = stderr
+        .lines()
+        .find(|l| l.contains("invalid configuration"))
+        .unwrap_or_else(|| panic!("{}", stderr));
+    assert!(error.contains("MCP_RAW_PASSTHROUGH and MCP_STDIN_VALIDATION"));
+    assert!(error.contains("use MCP_STDIN_VALIDATION=strict"));
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn redundant_setting_only_warns() {
+    let server = MockServer::echo();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_HEDGE_DELAY_MS", "100")]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#);
+    assert!(bridge.recv_line(Duration::from_secs(10)).is_some());
+    bridge.close_stdin();
+    let (code, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(0));
+    assert!(
+        stderr.contains("redundant configuration: MCP_HEDGE_DELAY_MS and MCP_HEDGE_METHODS"),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,143 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use std::thread;
+use std::time::Duration;
+
+fn recv(bridge: &BridgeProcess) -> serde_json::Value {
+    let line = bridge
+        .recv_line(Duration::from_secs(10))
+        .expect("stdout line");
+    serde_json::from_str(&line).unwrap()
+}
+
+#[test]
+fn paused_bridge_holds_traffic_and_releases_it_in_order() {
+    let server = MockServer::echo();
+    // One request at a time, so the upstream sees the release order.
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[("MCP_CONTROL_METHODS", "1"), ("MCP_MAX_CONCURRENCY", "1")],
+    );
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":"p","method":"bridge/pause"}"#);
+    assert_eq!(recv(&bridge)["result"]["paused"], true);
+
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list","params":{"n":1}}"#);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":2,"method":"tools/list","params":{"n":2}}"#);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":3,"method":"tools/list","params":{"n":3}}"#);
+    bridge.send_line(
+        r#"{"jsonrpc":"2.0","method":"notifications/cancelled","params":{"requestId":2}}"#,
+    );
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":"ping","method":"ping"}"#);
+    let pong = recv(&bridge);
+    assert_eq!(pong["id"], "ping");
+    assert_eq!(pong["result"], serde_json::json!({}));
+    thread::sleep(Duration::from_millis(200));
+    assert!(server.requests().is_empty());
+
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":"r","method":"bridge/resume"}"#);
+    let resumed = recv(&bridge);
+    assert_eq!(resumed["result"]["paused"], false);
+    assert_eq!(resumed["result"]["released"], 2);
+    assert_eq!(recv(&bridge)["id"], 1);
+    assert_eq!(recv(&bridge)["id"], 3);
+    let upstream: Vec<serde_json::Value> = server
+        .requests()
+        .iter()
+        .map(|r| serde_json::from_slice::<serde_json::Value>(&r.body).unwrap()["id"].clone())
+        .collect();
+    assert_eq!(upstream, vec![serde_json::json!(1), serde_json::json!(3)]);
+}
+
+#[test]
+fn pause_with_resume_after_releases_on_its_own() {
+    let server = MockServer::echo();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_CONTROL_METHODS", "1")]);
+    bridge.send_line(
+        r#"{"jsonrpc":"2.0","id":"p","method":"bridge/pause","params":{"resume_after_ms":300}}"#,
+    );
+    recv(&bridge);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#);
+    assert_eq!(recv(&bridge)["id"], 1);
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn control_methods_are_forwarded_unless_enabled() {
+    let server = MockServer::echo();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":"p","method":"bridge/pause"}"#);
+    recv(&bridge);
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn a_cancellation_while_paused_aborts_the_request_in_flight() {
+    let server = MockServer::start(|req| {
+        let msg: serde_json::Value = serde_json::from_slice(&req.body).unwrap();
+        if msg.get("id").is_none() {
+            return MockResponse::status(202);
+        }
+        let reply = serde_json::json!({"jsonrpc": "2.0", "id": msg["id"], "result": {}});
+        MockResponse::json(reply.to_string()).with_delay(Duration::from_secs(2))
+    });
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_CONTROL_METHODS", "1")]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":5,"method":"tools/call"}"#);
+    while server.requests().is_empty() {
+        thread::sleep(Duration::from_millis(20));
+    }
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":"p","method":"bridge/pause"}"#);
+    assert_eq!(recv(&bridge)["id"], "p");
+    bridge.send_line(
+        r#"{"jsonrpc":"2.0","method":"notifications/cancelled","params":{"requestId":5}}"#,
+    );
+    // The cancellation reaches the upstream, and the call's answer never
+    // reaches the client.
+    thread::sleep(Duration::from_millis(2500));
+    bridge.close_stdin();
+    let (code, lines) = bridge.wait(Duration::from_secs(10));
+    assert_eq!(code, Some(0));
+    assert!(lines.is_empty(), "{:?}", lines);
+    let methods: Vec<String> = server
+        .requests()
+        .iter()
+        .map(|r| serde_json::from_slice::<serde_json::Value>(&r.body).unwrap()["method"].to_string())
+        .collect();
+    assert_eq!(methods, [r#""tools/call""#, r#""notifications/cancelled""#]);
+}
+
+#[test]
+fn stats_are_answered_locally_while_paused() {
+    let server = MockServer::echo();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_CONTROL_METHODS", "1")]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#);
+    recv(&bridge);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":"p","method":"bridge/pause"}"#);
+    recv(&bridge);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":"s","method":"bridge/stats"}"#);
+    let stats = recv(&bridge);
+    assert_eq!(stats["id"], "s");
+    assert_eq!(stats["result"]["paused"], true);
+    assert_eq!(stats["result"]["requests"], 1);
+    assert_eq!(stats["result"]["methods"]["tools/list"]["count"], 1);
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn heartbeat_shows_the_pause() {
+    let server = MockServer::echo();
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[("MCP_CONTROL_METHODS", "1"), ("MCP_HEARTBEAT_SECS", "1")],
+    );
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":"p","method":"bridge/pause"}"#);
+    recv(&bridge);
+    thread::sleep(Duration::from_millis(1500));
+    bridge.close_stdin();
+    let (_, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    let beat = stderr
+        .lines()
+        .find(|l| l.contains("heartbeat: heartbeat"))
+        .unwrap_or_else(|| panic!("no heartbeat in stderr: {}", stderr));
+    assert!(beat.contains("paused=true"), "{}", beat);
+}
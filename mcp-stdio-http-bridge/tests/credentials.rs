@@ -0,0 +1,78 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{BridgeProcess, MockServer};
+use std::thread;
+use std::time::Duration;
+
+const TOKEN: &str = "rotation-test-token-9f3c";
+
+fn ping(bridge: &mut BridgeProcess, id: u32) {
+    bridge.send_line(&format!(
+        r#"{{"jsonrpc":"2.0","id":{},"method":"ping"}}"#,
+        id
+    ));
+    assert!(bridge.recv_line(Duration::from_secs(10)).is_some());
+}
+
+#[test]
+fn sigusr2_reacquires_credentials_without_interrupting_traffic() {
+    let server = MockServer::echo();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_BRIDGE_BEARER_TOKEN", TOKEN)]);
+    ping(&mut bridge, 1);
+    bridge.signal("USR2");
+    // Give the signal handler a moment before the next request.
+    thread::sleep(Duration::from_millis(200));
+    ping(&mut bridge, 2);
+    let expected = format!("Bearer {}", TOKEN);
+    for req in server.requests() {
+        assert_eq!(req.header("Authorization"), Some(expected.as_str()));
+    }
+    bridge.close_stdin();
+    let (code, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(0));
+    let line = stderr
+        .lines()
+        .find(|l| l.contains("re-acquired credentials"))
+        .unwrap_or_else(|| panic!("{}", stderr));
+    assert!(line.contains("…9f3c"), "{}", line);
+    assert!(!stderr.contains(TOKEN));
+}
+
+#[test]
+fn sigusr2_rereads_the_token_file() {
+    let server = MockServer::echo();
+    let path = std::env::temp_dir().join(format!("bridge-usr2-token-{}", std::process::id()));
+    std::fs::write(&path, "file-token-before-aaaa").unwrap();
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[
+            ("MCP_BEARER_TOKEN_FILE", path.to_str().unwrap()),
+            ("MCP_BEARER_TOKEN_RELOAD_EVERY", "0"),
+        ],
+    );
+    ping(&mut bridge, 1);
+    std::fs::write(&path, "file-token-after-bbbb").unwrap();
+    ping(&mut bridge, 2);
+    bridge.signal("USR2");
+    thread::sleep(Duration::from_millis(200));
+    ping(&mut bridge, 3);
+    bridge.close_stdin();
+    let (_, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    std::fs::remove_file(&path).unwrap();
+    let bearers: Vec<_> = server
+        .requests()
+        .iter()
+        .map(|r| r.header("Authorization").unwrap().to_string())
+        .collect();
+    assert_eq!(
+        bearers,
+        [
+            "Bearer file-token-before-aaaa",
+            "Bearer file-token-before-aaaa",
+            "Bearer file-token-after-bbbb",
+        ]
+    );
+    assert!(stderr.contains("re-acquired credentials"), "{}", stderr);
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use common::{BridgeProcess, MockServer};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+fn recv(bridge: &BridgeProcess) -> Value {
+    let line = bridge.recv_line(Duration::from_secs(10)).expect("stdout line");
+    serde_json::from_str(&line).unwrap()
+}
+
+#[test]
+fn echo_mode_answers_locally_in_order() {
+    let server = MockServer::echo();
+    let uri = format!("{}?key=s3cret", server.uri());
+    let mut bridge = BridgeProcess::spawn(&uri, &[("MCP_MODE", "echo")]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":0,"method":"initialize","params":{"protocolVersion":"2025-03-26"}}"#);
+    bridge.send_line(r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":"c","method":"tools/call","params":{"name":"t"}}"#);
+    let init = recv(&bridge);
+    assert_eq!(init["id"], 0);
+    assert_eq!(init["result"]["protocolVersion"], "2025-03-26");
+    assert_eq!(recv(&bridge), json!({"jsonrpc": "2.0", "id": 1, "result": {"tools": []}}));
+    assert_eq!(recv(&bridge), json!({"jsonrpc": "2.0", "id": "c", "result": {"name": "t"}}));
+    bridge.close_stdin();
+    let (code, rest, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(0));
+    assert!(rest.is_empty(), "{:?}", rest);
+    assert!(stderr.contains("ECHO MODE IS ON"), "{}", stderr);
+    assert!(!stderr.contains("s3cret"), "{}", stderr);
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn echo_mode_needs_its_exact_name() {
+    let server = MockServer::echo();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_MODE", "ECHO")]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{"n":1}}"#);
+    assert_eq!(recv(&bridge)["id"], 1);
+    bridge.close_stdin();
+    let (_, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert!(!stderr.contains("ECHO MODE IS ON"), "{}", stderr);
+    assert_eq!(server.requests().len(), 1);
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use common::MockServer;
+use futures_util::{stream, StreamExt};
+use mcp_stdio_http_bridge::config::Config;
+use mcp_stdio_http_bridge::embed::{BuildError, Ended};
+use mcp_stdio_http_bridge::Bridge;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_util::sync::PollSender;
+
+fn config(uri: &str) -> Config {
+    let uri = uri.to_string();
+    Config::from_lookup(move |name| (name == "MCP_BRIDGE_URI").then(|| uri.clone()))
+}
+
+fn call(id: u32) -> String {
+    json!({"jsonrpc": "2.0", "id": id, "method": "tools/call", "params": {"n": id}}).to_string()
+}
+
+#[tokio::test]
+async fn messages_pass_through_channels_in_memory() {
+    let server = MockServer::echo();
+    let bridge = Bridge::builder().config(config(&server.uri())).build().unwrap();
+    let (tx, mut answers) = tokio::sync::mpsc::channel(8);
+    let input = stream::iter([call(1), call(2)]);
+    let finished = bridge.run(input, PollSender::new(tx)).await;
+    assert_eq!(finished.ended, Ended::InputClosed);
+    assert!(finished.drained);
+    let mut ids = Vec::new();
+    while let Some(line) = answers.recv().await {
+        let answer: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(answer["result"]["n"], answer["id"]);
+        ids.push(answer["id"].as_u64().unwrap());
+    }
+    ids.sort();
+    assert_eq!(ids, [1, 2]);
+}
+
+#[tokio::test]
+async fn shutdown_handle_stops_a_bridge_whose_input_stays_open() {
+    let server = MockServer::echo();
+    let bridge = Bridge::builder().config(config(&server.uri())).build().unwrap();
+    let handle = bridge.shutdown_handle();
+    let (tx, mut answers) = tokio::sync::mpsc::channel(8);
+    let input = stream::iter([call(1)]).chain(stream::pending());
+    let running = tokio::spawn(bridge.run(input, PollSender::new(tx)));
+    let answer = tokio::time::timeout(Duration::from_secs(10), answers.recv()).await;
+    let answer: Value = serde_json::from_str(&answer.unwrap().unwrap()).unwrap();
+    assert_eq!(answer["id"], 1);
+    handle.shutdown();
+    let finished = tokio::time::timeout(Duration::from_secs(10), running).await;
+    assert_eq!(finished.unwrap().unwrap().ended, Ended::Shutdown);
+    assert_eq!(answers.recv().await, None);
+}
+
+#[tokio::test]
+async fn lines_are_read_from_any_byte_stream() {
+    let server = MockServer::echo();
+    let bridge = Bridge::builder().config(config(&server.uri())).build().unwrap();
+    let (client, bridge_end) = tokio::io::duplex(4096);
+    let (input, output) = tokio::io::split(bridge_end);
+    let running = tokio::spawn(bridge.run_lines(input, output));
+    let (mut from_bridge, mut to_bridge) = tokio::io::split(client);
+    to_bridge.write_all(format!("{}\n", call(7)).as_bytes()).await.unwrap();
+    to_bridge.shutdown().await.unwrap();
+    let mut out = String::new();
+    from_bridge.read_to_string(&mut out).await.unwrap();
+    let answer: Value = serde_json::from_str(out.trim_end()).unwrap();
+    assert_eq!(answer["id"], 7);
+    assert_eq!(running.await.unwrap().ended, Ended::InputClosed);
+}
+
+#[tokio::test]
+async fn a_panicking_input_is_reported() {
+    let server = MockServer::echo();
+    let bridge = Bridge::builder().config(config(&server.uri())).build().unwrap();
+    let (tx, _answers) = tokio::sync::mpsc::channel(8);
+    let input = stream::iter([call(1)]).chain(stream::poll_fn(|_| panic!("input broke")));
+    let running = bridge.run(input, PollSender::new(tx));
+    let finished = tokio::time::timeout(Duration::from_secs(10), running).await;
+    assert_eq!(finished.unwrap().ended, Ended::Panicked);
+}
+
+#[tokio::test]
+async fn an_unreadable_input_is_a_failure() {
+    let server = MockServer::echo();
+    let bridge = Bridge::builder().config(config(&server.uri())).build().unwrap();
+    let (client, bridge_end) = tokio::io::duplex(4096);
+    let (input, output) = tokio::io::split(bridge_end);
+    let running = tokio::spawn(bridge.run_lines(input, output));
+    let (_from_bridge, mut to_bridge) = tokio::io::split(client);
+    to_bridge.write_all(b"{\"id\":\"\xff\"}\n").await.unwrap();
+    let finished = tokio::time::timeout(Duration::from_secs(10), running).await;
+    assert_eq!(finished.unwrap().unwrap().ended, Ended::InputFailed);
+}
+
+#[test]
+fn a_bridge_needs_a_config() {
+    assert_eq!(Bridge::builder().build().err(), Some(BuildError::MissingConfig));
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer, RecordedRequest};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+const INITIALIZE: &str = r#"{"jsonrpc":"2.0","id":"init","method":"initialize","params":{}}"#;
+const INITIALIZED: &str = r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#;
+
+/// Answers every request, assigning `session` on `initialize`, until `down`
+/// is set; then answers 503.
+fn region(session: &'static str, down: Arc<AtomicBool>) -> MockServer {
+    MockServer::start(move |req| {
+        if down.load(Ordering::SeqCst) {
+            return MockResponse::status(503);
+        }
+        let msg: Value = serde_json::from_slice(&req.body).unwrap();
+        let Some(id) = msg.get("id") else {
+            return MockResponse::status(202);
+        };
+        let resp =
+            MockResponse::json(json!({"jsonrpc": "2.0", "id": id, "result": {}}).to_string());
+        if msg["method"] == "initialize" {
+            return resp.with_header("Mcp-Session-Id", session);
+        }
+        resp
+    })
+}
+
+fn summary(req: &RecordedRequest) -> (Value, Option<String>) {
+    let msg: Value = serde_json::from_slice(&req.body).unwrap();
+    (msg["method"].clone(), req.header("Mcp-Session-Id").map(str::to_string))
+}
+
+fn roundtrip(bridge: &mut BridgeProcess, line: &str) -> Value {
+    bridge.send_line(line);
+    serde_json::from_str(&bridge.recv_line(Duration::from_secs(10)).unwrap()).unwrap()
+}
+
+#[test]
+fn failing_endpoint_is_left_for_the_next_with_a_new_session() {
+    let primary_down = Arc::new(AtomicBool::new(false));
+    let primary = region("a1", primary_down.clone());
+    let secondary = region("b1", Arc::new(AtomicBool::new(false)));
+    let mut bridge = BridgeProcess::spawn(
+        &primary.uri(),
+        &[
+            ("MCP_FALLBACK_URIS", &secondary.uri()),
+            ("MCP_FAILOVER_AFTER", "2"),
+            ("MCP_RETRY_METHODS", "*"),
+        ],
+    );
+    roundtrip(&mut bridge, INITIALIZE);
+    bridge.send_line(INITIALIZED);
+    while primary.requests().len() < 2 {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    primary_down.store(true, Ordering::SeqCst);
+    let reply = roundtrip(&mut bridge, r#"{"jsonrpc":"2.0","id":1,"method":"tools/call"}"#);
+    assert_eq!(reply, json!({"jsonrpc": "2.0", "id": 1, "result": {}}));
+    // The bridge stays with the endpoint that worked.
+    let reply = roundtrip(&mut bridge, r#"{"jsonrpc":"2.0","id":2,"method":"tools/list"}"#);
+    assert_eq!(reply["id"], 2);
+    bridge.close_stdin();
+    let (code, lines, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(0), "stderr: {}", stderr);
+    // The replayed handshake is answered upstream only.
+    assert!(lines.is_empty(), "stdout: {:?}", lines);
+    assert!(stderr.contains("failing over"), "stderr: {}", stderr);
+    assert!(stderr.contains(&secondary.uri()), "stderr: {}", stderr);
+
+    let primary_calls = primary
+        .requests()
+        .iter()
+        .filter(|r| summary(r).0 == "tools/call")
+        .count();
+    assert_eq!(primary_calls, 2);
+    let sent: Vec<_> = secondary.requests().iter().map(summary).collect();
+    assert_eq!(
+        sent,
+        vec![
+            (json!("initialize"), None),
+            (json!("notifications/initialized"), Some("b1".to_string())),
+            (json!("tools/call"), Some("b1".to_string())),
+            (json!("tools/list"), Some("b1".to_string())),
+        ]
+    );
+}
+
+#[test]
+fn single_endpoint_keeps_retrying() {
+    let server = region("a1", Arc::new(AtomicBool::new(true)));
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[("MCP_FAILOVER_AFTER", "1"), ("MCP_TOTAL_BUDGET_MS", "1500")],
+    );
+    let reply = roundtrip(&mut bridge, r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#);
+    assert_eq!(reply["error"]["code"], -32603);
+    bridge.close_stdin();
+    let (_, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert!(!stderr.contains("failing over"), "stderr: {}", stderr);
+    assert!(server.requests().len() > 1);
+}
@@ -0,0 +1,221 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer, RecordedRequest};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const INITIALIZE: &str = r#"{"jsonrpc":"2.0","id":"init","method":"initialize","params":{}}"#;
+const INITIALIZED: &str = r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#;
+
+fn updated(n: usize) -> String {
+    let note = json!({
+        "jsonrpc": "2.0",
+        "method": "notifications/resources/updated",
+        "params": {"uri": format!("file:///{}", n)}
+    });
+    format!("data: {}\n\n", note)
+}
+
+/// Answers POSTs like the echo server, assigning session `s1` on
+/// initialize, and hands GETs to `get`.
+fn server<F>(get: F) -> MockServer
+where
+    F: Fn(&RecordedRequest) -> MockResponse + Send + Sync + 'static,
+{
+    MockServer::start(move |req| {
+        if req.method == "GET" {
+            return get(req);
+        }
+        let msg: Value = serde_json::from_slice(&req.body).unwrap();
+        if msg.get("id").is_none() {
+            return MockResponse::status(202);
+        }
+        let resp = MockResponse::json(
+            json!({"jsonrpc": "2.0", "id": msg["id"], "result": {}}).to_string(),
+        );
+        resp.with_header("Mcp-Session-Id", "s1")
+    })
+}
+
+fn sse() -> MockResponse {
+    MockResponse::status(200).with_header("Content-Type", "text/event-stream")
+}
+
+fn gets(server: &MockServer) -> Vec<RecordedRequest> {
+    server
+        .requests()
+        .into_iter()
+        .filter(|r| r.method == "GET")
+        .collect()
+}
+
+fn initialize(bridge: &mut BridgeProcess) {
+    bridge.send_line(INITIALIZE);
+    bridge
+        .recv_line(Duration::from_secs(10))
+        .expect("initialize response");
+}
+
+fn recv(bridge: &BridgeProcess) -> Value {
+    let line = bridge
+        .recv_line(Duration::from_secs(10))
+        .expect("stdout line");
+    serde_json::from_str(&line).unwrap()
+}
+
+#[test]
+fn server_messages_reach_the_client_once_initialized() {
+    // The stream stays open after its first event.
+    let server = server(|_| {
+        sse()
+            .with_chunk(Duration::ZERO, updated(1))
+            .with_chunk(Duration::from_secs(30), updated(2))
+    });
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[
+            ("MCP_ENABLE_GET_STREAM", "true"),
+            ("MCP_BRIDGE_BEARER_TOKEN", "secret"),
+        ],
+    );
+    initialize(&mut bridge);
+    thread::sleep(Duration::from_millis(300));
+    assert!(gets(&server).is_empty(), "GET before initialization");
+    bridge.send_line(INITIALIZED);
+    let pushed = recv(&bridge);
+    assert_eq!(pushed["method"], "notifications/resources/updated");
+    assert_eq!(pushed["params"]["uri"], "file:///1");
+    let get = &gets(&server)[0];
+    assert_eq!(get.path, "/mcp");
+    assert_eq!(get.header("Accept"), Some("text/event-stream"));
+    assert_eq!(get.header("Mcp-Session-Id"), Some("s1"));
+    assert_eq!(get.header("Authorization"), Some("Bearer secret"));
+    bridge.close_stdin();
+    let (code, rest) = bridge.wait(Duration::from_secs(10));
+    assert_eq!(code, Some(0));
+    assert!(rest.is_empty(), "{:?}", rest);
+}
+
+#[test]
+fn dropped_stream_is_reopened() {
+    let opened = Arc::new(AtomicUsize::new(0));
+    let counter = opened.clone();
+    // Each stream sends one event and ends.
+    let server = server(move |_| {
+        sse().with_chunk(
+            Duration::ZERO,
+            updated(counter.fetch_add(1, Ordering::SeqCst) + 1),
+        )
+    });
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_ENABLE_GET_STREAM", "1")]);
+    initialize(&mut bridge);
+    bridge.send_line(INITIALIZED);
+    assert_eq!(recv(&bridge)["params"]["uri"], "file:///1");
+    assert_eq!(recv(&bridge)["params"]["uri"], "file:///2");
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
+
+#[test]
+fn repeated_405_gives_up_until_reinitialized() {
+    let server = server(|_| MockResponse::status(405));
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[
+            ("MCP_ENABLE_GET_STREAM", "1"),
+            ("MCP_GET_STREAM_405_LIMIT", "2"),
+        ],
+    );
+    initialize(&mut bridge);
+    bridge.send_line(INITIALIZED);
+    // Attempts at 0 and 0.5 s; a third would come at 1.5 s.
+    thread::sleep(Duration::from_millis(2500));
+    assert_eq!(gets(&server).len(), 2);
+    // A new session may be served by an upstream that offers the stream.
+    initialize(&mut bridge);
+    bridge.send_line(INITIALIZED);
+    thread::sleep(Duration::from_millis(2500));
+    assert_eq!(gets(&server).len(), 4);
+    bridge.close_stdin();
+    let (_, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(
+        stderr
+            .matches("upstream does not offer the GET stream")
+            .count(),
+        2
+    );
+    let report = BridgeProcess::shutdown_report(&stderr).expect("shutdown report");
+    assert_eq!(report["stats"]["get_stream_disabled"], true);
+}
+
+#[test]
+fn stream_is_off_by_default() {
+    let server = server(|_| sse());
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[]);
+    initialize(&mut bridge);
+    bridge.send_line(INITIALIZED);
+    thread::sleep(Duration::from_millis(500));
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+    assert!(gets(&server).is_empty());
+}
+
+#[test]
+fn a_resumed_stream_gives_responses_their_client_ids() {
+    // The bridge id the call went upstream under.
+    let call_id: Arc<Mutex<Option<Value>>> = Arc::default();
+    let seen = call_id.clone();
+    let server = MockServer::start(move |req| {
+        if req.method == "GET" {
+            if req.header("Last-Event-ID") != Some("1") {
+                // Ends after one event, so the bridge resumes after it.
+                return sse().with_chunk(Duration::ZERO, format!("id: 1\n{}", updated(1)));
+            }
+            let deadline = Instant::now() + Duration::from_secs(5);
+            let id = loop {
+                if let Some(id) = seen.lock().unwrap().clone() {
+                    break id;
+                }
+                assert!(Instant::now() < deadline, "no call");
+                thread::sleep(Duration::from_millis(20));
+            };
+            let result = json!({"jsonrpc": "2.0", "id": id, "result": {"resumed": true}});
+            return sse()
+                .with_chunk(Duration::ZERO, format!("id: 2\ndata: {}\n\n", result))
+                .with_chunk(Duration::from_secs(30), updated(3));
+        }
+        let msg: Value = serde_json::from_slice(&req.body).unwrap();
+        if msg["method"] == "tools/call" {
+            *seen.lock().unwrap() = Some(msg["id"].clone());
+            // Its answer comes on the resumed GET stream instead.
+            return sse().with_chunk(Duration::from_secs(30), updated(2));
+        }
+        if msg.get("id").is_none() {
+            return MockResponse::status(202);
+        }
+        MockResponse::json(json!({"jsonrpc": "2.0", "id": msg["id"], "result": {}}).to_string())
+            .with_header("Mcp-Session-Id", "s1")
+    });
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[
+            ("MCP_ENABLE_GET_STREAM", "1"),
+            ("MCP_REWRITE_IDS", "1"),
+            ("MCP_SHUTDOWN_GRACE_MS", "200"),
+        ],
+    );
+    initialize(&mut bridge);
+    bridge.send_line(INITIALIZED);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":"call","method":"tools/call"}"#);
+    assert_eq!(recv(&bridge)["params"]["uri"], "file:///1");
+    assert_eq!(
+        recv(&bridge),
+        json!({"jsonrpc": "2.0", "id": "call", "result": {"resumed": true}})
+    );
+    assert_ne!(call_id.lock().unwrap().as_ref(), Some(&json!("call")));
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
@@ -0,0 +1,183 @@
+mod common;
+
+use common::{BridgeProcess, MockServer};
+use std::time::Duration;
+
+#[test]
+fn extra_headers_reach_the_upstream_and_replace_built_ins() {
+    let server = MockServer::echo();
+    let mut bridge = BridgeProcess::spawn_with_args(
+        &server.uri(),
+        &[(
+            "MCP_HEADERS",
+            "CF-Access-Client-Id: abc, CF-Access-Client-Secret: xyz",
+        )],
+        &[
+            "--header",
+            "Accept: application/json",
+            "--header",
+            "X-Tenant: t1",
+        ],
+    );
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#);
+    assert!(bridge.recv_line(Duration::from_secs(10)).is_some());
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+    let requests = server.requests();
+    let request = &requests[0];
+    // The flags win over MCP_HEADERS as a whole, like any other option.
+    assert_eq!(request.header("CF-Access-Client-Id"), None);
+    assert_eq!(request.header("X-Tenant"), Some("t1"));
+    let accept: Vec<&str> = request
+        .headers
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("Accept"))
+        .map(|(_, value)| value.as_str())
+        .collect();
+    assert_eq!(accept, ["application/json"]);
+}
+
+#[test]
+fn env_headers_are_sent_with_every_request() {
+    let server = MockServer::echo();
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[(
+            "MCP_HEADERS",
+            "CF-Access-Client-Id: abc, CF-Access-Client-Secret: xyz",
+        )],
+    );
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":2,"method":"ping"}"#);
+    assert!(bridge.recv_line(Duration::from_secs(10)).is_some());
+    assert!(bridge.recv_line(Duration::from_secs(10)).is_some());
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+    for request in server.requests() {
+        assert_eq!(request.header("CF-Access-Client-Id"), Some("abc"));
+        assert_eq!(request.header("CF-Access-Client-Secret"), Some("xyz"));
+    }
+}
+
+#[test]
+fn invalid_header_fails_startup() {
+    let server = MockServer::echo();
+    let bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_HEADERS", "X-Secret: a\u{7f}b")]);
+    let (code, lines, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(2));
+    assert!(lines.is_empty());
+    assert!(
+        stderr
+            .contains(r#"invalid header in env MCP_HEADERS: invalid value for header "X-Secret""#),
+        "{}",
+        stderr
+    );
+    assert!(!stderr.contains("a\u{7f}b"));
+    assert!(server.requests().is_empty());
+}
+
+const INITIALIZE: &str =
+    r#"{"jsonrpc":"2.0","id":0,"method":"initialize","params":{"protocolVersion":"2025-03-26"}}"#;
+
+#[test]
+fn user_agent_and_negotiated_protocol_version_are_sent() {
+    let server = MockServer::echo();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_NAME", "docs")]);
+    bridge.send_line(INITIALIZE);
+    assert!(bridge.recv_line(Duration::from_secs(10)).is_some());
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#);
+    assert!(bridge.recv_line(Duration::from_secs(10)).is_some());
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+    let requests = server.requests();
+    let agent = format!("mcp-stdio-http-bridge/{} (docs)", env!("CARGO_PKG_VERSION"));
+    assert_eq!(requests[0].header("User-Agent"), Some(agent.as_str()));
+    assert_eq!(requests[0].header("MCP-Protocol-Version"), None);
+    assert_eq!(requests[1].header("MCP-Protocol-Version"), Some("2025-03-26"));
+}
+
+#[test]
+fn configured_protocol_version_and_extra_headers_win() {
+    let server = MockServer::echo();
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[
+            ("MCP_PROTOCOL_VERSION", "2024-11-05"),
+            ("MCP_HEADERS", "User-Agent: custom/1"),
+        ],
+    );
+    bridge.send_line(INITIALIZE);
+    assert!(bridge.recv_line(Duration::from_secs(10)).is_some());
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#);
+    assert!(bridge.recv_line(Duration::from_secs(10)).is_some());
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+    for request in server.requests() {
+        assert_eq!(request.header("MCP-Protocol-Version"), Some("2024-11-05"));
+        assert_eq!(request.header("User-Agent"), Some("custom/1"));
+    }
+
+    let server = MockServer::echo();
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[("MCP_HEADERS", "MCP-Protocol-Version: 2099-01-01")],
+    );
+    bridge.send_line(INITIALIZE);
+    assert!(bridge.recv_line(Duration::from_secs(10)).is_some());
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#);
+    assert!(bridge.recv_line(Duration::from_secs(10)).is_some());
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+    let requests = server.requests();
+    let versions: Vec<&str> = requests[1]
+        .headers
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("MCP-Protocol-Version"))
+        .map(|(_, value)| value.as_str())
+        .collect();
+    assert_eq!(versions, ["2099-01-01"]);
+}
+
+#[test]
+fn meta_keys_become_headers_of_their_own_request() {
+    let server = MockServer::echo();
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[("MCP_META_HEADERS", "tenantId=X-Tenant-Id,traceId=X-Trace-Id")],
+    );
+    bridge.send_line(
+        r#"{"jsonrpc":"2.0","id":1,"method":"tools/list","params":{"_meta":{"tenantId":"acme"}}}"#,
+    );
+    assert!(bridge.recv_line(Duration::from_secs(10)).is_some());
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":2,"method":"tools/list"}"#);
+    assert!(bridge.recv_line(Duration::from_secs(10)).is_some());
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+    let requests = server.requests();
+    assert_eq!(requests[0].header("X-Tenant-Id"), Some("acme"));
+    assert_eq!(requests[0].header("X-Trace-Id"), None);
+    assert!(requests[0].body_str().contains(r#""tenantId":"acme""#));
+    assert_eq!(requests[1].header("X-Tenant-Id"), None);
+}
+
+#[test]
+fn stripped_meta_keys_leave_the_body() {
+    let server = MockServer::echo();
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[
+            ("MCP_META_HEADERS", "tenantId=X-Tenant-Id"),
+            ("MCP_META_STRIP", "1"),
+        ],
+    );
+    bridge.send_line(
+        r#"{"jsonrpc":"2.0","id":1,"method":"tools/list","params":{"_meta":{"tenantId":"acme","progressToken":3}}}"#,
+    );
+    assert!(bridge.recv_line(Duration::from_secs(10)).is_some());
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+    let requests = server.requests();
+    assert_eq!(requests[0].header("X-Tenant-Id"), Some("acme"));
+    assert!(!requests[0].body_str().contains("tenantId"));
+    assert!(requests[0].body_str().contains(r#""progressToken":3"#));
+}
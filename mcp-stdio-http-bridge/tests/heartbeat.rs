@@ -0,0 +1,75 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use std::thread;
+use std::time::Duration;
+
+/// The first heartbeat line in the bridge's log.
+fn heartbeat(stderr: &str) -> &str {
+    stderr
+        .lines()
+        .find(|l| l.contains("heartbeat: heartbeat"))
+        .unwrap_or_else(|| panic!("no heartbeat in stderr: {}", stderr))
+}
+
+#[test]
+fn heartbeat_logs_the_busiest_methods() {
+    let server = MockServer::echo();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_HEARTBEAT_SECS", "1")]);
+    for id in 1..=3 {
+        bridge.send_line(&format!(
+            r#"{{"jsonrpc":"2.0","id":{},"method":"tools/list"}}"#,
+            id
+        ));
+        bridge.recv_line(Duration::from_secs(10)).unwrap();
+    }
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":4,"method":"ping"}"#);
+    bridge.recv_line(Duration::from_secs(10)).unwrap();
+    thread::sleep(Duration::from_millis(1500));
+    bridge.close_stdin();
+    let (_, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    let beat = heartbeat(&stderr);
+    assert!(beat.contains("requests=4"), "{}", beat);
+    assert!(beat.contains("tools/list=3/0"), "{}", beat);
+    assert!(beat.contains("ping=1/0"), "{}", beat);
+}
+
+#[test]
+fn stats_file_is_written_at_exit_without_a_heartbeat() {
+    let server = MockServer::echo();
+    let path = std::env::temp_dir().join(format!("bridge-stats-{}.json", std::process::id()));
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[("MCP_STATS_FILE", path.to_str().unwrap())],
+    );
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#);
+    bridge.recv_line(Duration::from_secs(10)).unwrap();
+    bridge.close_stdin();
+    let (code, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(0), "stderr: {}", stderr);
+    assert!(!stderr.contains("heartbeat: heartbeat"), "stderr: {}", stderr);
+    let file: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(file["requests"], 1);
+    assert_eq!(file["methods"]["tools/list"]["count"], 1);
+}
+
+#[test]
+fn heartbeat_carries_the_latest_rate_limit() {
+    let server = MockServer::start(|req| {
+        let id = serde_json::from_slice::<serde_json::Value>(&req.body).unwrap()["id"].clone();
+        MockResponse::json(format!(r#"{{"jsonrpc":"2.0","id":{},"result":{{}}}}"#, id))
+            .with_header("X-RateLimit-Remaining", "42")
+            .with_header("X-RateLimit-Reset", "30")
+    });
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_HEARTBEAT_SECS", "1")]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#);
+    bridge.recv_line(Duration::from_secs(10)).unwrap();
+    thread::sleep(Duration::from_millis(1500));
+    bridge.close_stdin();
+    let (_, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    let beat = heartbeat(&stderr);
+    assert!(beat.contains("rate_limit_remaining=42"), "{}", beat);
+    assert!(beat.contains("rate_limit_reset=30"), "{}", beat);
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+#[test]
+fn hedge_wins_over_slow_first_request() {
+    let calls = AtomicUsize::new(0);
+    let server = MockServer::start(move |_| {
+        if calls.fetch_add(1, Ordering::SeqCst) == 0 {
+            MockResponse::json(r#"{"jsonrpc":"2.0","id":1,"result":{"from":"slow"}}"#)
+                .with_delay(Duration::from_secs(2))
+        } else {
+            MockResponse::json(r#"{"jsonrpc":"2.0","id":1,"result":{"from":"hedge"}}"#)
+        }
+    });
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[
+            ("MCP_HEDGE_METHODS", "tools/list"),
+            ("MCP_HEDGE_DELAY_MS", "100"),
+        ],
+    );
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#);
+    let line = bridge.recv_line(Duration::from_secs(10)).unwrap();
+    assert_eq!(
+        line,
+        r#"{"jsonrpc":"2.0","id":1,"result":{"from":"hedge"}}"#
+    );
+    assert_eq!(server.requests().len(), 2);
+    // The slow response would have arrived by now; it must not reach stdout.
+    assert_eq!(bridge.recv_line(Duration::from_millis(2500)), None);
+}
+
+#[test]
+fn side_effecting_methods_are_never_hedged() {
+    let server = MockServer::start(|_| {
+        MockResponse::json(r#"{"jsonrpc":"2.0","id":1,"result":{}}"#)
+            .with_delay(Duration::from_millis(500))
+    });
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[
+            ("MCP_HEDGE_METHODS", "tools/call"),
+            ("MCP_HEDGE_DELAY_MS", "50"),
+        ],
+    );
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/call"}"#);
+    bridge.recv_line(Duration::from_secs(10)).unwrap();
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn no_hedge_without_a_free_concurrency_slot() {
+    let server = MockServer::start(|_| {
+        MockResponse::json(r#"{"jsonrpc":"2.0","id":1,"result":{}}"#)
+            .with_delay(Duration::from_millis(500))
+    });
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[
+            ("MCP_MAX_CONCURRENCY", "1"),
+            ("MCP_HEDGE_METHODS", "tools/list"),
+            ("MCP_HEDGE_DELAY_MS", "100"),
+        ],
+    );
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#);
+    let line = bridge.recv_line(Duration::from_secs(10)).unwrap();
+    assert_eq!(line, r#"{"jsonrpc":"2.0","id":1,"result":{}}"#);
+    assert_eq!(server.requests().len(), 1);
+}
@@ -0,0 +1,36 @@
+mod common;
+
+use common::{BridgeProcess, MockServer};
+use serde_json::Value;
+use std::time::{Duration, Instant};
+
+#[test]
+fn prior_knowledge_against_an_http1_server_fails_fast_naming_the_setting() {
+    let server = MockServer::echo();
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[("MCP_HTTP_VERSION", "http2-prior-knowledge")],
+    );
+    let started = Instant::now();
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#);
+    let line = bridge
+        .recv_line(Duration::from_secs(10))
+        .expect("error answer");
+    // Not retried: the same server would refuse every attempt.
+    assert!(started.elapsed() < Duration::from_secs(2));
+    let answer: Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(answer["id"], 1);
+    let message = answer["error"]["message"].as_str().unwrap();
+    assert!(message.contains("does not speak HTTP/2"), "{}", message);
+    assert!(message.contains("MCP_HTTP_VERSION=http2-prior-knowledge"), "{}", message);
+}
+
+#[test]
+fn http1_only_reaches_an_http1_server() {
+    let server = MockServer::echo();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_HTTP_VERSION", "http1")]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"ping","params":{}}"#);
+    let line = bridge.recv_line(Duration::from_secs(10)).expect("answer");
+    let answer: Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(answer["result"], serde_json::json!({}));
+}
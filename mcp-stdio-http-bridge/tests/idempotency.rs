@@ -0,0 +1,109 @@
+mod common;
+
+use common::{run_lines, BridgeProcess, MockResponse, MockServer};
+use serde_json::Value;
+use serde_json::json;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+
+fn list(id: u32) -> String {
+    format!(r#"{{"jsonrpc":"2.0","id":{},"method":"tools/list"}}"#, id)
+}
+
+#[test]
+fn no_key_unless_configured() {
+    let server = MockServer::failing_first(1);
+    run_lines(&server.uri(), &[], &[list(1)]);
+    assert!(server.requests().iter().all(|r| r.header("idempotency-key").is_none()));
+}
+
+#[test]
+fn retries_repeat_the_key_and_messages_differ() {
+    let server = MockServer::failing_first(1);
+    run_lines(&server.uri(), &[("MCP_IDEMPOTENCY_HEADER", "true")], &[list(1), list(2)]);
+    let keys: Vec<_> = server
+        .requests()
+        .iter()
+        .map(|r| r.header("idempotency-key").expect("a key").to_string())
+        .collect();
+    assert_eq!(keys.len(), 3);
+    assert_eq!(keys[0], keys[1]);
+    assert_ne!(keys[1], keys[2]);
+    assert_eq!(keys[0].len(), 36);
+}
+
+#[test]
+fn failover_keeps_the_key() {
+    let primary = MockServer::start(|_| MockResponse::status(503));
+    let secondary = MockServer::echo();
+    run_lines(
+        &primary.uri(),
+        &[
+            ("MCP_IDEMPOTENCY_HEADER", "X-Request-Key"),
+            ("MCP_FALLBACK_URIS", &secondary.uri()),
+            ("MCP_FAILOVER_AFTER", "1"),
+        ],
+        &[list(1)],
+    );
+    let first = primary.requests()[0].header("x-request-key").unwrap().to_string();
+    assert_eq!(secondary.requests()[0].header("x-request-key"), Some(first.as_str()));
+}
+
+#[test]
+fn session_replay_carries_no_key() {
+    let sessions = AtomicUsize::new(0);
+    let expired = AtomicBool::new(false);
+    let server = MockServer::start(move |req| {
+        let msg: Value = serde_json::from_slice(&req.body).unwrap();
+        if msg["method"] == "initialize" {
+            let n = sessions.fetch_add(1, Ordering::SeqCst) + 1;
+            return MockResponse::json(
+                json!({"jsonrpc": "2.0", "id": msg["id"], "result": {}}).to_string(),
+            )
+            .with_header("Mcp-Session-Id", &format!("s{}", n));
+        }
+        // The first request in the first session finds it expired.
+        if req.header("Mcp-Session-Id") == Some("s1") && !expired.swap(true, Ordering::SeqCst) {
+            return MockResponse::status(404);
+        }
+        match msg.get("id") {
+            Some(id) => {
+                MockResponse::json(json!({"jsonrpc": "2.0", "id": id, "result": {}}).to_string())
+            }
+            None => MockResponse::status(202),
+        }
+    });
+    let initialize = r#"{"jsonrpc":"2.0","id":0,"method":"initialize","params":{}}"#.to_string();
+    run_lines(
+        &server.uri(),
+        &[("MCP_IDEMPOTENCY_HEADER", "true"), ("MCP_TRACE_CONTEXT", "1")],
+        &[initialize, list(1)],
+    );
+    let requests = server.requests();
+    let sent: Vec<_> = requests
+        .iter()
+        .map(|r| {
+            let msg: Value = serde_json::from_slice(&r.body).unwrap();
+            (msg["method"].clone(), r.header("idempotency-key"), r.header("traceparent"))
+        })
+        .collect();
+    assert_eq!(sent.len(), 5);
+    assert_eq!(sent[1].0, "tools/list");
+    let key = sent[1].1.expect("a key");
+    for (method, key, traceparent) in &sent[2..4] {
+        assert_eq!(*key, None, "{}", method);
+        assert_eq!(*traceparent, None, "{}", method);
+    }
+    assert_eq!(sent[4].0, "tools/list");
+    assert_eq!(sent[4].1, Some(key));
+    assert_eq!(sent[4].2, sent[1].2);
+}
+
+#[test]
+fn an_invalid_header_name_fails_startup() {
+    let server = MockServer::echo();
+    let bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_IDEMPOTENCY_HEADER", "Request Key")]);
+    let (code, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(2));
+    assert!(stderr.contains(r#"invalid header name "Request Key""#), "{}", stderr);
+}
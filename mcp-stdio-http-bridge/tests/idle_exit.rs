@@ -0,0 +1,21 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use std::time::{Duration, Instant};
+
+#[test]
+fn idle_bridge_exits_with_dedicated_code() {
+    let server =
+        MockServer::start(|_| MockResponse::json(r#"{"jsonrpc":"2.0","id":1,"result":{}}"#));
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_IDLE_EXIT_SECS", "1")]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#);
+    bridge.recv_line(Duration::from_secs(10)).unwrap();
+    let answered = Instant::now();
+    // stdin stays open: only the idle timeout can end the process.
+    let (code, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert!(answered.elapsed() >= Duration::from_millis(900));
+    assert_eq!(code, Some(3));
+    let report = BridgeProcess::shutdown_report(&stderr).unwrap();
+    assert_eq!(report["reason"], "idle timeout");
+    assert_eq!(report["drain_completed"], true);
+}
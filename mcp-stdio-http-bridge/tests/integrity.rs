@@ -0,0 +1,86 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use mcp_stdio_http_bridge::integrity::sha256_hex;
+use serde_json::Value;
+use std::time::Duration;
+
+const REQUEST: &str = r#"{"jsonrpc":"2.0","id":1,"method":"tools/call"}"#;
+const BODY: &str = r#"{"jsonrpc":"2.0","id":1,"result":{}}"#;
+
+fn error_of(bridge: &BridgeProcess) -> Value {
+    let line: Value =
+        serde_json::from_str(&bridge.recv_line(Duration::from_secs(10)).unwrap()).unwrap();
+    assert_eq!(line["error"]["code"], -32603);
+    line
+}
+
+#[test]
+fn outgoing_digest_is_sent_and_matching_echo_passes() {
+    let server = MockServer::start(|_| {
+        MockResponse::json(BODY).with_header("X-Content-SHA256", &sha256_hex(BODY.as_bytes()))
+    });
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_INTEGRITY_HEADER", "1")]);
+    bridge.send_line(REQUEST);
+    assert_eq!(bridge.recv_line(Duration::from_secs(10)).unwrap(), BODY);
+    assert_eq!(
+        server.requests()[0].header("X-Content-SHA256"),
+        Some(sha256_hex(REQUEST.as_bytes()).as_str())
+    );
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
+
+#[test]
+fn wrong_digest_is_reported_as_middlebox_corruption_after_capped_retries() {
+    let server = MockServer::start(|_| {
+        MockResponse::json(BODY).with_header("X-Content-SHA256", &"0".repeat(64))
+    });
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[
+            ("MCP_INTEGRITY_HEADER", "1"),
+            ("MCP_INTEGRITY_MAX_RETRIES", "2"),
+            ("MCP_RETRY_METHODS", "*"),
+        ],
+    );
+    bridge.send_line(REQUEST);
+    let err = error_of(&bridge);
+    let message = err["error"]["message"].as_str().unwrap();
+    assert!(
+        message.contains("possible middlebox corruption"),
+        "{}",
+        message
+    );
+    // The first attempt plus two retries.
+    assert_eq!(server.requests().len(), 3);
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
+
+#[test]
+fn truncated_body_is_reported_as_middlebox_corruption() {
+    let server = MockServer::start(|_| {
+        MockResponse::json(BODY)
+            .with_header("Content-Length", "4096")
+            .with_header("Connection", "close")
+    });
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[
+            ("MCP_INTEGRITY_HEADER", "1"),
+            ("MCP_INTEGRITY_MAX_RETRIES", "0"),
+        ],
+    );
+    bridge.send_line(REQUEST);
+    let err = error_of(&bridge);
+    let message = err["error"]["message"].as_str().unwrap();
+    assert!(
+        message.contains("body truncated: received 36 of 4096 bytes"),
+        "{}",
+        message
+    );
+    assert_eq!(server.requests().len(), 1);
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use serde_json::Value;
+use std::time::Duration;
+
+fn nested(depth: usize) -> String {
+    "[".repeat(depth) + &"]".repeat(depth)
+}
+
+#[test]
+fn deep_client_message_is_refused_locally() {
+    let server = MockServer::start(|req| MockResponse::json(req.body_str()));
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[]);
+    // The fuzzing case: far beyond any parser's recursion limit.
+    bridge.send_line(&format!(
+        r#"{{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{}}}"#,
+        nested(80_000)
+    ));
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":2,"method":"ping"}"#);
+    let refused: Value =
+        serde_json::from_str(&bridge.recv_line(Duration::from_secs(10)).unwrap()).unwrap();
+    assert_eq!(refused["error"]["code"], -32700);
+    assert!(refused["error"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("nesting depth exceeds limit of 128"));
+    let pong: Value =
+        serde_json::from_str(&bridge.recv_line(Duration::from_secs(10)).unwrap()).unwrap();
+    assert_eq!(pong["id"], 2);
+    assert_eq!(server.requests().len(), 1);
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
+
+#[test]
+fn deep_upstream_response_becomes_error_for_request() {
+    let server = MockServer::start(|_| {
+        MockResponse::json(format!(
+            r#"{{"jsonrpc":"2.0","id":7,"result":{{"content":{}}}}}"#,
+            nested(40)
+        ))
+    });
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[("MCP_MAX_JSON_DEPTH", "32"), ("MCP_REWRITE_IDS", "1")],
+    );
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":7,"method":"tools/call"}"#);
+    let line: Value =
+        serde_json::from_str(&bridge.recv_line(Duration::from_secs(10)).unwrap()).unwrap();
+    assert_eq!(line["id"], 7);
+    assert_eq!(line["error"]["code"], -32700);
+    assert!(line["error"]["message"]
+        .as_str()
+        .unwrap()
+        .starts_with("upstream response nesting depth"));
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
+
+#[test]
+fn passthrough_forwards_deep_messages_byte_for_byte() {
+    let server = MockServer::start(|req| MockResponse::json(req.body_str()));
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_RAW_PASSTHROUGH", "1")]);
+    let msg = format!(
+        r#"{{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{{"a":1,"a":{}}}}}"#,
+        nested(200)
+    );
+    bridge.send_line(&msg);
+    let line = bridge.recv_line(Duration::from_secs(10)).unwrap();
+    assert_eq!(server.requests()[0].body_str(), msg);
+    assert_eq!(line, msg);
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
@@ -0,0 +1,84 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use std::time::{Duration, Instant};
+
+#[test]
+fn idle_bridge_sends_keepalive_ping_without_client_output() {
+    let server = MockServer::echo();
+    let bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_KEEPALIVE_INTERVAL_SECS", "1")]);
+    assert_eq!(bridge.recv_line(Duration::from_millis(1500)), None);
+    let requests = server.requests();
+    assert!(!requests.is_empty());
+    let probe: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+    assert_eq!(probe["method"], "ping");
+    assert_eq!(probe["id"], "bridge-keepalive-1");
+}
+
+#[test]
+fn keepalive_interval_can_be_given_in_milliseconds() {
+    let server = MockServer::echo();
+    let bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_KEEPALIVE_INTERVAL_MS", "200")]);
+    assert_eq!(bridge.recv_line(Duration::from_millis(1000)), None);
+    let pings = server
+        .requests()
+        .iter()
+        .filter(|r| r.body_str().contains("bridge-keepalive-"))
+        .count();
+    assert!(pings >= 2, "{} pings", pings);
+}
+
+#[test]
+fn a_slow_probe_does_not_hold_client_requests() {
+    let server = MockServer::start(|req| {
+        let body = req.body_str();
+        if body.contains("bridge-keepalive-") {
+            return MockResponse::json(r#"{"jsonrpc":"2.0","id":"p","result":{}}"#)
+                .with_delay(Duration::from_secs(5));
+        }
+        MockResponse::json(r#"{"jsonrpc":"2.0","id":1,"result":{}}"#)
+    });
+    let mut bridge =
+        BridgeProcess::spawn(&server.uri(), &[("MCP_KEEPALIVE_INTERVAL_MS", "200")]);
+    // Wait until the first probe is out and stuck.
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while server.requests().is_empty() {
+        assert!(Instant::now() < deadline, "no keep-alive probe");
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    let sent = Instant::now();
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#);
+    let answer = bridge.recv_line(Duration::from_secs(10)).expect("answer");
+    assert!(answer.contains(r#""id":1"#), "{}", answer);
+    assert!(sent.elapsed() < Duration::from_secs(3), "{:?}", sent.elapsed());
+    // The probe never reaches the client, and shutdown does not wait for it.
+    bridge.close_stdin();
+    let (code, lines) = bridge.wait(Duration::from_secs(4));
+    assert_eq!(code, Some(0));
+    assert!(lines.is_empty(), "{:?}", lines);
+}
+
+#[test]
+fn connection_pool_settings_are_logged_at_startup() {
+    let server = MockServer::echo();
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[
+            ("MCP_POOL_MAX_IDLE", "4"),
+            ("MCP_POOL_IDLE_TIMEOUT_MS", "20000"),
+            ("MCP_TCP_KEEPALIVE_SECS", "15"),
+        ],
+    );
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#);
+    assert!(bridge.recv_line(Duration::from_secs(10)).is_some());
+    bridge.close_stdin();
+    let (code, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(0));
+    let line = stderr
+        .lines()
+        .find(|l| l.contains("upstream connection pool"))
+        .expect("pool settings logged");
+    assert!(line.contains("pool_max_idle=4"), "{}", line);
+    assert!(line.contains("pool_idle_timeout_ms=20000"), "{}", line);
+    assert!(line.contains("tcp_keepalive_secs=15"), "{}", line);
+}
@@ -0,0 +1,146 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use serde_json::Value;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+/// Starts a bridge listening on a free port and returns it with the
+/// address it bound.
+fn listening_bridge(uri: &str) -> (BridgeProcess, String) {
+    listening_bridge_with(uri, &[])
+}
+
+fn listening_bridge_with(uri: &str, envs: &[(&str, &str)]) -> (BridgeProcess, String) {
+    let mut envs = envs.to_vec();
+    envs.push(("MCP_LISTEN_ADDR", "127.0.0.1:0"));
+    let bridge = BridgeProcess::spawn(uri, &envs);
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        let stderr = bridge.stderr();
+        let bound = stderr
+            .lines()
+            .find(|l| l.contains("client listener bound"))
+            .and_then(|l| l.split("addr=").nth(1))
+            .map(|addr| addr.split_whitespace().next().unwrap().to_string());
+        if let Some(addr) = bound {
+            return (bridge, addr);
+        }
+        assert!(Instant::now() < deadline, "listener never bound; stderr:\n{}", stderr);
+        std::thread::sleep(Duration::from_millis(20));
+    }
+}
+
+struct Client {
+    stream: TcpStream,
+    lines: BufReader<TcpStream>,
+}
+
+impl Client {
+    fn connect(addr: &str) -> Self {
+        let stream = TcpStream::connect(addr).expect("connect to bridge");
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        let lines = BufReader::new(stream.try_clone().unwrap());
+        Self { stream, lines }
+    }
+
+    fn send_line(&mut self, line: &str) {
+        self.stream.write_all(format!("{}\n", line).as_bytes()).unwrap();
+    }
+
+    fn recv_json(&mut self) -> Value {
+        let mut line = String::new();
+        self.lines.read_line(&mut line).expect("answer from bridge");
+        serde_json::from_str(&line).unwrap()
+    }
+}
+
+#[test]
+fn each_connection_gets_only_its_own_answers() {
+    let server = MockServer::echo();
+    let (_bridge, addr) = listening_bridge(&server.uri());
+    let mut a = Client::connect(&addr);
+    let mut b = Client::connect(&addr);
+    // Both use id 1: the answers must still go back to the right client.
+    a.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list","params":{"client":"a"}}"#);
+    b.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list","params":{"client":"b"}}"#);
+    assert_eq!(a.recv_json()["result"]["client"], "a");
+    assert_eq!(b.recv_json()["result"]["client"], "b");
+    a.send_line(r#"{"jsonrpc":"2.0","id":2,"method":"ping","params":{"client":"a"}}"#);
+    assert_eq!(a.recv_json()["result"]["client"], "a");
+}
+
+#[test]
+fn closing_a_connection_cancels_only_its_requests() {
+    let server = MockServer::start(|req| {
+        let msg: Value = serde_json::from_slice(&req.body).unwrap();
+        let reply = serde_json::json!({"jsonrpc": "2.0", "id": msg["id"], "result": {}});
+        let response = MockResponse::json(reply.to_string());
+        match msg["method"].as_str() {
+            Some("tools/call") => response.with_delay(Duration::from_secs(30)),
+            _ => response,
+        }
+    });
+    let (bridge, addr) = listening_bridge(&server.uri());
+    let mut a = Client::connect(&addr);
+    let mut b = Client::connect(&addr);
+    a.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/call"}"#);
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while server.requests().is_empty() {
+        assert!(Instant::now() < deadline, "call never went upstream");
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    drop(a);
+    b.send_line(r#"{"jsonrpc":"2.0","id":2,"method":"ping"}"#);
+    assert_eq!(b.recv_json()["id"], 2);
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !bridge.stderr().contains("cancelling its in-flight requests") {
+        assert!(Instant::now() < deadline, "stderr: {}", bridge.stderr());
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    // Client b is still connected, and only a's requests were cancelled.
+    let stderr = bridge.stderr();
+    assert_eq!(stderr.matches("cancelling its in-flight requests").count(), 1);
+}
+
+#[test]
+fn a_flooding_client_does_not_starve_a_quiet_one() {
+    let server = MockServer::start(|req| {
+        let msg: Value = serde_json::from_slice(&req.body).unwrap();
+        let reply = serde_json::json!({"jsonrpc": "2.0", "id": msg["id"], "result": {}});
+        let response = MockResponse::json(reply.to_string());
+        match msg["method"].as_str() {
+            Some("tools/call") => response.with_delay(Duration::from_millis(300)),
+            _ => response,
+        }
+    });
+    let (_bridge, addr) = listening_bridge_with(&server.uri(), &[("MCP_MAX_CONCURRENCY", "2")]);
+    let mut flood = Client::connect(&addr);
+    let mut trickle = Client::connect(&addr);
+    for id in 0..40 {
+        flood.send_line(&format!(r#"{{"jsonrpc":"2.0","id":{},"method":"tools/call"}}"#, id));
+    }
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while server.requests().is_empty() {
+        assert!(Instant::now() < deadline, "flood never went upstream");
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    // Served in turn, each ping waits for about one call to finish, not
+    // for the 40 queued ahead of it (6 s at 2 at a time).
+    for id in 100..103 {
+        let sent = Instant::now();
+        trickle.send_line(&format!(r#"{{"jsonrpc":"2.0","id":{},"method":"ping"}}"#, id));
+        assert_eq!(trickle.recv_json()["id"], id);
+        let latency = sent.elapsed();
+        assert!(latency < Duration::from_millis(1500), "ping took {:?}", latency);
+    }
+    // Meanwhile the flood was held to the shared budget.
+    let upstream = server.requests().len();
+    assert!(upstream < 25, "{} requests went upstream", upstream);
+    // Two calls run at once, so either of the first two may answer first.
+    let first = flood.recv_json()["id"].as_u64().unwrap();
+    assert!(first < 2, "first flood reply was {}", first);
+}
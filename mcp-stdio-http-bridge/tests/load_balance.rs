@@ -0,0 +1,54 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use serde_json::Value;
+use std::time::Duration;
+
+fn roundtrip(bridge: &mut BridgeProcess, id: u32) -> Value {
+    bridge.send_line(&format!(r#"{{"jsonrpc":"2.0","id":{},"method":"tools/list"}}"#, id));
+    serde_json::from_str(&bridge.recv_line(Duration::from_secs(10)).unwrap()).unwrap()
+}
+
+#[test]
+fn round_robin_alternates_between_endpoints() {
+    let a = MockServer::echo();
+    let b = MockServer::echo();
+    let mut bridge = BridgeProcess::spawn(
+        &a.uri(),
+        &[
+            ("MCP_FALLBACK_URIS", &b.uri()),
+            ("MCP_LB_STRATEGY", "round-robin"),
+        ],
+    );
+    for id in 1..=4 {
+        assert_eq!(roundtrip(&mut bridge, id)["id"], id);
+    }
+    bridge.close_stdin();
+    let (code, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(0), "stderr: {}", stderr);
+    assert_eq!(a.requests().len(), 2);
+    assert_eq!(b.requests().len(), 2);
+}
+
+#[test]
+fn failing_endpoint_is_skipped_during_its_cooldown() {
+    let a = MockServer::start(|_| MockResponse::status(503));
+    let b = MockServer::echo();
+    let mut bridge = BridgeProcess::spawn(
+        &a.uri(),
+        &[
+            ("MCP_FALLBACK_URIS", &b.uri()),
+            ("MCP_LB_STRATEGY", "round-robin"),
+        ],
+    );
+    for id in 1..=3 {
+        assert_eq!(roundtrip(&mut bridge, id)["id"], id);
+    }
+    bridge.close_stdin();
+    let (code, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(0), "stderr: {}", stderr);
+    assert!(stderr.contains("skipping it for the cooldown"), "stderr: {}", stderr);
+    // Only the first attempt went to the failing endpoint.
+    assert_eq!(a.requests().len(), 1);
+    assert_eq!(b.requests().len(), 3);
+}
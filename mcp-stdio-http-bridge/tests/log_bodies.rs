@@ -0,0 +1,51 @@
+mod common;
+
+use common::{BridgeProcess, MockServer};
+use std::time::Duration;
+
+const REQUEST: &str = r#"{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{"name":"login","arguments":{"user":"ann","password":"hunter2"}}}"#;
+
+fn run(envs: &[(&str, &str)]) -> String {
+    let server = MockServer::echo();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), envs);
+    bridge.send_line(REQUEST);
+    let line = bridge.recv_line(Duration::from_secs(10)).unwrap();
+    // Only the log is redacted, never the messages themselves.
+    assert!(line.contains("hunter2"));
+    bridge.close_stdin();
+    let (code, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(0), "stderr: {}", stderr);
+    stderr
+}
+
+#[test]
+fn bodies_are_logged_redacted_without_credentials() {
+    let stderr = run(&[("MCP_LOG_BODIES", "1"), ("MCP_BRIDGE_BEARER_TOKEN", "tok-sekrit")]);
+    let request = stderr
+        .lines()
+        .find(|l| l.contains("upstream request body"))
+        .unwrap_or_else(|| panic!("no request body logged:\n{}", stderr));
+    assert!(request.contains(r#""user":"ann""#), "{}", request);
+    assert!(request.contains(r#""password":"[redacted]""#), "{}", request);
+    assert!(stderr.contains("upstream response line"), "stderr:\n{}", stderr);
+    assert!(!stderr.contains("hunter2"), "stderr:\n{}", stderr);
+    assert!(!stderr.contains("tok-sekrit"), "stderr:\n{}", stderr);
+}
+
+#[test]
+fn bodies_are_cut_to_the_limit() {
+    let stderr = run(&[("MCP_LOG_BODIES", "1"), ("MCP_LOG_BODY_LIMIT", "20")]);
+    let request = stderr
+        .lines()
+        .find(|l| l.contains("upstream request body"))
+        .unwrap_or_else(|| panic!("no request body logged:\n{}", stderr));
+    assert!(request.contains(" bytes]"), "{}", request);
+    assert!(!request.contains("login"), "{}", request);
+}
+
+#[test]
+fn bodies_are_not_logged_by_default() {
+    let stderr = run(&[]);
+    assert!(!stderr.contains("upstream request body"), "stderr:\n{}", stderr);
+    assert!(!stderr.contains("upstream response line"), "stderr:\n{}", stderr);
+}
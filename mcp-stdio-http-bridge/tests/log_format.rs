@@ -0,0 +1,47 @@
+mod common;
+
+use common::{BridgeProcess, MockServer};
+use std::time::Duration;
+
+fn run_one_request(envs: &[(&str, &str)]) -> String {
+    let server = MockServer::echo();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), envs);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#);
+    assert!(bridge.recv_line(Duration::from_secs(10)).is_some());
+    bridge.close_stdin();
+    let (code, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(0), "stderr: {}", stderr);
+    stderr
+}
+
+#[test]
+fn json_format_writes_one_object_per_line() {
+    let stderr = run_one_request(&[("MCP_LOG_FORMAT", "json")]);
+    let logs: Vec<serde_json::Value> = stderr
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap_or_else(|e| panic!("{}: {:?}", e, l)))
+        .filter(|v: &serde_json::Value| v["event"] != "bridge_shutdown")
+        .collect();
+    let start = logs
+        .iter()
+        .find(|v| v["message"] == "starting bridge")
+        .unwrap_or_else(|| panic!("no start line in\n{}", stderr));
+    assert_eq!(start["level"], "INFO");
+    assert!(start["uri"].is_string(), "event fields are top level: {}", start);
+}
+
+#[test]
+fn piped_stderr_has_no_ansi_escapes() {
+    for format in ["", "compact", "pretty"] {
+        let stderr = run_one_request(&[("MCP_LOG_FORMAT", format)]);
+        assert!(stderr.contains("starting bridge"), "stderr:\n{}", stderr);
+        assert!(!stderr.contains('\x1b'), "{:?} wrote escapes:\n{}", format, stderr);
+    }
+}
+
+#[test]
+fn unknown_format_warns_and_keeps_logging() {
+    let stderr = run_one_request(&[("MCP_LOG_FORMAT", "xml")]);
+    assert!(stderr.contains("unknown MCP_LOG_FORMAT \"xml\""), "stderr:\n{}", stderr);
+    assert!(stderr.contains("starting bridge"), "stderr:\n{}", stderr);
+}
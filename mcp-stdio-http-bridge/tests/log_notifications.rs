@@ -0,0 +1,65 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use std::time::Duration;
+
+const CALL: &str = r#"{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{"name":"slow"}}"#;
+const LOG: &str = r#"{"jsonrpc":"2.0","method":"notifications/message","params":{"level":"warning","logger":"indexer","data":"disk almost full"}}"#;
+const PROGRESS: &str = r#"{"jsonrpc":"2.0","method":"notifications/progress","params":{"progressToken":"t","progress":1}}"#;
+const RESULT: &str = r#"{"jsonrpc":"2.0","id":1,"result":{"content":[]}}"#;
+
+/// Answers the call over SSE with a log event and a progress notification
+/// ahead of the result.
+fn server() -> MockServer {
+    MockServer::start(|_| {
+        let body = format!("data: {}\n\ndata: {}\n\ndata: {}\n\n", LOG, PROGRESS, RESULT);
+        let mut response =
+            MockResponse::status(200).with_header("Content-Type", "text/event-stream");
+        response.body = body.into_bytes();
+        response
+    })
+}
+
+fn run(mode: Option<&str>) -> (Vec<String>, String) {
+    let server = server();
+    let envs: Vec<(&str, &str)> = mode.map(|m| ("MCP_LOG_NOTIFICATIONS", m)).into_iter().collect();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &envs);
+    bridge.send_line(CALL);
+    let mut lines = Vec::new();
+    while let Some(line) = bridge.recv_line(Duration::from_secs(10)) {
+        let done = line == RESULT;
+        lines.push(line);
+        if done {
+            break;
+        }
+    }
+    bridge.close_stdin();
+    let (_, rest, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    lines.extend(rest);
+    (lines, stderr)
+}
+
+#[test]
+fn log_notifications_are_forwarded_by_default() {
+    let (lines, _) = run(None);
+    assert_eq!(lines, [LOG, PROGRESS, RESULT]);
+}
+
+#[test]
+fn stderr_mode_logs_them_at_their_level_instead() {
+    let (lines, stderr) = run(Some("stderr"));
+    assert_eq!(lines, [PROGRESS, RESULT]);
+    let line = stderr
+        .lines()
+        .find(|l| l.contains("upstream: disk almost full"))
+        .unwrap_or_else(|| panic!("{}", stderr));
+    assert!(line.contains("WARN"), "{}", line);
+    assert!(line.contains("indexer"), "{}", line);
+}
+
+#[test]
+fn drop_mode_discards_them() {
+    let (lines, stderr) = run(Some("drop"));
+    assert_eq!(lines, [PROGRESS, RESULT]);
+    assert!(!stderr.contains("disk almost full"), "{}", stderr);
+}
@@ -0,0 +1,76 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use serde_json::Value;
+use std::time::Duration;
+
+fn recv_json(bridge: &BridgeProcess) -> Value {
+    let line = bridge.recv_line(Duration::from_secs(10)).expect("a line on stdout");
+    serde_json::from_str(&line).unwrap()
+}
+
+#[test]
+fn over_long_stdin_line_is_answered_and_skipped() {
+    let server = MockServer::echo();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_MAX_MESSAGE_BYTES", "1000")]);
+    let big = format!(
+        r#"{{"jsonrpc":"2.0","id":7,"method":"tools/call","params":{{"text":"{}"}}}}"#,
+        "x".repeat(5000)
+    );
+    bridge.send_line(&big);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":8,"method":"tools/list"}"#);
+    let error = recv_json(&bridge);
+    assert_eq!(error["id"], 7);
+    assert_eq!(error["error"]["code"], -32600);
+    assert!(error["error"]["message"].as_str().unwrap().contains("too large"));
+    assert_eq!(recv_json(&bridge)["id"], 8);
+    bridge.close_stdin();
+    let (code, _, _) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(0));
+    // Only the second request reached the upstream.
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn over_long_line_without_a_known_id_is_answered_with_a_null_id() {
+    let server = MockServer::echo();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_MAX_LINE_BYTES", "100")]);
+    // Without a newline in sight, the id comes too late to be found.
+    let big = format!(
+        r#"{{"jsonrpc":"2.0","method":"tools/call","params":{{"text":"{}"}},"id":7}}"#,
+        "x".repeat(5000)
+    );
+    bridge.send_line(&big);
+    let error = recv_json(&bridge);
+    assert_eq!(error["id"], Value::Null);
+    assert_eq!(error["error"]["code"], -32600);
+    let message = error["error"]["message"].as_str().unwrap();
+    assert!(message.contains("MCP_MAX_LINE_BYTES"), "{}", message);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":8,"method":"tools/list"}"#);
+    assert_eq!(recv_json(&bridge)["id"], 8);
+    bridge.close_stdin();
+    let (code, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(0));
+    assert!(stderr.contains("longer than MCP_MAX_LINE_BYTES"), "{}", stderr);
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn over_long_upstream_body_fails_without_retrying() {
+    let big = format!(
+        r#"{{"jsonrpc":"2.0","id":1,"result":{{"text":"{}"}}}}"#,
+        "x".repeat(5000)
+    );
+    let server = MockServer::start(move |_| MockResponse::json(big.clone()));
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_MAX_MESSAGE_BYTES", "1000")]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/call"}"#);
+    let error = recv_json(&bridge);
+    assert_eq!(error["id"], 1);
+    assert_eq!(error["error"]["code"], -32603);
+    let message = error["error"]["message"].as_str().unwrap();
+    assert!(message.contains("larger than 1000 bytes"), "{}", message);
+    bridge.close_stdin();
+    let (code, _, _) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(0));
+    assert_eq!(server.requests().len(), 1);
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// Answers `initialize` with every server capability, and anything else
+/// with an empty result.
+fn server() -> MockServer {
+    MockServer::start(|req| {
+        let msg: Value = serde_json::from_slice(&req.body).unwrap();
+        let Some(id) = msg.get("id") else {
+            return MockResponse::status(202);
+        };
+        let result = if msg["method"] == "initialize" {
+            json!({
+                "protocolVersion": "2025-03-26",
+                "capabilities": {"tools": {}, "resources": {"subscribe": true}, "prompts": {}, "logging": {}},
+                "serverInfo": {"name": "s"},
+            })
+        } else {
+            json!({})
+        };
+        MockResponse::json(json!({"jsonrpc": "2.0", "id": id, "result": result}).to_string())
+    })
+}
+
+fn recv(bridge: &BridgeProcess) -> Value {
+    serde_json::from_str(&bridge.recv_line(Duration::from_secs(10)).expect("answer")).unwrap()
+}
+
+fn methods(server: &MockServer) -> Vec<String> {
+    server
+        .requests()
+        .iter()
+        .map(|r| serde_json::from_slice::<Value>(&r.body).unwrap()["method"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn an_allowlist_blocks_everything_else_and_hides_its_capabilities() {
+    let server = server();
+    let mut bridge =
+        BridgeProcess::spawn(&server.uri(), &[("MCP_ALLOW_METHODS", "tools/list, tools/call")]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":0,"method":"initialize","params":{}}"#);
+    let init = recv(&bridge);
+    assert_eq!(init["result"]["capabilities"], json!({"tools": {}}));
+    bridge.send_line(r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"resources/read","params":{"uri":"file:///etc/passwd"}}"#);
+    assert_eq!(
+        recv(&bridge),
+        json!({"jsonrpc": "2.0", "id": 1, "error": {"code": -32601, "message": "method not allowed by bridge policy"}})
+    );
+    bridge.send_line(r#"{"jsonrpc":"2.0","method":"notifications/roots/list_changed"}"#);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":2,"method":"tools/list"}"#);
+    assert_eq!(recv(&bridge)["id"], 2);
+    bridge.close_stdin();
+    let (code, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(0));
+    assert!(
+        stderr.contains("dropping notification blocked by bridge policy"),
+        "{}",
+        stderr
+    );
+    assert_eq!(methods(&server), ["initialize", "notifications/initialized", "tools/list"]);
+}
+
+#[test]
+fn a_denylist_blocks_matching_methods_and_batches_holding_them() {
+    let server = server();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_DENY_METHODS", "resources/*")]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"resources/templates/list"}"#);
+    assert_eq!(recv(&bridge)["error"]["code"], -32601);
+    bridge.send_line(
+        r#"[{"jsonrpc":"2.0","id":2,"method":"tools/list"},{"jsonrpc":"2.0","id":3,"method":"resources/read"}]"#,
+    );
+    let errors = recv(&bridge);
+    assert_eq!(errors[0]["id"], 2);
+    assert_eq!(errors[1]["id"], 3);
+    assert_eq!(errors[1]["error"]["message"], "method not allowed by bridge policy");
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":4,"method":"prompts/list"}"#);
+    assert_eq!(recv(&bridge)["id"], 4);
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+    assert_eq!(methods(&server), ["prompts/list"]);
+}
@@ -0,0 +1,127 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer, RecordedRequest};
+use serde_json::Value;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+const RESULT: &str = r#"{"jsonrpc":"2.0","id":1,"result":{}}"#;
+
+/// Serves `token(n)` for the n-th call to `/token` and an empty result on
+/// `/mcp`.
+fn server(token: impl Fn(usize) -> MockResponse + Send + Sync + 'static) -> MockServer {
+    let calls = AtomicUsize::new(0);
+    MockServer::start(move |req| {
+        if req.path == "/token" {
+            return token(calls.fetch_add(1, Ordering::SeqCst));
+        }
+        let msg: Value = serde_json::from_slice(&req.body).unwrap();
+        MockResponse::json(
+            serde_json::json!({"jsonrpc": "2.0", "id": msg["id"], "result": {}}).to_string(),
+        )
+    })
+}
+
+fn spawn(server: &MockServer, envs: &[(&str, &str)]) -> BridgeProcess {
+    let token_url = format!("http://{}/token", server.addr);
+    let mut vars = vec![
+        ("MCP_OAUTH_TOKEN_URL", token_url.as_str()),
+        ("MCP_OAUTH_CLIENT_ID", "bridge"),
+        ("MCP_OAUTH_CLIENT_SECRET", "client-secret-1234"),
+    ];
+    vars.extend_from_slice(envs);
+    BridgeProcess::spawn(&server.uri(), &vars)
+}
+
+fn split(requests: Vec<RecordedRequest>) -> (Vec<RecordedRequest>, Vec<RecordedRequest>) {
+    requests.into_iter().partition(|r| r.path == "/token")
+}
+
+#[test]
+fn tokens_are_requested_and_refreshed_before_they_expire() {
+    let server = server(|n| {
+        MockResponse::json(format!(
+            r#"{{"access_token":"access-token-{}","token_type":"Bearer","expires_in":1}}"#,
+            n
+        ))
+    });
+    let mut bridge = spawn(&server, &[("MCP_OAUTH_SCOPE", "mcp:tools")]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#);
+    assert_eq!(bridge.recv_line(Duration::from_secs(10)).unwrap(), RESULT);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":2,"method":"tools/list"}"#);
+    assert!(bridge.recv_line(Duration::from_secs(10)).is_some());
+    // Refreshed at 800 ms, a fifth of the lifetime before expiry.
+    std::thread::sleep(Duration::from_millis(1000));
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":3,"method":"tools/list"}"#);
+    assert!(bridge.recv_line(Duration::from_secs(10)).is_some());
+    bridge.close_stdin();
+    let (_, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert!(!stderr.contains("client-secret-1234"), "{}", stderr);
+
+    let (tokens, calls) = split(server.requests());
+    assert_eq!(tokens.len(), 2);
+    assert_eq!(tokens[0].method, "POST");
+    // base64("bridge:client-secret-1234")
+    assert_eq!(
+        tokens[0].header("Authorization"),
+        Some("Basic YnJpZGdlOmNsaWVudC1zZWNyZXQtMTIzNA==")
+    );
+    assert_eq!(
+        tokens[0].body_str(),
+        "grant_type=client_credentials&scope=mcp%3Atools"
+    );
+    let bearers: Vec<_> = calls.iter().map(|r| r.header("Authorization")).collect();
+    assert_eq!(
+        bearers,
+        [
+            Some("Bearer access-token-0"),
+            Some("Bearer access-token-0"),
+            Some("Bearer access-token-1"),
+        ]
+    );
+}
+
+#[test]
+fn failing_token_endpoint_is_retried() {
+    let server = server(|n| match n {
+        0 => MockResponse::status(503),
+        _ => MockResponse::json(r#"{"access_token":"access-token-ok","expires_in":3600}"#),
+    });
+    let mut bridge = spawn(&server, &[]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#);
+    assert_eq!(bridge.recv_line(Duration::from_secs(10)).unwrap(), RESULT);
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+    let (tokens, calls) = split(server.requests());
+    assert_eq!(tokens.len(), 2);
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].header("Authorization"), Some("Bearer access-token-ok"));
+}
+
+#[test]
+fn rejected_credentials_fail_the_request_once_the_budget_is_spent() {
+    let server = server(|_| {
+        let mut response = MockResponse::json(
+            r#"{"error":"invalid_client","error_description":"unknown client"}"#,
+        );
+        response.status = 401;
+        response
+    });
+    let mut bridge = spawn(&server, &[("MCP_TOTAL_BUDGET_MS", "1000")]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#);
+    let line = bridge.recv_line(Duration::from_secs(10)).unwrap();
+    let err: Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(err["id"], 1);
+    assert_eq!(err["error"]["code"], -32603);
+    assert_eq!(
+        err["error"]["message"],
+        "bridge transport error: could not acquire credentials: \
+         token endpoint answered HTTP 401: invalid_client (unknown client) \
+         (MCP_TOTAL_BUDGET_MS of 1000 ms exhausted)"
+    );
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+    let (tokens, calls) = split(server.requests());
+    assert!(tokens.len() >= 2);
+    assert!(calls.is_empty());
+}
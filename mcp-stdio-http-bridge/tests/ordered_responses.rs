@@ -0,0 +1,84 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use std::time::{Duration, Instant};
+
+/// Answers each request with its own id, the first one a second late.
+fn slow_first_server() -> MockServer {
+    MockServer::start(|req| {
+        let msg: serde_json::Value = serde_json::from_slice(&req.body).unwrap();
+        let answer = serde_json::json!({"jsonrpc": "2.0", "id": msg["id"], "result": {}});
+        let response = MockResponse::json(answer.to_string());
+        if msg["params"]["slow"] == true {
+            return response.with_delay(Duration::from_secs(1));
+        }
+        response
+    })
+}
+
+fn answer_ids(vars: &[(&str, &str)]) -> Vec<serde_json::Value> {
+    let server = slow_first_server();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), vars);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{"slow":true}}"#);
+    std::thread::sleep(Duration::from_millis(100));
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":2,"method":"tools/list"}"#);
+    recv_ids(&bridge, 2)
+}
+
+/// The ids of the next `n` answers, in the order they arrive.
+fn recv_ids(bridge: &BridgeProcess, n: usize) -> Vec<serde_json::Value> {
+    (0..n)
+        .map(|_| {
+            let line = bridge.recv_line(Duration::from_secs(5)).expect("answer");
+            serde_json::from_str::<serde_json::Value>(&line).unwrap()["id"].clone()
+        })
+        .collect()
+}
+
+#[test]
+fn answers_arrive_as_they_complete_by_default() {
+    assert_eq!(answer_ids(&[]), [2, 1]);
+}
+
+#[test]
+fn ordered_answers_follow_the_requests() {
+    assert_eq!(answer_ids(&[("MCP_ORDERED_RESPONSES", "1")]), [1, 2]);
+}
+
+#[test]
+fn cached_answers_wait_for_earlier_requests() {
+    let server = slow_first_server();
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[("MCP_ORDERED_RESPONSES", "1"), ("MCP_CACHE", "tools/list=60s")],
+    );
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#);
+    bridge.recv_line(Duration::from_secs(5)).expect("first list");
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":2,"method":"tools/call","params":{"slow":true}}"#);
+    std::thread::sleep(Duration::from_millis(100));
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":3,"method":"tools/list"}"#);
+    assert_eq!(recv_ids(&bridge, 2), [2, 3]);
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[test]
+fn errors_for_refused_lines_wait_for_earlier_requests() {
+    let server = slow_first_server();
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[("MCP_ORDERED_RESPONSES", "1"), ("MCP_MAX_LINE_BYTES", "100")],
+    );
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{"slow":true}}"#);
+    // The reader refuses the long line at once, so the call must be out first.
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while server.requests().is_empty() {
+        assert!(Instant::now() < deadline, "call never went upstream");
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    let long = format!(
+        r#"{{"jsonrpc":"2.0","id":2,"method":"tools/call","params":{{"pad":"{}"}}}}"#,
+        "x".repeat(200)
+    );
+    bridge.send_line(&long);
+    assert_eq!(recv_ids(&bridge, 2), [1, 2]);
+}
@@ -0,0 +1,36 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use std::time::Duration;
+
+/// Odd spacing, a duplicate key and an integer beyond u64/f64 precision; any
+/// parse/re-serialize step would change these bytes.
+const PATHOLOGICAL: &str = r#"{ "jsonrpc" : "2.0","id":  1,"method":"tools/call" ,"params":{"a":1,"a":2,"n":123456789012345678901234567890,"x":1.50}}"#;
+
+#[test]
+fn raw_passthrough_preserves_bytes_end_to_end() {
+    let server = MockServer::start(|req| MockResponse::json(req.body_str()));
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[
+            ("MCP_RAW_PASSTHROUGH", "1"),
+            ("MCP_REWRITE_IDS", "1"),
+            ("MCP_METHOD_ALIASES", "tools/call=tools/invoke"),
+        ],
+    );
+    bridge.send_line(PATHOLOGICAL);
+    let line = bridge.recv_line(Duration::from_secs(10)).unwrap();
+    assert_eq!(server.requests()[0].body_str(), PATHOLOGICAL);
+    assert_eq!(line, PATHOLOGICAL);
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
+
+#[test]
+fn rewriting_features_do_change_bytes_without_passthrough() {
+    let server = MockServer::start(|req| MockResponse::json(req.body_str()));
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_REWRITE_IDS", "1")]);
+    bridge.send_line(PATHOLOGICAL);
+    bridge.recv_line(Duration::from_secs(10)).unwrap();
+    assert_ne!(server.requests()[0].body_str(), PATHOLOGICAL);
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use common::{BridgeProcess, MockServer};
+use std::time::Duration;
+
+/// Not resolvable, so only a proxy can have answered.
+const UPSTREAM: &str = "http://upstream.invalid/mcp";
+
+fn ping(uri: &str, envs: &[(&str, &str)]) {
+    let mut bridge = BridgeProcess::spawn(uri, envs);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#);
+    assert_eq!(
+        bridge.recv_line(Duration::from_secs(10)).unwrap(),
+        r#"{"jsonrpc":"2.0","id":1,"result":null}"#
+    );
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
+
+#[test]
+fn mcp_proxy_carries_upstream_traffic_instead_of_the_environment() {
+    let proxy = MockServer::echo();
+    let unused = MockServer::echo();
+    let proxy_url = format!("http://{}", proxy.addr);
+    let env_proxy = format!("http://{}", unused.addr);
+    ping(
+        UPSTREAM,
+        &[("MCP_PROXY", &proxy_url), ("HTTP_PROXY", &env_proxy)],
+    );
+    let requests = proxy.requests();
+    assert_eq!(requests.len(), 1);
+    // A forward proxy gets the absolute URL.
+    assert_eq!(requests[0].path, UPSTREAM);
+    assert!(unused.requests().is_empty());
+}
+
+#[test]
+fn environment_proxy_applies_by_default() {
+    let proxy = MockServer::echo();
+    let env_proxy = format!("http://{}", proxy.addr);
+    ping(UPSTREAM, &[("HTTP_PROXY", &env_proxy)]);
+    assert_eq!(proxy.requests()[0].path, UPSTREAM);
+}
+
+#[test]
+fn mcp_no_proxy_ignores_the_environment() {
+    let upstream = MockServer::echo();
+    let proxy = MockServer::echo();
+    let env_proxy = format!("http://{}", proxy.addr);
+    ping(
+        &upstream.uri(),
+        &[("MCP_NO_PROXY", "true"), ("HTTP_PROXY", &env_proxy)],
+    );
+    assert_eq!(upstream.requests()[0].path, "/mcp");
+    assert!(proxy.requests().is_empty());
+}
+
+#[test]
+fn proxy_and_no_proxy_together_fail_startup() {
+    let upstream = MockServer::echo();
+    let bridge = BridgeProcess::spawn(
+        &upstream.uri(),
+        &[
+            ("MCP_PROXY", "http://127.0.0.1:3128"),
+            ("MCP_NO_PROXY", "1"),
+        ],
+    );
+    let (code, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(2));
+    assert!(stderr.contains("MCP_PROXY and MCP_NO_PROXY"), "{}", stderr);
+}
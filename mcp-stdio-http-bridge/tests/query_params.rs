@@ -0,0 +1,29 @@
+mod common;
+
+use common::{BridgeProcess, MockServer};
+use std::time::Duration;
+
+#[test]
+fn configured_query_params_join_the_uri_s_own() {
+    let server = MockServer::echo();
+    let uri = format!("{}?agent=old&keep=1", server.uri());
+    let mut bridge = BridgeProcess::spawn(
+        &uri,
+        &[("MCP_QUERY_PARAMS", "agent=planner,env=staging area")],
+    );
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#);
+    assert!(bridge.recv_line(Duration::from_secs(10)).is_some());
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+    let requests = server.requests();
+    assert_eq!(requests[0].path, "/mcp?keep=1&agent=planner&env=staging+area");
+}
+
+#[test]
+fn an_invalid_pair_fails_startup() {
+    let server = MockServer::echo();
+    let bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_QUERY_PARAMS", "agent=planner,env")]);
+    let (code, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(2));
+    assert!(stderr.contains(r#"invalid query parameter "env""#), "{}", stderr);
+}
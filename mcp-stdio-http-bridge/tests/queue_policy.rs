@@ -0,0 +1,93 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn slow_server() -> MockServer {
+    MockServer::start(|req| {
+        let msg: serde_json::Value = serde_json::from_slice(&req.body).unwrap();
+        MockResponse::json(
+            serde_json::json!({"jsonrpc": "2.0", "id": msg["id"], "result": {}}).to_string(),
+        )
+        .with_delay(Duration::from_millis(800))
+    })
+}
+
+fn spawn(server: &MockServer, policy: &str) -> BridgeProcess {
+    BridgeProcess::spawn(
+        &server.uri(),
+        &[
+            ("MCP_MAX_CONCURRENCY", "1"),
+            ("MCP_MAX_QUEUE", "1"),
+            ("MCP_QUEUE_POLICY", policy),
+        ],
+    )
+}
+
+fn request(id: u32) -> String {
+    format!(r#"{{"jsonrpc":"2.0","id":{},"method":"tools/call"}}"#, id)
+}
+
+// The first call has to be in flight before the queue can fill behind it.
+fn wait_in_flight(server: &MockServer) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while server.requests().is_empty() {
+        assert!(Instant::now() < deadline, "first call never went upstream");
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+fn parse(line: &str) -> serde_json::Value {
+    serde_json::from_str(line).unwrap()
+}
+
+#[test]
+fn reject_answers_overflowing_request_with_busy_error() {
+    let server = slow_server();
+    let mut bridge = spawn(&server, "reject");
+    bridge.send_line(&request(1));
+    wait_in_flight(&server);
+    bridge.send_line(&request(2));
+    bridge.send_line(&request(3));
+    let busy = parse(&bridge.recv_line(Duration::from_secs(5)).unwrap());
+    assert_eq!(busy["id"], 3);
+    assert_eq!(busy["error"]["code"], -32005);
+    assert_eq!(busy["error"]["message"], "server busy: bridge queue full");
+    assert_eq!(
+        parse(&bridge.recv_line(Duration::from_secs(5)).unwrap())["id"],
+        1
+    );
+    assert_eq!(
+        parse(&bridge.recv_line(Duration::from_secs(5)).unwrap())["id"],
+        2
+    );
+    bridge.close_stdin();
+    let (_, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert!(stderr.contains("stdin queue full"), "stderr: {}", stderr);
+    assert!(stderr.contains("\"reject\""), "stderr: {}", stderr);
+}
+
+#[test]
+fn drop_oldest_answers_oldest_queued_request_with_busy_error() {
+    let server = slow_server();
+    let mut bridge = spawn(&server, "drop-oldest");
+    bridge.send_line(&request(1));
+    wait_in_flight(&server);
+    bridge.send_line(&request(2));
+    bridge.send_line(&request(3));
+    let busy = parse(&bridge.recv_line(Duration::from_secs(5)).unwrap());
+    assert_eq!(busy["id"], 2);
+    assert_eq!(busy["error"]["code"], -32005);
+    assert_eq!(
+        parse(&bridge.recv_line(Duration::from_secs(5)).unwrap())["id"],
+        1
+    );
+    assert_eq!(
+        parse(&bridge.recv_line(Duration::from_secs(5)).unwrap())["id"],
+        3
+    );
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+    assert_eq!(server.requests().len(), 2);
+}
@@ -0,0 +1,85 @@
+mod common;
+
+use common::{BridgeProcess, MockServer};
+use serde_json::Value;
+use std::time::Duration;
+
+const INITIALIZE: &str = r#"{"jsonrpc":"2.0","id":"init","method":"initialize","params":{}}"#;
+const INITIALIZED: &str = r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#;
+const PING: &str = r#"{"jsonrpc":"2.0","id":"ping","method":"ping"}"#;
+
+fn recv(bridge: &BridgeProcess) -> Value {
+    let line = bridge
+        .recv_line(Duration::from_secs(10))
+        .expect("stdout line");
+    serde_json::from_str(&line).unwrap()
+}
+
+/// Sends `notifications/initialized` followed by a ping and returns what came
+/// back before the pong: the acknowledgement, if any.
+fn initialized(bridge: &mut BridgeProcess) -> Option<Value> {
+    bridge.send_line(INITIALIZED);
+    bridge.send_line(PING);
+    let first = recv(bridge);
+    if first["id"] == "ping" {
+        return None;
+    }
+    assert_eq!(recv(bridge)["id"], "ping");
+    Some(first)
+}
+
+fn initialize(bridge: &mut BridgeProcess) {
+    bridge.send_line(INITIALIZE);
+    assert_eq!(recv(bridge)["id"], "init");
+}
+
+#[test]
+fn flag_on_acknowledges_once_per_session() {
+    // The echo server answers notifications with 202 and no body.
+    let server = MockServer::echo();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_QUIRK_ACK_INITIALIZED", "1")]);
+    assert_eq!(
+        initialized(&mut bridge),
+        None,
+        "acknowledged before initialize"
+    );
+    initialize(&mut bridge);
+    let ack = initialized(&mut bridge).expect("acknowledgement");
+    assert_eq!(ack["method"], "notifications/message");
+    assert_eq!(ack["params"]["level"], "debug");
+    assert_eq!(ack["params"]["logger"], "bridge");
+    assert!(ack.get("id").is_none());
+    assert_eq!(initialized(&mut bridge), None);
+    let forwarded = server
+        .requests()
+        .iter()
+        .filter(|r| r.body_str().contains("notifications/initialized"))
+        .count();
+    assert_eq!(forwarded, 3);
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
+
+#[test]
+fn flag_off_writes_nothing() {
+    let server = MockServer::echo();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[]);
+    initialize(&mut bridge);
+    assert_eq!(initialized(&mut bridge), None);
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
+
+#[test]
+fn reinitialized_session_is_acknowledged_again() {
+    let server = MockServer::echo();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_QUIRK_ACK_INITIALIZED", "1")]);
+    initialize(&mut bridge);
+    assert!(initialized(&mut bridge).is_some());
+    // What a client does once the upstream has expired its session.
+    initialize(&mut bridge);
+    assert!(initialized(&mut bridge).is_some());
+    assert_eq!(initialized(&mut bridge), None);
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
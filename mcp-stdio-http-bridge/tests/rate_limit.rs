@@ -0,0 +1,68 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use serde_json::Value;
+use std::time::{Duration, Instant};
+
+const REQUEST: &str = r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#;
+const RESULT: &str = r#"{"jsonrpc":"2.0","id":1,"result":{}}"#;
+
+/// Answers the first request with `status` and an HTML page, then succeeds.
+fn overloaded_once(status: u16, retry_after: Option<&'static str>) -> MockServer {
+    MockServer::failing_first_with(1, move || {
+        let response = MockResponse::html(status, "<html><body>slow down</body></html>");
+        match retry_after {
+            Some(wait) => response.with_header("Retry-After", wait),
+            None => response,
+        }
+    })
+}
+
+#[test]
+fn retry_after_sets_the_wait() {
+    let server = overloaded_once(429, Some("2"));
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[]);
+    let started = Instant::now();
+    bridge.send_line(REQUEST);
+    let line = bridge.recv_line(Duration::from_secs(10)).unwrap();
+    assert_eq!(line, RESULT);
+    // The first backoff would have been 500 ms.
+    assert!(started.elapsed() >= Duration::from_millis(1900));
+    assert_eq!(server.requests().len(), 2);
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
+
+#[test]
+fn unavailable_without_retry_after_backs_off() {
+    let server = overloaded_once(503, None);
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[]);
+    bridge.send_line(REQUEST);
+    let line = bridge.recv_line(Duration::from_secs(10)).unwrap();
+    assert_eq!(line, RESULT);
+    assert_eq!(server.requests().len(), 2);
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
+
+#[test]
+fn retry_after_beyond_the_budget_gives_up() {
+    let server = overloaded_once(429, Some("5"));
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_TOTAL_BUDGET_MS", "1000")]);
+    let started = Instant::now();
+    bridge.send_line(REQUEST);
+    let line = bridge.recv_line(Duration::from_secs(10)).unwrap();
+    assert!(started.elapsed() < Duration::from_secs(3));
+    let err: Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(err["id"], 1);
+    let message = err["error"]["message"].as_str().unwrap();
+    assert_eq!(
+        message,
+        "upstream rate limit exceeded: HTTP 429 Too Many Requests \
+         (MCP_TOTAL_BUDGET_MS of 1000 ms exhausted)"
+    );
+    assert_eq!(err["error"]["code"], -32004);
+    assert_eq!(server.requests().len(), 1);
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
@@ -0,0 +1,55 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use serde_json::Value;
+use std::time::Duration;
+
+const MSG: &str = r#"{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{"name":"x"}}"#;
+
+fn echo_server() -> MockServer {
+    MockServer::start(|req| {
+        let msg: Value = serde_json::from_slice(&req.body).unwrap();
+        MockResponse::json(format!(
+            r#"{{"jsonrpc":"2.0","id":{},"result":{{}}}}"#,
+            msg["id"]
+        ))
+    })
+}
+
+#[test]
+fn split_message_is_rejoined_around_a_valid_line() {
+    let server = echo_server();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_REASSEMBLE_SPLIT_JSON", "1")]);
+    bridge.send_line(&MSG[..30]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":2,"method":"ping"}"#);
+    let pong: Value =
+        serde_json::from_str(&bridge.recv_line(Duration::from_secs(10)).unwrap()).unwrap();
+    assert_eq!(pong["id"], 2);
+    bridge.send_line(&MSG[30..]);
+    let reply: Value =
+        serde_json::from_str(&bridge.recv_line(Duration::from_secs(10)).unwrap()).unwrap();
+    assert_eq!(reply["id"], 1);
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[1].body_str(), MSG);
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
+
+#[test]
+fn dangling_fragment_gets_one_parse_error_at_eof() {
+    let server = echo_server();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_REASSEMBLE_SPLIT_JSON", "1")]);
+    bridge.send_line(&MSG[..20]);
+    bridge.send_line(&MSG[20..40]);
+    bridge.close_stdin();
+    let (_, lines) = bridge.wait(Duration::from_secs(10));
+    assert_eq!(lines.len(), 1);
+    let err: Value = serde_json::from_str(&lines[0]).unwrap();
+    assert_eq!(err["error"]["code"], -32700);
+    assert!(err["error"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("from 2 lines"));
+    assert!(server.requests().is_empty());
+}
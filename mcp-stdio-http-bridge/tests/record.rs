@@ -0,0 +1,113 @@
+mod common;
+
+use common::{BridgeProcess, MockServer};
+use serde_json::Value;
+use std::path::PathBuf;
+use std::time::Duration;
+
+const TOKEN: &str = "s3cr3t-token";
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("bridge-{}-{}.jsonl", name, std::process::id()))
+}
+
+fn call(id: u32) -> String {
+    format!(r#"{{"jsonrpc":"2.0","id":{},"method":"tools/call","params":{{"n":{}}}}}"#, id, id)
+}
+
+fn entries(path: &PathBuf) -> Vec<Value> {
+    let text = std::fs::read_to_string(path).unwrap();
+    assert!(!text.contains(TOKEN), "{}", text);
+    text.lines().map(|l| serde_json::from_str(l).unwrap()).collect()
+}
+
+/// Runs a session against the echo server with recording on.
+fn record(path: &PathBuf) {
+    let _ = std::fs::remove_file(path);
+    let server = MockServer::echo();
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[
+            ("MCP_RECORD_FILE", path.to_str().unwrap()),
+            ("MCP_BRIDGE_BEARER_TOKEN", TOKEN),
+        ],
+    );
+    for id in 1..=2 {
+        bridge.send_line(&call(id));
+        bridge.recv_line(Duration::from_secs(10)).expect("answer");
+    }
+    bridge.close_stdin();
+    let (code, _) = bridge.wait(Duration::from_secs(10));
+    assert_eq!(code, Some(0));
+}
+
+#[test]
+fn every_direction_is_recorded_without_credentials() {
+    let path = temp_path("record");
+    record(&path);
+    let entries = entries(&path);
+    let kinds: Vec<&str> = entries.iter().map(|e| e["kind"].as_str().unwrap()).collect();
+    assert_eq!(
+        kinds,
+        ["stdin", "request", "response", "stdout", "stdin", "request", "response", "stdout"]
+    );
+    assert_eq!(entries[0]["line"], call(1));
+    assert_eq!(entries[1]["method"], "POST");
+    assert_eq!(entries[1]["body"], call(1));
+    let answer: Value = serde_json::from_str(entries[3]["line"].as_str().unwrap()).unwrap();
+    assert_eq!(answer["result"]["n"], 1);
+    let stamps: Vec<u64> = entries.iter().map(|e| e["ts_ms"].as_u64().unwrap()).collect();
+    assert!(stamps.windows(2).all(|w| w[0] <= w[1]), "{:?}", stamps);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn upstream_errors_are_recorded() {
+    let path = temp_path("record-error");
+    let _ = std::fs::remove_file(&path);
+    let server = MockServer::start(|_| common::MockResponse::status(400));
+    let mut bridge =
+        BridgeProcess::spawn(&server.uri(), &[("MCP_RECORD_FILE", path.to_str().unwrap())]);
+    bridge.send_line(&call(1));
+    bridge.recv_line(Duration::from_secs(10)).expect("error answer");
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+    let entries = entries(&path);
+    let error = entries.iter().find(|e| e["kind"] == "error").expect("error entry");
+    assert!(error["error"].as_str().unwrap().contains("400"), "{}", error);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn replay_feeds_recorded_stdin_through_the_bridge() {
+    let path = temp_path("replay");
+    record(&path);
+    let bridge = BridgeProcess::spawn_with_args(
+        "http://127.0.0.1:9/mcp",
+        &[("MCP_MODE", "echo")],
+        &["--replay", path.to_str().unwrap(), "--replay-speed", "0"],
+    );
+    let (code, lines, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(0), "{}", stderr);
+    let ids: Vec<Value> = lines
+        .iter()
+        .map(|l| serde_json::from_str::<Value>(l).unwrap()["id"].clone())
+        .collect();
+    assert_eq!(ids, [1, 2]);
+    assert!(stderr.contains("replay finished"), "{}", stderr);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn an_unwritable_record_file_does_not_stop_the_bridge() {
+    let server = MockServer::echo();
+    let dir = std::env::temp_dir();
+    let mut bridge =
+        BridgeProcess::spawn(&server.uri(), &[("MCP_RECORD_FILE", dir.to_str().unwrap())]);
+    bridge.send_line(&call(1));
+    assert!(bridge.recv_line(Duration::from_secs(10)).is_some());
+    bridge.close_stdin();
+    let (code, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(0));
+    assert!(stderr.contains("not recording"), "{}", stderr);
+}
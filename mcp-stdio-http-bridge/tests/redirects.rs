@@ -0,0 +1,120 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+const PING: &str = r#"{"jsonrpc":"2.0","id":1,"method":"ping","params":{"n":1}}"#;
+
+/// A server that redirects every request to `to` with `status`.
+fn redirecting(status: u16, to: String) -> MockServer {
+    MockServer::start(move |_| MockResponse::status(status).with_header("Location", &to))
+}
+
+/// Sends one ping through a bridge with `envs` and returns its answer.
+fn ping(uri: &str, envs: &[(&str, &str)]) -> serde_json::Value {
+    let mut envs = envs.to_vec();
+    envs.push(("MCP_BRIDGE_BEARER_TOKEN", "secret"));
+    let mut bridge = BridgeProcess::spawn(uri, &envs);
+    bridge.send_line(PING);
+    let line = bridge.recv_line(Duration::from_secs(10)).expect("an answer");
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+    serde_json::from_str(&line).unwrap()
+}
+
+#[test]
+fn redirects_are_followed_by_default_without_credentials() {
+    let target = MockServer::echo();
+    let origin = redirecting(307, target.uri());
+    let answer = ping(&origin.uri(), &[]);
+    assert_eq!(answer["result"]["n"], 1);
+    let requests = target.requests();
+    assert_eq!(requests[0].method, "POST");
+    assert_eq!(requests[0].body_str(), PING);
+    // Another port is another host, and reqwest drops the token for it.
+    assert_eq!(requests[0].header("authorization"), None);
+}
+
+#[test]
+fn auth_hosts_keep_credentials_across_hosts() {
+    let target = MockServer::echo();
+    let origin = redirecting(308, target.uri().replace("127.0.0.1", "localhost"));
+    let answer = ping(&origin.uri(), &[("MCP_REDIRECT_AUTH_HOSTS", "LOCALHOST")]);
+    assert_eq!(answer["result"]["n"], 1);
+    let requests = target.requests();
+    assert_eq!(requests[0].method, "POST");
+    assert_eq!(requests[0].body_str(), PING);
+    assert_eq!(requests[0].header("authorization"), Some("Bearer secret"));
+}
+
+#[test]
+fn auth_hosts_do_not_apply_to_other_hosts() {
+    let target = MockServer::echo();
+    let origin = redirecting(307, target.uri());
+    ping(&origin.uri(), &[("MCP_REDIRECT_AUTH_HOSTS", "localhost")]);
+    assert_eq!(target.requests()[0].header("authorization"), None);
+}
+
+#[test]
+fn none_answers_the_redirect_with_an_error() {
+    let target = MockServer::echo();
+    let origin = redirecting(307, target.uri());
+    let answer = ping(&origin.uri(), &[("MCP_REDIRECT", "none")]);
+    assert!(answer["error"].is_object(), "{}", answer);
+    assert!(target.requests().is_empty());
+}
+
+#[test]
+fn follow_same_host_stays_on_the_host() {
+    let target = MockServer::echo();
+    let origin = redirecting(307, target.uri());
+    let answer = ping(&origin.uri(), &[("MCP_REDIRECT", "follow-same-host")]);
+    assert!(answer["error"].is_object(), "{}", answer);
+    assert!(target.requests().is_empty());
+
+    let server = MockServer::start(|req| match req.path.as_str() {
+        "/mcp" => MockResponse::status(308).with_header("Location", "/v2/mcp"),
+        _ => MockResponse::json(r#"{"jsonrpc":"2.0","id":1,"result":{"moved":true}}"#),
+    });
+    let answer = ping(&server.uri(), &[("MCP_REDIRECT", "follow-same-host")]);
+    assert_eq!(answer["result"]["moved"], true);
+    let requests = server.requests();
+    assert_eq!(requests[1].path, "/v2/mcp");
+    assert_eq!(requests[1].header("authorization"), Some("Bearer secret"));
+}
+
+#[test]
+fn chains_past_the_limit_fail() {
+    let server = MockServer::start(|_| MockResponse::status(307).with_header("Location", "/mcp"));
+    let answer = ping(&server.uri(), &[("MCP_REDIRECT_LIMIT", "2")]);
+    assert!(answer["error"].is_object(), "{}", answer);
+    assert_eq!(server.requests().len(), 3);
+}
+
+#[test]
+fn the_limit_covers_hops_taken_with_credentials() {
+    // Bounces between two names for the same server, each another host.
+    let port = Arc::new(OnceLock::<u16>::new());
+    let known = port.clone();
+    let server = MockServer::start(move |req| {
+        let port = known.get().unwrap();
+        let to = match req.path.as_str() {
+            "/mcp" => format!("http://localhost:{}/a", port),
+            _ => format!("http://127.0.0.1:{}/mcp", port),
+        };
+        MockResponse::status(308).with_header("Location", &to)
+    });
+    port.set(server.addr.port()).unwrap();
+    let answer = ping(
+        &server.uri(),
+        &[
+            ("MCP_REDIRECT_LIMIT", "2"),
+            ("MCP_REDIRECT_AUTH_HOSTS", "localhost,127.0.0.1"),
+        ],
+    );
+    assert!(answer["error"].is_object(), "{}", answer);
+    let requests = server.requests();
+    assert_eq!(requests.len(), 3);
+    assert!(requests.iter().all(|r| r.header("authorization") == Some("Bearer secret")));
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use serde_json::Value;
+use std::time::Duration;
+
+fn recv_json(bridge: &BridgeProcess) -> Value {
+    let line = bridge.recv_line(Duration::from_secs(10)).expect("a line on stdout");
+    serde_json::from_str(&line).unwrap()
+}
+
+#[test]
+fn request_retried_past_the_deadline_is_given_up() {
+    let server = MockServer::start(|_| MockResponse::status(503));
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[("MCP_REQUEST_DEADLINE_MS", "1500"), ("MCP_RETRY_METHODS", "*")],
+    );
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":"slow","method":"tools/call"}"#);
+    let error = recv_json(&bridge);
+    assert_eq!(error["id"], "slow");
+    assert_eq!(error["error"]["code"], -32603);
+    let message = error["error"]["message"].as_str().unwrap();
+    assert!(message.contains("gave up"), "{}", message);
+    assert!(message.contains("1500 ms"), "{}", message);
+    // No more retries once the client has its answer.
+    let attempts = server.requests().len();
+    std::thread::sleep(Duration::from_millis(1500));
+    assert_eq!(server.requests().len(), attempts);
+    bridge.close_stdin();
+    let (code, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(0), "stderr: {}", stderr);
+}
+
+#[test]
+fn progress_keeps_a_streamed_request_alive() {
+    let progress = r#"{"jsonrpc":"2.0","method":"notifications/progress","params":{"progressToken":1,"progress":1}}"#;
+    let server = MockServer::start(move |_| {
+        let mut response = MockResponse::status(200).with_header("Content-Type", "text/event-stream");
+        for _ in 0..4 {
+            response = response.with_chunk(Duration::from_millis(400), format!("data: {}\n\n", progress));
+        }
+        response.with_chunk(
+            Duration::from_millis(400),
+            "data: {\"jsonrpc\":\"2.0\",\"id\":1,\"result\":{}}\n\n",
+        )
+    });
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_REQUEST_DEADLINE_MS", "1000")]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/call"}"#);
+    for _ in 0..4 {
+        assert_eq!(recv_json(&bridge)["method"], "notifications/progress");
+    }
+    let answer = recv_json(&bridge);
+    assert_eq!(answer["id"], 1);
+    assert!(answer.get("error").is_none(), "{}", answer);
+    bridge.close_stdin();
+    let (code, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(0), "stderr: {}", stderr);
+    assert_eq!(server.requests().len(), 1);
+}
@@ -0,0 +1,224 @@
+//! Exact client-visible output of every kind of response the bridge writes:
+//! relayed upstream lines, local answers and synthesized errors.
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use std::time::Duration;
+
+fn recv(bridge: &BridgeProcess) -> String {
+    bridge
+        .recv_line(Duration::from_secs(10))
+        .expect("stdout line")
+}
+
+fn finish(mut bridge: BridgeProcess) {
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
+
+#[test]
+fn upstream_lines_get_client_ids_and_method_names_back() {
+    let server = MockServer::start(|req| {
+        let request: serde_json::Value = serde_json::from_slice(&req.body).unwrap();
+        let mut response =
+            MockResponse::status(200).with_header("Content-Type", "text/event-stream");
+        response.body = format!(
+            "data: {{\"jsonrpc\":\"2.0\",\"method\":\"notifications/srv\",\"params\":{{}}}}\n\n\
+             data: {{\"jsonrpc\":\"2.0\",\"id\":{},\"result\":{{}}}}\n\n",
+            request["id"]
+        )
+        .into_bytes();
+        response
+    });
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[
+            ("MCP_REWRITE_IDS", "1"),
+            ("MCP_METHOD_ALIASES", "notifications/cli=notifications/srv"),
+        ],
+    );
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":"a","method":"tools/list"}"#);
+    assert_eq!(
+        recv(&bridge),
+        r#"{"jsonrpc":"2.0","method":"notifications/cli","params":{}}"#
+    );
+    assert_eq!(recv(&bridge), r#"{"jsonrpc":"2.0","id":"a","result":{}}"#);
+    finish(bridge);
+}
+
+#[test]
+fn depth_refusal() {
+    let server = MockServer::echo();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_MAX_JSON_DEPTH", "4")]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"x","params":[[[[[]]]]]}"#);
+    assert_eq!(
+        recv(&bridge),
+        r#"{"jsonrpc":"2.0","id":null,"error":{"code":-32700,"message":"message nesting depth exceeds limit of 4"}}"#
+    );
+    finish(bridge);
+}
+
+#[test]
+fn control_replies() {
+    let server = MockServer::echo();
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[("MCP_CONTROL_METHODS", "1"), ("MCP_REWRITE_IDS", "1")],
+    );
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":"p","method":"bridge/pause"}"#);
+    assert_eq!(
+        recv(&bridge),
+        r#"{"jsonrpc":"2.0","id":"p","result":{"paused":true}}"#
+    );
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":9,"method":"tools/list"}"#);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":"ping","method":"ping"}"#);
+    assert_eq!(
+        recv(&bridge),
+        r#"{"jsonrpc":"2.0","id":"ping","result":{}}"#
+    );
+    // A notification gets no reply.
+    bridge.send_line(r#"{"jsonrpc":"2.0","method":"bridge/pause"}"#);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":"r","method":"bridge/resume"}"#);
+    assert_eq!(
+        recv(&bridge),
+        r#"{"jsonrpc":"2.0","id":"r","result":{"paused":false,"released":1}}"#
+    );
+    assert_eq!(recv(&bridge), r#"{"jsonrpc":"2.0","id":9,"result":null}"#);
+    finish(bridge);
+}
+
+#[test]
+fn rate_limit_notice() {
+    let server = MockServer::start(|_| {
+        MockResponse::json(r#"{"jsonrpc":"2.0","id":1,"result":{}}"#)
+            .with_header("X-RateLimit-Remaining", "3")
+    });
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_RATE_LIMIT_WARN_BELOW", "10")]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#);
+    assert_eq!(recv(&bridge), r#"{"jsonrpc":"2.0","id":1,"result":{}}"#);
+    assert_eq!(
+        recv(&bridge),
+        r#"{"jsonrpc":"2.0","method":"notifications/message","params":{"level":"warning","logger":"mcp-stdio-http-bridge","data":"upstream rate limit nearly exhausted: 3 requests remaining"}}"#
+    );
+    finish(bridge);
+}
+
+#[test]
+fn budget_error_carries_the_client_id() {
+    let server =
+        MockServer::start(|_| MockResponse::status(200).with_delay(Duration::from_secs(30)));
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[
+            ("MCP_REWRITE_IDS", "1"),
+            ("MCP_TOTAL_BUDGET_MS", "300"),
+            ("MCP_RETRY_METHODS", "*"),
+        ],
+    );
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":"c","method":"tools/call"}"#);
+    let line = recv(&bridge);
+    assert!(
+        line.starts_with(r#"{"jsonrpc":"2.0","id":"c","error":{"code":-32603,"message":"bridge transport error: "#),
+        "{}",
+        line
+    );
+    assert!(
+        line.ends_with(r#" (MCP_TOTAL_BUDGET_MS of 300 ms exhausted)"}}"#),
+        "{}",
+        line
+    );
+    finish(bridge);
+}
+
+fn invalid_utf8_server() -> MockServer {
+    MockServer::start(|_| {
+        let mut response = MockResponse::json("");
+        response.body = b"{\"s\":\"\xff\"}".to_vec();
+        response
+    })
+}
+
+#[test]
+fn non_retryable_error() {
+    let server = invalid_utf8_server();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/call"}"#);
+    assert_eq!(
+        recv(&bridge),
+        r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32603,"message":"bridge transport error: invalid UTF-8 in response at byte 6"}}"#
+    );
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":"s","method":"tools/call"}"#);
+    assert_eq!(
+        recv(&bridge),
+        r#"{"jsonrpc":"2.0","id":"s","error":{"code":-32603,"message":"bridge transport error: invalid UTF-8 in response at byte 6"}}"#
+    );
+    finish(bridge);
+}
+
+#[test]
+fn non_retryable_error_answers_each_request_in_a_batch() {
+    let server = invalid_utf8_server();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_REWRITE_IDS", "1")]);
+    bridge.send_line(
+        r#"[{"jsonrpc":"2.0","id":1,"method":"tools/list"},{"jsonrpc":"2.0","method":"notifications/x"},{"jsonrpc":"2.0","id":"b","method":"tools/call"}]"#,
+    );
+    assert_eq!(
+        recv(&bridge),
+        r#"[{"jsonrpc":"2.0","id":1,"error":{"code":-32603,"message":"bridge transport error: invalid UTF-8 in response at byte 6"}},{"jsonrpc":"2.0","id":"b","error":{"code":-32603,"message":"bridge transport error: invalid UTF-8 in response at byte 6"}}]"#
+    );
+    finish(bridge);
+}
+
+#[test]
+fn failed_notifications_are_only_logged() {
+    let server = invalid_utf8_server();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_MAX_CONCURRENCY", "1")]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","method":"notifications/cancelled","params":{"requestId":7}}"#);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/call"}"#);
+    assert_eq!(
+        recv(&bridge),
+        r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32603,"message":"bridge transport error: invalid UTF-8 in response at byte 6"}}"#
+    );
+    assert_eq!(bridge.recv_line(Duration::from_millis(300)), None);
+    bridge.close_stdin();
+    let (_, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert!(stderr.contains("notifications get no answer"), "{}", stderr);
+    assert!(stderr.contains("notifications/cancelled"), "{}", stderr);
+}
+
+#[test]
+fn accepted_notifications_get_no_answer() {
+    let server = MockServer::echo();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#);
+    assert_eq!(recv(&bridge), r#"{"jsonrpc":"2.0","id":1,"result":null}"#);
+    assert_eq!(bridge.recv_line(Duration::from_millis(300)), None);
+    assert_eq!(server.requests().len(), 2);
+    finish(bridge);
+}
+
+/// With one request at a time, nothing overtakes an earlier response.
+#[test]
+fn local_answers_wait_for_earlier_responses() {
+    let server = MockServer::start(|req| {
+        MockResponse::json(req.body_str()).with_delay(Duration::from_millis(300))
+    });
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[("MCP_CONTROL_METHODS", "1"), ("MCP_MAX_CONCURRENCY", "1")],
+    );
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":2,"method":"bridge/pause"}"#);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":3,"method":"ping"}"#);
+    assert_eq!(
+        recv(&bridge),
+        r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#
+    );
+    assert_eq!(
+        recv(&bridge),
+        r#"{"jsonrpc":"2.0","id":2,"result":{"paused":true}}"#
+    );
+    assert_eq!(recv(&bridge), r#"{"jsonrpc":"2.0","id":3,"result":{}}"#);
+    finish(bridge);
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use common::{dead_uri, BridgeProcess, MockResponse, MockServer};
+use serde_json::Value;
+use std::time::{Duration, Instant};
+
+const REQUEST: &str = r#"{"jsonrpc":"2.0","id":"b","method":"tools/call"}"#;
+
+fn budget_error(bridge: &BridgeProcess) -> Value {
+    let line = bridge.recv_line(Duration::from_secs(10)).unwrap();
+    let err: Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(err["id"], "b");
+    assert_eq!(err["error"]["code"], -32603);
+    err
+}
+
+#[test]
+fn budget_cuts_a_hanging_attempt_short() {
+    let server = MockServer::start(|_| {
+        MockResponse::json(r#"{"jsonrpc":"2.0","id":"b","result":{}}"#)
+            .with_delay(Duration::from_secs(30))
+    });
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[
+            ("MCP_ATTEMPT_TIMEOUT_MS", "20000"),
+            ("MCP_TOTAL_BUDGET_MS", "800"),
+            ("MCP_RETRY_METHODS", "*"),
+        ],
+    );
+    let started = Instant::now();
+    bridge.send_line(REQUEST);
+    let err = budget_error(&bridge);
+    assert!(started.elapsed() < Duration::from_secs(5));
+    let message = err["error"]["message"].as_str().unwrap();
+    assert!(
+        message.contains("MCP_TOTAL_BUDGET_MS of 800 ms exhausted"),
+        "{}",
+        message
+    );
+    assert_eq!(server.requests().len(), 1);
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
+
+#[test]
+fn budget_skips_a_backoff_it_cannot_afford() {
+    // Every connection is refused, so every attempt fails at once.
+    let mut bridge = BridgeProcess::spawn(
+        &dead_uri(),
+        &[("MCP_TOTAL_BUDGET_MS", "1200"), ("MCP_BACKOFF_JITTER", "none")],
+    );
+    let started = Instant::now();
+    bridge.send_line(REQUEST);
+    budget_error(&bridge);
+    // One 500 ms backoff fits; the following 1 s one does not, so the error
+    // arrives well before the budget itself is spent.
+    let elapsed = started.elapsed();
+    assert!(elapsed >= Duration::from_millis(500), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_millis(1200), "{:?}", elapsed);
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
+
+#[test]
+fn max_retries_answers_with_the_last_error_and_resets_per_message() {
+    let server = MockServer::failing_first(3);
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[
+            ("MCP_MAX_RETRIES", "2"),
+            ("MCP_MAX_CONCURRENCY", "1"),
+            ("MCP_RETRY_METHODS", "*"),
+        ],
+    );
+    bridge.send_line(REQUEST);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":"next","method":"tools/call"}"#);
+    let line = bridge.recv_line(Duration::from_secs(10)).unwrap();
+    let err: Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(err["id"], "b");
+    let message = err["error"]["message"].as_str().unwrap();
+    assert!(message.contains("503"), "{}", message);
+    assert!(message.contains("gave up after 3 attempts"), "{}", message);
+    let line = bridge.recv_line(Duration::from_secs(10)).unwrap();
+    let ok: Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(ok["id"], "next");
+    assert!(ok.get("error").is_none(), "{}", ok);
+    assert_eq!(server.requests().len(), 4);
+    bridge.close_stdin();
+    let (_, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert!(stderr.contains("MCP_MAX_RETRIES exhausted"), "{}", stderr);
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use common::{dead_uri, BridgeProcess, MockServer};
+use serde_json::Value;
+use std::time::Duration;
+
+fn recv(bridge: &BridgeProcess) -> Value {
+    let line = bridge.recv_line(Duration::from_secs(10)).expect("stdout line");
+    serde_json::from_str(&line).unwrap()
+}
+
+#[test]
+fn side_effecting_requests_get_one_attempt() {
+    let server = MockServer::failing_first(1);
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/call"}"#);
+    let err = recv(&bridge);
+    assert_eq!(err["id"], 1);
+    let message = err["error"]["message"].as_str().unwrap();
+    assert!(message.contains("503"), "{}", message);
+    assert!(message.contains("not in MCP_RETRY_METHODS"), "{}", message);
+    assert_eq!(server.requests().len(), 1);
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
+
+#[test]
+fn read_only_requests_are_retried() {
+    let server = MockServer::failing_first(1);
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_BACKOFF_JITTER", "none")]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#);
+    let ok = recv(&bridge);
+    assert_eq!(ok["id"], 1);
+    assert!(ok.get("error").is_none(), "{}", ok);
+    assert_eq!(server.requests().len(), 2);
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
+
+#[test]
+fn the_list_can_name_any_method() {
+    let server = MockServer::failing_first(1);
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_RETRY_METHODS", "tools/call")]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/call"}"#);
+    assert!(recv(&bridge).get("error").is_none());
+    assert_eq!(server.requests().len(), 2);
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
+
+#[test]
+fn a_request_that_never_went_out_is_retried_anyway() {
+    let mut bridge = BridgeProcess::spawn(&dead_uri(), &[("MCP_MAX_RETRIES", "1")]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/call"}"#);
+    let err = recv(&bridge);
+    let message = err["error"]["message"].as_str().unwrap();
+    assert!(message.contains("gave up after 2 attempts"), "{}", message);
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
@@ -0,0 +1,27 @@
+mod common;
+
+use common::{BridgeProcess, MockServer};
+use std::time::Duration;
+
+#[test]
+fn reused_client_ids_are_unique_upstream_and_restored_on_stdout() {
+    let server = MockServer::echo();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_REWRITE_IDS", "1")]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list","params":{"n":1}}"#);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list","params":{"n":2}}"#);
+    // Both are in flight at once, so they may be answered in either order.
+    let mut lines = [
+        bridge.recv_line(Duration::from_secs(10)).unwrap(),
+        bridge.recv_line(Duration::from_secs(10)).unwrap(),
+    ];
+    lines.sort();
+    assert_eq!(lines[0], r#"{"jsonrpc":"2.0","id":1,"result":{"n":1}}"#);
+    assert_eq!(lines[1], r#"{"jsonrpc":"2.0","id":1,"result":{"n":2}}"#);
+    let upstream_ids: Vec<serde_json::Value> = server
+        .requests()
+        .iter()
+        .map(|r| serde_json::from_slice::<serde_json::Value>(&r.body).unwrap()["id"].clone())
+        .collect();
+    assert_eq!(upstream_ids.len(), 2);
+    assert_ne!(upstream_ids[0], upstream_ids[1]);
+}
@@ -0,0 +1,219 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer, RecordedRequest};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+
+const INITIALIZE: &str = r#"{"jsonrpc":"2.0","id":"init","method":"initialize","params":{}}"#;
+const PING: &str = r#"{"jsonrpc":"2.0","id":"ping","method":"ping"}"#;
+
+fn method(req: &RecordedRequest) -> Value {
+    serde_json::from_slice::<Value>(&req.body).unwrap()["method"].clone()
+}
+
+/// Echoes requests and assigns `s1`, `s2`, ... to each `initialize`.
+fn assigning_server() -> MockServer {
+    let sessions = AtomicUsize::new(0);
+    MockServer::start(move |req| {
+        let msg: Value = serde_json::from_slice(&req.body).unwrap();
+        let resp = MockResponse::json(
+            json!({"jsonrpc": "2.0", "id": msg["id"], "result": {}}).to_string(),
+        );
+        if msg["method"] == "initialize" {
+            let n = sessions.fetch_add(1, Ordering::SeqCst) + 1;
+            return resp.with_header("Mcp-Session-Id", &format!("s{}", n));
+        }
+        resp
+    })
+}
+
+fn roundtrip(bridge: &mut BridgeProcess, line: &str) -> Value {
+    bridge.send_line(line);
+    let line = bridge
+        .recv_line(Duration::from_secs(10))
+        .expect("stdout line");
+    serde_json::from_str(&line).unwrap()
+}
+
+#[test]
+fn session_id_is_echoed_until_the_client_reinitializes() {
+    let server = assigning_server();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[]);
+    roundtrip(&mut bridge, INITIALIZE);
+    roundtrip(&mut bridge, PING);
+    roundtrip(&mut bridge, INITIALIZE);
+    roundtrip(&mut bridge, PING);
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+    let sent: Vec<_> = server
+        .requests()
+        .iter()
+        .map(|r| (method(r), r.header("Mcp-Session-Id").map(str::to_string)))
+        .collect();
+    assert_eq!(
+        sent,
+        vec![
+            (json!("initialize"), None),
+            (json!("ping"), Some("s1".into())),
+            // A new initialize starts a new session rather than joining the old one.
+            (json!("initialize"), None),
+            (json!("ping"), Some("s2".into())),
+        ]
+    );
+    assert!(server.requests().iter().all(|r| r.path == "/mcp"));
+}
+
+#[test]
+fn session_style_places_the_id() {
+    for (style, path, header) in [
+        ("header", "/mcp", Some("s1")),
+        ("query", "/mcp?sid=s1", None),
+        ("both", "/mcp?sid=s1", Some("s1")),
+    ] {
+        let server = assigning_server();
+        let mut bridge = BridgeProcess::spawn(
+            &server.uri(),
+            &[
+                ("MCP_SESSION_STYLE", style),
+                ("MCP_SESSION_QUERY_PARAM", "sid"),
+            ],
+        );
+        roundtrip(&mut bridge, INITIALIZE);
+        roundtrip(&mut bridge, PING);
+        roundtrip(&mut bridge, PING);
+        bridge.close_stdin();
+        bridge.wait(Duration::from_secs(10));
+        let requests = server.requests();
+        assert_eq!(requests[0].path, "/mcp", "{}", style);
+        for ping in &requests[1..] {
+            assert_eq!(ping.path, path, "{}", style);
+            assert_eq!(ping.header("Mcp-Session-Id"), header, "{}", style);
+        }
+    }
+}
+
+#[test]
+fn expired_session_is_reported_under_the_request_id() {
+    let server = MockServer::start(|req| {
+        let msg: Value = serde_json::from_slice(&req.body).unwrap();
+        if req.header("Mcp-Session-Id").is_some() {
+            return MockResponse::status(404);
+        }
+        MockResponse::json(json!({"jsonrpc": "2.0", "id": msg["id"], "result": {}}).to_string())
+            .with_header("Mcp-Session-Id", "gone")
+    });
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_SESSION_REINIT_MAX", "0")]);
+    roundtrip(&mut bridge, INITIALIZE);
+    let err = roundtrip(
+        &mut bridge,
+        r#"{"jsonrpc":"2.0","id":"call","method":"tools/call"}"#,
+    );
+    assert_eq!(err["id"], "call");
+    assert_eq!(
+        err["error"],
+        json!({
+            "code": -32603,
+            "message": "bridge transport error: upstream session expired (HTTP 404), re-initialize"
+        })
+    );
+    // Not retried, and the next request goes out without the stale id.
+    assert_eq!(roundtrip(&mut bridge, PING)["result"], json!({}));
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+    let requests = server.requests();
+    assert_eq!(requests.len(), 3);
+    assert_eq!(requests[2].header("Mcp-Session-Id"), None);
+}
+
+#[test]
+fn expired_session_is_replaced_and_the_request_retried() {
+    let sessions = AtomicUsize::new(0);
+    let expired = AtomicBool::new(false);
+    let server = MockServer::start(move |req| {
+        let msg: Value = serde_json::from_slice(&req.body).unwrap();
+        if msg["method"] == "initialize" {
+            let n = sessions.fetch_add(1, Ordering::SeqCst) + 1;
+            return MockResponse::json(
+                json!({"jsonrpc": "2.0", "id": msg["id"], "result": {"n": n}}).to_string(),
+            )
+            .with_header("Mcp-Session-Id", &format!("s{}", n));
+        }
+        if msg["method"] == "tools/call" && req.header("Mcp-Session-Id") == Some("s1") {
+            expired.store(true, Ordering::SeqCst);
+        }
+        if expired.load(Ordering::SeqCst) && req.header("Mcp-Session-Id") == Some("s1") {
+            return MockResponse::status(404);
+        }
+        match msg.get("id") {
+            Some(id) => {
+                MockResponse::json(json!({"jsonrpc": "2.0", "id": id, "result": {}}).to_string())
+            }
+            None => MockResponse::status(202),
+        }
+    });
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[]);
+    roundtrip(&mut bridge, INITIALIZE);
+    let reply = roundtrip(
+        &mut bridge,
+        r#"{"jsonrpc":"2.0","id":"call","method":"tools/call"}"#,
+    );
+    assert_eq!(reply, json!({"jsonrpc": "2.0", "id": "call", "result": {}}));
+    bridge.close_stdin();
+    let (_, lines, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    // The replayed initialize is answered upstream only.
+    assert!(lines.is_empty(), "stdout: {:?}", lines);
+    assert!(stderr.contains("re-initialized expired upstream session"));
+    let sent: Vec<_> = server
+        .requests()
+        .iter()
+        .map(|r| (method(r), r.header("Mcp-Session-Id").map(str::to_string)))
+        .collect();
+    assert_eq!(
+        sent,
+        vec![
+            (json!("initialize"), None),
+            (json!("tools/call"), Some("s1".into())),
+            (json!("initialize"), None),
+            (json!("notifications/initialized"), Some("s2".into())),
+            (json!("tools/call"), Some("s2".into())),
+        ]
+    );
+    let replayed: Value = serde_json::from_slice(&server.requests()[2].body).unwrap();
+    assert_eq!(replayed["id"], "bridge-reinit-1");
+}
+
+#[test]
+fn reinitialization_is_capped() {
+    // Every session expires as soon as it is used for a call.
+    let server = MockServer::start(|req| {
+        let msg: Value = serde_json::from_slice(&req.body).unwrap();
+        if msg["method"] == "tools/call" {
+            return MockResponse::status(404);
+        }
+        match msg.get("id") {
+            Some(id) => {
+                MockResponse::json(json!({"jsonrpc": "2.0", "id": id, "result": {}}).to_string())
+                    .with_header("Mcp-Session-Id", "doomed")
+            }
+            None => MockResponse::status(202),
+        }
+    });
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_SESSION_REINIT_MAX", "2")]);
+    roundtrip(&mut bridge, INITIALIZE);
+    let err = roundtrip(
+        &mut bridge,
+        r#"{"jsonrpc":"2.0","id":"call","method":"tools/call"}"#,
+    );
+    assert_eq!(err["id"], "call");
+    assert_eq!(err["error"]["code"], -32603);
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+    let initializes = server
+        .requests()
+        .iter()
+        .filter(|r| method(r) == "initialize")
+        .count();
+    // The client's own, then one per allowed re-initialization.
+    assert_eq!(initializes, 3);
+}
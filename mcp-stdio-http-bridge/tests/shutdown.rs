@@ -0,0 +1,191 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// These tests cover calls already in flight, so wait until the call is out.
+fn wait_upstream(server: &MockServer) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while server.requests().is_empty() {
+        assert!(Instant::now() < deadline, "call never went upstream");
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn stdin_eof_flushes_inflight_response() {
+    let server = MockServer::start(|_| {
+        MockResponse::json(r#"{"jsonrpc":"2.0","id":1,"result":{}}"#)
+            .with_delay(Duration::from_millis(300))
+    });
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#);
+    wait_upstream(&server);
+    bridge.close_stdin();
+    let (_, lines) = bridge.wait(Duration::from_secs(10));
+    assert_eq!(lines, vec![r#"{"jsonrpc":"2.0","id":1,"result":{}}"#.to_string()]);
+}
+
+#[cfg(unix)]
+#[test]
+fn sigterm_flushes_inflight_response() {
+    let server = MockServer::start(|_| {
+        MockResponse::json(r#"{"jsonrpc":"2.0","id":7,"result":{"ok":true}}"#)
+            .with_delay(Duration::from_millis(500))
+    });
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":7,"method":"tools/list"}"#);
+    wait_upstream(&server);
+    bridge.signal("TERM");
+    let (_, lines) = bridge.wait(Duration::from_secs(10));
+    assert_eq!(
+        lines,
+        vec![r#"{"jsonrpc":"2.0","id":7,"result":{"ok":true}}"#.to_string()]
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn abandoned_request_gets_terminated_error_before_exit() {
+    let server = MockServer::start(|_| {
+        MockResponse::json(r#"{"jsonrpc":"2.0","id":"slow","result":{}}"#)
+            .with_delay(Duration::from_secs(30))
+    });
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":"slow","method":"tools/call"}"#);
+    wait_upstream(&server);
+    bridge.signal("TERM");
+    let (_, lines) = bridge.wait(Duration::from_secs(15));
+    assert_eq!(lines.len(), 1, "stdout: {:?}", lines);
+    let err: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+    assert_eq!(err["id"], "slow");
+    assert_eq!(err["error"]["message"], "bridge terminated");
+}
+
+#[test]
+fn stdin_eof_writes_shutdown_report() {
+    let server = MockServer::echo();
+    let path = std::env::temp_dir().join(format!("bridge-report-{}.json", std::process::id()));
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[("MCP_SHUTDOWN_REPORT_PATH", path.to_str().unwrap())],
+    );
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#);
+    bridge.recv_line(Duration::from_secs(10)).unwrap();
+    bridge.close_stdin();
+    let (_, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    let report = BridgeProcess::shutdown_report(&stderr).expect("report on stderr");
+    assert_eq!(report["reason"], "stdin closed");
+    assert_eq!(report["drain_completed"], true);
+    assert_eq!(report["terminated_ids"], serde_json::json!([]));
+    assert_eq!(report["stats"]["requests"], 1);
+    assert!(report.get("uptime_secs").is_some());
+    assert!(report.get("last_transport_error").is_some());
+    let file: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(file, report);
+}
+
+#[cfg(unix)]
+#[test]
+fn sigterm_report_lists_terminated_ids() {
+    let server = MockServer::start(|_| {
+        MockResponse::json(r#"{"jsonrpc":"2.0","id":"slow","result":{}}"#)
+            .with_delay(Duration::from_secs(30))
+    });
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":"slow","method":"tools/call"}"#);
+    thread::sleep(Duration::from_millis(150));
+    bridge.signal("TERM");
+    let (_, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(15));
+    let report = BridgeProcess::shutdown_report(&stderr).expect("report on stderr");
+    assert_eq!(report["reason"], "signal: SIGTERM");
+    assert_eq!(report["terminated_ids"], serde_json::json!(["slow"]));
+    assert_eq!(report["drain_completed"], false);
+}
+
+#[cfg(unix)]
+#[test]
+fn sigterm_sends_queued_requests_before_exit() {
+    let server = MockServer::start(|req| {
+        let msg: serde_json::Value = serde_json::from_slice(&req.body).unwrap();
+        MockResponse::json(
+            serde_json::json!({"jsonrpc": "2.0", "id": msg["id"], "result": {}}).to_string(),
+        )
+        .with_delay(Duration::from_millis(300))
+    });
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_MAX_CONCURRENCY", "1")]);
+    for id in 1..=3 {
+        bridge.send_line(&format!(r#"{{"jsonrpc":"2.0","id":{},"method":"ping"}}"#, id));
+    }
+    thread::sleep(Duration::from_millis(150));
+    bridge.signal("TERM");
+    let (_, lines) = bridge.wait(Duration::from_secs(15));
+    let ids: Vec<i64> = lines
+        .iter()
+        .map(|l| {
+            let v: serde_json::Value = serde_json::from_str(l).unwrap();
+            assert!(v.get("result").is_some(), "line: {}", l);
+            v["id"].as_i64().unwrap()
+        })
+        .collect();
+    assert_eq!(ids, vec![1, 2, 3]);
+    assert_eq!(server.requests().len(), 3);
+}
+
+#[cfg(unix)]
+#[test]
+fn queued_requests_left_after_grace_get_shutdown_error() {
+    let server = MockServer::start(|_| {
+        MockResponse::json(r#"{"jsonrpc":"2.0","id":"slow","result":{}}"#)
+            .with_delay(Duration::from_secs(30))
+    });
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[("MCP_MAX_CONCURRENCY", "1"), ("MCP_SHUTDOWN_GRACE_MS", "500")],
+    );
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":"slow","method":"tools/call"}"#);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":"queued","method":"tools/call"}"#);
+    bridge.send_line(r#"{"jsonrpc":"2.0","method":"notifications/progress"}"#);
+    thread::sleep(Duration::from_millis(150));
+    bridge.signal("TERM");
+    let (_, lines, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    let errors: Vec<serde_json::Value> = lines
+        .iter()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(errors.len(), 2, "stdout: {:?}", lines);
+    let queued = errors.iter().find(|e| e["id"] == "queued").unwrap();
+    assert_eq!(queued["error"]["message"], "bridge shutting down");
+    let slow = errors.iter().find(|e| e["id"] == "slow").unwrap();
+    assert_eq!(slow["error"]["message"], "bridge terminated");
+    assert_eq!(server.requests().len(), 1);
+    let report = BridgeProcess::shutdown_report(&stderr).expect("report on stderr");
+    assert_eq!(report["drain_completed"], false);
+}
+
+#[cfg(unix)]
+#[test]
+fn response_produced_just_before_sigterm_reaches_stdout() {
+    for round in 0..5 {
+        let server = MockServer::start(|_| {
+            MockResponse::json(r#"{"jsonrpc":"2.0","id":1,"result":{}}"#)
+                .with_delay(Duration::from_millis(20))
+        });
+        let mut bridge = BridgeProcess::spawn(&server.uri(), &[]);
+        bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#);
+        while server.requests().is_empty() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        bridge.signal("TERM");
+        let (_, lines) = bridge.wait(Duration::from_secs(10));
+        assert_eq!(
+            lines,
+            vec![r#"{"jsonrpc":"2.0","id":1,"result":{}}"#.to_string()],
+            "round {}",
+            round
+        );
+    }
+}
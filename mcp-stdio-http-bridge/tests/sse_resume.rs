@@ -0,0 +1,79 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+const CALL: &str = r#"{"jsonrpc":"2.0","id":"call","method":"tools/call"}"#;
+
+fn progress(id: u32) -> String {
+    let note = json!({
+        "jsonrpc": "2.0",
+        "method": "notifications/progress",
+        "params": {"progressToken": "t", "progress": id}
+    });
+    format!("id: {}\ndata: {}\n\n", id, note)
+}
+
+fn result(id: u32) -> String {
+    let result = json!({"jsonrpc": "2.0", "id": "call", "result": {}});
+    format!("id: {}\ndata: {}\n\n", id, result)
+}
+
+fn sse() -> MockResponse {
+    MockResponse::status(200).with_header("Content-Type", "text/event-stream")
+}
+
+/// The POST stream sends one event and stalls; the GET that resumes it
+/// replays that event before the rest.
+fn resumable_server() -> MockServer {
+    MockServer::start(|req| match req.method.as_str() {
+        "POST" => sse()
+            .with_chunk(Duration::ZERO, progress(1))
+            .with_chunk(Duration::from_secs(5), result(2)),
+        _ if req.header("Last-Event-ID") == Some("1") => sse()
+            .with_chunk(Duration::ZERO, progress(1))
+            .with_chunk(Duration::ZERO, result(2)),
+        _ => MockResponse::status(405),
+    })
+}
+
+fn recv(bridge: &BridgeProcess) -> Value {
+    let line = bridge.recv_line(Duration::from_secs(10)).expect("stdout line");
+    serde_json::from_str(&line).unwrap()
+}
+
+#[test]
+fn interrupted_stream_resumes_after_its_last_event_id() {
+    let server = resumable_server();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_ATTEMPT_TIMEOUT_MS", "500")]);
+    bridge.send_line(CALL);
+    assert_eq!(recv(&bridge)["params"]["progress"], 1);
+    // The replayed progress event is dropped.
+    assert_eq!(recv(&bridge), json!({"jsonrpc": "2.0", "id": "call", "result": {}}));
+    bridge.close_stdin();
+    let (_, rest, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert!(rest.is_empty(), "{:?}", rest);
+    assert!(stderr.contains("upstream stream interrupted, resuming"), "{}", stderr);
+    let requests = server.requests();
+    let methods: Vec<&str> = requests.iter().map(|r| r.method.as_str()).collect();
+    assert_eq!(methods, ["POST", "GET"]);
+    assert_eq!(requests[1].header("Accept"), Some("text/event-stream"));
+}
+
+#[test]
+fn resumption_can_be_turned_off() {
+    let server = resumable_server();
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[("MCP_ATTEMPT_TIMEOUT_MS", "500"), ("MCP_SSE_RESUME_MAX", "0")],
+    );
+    bridge.send_line(CALL);
+    assert_eq!(recv(&bridge)["params"]["progress"], 1);
+    let err = recv(&bridge);
+    assert_eq!(err["id"], "call");
+    assert_eq!(err["error"]["code"], -32603);
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+    assert_eq!(server.requests().len(), 1);
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use common::{dead_uri, BridgeProcess, MockResponse, MockServer};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[test]
+fn required_probe_exits_when_upstream_is_unreachable() {
+    let uri = format!("{}?key=probe-secret", dead_uri());
+    let bridge = BridgeProcess::spawn(&uri, &[("MCP_STARTUP_PROBE", "required")]);
+    let (code, lines, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(4), "stderr: {}", stderr);
+    assert!(lines.is_empty());
+    assert!(stderr.contains("startup probe failed"), "stderr: {}", stderr);
+    assert!(!stderr.contains("probe-secret"), "stderr: {}", stderr);
+}
+
+#[test]
+fn required_probe_passes_and_bridge_starts() {
+    let server = MockServer::echo();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_STARTUP_PROBE", "required")]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#);
+    let line = bridge.recv_line(Duration::from_secs(10)).unwrap();
+    let response: serde_json::Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(response["id"], 1);
+    bridge.close_stdin();
+    let (code, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(0));
+    assert!(stderr.contains("startup probe ok"), "stderr: {}", stderr);
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(requests[0].body_str().contains("bridge-startup-probe"));
+    assert_eq!(requests[0].header("mcp-session-id"), None);
+}
+
+/// Waits for `needle` on stderr while the bridge keeps running, then stops
+/// it through stdin.
+fn keeps_running_after(mut bridge: BridgeProcess, needle: &str) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !bridge.stderr().contains(needle) {
+        assert!(Instant::now() < deadline, "stderr: {}", bridge.stderr());
+        thread::sleep(Duration::from_millis(20));
+    }
+    bridge.close_stdin();
+    let (code, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(0), "stderr: {}", stderr);
+}
+
+#[test]
+fn sessionless_400_counts_as_reachable() {
+    let server = MockServer::start(|_| MockResponse::status(400));
+    let bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_STARTUP_PROBE", "required")]);
+    keeps_running_after(bridge, "startup probe ok");
+}
+
+#[test]
+fn optional_probe_failure_only_warns() {
+    let bridge = BridgeProcess::spawn(&dead_uri(), &[("MCP_STARTUP_PROBE", "true")]);
+    keeps_running_after(bridge, "starting anyway");
+}
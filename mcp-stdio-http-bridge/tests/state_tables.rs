@@ -0,0 +1,49 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+const LIMIT: usize = 20;
+
+#[test]
+fn abandoned_requests_keep_the_id_table_bounded() {
+    // Accepted but never answered: every request is abandoned upstream.
+    let server = MockServer::start(|_| MockResponse::status(202));
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[
+            ("MCP_REWRITE_IDS", "1"),
+            ("MCP_STATE_TABLE_LIMIT", &LIMIT.to_string()),
+        ],
+    );
+    for id in 0..10 * LIMIT {
+        bridge.send_line(&format!(
+            r#"{{"jsonrpc":"2.0","id":"r{}","method":"tools/call"}}"#,
+            id
+        ));
+    }
+    // Oldest first, each forgotten to make room for a later request.
+    for id in 0..9 * LIMIT {
+        let line = bridge
+            .recv_line(Duration::from_secs(10))
+            .unwrap_or_else(|| panic!("no eviction error for r{}", id));
+        let err: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(err["id"], format!("r{}", id));
+        assert_eq!(
+            err["error"],
+            json!({"code": -32603, "message": "request state evicted"})
+        );
+    }
+    bridge.close_stdin();
+    let (code, rest, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(0));
+    assert!(rest.is_empty(), "{:?}", rest);
+    assert_eq!(server.requests().len(), 10 * LIMIT);
+    let report = BridgeProcess::shutdown_report(&stderr).expect("shutdown report");
+    assert_eq!(
+        report["stats"]["state_tables"]["ids"],
+        json!({"size": LIMIT, "peak": LIMIT, "limit": LIMIT})
+    );
+    assert_eq!(stderr.matches("state table reached 80%").count(), 1);
+}
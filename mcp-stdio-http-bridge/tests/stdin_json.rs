@@ -0,0 +1,52 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use serde_json::Value;
+use std::time::Duration;
+
+fn recv(bridge: &BridgeProcess) -> Value {
+    serde_json::from_str(&bridge.recv_line(Duration::from_secs(10)).unwrap()).unwrap()
+}
+
+#[test]
+fn malformed_line_is_answered_locally() {
+    let server = MockServer::start(|req| MockResponse::json(req.body_str()));
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[]);
+    // A truncated write.
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/ca"#);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":2,"method":"ping"}"#);
+    let refused = recv(&bridge);
+    assert_eq!(refused["jsonrpc"], "2.0");
+    assert_eq!(refused["id"], Value::Null);
+    assert_eq!(refused["error"]["code"], -32700);
+    assert!(refused["error"]["message"]
+        .as_str()
+        .unwrap()
+        .starts_with("invalid JSON: "));
+    assert_eq!(recv(&bridge)["id"], 2);
+    assert_eq!(server.requests().len(), 1);
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
+
+#[test]
+fn json_that_is_not_json_rpc_is_forwarded() {
+    let server = MockServer::start(|req| MockResponse::json(req.body_str()));
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[]);
+    bridge.send_line(r#"{"hello":"world"}"#);
+    assert_eq!(recv(&bridge)["hello"], "world");
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
+
+#[test]
+fn validation_can_be_turned_off() {
+    let server = MockServer::start(|_| MockResponse::status(400));
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_VALIDATE_STDIN_JSON", "0")]);
+    bridge.send_line("not json");
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].body_str(), "not json");
+}
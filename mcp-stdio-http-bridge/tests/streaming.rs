@@ -0,0 +1,153 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+const CALL: &str = r#"{"jsonrpc":"2.0","id":"call","method":"tools/call"}"#;
+
+fn progress(n: u32) -> String {
+    let note = json!({
+        "jsonrpc": "2.0",
+        "method": "notifications/progress",
+        "params": {"progressToken": "t", "progress": n}
+    });
+    format!("data: {}\n\n", note)
+}
+
+fn result() -> String {
+    format!(
+        "data: {}\n\n",
+        json!({"jsonrpc": "2.0", "id": "call", "result": {}})
+    )
+}
+
+fn sse() -> MockResponse {
+    MockResponse::status(200).with_header("Content-Type", "text/event-stream")
+}
+
+fn recv(bridge: &BridgeProcess, timeout: Duration) -> Value {
+    let line = bridge.recv_line(timeout).expect("stdout line");
+    serde_json::from_str(&line).unwrap()
+}
+
+#[test]
+fn events_are_relayed_before_the_stream_ends() {
+    let server = MockServer::start(|_| {
+        sse()
+            .with_chunk(Duration::ZERO, progress(1))
+            .with_chunk(Duration::from_secs(3), result())
+    });
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[]);
+    bridge.send_line(CALL);
+    let started = Instant::now();
+    let first = recv(&bridge, Duration::from_secs(10));
+    assert_eq!(first["method"], "notifications/progress");
+    assert!(
+        started.elapsed() < Duration::from_secs(2),
+        "{:?}",
+        started.elapsed()
+    );
+    assert_eq!(recv(&bridge, Duration::from_secs(10))["id"], "call");
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
+
+#[test]
+fn events_split_across_chunks_are_reassembled() {
+    let body = format!("{}{}", progress(1), result());
+    let (head, tail) = body.split_at(body.len() / 2 + 3);
+    let (head, tail) = (head.to_string(), tail.to_string());
+    let server = MockServer::start(move |_| {
+        sse()
+            .with_chunk(Duration::ZERO, head.clone())
+            .with_chunk(Duration::from_millis(200), tail.clone())
+    });
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[]);
+    bridge.send_line(CALL);
+    assert_eq!(
+        recv(&bridge, Duration::from_secs(10))["params"]["progress"],
+        1
+    );
+    assert_eq!(recv(&bridge, Duration::from_secs(10))["result"], json!({}));
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
+
+#[test]
+fn attempt_timeout_bounds_idle_time_not_the_whole_stream() {
+    let server = MockServer::start(|_| {
+        let mut resp = sse();
+        for n in 0..6 {
+            resp = resp.with_chunk(Duration::from_millis(300), progress(n));
+        }
+        resp.with_chunk(Duration::from_millis(300), result())
+    });
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_ATTEMPT_TIMEOUT_MS", "1000")]);
+    bridge.send_line(CALL);
+    for n in 0..6 {
+        assert_eq!(
+            recv(&bridge, Duration::from_secs(10))["params"]["progress"],
+            n
+        );
+    }
+    assert_eq!(recv(&bridge, Duration::from_secs(10))["result"], json!({}));
+    bridge.close_stdin();
+    let (code, rest) = bridge.wait(Duration::from_secs(10));
+    assert_eq!(code, Some(0));
+    assert!(rest.is_empty(), "{:?}", rest);
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn stalled_stream_is_answered_without_a_retry() {
+    let server = MockServer::start(|_| {
+        sse()
+            .with_chunk(Duration::ZERO, progress(1))
+            .with_chunk(Duration::from_secs(3), result())
+    });
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_ATTEMPT_TIMEOUT_MS", "500")]);
+    bridge.send_line(CALL);
+    assert_eq!(
+        recv(&bridge, Duration::from_secs(10))["params"]["progress"],
+        1
+    );
+    let err = recv(&bridge, Duration::from_secs(10));
+    assert_eq!(err["id"], "call");
+    assert_eq!(
+        err["error"],
+        json!({
+            "code": -32603,
+            "message": "bridge transport error: network: request timed out (stream interrupted)"
+        })
+    );
+    bridge.close_stdin();
+    let (_, rest) = bridge.wait(Duration::from_secs(10));
+    assert!(rest.is_empty(), "{:?}", rest);
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn ndjson_lines_are_relayed_one_by_one() {
+    // Each line of the SSE framing's `data:` payloads, one per line.
+    let line = |sse: String| format!("{}\n", &sse["data: ".len()..sse.len() - 2]);
+    let server = MockServer::start(move |_| {
+        MockResponse::status(200)
+            .with_header("Content-Type", "application/x-ndjson")
+            .with_chunk(Duration::ZERO, line(progress(1)))
+            .with_chunk(Duration::from_secs(3), format!("\r\n{}", line(result())))
+    });
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_SPLIT_BATCH_RESPONSES", "1")]);
+    bridge.send_line(CALL);
+    let started = Instant::now();
+    assert_eq!(recv(&bridge, Duration::from_secs(10))["method"], "notifications/progress");
+    assert!(
+        started.elapsed() < Duration::from_secs(2),
+        "{:?}",
+        started.elapsed()
+    );
+    assert_eq!(recv(&bridge, Duration::from_secs(10))["id"], "call");
+    bridge.close_stdin();
+    let (_, rest) = bridge.wait(Duration::from_secs(10));
+    assert!(rest.is_empty(), "{:?}", rest);
+}
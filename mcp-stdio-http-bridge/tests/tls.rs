@@ -0,0 +1,156 @@
+mod common;
+
+use common::{BridgeProcess, MockServer};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+const RESULT: &str = r#"{"jsonrpc":"2.0","id":1,"result":{}}"#;
+
+#[test]
+fn unusable_client_certificate_fails_startup() {
+    let server = MockServer::echo();
+    // A certificate where the key should be.
+    let cert = format!("{}/client.crt", FIXTURES);
+    let key = format!("{}/localhost.crt", FIXTURES);
+    let bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[("MCP_CLIENT_CERT", &cert), ("MCP_CLIENT_KEY", &key)],
+    );
+    let (code, lines, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(2));
+    assert!(lines.is_empty());
+    let expected = format!("cannot use client certificate {} with key {}", cert, key);
+    assert!(stderr.contains(&expected), "{}", stderr);
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn client_certificate_does_not_disturb_plain_http() {
+    let server = MockServer::echo();
+    let cert = format!("{}/client.p12", FIXTURES);
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[
+            ("MCP_CLIENT_CERT", &cert),
+            ("MCP_CLIENT_CERT_PASSWORD", "bridge-test"),
+        ],
+    );
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#);
+    assert!(bridge.recv_line(Duration::from_secs(10)).is_some());
+    bridge.close_stdin();
+    let (code, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(0));
+    assert!(!stderr.contains("bridge-test"));
+}
+
+/// HTTPS server with the self-signed `localhost` certificate, answering
+/// every request with the same JSON-RPC result.
+fn https_server() -> SocketAddr {
+    let cert = std::fs::read(format!("{}/localhost.crt", FIXTURES)).unwrap();
+    let key = std::fs::read(format!("{}/localhost.key", FIXTURES)).unwrap();
+    let identity = native_tls::Identity::from_pkcs8(&cert, &key).unwrap();
+    let acceptor = Arc::new(native_tls::TlsAcceptor::new(identity).unwrap());
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for tcp in listener.incoming().flatten() {
+            let acceptor = acceptor.clone();
+            thread::spawn(move || {
+                let Ok(stream) = acceptor.accept(tcp) else {
+                    return;
+                };
+                let mut reader = BufReader::new(stream);
+                loop {
+                    let mut len = 0;
+                    loop {
+                        let mut line = String::new();
+                        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                            return;
+                        }
+                        let line = line.trim_end().to_ascii_lowercase();
+                        if line.is_empty() {
+                            break;
+                        }
+                        if let Some(value) = line.strip_prefix("content-length:") {
+                            len = value.trim().parse().unwrap_or(0);
+                        }
+                    }
+                    let mut body = vec![0u8; len];
+                    if reader.read_exact(&mut body).is_err() {
+                        return;
+                    }
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                        RESULT.len(),
+                        RESULT
+                    );
+                    if reader.get_mut().write_all(response.as_bytes()).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+    addr
+}
+
+fn ping_https(envs: &[(&str, &str)]) -> (String, String) {
+    let addr = https_server();
+    let uri = format!("https://localhost:{}/mcp", addr.port());
+    let mut bridge = BridgeProcess::spawn(&uri, envs);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#);
+    let line = bridge.recv_line(Duration::from_secs(10)).unwrap();
+    bridge.close_stdin();
+    let (_, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    (line, stderr)
+}
+
+#[test]
+fn self_signed_upstream_is_refused_by_default() {
+    let (line, _) = ping_https(&[("MCP_TOTAL_BUDGET_MS", "300")]);
+    let err: serde_json::Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(err["error"]["code"], -32603);
+}
+
+#[test]
+fn ca_bundle_trusts_the_upstream() {
+    let ca = format!("{}/localhost.crt", FIXTURES);
+    let (line, _) = ping_https(&[("MCP_CA_CERT", &ca)]);
+    assert_eq!(line, RESULT);
+}
+
+#[test]
+fn insecure_mode_accepts_any_certificate_and_says_so() {
+    let (line, stderr) = ping_https(&[("MCP_TLS_INSECURE", "true")]);
+    assert_eq!(line, RESULT);
+    assert!(
+        stderr.contains("TLS CERTIFICATE VERIFICATION IS OFF"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn malformed_ca_bundle_fails_startup() {
+    let server = MockServer::echo();
+    let ca = format!("{}/client.key", FIXTURES);
+    let bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_CA_CERT", &ca)]);
+    let (code, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(2));
+    let expected = format!("no PEM certificates in CA bundle {}", ca);
+    assert!(stderr.contains(&expected), "{}", stderr);
+}
+
+#[test]
+fn check_reports_an_untrusted_certificate_as_tls() {
+    let addr = https_server();
+    let uri = format!("https://localhost:{}/mcp", addr.port());
+    let bridge = BridgeProcess::spawn_with_args(&uri, &[], &["--check"]);
+    let (code, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(12), "{}", stderr);
+    assert!(stderr.contains("initialize failed (tls)"), "{}", stderr);
+}
@@ -0,0 +1,158 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use serde_json::Value;
+use std::path::PathBuf;
+use std::time::Duration;
+
+const OLD: &str = "projected-token-old-1111";
+const NEW: &str = "projected-token-new-2222";
+
+fn token_file(name: &str, token: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("bridge-{}-{}", name, std::process::id()));
+    std::fs::write(&path, format!("{}\n", token)).unwrap();
+    path
+}
+
+fn ping(bridge: &mut BridgeProcess, id: u32) -> Value {
+    bridge.send_line(&format!(
+        r#"{{"jsonrpc":"2.0","id":{},"method":"ping"}}"#,
+        id
+    ));
+    serde_json::from_str(&bridge.recv_line(Duration::from_secs(10)).unwrap()).unwrap()
+}
+
+fn bearers(server: &MockServer) -> Vec<String> {
+    server
+        .requests()
+        .iter()
+        .map(|r| r.header("Authorization").unwrap_or("-").to_string())
+        .collect()
+}
+
+#[test]
+fn rotation_is_picked_up_every_nth_request() {
+    let server = MockServer::echo();
+    let path = token_file("token-nth", OLD);
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[
+            ("MCP_BEARER_TOKEN_FILE", path.to_str().unwrap()),
+            ("MCP_BEARER_TOKEN_RELOAD_EVERY", "2"),
+        ],
+    );
+    ping(&mut bridge, 1);
+    std::fs::write(&path, NEW).unwrap();
+    ping(&mut bridge, 2);
+    ping(&mut bridge, 3);
+    // A failed reload keeps the token in hand.
+    std::fs::remove_file(&path).unwrap();
+    ping(&mut bridge, 4);
+    bridge.close_stdin();
+    let (code, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(0));
+    assert_eq!(
+        bearers(&server),
+        [
+            format!("Bearer {}", OLD),
+            format!("Bearer {}", NEW),
+            format!("Bearer {}", NEW),
+            format!("Bearer {}", NEW),
+        ]
+    );
+    assert!(
+        stderr.contains("could not refresh credentials"),
+        "{}",
+        stderr
+    );
+    assert!(!stderr.contains(OLD) && !stderr.contains(NEW));
+}
+
+#[test]
+fn unauthorized_rereads_the_token_file() {
+    let server = MockServer::start(|req| {
+        if req.header("Authorization") != Some(&format!("Bearer {}", NEW)) {
+            return MockResponse::status(401);
+        }
+        let msg: Value = serde_json::from_slice(&req.body).unwrap();
+        MockResponse::json(
+            serde_json::json!({"jsonrpc": "2.0", "id": msg["id"], "result": {}}).to_string(),
+        )
+    });
+    let path = token_file("token-401", OLD);
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[
+            ("MCP_BEARER_TOKEN_FILE", path.to_str().unwrap()),
+            ("MCP_BEARER_TOKEN_RELOAD_EVERY", "0"),
+        ],
+    );
+    assert_eq!(ping(&mut bridge, 1)["error"]["code"], -32001);
+    std::fs::write(&path, NEW).unwrap();
+    assert_eq!(ping(&mut bridge, 2)["result"], serde_json::json!({}));
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+    std::fs::remove_file(&path).unwrap();
+    // The 401 re-reads the file and retries once, still with the old token.
+    assert_eq!(
+        bearers(&server),
+        [
+            format!("Bearer {}", OLD),
+            format!("Bearer {}", OLD),
+            format!("Bearer {}", NEW),
+        ]
+    );
+}
+
+#[test]
+fn a_token_rotated_before_the_401_is_used_on_the_retry() {
+    let path = token_file("token-401-retry", OLD);
+    let rotate = path.clone();
+    let server = MockServer::start(move |req| {
+        if req.header("Authorization") != Some(&format!("Bearer {}", NEW)) {
+            std::fs::write(&rotate, NEW).unwrap();
+            return MockResponse::status(401);
+        }
+        let msg: Value = serde_json::from_slice(&req.body).unwrap();
+        MockResponse::json(
+            serde_json::json!({"jsonrpc": "2.0", "id": msg["id"], "result": {}}).to_string(),
+        )
+    });
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[
+            ("MCP_BEARER_TOKEN_FILE", path.to_str().unwrap()),
+            ("MCP_BEARER_TOKEN_RELOAD_EVERY", "0"),
+        ],
+    );
+    assert_eq!(ping(&mut bridge, 1)["result"], serde_json::json!({}));
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+        bearers(&server),
+        [format!("Bearer {}", OLD), format!("Bearer {}", NEW)]
+    );
+}
+
+#[test]
+fn missing_token_file_fails_startup() {
+    let server = MockServer::echo();
+    let path = std::env::temp_dir().join(format!("bridge-no-token-{}", std::process::id()));
+    let bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[("MCP_BEARER_TOKEN_FILE", path.to_str().unwrap())],
+    );
+    let (code, lines, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(2));
+    assert!(lines.is_empty());
+    assert!(
+        stderr.contains(&format!(
+            "cannot read bearer token file {} (from env MCP_BEARER_TOKEN_FILE)",
+            path.display()
+        )),
+        "{}",
+        stderr
+    );
+    assert!(server.requests().is_empty());
+}
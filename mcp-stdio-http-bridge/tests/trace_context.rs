@@ -0,0 +1,91 @@
+mod common;
+
+use common::{run_lines, BridgeProcess, MockResponse, MockServer};
+use serde_json::Value;
+use std::time::Duration;
+
+const PARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+fn with_parent(id: u32) -> String {
+    format!(
+        r#"{{"jsonrpc":"2.0","id":{},"method":"tools/list","params":{{"_meta":{{"traceparent":"{}","tracestate":"agent=1"}}}}}}"#,
+        id, PARENT
+    )
+}
+
+#[test]
+fn no_trace_headers_by_default() {
+    let server = MockServer::echo();
+    run_lines(&server.uri(), &[], &[&with_parent(1)]);
+    assert_eq!(server.requests()[0].header("traceparent"), None);
+}
+
+#[test]
+fn meta_context_is_passed_on_unchanged() {
+    let server = MockServer::echo();
+    run_lines(&server.uri(), &[("MCP_TRACE_CONTEXT", "1")], &[&with_parent(1)]);
+    let request = &server.requests()[0];
+    assert_eq!(request.header("traceparent"), Some(PARENT));
+    assert_eq!(request.header("tracestate"), Some("agent=1"));
+}
+
+#[test]
+fn retries_carry_the_same_new_context() {
+    let server = MockServer::failing_first(1);
+    let ping = r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#;
+    run_lines(&server.uri(), &[("MCP_TRACE_CONTEXT", "1")], &[ping]);
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    let sent = requests[0].header("traceparent").expect("a traceparent");
+    assert_eq!(sent.len(), 55);
+    assert!(sent.starts_with("00-") && sent.ends_with("-00"), "{}", sent);
+    assert_eq!(requests[1].header("traceparent"), Some(sent));
+    assert_eq!(requests[0].header("tracestate"), None);
+}
+
+#[test]
+fn exported_spans_join_the_client_trace() {
+    let collector = MockServer::start(|_| MockResponse::status(200));
+    let server = MockServer::failing_first(1);
+    let endpoint = format!("http://{}", collector.addr);
+    run_lines(&server.uri(), &[("MCP_OTEL_EXPORTER", &endpoint)], &[&with_parent(1)]);
+    let requests = server.requests();
+    let sent = requests[0].header("traceparent").unwrap();
+    assert_eq!(requests[1].header("traceparent"), Some(sent));
+    // The bridge's own span, under the client's.
+    assert!(sent.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"), "{}", sent);
+    assert_ne!(sent, PARENT);
+
+    let exports = collector.requests();
+    assert_eq!(exports.len(), 1);
+    assert_eq!(exports[0].path, "/v1/traces");
+    let body: Value = serde_json::from_slice(&exports[0].body).unwrap();
+    let span = &body["resourceSpans"][0]["scopeSpans"][0]["spans"][0];
+    assert_eq!(span["traceId"], "4bf92f3577b34da6a3ce929d0e0e4736");
+    assert_eq!(span["parentSpanId"], "00f067aa0ba902b7");
+    assert_eq!(span["spanId"], sent[36..52]);
+    assert_eq!(span["traceState"], "agent=1");
+    assert_eq!(span["name"], "tools/list");
+    let attribute = |key: &str| {
+        span["attributes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|a| a["key"] == key)
+            .map(|a| a["value"].clone())
+    };
+    assert_eq!(attribute("mcp.bridge.retries"), Some(serde_json::json!({"intValue": "1"})));
+    assert_eq!(
+        attribute("http.response.status_code"),
+        Some(serde_json::json!({"intValue": "200"}))
+    );
+}
+
+#[test]
+fn an_invalid_exporter_url_fails_startup() {
+    let server = MockServer::echo();
+    let bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_OTEL_EXPORTER", "otel:4318")]);
+    let (code, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(2));
+    assert!(stderr.contains("otel_exporter from env MCP_OTEL_EXPORTER is not an http(s) URL"), "{}", stderr);
+}
@@ -0,0 +1,82 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use serde_json::Value;
+use std::time::Duration;
+
+/// `X-RateLimit-Remaining` sent with the response to request `id`.
+const REMAINING: [u64; 5] = [50, 5, 4, 60, 3];
+
+fn server() -> MockServer {
+    MockServer::start(|req| {
+        let body: Value = serde_json::from_str(&req.body_str()).unwrap();
+        let id = body["id"].as_u64().unwrap();
+        MockResponse::json(format!(r#"{{"jsonrpc":"2.0","id":{},"result":{{}}}}"#, id))
+            .with_header("Deprecation", "@1761955200")
+            .with_header("Sunset", "Mon, 01 Dec 2025 00:00:00 GMT")
+            .with_header(
+                "X-RateLimit-Remaining",
+                &REMAINING[id as usize - 1].to_string(),
+            )
+            .with_header("X-RateLimit-Reset", "30")
+    })
+}
+
+fn recv(bridge: &BridgeProcess) -> Value {
+    serde_json::from_str(&bridge.recv_line(Duration::from_secs(10)).unwrap()).unwrap()
+}
+
+#[test]
+fn deprecation_is_logged_once_and_rate_limit_warns_once_per_dip() {
+    let server = server();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_RATE_LIMIT_WARN_BELOW", "10")]);
+    let mut warnings = Vec::new();
+    for id in 1..=REMAINING.len() {
+        bridge.send_line(&format!(
+            r#"{{"jsonrpc":"2.0","id":{},"method":"tools/list"}}"#,
+            id
+        ));
+        assert_eq!(recv(&bridge)["id"], id);
+        if REMAINING[id - 1] < 10 && (id == 1 || REMAINING[id - 2] >= 10) {
+            let notice = recv(&bridge);
+            assert_eq!(notice["method"], "notifications/message");
+            assert_eq!(notice["params"]["level"], "warning");
+            warnings.push(notice["params"]["data"].as_str().unwrap().to_string());
+        }
+    }
+    assert_eq!(
+        warnings,
+        vec![
+            "upstream rate limit nearly exhausted: 5 requests remaining (reset 30)",
+            "upstream rate limit nearly exhausted: 3 requests remaining (reset 30)",
+        ]
+    );
+    bridge.close_stdin();
+    let (code, lines, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(0));
+    assert!(lines.is_empty(), "unexpected output {:?}", lines);
+    let deprecations: Vec<&str> = stderr
+        .lines()
+        .filter(|l| l.contains("upstream marked this endpoint as deprecated"))
+        .collect();
+    assert_eq!(deprecations.len(), 1, "{}", stderr);
+    assert!(deprecations[0].contains("Sat, 01 Nov 2025 00:00:00 GMT"));
+    assert!(deprecations[0].contains("Mon, 01 Dec 2025 00:00:00 GMT"));
+}
+
+#[test]
+fn no_client_warning_without_a_threshold() {
+    let server = server();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[]);
+    for id in 1..=REMAINING.len() {
+        bridge.send_line(&format!(
+            r#"{{"jsonrpc":"2.0","id":{},"method":"tools/list"}}"#,
+            id
+        ));
+        assert_eq!(recv(&bridge)["id"], id);
+    }
+    bridge.close_stdin();
+    let (code, lines) = bridge.wait(Duration::from_secs(10));
+    assert_eq!(code, Some(0));
+    assert!(lines.is_empty(), "unexpected output {:?}", lines);
+}
@@ -0,0 +1,128 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use serde_json::Value;
+use std::time::Duration;
+
+const REQUEST: &str = r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#;
+const RESULT: &str = r#"{"jsonrpc":"2.0","id":1,"result":{}}"#;
+
+fn html(status: u16) -> MockResponse {
+    MockResponse::html(status, "<html><body>upstream trouble</body></html>")
+}
+
+/// Answers the first request with `status` and an HTML page, then succeeds.
+fn failing_once(status: u16) -> MockServer {
+    MockServer::failing_first_with(1, move || html(status))
+}
+
+fn exchange(server: &MockServer, envs: &[(&str, &str)]) -> String {
+    let mut bridge = BridgeProcess::spawn(&server.uri(), envs);
+    bridge.send_line(REQUEST);
+    let line = bridge.recv_line(Duration::from_secs(10)).unwrap();
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+    line
+}
+
+#[test]
+fn bad_gateway_is_retried() {
+    let server = failing_once(502);
+    assert_eq!(exchange(&server, &[]), RESULT);
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[test]
+fn retry_statuses_can_be_turned_off() {
+    let server = failing_once(502);
+    assert_eq!(
+        exchange(&server, &[("MCP_RETRY_STATUSES", "none")]),
+        r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32603,"message":"bridge transport error: upstream answered HTTP 502 Bad Gateway","data":{"status":502,"body":"<html><body>upstream trouble</body></html>"}}}"#
+    );
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn client_errors_get_their_own_codes() {
+    for (status, code, message) in [
+        (
+            403,
+            -32001,
+            "upstream authentication failed: HTTP 403 Forbidden",
+        ),
+        (
+            404,
+            -32002,
+            "upstream endpoint not found: HTTP 404 Not Found",
+        ),
+        (
+            413,
+            -32003,
+            "payload too large for upstream: HTTP 413 Payload Too Large",
+        ),
+    ] {
+        let server = failing_once(status);
+        let err: Value = serde_json::from_str(&exchange(&server, &[])).unwrap();
+        assert_eq!(err["id"], 1);
+        assert_eq!(err["error"]["code"], code);
+        assert_eq!(err["error"]["message"], message);
+        assert_eq!(err["error"]["data"]["status"], status);
+        assert_eq!(
+            err["error"]["data"]["body"],
+            "<html><body>upstream trouble</body></html>"
+        );
+        assert_eq!(server.requests().len(), 1);
+    }
+}
+
+#[test]
+fn other_statuses_are_internal_errors() {
+    let server = failing_once(500);
+    assert_eq!(
+        exchange(&server, &[]),
+        r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32603,"message":"bridge transport error: upstream answered HTTP 500 Internal Server Error","data":{"status":500,"body":"<html><body>upstream trouble</body></html>"}}}"#
+    );
+}
+
+#[test]
+fn json_rpc_errors_are_relayed_whatever_the_status() {
+    let answer = r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32602,"message":"unknown tool"}}"#;
+    let server = MockServer::start(move |_| {
+        let mut response = MockResponse::json(answer);
+        response.status = 400;
+        response
+    });
+    assert_eq!(exchange(&server, &[]), answer);
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn unauthorized_is_retried_once_with_fresh_credentials() {
+    let server = failing_once(401);
+    assert_eq!(exchange(&server, &[]), RESULT);
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[test]
+fn a_second_unauthorized_is_reported_with_its_challenge() {
+    let challenge = r#"Bearer error="invalid_token", error_description="revoked""#;
+    let server = MockServer::start(move |_| html(401).with_header("WWW-Authenticate", challenge));
+    let err: Value = serde_json::from_str(&exchange(&server, &[])).unwrap();
+    assert_eq!(err["error"]["code"], -32001);
+    assert_eq!(
+        err["error"]["message"],
+        format!(
+            "upstream authentication failed: HTTP 401 Unauthorized, WWW-Authenticate: {}",
+            challenge
+        )
+    );
+    assert_eq!(err["error"]["data"]["status"], 401);
+    assert_eq!(err["error"]["data"]["wwwAuthenticate"], challenge);
+    assert_eq!(
+        err["error"]["data"]["body"],
+        "<html><body>upstream trouble</body></html>"
+    );
+    // Once with the old credentials and once with the re-acquired ones,
+    // and no more.
+    assert_eq!(server.requests().len(), 2);
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use common::{BridgeProcess, MockResponse, MockServer};
+use serde_json::Value;
+use std::time::Duration;
+
+/// A lone 0xff and a truncated two-byte sequence inside a string.
+const INVALID_REQUEST: &[u8] =
+    b"{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"tools/call\",\"params\":{\"s\":\"a\xffb\xc3\"}}";
+
+fn invalid_response() -> MockResponse {
+    MockResponse {
+        body: b"{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":{\"s\":\"latin1 \xe9\"}}".to_vec(),
+        ..MockResponse::json("")
+    }
+}
+
+#[test]
+fn lossy_stdin_forwards_replacement_characters() {
+    let server =
+        MockServer::start(|_| MockResponse::json(r#"{"jsonrpc":"2.0","id":1,"result":{}}"#));
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_STDIN_VALIDATION", "lossy")]);
+    bridge.send_bytes(INVALID_REQUEST);
+    bridge.recv_line(Duration::from_secs(10)).unwrap();
+    assert_eq!(
+        server.requests()[0].body,
+        "{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"tools/call\",\"params\":{\"s\":\"a\u{fffd}b\u{fffd}\"}}"
+            .as_bytes()
+    );
+    bridge.close_stdin();
+    let (_, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    let warning = stderr
+        .lines()
+        .find(|l| l.contains("replacing invalid UTF-8"))
+        .unwrap();
+    assert!(warning.contains("62"), "{}", warning);
+    assert!(warning.contains("replaced=2"), "{}", warning);
+}
+
+#[test]
+fn bytes_stdin_forwards_the_line_as_read() {
+    let server =
+        MockServer::start(|_| MockResponse::json(r#"{"jsonrpc":"2.0","id":1,"result":{}}"#));
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_STDIN_VALIDATION", "bytes")]);
+    bridge.send_bytes(INVALID_REQUEST);
+    let line: Value =
+        serde_json::from_str(&bridge.recv_line(Duration::from_secs(10)).unwrap()).unwrap();
+    assert_eq!(line["id"], 1);
+    // A valid line goes through the same as under strict.
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":2,"method":"tools/list"}"#);
+    bridge.recv_line(Duration::from_secs(10)).unwrap();
+    let requests = server.requests();
+    assert_eq!(requests[0].body, INVALID_REQUEST);
+    assert_eq!(
+        requests[1].body,
+        br#"{"jsonrpc":"2.0","id":2,"method":"tools/list"}"#
+    );
+    bridge.close_stdin();
+    let (code, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(0), "{}", stderr);
+    assert!(!stderr.contains("invalid UTF-8"), "{}", stderr);
+}
+
+#[test]
+fn strict_stdin_stops_at_invalid_utf8() {
+    let server =
+        MockServer::start(|_| MockResponse::json(r#"{"jsonrpc":"2.0","id":1,"result":{}}"#));
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[]);
+    bridge.send_bytes(INVALID_REQUEST);
+    let (code, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert!(stderr.contains("did not contain valid UTF-8"));
+    assert!(stderr.contains("offset=62"), "{}", stderr);
+    assert!(server.requests().is_empty());
+    // A stdio failure, told apart from a clean stdin EOF.
+    assert_eq!(code, Some(5), "{}", stderr);
+    let report = BridgeProcess::shutdown_report(&stderr).expect("shutdown report");
+    assert_eq!(report["reason"], "stdin read failed");
+}
+
+#[test]
+fn lossy_stdout_replaces_invalid_upstream_bytes() {
+    let server = MockServer::start(|_| invalid_response());
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_STDOUT_VALIDATION", "lossy")]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/call"}"#);
+    let line: Value =
+        serde_json::from_str(&bridge.recv_line(Duration::from_secs(10)).unwrap()).unwrap();
+    assert_eq!(line["result"]["s"], "latin1 \u{fffd}");
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
+
+#[test]
+fn strict_stdout_reports_invalid_upstream_bytes() {
+    let server = MockServer::start(|_| invalid_response());
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/call"}"#);
+    let line: Value =
+        serde_json::from_str(&bridge.recv_line(Duration::from_secs(10)).unwrap()).unwrap();
+    assert_eq!(line["error"]["code"], -32603);
+    assert!(line["error"]["message"]
+        .as_str()
+        .unwrap()
+        .ends_with("invalid UTF-8 in response at byte 46"));
+    bridge.close_stdin();
+    bridge.wait(Duration::from_secs(10));
+}
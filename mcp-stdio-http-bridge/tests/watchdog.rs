@@ -0,0 +1,38 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{BridgeProcess, MockServer};
+use std::process::Command;
+use std::time::Duration;
+
+#[test]
+fn the_bridge_shuts_down_when_the_watched_process_exits() {
+    let server = MockServer::echo();
+    let mut parent = Command::new("sleep").arg("60").spawn().unwrap();
+    let pid = parent.id().to_string();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_PARENT_PID", &pid)]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{"n":1}}"#);
+    assert!(bridge.recv_line(Duration::from_secs(10)).is_some());
+    parent.kill().unwrap();
+    parent.wait().unwrap();
+    // Stdin stays open: only the watchdog can end the bridge.
+    let (code, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(0), "{}", stderr);
+    let report = BridgeProcess::shutdown_report(&stderr).expect("shutdown report");
+    assert_eq!(report["reason"], "parent process exited");
+}
+
+#[test]
+fn a_live_process_keeps_the_bridge_running() {
+    let server = MockServer::echo();
+    let pid = std::process::id().to_string();
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[("MCP_PARENT_PID", &pid)]);
+    std::thread::sleep(Duration::from_secs(3));
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{"n":1}}"#);
+    assert!(bridge.recv_line(Duration::from_secs(10)).is_some());
+    bridge.close_stdin();
+    let (_, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    let report = BridgeProcess::shutdown_report(&stderr).expect("shutdown report");
+    assert_eq!(report["reason"], "stdin closed");
+}
@@ -0,0 +1,166 @@
+#![cfg(feature = "websocket")]
+
+mod common;
+
+use common::BridgeProcess;
+use serde_json::{json, Value};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::{accept_hdr, Message, WebSocket};
+
+type Socket = WebSocket<TcpStream>;
+/// The headers of each handshake, in connection order.
+type Handshakes = Arc<Mutex<Vec<Vec<(String, String)>>>>;
+
+/// A WebSocket server handing each connection, with its number, to a handler
+/// on its own thread, and recording the handshake headers.
+struct WsServer {
+    addr: SocketAddr,
+    handshakes: Handshakes,
+}
+
+impl WsServer {
+    fn start<F>(handler: F) -> Self
+    where
+        F: Fn(usize, Socket) + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind websocket server");
+        let addr = listener.local_addr().unwrap();
+        let handshakes = Arc::new(Mutex::new(Vec::new()));
+        let recorded = handshakes.clone();
+        let handler = Arc::new(handler);
+        thread::spawn(move || {
+            for (n, stream) in listener.incoming().enumerate() {
+                let Ok(stream) = stream else { break };
+                let recorded = recorded.clone();
+                let handler = handler.clone();
+                thread::spawn(move || {
+                    // tungstenite's callback signature fixes the error type.
+                    #[allow(clippy::result_large_err)]
+                    let record = |req: &Request, response: Response| {
+                        let headers = req
+                            .headers()
+                            .iter()
+                            .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
+                            .collect();
+                        recorded.lock().unwrap().push(headers);
+                        Ok(response)
+                    };
+                    if let Ok(socket) = accept_hdr(stream, record) {
+                        handler(n, socket);
+                    }
+                });
+            }
+        });
+        Self { addr, handshakes }
+    }
+
+    /// Answers each request with its params, on every connection.
+    fn echo() -> Self {
+        Self::start(|_, mut socket| echo_requests(&mut socket))
+    }
+
+    fn uri(&self) -> String {
+        format!("ws://{}/mcp", self.addr)
+    }
+
+    fn header(&self, connection: usize, name: &str) -> Option<String> {
+        self.handshakes.lock().unwrap()[connection]
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.clone())
+    }
+}
+
+fn echo_requests(socket: &mut Socket) {
+    while let Ok(frame) = socket.read() {
+        let Message::Text(text) = frame else {
+            continue;
+        };
+        let msg: Value = serde_json::from_str(&text).unwrap();
+        if let Some(id) = msg.get("id") {
+            let reply = json!({"jsonrpc": "2.0", "id": id, "result": msg.get("params")});
+            if socket.send(Message::Text(reply.to_string())).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+fn recv_json(bridge: &BridgeProcess) -> Value {
+    let line = bridge
+        .recv_line(Duration::from_secs(10))
+        .expect("bridge output");
+    serde_json::from_str(&line).unwrap()
+}
+
+#[test]
+fn lines_travel_as_text_frames_with_credentials_in_the_handshake() {
+    let server = WsServer::echo();
+    let mut bridge = BridgeProcess::spawn(
+        &server.uri(),
+        &[("MCP_BRIDGE_BEARER_TOKEN", "ws-token"), ("MCP_HEADERS", "X-Tenant: blue")],
+    );
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list","params":{"page":1}}"#);
+    let reply = recv_json(&bridge);
+    assert_eq!(reply["id"], 1);
+    assert_eq!(reply["result"], json!({"page": 1}));
+    assert_eq!(server.header(0, "authorization").as_deref(), Some("Bearer ws-token"));
+    assert_eq!(server.header(0, "x-tenant").as_deref(), Some("blue"));
+    assert_eq!(server.header(0, "sec-websocket-protocol").as_deref(), Some("mcp"));
+    bridge.close_stdin();
+    let (code, lines) = bridge.wait(Duration::from_secs(10));
+    assert_eq!(code, Some(0));
+    assert!(lines.is_empty(), "{:?}", lines);
+}
+
+#[test]
+fn dropped_connection_fails_its_requests_and_is_reopened() {
+    // The first connection takes one request and goes away unanswered.
+    let server = WsServer::start(|n, mut socket| {
+        if n == 0 {
+            let _ = socket.read();
+            return;
+        }
+        echo_requests(&mut socket);
+    });
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/call"}"#);
+    let lost = recv_json(&bridge);
+    assert_eq!(lost["id"], 1);
+    assert_eq!(lost["error"]["message"], "upstream connection lost");
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":2,"method":"ping","params":{}}"#);
+    let reply = recv_json(&bridge);
+    assert_eq!(reply["id"], 2);
+    assert_eq!(reply["result"], json!({}));
+    bridge.close_stdin();
+    let (code, _, stderr) = bridge.wait_with_stderr(Duration::from_secs(10));
+    assert_eq!(code, Some(0), "stderr: {}", stderr);
+    assert!(stderr.contains("reconnecting with backoff"), "stderr: {}", stderr);
+}
+
+#[test]
+fn requests_in_flight_at_shutdown_are_answered_within_the_grace() {
+    let server = WsServer::start(|_, mut socket| {
+        let Ok(Message::Text(text)) = socket.read() else {
+            return;
+        };
+        let msg: Value = serde_json::from_str(&text).unwrap();
+        thread::sleep(Duration::from_millis(300));
+        let reply = json!({"jsonrpc": "2.0", "id": msg["id"], "result": {}});
+        let _ = socket.send(Message::Text(reply.to_string()));
+        echo_requests(&mut socket);
+    });
+    let mut bridge = BridgeProcess::spawn(&server.uri(), &[]);
+    bridge.send_line(r#"{"jsonrpc":"2.0","id":7,"method":"tools/call"}"#);
+    bridge.close_stdin();
+    let (code, lines) = bridge.wait(Duration::from_secs(10));
+    assert_eq!(code, Some(0));
+    assert_eq!(lines.len(), 1, "{:?}", lines);
+    let reply: Value = serde_json::from_str(&lines[0]).unwrap();
+    assert_eq!(reply["id"], 7);
+    assert_eq!(reply["result"], json!({}));
+}